@@ -52,6 +52,15 @@ fn ewald_energy_computation(c: &mut Criterion) {
     c.bench_function("water::ewald::molecular_virial", move |b| b.iter(|| {
         let _ = ewald.molecular_virial(&system);
     }));
+
+    // Moving all the molecules forces an update of the real-space neighbor
+    // list before computing the energy
+    let mut system = utils::get_system("water");
+    let ewald = get_ewald();
+    c.bench_function("water::ewald::energy_after_moves", move |b| b.iter_with_setup(
+        || utils::move_all_rigid_molecule(&mut system),
+        |system| ewald.energy(&system)
+    ));
 }
 
 fn ewald_monte_carlo_cache(c: &mut Criterion) {
@@ -101,6 +110,15 @@ fn wolf_energy_computation(c: &mut Criterion) {
     c.bench_function("water::wolf::molecular_virial", move |b| b.iter(|| {
         let _ = wolf.molecular_virial(&system);
     }));
+
+    // Moving all the molecules forces an update of the real-space neighbor
+    // list before computing the energy
+    let mut system = utils::get_system("water");
+    let wolf = get_wolf();
+    c.bench_function("water::wolf::energy_after_moves", move |b| b.iter_with_setup(
+        || utils::move_all_rigid_molecule(&mut system),
+        |system| wolf.energy(&system)
+    ));
 }
 
 fn wolf_monte_carlo_cache(c: &mut Criterion) {
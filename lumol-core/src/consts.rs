@@ -11,3 +11,5 @@ pub const BOHR_RADIUS: f64 = 0.52917720859;
 pub const AVOGADRO_NUMBER: f64 = 6.02214179e23;
 /// 4 * π * epsilon_0
 pub const FOUR_PI_EPSILON_0: f64 = 7.197589831304046;
+/// Planck constant
+pub const PLANCK: f64 = 0.0399031268153784;
@@ -0,0 +1,309 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Grid-based correction map for pairs of consecutive dihedral angles
+use std::f64::consts::PI;
+
+/// Cubic Hermite basis functions, and their derivatives, at `t` in `[0, 1]`.
+/// The functions are returned as `([h00, h01], [h10, h11])`, where `h0x`
+/// multiply the values and `h1x` multiply the derivatives at the two ends of
+/// the interval.
+#[inline]
+fn hermite(t: f64) -> ([f64; 2], [f64; 2]) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let values = [2.0 * t3 - 3.0 * t2 + 1.0, -2.0 * t3 + 3.0 * t2];
+    let derivatives = [t3 - 2.0 * t2 + t, t3 - t2];
+    (values, derivatives)
+}
+
+/// Derivatives of the cubic Hermite basis functions with respect to `t`.
+#[inline]
+fn hermite_derivatives(t: f64) -> ([f64; 2], [f64; 2]) {
+    let t2 = t * t;
+    let values = [6.0 * t2 - 6.0 * t, -6.0 * t2 + 6.0 * t];
+    let derivatives = [3.0 * t2 - 4.0 * t + 1.0, 3.0 * t2 - 2.0 * t];
+    (values, derivatives)
+}
+
+/// Correction map (CMAP) potential for two consecutive dihedral angles `phi`
+/// and `psi`, as used in the CHARMM force field for protein backbones.
+///
+/// The energy is given on a regular `n x n` grid covering `[-π, π)` for both
+/// angles, and interpolated between the grid points using bicubic
+/// interpolation. The derivatives of the energy at the grid points are
+/// computed from the grid values using periodic centered finite differences.
+///
+/// For five particles `i, j, k, m, n`, the angle `phi` is the dihedral angle
+/// `i, j, k, m` and the angle `psi` is the dihedral angle `j, k, m, n`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::f64::consts::PI;
+/// use lumol_core::energy::Cmap;
+///
+/// // A 4 x 4 grid, with a single non-zero value at phi = psi = -π
+/// let mut values = vec![0.0; 16];
+/// values[0] = 5.0;
+/// let cmap = Cmap::new(4, values);
+///
+/// // The interpolation goes through the grid points
+/// assert_eq!(cmap.energy(-PI, -PI), 5.0);
+/// assert!(cmap.energy(PI / 2.0, PI / 2.0).abs() < 1e-12);
+///
+/// // The map is periodic
+/// assert_eq!(cmap.energy(-PI, -PI), cmap.energy(PI, PI));
+///
+/// // The forces are zero at the maximum of the map
+/// assert_eq!(cmap.force(-PI, -PI), (0.0, 0.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cmap {
+    /// Number of grid points for each angle
+    size: usize,
+    /// Grid spacing, in radians
+    delta: f64,
+    /// Energy at the grid points, the value for `phi_i, psi_j` is at index
+    /// `i * size + j`.
+    values: Vec<f64>,
+    /// Derivatives of the energy with respect to `phi` at the grid points
+    d_phi: Vec<f64>,
+    /// Derivatives of the energy with respect to `psi` at the grid points
+    d_psi: Vec<f64>,
+    /// Cross derivatives of the energy with respect to `phi` and `psi` at the
+    /// grid points
+    d_phi_psi: Vec<f64>,
+}
+
+impl Cmap {
+    /// Create a new `Cmap` from the energy `values` on a `size x size` grid.
+    /// The value for the angles `phi = -π + i * 2π / size` and `psi = -π + j
+    /// * 2π / size` should be at index `i * size + j` in `values`.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is smaller than 2, or if `values` does not contains `size *
+    /// size` elements.
+    pub fn new(size: usize, values: Vec<f64>) -> Cmap {
+        assert!(size >= 2, "CMAP grid must contain at least two points");
+        assert_eq!(values.len(), size * size, "wrong number of values in CMAP grid");
+        let delta = 2.0 * PI / size as f64;
+
+        let index = |i: usize, j: usize| (i % size) * size + (j % size);
+        let mut d_phi = vec![0.0; size * size];
+        let mut d_psi = vec![0.0; size * size];
+        let mut d_phi_psi = vec![0.0; size * size];
+        for i in 0..size {
+            let (next_i, previous_i) = (i + 1, i + size - 1);
+            for j in 0..size {
+                let (next_j, previous_j) = (j + 1, j + size - 1);
+                d_phi[index(i, j)] = (
+                    values[index(next_i, j)] - values[index(previous_i, j)]
+                ) / (2.0 * delta);
+                d_psi[index(i, j)] = (
+                    values[index(i, next_j)] - values[index(i, previous_j)]
+                ) / (2.0 * delta);
+                d_phi_psi[index(i, j)] = (
+                    values[index(next_i, next_j)] - values[index(next_i, previous_j)] -
+                    values[index(previous_i, next_j)] + values[index(previous_i, previous_j)]
+                ) / (4.0 * delta * delta);
+            }
+        }
+
+        Cmap {
+            size: size,
+            delta: delta,
+            values: values,
+            d_phi: d_phi,
+            d_psi: d_psi,
+            d_phi_psi: d_phi_psi,
+        }
+    }
+
+    /// Get the number of grid points for each angle in this map
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get a new `Cmap` with the roles of `phi` and `psi` exchanged. This is
+    /// the map to use when the five particles are given in reverse order.
+    pub fn transposed(&self) -> Cmap {
+        let size = self.size;
+        let mut values = vec![0.0; size * size];
+        for i in 0..size {
+            for j in 0..size {
+                values[j * size + i] = self.values[i * size + j];
+            }
+        }
+        Cmap::new(size, values)
+    }
+
+    /// Check if this map is symmetric, i.e. if exchanging the roles of `phi`
+    /// and `psi` gives the same map.
+    pub fn is_symmetric(&self) -> bool {
+        let size = self.size;
+        for i in 0..size {
+            for j in (i + 1)..size {
+                if self.values[i * size + j] != self.values[j * size + i] {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    /// Get the energy for the dihedral angles `phi` and `psi`
+    pub fn energy(&self, phi: f64, psi: f64) -> f64 {
+        self.interpolate(phi, psi).0
+    }
+
+    /// Get the generalized forces `(- dE/dphi, - dE/dpsi)` for the dihedral
+    /// angles `phi` and `psi`
+    pub fn force(&self, phi: f64, psi: f64) -> (f64, f64) {
+        let (_, d_phi, d_psi) = self.interpolate(phi, psi);
+        (-d_phi, -d_psi)
+    }
+
+    /// Get the grid cell containing `angle`, and the reduced position of
+    /// the angle inside this cell.
+    fn locate(&self, angle: f64) -> (usize, f64) {
+        let x = (angle + PI) / self.delta;
+        let cell = x.floor();
+        let t = x - cell;
+        let size = self.size as i64;
+        let cell = (((cell as i64) % size) + size) % size;
+        (cell as usize, t)
+    }
+
+    /// Compute the energy and its derivatives with respect to `phi` and `psi`
+    /// using bicubic Hermite interpolation of the grid values.
+    fn interpolate(&self, phi: f64, psi: f64) -> (f64, f64, f64) {
+        let (i, u) = self.locate(phi);
+        let (j, v) = self.locate(psi);
+        let h = self.delta;
+
+        let (a_u, b_u) = hermite(u);
+        let (da_u, db_u) = hermite_derivatives(u);
+        let (a_v, b_v) = hermite(v);
+        let (da_v, db_v) = hermite_derivatives(v);
+
+        let mut energy = 0.0;
+        let mut d_phi = 0.0;
+        let mut d_psi = 0.0;
+        for a in 0..2 {
+            for b in 0..2 {
+                let index = ((i + a) % self.size) * self.size + (j + b) % self.size;
+                let f = self.values[index];
+                let fx = h * self.d_phi[index];
+                let fy = h * self.d_psi[index];
+                let fxy = h * h * self.d_phi_psi[index];
+
+                energy += a_u[a] * a_v[b] * f + b_u[a] * a_v[b] * fx +
+                          a_u[a] * b_v[b] * fy + b_u[a] * b_v[b] * fxy;
+                d_phi += da_u[a] * a_v[b] * f + db_u[a] * a_v[b] * fx +
+                         da_u[a] * b_v[b] * fy + db_u[a] * b_v[b] * fxy;
+                d_psi += a_u[a] * da_v[b] * f + b_u[a] * da_v[b] * fx +
+                         a_u[a] * db_v[b] * fy + b_u[a] * db_v[b] * fxy;
+            }
+        }
+
+        (energy, d_phi / h, d_psi / h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const EPS: f64 = 1e-9;
+
+    fn smooth_map(size: usize) -> Cmap {
+        let delta = 2.0 * PI / size as f64;
+        let mut values = Vec::new();
+        for i in 0..size {
+            let phi = -PI + i as f64 * delta;
+            for j in 0..size {
+                let psi = -PI + j as f64 * delta;
+                values.push(f64::cos(phi) + 0.5 * f64::sin(2.0 * psi) + 0.3 * f64::cos(phi - psi));
+            }
+        }
+        Cmap::new(size, values)
+    }
+
+    #[test]
+    fn grid_points() {
+        let cmap = smooth_map(24);
+        let delta = 2.0 * PI / 24.0;
+        for &(i, j) in &[(0, 0), (3, 7), (12, 23), (23, 5)] {
+            let phi = -PI + i as f64 * delta;
+            let psi = -PI + j as f64 * delta;
+            let expected = f64::cos(phi) + 0.5 * f64::sin(2.0 * psi) + 0.3 * f64::cos(phi - psi);
+            assert_relative_eq!(cmap.energy(phi, psi), expected, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let cmap = smooth_map(24);
+        for &(phi, psi) in &[(0.1, 0.2), (-2.5, 1.3), (3.0, -3.0), (1.7, 0.4)] {
+            let expected = f64::cos(phi) + 0.5 * f64::sin(2.0 * psi) + 0.3 * f64::cos(phi - psi);
+            assert_relative_eq!(cmap.energy(phi, psi), expected, epsilon = 1e-2);
+        }
+    }
+
+    #[test]
+    fn periodicity() {
+        let cmap = smooth_map(12);
+        let (phi, psi) = (0.3, -1.2);
+        assert_relative_eq!(cmap.energy(phi, psi), cmap.energy(phi + 2.0 * PI, psi), epsilon = 1e-12);
+        assert_relative_eq!(cmap.energy(phi, psi), cmap.energy(phi, psi - 2.0 * PI), epsilon = 1e-12);
+
+        // Continuity at the grid boundaries
+        assert_relative_eq!(cmap.energy(PI - 1e-12, psi), cmap.energy(-PI, psi), epsilon = 1e-9);
+        assert_relative_eq!(cmap.energy(phi, PI - 1e-12), cmap.energy(phi, -PI), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn force() {
+        let cmap = smooth_map(24);
+        for &(phi, psi) in &[(0.1, 0.2), (-2.5, 1.3), (3.0, -3.0), (1.7, 0.4)] {
+            let (force_phi, force_psi) = cmap.force(phi, psi);
+
+            let e0 = cmap.energy(phi, psi);
+            let e1 = cmap.energy(phi + EPS, psi);
+            assert_relative_eq!((e0 - e1) / EPS, force_phi, epsilon = 1e-6);
+
+            let e1 = cmap.energy(phi, psi + EPS);
+            assert_relative_eq!((e0 - e1) / EPS, force_psi, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn transposed() {
+        let cmap = smooth_map(24);
+        let transposed = cmap.transposed();
+        for &(phi, psi) in &[(0.1, 0.2), (-2.5, 1.3), (3.0, -3.0)] {
+            assert_relative_eq!(cmap.energy(phi, psi), transposed.energy(psi, phi), epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn symmetric() {
+        let cmap = smooth_map(24);
+        assert!(!cmap.is_symmetric());
+
+        let mut values = vec![0.0; 9];
+        values[1] = 2.0;
+        values[3] = 2.0;
+        values[8] = -1.0;
+        assert!(Cmap::new(3, values).is_symmetric());
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_size() {
+        let _ = Cmap::new(4, vec![0.0; 15]);
+    }
+}
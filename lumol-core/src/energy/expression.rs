@@ -0,0 +1,164 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! User-defined potentials from mathematical expressions
+use std::sync::Arc;
+
+use caldyn::{Context, Expr};
+pub use caldyn::Error as ExpressionError;
+
+use energy::{PairPotential, Potential};
+use units;
+
+/// Relative step used for finite differences derivatives of the energy
+const FINITE_DIFFERENCE_STEP: f64 = 1e-6;
+
+/// Evaluate `expr` with the variable `r` set to the given value. Units names
+/// (`A`, `kJ`, `mol`, ...) are available in the expression and evaluate to the
+/// corresponding conversion factor to internal units.
+fn evaluate(expr: &Expr, r: f64) -> Result<f64, ExpressionError> {
+    let mut context = Context::new();
+    context.set("r", r);
+    context.set_query(|name| units::CONVERSION_FACTORS.get(name).cloned());
+    expr.eval(&context)
+}
+
+/// Potential defined by an user-provided mathematical expression.
+///
+/// The energy expression `U(r)` is a function of the distance `r`, and can
+/// use units names to convert values to the internal units. If an expression
+/// is provided for the force `F(r) = - dU/dr`, it is used directly. Else, the
+/// force is computed from the energy using centered finite differences.
+///
+/// This potential is intended to prototype new functional forms without
+/// recompiling the code, and is much slower than the native potentials. Tail
+/// corrections are not available for this potential, and are always zero.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// use lumol_core::energy::ExprPotential;
+///
+/// let potential = ExprPotential::new("3 * (r - 2)^2", Some("-6 * (r - 2)")).unwrap();
+/// assert_eq!(potential.energy(2.0), 0.0);
+/// assert_eq!(potential.energy(3.0), 3.0);
+/// assert_eq!(potential.force(3.0), -6.0);
+///
+/// // Units are available in the expression
+/// let potential = ExprPotential::new("kJ/mol", None).unwrap();
+/// assert_eq!(potential.energy(2.0), lumol_core::units::from(1.0, "kJ/mol").unwrap());
+/// assert_eq!(potential.force(2.0), 0.0);
+///
+/// // Unknown variables are an error
+/// assert!(ExprPotential::new("3 * x", None).is_err());
+/// ```
+#[derive(Clone)]
+pub struct ExprPotential {
+    /// Expression for the energy
+    energy: Arc<Expr>,
+    /// Expression for the force, if any
+    force: Option<Arc<Expr>>,
+}
+
+impl ExprPotential {
+    /// Create a new `ExprPotential` from the `energy` expression and an
+    /// optional `force` expression. This function returns an error if the
+    /// expressions can not be parsed, or if they use undefined variables.
+    pub fn new(energy: &str, force: Option<&str>) -> Result<ExprPotential, ExpressionError> {
+        let energy = Expr::parse(energy)?;
+        // Check that all the variables are defined
+        let _ = evaluate(&energy, 1.0)?;
+
+        let force = match force {
+            Some(force) => {
+                let force = Expr::parse(force)?;
+                let _ = evaluate(&force, 1.0)?;
+                Some(Arc::new(force))
+            }
+            None => None,
+        };
+
+        Ok(ExprPotential {
+            energy: Arc::new(energy),
+            force: force,
+        })
+    }
+}
+
+impl Potential for ExprPotential {
+    fn energy(&self, r: f64) -> f64 {
+        evaluate(&self.energy, r).expect("energy expression was validated at construction")
+    }
+
+    fn force(&self, r: f64) -> f64 {
+        if let Some(ref force) = self.force {
+            evaluate(force, r).expect("force expression was validated at construction")
+        } else {
+            let delta = FINITE_DIFFERENCE_STEP * f64::max(r.abs(), 1.0);
+            (self.energy(r - delta) - self.energy(r + delta)) / (2.0 * delta)
+        }
+    }
+}
+
+impl PairPotential for ExprPotential {
+    // There is no general way to integrate the expression, so tail
+    // corrections are disabled for this potential.
+    fn tail_energy(&self, _: f64) -> f64 {
+        0.0
+    }
+
+    fn tail_virial(&self, _: f64) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use energy::{LennardJones, PairPotential, Potential};
+    use units;
+
+    #[test]
+    fn energy() {
+        let lj = LennardJones {
+            sigma: 2.0,
+            epsilon: 0.8,
+        };
+        let expr = ExprPotential::new("4 * 0.8 * ((2 / r)^12 - (2 / r)^6)", None).unwrap();
+        for &r in &[1.8, 2.0, 2.5, 4.0] {
+            assert_relative_eq!(expr.energy(r), lj.energy(r), epsilon = 1e-12);
+        }
+
+        let expr = ExprPotential::new("2 * kJ/mol * r / A", None).unwrap();
+        let expected = units::from(2.0, "kJ/mol").unwrap() * 3.0;
+        assert_ulps_eq!(expr.energy(3.0), expected);
+    }
+
+    #[test]
+    fn force() {
+        let lj = LennardJones {
+            sigma: 2.0,
+            epsilon: 0.8,
+        };
+        let expr = ExprPotential::new("4 * 0.8 * ((2 / r)^12 - (2 / r)^6)", None).unwrap();
+        for &r in &[1.8, 2.0, 2.5, 4.0] {
+            assert_relative_eq!(expr.force(r), lj.force(r), epsilon = 1e-6);
+        }
+
+        let expr = ExprPotential::new("0.5 * 3 * (r - 2)^2", Some("3 * (2 - r)")).unwrap();
+        assert_eq!(expr.force(2.5), -1.5);
+        assert_eq!(expr.force(2.0), 0.0);
+
+        assert_eq!(expr.tail_energy(3.0), 0.0);
+        assert_eq!(expr.tail_virial(3.0), 0.0);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(ExprPotential::new("3 * (r + 2", None).is_err());
+        assert!(ExprPotential::new("3 * foo", None).is_err());
+        assert!(ExprPotential::new("3 * r", Some("2 +")).is_err());
+        assert!(ExprPotential::new("3 * r", Some("y")).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Sampling of potentials on a grid, and fitting of analytic potentials to
+//! tabulated values.
+use std::io::{self, Write};
+
+use energy::{Buckingham, LennardJones, Potential};
+use math::*;
+
+/// Energy and force of a potential, sampled on a grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PotentialTable {
+    /// Values of the variable at the grid points
+    pub x: Vec<f64>,
+    /// Energy at the grid points
+    pub energy: Vec<f64>,
+    /// Force at the grid points
+    pub force: Vec<f64>,
+}
+
+impl PotentialTable {
+    /// Write this table to `writer`, with one line for each grid point
+    /// containing the values of the variable, the energy and the force, in
+    /// internal units.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "# x energy force")?;
+        for ((x, energy), force) in self.x.iter().zip(&self.energy).zip(&self.force) {
+            writeln!(writer, "{} {} {}", x, energy, force)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sample the `potential` on a regular grid of `size` points between `min`
+/// and `max`, both included.
+///
+/// # Panics
+///
+/// If `size` is smaller than 2.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{tabulate, Harmonic};
+///
+/// let potential = Harmonic { k: 2.0, x0: 1.0 };
+/// let table = tabulate(&potential, 0.0, 2.0, 5);
+///
+/// assert_eq!(table.x, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+/// assert_eq!(table.energy, vec![1.0, 0.25, 0.0, 0.25, 1.0]);
+/// assert_eq!(table.force, vec![2.0, 1.0, 0.0, -1.0, -2.0]);
+/// ```
+pub fn tabulate<P: Potential + ?Sized>(
+    potential: &P,
+    min: f64,
+    max: f64,
+    size: usize,
+) -> PotentialTable {
+    assert!(size >= 2, "can not tabulate a potential with less than 2 points");
+    let delta = (max - min) / (size - 1) as f64;
+
+    let mut table = PotentialTable {
+        x: Vec::with_capacity(size),
+        energy: Vec::with_capacity(size),
+        force: Vec::with_capacity(size),
+    };
+    for i in 0..size {
+        let x = min + i as f64 * delta;
+        table.x.push(x);
+        table.energy.push(potential.energy(x));
+        table.force.push(potential.force(x));
+    }
+    return table;
+}
+
+/// Fit the energy `V(r) = a f(r) + b g(r)` to the `energies` at distances
+/// `r` by linear least squares. This function returns the values of `a` and
+/// `b` and the sum of the squared residuals, or `None` if the system is
+/// singular.
+fn linear_fit<F, G>(r: &[f64], energies: &[f64], f: F, g: G) -> Option<(f64, f64, f64)>
+    where F: Fn(f64) -> f64, G: Fn(f64) -> f64
+{
+    let (mut ff, mut fg, mut gg, mut fe, mut ge) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (&r, &energy) in r.iter().zip(energies) {
+        let (fr, gr) = (f(r), g(r));
+        ff += fr * fr;
+        fg += fr * gr;
+        gg += gr * gr;
+        fe += fr * energy;
+        ge += gr * energy;
+    }
+
+    let determinant = ff * gg - fg * fg;
+    if !(determinant.abs() > 1e-12 * ff * gg) {
+        return None;
+    }
+
+    let a = (fe * gg - ge * fg) / determinant;
+    let b = (ff * ge - fg * fe) / determinant;
+
+    let residual = r.iter().zip(energies).map(|(&r, &energy)| {
+        let delta = a * f(r) + b * g(r) - energy;
+        delta * delta
+    }).sum();
+
+    return Some((a, b, residual));
+}
+
+/// Fit a [Lennard-Jones](struct.LennardJones.html) potential to the
+/// tabulated `energies` at distances `r`, using least squares.
+///
+/// This function returns `None` if the fit does not give a physical potential
+/// with positive `sigma` and `epsilon`, or if there are not enough data
+/// points. The points at short distances, where the energy is very high,
+/// have a large weight in the fit and may need to be removed from the data.
+///
+/// # Panics
+///
+/// If `r` and `energies` do not have the same size.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{fit_lennard_jones, tabulate, LennardJones};
+///
+/// let potential = LennardJones { sigma: 3.2, epsilon: 0.8 };
+/// let table = tabulate(&potential, 3.0, 10.0, 100);
+///
+/// let fitted = fit_lennard_jones(&table.x, &table.energy).unwrap();
+/// assert!(f64::abs(fitted.sigma - 3.2) < 1e-9);
+/// assert!(f64::abs(fitted.epsilon - 0.8) < 1e-9);
+/// ```
+pub fn fit_lennard_jones(r: &[f64], energies: &[f64]) -> Option<LennardJones> {
+    assert_eq!(r.len(), energies.len(), "wrong number of energies in fit");
+    // V(r) = A / r^12 - B / r^6, with A = 4 epsilon sigma^12 and
+    // B = 4 epsilon sigma^6
+    let (a, b, _) = linear_fit(r, energies, |r| f64::powi(r, -12), |r| -f64::powi(r, -6))?;
+    if a <= 0.0 || b <= 0.0 {
+        return None;
+    }
+
+    Some(LennardJones {
+        sigma: f64::powf(a / b, 1.0 / 6.0),
+        epsilon: b * b / (4.0 * a),
+    })
+}
+
+/// Fit a [Buckingham](struct.Buckingham.html) potential to the tabulated
+/// `energies` at distances `r`, using least squares.
+///
+/// For a given value of `rho`, the energy is linear in `a` and `c`, which are
+/// obtained by linear least squares. The value of `rho` minimizing the
+/// residuals is then searched for between `0.001 * r_max` and `r_max`.
+///
+/// This function returns `None` if the fit does not give a physical potential
+/// with positive `a` and `c`, or if there are not enough data points. The
+/// points at short distances, where the energy is very high, have a large
+/// weight in the fit and may need to be removed from the data.
+///
+/// # Panics
+///
+/// If `r` and `energies` do not have the same size.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{fit_buckingham, tabulate, Buckingham};
+///
+/// let potential = Buckingham { a: 1000.0, c: 20.0, rho: 0.3 };
+/// let table = tabulate(&potential, 2.0, 10.0, 100);
+///
+/// let fitted = fit_buckingham(&table.x, &table.energy).unwrap();
+/// assert!(f64::abs(fitted.rho - 0.3) < 1e-6);
+/// assert!(f64::abs(fitted.a - 1000.0) / 1000.0 < 1e-4);
+/// assert!(f64::abs(fitted.c - 20.0) / 20.0 < 1e-4);
+/// ```
+pub fn fit_buckingham(r: &[f64], energies: &[f64]) -> Option<Buckingham> {
+    assert_eq!(r.len(), energies.len(), "wrong number of energies in fit");
+    let r_max = r.iter().cloned().fold(0.0, f64::max);
+    if r_max <= 0.0 {
+        return None;
+    }
+
+    let fit = |rho: f64| linear_fit(r, energies, |r| exp(-r / rho), |r| -f64::powi(r, -6));
+    let residual = |rho: f64| fit(rho).map_or(f64::INFINITY, |(_, _, residual)| residual);
+
+    // Coarse scan of rho on a logarithmic grid
+    const SCAN_POINTS: usize = 200;
+    let (rho_min, rho_max) = (1e-3 * r_max, r_max);
+    let factor = f64::powf(rho_max / rho_min, 1.0 / (SCAN_POINTS - 1) as f64);
+    let grid = (0..SCAN_POINTS).map(|i| rho_min * f64::powi(factor, i as i32))
+                               .collect::<Vec<_>>();
+
+    let mut best = 0;
+    let mut best_residual = f64::INFINITY;
+    for (i, &rho) in grid.iter().enumerate() {
+        let value = residual(rho);
+        if value < best_residual {
+            best = i;
+            best_residual = value;
+        }
+    }
+    if !best_residual.is_finite() {
+        return None;
+    }
+
+    // Golden section search between the neighbors of the best grid point
+    let golden = (sqrt(5.0) - 1.0) / 2.0;
+    let mut lower = grid[best.saturating_sub(1)];
+    let mut upper = grid[usize::min(best + 1, SCAN_POINTS - 1)];
+    let mut left = upper - golden * (upper - lower);
+    let mut right = lower + golden * (upper - lower);
+    let mut left_residual = residual(left);
+    let mut right_residual = residual(right);
+    for _ in 0..200 {
+        if upper - lower < 1e-12 * upper {
+            break;
+        }
+
+        if left_residual < right_residual {
+            upper = right;
+            right = left;
+            right_residual = left_residual;
+            left = upper - golden * (upper - lower);
+            left_residual = residual(left);
+        } else {
+            lower = left;
+            left = right;
+            left_residual = right_residual;
+            right = lower + golden * (upper - lower);
+            right_residual = residual(right);
+        }
+    }
+
+    let rho = (lower + upper) / 2.0;
+    let (a, c, _) = fit(rho)?;
+    if a <= 0.0 || c <= 0.0 {
+        return None;
+    }
+
+    Some(Buckingham {
+        a: a,
+        c: c,
+        rho: rho,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use energy::{Harmonic, PairPotential};
+
+    #[test]
+    fn tabulate_potential() {
+        let potential = LennardJones {
+            sigma: 2.0,
+            epsilon: 0.8,
+        };
+        let table = tabulate(&potential, 1.5, 6.0, 10);
+        assert_eq!(table.x.len(), 10);
+        assert_eq!(table.x[0], 1.5);
+        assert_eq!(table.x[9], 6.0);
+        for i in 0..10 {
+            assert_eq!(table.energy[i], potential.energy(table.x[i]));
+            assert_eq!(table.force[i], potential.force(table.x[i]));
+        }
+
+        // Trait objects can also be tabulated
+        let potential: Box<PairPotential> = Box::new(potential);
+        assert_eq!(tabulate(&*potential, 1.5, 6.0, 10), table);
+    }
+
+    #[test]
+    #[should_panic]
+    fn tabulate_too_small() {
+        let _ = tabulate(&Harmonic { k: 1.0, x0: 0.0 }, 0.0, 1.0, 1);
+    }
+
+    #[test]
+    fn write() {
+        let table = tabulate(&Harmonic { k: 2.0, x0: 1.0 }, 0.0, 4.0, 3);
+        let mut buffer = Vec::new();
+        table.write(&mut buffer).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert_eq!(output, "# x energy force\n0 1 2\n2 1 -2\n4 9 -6\n");
+    }
+
+    #[test]
+    fn lennard_jones() {
+        let potential = LennardJones {
+            sigma: 3.4,
+            epsilon: 0.997,
+        };
+        let table = tabulate(&potential, 3.2, 12.0, 200);
+        let fitted = fit_lennard_jones(&table.x, &table.energy).unwrap();
+        assert_relative_eq!(fitted.sigma, 3.4, max_relative = 1e-10);
+        assert_relative_eq!(fitted.epsilon, 0.997, max_relative = 1e-10);
+
+        // Purely repulsive data can not be fitted with Lennard-Jones
+        let energies = table.x.iter()
+                              .map(|&r| f64::powi(r, -12) + 1e-3 * f64::powi(r, -6))
+                              .collect::<Vec<_>>();
+        assert!(fit_lennard_jones(&table.x, &energies).is_none());
+
+        // Not enough data
+        assert!(fit_lennard_jones(&[3.0], &[-0.3]).is_none());
+        assert!(fit_lennard_jones(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn buckingham() {
+        let potential = Buckingham {
+            a: 2000.0,
+            c: 30.0,
+            rho: 0.25,
+        };
+        let table = tabulate(&potential, 1.8, 8.0, 300);
+        let fitted = fit_buckingham(&table.x, &table.energy).unwrap();
+        assert_relative_eq!(fitted.rho, 0.25, max_relative = 1e-6);
+        assert_relative_eq!(fitted.a, 2000.0, max_relative = 1e-4);
+        assert_relative_eq!(fitted.c, 30.0, max_relative = 1e-4);
+
+        // Fit a Buckingham potential on Lennard-Jones data
+        let lj = LennardJones {
+            sigma: 3.4,
+            epsilon: 0.997,
+        };
+        let table = tabulate(&lj, 3.4, 10.0, 100);
+        let fitted = fit_buckingham(&table.x, &table.energy).unwrap();
+        for (&r, &energy) in table.x.iter().zip(&table.energy).filter(|&(&r, _)| r > 3.8) {
+            assert!(f64::abs(fitted.energy(r) - energy) < 0.1 * lj.epsilon, "r = {}", r);
+        }
+
+        assert!(fit_buckingham(&[], &[]).is_none());
+    }
+}
@@ -2,7 +2,7 @@
 // Copyright (C) Lumol's contributors — BSD license
 
 use energy::{AnglePotential, BondPotential, DihedralPotential, PairPotential};
-use energy::Potential;
+use energy::{Potential, WallPotential};
 use math::*;
 use std::f64::consts::PI;
 
@@ -46,6 +46,7 @@ impl PairPotential for NullPotential {
 impl BondPotential for NullPotential {}
 impl AnglePotential for NullPotential {}
 impl DihedralPotential for NullPotential {}
+impl WallPotential for NullPotential {}
 
 /// Lennard-Jones potential.
 ///
@@ -104,6 +105,55 @@ impl PairPotential for LennardJones {
     }
 }
 
+/// Mixing rules, used to get the parameters of Lennard-Jones interactions
+/// between different particles from the parameters for each particle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixingRule {
+    /// Lorentz-Berthelot rule: arithmetic mean for `sigma` and geometric mean
+    /// for `epsilon`.
+    ///
+    /// $$ \sigma_{ij} = \frac{\sigma_i + \sigma_j}{2} \qquad
+    ///    \epsilon_{ij} = \sqrt{\epsilon_i \epsilon_j} $$
+    LorentzBerthelot,
+    /// Geometric rule, as used in the OPLS force field: geometric mean for
+    /// both `sigma` and `epsilon`.
+    ///
+    /// $$ \sigma_{ij} = \sqrt{\sigma_i \sigma_j} \qquad
+    ///    \epsilon_{ij} = \sqrt{\epsilon_i \epsilon_j} $$
+    Geometric,
+}
+
+impl LennardJones {
+    /// Get the Lennard-Jones potential between particles described by `self`
+    /// and particles described by `other`, using the given mixing `rule`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::energy::{LennardJones, MixingRule};
+    /// let first = LennardJones { sigma: 2.0, epsilon: 1.0 };
+    /// let second = LennardJones { sigma: 8.0, epsilon: 4.0 };
+    ///
+    /// let mixed = first.mix(&second, MixingRule::LorentzBerthelot);
+    /// assert_eq!(mixed.sigma, 5.0);
+    /// assert_eq!(mixed.epsilon, 2.0);
+    ///
+    /// let mixed = first.mix(&second, MixingRule::Geometric);
+    /// assert_eq!(mixed.sigma, 4.0);
+    /// assert_eq!(mixed.epsilon, 2.0);
+    /// ```
+    pub fn mix(&self, other: &LennardJones, rule: MixingRule) -> LennardJones {
+        let sigma = match rule {
+            MixingRule::LorentzBerthelot => 0.5 * (self.sigma + other.sigma),
+            MixingRule::Geometric => f64::sqrt(self.sigma * other.sigma),
+        };
+        LennardJones {
+            sigma: sigma,
+            epsilon: f64::sqrt(self.epsilon * other.epsilon),
+        }
+    }
+}
+
 /// Harmonic potential.
 ///
 /// $$ V(x) = \frac{1}{2} k (x - x_0)^2 $$
@@ -156,6 +206,7 @@ impl PairPotential for Harmonic {
 impl BondPotential for Harmonic {}
 impl AnglePotential for Harmonic {}
 impl DihedralPotential for Harmonic {}
+impl WallPotential for Harmonic {}
 
 /// Cosine harmonic potential.
 ///
@@ -207,6 +258,98 @@ impl Potential for CosineHarmonic {
 impl AnglePotential for CosineHarmonic {}
 impl DihedralPotential for CosineHarmonic {}
 
+/// Cosine angle potential.
+///
+/// $$ V(x) = k \left[1 + \cos(x) \right] $$
+///
+/// where $k$ is the force constant. The minimum of this potential is at
+/// $x = \pi$, and it is mainly used for the stiffness of coarse-grained
+/// polymers chains.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::CosineAngle;
+/// # use std::f64::consts::PI;
+/// let potential = CosineAngle { k: 10.0 };
+/// assert_eq!(potential.energy(PI), 0.0);
+/// assert_eq!(potential.energy(0.0), 20.0);
+///
+/// assert_eq!(potential.force(PI / 2.0), 10.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct CosineAngle {
+    /// Force constant
+    pub k: f64,
+}
+
+impl Potential for CosineAngle {
+    fn energy(&self, x: f64) -> f64 {
+        self.k * (1.0 + cos(x))
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        self.k * sin(x)
+    }
+}
+
+impl AnglePotential for CosineAngle {}
+
+/// Cosine squared angle potential.
+///
+/// $$ V(x) = k \left[\cos(x) - \cos(x_0) \right]^2 $$
+///
+/// where $x_0$ is the equilibrium value, and $k$ the force constant. This is
+/// the harmonic-in-cosine potential used by the GROMOS force field and many
+/// coarse-grained models. It only differs from the
+/// [`CosineHarmonic`](struct.CosineHarmonic.html) potential by the absence of
+/// the $\frac{1}{2}$ factor, making it easier to use force constants from
+/// these force fields directly.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::CosineSquaredAngle;
+/// let potential = CosineSquaredAngle::new(/*k*/ 100.0, /*x0*/ 2.0);
+/// assert_eq!(potential.energy(2.0), 0.0);
+/// assert_eq!(potential.energy(3.0), 32.929884156201105);
+///
+/// assert_eq!(potential.force(2.0), 0.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct CosineSquaredAngle {
+    /// Force constant
+    k: f64,
+    /// Cosine of the equilibrium value
+    cos_x0: f64,
+}
+
+impl CosineSquaredAngle {
+    /// Create a new `CosineSquaredAngle` potentials, with force constant of
+    /// `k` and equilibrium value of `x0`
+    pub fn new(k: f64, x0: f64) -> CosineSquaredAngle {
+        CosineSquaredAngle {
+            k: k,
+            cos_x0: cos(x0),
+        }
+    }
+}
+
+impl Potential for CosineSquaredAngle {
+    fn energy(&self, x: f64) -> f64 {
+        let dr = cos(x) - self.cos_x0;
+        self.k * dr * dr
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        2.0 * self.k * (cos(x) - self.cos_x0) * sin(x)
+    }
+}
+
+impl AnglePotential for CosineSquaredAngle {}
+
 /// Torsion potential.
 ///
 /// This potential is intended for use with dihedral angles, using a custom
@@ -255,6 +398,161 @@ impl Potential for Torsion {
 
 impl DihedralPotential for Torsion {}
 
+/// CHARMM dihedral potential.
+///
+/// This potential is a sum of [`Torsion`](struct.Torsion.html) terms with
+/// different multiplicities, as used in the CHARMM force field for dihedral
+/// angles.
+///
+/// $$ V(x) = \sum_i k_i (1 + \cos(n_i x - \delta_i))$$
+///
+/// where $k_i$ are the force constants, $n_i$ the multiplicities, and
+/// $\delta_i$ the phase angles of the terms.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::{CharmmDihedral, Torsion};
+/// # use std::f64::consts::PI;
+/// let potential = CharmmDihedral {
+///     terms: vec![
+///         Torsion { k: 10.0, n: 1, delta: 0.0 },
+///         Torsion { k: 5.0, n: 3, delta: PI },
+///     ]
+/// };
+/// assert_eq!(potential.energy(0.0), 20.0);
+/// assert!(potential.force(0.0).abs() < 1e-12);
+/// ```
+#[derive(Clone)]
+pub struct CharmmDihedral {
+    /// Terms of the potential
+    pub terms: Vec<Torsion>,
+}
+
+impl Potential for CharmmDihedral {
+    fn energy(&self, phi: f64) -> f64 {
+        self.terms.iter().map(|term| term.energy(phi)).sum()
+    }
+
+    fn force(&self, phi: f64) -> f64 {
+        self.terms.iter().map(|term| term.force(phi)).sum()
+    }
+}
+
+impl DihedralPotential for CharmmDihedral {}
+
+/// OPLS dihedral potential.
+///
+/// This potential is the four terms Fourier series used for dihedral angles
+/// in the OPLS force field.
+///
+/// $$ V(x) = \frac{1}{2} \left[ V_1 (1 + \cos x) + V_2 (1 - \cos 2x)
+///    + V_3 (1 + \cos 3x) + V_4 (1 - \cos 4x) \right] $$
+///
+/// where $V_1$, $V_2$, $V_3$ and $V_4$ are the Fourier coefficients.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::Opls;
+/// # use std::f64::consts::PI;
+/// let potential = Opls { v1: 2.0, v2: 4.0, v3: 6.0, v4: 8.0 };
+/// assert_eq!(potential.energy(0.0), 8.0);
+/// assert!(potential.energy(PI).abs() < 1e-12);
+///
+/// assert_eq!(potential.force(0.0), 0.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Opls {
+    /// First Fourier coefficient
+    pub v1: f64,
+    /// Second Fourier coefficient
+    pub v2: f64,
+    /// Third Fourier coefficient
+    pub v3: f64,
+    /// Fourth Fourier coefficient
+    pub v4: f64,
+}
+
+impl Potential for Opls {
+    fn energy(&self, phi: f64) -> f64 {
+        0.5 * (
+            self.v1 * (1.0 + cos(phi)) +
+            self.v2 * (1.0 - cos(2.0 * phi)) +
+            self.v3 * (1.0 + cos(3.0 * phi)) +
+            self.v4 * (1.0 - cos(4.0 * phi))
+        )
+    }
+
+    fn force(&self, phi: f64) -> f64 {
+        0.5 * (
+            self.v1 * sin(phi) -
+            2.0 * self.v2 * sin(2.0 * phi) +
+            3.0 * self.v3 * sin(3.0 * phi) -
+            4.0 * self.v4 * sin(4.0 * phi)
+        )
+    }
+}
+
+impl DihedralPotential for Opls {}
+
+/// Ryckaert-Bellemans dihedral potential.
+///
+/// This potential is a polynomial in the cosine of the dihedral angle, and is
+/// used by many force fields converted to the GROMACS format.
+///
+/// $$ V(x) = \sum_{n = 0}^5 C_n \cos^n(x - \pi) $$
+///
+/// where $C_n$ are the polynomial coefficients. Following the polymer
+/// convention, the angle in the cosine is $x - \pi$, so that the *trans*
+/// conformation corresponds to $x = \pi$.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::RyckaertBellemans;
+/// # use std::f64::consts::PI;
+/// let potential = RyckaertBellemans { c: [9.28, 12.16, -13.12, -3.06, 26.24, -31.5] };
+/// assert_eq!(potential.energy(0.0), 44.8);
+///
+/// // trans conformation
+/// assert!(potential.energy(PI).abs() < 1e-12);
+/// assert!(potential.force(PI).abs() < 1e-12);
+/// ```
+#[derive(Clone, Copy)]
+pub struct RyckaertBellemans {
+    /// Polynomial coefficients, from $C_0$ to $C_5$
+    pub c: [f64; 6],
+}
+
+impl Potential for RyckaertBellemans {
+    fn energy(&self, phi: f64) -> f64 {
+        let cos_psi = -cos(phi);
+        // Horner evaluation of the polynomial
+        let mut energy = 0.0;
+        for &c in self.c.iter().rev() {
+            energy = energy * cos_psi + c;
+        }
+        return energy;
+    }
+
+    fn force(&self, phi: f64) -> f64 {
+        let cos_psi = -cos(phi);
+        let sin_psi = -sin(phi);
+        // Horner evaluation of the derivative of the polynomial
+        let mut derivative = 0.0;
+        for (n, &c) in self.c.iter().enumerate().skip(1).rev() {
+            derivative = derivative * cos_psi + (n as f64) * c;
+        }
+        return derivative * sin_psi;
+    }
+}
+
+impl DihedralPotential for RyckaertBellemans {}
+
 /// Buckingham potential.
 ///
 /// $$ V(x) = A \exp \left(\frac{\sigma - r}{\rho} \right) - \frac{C}{r^6} $$
@@ -369,7 +667,7 @@ impl PairPotential for BornMayerHuggins {
         let rc2 = rc * rc;
         let rc3 = rc2 * rc;
         let exp = exp((self.sigma - rc) / self.rho);
-        let factor = rc2 - 2.0 * rc * self.rho + 2.0 * self.rho * self.rho;
+        let factor = rc2 + 2.0 * rc * self.rho + 2.0 * self.rho * self.rho;
         self.a * self.rho * exp * factor - self.c / (3.0 * rc3) + self.d / (5.0 * rc2 * rc3)
     }
 
@@ -379,7 +677,7 @@ impl PairPotential for BornMayerHuggins {
         let exp = exp((self.sigma - rc) / self.rho);
         let factor = rc3 + 3.0 * rc2 * self.rho + 6.0 * rc * self.rho * self.rho +
                      6.0 * self.rho * self.rho * self.rho;
-        self.a * exp * factor - 20.0 * self.c / rc3 + 8.0 * self.d / (5.0 * rc2 * rc3)
+        self.a * exp * factor - 2.0 * self.c / rc3 + 8.0 * self.d / (5.0 * rc2 * rc3)
     }
 }
 
@@ -493,6 +791,84 @@ impl PairPotential for Gaussian {
     }
 }
 
+/// Soft repulsive potential.
+///
+/// $$ V(r) = a \left(1 - \frac r{r_c} \right)^2 $$
+///
+/// for $r < r_c$, and 0 after $r_c$. $a$ is the maximal repulsion energy and
+/// $r_c$ the range of the potential. This potential is finite at $r = 0$, and
+/// is commonly used for the conservative interactions in dissipative
+/// particle dynamics (DPD) and other coarse-grained soft matter models.
+///
+/// # Restrictions
+///
+/// $r_c$ has to be positive
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::SoftRepulsive;
+/// let potential = SoftRepulsive::new(25.0, 1.0);
+/// assert_eq!(potential.energy(0.0), 25.0);
+/// assert_eq!(potential.force(0.0), 50.0);
+///
+/// assert_eq!(potential.energy(0.5), 6.25);
+/// assert_eq!(potential.force(0.5), 25.0);
+///
+/// assert_eq!(potential.energy(1.5), 0.0);
+/// assert_eq!(potential.force(1.5), 0.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct SoftRepulsive {
+    /// Maximal repulsion energy, at zero distance
+    a: f64,
+    /// Range of the potential
+    rc: f64,
+}
+
+impl SoftRepulsive {
+    /// Create a new `SoftRepulsive` potential with a maximal energy of `a` and
+    /// a range of `rc`
+    pub fn new(a: f64, rc: f64) -> SoftRepulsive {
+        if rc <= 0.0 {
+            panic!("\"rc\" has to be positive in soft repulsive potential")
+        }
+        SoftRepulsive { a: a, rc: rc }
+    }
+}
+
+impl Potential for SoftRepulsive {
+    fn energy(&self, r: f64) -> f64 {
+        if r < self.rc {
+            let x = 1.0 - r / self.rc;
+            self.a * x * x
+        } else {
+            0.0
+        }
+    }
+
+    fn force(&self, r: f64) -> f64 {
+        if r < self.rc {
+            2.0 * self.a / self.rc * (1.0 - r / self.rc)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl PairPotential for SoftRepulsive {
+    // The potential is exactly zero after its range, so there is no tail
+    // correction as long as the cutoff is larger than the range.
+    fn tail_energy(&self, _: f64) -> f64 {
+        0.0
+    }
+
+    fn tail_virial(&self, _: f64) -> f64 {
+        0.0
+    }
+}
+
 /// Mie potential.
 ///
 /// This is a generalization of the Lennard-Jones potential with arbitrary
@@ -593,6 +969,377 @@ impl PairPotential for Mie {
     }
 }
 
+/// 12-6-4 Lennard-Jones potential.
+///
+/// $$ V(r) = 4 \epsilon \left[ \left(\frac \sigma r \right)^{12} -
+///    \left(\frac \sigma r \right)^6 \right] - \frac{C_4}{r^4} $$
+///
+/// where $\sigma$ and $\epsilon$ are the usual Lennard-Jones parameters, and
+/// $C_4$ accounts for the interaction between the charge of an ion and the
+/// dipole it induces in the other particle. This potential was introduced by
+/// Li and Merz to model divalent metal ions in water (J. Chem. Theory Comput.
+/// 2014, 10, 289–297).
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::LennardJones1264;
+/// let potential = LennardJones1264 { sigma: 2.0, epsilon: 10.0, c4: 16.0 };
+/// assert_eq!(potential.energy(2.0), -1.0);
+/// assert_eq!(potential.force(2.0), 118.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct LennardJones1264 {
+    /// Distance constant of the Lennard-Jones part
+    pub sigma: f64,
+    /// Energy constant of the Lennard-Jones part
+    pub epsilon: f64,
+    /// Strength of the ion-induced dipole interaction
+    pub c4: f64,
+}
+
+impl Potential for LennardJones1264 {
+    fn energy(&self, r: f64) -> f64 {
+        let s6 = f64::powi(self.sigma / r, 6);
+        4.0 * self.epsilon * (f64::powi(s6, 2) - s6) - self.c4 / f64::powi(r, 4)
+    }
+
+    fn force(&self, r: f64) -> f64 {
+        let s6 = f64::powi(self.sigma / r, 6);
+        let lj = -24.0 * self.epsilon * (s6 - 2.0 * f64::powi(s6, 2)) / r;
+        lj - 4.0 * self.c4 / f64::powi(r, 5)
+    }
+}
+
+impl PairPotential for LennardJones1264 {
+    fn tail_energy(&self, cutoff: f64) -> f64 {
+        let s3 = self.sigma * self.sigma * self.sigma;
+        let rc3 = cutoff * cutoff * cutoff;
+        let s9 = s3 * s3 * s3;
+        let rc9 = rc3 * rc3 * rc3;
+        4.0 / 3.0 * self.epsilon * s3 * (1.0 / 3.0 * s9 / rc9 - s3 / rc3) - self.c4 / cutoff
+    }
+
+    fn tail_virial(&self, cutoff: f64) -> f64 {
+        let s3 = self.sigma * self.sigma * self.sigma;
+        let rc3 = cutoff * cutoff * cutoff;
+        let s9 = s3 * s3 * s3;
+        let rc9 = rc3 * rc3 * rc3;
+        8.0 * self.epsilon * s3 * (2.0 / 3.0 * s9 / rc9 - s3 / rc3) - 4.0 * self.c4 / cutoff
+    }
+}
+
+/// 9-3 Lennard-Jones potential, for the interaction between a particle and
+/// a flat wall made of Lennard-Jones particles.
+///
+/// $$ V(z) = \epsilon \left[ \frac{2}{15} \left(\frac \sigma z \right)^9 -
+///    \left(\frac \sigma z \right)^3 \right] $$
+///
+/// where $z$ is the distance between the particle and the wall, $\sigma$ the
+/// distance constant and $\epsilon$ the energetic constant. This potential is
+/// the integral of the [Lennard-Jones](struct.LennardJones.html) potential
+/// over a semi-infinite solid, with all the constants included in $\sigma$
+/// and $\epsilon$.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::LennardJones93;
+/// let potential = LennardJones93 { sigma: 2.0, epsilon: 1.5 };
+/// assert_eq!(potential.energy(2.0), -1.3);
+/// assert_eq!(potential.force(2.0), -1.35);
+///
+/// // The minimum of the potential is at (2/5)^(1/6) sigma
+/// assert!(potential.force(2.0 * f64::powf(0.4, 1.0 / 6.0)).abs() < 1e-12);
+/// ```
+#[derive(Clone, Copy)]
+pub struct LennardJones93 {
+    /// Distance constant of the potential
+    pub sigma: f64,
+    /// Energy constant of the potential
+    pub epsilon: f64,
+}
+
+impl Potential for LennardJones93 {
+    fn energy(&self, z: f64) -> f64 {
+        let s3 = f64::powi(self.sigma / z, 3);
+        self.epsilon * (2.0 / 15.0 * s3 * s3 * s3 - s3)
+    }
+
+    fn force(&self, z: f64) -> f64 {
+        let s3 = f64::powi(self.sigma / z, 3);
+        self.epsilon * (6.0 / 5.0 * s3 * s3 * s3 - 3.0 * s3) / z
+    }
+}
+
+impl WallPotential for LennardJones93 {}
+
+
+/// Finite extensible nonlinear elastic (FENE) potential.
+///
+/// $$ V(r) = - \frac{1}{2} k r_0^2 \ln \left[1 - \left(\frac{r}{r_0}
+///    \right)^2 \right] $$
+///
+/// where $k$ is the spring constant and $r_0$ the maximal extension of the
+/// bond. This potential is mainly used for bonds in bead-spring polymer models
+/// such as the Kremer-Grest model.
+///
+/// # Restrictions
+///
+/// The potential diverges when $r$ goes to $r_0$. For $r \geq r_0$ the energy
+/// is infinite, and the force is set to $-\infty$ to pull the particles back
+/// together.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::Fene;
+/// // Kremer-Grest parameters in reduced units
+/// let potential = Fene { k: 30.0, r0: 1.5 };
+/// assert_eq!(potential.energy(0.0), 0.0);
+/// assert!(f64::abs(potential.energy(1.0) - 19.837799940446516) < 1e-12);
+/// assert_eq!(potential.force(1.0), -54.0);
+///
+/// assert_eq!(potential.energy(1.5), f64::INFINITY);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Fene {
+    /// Spring constant
+    pub k: f64,
+    /// Maximal extension of the bond
+    pub r0: f64,
+}
+
+impl Potential for Fene {
+    fn energy(&self, r: f64) -> f64 {
+        if r >= self.r0 {
+            warn_once!("FENE bond extended beyond its maximal extension, the energy is infinite");
+            return f64::INFINITY;
+        }
+        let x = r / self.r0;
+        -0.5 * self.k * self.r0 * self.r0 * f64::ln(1.0 - x * x)
+    }
+
+    fn force(&self, r: f64) -> f64 {
+        if r >= self.r0 {
+            warn_once!("FENE bond extended beyond its maximal extension, the force is infinite");
+            return f64::NEG_INFINITY;
+        }
+        let x = r / self.r0;
+        -self.k * r / (1.0 - x * x)
+    }
+}
+
+impl BondPotential for Fene {}
+
+/// Quartic potential.
+///
+/// $$ V(x) = k_2 (x - x_0)^2 + k_3 (x - x_0)^3 + k_4 (x - x_0)^4 $$
+///
+/// where $x_0$ is the equilibrium value, and $k_2$, $k_3$ and $k_4$ are the
+/// force constants. This is the bond stretching term of class-2 force fields
+/// such as COMPASS. Note that there is no $\frac{1}{2}$ factor in front of
+/// the quadratic term.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::Quartic;
+/// let potential = Quartic { x0: 1.5, k2: 300.0, k3: -650.0, k4: 1300.0 };
+/// assert_eq!(potential.energy(1.5), 0.0);
+/// assert_eq!(potential.energy(2.5), 950.0);
+///
+/// assert_eq!(potential.force(1.5), 0.0);
+/// assert_eq!(potential.force(2.5), -3850.0);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Quartic {
+    /// Equilibrium value
+    pub x0: f64,
+    /// Quadratic force constant
+    pub k2: f64,
+    /// Cubic force constant
+    pub k3: f64,
+    /// Quartic force constant
+    pub k4: f64,
+}
+
+impl Potential for Quartic {
+    fn energy(&self, x: f64) -> f64 {
+        let dx = x - self.x0;
+        let dx2 = dx * dx;
+        dx2 * (self.k2 + self.k3 * dx + self.k4 * dx2)
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        let dx = x - self.x0;
+        -dx * (2.0 * self.k2 + 3.0 * self.k3 * dx + 4.0 * self.k4 * dx * dx)
+    }
+}
+
+impl BondPotential for Quartic {}
+
+/// Point after which the force of a [`CappedBond`](struct.CappedBond.html) is
+/// constant.
+#[derive(Clone, Copy)]
+struct ForceCap {
+    /// Position of the cap
+    x: f64,
+    /// Energy of the initial potential at the cap
+    energy: f64,
+    /// Force of the initial potential at the cap
+    force: f64,
+}
+
+/// Find the point where the norm of the force of `potential` becomes larger
+/// than `max_force`, going from `reference` in the given `direction`. When
+/// going in the negative direction, the search stops at zero. This function
+/// returns `None` if no such point exists.
+fn find_force_cap(
+    potential: &BondPotential,
+    reference: f64,
+    direction: f64,
+    max_force: f64,
+) -> Option<ForceCap> {
+    // Infinite and NaN forces are also considered as too large
+    let too_large = |x: f64| !(f64::abs(potential.force(x)) <= max_force);
+
+    let step = 1e-3 * f64::max(reference.abs(), 1.0);
+    let mut good = reference;
+    let mut bad = None;
+    for n in 0..64 {
+        let mut x = reference + direction * step * f64::powi(2.0, n);
+        if x < 0.0 {
+            x = 0.0;
+        }
+
+        if too_large(x) {
+            bad = Some(x);
+            break;
+        } else if x == 0.0 {
+            break;
+        }
+        good = x;
+    }
+
+    let mut bad = bad?;
+    for _ in 0..100 {
+        let middle = 0.5 * (good + bad);
+        if middle == good || middle == bad {
+            break;
+        }
+
+        if too_large(middle) {
+            bad = middle;
+        } else {
+            good = middle;
+        }
+    }
+
+    Some(ForceCap {
+        x: good,
+        energy: potential.energy(good),
+        force: potential.force(good),
+    })
+}
+
+/// Bond potential with a capped force.
+///
+/// This potential wraps another bond potential, and limits the norm of the
+/// force to a maximal value. Close to the `reference` value (usually the
+/// equilibrium bond length), the energy and the force are the ones of the
+/// initial potential. When the force of the initial potential becomes larger
+/// than the maximal force, the capped potential continues linearly, with a
+/// constant force. This allows to relax initial configurations containing
+/// badly stretched or compressed bonds without the integrator blowing up.
+///
+/// The points where the force reaches the maximal value are searched
+/// numerically on both sides of the reference value when creating the
+/// potential. The search for the lower point stops at zero.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_core::energy::Potential;
+/// # use lumol_core::energy::{CappedBond, Harmonic};
+/// let harmonic = Harmonic { k: 100.0, x0: 1.0 };
+/// let potential = CappedBond::new(Box::new(harmonic), 1.0, 50.0);
+///
+/// // The potential is unchanged where the force is small enough
+/// assert_eq!(potential.energy(1.2), harmonic.energy(1.2));
+/// assert_eq!(potential.force(1.2), harmonic.force(1.2));
+///
+/// // And linear where the force of the harmonic potential is too large
+/// assert!(f64::abs(potential.force(3.0) + 50.0) < 1e-9);
+/// assert!(f64::abs(potential.energy(3.0) - 87.5) < 1e-9);
+/// assert!(f64::abs(potential.force(0.2) - 50.0) < 1e-9);
+/// ```
+#[derive(Clone)]
+pub struct CappedBond {
+    /// Initial potential
+    potential: Box<BondPotential>,
+    /// Force cap at smaller values than the reference, if any
+    lower: Option<ForceCap>,
+    /// Force cap at larger values than the reference, if any
+    upper: Option<ForceCap>,
+}
+
+impl CappedBond {
+    /// Create a new `CappedBond` from the initial `potential`, limiting the
+    /// norm of the force to `max_force`. The force caps are searched on both
+    /// sides of the `reference` value.
+    ///
+    /// # Panics
+    ///
+    /// If `max_force` is not positive, or if the force at the `reference`
+    /// value is already larger than `max_force`.
+    pub fn new(potential: Box<BondPotential>, reference: f64, max_force: f64) -> CappedBond {
+        assert!(max_force > 0.0, "the maximal force must be positive in capped bond potential");
+        assert!(
+            f64::abs(potential.force(reference)) <= max_force,
+            "the force at the reference value is larger than the maximal force"
+        );
+
+        let lower = find_force_cap(&*potential, reference, -1.0, max_force);
+        let upper = find_force_cap(&*potential, reference, 1.0, max_force);
+        CappedBond {
+            potential: potential,
+            lower: lower,
+            upper: upper,
+        }
+    }
+
+    /// Get the force cap that applies at `x`, if any
+    fn cap(&self, x: f64) -> Option<ForceCap> {
+        match (self.lower, self.upper) {
+            (Some(lower), _) if x < lower.x => Some(lower),
+            (_, Some(upper)) if x > upper.x => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+impl Potential for CappedBond {
+    fn energy(&self, x: f64) -> f64 {
+        match self.cap(x) {
+            Some(cap) => cap.energy - cap.force * (x - cap.x),
+            None => self.potential.energy(x),
+        }
+    }
+
+    fn force(&self, x: f64) -> f64 {
+        match self.cap(x) {
+            Some(cap) => cap.force,
+            None => self.potential.force(x),
+        }
+    }
+}
+
+impl BondPotential for CappedBond {}
 
 #[cfg(test)]
 mod tests {
@@ -642,6 +1389,92 @@ mod tests {
         assert_relative_eq!((e0 - e1) / EPS, lj.force(4.0), epsilon = 1e-6);
     }
 
+    #[test]
+    fn lj_mixing() {
+        let first = LennardJones {
+            epsilon: 0.8,
+            sigma: 2.0,
+        };
+        let second = LennardJones {
+            epsilon: 0.2,
+            sigma: 3.0,
+        };
+
+        let mixed = first.mix(&second, MixingRule::LorentzBerthelot);
+        assert_eq!(mixed.sigma, 2.5);
+        assert_ulps_eq!(mixed.epsilon, 0.4);
+
+        let mixed = first.mix(&second, MixingRule::Geometric);
+        assert_ulps_eq!(mixed.sigma, f64::sqrt(6.0));
+        assert_ulps_eq!(mixed.epsilon, 0.4);
+
+        // Mixing is symmetric, and mixing a potential with itself does not
+        // change it
+        let other = second.mix(&first, MixingRule::LorentzBerthelot);
+        assert_eq!(other.sigma, 2.5);
+        assert_ulps_eq!(other.epsilon, 0.4);
+
+        let same = first.mix(&first, MixingRule::Geometric);
+        assert_ulps_eq!(same.sigma, 2.0);
+        assert_ulps_eq!(same.epsilon, 0.8);
+    }
+
+    #[test]
+    fn lj_9_3() {
+        let potential = LennardJones93 {
+            epsilon: 0.8,
+            sigma: 2.0,
+        };
+        assert_relative_eq!(potential.energy(2.0), -0.6933333333333334, epsilon = 1e-12);
+        assert_relative_eq!(potential.energy(3.0), -0.2342623922505038, epsilon = 1e-12);
+        assert_relative_eq!(potential.force(3.0), -0.2287131026774373, epsilon = 1e-12);
+
+        let minimum = 2.0 * f64::powf(0.4, 1.0 / 6.0);
+        assert!(potential.force(minimum).abs() < 1e-12);
+
+        let e0 = potential.energy(2.5);
+        let e1 = potential.energy(2.5 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, potential.force(2.5), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn lj_12_6_4() {
+        let potential = LennardJones1264 {
+            epsilon: 0.5,
+            sigma: 2.0,
+            c4: 3.0,
+        };
+        assert_relative_eq!(potential.energy(2.5), -0.4636490465280001, epsilon = 1e-12);
+        assert_relative_eq!(potential.force(2.5), -0.7214642233344, epsilon = 1e-12);
+
+        assert_relative_eq!(potential.tail_energy(10.0), -0.3426657564444444, epsilon = 1e-12);
+        assert_relative_eq!(potential.tail_virial(10.0), -1.4559890773333333, epsilon = 1e-12);
+
+        // Without C4 term, this is a Lennard-Jones potential
+        let lj = LennardJones {
+            epsilon: 0.5,
+            sigma: 2.0,
+        };
+        let potential = LennardJones1264 {
+            epsilon: 0.5,
+            sigma: 2.0,
+            c4: 0.0,
+        };
+        assert_eq!(potential.energy(3.0), lj.energy(3.0));
+        assert_eq!(potential.force(3.0), lj.force(3.0));
+        assert_eq!(potential.tail_energy(8.0), lj.tail_energy(8.0));
+        assert_eq!(potential.tail_virial(8.0), lj.tail_virial(8.0));
+
+        let potential = LennardJones1264 {
+            epsilon: 0.5,
+            sigma: 2.0,
+            c4: 3.0,
+        };
+        let e0 = potential.energy(4.0);
+        let e1 = potential.energy(4.0 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, potential.force(4.0), epsilon = 1e-6);
+    }
+
     #[test]
     fn harmonic() {
         let harmonic = Harmonic { k: 50.0, x0: 2.0 };
@@ -675,6 +1508,41 @@ mod tests {
         assert_relative_eq!((e0 - e1) / EPS, harmonic.force(2.3), epsilon = 1e-6);
     }
 
+    #[test]
+    fn cosine_angle() {
+        let cosine = CosineAngle { k: 5.0 };
+        assert_eq!(cosine.energy(PI), 0.0);
+        assert_eq!(cosine.energy(2.5), 5.0 * (1.0 + cos(2.5)));
+
+        assert_eq!(cosine.force(PI / 2.0), 5.0);
+        assert_eq!(cosine.force(2.5), 5.0 * sin(2.5));
+
+        let e0 = cosine.energy(2.3);
+        let e1 = cosine.energy(2.3 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, cosine.force(2.3), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn cosine_squared_angle() {
+        let cosine = CosineSquaredAngle::new(50.0, 2.0);
+        assert_eq!(cosine.energy(2.0), 0.0);
+        let dcos = cos(2.5) - cos(2.0);
+        assert_eq!(cosine.energy(2.5), 50.0 * dcos * dcos);
+
+        assert_eq!(cosine.force(2.0), 0.0);
+        assert_eq!(cosine.force(2.5), 2.0 * 50.0 * dcos * sin(2.5));
+
+        // This is the same as the cosine harmonic potential with twice the
+        // force constant
+        let harmonic = CosineHarmonic::new(100.0, 2.0);
+        assert_ulps_eq!(cosine.energy(1.7), harmonic.energy(1.7));
+        assert_ulps_eq!(cosine.force(1.7), harmonic.force(1.7));
+
+        let e0 = cosine.energy(2.3);
+        let e1 = cosine.energy(2.3 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, cosine.force(2.3), epsilon = 1e-6);
+    }
+
     #[test]
     fn torsion() {
         let torsion = Torsion {
@@ -693,6 +1561,116 @@ mod tests {
         assert_relative_eq!((e0 - e1) / EPS, torsion.force(4.0), epsilon = 1e-6);
     }
 
+    #[test]
+    fn charmm_dihedral() {
+        let first = Torsion {
+            k: 0.2,
+            n: 1,
+            delta: 3.0,
+        };
+        let second = Torsion {
+            k: 0.4,
+            n: 2,
+            delta: 0.0,
+        };
+        let third = Torsion {
+            k: 0.3,
+            n: 6,
+            delta: PI,
+        };
+        let charmm = CharmmDihedral {
+            terms: vec![first, second, third],
+        };
+
+        for &phi in &[0.0, 1.1, 2.5] {
+            let energy = first.energy(phi) + second.energy(phi) + third.energy(phi);
+            let force = first.force(phi) + second.force(phi) + third.force(phi);
+            assert_eq!(charmm.energy(phi), energy);
+            assert_eq!(charmm.force(phi), force);
+        }
+
+        let empty = CharmmDihedral { terms: vec![] };
+        assert_eq!(empty.energy(1.0), 0.0);
+        assert_eq!(empty.force(1.0), 0.0);
+
+        for &phi in &[0.3, 1.2, 2.5, 4.0] {
+            let e0 = charmm.energy(phi);
+            let e1 = charmm.energy(phi + EPS);
+            assert_relative_eq!((e0 - e1) / EPS, charmm.force(phi), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn opls() {
+        let opls = Opls {
+            v1: 1.3,
+            v2: -0.05,
+            v3: 0.2,
+            v4: 0.1,
+        };
+        assert_eq!(opls.energy(0.0), 1.5);
+        assert_eq!(opls.force(0.0), 0.0);
+
+        let energy = 0.5 * (
+            1.3 * (1.0 + cos(1.1)) - 0.05 * (1.0 - cos(2.0 * 1.1)) +
+            0.2 * (1.0 + cos(3.0 * 1.1)) + 0.1 * (1.0 - cos(4.0 * 1.1))
+        );
+        assert_relative_eq!(opls.energy(1.1), energy, epsilon = 1e-12);
+
+        // Each term is a torsion potential
+        let torsion = Torsion {
+            k: 0.5 * 0.2,
+            n: 3,
+            delta: 0.0,
+        };
+        let single = Opls {
+            v1: 0.0,
+            v2: 0.0,
+            v3: 0.2,
+            v4: 0.0,
+        };
+        assert_relative_eq!(single.energy(2.0), torsion.energy(2.0), epsilon = 1e-12);
+        assert_relative_eq!(single.force(2.0), torsion.force(2.0), epsilon = 1e-12);
+
+        for &phi in &[0.3, 1.2, 2.5, 4.0] {
+            let e0 = opls.energy(phi);
+            let e1 = opls.energy(phi + EPS);
+            assert_relative_eq!((e0 - e1) / EPS, opls.force(phi), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn ryckaert_bellemans() {
+        let c = [9.28, 12.16, -13.12, -3.06, 26.24, -31.5];
+        let rb = RyckaertBellemans { c: c };
+        assert_relative_eq!(rb.energy(PI), 0.0, epsilon = 1e-12);
+        assert_ulps_eq!(rb.energy(0.0), 9.28 - 12.16 - 13.12 + 3.06 + 26.24 + 31.5);
+
+        let cos_psi = -cos(1.1);
+        let mut energy = 0.0;
+        for n in 0..6 {
+            energy += c[n] * f64::powi(cos_psi, n as i32);
+        }
+        assert_relative_eq!(rb.energy(1.1), energy, epsilon = 1e-12);
+
+        // Zero-th and first order terms are equivalent to a torsion potential
+        let rb = RyckaertBellemans { c: [2.0, 2.0, 0.0, 0.0, 0.0, 0.0] };
+        let torsion = Torsion {
+            k: 2.0,
+            n: 1,
+            delta: PI,
+        };
+        assert_relative_eq!(rb.energy(0.7), torsion.energy(0.7), epsilon = 1e-12);
+        assert_relative_eq!(rb.force(0.7), torsion.force(0.7), epsilon = 1e-12);
+
+        let rb = RyckaertBellemans { c: c };
+        for &phi in &[0.3, 1.2, 2.5, 4.0] {
+            let e0 = rb.energy(phi);
+            let e1 = rb.energy(phi + EPS);
+            assert_relative_eq!((e0 - e1) / EPS, rb.force(phi), epsilon = 1e-5);
+        }
+    }
+
     #[test]
     fn buckingham() {
         let buckingham = Buckingham {
@@ -727,8 +1705,23 @@ mod tests {
         assert_eq!(born.energy(2.0), 1.986328125);
         assert_eq!(born.force(2.0), 0.9609375);
 
-        assert_eq!(born.tail_energy(10.0), 4.981521444402363);
-        assert_eq!(born.tail_virial(10.0), 69.13986044386026);
+        assert_ulps_eq!(born.tail_energy(10.0), 10.842525888797299);
+        assert_ulps_eq!(born.tail_virial(10.0), 69.15786044386026);
+
+        // Check the tail corrections against a numerical integration
+        let (rc, n) = (10.0, 100_000);
+        let mut tail_energy = 0.0;
+        let mut tail_virial = 0.0;
+        for i in 0..n {
+            // Use r = rc / t to integrate on [rc, +∞)
+            let t = (i as f64 + 0.5) / n as f64;
+            let r = rc / t;
+            let dr = rc / (t * t * n as f64);
+            tail_energy += r * r * born.energy(r) * dr;
+            tail_virial += r * r * r * born.force(r) * dr;
+        }
+        assert_relative_eq!(born.tail_energy(rc), tail_energy, max_relative = 1e-8);
+        assert_relative_eq!(born.tail_virial(rc), tail_virial, max_relative = 1e-8);
 
         let e0 = born.energy(4.0);
         let e1 = born.energy(4.0 + EPS);
@@ -776,6 +1769,33 @@ mod tests {
         assert_eq!(gaussian.energy(0.0), -8.0);
     }
 
+    #[test]
+    fn soft_repulsive() {
+        let potential = SoftRepulsive::new(4.0, 2.0);
+        assert_eq!(potential.energy(0.0), 4.0);
+        assert_eq!(potential.energy(1.0), 1.0);
+        assert_eq!(potential.energy(2.0), 0.0);
+        assert_eq!(potential.energy(3.0), 0.0);
+
+        assert_eq!(potential.force(0.0), 4.0);
+        assert_eq!(potential.force(1.0), 2.0);
+        assert_eq!(potential.force(2.0), 0.0);
+        assert_eq!(potential.force(3.0), 0.0);
+
+        assert_eq!(potential.tail_energy(2.5), 0.0);
+        assert_eq!(potential.tail_virial(2.5), 0.0);
+
+        let e0 = potential.energy(0.7);
+        let e1 = potential.energy(0.7 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, potential.force(0.7), epsilon = 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "\"rc\" has to be positive")]
+    fn soft_repulsive_wrong_input() {
+        let _ = SoftRepulsive::new(4.0, -2.0);
+    }
+
     #[test]
     fn test_mie() {
         let mie = Mie::new(2.0, 0.8, 12.0, 6.0);
@@ -798,6 +1818,94 @@ mod tests {
         assert_relative_eq!((e0 - e1) / EPS, mie.force(4.0), epsilon = 1e-6);
     }
 
+    #[test]
+    fn fene() {
+        let fene = Fene { k: 30.0, r0: 1.5 };
+        assert_eq!(fene.energy(0.0), 0.0);
+        assert_eq!(fene.force(0.0), 0.0);
+
+        // Values for the Kremer-Grest bead-spring model, in reduced units
+        assert_relative_eq!(fene.energy(0.97), 18.278673907918918, epsilon = 1e-12);
+        assert_relative_eq!(fene.force(0.97), -50.01527767168283, epsilon = 1e-12);
+        assert_relative_eq!(fene.energy(1.2), 34.480729604204356, epsilon = 1e-12);
+        assert_relative_eq!(fene.force(1.2), -100.0, epsilon = 1e-12);
+
+        // Divergence guard
+        assert_eq!(fene.energy(1.5), f64::INFINITY);
+        assert_eq!(fene.energy(2.0), f64::INFINITY);
+        assert_eq!(fene.force(2.0), f64::NEG_INFINITY);
+
+        let e0 = fene.energy(1.1);
+        let e1 = fene.energy(1.1 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, fene.force(1.1), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn capped_bond() {
+        let harmonic = Harmonic { k: 100.0, x0: 1.0 };
+        let potential = CappedBond::new(Box::new(harmonic), 1.0, 50.0);
+        for &x in &[0.6, 1.0, 1.3, 1.45] {
+            assert_eq!(potential.energy(x), harmonic.energy(x));
+            assert_eq!(potential.force(x), harmonic.force(x));
+        }
+
+        assert_relative_eq!(potential.force(1.6), -50.0, epsilon = 1e-9);
+        assert_relative_eq!(potential.force(12.0), -50.0, epsilon = 1e-9);
+        assert_relative_eq!(potential.force(0.4), 50.0, epsilon = 1e-9);
+        assert_relative_eq!(potential.force(0.0), 50.0, epsilon = 1e-9);
+
+        // The energy is continuous and linear outside of the caps
+        assert_relative_eq!(potential.energy(1.5), 12.5, epsilon = 1e-9);
+        assert_relative_eq!(potential.energy(2.0), 37.5, epsilon = 1e-9);
+        assert_relative_eq!(potential.energy(0.0), 37.5, epsilon = 1e-9);
+
+        for &x in &[0.2, 0.7, 1.2, 2.5] {
+            let e0 = potential.energy(x);
+            let e1 = potential.energy(x + EPS);
+            assert_relative_eq!((e0 - e1) / EPS, potential.force(x), epsilon = 1e-6);
+        }
+
+        // No cap when the force never becomes large enough
+        let potential = CappedBond::new(Box::new(harmonic), 1.0, 500.0);
+        assert_eq!(potential.force(0.0), 100.0);
+        assert_relative_eq!(potential.force(7.0), -500.0, epsilon = 1e-9);
+
+        // Diverging potentials can also be capped
+        let fene = Fene { k: 30.0, r0: 1.5 };
+        let potential = CappedBond::new(Box::new(fene), 0.0, 100.0);
+        assert_eq!(potential.energy(1.0), fene.energy(1.0));
+        assert_relative_eq!(potential.force(1.4), -100.0, epsilon = 1e-9);
+        assert_relative_eq!(potential.force(3.0), -100.0, epsilon = 1e-9);
+        assert!(potential.energy(3.0).is_finite());
+    }
+
+    #[test]
+    #[should_panic(expected = "the force at the reference value is larger than the maximal force")]
+    fn capped_bond_wrong_reference() {
+        let _ = CappedBond::new(Box::new(Harmonic { k: 100.0, x0: 1.0 }), 3.0, 50.0);
+    }
+
+    #[test]
+    fn quartic() {
+        let quartic = Quartic {
+            x0: 1.5,
+            k2: 300.0,
+            k3: -650.0,
+            k4: 1300.0,
+        };
+        assert_eq!(quartic.energy(1.5), 0.0);
+        assert_eq!(quartic.force(1.5), 0.0);
+
+        assert_relative_eq!(quartic.energy(1.7), 8.88, epsilon = 1e-12);
+        assert_relative_eq!(quartic.force(1.7), -83.6, epsilon = 1e-12);
+        assert_relative_eq!(quartic.energy(1.3), 19.28, epsilon = 1e-12);
+        assert_relative_eq!(quartic.force(1.3), 239.6, epsilon = 1e-12);
+
+        let e0 = quartic.energy(1.6);
+        let e1 = quartic.energy(1.6 + EPS);
+        assert_relative_eq!((e0 - e1) / EPS, quartic.force(1.6), epsilon = 1e-5);
+    }
+
     #[test]
     #[should_panic(expected = "The repulsive exponent n has to be larger than the attractive exponent m")]
     fn test_mie_n_lower_m() {
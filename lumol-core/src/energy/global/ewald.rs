@@ -10,7 +10,7 @@ use std::f64;
 use rayon::prelude::*;
 
 use math::*;
-use sys::{Configuration, UnitCell, CellShape};
+use sys::{Configuration, UnitCell, CellShape, SharedNeighborList};
 use types::{Matrix3, Vector3D, Array3, Complex};
 use consts::FOUR_PI_EPSILON_0;
 use energy::{PairRestriction, RestrictionInfo};
@@ -144,6 +144,10 @@ impl EwaldFactors {
         let kmax3d = 4 * kmax * kmax * kmax + 6 * kmax * kmax + 3 * kmax;
         self.reserve(kmax3d as usize);
 
+        assert!(
+            cell.is_infinite() || cell.is_fully_periodic(),
+            "Ewald is only defined for cells periodic in all directions"
+        );
         match cell.shape() {
             CellShape::Infinite => panic!("Ewald is not defined with infinite unit cell"),
             CellShape::Orthorhombic => self.compute_ortho(cell, parameters),
@@ -285,6 +289,8 @@ pub struct Ewald {
     previous_cell: Option<UnitCell>,
     /// Update the cached quantities
     updater: Option<Box<Fn(&mut Ewald) + Sync + Send>>,
+    /// Neighbor list for the real-space pairs
+    neighbors: SharedNeighborList,
 }
 
 impl Clone for Ewald {
@@ -298,6 +304,7 @@ impl Clone for Ewald {
             efield: self.efield.clone(),
             previous_cell: self.previous_cell,
             updater: None,
+            neighbors: self.neighbors.clone(),
         }
     }
 }
@@ -340,6 +347,7 @@ impl Ewald {
             efield: Vec::new(),
             previous_cell: None,
             updater: None,
+            neighbors: SharedNeighborList::default(),
         }
     }
 
@@ -470,6 +478,7 @@ impl Ewald {
     fn real_space_energy(&self, configuration: &Configuration) -> f64 {
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.rc);
 
         let energies = (0..natoms).into_par_iter().map(|i| {
             let mut local_energy = 0.0;
@@ -478,7 +487,7 @@ impl Ewald {
                 return 0.0;
             }
 
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -502,6 +511,7 @@ impl Ewald {
 
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.rc);
         // Each thread (and not each iteration of the loop below) get its own
         // storage in a `ThreadLocalVec`.
         let thread_local_forces = ThreadLocalVec::with_size(natoms);
@@ -516,7 +526,7 @@ impl Ewald {
                 return;
             }
 
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -542,6 +552,7 @@ impl Ewald {
     fn real_space_atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.rc);
 
         let virials = (0..natoms).into_par_iter().map(|i| {
             let qi = charges[i];
@@ -550,7 +561,7 @@ impl Ewald {
             }
             let mut local_virial = Matrix3::zero();
 
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -650,6 +661,40 @@ impl Ewald {
 
         return new_energy - old_energy;
     }
+
+    /// Real space contribution to the energy of all the pairs containing at
+    /// least one particle of the molecule at `molecule_id`
+    fn real_space_molecule_energy(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let charges = configuration.particles().charge;
+        let indexes = configuration.molecule(molecule_id).indexes();
+
+        let mut energy = 0.0;
+        for i in indexes.clone() {
+            let qi = charges[i];
+            if qi == 0.0 {
+                continue;
+            }
+
+            for j in 0..configuration.size() {
+                // Pairs inside the molecule are only counted once
+                if j == i || (indexes.start <= j && j < indexes.end && j < i) {
+                    continue;
+                }
+
+                let qj = charges[j];
+                if qj == 0.0 {
+                    continue;
+                }
+
+                let path = configuration.bond_path(i, j);
+                let info = self.restriction.information(path);
+
+                let r = configuration.distance(i, j);
+                energy += self.real_space_energy_pair(info, qi * qj, r);
+            }
+        }
+        return energy;
+    }
 }
 
 /// Self-interaction correction
@@ -663,6 +708,17 @@ impl Ewald {
                               .sum::<f64>();
         return -self.alpha / sqrt(PI) * q2 / FOUR_PI_EPSILON_0;
     }
+
+    /// Self-interaction contribution to the energy of the molecule at
+    /// `molecule_id`
+    fn molecule_self_energy(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let charges = configuration.particles().charge;
+        let q2 = configuration.molecule(molecule_id)
+                              .indexes()
+                              .map(|i| charges[i] * charges[i])
+                              .sum::<f64>();
+        return -self.alpha / sqrt(PI) * q2 / FOUR_PI_EPSILON_0;
+    }
 }
 
 
@@ -791,40 +847,48 @@ impl Ewald {
         return atomic - correction;
     }
 
-    /// Compute the Fourier transform of the electrostatic density changes
-    /// while moving the molecule with the given `molecule_id` to
-    /// `new_positions`
-    fn delta_rho_move_rigid_molecules(
-        &mut self,
-        configuration: &Configuration,
-        molecule_id: usize,
-        new_positions: &[Vector3D],
-    ) -> Vec<Complex> {
-        let molecule = configuration.molecule(molecule_id);
-        let mut new_energyikr = Ewald3DArray::zeros((-self.kmax..(self.kmax + 1), 3, molecule.size()));
+    /// Compute the phase factors `e^{i k r}` for particles at the given
+    /// `positions` in the `cell`
+    fn phases(&self, cell: &UnitCell, positions: &[Vector3D]) -> Ewald3DArray {
+        let mut eikr = Ewald3DArray::zeros((-self.kmax..(self.kmax + 1), 3, positions.len()));
 
         // Do the k=0, 1 cases first
         for spatial in 0..3 {
             let mut k_idx = [0.0, 0.0, 0.0];
             k_idx[spatial] = 1.0;
-            let kvec = configuration.cell.k_vector(k_idx);
-            for i in 0..molecule.size() {
-                new_energyikr[(0, spatial, i)] = Complex::cartesian(1.0, 0.0);
-                new_energyikr[(1, spatial, i)] = Complex::polar(1.0, kvec * new_positions[i]);
-                new_energyikr[(-1, spatial, i)] = new_energyikr[(1, spatial, i)].conj();
+            let kvec = cell.k_vector(k_idx);
+            for i in 0..positions.len() {
+                eikr[(0, spatial, i)] = Complex::cartesian(1.0, 0.0);
+                eikr[(1, spatial, i)] = Complex::polar(1.0, kvec * positions[i]);
+                eikr[(-1, spatial, i)] = eikr[(1, spatial, i)].conj();
             }
         }
 
         // Use recursive definition for computing the factor for all the other values of k.
         for spatial in 0..3 {
             for k in 2..(self.kmax + 1) {
-                for i in 0..molecule.size() {
-                    new_energyikr[(k, spatial, i)] = new_energyikr[(k - 1, spatial, i)] * new_energyikr[(1, spatial, i)];
-                    new_energyikr[(-k, spatial, i)] = new_energyikr[(k, spatial, i)].conj();
+                for i in 0..positions.len() {
+                    eikr[(k, spatial, i)] = eikr[(k - 1, spatial, i)] * eikr[(1, spatial, i)];
+                    eikr[(-k, spatial, i)] = eikr[(k, spatial, i)].conj();
                 }
             }
         }
 
+        return eikr;
+    }
+
+    /// Compute the Fourier transform of the electrostatic density changes
+    /// while moving the molecule with the given `molecule_id` to
+    /// `new_positions`
+    fn delta_rho_move_rigid_molecules(
+        &mut self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> Vec<Complex> {
+        let molecule = configuration.molecule(molecule_id);
+        let new_energyikr = self.phases(&configuration.cell, new_positions);
+
         let mut delta = Vec::new();
         let charges = configuration.particles().charge;
         for &(ikx, iky, ikz) in &self.factors.kvecs {
@@ -846,35 +910,147 @@ impl Ewald {
         return delta;
     }
 
-    fn kspace_move_molecule_cost(
-        &mut self,
+    /// Compute the Fourier transform of the electrostatic density of the
+    /// particles in the molecule with the given `molecule_id`, using the
+    /// phase factors of these particles in `eikr`
+    fn molecule_rho(
+        &self,
         configuration: &Configuration,
         molecule_id: usize,
-        new_positions: &[Vector3D],
-    ) -> f64 {
+        eikr: &Ewald3DArray,
+    ) -> Vec<Complex> {
+        let charges = configuration.particles().charge;
+        let indexes = configuration.molecule(molecule_id).indexes();
+
+        let mut rho = Vec::new();
+        for &(ikx, iky, ikz) in &self.factors.kvecs {
+            let mut partial = Complex::zero();
+            for (i, part_i) in indexes.clone().enumerate() {
+                let phi = eikr[(ikx, 0, i)] * eikr[(iky, 1, i)] * eikr[(ikz, 2, i)];
+                partial += charges[part_i] * phi;
+            }
+            rho.push(partial);
+        }
+        return rho;
+    }
+
+    /// Get the k-space energy change when adding `delta_rho` to the cached
+    /// Fourier transform of the electrostatic density
+    fn kspace_delta_energy(&self, delta_rho: &[Complex]) -> f64 {
         let mut old_energy = 0.0;
         for (factor, &rho) in zip!(&self.factors.energy, &self.rho) {
             old_energy += factor * rho.norm2();
         }
         old_energy /= FOUR_PI_EPSILON_0;
 
-        let delta_rho = self.delta_rho_move_rigid_molecules(
-            configuration, molecule_id, new_positions
-        );
-
         let mut new_energy = 0.0;
-        for (factor, &rho, &delta) in zip!(&self.factors.energy, &self.rho, &delta_rho) {
+        for (factor, &rho, &delta) in zip!(&self.factors.energy, &self.rho, delta_rho) {
             new_energy += factor * (rho + delta).norm2();
         }
         new_energy /= FOUR_PI_EPSILON_0;
 
+        return new_energy - old_energy;
+    }
+
+    fn kspace_move_molecule_cost(
+        &mut self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        let delta_rho = self.delta_rho_move_rigid_molecules(
+            configuration, molecule_id, new_positions
+        );
+        let cost = self.kspace_delta_energy(&delta_rho);
+
         self.updater = Some(Box::new(move |ewald: &mut Ewald| {
             for (rho, &delta) in zip!(&mut ewald.rho, &delta_rho) {
                 *rho += delta;
             }
         }));
 
-        return new_energy - old_energy;
+        return cost;
+    }
+
+    fn kspace_add_molecule_cost(
+        &mut self,
+        configuration: &Configuration,
+        molecule_id: usize,
+    ) -> f64 {
+        let indexes = configuration.molecule(molecule_id).indexes();
+        let positions = configuration.particles().position;
+        let eikr = self.phases(&configuration.cell, &positions[indexes.clone()]);
+        let delta_rho = self.molecule_rho(configuration, molecule_id, &eikr);
+        let cost = self.kspace_delta_energy(&delta_rho);
+
+        self.updater = Some(Box::new(move |ewald: &mut Ewald| {
+            for (rho, &delta) in zip!(&mut ewald.rho, &delta_rho) {
+                *rho += delta;
+            }
+
+            // Insert the phase factors of the new particles in the cached ones
+            let kmax = ewald.kmax;
+            let natoms = ewald.eikr.data.dim().2;
+            let added = indexes.end - indexes.start;
+            let mut new_eikr = Ewald3DArray::zeros((-kmax..(kmax + 1), 3, natoms + added));
+            for k in -kmax..(kmax + 1) {
+                for spatial in 0..3 {
+                    for i in 0..indexes.start {
+                        new_eikr[(k, spatial, i)] = ewald.eikr[(k, spatial, i)];
+                    }
+                    for i in 0..added {
+                        new_eikr[(k, spatial, indexes.start + i)] = eikr[(k, spatial, i)];
+                    }
+                    for i in indexes.start..natoms {
+                        new_eikr[(k, spatial, i + added)] = ewald.eikr[(k, spatial, i)];
+                    }
+                }
+            }
+            ewald.eikr = new_eikr;
+        }));
+
+        return cost;
+    }
+
+    fn kspace_remove_molecule_cost(
+        &mut self,
+        configuration: &Configuration,
+        molecule_id: usize,
+    ) -> f64 {
+        let indexes = configuration.molecule(molecule_id).indexes();
+        let positions = configuration.particles().position;
+        let eikr = self.phases(&configuration.cell, &positions[indexes.clone()]);
+        let delta_rho = self.molecule_rho(configuration, molecule_id, &eikr)
+                            .into_iter()
+                            .map(|rho| -rho)
+                            .collect::<Vec<_>>();
+        let cost = self.kspace_delta_energy(&delta_rho);
+
+        self.updater = Some(Box::new(move |ewald: &mut Ewald| {
+            for (rho, &delta) in zip!(&mut ewald.rho, &delta_rho) {
+                *rho += delta;
+            }
+
+            // Remove the phase factors of the removed particles from the
+            // cached ones
+            let kmax = ewald.kmax;
+            let natoms = ewald.eikr.data.dim().2;
+            let removed = indexes.end - indexes.start;
+            let mut new_eikr = Ewald3DArray::zeros((-kmax..(kmax + 1), 3, natoms - removed));
+            for k in -kmax..(kmax + 1) {
+                for spatial in 0..3 {
+                    for i in 0..indexes.start {
+                        new_eikr[(k, spatial, i)] = ewald.eikr[(k, spatial, i)];
+                    }
+                    for i in indexes.end..natoms {
+                        new_eikr[(k, spatial, i - removed)] = ewald.eikr[(k, spatial, i)];
+                    }
+                }
+            }
+            ewald.eikr = new_eikr;
+        }));
+
+        return cost;
     }
 }
 
@@ -983,6 +1159,24 @@ impl GlobalCache for SharedEwald {
         return real + kspace;
     }
 
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let mut ewald = self.write();
+        ewald.precompute(&configuration.cell);
+        let real = ewald.real_space_molecule_energy(configuration, molecule_id);
+        let self_e = ewald.molecule_self_energy(configuration, molecule_id);
+        let kspace = ewald.kspace_add_molecule_cost(configuration, molecule_id);
+        return real + self_e + kspace;
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let mut ewald = self.write();
+        ewald.precompute(&configuration.cell);
+        let real = ewald.real_space_molecule_energy(configuration, molecule_id);
+        let self_e = ewald.molecule_self_energy(configuration, molecule_id);
+        let kspace = ewald.kspace_remove_molecule_cost(configuration, molecule_id);
+        return kspace - real - self_e;
+    }
+
     fn update(&self) {
         let mut ewald = self.write();
         if ewald.updater.is_some() {
@@ -1404,6 +1598,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn add_remove_molecule() {
+        let mut system = system_from_xyz("6
+        cell: 20.0
+        H  0.3 -0.3 -0.8
+        O  0.0  0.0  0.0
+        H -0.7 -0.7  0.3
+        H  2.3  1.7 -0.8
+        O  2.0  2.0  0.0
+        H  1.3  1.3  0.3
+        ");
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(1, 2).is_empty());
+        assert!(system.add_bond(3, 4).is_empty());
+        assert!(system.add_bond(4, 5).is_empty());
+        assert!(system.molecules().count() == 2);
+
+        for particle in system.particles_mut() {
+            if particle.name == "O" {
+                *particle.charge = -0.8476;
+            } else if particle.name == "H" {
+                *particle.charge = 0.4238;
+            }
+        }
+
+        let mut ewald = SharedEwald::new(Ewald::new(8.0, 10, None));
+        ewald.set_restriction(PairRestriction::InterMolecular);
+        let check = ewald.clone();
+
+        // Initialize cached values
+        let _ = ewald.energy(&system);
+        let old_energy = check.energy(&system);
+
+        let cost = ewald.remove_molecule_cost(&system, 0);
+        let molecule = system.remove_molecule(0);
+        ewald.update();
+        let new_energy = check.energy(&system);
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-12);
+
+        system.add_molecule(molecule);
+        let cost = ewald.add_molecule_cost(&system, 1);
+        ewald.update();
+        assert_relative_eq!(cost, old_energy - new_energy, max_relative = 1e-12);
+
+        // The cache is still usable with the added molecule
+        let new_positions = &[
+            Vector3D::new(0.41727, 2.29401, -0.0558),
+            Vector3D::new(0.5097743599026461, 3.194114034722624, -0.020364564697826326),
+            Vector3D::new(-0.2501317777731211, 3.562366060753896, -0.6178033542374419),
+        ];
+        let cost = ewald.move_molecule_cost(&system, 1, new_positions);
+        system.particles_mut().position[3] = new_positions[0];
+        system.particles_mut().position[4] = new_positions[1];
+        system.particles_mut().position[5] = new_positions[2];
+        let new_energy = check.energy(&system);
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-12);
+    }
+
     // Comparing the value for each component of Ewald energy with the NIST
     // reference. See `tests/nist-spce.rs` for more information. These tests
     // check values that are not accessible from the outside of lumol-core.
@@ -65,6 +65,14 @@ use types::{Matrix3, Vector3D};
 ///         unimplemented!()
 ///     }
 ///
+///     fn add_molecule_cost(&self, _: &Configuration, _: usize) -> f64 {
+///         unimplemented!()
+///     }
+///
+///     fn remove_molecule_cost(&self, _: &Configuration, _: usize) -> f64 {
+///         unimplemented!()
+///     }
+///
 ///     fn update(&self) {
 ///         unimplemented!()
 ///     }
@@ -160,6 +168,15 @@ impl_box_clone!(GlobalPotential, BoxCloneGlobal, box_clone_gobal);
 ///         return 0.0
 ///     }
 ///
+///     fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+///         // Each new particle shifts the energy by delta
+///         self.delta * configuration.molecule(molecule_id).size() as f64
+///     }
+///
+///     fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+///         - self.delta * configuration.molecule(molecule_id).size() as f64
+///     }
+///
 ///     fn update(&self) {
 ///         // We are not storing anything in the ShiftAll struct, so this
 ///         // function is a no-op.
@@ -180,7 +197,23 @@ pub trait GlobalCache {
         new_positions: &[Vector3D],
     ) -> f64;
 
-    /// Update the cache as needed after a call to `move_molecule_cost`.
+    /// Get the cost of adding a molecule in the system.
+    ///
+    /// This function is passed the current `configuration`, already
+    /// containing the new molecule, and the index of the new molecule in the
+    /// configuration. The cached quantities still correspond to the
+    /// configuration without the new molecule.
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64;
+
+    /// Get the cost of removing a molecule from the system.
+    ///
+    /// This function is passed the current `configuration` and the index of
+    /// the molecule to remove in the configuration. The molecule is still in
+    /// the configuration.
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64;
+
+    /// Update the cache as needed after a call to `move_molecule_cost`,
+    /// `add_molecule_cost` or `remove_molecule_cost`.
     ///
     /// If the Monte Carlo move is accepted, this function will be called and
     /// should update any cached quantity so that further call to
@@ -203,8 +236,43 @@ pub trait CoulombicPotential: GlobalPotential + BoxCloneCoulombic {
 
 impl_box_clone!(CoulombicPotential, BoxCloneCoulombic, box_clone_coulombic);
 
+/// Check that all the `particles` used by a potential defined with particles
+/// indexes are before the molecule at `molecule_id` in the `configuration`.
+/// Adding or removing this molecule does not change the particles at these
+/// indexes, and the energy of the potential.
+///
+/// # Panics
+///
+/// If any of the particles is inside or after the molecule.
+fn check_before_molecule<'a, I>(
+    configuration: &Configuration,
+    molecule_id: usize,
+    particles: I,
+    potential: &str,
+) where
+    I: IntoIterator<Item = &'a usize>,
+{
+    let start = configuration.molecule(molecule_id).indexes().start;
+    for &i in particles {
+        assert!(
+            i < start,
+            "can not add or remove molecules before the particle {} used by {}",
+            i, potential
+        );
+    }
+}
+
 mod wolf;
 pub use self::wolf::Wolf;
 
 mod ewald;
 pub use self::ewald::{Ewald, SharedEwald};
+
+mod restraints;
+pub use self::restraints::{DistanceRestraint, PositionRestraints};
+
+mod walls;
+pub use self::walls::Wall;
+
+mod nonbonded_angles;
+pub use self::nonbonded_angles::NonBondedAngle;
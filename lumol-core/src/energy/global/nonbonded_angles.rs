@@ -0,0 +1,457 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Three-body angular potentials acting on neighbor triplets
+use energy::AnglePotential;
+use math::{acos, exp, sqrt};
+use sys::{Configuration, UnitCell};
+use types::{Matrix3, Vector3D};
+
+use super::{GlobalCache, GlobalPotential};
+
+/// A neighbor of a central particle
+struct Neighbor {
+    /// Index of the neighbor particle
+    index: usize,
+    /// Vector from the central particle to the nearest image of the neighbor
+    vector: Vector3D,
+    /// Distance between the central particle and the neighbor
+    distance: f64,
+}
+
+/// A three-body angular potential acting on triplets of neighbor particles,
+/// without requiring any bond between them.
+///
+/// This kind of potential is used in Stillinger-Weber models for silicon, or
+/// in the monatomic mW model of water. For each particle `i` with type `b`,
+/// and each pair of neighbors `j` and `k` with types `a` and `c` closer than
+/// the cutoff distance $r_c$, the energy is
+///
+/// $$ V = V_\theta(\theta_{jik}) \ g(r_{ij}) \ g(r_{ik}) $$
+///
+/// where $V_\theta$ is an [angle potential][AnglePotential], and $g$ is a
+/// Stillinger-Weber style radial function, going smoothly to zero at the
+/// cutoff distance:
+///
+/// $$ g(r) = \exp \left(\frac{\gamma}{r - r_c}\right) $$
+///
+/// Using $\gamma = 0$ gives $g(r) = 1$ everywhere inside the cutoff, and the
+/// energy is discontinuous at the cutoff.
+///
+/// The neighbor triplets are built from the current positions every time the
+/// energy or the forces are computed. Bonds between the particles are not
+/// taken into account, and all the triplets inside the cutoff contribute to
+/// the energy.
+///
+/// [AnglePotential]: trait.AnglePotential.html
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{GlobalPotential, Harmonic, NonBondedAngle};
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell};
+/// use std::f64::consts::PI;
+///
+/// let mut system = System::with_cell(UnitCell::cubic(20.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("W", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("W", [1.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("W", [0.0, 1.0, 0.0].into())));
+///
+/// let potential = NonBondedAngle::new(
+///     ("W", "W", "W"),
+///     Box::new(Harmonic { k: 10.0, x0: PI / 2.0 }),
+///     2.0,
+///     0.0,
+/// );
+///
+/// // The angles centered on the second and third particles are not at the
+/// // equilibrium value, and are both equal to 45°.
+/// let expected = 2.0 * 0.5 * 10.0 * (PI / 4.0) * (PI / 4.0);
+/// assert!(f64::abs(potential.energy(&system) - expected) < 1e-12);
+/// ```
+#[derive(Clone)]
+pub struct NonBondedAngle {
+    /// Names of the particles in the triplets, the central particle being
+    /// the second one
+    names: (String, String, String),
+    /// Angular part of the potential
+    potential: Box<AnglePotential>,
+    /// Cutoff distance for the neighbors
+    cutoff: f64,
+    /// Decay length of the radial part of the potential
+    gamma: f64,
+}
+
+impl NonBondedAngle {
+    /// Create a new `NonBondedAngle` acting on the triplets of particles with
+    /// the types given in `names`, the central particle being the second one. The
+    /// angular part of the potential is given by `potential`, and the radial
+    /// part uses the `cutoff` distance and the `gamma` decay length.
+    ///
+    /// # Panics
+    ///
+    /// If the cutoff is not positive, or if `gamma` is negative.
+    pub fn new<S: Into<String>>(
+        names: (S, S, S),
+        potential: Box<AnglePotential>,
+        cutoff: f64,
+        gamma: f64,
+    ) -> NonBondedAngle {
+        assert!(cutoff > 0.0, "the cutoff must be positive in non-bonded angle potential");
+        assert!(gamma >= 0.0, "gamma can not be negative in non-bonded angle potential");
+        NonBondedAngle {
+            names: (names.0.into(), names.1.into(), names.2.into()),
+            potential: potential,
+            cutoff: cutoff,
+            gamma: gamma,
+        }
+    }
+
+    /// Get the radial function and its derivative at distance `r`
+    fn radial(&self, r: f64) -> (f64, f64) {
+        debug_assert!(r < self.cutoff);
+        if self.gamma == 0.0 {
+            return (1.0, 0.0);
+        }
+        let dr = r - self.cutoff;
+        let g = exp(self.gamma / dr);
+        return (g, -g * self.gamma / (dr * dr));
+    }
+
+    /// Check if the particles with names `a` and `c` can be the outer
+    /// particles of a triplet
+    fn is_outer_pair(&self, a: &str, c: &str) -> bool {
+        (a == self.names.0 && c == self.names.2) || (a == self.names.2 && c == self.names.0)
+    }
+
+    /// Get the neighbors of the particle `i` inside the cutoff distance,
+    /// using the given `positions`
+    fn neighbors(
+        &self,
+        cell: &UnitCell,
+        names: &[String],
+        positions: &[Vector3D],
+        i: usize,
+    ) -> Vec<Neighbor> {
+        let mut neighbors = Vec::new();
+        for j in 0..positions.len() {
+            if j == i || (names[j] != self.names.0 && names[j] != self.names.2) {
+                continue;
+            }
+
+            let mut vector = positions[j] - positions[i];
+            cell.vector_image(&mut vector);
+            let distance = vector.norm();
+            if distance < self.cutoff {
+                neighbors.push(Neighbor {
+                    index: j,
+                    vector: vector,
+                    distance: distance,
+                });
+            }
+        }
+        return neighbors;
+    }
+
+    /// Call `function` with the index of the central particle and the two
+    /// neighbors of all the triplets in the system, using the given
+    /// `positions`
+    fn for_each_triplet<F>(
+        &self,
+        configuration: &Configuration,
+        positions: &[Vector3D],
+        mut function: F,
+    ) where
+        F: FnMut(usize, &Neighbor, &Neighbor),
+    {
+        let names = configuration.particles().atom_type;
+        for i in 0..positions.len() {
+            if names[i] != self.names.1 {
+                continue;
+            }
+
+            let neighbors = self.neighbors(&configuration.cell, names, positions, i);
+            for (n, first) in neighbors.iter().enumerate() {
+                for second in &neighbors[(n + 1)..] {
+                    if self.is_outer_pair(&names[first.index], &names[second.index]) {
+                        function(i, first, second);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compute the energy of the triplet made of a central particle and its
+    /// two neighbors `first` and `second`
+    fn triplet_energy(&self, first: &Neighbor, second: &Neighbor) -> f64 {
+        let cos = first.vector * second.vector / (first.distance * second.distance);
+        let theta = acos(f64::max(-1.0, f64::min(1.0, cos)));
+        let (g1, _) = self.radial(first.distance);
+        let (g2, _) = self.radial(second.distance);
+        return self.potential.energy(theta) * g1 * g2;
+    }
+
+    /// Compute the energy of all the triplets using the given `positions`
+    fn energy_with(&self, configuration: &Configuration, positions: &[Vector3D]) -> f64 {
+        let mut energy = 0.0;
+        self.for_each_triplet(configuration, positions, |_, first, second| {
+            energy += self.triplet_energy(first, second);
+        });
+        return energy;
+    }
+
+    /// Compute the energy of the triplets containing at least one particle
+    /// of the molecule at `molecule_id`
+    fn molecule_energy(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let indexes = configuration.molecule(molecule_id).indexes();
+        let inside = |i: usize| indexes.start <= i && i < indexes.end;
+
+        let mut energy = 0.0;
+        let positions = configuration.particles().position;
+        self.for_each_triplet(configuration, positions, |i, first, second| {
+            if inside(i) || inside(first.index) || inside(second.index) {
+                energy += self.triplet_energy(first, second);
+            }
+        });
+        return energy;
+    }
+
+    /// Compute the forces acting on the two neighbors in a triplet. The force
+    /// acting on the central particle is the opposite of the sum of these
+    /// two forces.
+    fn triplet_forces(&self, first: &Neighbor, second: &Neighbor) -> (Vector3D, Vector3D) {
+        let r1 = first.vector / first.distance;
+        let r2 = second.vector / second.distance;
+        let cos = r1 * r2;
+        let theta = acos(f64::max(-1.0, f64::min(1.0, cos)));
+        let sin_inv = 1.0 / sqrt(1.0 - cos * cos);
+
+        // Derivatives of the angle with respect to the neighbors positions
+        let d1 = sin_inv * (cos * r1 - r2) / first.distance;
+        let d2 = sin_inv * (cos * r2 - r1) / second.distance;
+
+        let (g1, dg1) = self.radial(first.distance);
+        let (g2, dg2) = self.radial(second.distance);
+        let energy = self.potential.energy(theta);
+        let force = self.potential.force(theta);
+
+        let f1 = force * g1 * g2 * d1 - energy * dg1 * g2 * r1;
+        let f2 = force * g1 * g2 * d2 - energy * g1 * dg2 * r2;
+        return (f1, f2);
+    }
+}
+
+impl GlobalCache for NonBondedAngle {
+    fn move_molecule_cost(
+        &self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        // Moving a molecule changes the triplets of all the neighbors, so the
+        // simplest is to compute the energy again with the new positions
+        let old_positions = configuration.particles().position;
+        let indexes = configuration.molecule(molecule_id).indexes();
+        let mut positions = old_positions.to_vec();
+        for (i, position) in indexes.zip(new_positions) {
+            positions[i] = *position;
+        }
+
+        let old = self.energy_with(configuration, old_positions);
+        let new = self.energy_with(configuration, &positions);
+        return new - old;
+    }
+
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        // Only the triplets containing the new particles are added
+        return self.molecule_energy(configuration, molecule_id);
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        return -self.molecule_energy(configuration, molecule_id);
+    }
+
+    fn update(&self) {
+        // Nothing to do
+    }
+}
+
+impl GlobalPotential for NonBondedAngle {
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+
+    fn energy(&self, configuration: &Configuration) -> f64 {
+        self.energy_with(configuration, configuration.particles().position)
+    }
+
+    fn forces(&self, configuration: &Configuration, forces: &mut [Vector3D]) {
+        assert_eq!(forces.len(), configuration.size());
+        let positions = configuration.particles().position;
+        self.for_each_triplet(configuration, positions, |i, first, second| {
+            let (f1, f2) = self.triplet_forces(first, second);
+            forces[first.index] += f1;
+            forces[second.index] += f2;
+            forces[i] -= f1 + f2;
+        });
+    }
+
+    fn atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
+        let mut virial = Matrix3::zero();
+        let positions = configuration.particles().position;
+        self.for_each_triplet(configuration, positions, |_, first, second| {
+            let (f1, f2) = self.triplet_forces(first, second);
+            virial += f1.tensorial(&first.vector) + f2.tensorial(&second.vector);
+        });
+        return virial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use energy::{CosineHarmonic, GlobalCache, GlobalPotential, Harmonic, Potential};
+    use std::f64::consts::PI;
+    use sys::System;
+    use types::{Matrix3, Vector3D};
+    use utils::system_from_xyz;
+
+    fn testing_system() -> System {
+        system_from_xyz(
+            "5
+            cell: 10.0
+            W 0.0 0.0 0.0
+            W 1.2 0.3 0.0
+            W -0.4 1.1 0.5
+            O 0.3 -0.9 0.2
+            W 9.2 0.1 9.6
+            ",
+        )
+    }
+
+    fn stillinger_weber() -> NonBondedAngle {
+        NonBondedAngle::new(
+            ("W", "W", "W"),
+            Box::new(CosineHarmonic::new(4.0, 1.9)),
+            1.8,
+            1.2,
+        )
+    }
+
+    #[test]
+    fn energy() {
+        let system = system_from_xyz(
+            "4
+            cell: 10.0
+            W 0.0 0.0 0.0
+            W 1.0 0.0 0.0
+            O 0.0 1.0 0.0
+            W 0.0 0.0 1.0
+            ",
+        );
+        let potential = NonBondedAngle::new(
+            ("W", "W", "O"),
+            Box::new(Harmonic { k: 2.0, x0: 1.0 }),
+            1.2,
+            0.0,
+        );
+
+        // Only the triplets centered on the first particle are inside the
+        // cutoff, and the O particle must be one of the outer particles
+        let harmonic = Harmonic { k: 2.0, x0: 1.0 };
+        let expected = 2.0 * harmonic.energy(PI / 2.0);
+        assert_ulps_eq!(potential.energy(&system), expected);
+
+        // Triplets centered on the second and last particles are also inside
+        // the cutoff for this potential
+        let potential = stillinger_weber();
+        let cosine = CosineHarmonic::new(4.0, 1.9);
+        let g = |r: f64| f64::exp(1.2 / (r - 1.8));
+        let expected = cosine.energy(PI / 2.0) * g(1.0) * g(1.0)
+                     + 2.0 * cosine.energy(PI / 4.0) * g(1.0) * g(f64::sqrt(2.0));
+        assert_relative_eq!(potential.energy(&system), expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn forces() {
+        let mut system = testing_system();
+        let potential = stillinger_weber();
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        potential.forces(&system, &mut forces);
+        assert_eq!(forces[3], Vector3D::zero());
+
+        let eps = 1e-9;
+        for i in 0..system.size() {
+            for axis in 0..3 {
+                let e = potential.energy(&system);
+                system.particles_mut().position[i][axis] += eps;
+                let e1 = potential.energy(&system);
+                system.particles_mut().position[i][axis] -= eps;
+                assert_relative_eq!((e - e1) / eps, forces[i][axis], epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_virial() {
+        let system = testing_system();
+        let potential = stillinger_weber();
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        potential.forces(&system, &mut forces);
+
+        // The system is small enough to compute the virial using unwrapped
+        // positions around the first particle
+        let mut expected = Matrix3::zero();
+        let positions = system.particles().position;
+        for i in 0..system.size() {
+            let r = system.nearest_image(i, 0) + positions[0];
+            expected += forces[i].tensorial(&r);
+        }
+
+        let virial = potential.atomic_virial(&system);
+        for i in 0..3 {
+            for j in 0..3 {
+                assert_relative_eq!(virial[i][j], expected[i][j], epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn move_molecule_cost() {
+        let mut system = testing_system();
+        let potential = stillinger_weber();
+
+        let old = potential.energy(&system);
+        let new_positions = [Vector3D::new(0.9, 0.8, 0.1)];
+        let cost = potential.move_molecule_cost(&system, 2, &new_positions);
+
+        system.particles_mut().position[2] = new_positions[0];
+        let new = potential.energy(&system);
+        assert_ulps_eq!(cost, new - old);
+    }
+
+    #[test]
+    fn add_remove_molecule_cost() {
+        let mut system = testing_system();
+        let potential = stillinger_weber();
+
+        let old = potential.energy(&system);
+        let cost = potential.remove_molecule_cost(&system, 1);
+        let molecule = system.remove_molecule(1);
+        let new = potential.energy(&system);
+        assert_relative_eq!(cost, new - old, epsilon = 1e-12);
+
+        system.add_molecule(molecule);
+        let cost = potential.add_molecule_cost(&system, 4);
+        assert_relative_eq!(cost, old - new, epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "the cutoff must be positive in non-bonded angle potential")]
+    fn negative_cutoff() {
+        let potential = Box::new(Harmonic { k: 2.0, x0: 1.0 });
+        let _ = NonBondedAngle::new(("W", "W", "W"), potential, -1.0, 0.0);
+    }
+}
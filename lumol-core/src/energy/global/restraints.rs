@@ -0,0 +1,641 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Restraints are additional potentials used to hold parts of the system in
+//! place during a simulation.
+use sys::Configuration;
+use types::{Matrix3, Vector3D};
+
+use super::{check_before_molecule, GlobalCache, GlobalPotential};
+
+/// An harmonic tether between a single particle and a fixed point
+#[derive(Clone, Debug)]
+struct Tether {
+    /// Index of the restrained particle
+    particle: usize,
+    /// Reference position for this particle
+    reference: Vector3D,
+    /// Force constant of the tether
+    k: f64,
+}
+
+impl Tether {
+    /// Get the displacement of the particle from the reference position,
+    /// using the nearest periodic image of the particle.
+    fn displacement(&self, configuration: &Configuration, position: &Vector3D) -> Vector3D {
+        let mut delta = position - self.reference;
+        configuration.cell.vector_image(&mut delta);
+        return delta;
+    }
+}
+
+/// Harmonic position restraints, tethering some particles to fixed reference
+/// positions.
+///
+/// Each restrained particle `i` adds an energy contribution
+///
+/// $$ V_i = \frac 12 k_i |\vec r_i - \vec r_i^0|^2 $$
+///
+/// where $\vec r_i^0$ is the reference position for this particle, and
+/// $k_i$ the force constant of the tether. The displacement from the
+/// reference position uses the nearest periodic image of the particle.
+///
+/// The virial contribution is computed as if the reference position was a
+/// fixed particle bonded to the restrained particle.
+///
+/// The restraints are defined using particles indexes in the configuration,
+/// and will not be updated if the particles are re-ordered, or if molecules
+/// are added or removed from the system. Monte Carlo moves can only add or
+/// remove molecules after all the restrained particles.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{GlobalPotential, PositionRestraints};
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell};
+/// use lumol_core::types::Vector3D;
+///
+/// let mut system = System::with_cell(UnitCell::cubic(10.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [1.0, 0.0, 0.0].into())));
+///
+/// let mut restraints = PositionRestraints::new();
+/// restraints.add(0, Vector3D::zero(), 3.0);
+/// assert_eq!(restraints.energy(&system), 1.5);
+///
+/// system.add_global_potential(Box::new(restraints));
+/// assert_eq!(system.potential_energy(), 1.5);
+/// assert_eq!(system.forces(), vec![Vector3D::new(-3.0, 0.0, 0.0)]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PositionRestraints {
+    tethers: Vec<Tether>,
+}
+
+impl PositionRestraints {
+    /// Create a new empty set of position restraints
+    pub fn new() -> PositionRestraints {
+        PositionRestraints {
+            tethers: Vec::new(),
+        }
+    }
+
+    /// Restrain the `particle` at index `i` to the `reference` position, using
+    /// the force constant `k`.
+    pub fn add(&mut self, particle: usize, reference: Vector3D, k: f64) {
+        self.tethers.push(Tether {
+            particle: particle,
+            reference: reference,
+            k: k,
+        });
+    }
+
+    /// Get the number of restrained particles
+    pub fn len(&self) -> usize {
+        self.tethers.len()
+    }
+
+    /// Check if there are no restrained particles
+    pub fn is_empty(&self) -> bool {
+        self.tethers.is_empty()
+    }
+}
+
+impl GlobalCache for PositionRestraints {
+    fn move_molecule_cost(
+        &self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        let positions = configuration.particles().position;
+        let indexes = configuration.molecule(molecule_id).indexes();
+
+        let mut cost = 0.0;
+        for tether in &self.tethers {
+            if tether.particle < indexes.start || tether.particle >= indexes.end {
+                continue;
+            }
+
+            let old = tether.displacement(configuration, &positions[tether.particle]);
+            let new_position = &new_positions[tether.particle - indexes.start];
+            let new = tether.displacement(configuration, new_position);
+            cost += 0.5 * tether.k * (new.norm2() - old.norm2());
+        }
+        return cost;
+    }
+
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let particles = self.tethers.iter().map(|tether| &tether.particle);
+        check_before_molecule(configuration, molecule_id, particles, "position restraints");
+        return 0.0;
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let particles = self.tethers.iter().map(|tether| &tether.particle);
+        check_before_molecule(configuration, molecule_id, particles, "position restraints");
+        return 0.0;
+    }
+
+    fn update(&self) {
+        // Nothing to do
+    }
+}
+
+impl GlobalPotential for PositionRestraints {
+    fn cutoff(&self) -> Option<f64> {
+        None
+    }
+
+    fn energy(&self, configuration: &Configuration) -> f64 {
+        let positions = configuration.particles().position;
+        let mut energy = 0.0;
+        for tether in &self.tethers {
+            let delta = tether.displacement(configuration, &positions[tether.particle]);
+            energy += 0.5 * tether.k * delta.norm2();
+        }
+        return energy;
+    }
+
+    fn forces(&self, configuration: &Configuration, forces: &mut [Vector3D]) {
+        assert_eq!(forces.len(), configuration.size());
+        let positions = configuration.particles().position;
+        for tether in &self.tethers {
+            let delta = tether.displacement(configuration, &positions[tether.particle]);
+            forces[tether.particle] -= tether.k * delta;
+        }
+    }
+
+    fn atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
+        let positions = configuration.particles().position;
+        let mut virial = Matrix3::zero();
+        for tether in &self.tethers {
+            let delta = tether.displacement(configuration, &positions[tether.particle]);
+            let force = -tether.k * delta;
+            virial += force.tensorial(&delta);
+        }
+        return virial;
+    }
+}
+
+/// Distance restraint between two particles, or between the centers of mass
+/// of two groups of particles.
+///
+/// The restraint potential is zero when the distance `r` between the centers
+/// of mass of the two groups is between `lower` and `upper`. Outside of this
+/// flat region, using $\delta$ for the distance to the flat region, the
+/// potential is harmonic:
+///
+/// $$ V(r) = \frac 12 k \delta^2 $$
+///
+/// until $\delta$ reaches the `linear` distance. Further away, the potential
+/// is linear and the restraint force has a constant magnitude:
+///
+/// $$ V(r) = k\ l \left(\delta - \frac l2 \right) $$
+///
+/// where $l$ is the `linear` distance. A simple harmonic restraint is obtained
+/// with `lower == upper`, and `linear = std::f64::INFINITY`. The distance
+/// between the centers of mass uses the nearest periodic images of the
+/// particles, and the groups should be smaller than half the unit cell.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{GlobalPotential, DistanceRestraint};
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell};
+///
+/// let mut system = System::with_cell(UnitCell::cubic(10.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [4.0, 0.0, 0.0].into())));
+///
+/// let restraint = DistanceRestraint {
+///     first: vec![0],
+///     second: vec![1],
+///     lower: 1.0,
+///     upper: 2.0,
+///     k: 3.0,
+///     linear: std::f64::INFINITY,
+/// };
+/// assert_eq!(restraint.energy(&system), 0.5 * 3.0 * 2.0 * 2.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DistanceRestraint {
+    /// Indexes of the particles in the first group
+    pub first: Vec<usize>,
+    /// Indexes of the particles in the second group
+    pub second: Vec<usize>,
+    /// Lower bound of the flat region
+    pub lower: f64,
+    /// Upper bound of the flat region
+    pub upper: f64,
+    /// Force constant of the harmonic region
+    pub k: f64,
+    /// Distance from the flat region after which the potential is linear
+    pub linear: f64,
+}
+
+impl DistanceRestraint {
+    /// Get the restraint energy at distance `r`
+    fn energy_at(&self, r: f64) -> f64 {
+        let delta = if r > self.upper {
+            r - self.upper
+        } else if r < self.lower {
+            self.lower - r
+        } else {
+            return 0.0;
+        };
+
+        if delta <= self.linear {
+            0.5 * self.k * delta * delta
+        } else {
+            self.k * self.linear * (delta - 0.5 * self.linear)
+        }
+    }
+
+    /// Get the restraint force `- dV/dr` at distance `r`
+    fn force_at(&self, r: f64) -> f64 {
+        if r > self.upper {
+            -self.k * f64::min(r - self.upper, self.linear)
+        } else if r < self.lower {
+            self.k * f64::min(self.lower - r, self.linear)
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the center of mass of the particles in `group`, using `position`
+    /// to get the position of each particle. All the particles are brought
+    /// back close to the first one in the group using the nearest periodic
+    /// image.
+    fn center<F>(&self, configuration: &Configuration, group: &[usize], position: F) -> Vector3D
+        where F: Fn(usize) -> Vector3D
+    {
+        let masses = configuration.particles().mass;
+        let origin = position(group[0]);
+        let mut total_mass = 0.0;
+        let mut center = Vector3D::zero();
+        for &i in group {
+            let mut delta = position(i) - origin;
+            configuration.cell.vector_image(&mut delta);
+            center += masses[i] * delta;
+            total_mass += masses[i];
+        }
+        return origin + center / total_mass;
+    }
+
+    /// Get the vector between the centers of mass of the two groups, using
+    /// `position` to get the position of each particle.
+    fn separation<F>(&self, configuration: &Configuration, position: F) -> Vector3D
+        where F: Fn(usize) -> Vector3D
+    {
+        let first = self.center(configuration, &self.first, &position);
+        let second = self.center(configuration, &self.second, &position);
+        let mut rij = first - second;
+        configuration.cell.vector_image(&mut rij);
+        return rij;
+    }
+
+    /// Get the force acting on the first group, and the vector between the
+    /// centers of mass of the two groups
+    fn group_force(&self, configuration: &Configuration) -> (Vector3D, Vector3D) {
+        let positions = configuration.particles().position;
+        let rij = self.separation(configuration, |i| positions[i]);
+        let r = rij.norm();
+        if r == 0.0 {
+            // The direction of the force is undefined
+            return (Vector3D::zero(), rij);
+        }
+        return (self.force_at(r) / r * rij, rij);
+    }
+}
+
+impl GlobalCache for DistanceRestraint {
+    fn move_molecule_cost(
+        &self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        let indexes = configuration.molecule(molecule_id).indexes();
+        let moved = |i: &usize| indexes.start <= *i && *i < indexes.end;
+        if !self.first.iter().any(&moved) && !self.second.iter().any(&moved) {
+            return 0.0;
+        }
+
+        let positions = configuration.particles().position;
+        let old = self.separation(configuration, |i| positions[i]).norm();
+        let new = self.separation(configuration, |i| {
+            if moved(&i) {
+                new_positions[i - indexes.start]
+            } else {
+                positions[i]
+            }
+        }).norm();
+
+        return self.energy_at(new) - self.energy_at(old);
+    }
+
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let particles = self.first.iter().chain(&self.second);
+        check_before_molecule(configuration, molecule_id, particles, "a distance restraint");
+        return 0.0;
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let particles = self.first.iter().chain(&self.second);
+        check_before_molecule(configuration, molecule_id, particles, "a distance restraint");
+        return 0.0;
+    }
+
+    fn update(&self) {
+        // Nothing to do
+    }
+}
+
+impl GlobalPotential for DistanceRestraint {
+    fn cutoff(&self) -> Option<f64> {
+        None
+    }
+
+    fn energy(&self, configuration: &Configuration) -> f64 {
+        let positions = configuration.particles().position;
+        let r = self.separation(configuration, |i| positions[i]).norm();
+        return self.energy_at(r);
+    }
+
+    fn forces(&self, configuration: &Configuration, forces: &mut [Vector3D]) {
+        assert_eq!(forces.len(), configuration.size());
+        let masses = configuration.particles().mass;
+        let (force, _) = self.group_force(configuration);
+
+        let first_mass = self.first.iter().map(|&i| masses[i]).sum::<f64>();
+        for &i in &self.first {
+            forces[i] += masses[i] / first_mass * force;
+        }
+
+        let second_mass = self.second.iter().map(|&i| masses[i]).sum::<f64>();
+        for &i in &self.second {
+            forces[i] -= masses[i] / second_mass * force;
+        }
+    }
+
+    fn atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
+        let (force, rij) = self.group_force(configuration);
+        return force.tensorial(&rij);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use energy::{GlobalCache, GlobalPotential};
+    use types::{Matrix3, Vector3D};
+    use utils::system_from_xyz;
+
+    fn restraints() -> PositionRestraints {
+        let mut restraints = PositionRestraints::new();
+        restraints.add(0, Vector3D::new(0.0, 0.0, 0.0), 2.0);
+        restraints.add(2, Vector3D::new(9.5, 0.0, 1.0), 4.0);
+        return restraints;
+    }
+
+    #[test]
+    fn energy() {
+        let system = system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 0.0 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.0
+            ",
+        );
+        let restraints = restraints();
+        assert_eq!(restraints.len(), 2);
+
+        // The third particle uses the periodic image at (10.5, 0, 0)
+        let expected = 0.5 * 2.0 * 1.0 + 0.5 * 4.0 * (1.0 + 1.0);
+        assert_ulps_eq!(restraints.energy(&system), expected);
+    }
+
+    #[test]
+    fn forces() {
+        let mut system = system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 0.2 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.3
+            ",
+        );
+        let restraints = restraints();
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        restraints.forces(&system, &mut forces);
+        assert_eq!(forces[1], Vector3D::zero());
+
+        let eps = 1e-9;
+        for &i in &[0, 2] {
+            for axis in 0..3 {
+                let e = restraints.energy(&system);
+                system.particles_mut().position[i][axis] += eps;
+                let e1 = restraints.energy(&system);
+                system.particles_mut().position[i][axis] -= eps;
+                assert_relative_eq!((e - e1) / eps, forces[i][axis], epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_virial() {
+        let system = system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 0.0 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.0
+            ",
+        );
+        let restraints = restraints();
+
+        let expected = Matrix3::new([
+            [-2.0 - 4.0, 0.0, 4.0],
+            [0.0, 0.0, 0.0],
+            [4.0, 0.0, -4.0],
+        ]);
+        assert_eq!(restraints.atomic_virial(&system), expected);
+    }
+
+    #[test]
+    fn move_molecule_cost() {
+        let system = system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 0.0 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.0
+            ",
+        );
+        let restraints = restraints();
+
+        let new_positions = [Vector3D::new(2.0, 0.0, 0.0)];
+        let cost = restraints.move_molecule_cost(&system, 0, &new_positions);
+        assert_ulps_eq!(cost, 0.5 * 2.0 * (4.0 - 1.0));
+
+        // Moving a non restrained particle does not cost anything
+        let cost = restraints.move_molecule_cost(&system, 1, &new_positions);
+        assert_eq!(cost, 0.0);
+    }
+
+    #[test]
+    fn add_remove_molecule_cost() {
+        let system = system_from_xyz(
+            "4
+            cell: 10.0
+            Ar 1.0 0.0 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.0
+            Ar 2.0 2.0 0.0
+            ",
+        );
+        let restraints = restraints();
+        assert_eq!(restraints.add_molecule_cost(&system, 3), 0.0);
+        assert_eq!(restraints.remove_molecule_cost(&system, 3), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "before the particle 2 used by position restraints")]
+    fn remove_before_restrained_particle() {
+        let system = system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 0.0 0.0
+            Ar 3.0 3.0 3.0
+            Ar 0.5 0.0 0.0
+            ",
+        );
+        let _ = restraints().remove_molecule_cost(&system, 1);
+    }
+
+    mod distance {
+        use energy::{DistanceRestraint, GlobalCache, GlobalPotential};
+        use sys::System;
+        use types::Vector3D;
+        use utils::system_from_xyz;
+
+        fn testing_system() -> System {
+            system_from_xyz(
+                "4
+                cell: 10.0
+                O 9.5 0.0 0.0
+                H 0.3 0.2 0.0
+                Ar 3.0 0.0 0.5
+                Ar 3.5 0.7 0.0
+                ",
+            )
+        }
+
+        fn restraint() -> DistanceRestraint {
+            DistanceRestraint {
+                first: vec![0, 1],
+                second: vec![2, 3],
+                lower: 1.0,
+                upper: 2.0,
+                k: 4.0,
+                linear: 1.5,
+            }
+        }
+
+        #[test]
+        fn energy_regimes() {
+            let restraint = restraint();
+            // flat region
+            assert_eq!(restraint.energy_at(1.0), 0.0);
+            assert_eq!(restraint.energy_at(1.5), 0.0);
+            assert_eq!(restraint.energy_at(2.0), 0.0);
+            assert_eq!(restraint.force_at(1.5), 0.0);
+            // harmonic region
+            assert_eq!(restraint.energy_at(3.0), 2.0);
+            assert_eq!(restraint.energy_at(0.5), 0.5);
+            assert_eq!(restraint.force_at(3.0), -4.0);
+            assert_eq!(restraint.force_at(0.5), 2.0);
+            // linear region, continuous with the harmonic one
+            assert_ulps_eq!(restraint.energy_at(3.5), 0.5 * 4.0 * 1.5 * 1.5);
+            assert_ulps_eq!(restraint.energy_at(4.5), 4.0 * 1.5 * (2.5 - 0.75));
+            assert_eq!(restraint.force_at(4.5), -6.0);
+            assert_eq!(restraint.force_at(10.5), -6.0);
+        }
+
+        #[test]
+        fn center_of_mass() {
+            let system = testing_system();
+            let restraint = restraint();
+            let positions = system.particles().position;
+
+            // The oxygen is brought back close to the hydrogen
+            let center = restraint.center(&system, &[0, 1], |i| positions[i]);
+            let masses = system.particles().mass;
+            let total_mass = masses[0] + masses[1];
+            assert_relative_eq!(center[0], 9.5 + masses[1] * 0.8 / total_mass, epsilon = 1e-12);
+            assert_relative_eq!(center[1], masses[1] * 0.2 / total_mass, epsilon = 1e-12);
+        }
+
+        #[test]
+        fn forces() {
+            let mut system = testing_system();
+            let restraint = restraint();
+
+            let mut forces = vec![Vector3D::zero(); system.size()];
+            restraint.forces(&system, &mut forces);
+            let total = forces.iter().fold(Vector3D::zero(), |sum, force| sum + force);
+            assert_ulps_eq!(total.norm(), 0.0);
+
+            let eps = 1e-9;
+            for i in 0..system.size() {
+                for axis in 0..3 {
+                    let e = restraint.energy(&system);
+                    system.particles_mut().position[i][axis] += eps;
+                    let e1 = restraint.energy(&system);
+                    system.particles_mut().position[i][axis] -= eps;
+                    assert_relative_eq!((e - e1) / eps, forces[i][axis], epsilon = 1e-6);
+                }
+            }
+        }
+
+        #[test]
+        fn atomic_virial() {
+            let system = testing_system();
+            let restraint = restraint();
+
+            let mut forces = vec![Vector3D::zero(); system.size()];
+            restraint.forces(&system, &mut forces);
+            let force = forces[0] + forces[1];
+
+            let positions = system.particles().position;
+            let first = restraint.center(&system, &[0, 1], |i| positions[i]);
+            let second = restraint.center(&system, &[2, 3], |i| positions[i]);
+            let mut rij = first - second;
+            system.cell.vector_image(&mut rij);
+
+            let virial = restraint.atomic_virial(&system);
+            let expected = force.tensorial(&rij);
+            for i in 0..3 {
+                for j in 0..3 {
+                    assert_ulps_eq!(virial[i][j], expected[i][j]);
+                }
+            }
+        }
+
+        #[test]
+        fn move_molecule_cost() {
+            let mut system = testing_system();
+            let restraint = restraint();
+
+            let old = restraint.energy(&system);
+            let new_positions = [Vector3D::new(4.0, 0.0, 0.5)];
+            let cost = restraint.move_molecule_cost(&system, 2, &new_positions);
+
+            system.particles_mut().position[2] = new_positions[0];
+            let new = restraint.energy(&system);
+            assert_ulps_eq!(cost, new - old);
+        }
+    }
+}
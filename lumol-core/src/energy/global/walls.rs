@@ -0,0 +1,330 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Planar walls acting on some particles in the system
+use energy::WallPotential;
+use sys::Configuration;
+use types::{Matrix3, Vector3D};
+
+use super::{check_before_molecule, GlobalCache, GlobalPotential};
+
+/// A planar wall, acting on a set of particles.
+///
+/// The wall is the plane going through the `origin` point and perpendicular
+/// to the `normal` vector. The selected particles interact with the wall
+/// using a [`WallPotential`][WallPotential] depending on the distance `z`
+/// between the particle and the wall, measured along the normal:
+///
+/// $$ z = (\vec r_i - \vec r_0) \cdot \vec n $$
+///
+/// The particles should stay on the side of the wall the normal points to.
+/// The potential is only computed when `z` is smaller than the cutoff
+/// distance, and is shifted to be zero at the cutoff. The positions of the
+/// particles are used as-is, without applying periodic boundary conditions:
+/// walls are intended for systems which are not periodic in the direction
+/// perpendicular to the wall, like confined fluids in slit pores.
+///
+/// The virial contribution is computed as if the force was acting between the
+/// particle and its projection on the wall plane.
+///
+/// [WallPotential]: trait.WallPotential.html
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::energy::{GlobalPotential, Harmonic, Wall};
+/// use lumol_core::sys::{System, Molecule, Particle};
+/// use lumol_core::types::Vector3D;
+///
+/// let mut system = System::new();
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 1.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 5.0].into())));
+///
+/// // An harmonic wall in the xy plane, repelling the particles closer than
+/// // 2 A from the wall.
+/// let wall = Wall::new(
+///     vec![0, 1],
+///     Vector3D::zero(),
+///     Vector3D::new(0.0, 0.0, 1.0),
+///     Box::new(Harmonic { k: 10.0, x0: 2.0 }),
+///     2.0,
+/// );
+///
+/// assert_eq!(wall.energy(&system), 5.0);
+///
+/// let mut forces = vec![Vector3D::zero(); 2];
+/// wall.forces(&system, &mut forces);
+/// assert_eq!(forces[0], Vector3D::new(0.0, 0.0, 10.0));
+/// assert_eq!(forces[1], Vector3D::zero());
+/// ```
+#[derive(Clone)]
+pub struct Wall {
+    /// Indexes of the particles interacting with the wall
+    particles: Vec<usize>,
+    /// A point in the wall plane
+    origin: Vector3D,
+    /// Unit vector normal to the wall
+    normal: Vector3D,
+    /// Potential between the particles and the wall
+    potential: Box<WallPotential>,
+    /// Cutoff distance for the potential
+    cutoff: f64,
+    /// Energy shift, to get zero energy at the cutoff
+    shift: f64,
+}
+
+impl Wall {
+    /// Create a new `Wall` acting on the `particles`, going through the
+    /// `origin` point and perpendicular to the `normal` vector. The particles
+    /// interact with the wall using the given `potential`, up to the `cutoff`
+    /// distance.
+    ///
+    /// # Panics
+    ///
+    /// If the `normal` vector is null.
+    pub fn new(
+        particles: Vec<usize>,
+        origin: Vector3D,
+        normal: Vector3D,
+        potential: Box<WallPotential>,
+        cutoff: f64,
+    ) -> Wall {
+        assert!(normal.norm() > 0.0, "the normal vector to a wall can not be null");
+        let shift = potential.energy(cutoff);
+        Wall {
+            particles: particles,
+            origin: origin,
+            normal: normal.normalized(),
+            potential: potential,
+            cutoff: cutoff,
+            shift: shift,
+        }
+    }
+
+    /// Get the distance between the wall and the given `position`
+    fn distance(&self, position: &Vector3D) -> f64 {
+        (position - self.origin) * self.normal
+    }
+
+    /// Get the energy of a particle at distance `z` from the wall
+    fn energy_at(&self, z: f64) -> f64 {
+        if z < self.cutoff {
+            self.potential.energy(z) - self.shift
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the force acting on a particle at distance `z` from the wall
+    fn force_at(&self, z: f64) -> Vector3D {
+        if z < self.cutoff {
+            self.potential.force(z) * self.normal
+        } else {
+            Vector3D::zero()
+        }
+    }
+}
+
+impl GlobalCache for Wall {
+    fn move_molecule_cost(
+        &self,
+        configuration: &Configuration,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        let positions = configuration.particles().position;
+        let indexes = configuration.molecule(molecule_id).indexes();
+
+        let mut cost = 0.0;
+        for &i in &self.particles {
+            if i < indexes.start || i >= indexes.end {
+                continue;
+            }
+
+            let old = self.distance(&positions[i]);
+            let new = self.distance(&new_positions[i - indexes.start]);
+            cost += self.energy_at(new) - self.energy_at(old);
+        }
+        return cost;
+    }
+
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        check_before_molecule(configuration, molecule_id, &self.particles, "a wall");
+        return 0.0;
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        check_before_molecule(configuration, molecule_id, &self.particles, "a wall");
+        return 0.0;
+    }
+
+    fn update(&self) {
+        // Nothing to do
+    }
+}
+
+impl GlobalPotential for Wall {
+    fn cutoff(&self) -> Option<f64> {
+        None
+    }
+
+    fn energy(&self, configuration: &Configuration) -> f64 {
+        let positions = configuration.particles().position;
+        let mut energy = 0.0;
+        for &i in &self.particles {
+            energy += self.energy_at(self.distance(&positions[i]));
+        }
+        return energy;
+    }
+
+    fn forces(&self, configuration: &Configuration, forces: &mut [Vector3D]) {
+        assert_eq!(forces.len(), configuration.size());
+        let positions = configuration.particles().position;
+        for &i in &self.particles {
+            forces[i] += self.force_at(self.distance(&positions[i]));
+        }
+    }
+
+    fn atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
+        let positions = configuration.particles().position;
+        let mut virial = Matrix3::zero();
+        for &i in &self.particles {
+            let z = self.distance(&positions[i]);
+            let force = self.force_at(z);
+            virial += force.tensorial(&(z * self.normal));
+        }
+        return virial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use energy::{GlobalCache, GlobalPotential, Harmonic, LennardJones93};
+    use sys::{Molecule, Particle, System};
+    use types::{Matrix3, Vector3D};
+    use utils::system_from_xyz;
+
+    fn testing_system() -> System {
+        system_from_xyz(
+            "3
+            cell: 10.0
+            Ar 1.0 2.0 1.5
+            Ar 0.5 0.3 2.5
+            Ar 4.0 3.0 6.0
+            ",
+        )
+    }
+
+    fn lj_wall() -> Wall {
+        Wall::new(
+            vec![0, 1, 2],
+            Vector3D::new(0.0, 0.0, 0.5),
+            Vector3D::new(0.0, 0.0, 2.0),
+            Box::new(LennardJones93 {
+                sigma: 1.2,
+                epsilon: 0.5,
+            }),
+            4.0,
+        )
+    }
+
+    #[test]
+    fn energy() {
+        let system = testing_system();
+        let wall = lj_wall();
+
+        let lj = LennardJones93 {
+            sigma: 1.2,
+            epsilon: 0.5,
+        };
+        let shift = lj.energy(4.0);
+        let expected = lj.energy(1.0) - shift + lj.energy(2.0) - shift;
+        assert_ulps_eq!(wall.energy(&system), expected);
+
+        // Harmonic walls act on the other side of the system
+        let wall = Wall::new(
+            vec![0, 2],
+            Vector3D::new(0.0, 0.0, 8.0),
+            Vector3D::new(0.0, 0.0, -1.0),
+            Box::new(Harmonic { k: 3.0, x0: 2.5 }),
+            2.5,
+        );
+        assert_ulps_eq!(wall.energy(&system), 0.5 * 3.0 * 0.5 * 0.5);
+    }
+
+    #[test]
+    fn forces() {
+        let mut system = testing_system();
+        let wall = lj_wall();
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        wall.forces(&system, &mut forces);
+        assert_eq!(forces[2], Vector3D::zero());
+
+        let eps = 1e-9;
+        for i in 0..2 {
+            for axis in 0..3 {
+                let e = wall.energy(&system);
+                system.particles_mut().position[i][axis] += eps;
+                let e1 = wall.energy(&system);
+                system.particles_mut().position[i][axis] -= eps;
+                assert_relative_eq!((e - e1) / eps, forces[i][axis], epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_virial() {
+        let system = testing_system();
+        let wall = lj_wall();
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        wall.forces(&system, &mut forces);
+        let expected = Matrix3::new([
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, forces[0][2] * 1.0 + forces[1][2] * 2.0],
+        ]);
+        assert_ulps_eq!(wall.atomic_virial(&system)[2][2], expected[2][2]);
+        assert_eq!(wall.atomic_virial(&system)[0], expected[0]);
+    }
+
+    #[test]
+    fn move_molecule_cost() {
+        let mut system = testing_system();
+        let wall = lj_wall();
+
+        let old = wall.energy(&system);
+        let new_positions = [Vector3D::new(0.0, 0.0, 3.0)];
+        let cost = wall.move_molecule_cost(&system, 1, &new_positions);
+
+        system.particles_mut().position[1] = new_positions[0];
+        let new = wall.energy(&system);
+        assert_ulps_eq!(cost, new - old);
+    }
+
+    #[test]
+    fn add_remove_molecule_cost() {
+        let mut system = testing_system();
+        let wall = Wall::new(
+            vec![0],
+            Vector3D::zero(),
+            Vector3D::new(0.0, 0.0, 1.0),
+            Box::new(Harmonic { k: 3.0, x0: 2.5 }),
+            2.5,
+        );
+
+        assert_eq!(wall.remove_molecule_cost(&system, 2), 0.0);
+        let _ = system.remove_molecule(2);
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        assert_eq!(wall.add_molecule_cost(&system, 2), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "before the particle 1 used by a wall")]
+    fn remove_wall_particle() {
+        let system = testing_system();
+        let _ = lj_wall().remove_molecule_cost(&system, 1);
+    }
+}
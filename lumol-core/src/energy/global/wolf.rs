@@ -8,7 +8,7 @@ use consts::FOUR_PI_EPSILON_0;
 use energy::PairRestriction;
 use math::*;
 use utils::ThreadLocalVec;
-use sys::Configuration;
+use sys::{Configuration, SharedNeighborList};
 use types::{Matrix3, Vector3D};
 
 use super::{CoulombicPotential, GlobalCache, GlobalPotential};
@@ -61,6 +61,8 @@ pub struct Wolf {
     force_constant: f64,
     /// Restriction scheme
     restriction: PairRestriction,
+    /// Neighbor list for the real-space pairs
+    neighbors: SharedNeighborList,
 }
 
 impl Wolf {
@@ -80,6 +82,7 @@ impl Wolf {
             energy_constant: energy_constant,
             force_constant: force_constant,
             restriction: PairRestriction::None,
+            neighbors: SharedNeighborList::default(),
         }
     }
 
@@ -115,6 +118,46 @@ impl Wolf {
             return qiqj * (factor - self.force_constant) / (rij * FOUR_PI_EPSILON_0);
         }
     }
+
+    /// Compute the energy of all the pairs containing at least one particle
+    /// of the molecule at `molecule_id`, including the self interaction of
+    /// the particles in this molecule.
+    fn molecule_energy(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        let charges = configuration.particles().charge;
+        let indexes = configuration.molecule(molecule_id).indexes();
+
+        let mut energy = 0.0;
+        for i in indexes.clone() {
+            let qi = charges[i];
+            if qi == 0.0 {
+                continue;
+            }
+
+            for j in 0..configuration.size() {
+                // Pairs inside the molecule are only counted once
+                if j == i || (indexes.start <= j && j < indexes.end && j < i) {
+                    continue;
+                }
+
+                let qj = charges[j];
+                if qj == 0.0 {
+                    continue;
+                }
+
+                let path = configuration.bond_path(i, j);
+                let info = self.restriction.information(path);
+                if info.excluded {
+                    continue;
+                }
+
+                let rij = configuration.distance(i, j);
+                energy += info.scaling * self.energy_pair(qi * qj, rij);
+            }
+
+            energy -= self.energy_self(qi);
+        }
+        return energy;
+    }
 }
 
 impl GlobalCache for Wolf {
@@ -164,6 +207,14 @@ impl GlobalCache for Wolf {
         return new_energy - old_energy;
     }
 
+    fn add_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        return self.molecule_energy(configuration, molecule_id);
+    }
+
+    fn remove_molecule_cost(&self, configuration: &Configuration, molecule_id: usize) -> f64 {
+        return -self.molecule_energy(configuration, molecule_id);
+    }
+
     fn update(&self) {
         // Nothing to do
     }
@@ -177,6 +228,7 @@ impl GlobalPotential for Wolf {
     fn energy(&self, configuration: &Configuration) -> f64 {
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.cutoff);
 
         let energies = (0..natoms).into_par_iter().map(|i| {
             let mut energy = 0.0;
@@ -185,7 +237,7 @@ impl GlobalPotential for Wolf {
                 return 0.0;
             }
 
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -211,6 +263,7 @@ impl GlobalPotential for Wolf {
 
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.cutoff);
         // To avoid race conditions, each thread needs its own local forces Vec
         let thread_local_forces = ThreadLocalVec::with_size(natoms);
 
@@ -223,7 +276,7 @@ impl GlobalPotential for Wolf {
             if qi == 0.0 {
                 return;
             }
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -251,6 +304,7 @@ impl GlobalPotential for Wolf {
     fn atomic_virial(&self, configuration: &Configuration) -> Matrix3 {
         let natoms = configuration.size();
         let charges = configuration.particles().charge;
+        let neighbors = self.neighbors.get(configuration, self.cutoff);
 
         let virials = (0..natoms).into_par_iter().map(|i| {
             let qi = charges[i];
@@ -259,7 +313,7 @@ impl GlobalPotential for Wolf {
             }
             let mut local_virial = Matrix3::zero();
 
-            for j in i + 1..natoms {
+            for &j in neighbors.neighbors(i) {
                 let qj = charges[j];
                 if qj == 0.0 {
                     continue;
@@ -494,5 +548,22 @@ mod tests {
             let new_energy = check.energy(&system);
             assert_ulps_eq!(cost, new_energy - old_energy);
         }
+
+        #[test]
+        fn add_remove_molecule() {
+            let mut system = testing_system();
+            let mut wolf = Wolf::new(8.0);
+            wolf.set_restriction(PairRestriction::InterMolecular);
+
+            let old_energy = wolf.energy(&system);
+            let cost = wolf.remove_molecule_cost(&system, 0);
+            let molecule = system.remove_molecule(0);
+            let new_energy = wolf.energy(&system);
+            assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-12);
+
+            system.add_molecule(molecule);
+            let cost = wolf.add_molecule_cost(&system, 1);
+            assert_relative_eq!(cost, old_energy - new_energy, max_relative = 1e-12);
+        }
     }
 }
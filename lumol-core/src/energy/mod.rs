@@ -238,20 +238,59 @@ impl_box_clone!(AnglePotential, BoxCloneAngle, box_clone_angle);
 pub trait DihedralPotential: Potential + BoxCloneDihedral {}
 impl_box_clone!(DihedralPotential, BoxCloneDihedral, box_clone_dihedral);
 
+/// Marker trait for potentials that can be used for the interaction between a
+/// particle and a [wall](struct.Wall.html). The variable of the potential is
+/// the distance between the particle and the wall.
+///
+/// # Example
+///
+/// ```
+/// use lumol_core::energy::{Potential, WallPotential};
+///
+/// // A no-op potential
+/// #[derive(Clone)]
+/// struct Null;
+///
+/// impl Potential for Null {
+///     fn energy(&self, x: f64) -> f64 {0.0}
+///     fn force(&self, x: f64) -> f64 {0.0}
+/// }
+///
+/// // Now we can use the Null potential for walls
+/// impl WallPotential for Null {}
+/// ```
+pub trait WallPotential: Potential + BoxCloneWall {}
+impl_box_clone!(WallPotential, BoxCloneWall, box_clone_wall);
+
 mod functions;
 pub use self::functions::{BornMayerHuggins, Buckingham, Gaussian, Morse, Torsion};
 pub use self::functions::{CosineHarmonic, Harmonic, LennardJones, NullPotential};
-pub use self::functions::Mie;
+pub use self::functions::{MixingRule, SoftRepulsive};
+pub use self::functions::{CosineAngle, CosineSquaredAngle, Opls, RyckaertBellemans};
+pub use self::functions::CharmmDihedral;
+pub use self::functions::{Fene, LennardJones1264, LennardJones93, Mie, Quartic};
+pub use self::functions::CappedBond;
+
+mod expression;
+pub use self::expression::{ExprPotential, ExpressionError};
+
+mod cmap;
+pub use self::cmap::Cmap;
 
 mod computations;
 pub use self::computations::{Computation, TableComputation};
 
+mod fitting;
+pub use self::fitting::{fit_buckingham, fit_lennard_jones, tabulate, PotentialTable};
+
 mod restrictions;
 pub use self::restrictions::{PairRestriction, RestrictionInfo, BondPath};
 
 mod global;
 pub use self::global::{CoulombicPotential, GlobalCache, GlobalPotential};
 pub use self::global::{Ewald, SharedEwald, Wolf};
+pub use self::global::{DistanceRestraint, PositionRestraints};
+pub use self::global::{NonBondedAngle, Wall};
 
 mod pairs;
 pub use self::pairs::PairInteraction;
@@ -38,9 +38,11 @@ extern crate soa_derive;
 #[macro_use]
 extern crate approx;
 
+extern crate caldyn;
 extern crate chemfiles;
 extern crate ndarray;
 extern crate num_traits as num;
+extern crate rand;
 extern crate rayon;
 extern crate special;
 extern crate thread_local;
@@ -72,6 +74,7 @@ pub mod consts;
 pub mod types;
 pub mod energy;
 pub mod sys;
+pub mod profiler;
 
 pub use self::types::*;
 pub use self::energy::*;
@@ -0,0 +1,161 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Opt-in timers measuring the time spent in the different parts of a
+//! simulation.
+//!
+//! The profiler is disabled by default, and timers are then almost free. When
+//! it is enabled, each [`Timer`](struct.Timer.html) adds the time elapsed
+//! between its creation and its destruction to a global counter associated
+//! with the timer name. Timers can be nested: the time spent computing the
+//! forces is also counted in the time spent in the integrator.
+//!
+//! # Examples
+//!
+//! ```
+//! use lumol_core::profiler;
+//!
+//! profiler::enable();
+//! {
+//!     let _timer = profiler::start("my algorithm");
+//!     // do some work
+//! }
+//!
+//! let timings = profiler::timings();
+//! assert_eq!(timings[0].0, "my algorithm");
+//! assert_eq!(timings[0].1.calls, 1);
+//! profiler::disable();
+//! ```
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static!{
+    static ref TIMINGS: Mutex<BTreeMap<&'static str, Timing>> = Mutex::new(BTreeMap::new());
+}
+
+/// Accumulated time spent in a timer
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timing {
+    /// Total time spent in this timer
+    pub total: Duration,
+    /// Number of times this timer was used
+    pub calls: u64,
+}
+
+/// Enable the profiler. The timers created before this call are not
+/// measured.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Disable the profiler. The accumulated timings are kept until the next
+/// call to [`reset`](fn.reset.html).
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Check if the profiler is enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Remove all the accumulated timings
+pub fn reset() {
+    TIMINGS.lock().expect("poisoned profiler lock").clear();
+}
+
+/// Get the accumulated timings, sorted by timer name
+pub fn timings() -> Vec<(&'static str, Timing)> {
+    let timings = TIMINGS.lock().expect("poisoned profiler lock");
+    return timings.iter().map(|(&name, &timing)| (name, timing)).collect();
+}
+
+/// Start a new timer with the given `name`. The timer stops when the returned
+/// value is dropped.
+pub fn start(name: &'static str) -> Timer {
+    Timer {
+        name: name,
+        start: if is_enabled() { Some(Instant::now()) } else { None },
+    }
+}
+
+/// Log the accumulated timings, as a fraction of the time spent in the
+/// `reference` timer.
+pub fn report(reference: &str) {
+    let timings = timings();
+    let total = timings.iter()
+        .find(|&&(name, _)| name == reference)
+        .map_or(0.0, |&(_, timing)| seconds(timing.total));
+
+    info!("Time spent in the different parts of the simulation:");
+    for (name, timing) in timings {
+        let time = seconds(timing.total);
+        if total > 0.0 {
+            info!(
+                "    {:<20} {:>12.3} s {:>6.1} % ({} calls)",
+                name, time, 100.0 * time / total, timing.calls
+            );
+        } else {
+            info!("    {:<20} {:>12.3} s ({} calls)", name, time, timing.calls);
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + 1e-9 * f64::from(duration.subsec_nanos())
+}
+
+/// A running timer, created by [`start`](fn.start.html). The elapsed time is
+/// added to the profiler when the timer is dropped.
+pub struct Timer {
+    name: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let elapsed = start.elapsed();
+            let mut timings = TIMINGS.lock().expect("poisoned profiler lock");
+            let timing = timings.entry(self.name).or_insert_with(Timing::default);
+            timing.total += elapsed;
+            timing.calls += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers() {
+        // The profiler is global, only use it in this test
+        {
+            let _timer = start("disabled");
+        }
+        assert!(timings().iter().all(|&(name, _)| name != "disabled"));
+
+        enable();
+        assert!(is_enabled());
+        for _ in 0..3 {
+            let _timer = start("enabled");
+            let _nested = start("nested");
+        }
+        disable();
+
+        let timings = timings();
+        let enabled = timings.iter().find(|&&(name, _)| name == "enabled").unwrap().1;
+        let nested = timings.iter().find(|&&(name, _)| name == "nested").unwrap().1;
+        assert_eq!(enabled.calls, 3);
+        assert_eq!(nested.calls, 3);
+        assert!(enabled.total >= nested.total);
+
+        reset();
+        assert!(super::timings().is_empty());
+    }
+}
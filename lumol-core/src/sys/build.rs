@@ -0,0 +1,606 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Helpers to build simple starting configurations, like crystals or grids
+//! of solvent molecules.
+use std::f64;
+use std::f64::consts::PI;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use sys::{Molecule, MoleculeTemplate, Particle, System, UnitCell};
+use types::{Matrix3, Vector3D};
+
+/// Ratio between the c and a lattice parameters for ideal hexagonal close
+/// packing, sqrt(8/3)
+const HCP_C_OVER_A: f64 = 1.632_993_161_855_452;
+
+/// Crystal lattices that can be used with [`crystal`](fn.crystal.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lattice {
+    /// Simple cubic lattice, with one site in the conventional cell
+    SimpleCubic,
+    /// Body-centered cubic lattice, with two sites in the conventional cell
+    BodyCenteredCubic,
+    /// Face-centered cubic lattice, with four sites in the conventional cell
+    FaceCenteredCubic,
+    /// Hexagonal close-packed lattice, with two sites in the hexagonal
+    /// conventional cell. The ideal c/a ratio of sqrt(8/3) is used, so that
+    /// all the nearest neighbors are at a distance a.
+    HexagonalClosePacked,
+    /// Diamond lattice, with eight sites in the conventional cell
+    Diamond,
+}
+
+impl Lattice {
+    /// Get the fractional coordinates of the sites in the conventional cell
+    fn basis(&self) -> Vec<Vector3D> {
+        match *self {
+            Lattice::SimpleCubic => vec![Vector3D::new(0.0, 0.0, 0.0)],
+            Lattice::BodyCenteredCubic => vec![
+                Vector3D::new(0.0, 0.0, 0.0),
+                Vector3D::new(0.5, 0.5, 0.5),
+            ],
+            Lattice::FaceCenteredCubic => vec![
+                Vector3D::new(0.0, 0.0, 0.0),
+                Vector3D::new(0.5, 0.5, 0.0),
+                Vector3D::new(0.5, 0.0, 0.5),
+                Vector3D::new(0.0, 0.5, 0.5),
+            ],
+            Lattice::HexagonalClosePacked => vec![
+                Vector3D::new(0.0, 0.0, 0.0),
+                Vector3D::new(1.0 / 3.0, 2.0 / 3.0, 0.5),
+            ],
+            Lattice::Diamond => {
+                let fcc = Lattice::FaceCenteredCubic.basis();
+                let shifted = fcc.iter()
+                                 .map(|site| site + Vector3D::new(0.25, 0.25, 0.25))
+                                 .collect::<Vec<_>>();
+                fcc.into_iter().chain(shifted).collect()
+            }
+        }
+    }
+
+    /// Get the number of sites in the conventional cell of this lattice
+    pub fn sites(&self) -> usize {
+        self.basis().len()
+    }
+
+    /// Get the conventional cell of this lattice, using the lattice parameter
+    /// `a`.
+    pub fn cell(&self, a: f64) -> UnitCell {
+        self.supercell(a, [1, 1, 1])
+    }
+
+    /// Get the cell containing `repeat` conventional cells of this lattice
+    /// along each cell vector, using the lattice parameter `a`.
+    fn supercell(&self, a: f64, repeat: [usize; 3]) -> UnitCell {
+        let na = repeat[0] as f64;
+        let nb = repeat[1] as f64;
+        let nc = repeat[2] as f64;
+        match *self {
+            Lattice::HexagonalClosePacked => {
+                let c = HCP_C_OVER_A * a;
+                UnitCell::triclinic(na * a, nb * a, nc * c, 90.0, 90.0, 120.0)
+            }
+            _ => UnitCell::ortho(na * a, nb * a, nc * a),
+        }
+    }
+
+    /// Get the lattice parameter giving a number `density` of sites (in
+    /// sites per A^3) for this lattice.
+    ///
+    /// # Panics
+    ///
+    /// If the `density` is not positive.
+    pub fn lattice_parameter(&self, density: f64) -> f64 {
+        assert!(density > 0.0, "the density of a lattice must be positive");
+        let volume = self.sites() as f64 / density;
+        return f64::cbrt(volume / self.cell(1.0).volume());
+    }
+}
+
+/// Create a crystal of particles named `name`, with the given `lattice` and
+/// lattice parameter `a`. The conventional cell of the lattice is repeated
+/// `repeat` times along each cell vector, and the unit cell of the system is
+/// set accordingly.
+///
+/// # Panics
+///
+/// If `a` is not positive, or if any value in `repeat` is zero.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::build::{crystal, Lattice};
+///
+/// let system = crystal(Lattice::FaceCenteredCubic, "Ar", 5.26, [3, 3, 3]);
+/// assert_eq!(system.size(), 4 * 27);
+/// assert_eq!(system.cell.a(), 3.0 * 5.26);
+///
+/// // Get the lattice parameter from the number density
+/// let lattice = Lattice::BodyCenteredCubic;
+/// let a = lattice.lattice_parameter(0.05);
+/// let system = crystal(lattice, "Na", a, [4, 4, 4]);
+/// assert_eq!(system.size(), 128);
+/// ```
+pub fn crystal<S>(lattice: Lattice, name: S, a: f64, repeat: [usize; 3]) -> System
+where
+    S: Into<String>,
+{
+    assert!(a > 0.0, "the lattice parameter of a crystal must be positive");
+    assert!(
+        repeat.iter().all(|&n| n > 0),
+        "the conventional cell of a crystal must be repeated at least once"
+    );
+
+    let name = name.into();
+    let basis = lattice.basis();
+    let mut system = System::with_cell(lattice.supercell(a, repeat));
+    for i in 0..repeat[0] {
+        for j in 0..repeat[1] {
+            for k in 0..repeat[2] {
+                for site in &basis {
+                    let fractional = Vector3D::new(
+                        (i as f64 + site[0]) / repeat[0] as f64,
+                        (j as f64 + site[1]) / repeat[1] as f64,
+                        (k as f64 + site[2]) / repeat[2] as f64,
+                    );
+                    let position = system.cell.cartesian(&fractional);
+                    system.add_molecule(Molecule::new(Particle::with_position(&*name, position)));
+                }
+            }
+        }
+    }
+    return system;
+}
+
+/// Create a system containing `n` copies of the molecule described by
+/// `template`, on a simple cubic grid in a cubic unit cell. The size of the
+/// cell is set to get the given mass `density` (in u/A^3). The grid is only
+/// completely filled if `n` is a cube (8, 27, 64, ...).
+///
+/// # Panics
+///
+/// If `n` is zero, if the `density` is not positive or if the particles in
+/// the `template` do not have any mass.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::MoleculeTemplate;
+/// use lumol_core::sys::build::solvent_grid;
+/// use lumol_core::types::Vector3D;
+/// use lumol_core::units;
+///
+/// let water = MoleculeTemplate::new()
+///     .particle("O", Vector3D::new(0.0, 0.0, 0.0), -0.8476)
+///     .particle("H", Vector3D::new(0.8164, 0.5773, 0.0), 0.4238)
+///     .particle("H", Vector3D::new(-0.8164, 0.5773, 0.0), 0.4238)
+///     .bond(0, 1)
+///     .bond(0, 2);
+///
+/// let density = units::from(1.0, "g/cm^3").unwrap();
+/// let system = solvent_grid(&water, 216, density);
+/// assert_eq!(system.molecules().count(), 216);
+/// ```
+pub fn solvent_grid(template: &MoleculeTemplate, n: usize, density: f64) -> System {
+    assert!(n > 0, "can not create a solvent grid without molecules");
+    assert!(density > 0.0, "the density of a solvent grid must be positive");
+    let mass = template.mass();
+    assert!(mass > 0.0, "can not create a solvent grid from a massless molecule template");
+
+    let volume = n as f64 * mass / density;
+    let mut system = System::with_cell(UnitCell::cubic(f64::cbrt(volume)));
+    system.add_molecules(template, n);
+    return system;
+}
+
+/// Solvate the `solute` in the `solvent_box`, removing all the solvent
+/// molecules with a particle closer than `tolerance` to any particle of the
+/// solute.
+///
+/// The returned system contains the solute molecules followed by the
+/// remaining solvent molecules, and uses the unit cell of the solvent box
+/// and the interactions of the solute. The positions of the solute
+/// particles are used as is, and should be inside the solvent box.
+///
+/// # Panics
+///
+/// If the unit cell of the solvent box is infinite, or if the `tolerance`
+/// is negative.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell};
+/// use lumol_core::sys::build::{crystal, solvate, Lattice};
+///
+/// let solvent = crystal(Lattice::SimpleCubic, "Ar", 4.0, [5, 5, 5]);
+///
+/// let mut solute = System::new();
+/// solute.add_molecule(Molecule::new(Particle::with_position("Na", [8.0, 8.0, 8.0].into())));
+///
+/// let system = solvate(&solute, &solvent, 2.5);
+/// assert_eq!(system.size(), 125);
+/// assert_eq!(system.particles().name[0], "Na");
+/// ```
+pub fn solvate(solute: &System, solvent_box: &System, tolerance: f64) -> System {
+    assert!(
+        !solvent_box.cell.is_infinite(),
+        "can not solvate in a solvent box with an infinite cell"
+    );
+    assert!(tolerance >= 0.0, "the tolerance of solvation can not be negative");
+
+    let cell = solvent_box.cell;
+    let tolerance2 = tolerance * tolerance;
+    let solute_positions = solute.particles().position;
+    let overlaps = |position: &Vector3D| {
+        solute_positions.iter().any(|solute_position| {
+            let mut r = position - solute_position;
+            cell.vector_image(&mut r);
+            r.norm2() < tolerance2
+        })
+    };
+
+    // Copy the solvent molecules without overlap, storing the translation
+    // of the indexes of the kept molecules
+    let mut solvent = System::with_cell(cell);
+    let mut shifts = Vec::new();
+    for molecule in solvent_box.molecules() {
+        if molecule.particles().position.iter().any(&overlaps) {
+            shifts.push(None);
+        } else {
+            let start = molecule.indexes().start as isize;
+            shifts.push(Some(solvent.size() as isize - start));
+            solvent.add_molecule(molecule.to_owned());
+        }
+    }
+
+    let mut solvent_sites = Vec::new();
+    for site in solvent_box.virtual_sites() {
+        if let Some(shift) = shifts[solvent_box.molecule_id(site.site())] {
+            let mut site = site.clone();
+            site.translate_by(shift);
+            solvent_sites.push(site);
+        }
+    }
+
+    let mut system = solute.clone();
+    system.cell = cell;
+    let offset = system.size() as isize;
+    system.merge(&solvent);
+    for mut site in solvent_sites {
+        site.translate_by(offset);
+        system.add_virtual_site(site);
+    }
+    return system;
+}
+
+/// Default minimal distance between particles for random packing, in A
+const DEFAULT_PACKING_TOLERANCE: f64 = 2.0;
+/// Default number of insertion attempts for each molecule in random packing
+const DEFAULT_PACKING_ATTEMPTS: usize = 1000;
+
+/// Insert copies of a molecule at random positions and orientations in a
+/// system, rejecting the insertions where a particle of the new molecule is
+/// closer than a given tolerance to any particle already in the system.
+///
+/// This is intended to quickly prepare liquid boxes or to solvate a solute.
+/// The overlap check compares all the pairs of particles, and becomes slow
+/// for very large systems.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, UnitCell, MoleculeTemplate};
+/// use lumol_core::sys::build::RandomPacking;
+/// use lumol_core::types::Vector3D;
+///
+/// let co2 = MoleculeTemplate::new()
+///     .particle("O", Vector3D::new(-1.16, 0.0, 0.0), -0.35)
+///     .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.7)
+///     .particle("O", Vector3D::new(1.16, 0.0, 0.0), -0.35)
+///     .bond(0, 1)
+///     .bond(1, 2);
+///
+/// let mut system = System::with_cell(UnitCell::cubic(30.0));
+/// let mut packing = RandomPacking::new().tolerance(2.5).seed(42);
+/// let inserted = packing.pack(&mut system, &co2, 50);
+/// assert_eq!(inserted, 50);
+/// assert_eq!(system.molecules().count(), 50);
+/// ```
+pub struct RandomPacking {
+    /// Minimal distance between a new particle and the existing ones
+    tolerance: f64,
+    /// Maximal number of insertion attempts for each molecule
+    max_attempts: usize,
+    /// Random number generator used for positions and orientations
+    rng: XorShiftRng,
+}
+
+impl RandomPacking {
+    /// Create a new `RandomPacking` with a tolerance of 2 A and up to 1000
+    /// insertion attempts for each molecule.
+    pub fn new() -> RandomPacking {
+        RandomPacking {
+            tolerance: DEFAULT_PACKING_TOLERANCE,
+            max_attempts: DEFAULT_PACKING_ATTEMPTS,
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+        }
+    }
+
+    /// Set the minimal distance between a new particle and the particles
+    /// already in the system.
+    ///
+    /// # Panics
+    ///
+    /// If the `tolerance` is negative.
+    pub fn tolerance(mut self, tolerance: f64) -> RandomPacking {
+        assert!(tolerance >= 0.0, "the tolerance of random packing can not be negative");
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the maximal number of insertion attempts for each molecule.
+    pub fn max_attempts(mut self, max_attempts: usize) -> RandomPacking {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the seed of the random number generator.
+    pub fn seed(mut self, seed: u64) -> RandomPacking {
+        let mut bytes = [0; 16];
+        for i in 0..8 {
+            bytes[2 * i] = ((seed >> (8 * i)) & 0xff) as u8;
+            bytes[2 * i + 1] = 0xa5;
+        }
+        self.rng = XorShiftRng::from_seed(bytes);
+        self
+    }
+
+    /// Try to insert `n` copies of the molecule described by `template` in
+    /// the `system`, and return the number of molecules actually inserted.
+    /// This can be less than `n` if a molecule could not be inserted without
+    /// overlap after the maximal number of attempts, in which case no more
+    /// insertions are tried.
+    ///
+    /// # Panics
+    ///
+    /// If the unit cell of the system is infinite, or if the template is
+    /// empty.
+    pub fn pack(&mut self, system: &mut System, template: &MoleculeTemplate, n: usize) -> usize {
+        assert!(
+            !system.cell.is_infinite(),
+            "can not pack molecules from a template in an infinite cell"
+        );
+        let molecule = template.molecule();
+        let center = template.center();
+
+        for inserted in 0..n {
+            let mut found = None;
+            for _ in 0..self.max_attempts {
+                let rotation = self.random_rotation();
+                let fractional = Vector3D::new(self.rng.gen(), self.rng.gen(), self.rng.gen());
+                let position = system.cell.cartesian(&fractional);
+
+                let positions = molecule.particles().position.iter()
+                    .map(|particle| position + rotation * (particle - center))
+                    .collect::<Vec<_>>();
+                if !self.overlaps(system, &positions) {
+                    found = Some(positions);
+                    break;
+                }
+            }
+
+            if let Some(positions) = found {
+                let mut molecule = molecule.clone();
+                molecule.particles_mut().position.copy_from_slice(&positions);
+                system.add_molecule(molecule);
+            } else {
+                warn!(
+                    "could only insert {} out of {} molecules without overlap in random packing",
+                    inserted, n
+                );
+                return inserted;
+            }
+        }
+        return n;
+    }
+
+    /// Check if any of the `positions` is closer to a particle in the
+    /// `system` than the tolerance
+    fn overlaps(&self, system: &System, positions: &[Vector3D]) -> bool {
+        let tolerance2 = self.tolerance * self.tolerance;
+        for existing in system.particles().position {
+            for position in positions {
+                let mut r = position - existing;
+                system.cell.vector_image(&mut r);
+                if r.norm2() < tolerance2 {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    /// Get an uniformly distributed random rotation matrix, using the
+    /// quaternion method from K. Shoemake, Graphics Gems III (1992)
+    fn random_rotation(&mut self) -> Matrix3 {
+        let u1 = self.rng.gen::<f64>();
+        let u2 = self.rng.gen::<f64>();
+        let u3 = self.rng.gen::<f64>();
+
+        let x = f64::sqrt(1.0 - u1) * f64::sin(2.0 * PI * u2);
+        let y = f64::sqrt(1.0 - u1) * f64::cos(2.0 * PI * u2);
+        let z = f64::sqrt(u1) * f64::sin(2.0 * PI * u3);
+        let w = f64::sqrt(u1) * f64::cos(2.0 * PI * u3);
+
+        Matrix3::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ])
+    }
+}
+
+impl Default for RandomPacking {
+    fn default() -> RandomPacking {
+        RandomPacking::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Count the neighbors of the particle closest to the center of the cell
+    /// at the given `distance`, without using periodic boundary conditions.
+    fn central_neighbors(system: &System, distance: f64) -> usize {
+        let positions = system.particles().position;
+        let center = system.cell.cartesian(&Vector3D::new(0.5, 0.5, 0.5));
+        let mut central = 0;
+        for (i, position) in positions.iter().enumerate() {
+            if (position - center).norm() < (positions[central] - center).norm() {
+                central = i;
+            }
+        }
+
+        positions.iter()
+                 .filter(|&&position| {
+                     f64::abs((position - positions[central]).norm() - distance) < 1e-9
+                 })
+                 .count()
+    }
+
+    #[test]
+    fn lattices() {
+        let a = 3.0;
+        let system = crystal(Lattice::SimpleCubic, "Ar", a, [4, 4, 4]);
+        assert_eq!(system.size(), 64);
+        assert_eq!(central_neighbors(&system, a), 6);
+
+        let system = crystal(Lattice::BodyCenteredCubic, "Ar", a, [4, 4, 4]);
+        assert_eq!(system.size(), 128);
+        assert_eq!(central_neighbors(&system, a * f64::sqrt(3.0) / 2.0), 8);
+
+        let system = crystal(Lattice::FaceCenteredCubic, "Ar", a, [4, 4, 4]);
+        assert_eq!(system.size(), 256);
+        assert_eq!(central_neighbors(&system, a / f64::sqrt(2.0)), 12);
+
+        let system = crystal(Lattice::HexagonalClosePacked, "Ar", a, [4, 4, 4]);
+        assert_eq!(system.size(), 128);
+        assert_eq!(central_neighbors(&system, a), 12);
+
+        let system = crystal(Lattice::Diamond, "C", a, [4, 4, 4]);
+        assert_eq!(system.size(), 512);
+        assert_eq!(central_neighbors(&system, a * f64::sqrt(3.0) / 4.0), 4);
+    }
+
+    #[test]
+    fn lattice_parameter() {
+        for &lattice in &[
+            Lattice::SimpleCubic,
+            Lattice::BodyCenteredCubic,
+            Lattice::FaceCenteredCubic,
+            Lattice::HexagonalClosePacked,
+            Lattice::Diamond,
+        ] {
+            let a = lattice.lattice_parameter(0.02);
+            let system = crystal(lattice, "Ar", a, [2, 3, 4]);
+            assert_relative_eq!(system.size() as f64 / system.volume(), 0.02, max_relative = 1e-12);
+        }
+    }
+
+    #[test]
+    fn solvent() {
+        let template = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("O", Vector3D::new(1.2, 0.0, 0.0), 0.0)
+            .bond(0, 1);
+
+        let system = solvent_grid(&template, 27, 0.5);
+        assert_eq!(system.size(), 54);
+        assert_eq!(system.molecules().count(), 27);
+        let mass = system.particles().mass.iter().sum::<f64>();
+        assert_relative_eq!(mass / system.volume(), 0.5, max_relative = 1e-12);
+    }
+
+    #[test]
+    fn random_packing() {
+        let template = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("O", Vector3D::new(1.2, 0.0, 0.0), 0.0)
+            .bond(0, 1);
+
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 5.0, 5.0].into())));
+
+        let mut packing = RandomPacking::new().tolerance(2.5).seed(1234);
+        assert_eq!(packing.pack(&mut system, &template, 30), 30);
+        assert_eq!(system.molecules().count(), 31);
+        assert_eq!(system.size(), 61);
+
+        for i in 0..system.size() {
+            for j in (i + 1)..system.size() {
+                if system.are_in_same_molecule(i, j) {
+                    // The geometry of the molecules is conserved
+                    assert_relative_eq!(system.distance(i, j), 1.2, epsilon = 1e-12);
+                } else {
+                    assert!(system.distance(i, j) >= 2.5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_packing_failure() {
+        let template = MoleculeTemplate::new().particle("Ar", Vector3D::zero(), 0.0);
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+
+        // There is no space for a second particle with this tolerance
+        let mut packing = RandomPacking::new().tolerance(9.0).max_attempts(100);
+        assert_eq!(packing.pack(&mut system, &template, 3), 1);
+        assert_eq!(system.size(), 1);
+    }
+
+    #[test]
+    fn solvation() {
+        let solvent = crystal(Lattice::SimpleCubic, "Ar", 3.0, [4, 4, 4]);
+
+        let mut solute = System::new();
+        let mut molecule = Molecule::new(Particle::with_position("C", [0.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("O", [1.5, 0.0, 0.0].into()));
+        solute.add_molecule(molecule);
+
+        let system = solvate(&solute, &solvent, 2.0);
+        // Particles at (0, 0, 0) and (3, 0, 0) are removed
+        assert_eq!(system.size(), 64);
+        assert_eq!(system.molecules().count(), 63);
+        assert_eq!(system.cell, solvent.cell);
+        assert_eq!(system.particles().name[0], "C");
+        assert_eq!(system.molecule(0).bonds().len(), 1);
+
+        // Periodic boundary conditions are used
+        let mut solute = System::new();
+        solute.add_molecule(Molecule::new(Particle::with_position("Na", [11.8, 0.0, 0.0].into())));
+        let system = solvate(&solute, &solvent, 0.5);
+        assert_eq!(system.size(), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "the conventional cell of a crystal must be repeated at least once")]
+    fn no_repetition() {
+        let _ = crystal(Lattice::SimpleCubic, "Ar", 3.0, [2, 0, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not create a solvent grid from a massless molecule template")]
+    fn massless_solvent() {
+        let template = MoleculeTemplate::new().particle("X", Vector3D::zero(), 0.0);
+        let _ = solvent_grid(&template, 8, 1.0);
+    }
+}
@@ -34,12 +34,16 @@ pub struct EnergyCache {
     angles: f64,
     /// Energy of all the dihedrals angles in the system
     dihedrals: f64,
+    /// Energy of all the improper dihedrals angles in the system
+    impropers: f64,
     /// Energy of coulombic interactions
     coulomb: f64,
     /// Energy of global interactions
     global: f64,
     /// Callback to be called to update the cache if the system is modified
     updater: Option<UpdateCallback>,
+    /// Energy change computed by the last call to a `*_cost` function
+    last_cost: Option<f64>,
 }
 
 impl EnergyCache {
@@ -52,9 +56,11 @@ impl EnergyCache {
             bonds: 0.0,
             angles: 0.0,
             dihedrals: 0.0,
+            impropers: 0.0,
             coulomb: 0.0,
             global: 0.0,
             updater: None,
+            last_cost: None,
         }
     }
 
@@ -66,8 +72,10 @@ impl EnergyCache {
         self.bonds = 0.0;
         self.angles = 0.0;
         self.dihedrals = 0.0;
+        self.impropers = 0.0;
         self.coulomb = 0.0;
         self.global = 0.0;
+        self.last_cost = None;
     }
 
     /// Initialize the cache to be used with `system`. After a call to this
@@ -94,6 +102,7 @@ impl EnergyCache {
         self.bonds = evaluator.bonds();
         self.angles = evaluator.angles();
         self.dihedrals = evaluator.dihedrals();
+        self.impropers = evaluator.impropers();
         self.coulomb = evaluator.coulomb();
         self.global = evaluator.global();
     }
@@ -107,6 +116,7 @@ impl EnergyCache {
         energy += self.bonds;
         energy += self.angles;
         energy += self.dihedrals;
+        energy += self.impropers;
 
         energy += self.coulomb;
         energy += self.global;
@@ -114,9 +124,17 @@ impl EnergyCache {
         return energy;
     }
 
+    /// Get the energy change computed by the last call to a
+    /// `EnergyCache::*_cost` function, or `None` if the cache was updated or
+    /// marked as unused since then.
+    pub fn last_cost(&self) -> Option<f64> {
+        self.last_cost
+    }
+
     /// Update the cache after a call to a `EnergyCache::*_cost` function or
     /// `EnergyCache::unused`.
     pub fn update(&mut self, system: &mut System) {
+        self.last_cost = None;
         let updater = mem::replace(&mut self.updater, None);
         if let Some(updater) = updater {
             updater(self, system);
@@ -131,6 +149,7 @@ impl EnergyCache {
     /// still want it to be updated. Future call to `EnergyCache::update` will
     /// recompute the full cache.
     pub fn unused(&mut self) {
+        self.last_cost = None;
         self.updater = Some(Box::new(|cache, system| {
             cache.init(system);
         }))
@@ -176,7 +195,7 @@ impl EnergyCache {
 
         // Pairs tail correction do not change when moving a single molecule
 
-        // Bonds / Angles / Dihedrals terms do not change
+        // Bonds / Angles / Dihedrals / Impropers terms do not change
 
         let coulomb_delta = if let Some(coulomb) = system.coulomb_potential() {
             coulomb.move_molecule_cost(system, molecule_id, new_positions)
@@ -220,6 +239,107 @@ impl EnergyCache {
                 global.update();
             }
         }));
+        self.last_cost = Some(cost);
+        return cost;
+    }
+
+    /// Get the cost of changing the positions of the particles in the
+    /// molecule at `molecule_id` to `new_positions`, where the molecule does
+    /// not need to move as a rigid body.
+    ///
+    /// Contrary to `move_molecule_cost`, this function computes the changes
+    /// in the intramolecular pairs and in the bonds, angles, dihedral and
+    /// improper interactions. It can be used for moves changing the internal
+    /// configuration of flexible molecules. The energies of the bonded,
+    /// coulombic and global potentials are computed on a copy of the system,
+    /// to keep the internal state of the potentials unchanged if the move is
+    /// rejected.
+    ///
+    /// This function ***DOES NOT*** update the cache, the `update` function
+    /// MUST be called if the molecule is effectively moved.
+    pub fn deform_molecule_cost(
+        &mut self,
+        system: &System,
+        molecule_id: usize,
+        new_positions: &[Vector3D],
+    ) -> f64 {
+        let evaluator = system.energy_evaluator();
+        let positions = system.particles().position;
+        let indexes = system.molecule(molecule_id).indexes();
+        let (start, end) = (indexes.start, indexes.end);
+        assert_eq!(new_positions.len(), end - start, "wrong size for the new positions");
+
+        let new_position = |j: usize| if start <= j && j < end {
+            new_positions[j - start]
+        } else {
+            positions[j]
+        };
+
+        let mut new_pairs = Array2::<f64>::zeros((end - start, system.size()));
+        let mut pairs_delta = 0.0;
+        for i in start..end {
+            for j in 0..system.size() {
+                // Pairs inside the molecule are only counted once
+                if j == i || (start <= j && j < end && j > i) {
+                    continue;
+                }
+                let r = system.cell.distance(&new_position(j), &new_positions[i - start]);
+                let path = system.bond_path(i, j);
+                let energy = evaluator.pair(path, r, i, j);
+
+                pairs_delta += energy;
+                new_pairs[(i - start, j)] = energy;
+                pairs_delta -= self.pairs_cache[(i, j)];
+            }
+        }
+
+        // Pairs tail correction do not change when moving particles
+
+        let mut copy = system.clone();
+        {
+            let mut molecule = copy.molecule_mut(molecule_id);
+            for (position, new) in soa_zip!(
+                molecule.particles_mut(), [mut position], new_positions
+            ) {
+                *position = *new;
+            }
+        }
+        let evaluator = copy.energy_evaluator();
+        let bonds = evaluator.bonds();
+        let angles = evaluator.angles();
+        let dihedrals = evaluator.dihedrals();
+        let impropers = evaluator.impropers();
+        let coulomb = evaluator.coulomb();
+        let global = evaluator.global();
+
+        let cost = pairs_delta + (bonds - self.bonds) + (angles - self.angles)
+            + (dihedrals - self.dihedrals) + (impropers - self.impropers)
+            + (coulomb - self.coulomb) + (global - self.global);
+
+        self.updater = Some(Box::new(move |cache, system| {
+            debug_assert_eq!(system.size(), cache.pairs_cache.dim().0);
+            for i in start..end {
+                for j in 0..system.size() {
+                    if j == i || (start <= j && j < end && j > i) {
+                        continue;
+                    }
+                    cache.pairs_cache[(i, j)] = new_pairs[(i - start, j)];
+                    cache.pairs_cache[(j, i)] = new_pairs[(i - start, j)];
+                }
+            }
+
+            cache.pairs += pairs_delta;
+            cache.bonds = bonds;
+            cache.angles = angles;
+            cache.dihedrals = dihedrals;
+            cache.impropers = impropers;
+            // Compute again the energy of the potentials in the system to
+            // update their internal state
+            let evaluator = system.energy_evaluator();
+            cache.coulomb = evaluator.coulomb();
+            cache.global = evaluator.global();
+        }));
+        self.last_cost = Some(cost);
         return cost;
     }
 
@@ -233,9 +353,9 @@ impl EnergyCache {
     /// - Coulomb interactions;
     /// - global interactions;
     ///
-    /// It **DOES NOT** recompute bonds, angles and dihedral interactions. You
-    /// must not use this function when the intramolecular configuration
-    /// changed.
+    /// It **DOES NOT** recompute bonds, angles, dihedral and improper
+    /// interactions. You must not use this function when the intramolecular
+    /// configuration changed.
     ///
     /// This function ***DOES NOT*** update the cache, the `update` function
     /// MUST be called if the molecules are effectively moved.
@@ -292,14 +412,207 @@ impl EnergyCache {
                 }
             }
         }));
+        self.last_cost = Some(cost);
         cost
     }
+
+    /// Get the cost of adding the molecule at `molecule_id` to the system.
+    /// The molecule must already be in the `system` as its last molecule,
+    /// while this cache still describes the system without this molecule.
+    ///
+    /// This function computes the energy of the pairs and the bonded terms
+    /// involving the new molecule, and the change in the long range
+    /// corrections. The changes in the coulombic and global energies are
+    /// computed with the `GlobalCache::add_molecule_cost` function of these
+    /// potentials.
+    ///
+    /// This function ***DOES NOT*** update the cache, the `update` function
+    /// MUST be called if the molecule is effectively kept in the system.
+    ///
+    /// # Panics
+    ///
+    /// If the molecule is not the last one in the system.
+    pub fn add_molecule_cost(&mut self, system: &System, molecule_id: usize) -> f64 {
+        let indexes = system.molecule(molecule_id).indexes();
+        assert_eq!(
+            indexes.end, system.size(),
+            "the added molecule must be the last one in the system"
+        );
+        let start = indexes.start;
+        debug_assert_eq!(self.pairs_cache.dim(), (start, start));
+
+        // Pairs between the new particles and all the previous particles,
+        // including the other new particles
+        let evaluator = system.energy_evaluator();
+        let mut new_pairs = Vec::new();
+        let mut pairs_delta = 0.0;
+        for i in indexes {
+            for j in 0..i {
+                let r = system.nearest_image(i, j).norm();
+                let path = system.bond_path(i, j);
+                let energy = evaluator.pair(path, r, i, j);
+                pairs_delta += energy;
+                new_pairs.push(energy);
+            }
+        }
+
+        // The system already contains the new molecule
+        let pairs_tail_delta = evaluator.pairs_tail() - self.pairs_tail;
+
+        let bonds_delta = evaluator.molecule_bonds(molecule_id);
+        let angles_delta = evaluator.molecule_angles(molecule_id);
+        let dihedrals_delta = evaluator.molecule_dihedrals(molecule_id);
+        let impropers_delta = evaluator.molecule_impropers(molecule_id);
+
+        let coulomb_delta = if let Some(coulomb) = system.coulomb_potential() {
+            coulomb.add_molecule_cost(system, molecule_id)
+        } else {
+            0.0
+        };
+
+        let mut global_delta = 0.0;
+        for global in system.global_potentials() {
+            global_delta += global.add_molecule_cost(system, molecule_id);
+        }
+
+        let cost = pairs_delta + pairs_tail_delta + bonds_delta + angles_delta
+            + dihedrals_delta + impropers_delta + coulomb_delta + global_delta;
+
+        self.updater = Some(Box::new(move |cache, system| {
+            let size = system.size();
+            let mut pairs_cache = Array2::zeros((size, size));
+            for i in 0..start {
+                for j in 0..start {
+                    pairs_cache[(i, j)] = cache.pairs_cache[(i, j)];
+                }
+            }
+            let mut energies = new_pairs.iter();
+            for i in start..size {
+                for j in 0..i {
+                    let energy = *energies.next().expect("missing pair energy in cache");
+                    pairs_cache[(i, j)] = energy;
+                    pairs_cache[(j, i)] = energy;
+                }
+            }
+            cache.pairs_cache = pairs_cache;
+
+            cache.pairs += pairs_delta;
+            cache.pairs_tail += pairs_tail_delta;
+            cache.bonds += bonds_delta;
+            cache.angles += angles_delta;
+            cache.dihedrals += dihedrals_delta;
+            cache.impropers += impropers_delta;
+            cache.coulomb += coulomb_delta;
+            cache.global += global_delta;
+
+            // Update the cache for the global potentials
+            if let Some(coulomb) = system.coulomb_potential() {
+                coulomb.update();
+            }
+
+            for global in system.global_potentials() {
+                global.update();
+            }
+        }));
+        self.last_cost = Some(cost);
+        return cost;
+    }
+
+    /// Get the cost of removing the molecule at `molecule_id` from the
+    /// `system`. The molecule is still in the system when calling this
+    /// function.
+    ///
+    /// This function computes the energy of the pairs and the bonded terms
+    /// involving the removed molecule, and the change in the long range
+    /// corrections. The changes in the coulombic and global energies are
+    /// computed with the `GlobalCache::remove_molecule_cost` function of
+    /// these potentials.
+    ///
+    /// This function ***DOES NOT*** update the cache, the `update` function
+    /// MUST be called after the molecule is effectively removed from the
+    /// system.
+    pub fn remove_molecule_cost(&mut self, system: &System, molecule_id: usize) -> f64 {
+        let indexes = system.molecule(molecule_id).indexes();
+        let (start, end) = (indexes.start, indexes.end);
+        let size = system.size();
+        debug_assert_eq!(self.pairs_cache.dim(), (size, size));
+
+        let mut pairs_delta = 0.0;
+        for i in start..end {
+            for j in 0..size {
+                // Pairs inside the molecule are only counted once
+                if j < start || j >= end || j > i {
+                    pairs_delta -= self.pairs_cache[(i, j)];
+                }
+            }
+        }
+
+        let evaluator = system.energy_evaluator();
+        let mut composition = system.composition();
+        for &kind in system.molecule(molecule_id).particles().kind {
+            composition.remove_particle(kind);
+        }
+        let pairs_tail_delta = evaluator.pairs_tail_for(&composition) - self.pairs_tail;
+
+        let bonds_delta = -evaluator.molecule_bonds(molecule_id);
+        let angles_delta = -evaluator.molecule_angles(molecule_id);
+        let dihedrals_delta = -evaluator.molecule_dihedrals(molecule_id);
+        let impropers_delta = -evaluator.molecule_impropers(molecule_id);
+
+        let coulomb_delta = if let Some(coulomb) = system.coulomb_potential() {
+            coulomb.remove_molecule_cost(system, molecule_id)
+        } else {
+            0.0
+        };
+
+        let mut global_delta = 0.0;
+        for global in system.global_potentials() {
+            global_delta += global.remove_molecule_cost(system, molecule_id);
+        }
+
+        let cost = pairs_delta + pairs_tail_delta + bonds_delta + angles_delta
+            + dihedrals_delta + impropers_delta + coulomb_delta + global_delta;
+
+        self.updater = Some(Box::new(move |cache, system| {
+            let removed = end - start;
+            let old = |i: usize| if i < start { i } else { i + removed };
+            let size = system.size();
+            debug_assert_eq!(size + removed, cache.pairs_cache.dim().0);
+            let mut pairs_cache = Array2::zeros((size, size));
+            for i in 0..size {
+                for j in 0..size {
+                    pairs_cache[(i, j)] = cache.pairs_cache[(old(i), old(j))];
+                }
+            }
+            cache.pairs_cache = pairs_cache;
+
+            cache.pairs += pairs_delta;
+            cache.pairs_tail += pairs_tail_delta;
+            cache.bonds += bonds_delta;
+            cache.angles += angles_delta;
+            cache.dihedrals += dihedrals_delta;
+            cache.impropers += impropers_delta;
+            cache.coulomb += coulomb_delta;
+            cache.global += global_delta;
+
+            // Update the cache for the global potentials
+            if let Some(coulomb) = system.coulomb_potential() {
+                coulomb.update();
+            }
+
+            for global in system.global_potentials() {
+                global.update();
+            }
+        }));
+        self.last_cost = Some(cost);
+        return cost;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use energy::{Harmonic, LennardJones, NullPotential, Wolf};
+    use energy::{Ewald, Harmonic, LennardJones, NullPotential, SharedEwald, Wolf};
     use energy::PairInteraction;
     use sys::System;
     use types::Vector3D;
@@ -420,6 +733,7 @@ mod tests {
             Vector3D::new(-1.5225595970887147, 3.159720742250528, -0.46993124185939705),
         ];
         let cost = cache.move_molecule_cost(&system, 0, new_positions);
+        assert_eq!(cache.last_cost(), Some(cost));
 
         system.particles_mut().position[0] = new_positions[0];
         system.particles_mut().position[1] = new_positions[1];
@@ -430,6 +744,7 @@ mod tests {
 
         cache.update(&mut system);
         assert_ulps_eq!(cache.energy(), new_energy);
+        assert_eq!(cache.last_cost(), None);
 
         // Check that the cache is really updated
         let old_energy = new_energy;
@@ -483,4 +798,116 @@ mod tests {
         let new_energy = new_system.potential_energy();
         assert_ulps_eq!(cost, new_energy - old_energy, epsilon = 1e-12);
     }
+
+    fn check_add_remove_molecule(mut system: System) {
+        // Compute the energy using a copy of the system, to keep the state of
+        // the coulombic potential between the cost and the update of the cache
+        let energy = |system: &System| system.clone().potential_energy();
+        let mut cache = EnergyCache::new();
+        let old_energy = energy(&system);
+        cache.init(&system);
+
+        // Add a copy of the first molecule
+        let mut molecule = system.molecule(0).to_owned();
+        for position in molecule.particles_mut().position {
+            *position += Vector3D::new(0.0, 2.5, 0.5);
+        }
+        system.add_molecule(molecule);
+
+        let cost = cache.add_molecule_cost(&system, 2);
+        let new_energy = energy(&system);
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-9);
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), new_energy, max_relative = 1e-9);
+
+        // Remove the second molecule
+        let old_energy = new_energy;
+        let cost = cache.remove_molecule_cost(&system, 1);
+        let _ = system.remove_molecule(1);
+        let new_energy = energy(&system);
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-9);
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), new_energy, max_relative = 1e-9);
+
+        // The cache is still usable to move molecules
+        let old_energy = new_energy;
+        let new_positions = system.molecule(1).particles().position.iter()
+            .map(|position| position + Vector3D::new(0.3, 0.0, 0.0))
+            .collect::<Vec<_>>();
+        let cost = cache.move_molecule_cost(&system, 1, &new_positions);
+        {
+            let mut molecule = system.molecule_mut(1);
+            for (position, new) in soa_zip!(
+                molecule.particles_mut(), [mut position], &new_positions
+            ) {
+                *position = *new;
+            }
+        }
+        let new_energy = energy(&system);
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-9);
+    }
+
+    #[test]
+    fn add_remove_molecule() {
+        check_add_remove_molecule(testing_system());
+
+        // Using Ewald summation and tail corrections
+        let mut system = testing_system();
+        system.set_coulomb_potential(Box::new(SharedEwald::new(Ewald::new(4.5, 5, None))));
+        let mut pair = PairInteraction::new(
+            Box::new(LennardJones {
+                sigma: 1.0,
+                epsilon: units::from(0.3, "kJ/mol").unwrap(),
+            }),
+            3.0,
+        );
+        pair.enable_tail_corrections();
+        system.add_pair_potential(("O", "H"), pair);
+        check_add_remove_molecule(system);
+    }
+
+    #[test]
+    fn deform_molecule() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        let old_energy = system.potential_energy();
+        cache.init(&system);
+
+        // Change the intramolecular configuration of the first molecule
+        let mut new_positions = system.molecule(0).particles().position.to_vec();
+        new_positions[0] += Vector3D::new(0.0, 0.4, 0.1);
+        new_positions[2] += Vector3D::new(0.3, -0.2, 0.0);
+
+        let cost = cache.deform_molecule_cost(&system, 0, &new_positions);
+        {
+            let mut molecule = system.molecule_mut(0);
+            for (position, new) in soa_zip!(
+                molecule.particles_mut(), [mut position], &new_positions
+            ) {
+                *position = *new;
+            }
+        }
+        let new_energy = system.potential_energy();
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-9);
+
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), new_energy, max_relative = 1e-9);
+
+        // The cache is still usable to move molecules
+        let old_energy = new_energy;
+        let new_positions = system.molecule(1).particles().position.iter()
+            .map(|position| position + Vector3D::new(0.0, 0.0, 0.5))
+            .collect::<Vec<_>>();
+        let cost = cache.move_molecule_cost(&system, 1, &new_positions);
+        {
+            let mut molecule = system.molecule_mut(1);
+            for (position, new) in soa_zip!(
+                molecule.particles_mut(), [mut position], &new_positions
+            ) {
+                *position = *new;
+            }
+        }
+        let new_energy = system.potential_energy();
+        assert_relative_eq!(cost, new_energy - old_energy, max_relative = 1e-9);
+    }
 }
@@ -0,0 +1,317 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Cell lists, used to find pairs of close particles in linear time.
+use std::f64;
+
+use math::floor;
+use sys::Configuration;
+use types::Vector3D;
+
+/// A cell list (or linked list) decomposition of a configuration.
+///
+/// The unit cell is divided in bins along its three vectors, with bins at
+/// least as wide as a given cutoff distance. Any pair of particles closer than
+/// the cutoff distance is then either in the same bin or in neighboring bins,
+/// and the pairs can be enumerated by only looking at these bins. This makes
+/// the cost of enumerating the pairs linear in the number of particles.
+///
+/// Triclinic cells are divided along their cell vectors, using the distance
+/// between opposite faces of the cell to compute the number of bins. For
+/// infinite cells, the bounding box of the particles is divided instead. The
+/// same is done along the non-periodic directions of partially periodic
+/// cells.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell, CellList};
+///
+/// let mut system = System::with_cell(UnitCell::cubic(20.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [3.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [10.0, 10.0, 10.0].into())));
+///
+/// let cells = CellList::new(&system, 5.0);
+/// // All the pairs closer than the cutoff are in neighboring bins
+/// assert!(cells.neighbors(0).contains(&1));
+/// // Only the particles with a larger index are returned
+/// assert!(cells.neighbors(2).is_empty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CellList {
+    /// Number of bins along each cell vector
+    bins: [usize; 3],
+    /// Are the bins periodic along each cell vector? This is false for
+    /// infinite cells
+    periodic: [bool; 3],
+    /// Indexes of the particles in each bin
+    particles: Vec<Vec<usize>>,
+    /// Bin containing each particle
+    particle_bins: Vec<[usize; 3]>,
+}
+
+impl CellList {
+    /// Build the cell list for the `configuration`, using bins at least as
+    /// wide as `cutoff`.
+    ///
+    /// # Panics
+    ///
+    /// If the cutoff is not positive.
+    pub fn new(configuration: &Configuration, cutoff: f64) -> CellList {
+        assert!(cutoff > 0.0, "the cutoff of a cell list must be positive");
+        let positions = configuration.particles().position;
+        let cell = &configuration.cell;
+
+        // Get the fractional coordinates of the particles, in [0, 1), and
+        // the width of the region to divide in bins along each axis. Along
+        // non-periodic axes, the bounding box of the particles is used, and
+        // infinite cells are handled as a non-periodic cubic cell of side 1.
+        let periodic = cell.periodicity();
+        let (mut fractional, lengths) = if cell.is_infinite() {
+            (positions.to_vec(), Vector3D::new(1.0, 1.0, 1.0))
+        } else {
+            let fractional = positions.iter().map(|position| cell.fractional(position));
+            (fractional.collect::<Vec<_>>(), cell.lengths())
+        };
+
+        let mut widths = lengths;
+        for k in 0..3 {
+            if periodic[k] {
+                for value in &mut fractional {
+                    value[k] -= floor(value[k]);
+                }
+            } else {
+                let mut min = f64::INFINITY;
+                let mut max = f64::NEG_INFINITY;
+                for value in &fractional {
+                    min = f64::min(min, value[k]);
+                    max = f64::max(max, value[k]);
+                }
+
+                let width = if fractional.is_empty() { 0.0 } else { max - min };
+                for value in &mut fractional {
+                    value[k] -= min;
+                    if width > 0.0 {
+                        value[k] /= width;
+                    }
+                }
+                widths[k] = width * lengths[k];
+            }
+        }
+
+        // Limit the total number of bins for sparse systems, using larger
+        // bins is always possible.
+        let max_bins = f64::ceil(f64::cbrt(2.0 * positions.len() as f64));
+        let mut bins = [1; 3];
+        for k in 0..3 {
+            bins[k] = f64::max(1.0, f64::min(max_bins, floor(widths[k] / cutoff))) as usize;
+        }
+
+        let mut particles = vec![Vec::new(); bins[0] * bins[1] * bins[2]];
+        let mut particle_bins = Vec::with_capacity(positions.len());
+        for (i, fractional) in fractional.iter().enumerate() {
+            let mut bin = [0; 3];
+            for k in 0..3 {
+                // Use min to guard against rounding errors giving 1.0
+                bin[k] = usize::min(floor(fractional[k] * bins[k] as f64) as usize, bins[k] - 1);
+            }
+            particles[(bin[0] * bins[1] + bin[1]) * bins[2] + bin[2]].push(i);
+            particle_bins.push(bin);
+        }
+
+        CellList {
+            bins: bins,
+            periodic: periodic,
+            particles: particles,
+            particle_bins: particle_bins,
+        }
+    }
+
+    /// Get the number of bins along each cell vector
+    pub fn bins(&self) -> [usize; 3] {
+        self.bins
+    }
+
+    /// Get the indexes of the particles in the bin at the given `bin`
+    /// position along the three cell vectors.
+    ///
+    /// # Panics
+    ///
+    /// If `bin` is not a valid bin position.
+    pub fn particles_in(&self, bin: [usize; 3]) -> &[usize] {
+        assert!(
+            bin[0] < self.bins[0] && bin[1] < self.bins[1] && bin[2] < self.bins[2],
+            "invalid bin position in cell list"
+        );
+        &self.particles[(bin[0] * self.bins[1] + bin[1]) * self.bins[2] + bin[2]]
+    }
+
+    /// Get the indexes of the neighboring bins of `bin` along the axis `k`,
+    /// including `bin` itself
+    fn neighbor_bins(&self, bin: usize, k: usize) -> Vec<usize> {
+        let n = self.bins[k];
+        let mut neighbors = Vec::with_capacity(3);
+        if self.periodic[k] {
+            for delta in 0..3 {
+                neighbors.push((bin + n + delta - 1) % n);
+            }
+        } else {
+            for delta in 0..3 {
+                if bin + delta >= 1 && bin + delta - 1 < n {
+                    neighbors.push(bin + delta - 1);
+                }
+            }
+        }
+        // With less than three bins, the same bin can appear multiple times
+        neighbors.sort();
+        neighbors.dedup();
+        return neighbors;
+    }
+
+    /// Get the particles `j > i` in the same bin as the particle `i` or in
+    /// the neighboring bins, sorted by index. All the particles `j > i`
+    /// closer to `i` than the cutoff distance are in this list, but the
+    /// list can also contain particles further away.
+    pub fn neighbors(&self, i: usize) -> Vec<usize> {
+        let bin = self.particle_bins[i];
+        let mut neighbors = Vec::new();
+        for a in self.neighbor_bins(bin[0], 0) {
+            for b in self.neighbor_bins(bin[1], 1) {
+                for c in self.neighbor_bins(bin[2], 2) {
+                    let index = (a * self.bins[1] + b) * self.bins[2] + c;
+                    neighbors.extend(self.particles[index].iter().filter(|&&j| j > i));
+                }
+            }
+        }
+        neighbors.sort();
+        return neighbors;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{Molecule, Particle, System, UnitCell};
+    use types::Vector3D;
+
+    /// Get a system with particles on a regular grid, in the given `cell`
+    fn grid_system(cell: UnitCell, n: usize) -> System {
+        let mut system = System::with_cell(cell);
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
+                    let fractional = Vector3D::new(
+                        (i as f64 + 0.1) / n as f64,
+                        (j as f64 + 0.3) / n as f64,
+                        (k as f64 + 0.7) / n as f64,
+                    );
+                    let position = if cell.is_infinite() {
+                        10.0 * fractional
+                    } else {
+                        cell.cartesian(&fractional)
+                    };
+                    system.add_molecule(Molecule::new(Particle::with_position("Ar", position)));
+                }
+            }
+        }
+        return system;
+    }
+
+    /// Check that the cell list contains all the pairs closer than the cutoff
+    fn check_pairs(system: &System, cutoff: f64) {
+        let cells = CellList::new(system, cutoff);
+        for i in 0..system.size() {
+            let neighbors = cells.neighbors(i);
+            for j in (i + 1)..system.size() {
+                if system.distance(i, j) < cutoff {
+                    assert!(neighbors.contains(&j), "missing pair {}-{}", i, j);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bins() {
+        let system = grid_system(UnitCell::ortho(20.0, 30.0, 9.0), 6);
+        let cells = CellList::new(&system, 4.0);
+        assert_eq!(cells.bins(), [5, 7, 2]);
+
+        // The number of bins is limited for sparse systems
+        let system = grid_system(UnitCell::cubic(100.0), 2);
+        let cells = CellList::new(&system, 4.0);
+        assert_eq!(cells.bins(), [3, 3, 3]);
+    }
+
+    #[test]
+    fn particles_in() {
+        let system = grid_system(UnitCell::cubic(20.0), 4);
+        let cells = CellList::new(&system, 10.0);
+        assert_eq!(cells.bins(), [2, 2, 2]);
+
+        let mut count = 0;
+        for a in 0..2 {
+            for b in 0..2 {
+                for c in 0..2 {
+                    let particles = cells.particles_in([a, b, c]);
+                    assert_eq!(particles.len(), 8);
+                    for &i in particles {
+                        let fractional = system.cell.fractional(&system.particles().position[i]);
+                        assert_eq!((fractional[0] * 2.0) as usize, a);
+                        assert_eq!((fractional[1] * 2.0) as usize, b);
+                        assert_eq!((fractional[2] * 2.0) as usize, c);
+                    }
+                    count += particles.len();
+                }
+            }
+        }
+        assert_eq!(count, system.size());
+    }
+
+    #[test]
+    fn orthorhombic() {
+        let system = grid_system(UnitCell::ortho(20.0, 30.0, 9.0), 6);
+        check_pairs(&system, 4.0);
+        check_pairs(&system, 7.0);
+    }
+
+    #[test]
+    fn triclinic() {
+        let system = grid_system(UnitCell::triclinic(20.0, 22.0, 25.0, 70.0, 110.0, 80.0), 6);
+        check_pairs(&system, 4.0);
+        check_pairs(&system, 6.5);
+    }
+
+    #[test]
+    fn infinite() {
+        let system = grid_system(UnitCell::infinite(), 6);
+        check_pairs(&system, 2.0);
+        check_pairs(&system, 3.0);
+
+        let cells = CellList::new(&system, 2.0);
+        assert_eq!(cells.bins(), [4, 4, 4]);
+    }
+
+    #[test]
+    fn partially_periodic() {
+        let mut cell = UnitCell::ortho(20.0, 30.0, 9.0);
+        cell.set_periodicity([true, true, false]);
+        let mut system = grid_system(cell, 6);
+        // Spread the particles along the non-periodic direction
+        for position in system.particles_mut().position {
+            position[2] *= 3.0;
+        }
+        check_pairs(&system, 4.0);
+        check_pairs(&system, 7.0);
+
+        let cells = CellList::new(&system, 4.0);
+        assert_eq!(cells.bins(), [5, 7, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the cutoff of a cell list must be positive")]
+    fn negative_cutoff() {
+        let system = grid_system(UnitCell::cubic(10.0), 2);
+        let _ = CellList::new(&system, -1.0);
+    }
+}
@@ -4,7 +4,7 @@
 //! [Chemfiles](https://chemfiles.org/) conversion for Lumol.
 
 use chemfiles;
-use sys::{CellShape, Molecule, Particle, ParticleRef, System, UnitCell};
+use sys::{CellShape, Molecule, Particle, ParticleRef, Property, System, UnitCell};
 use types::Vector3D;
 
 use std::error;
@@ -45,8 +45,8 @@ pub trait ToLumol {
 impl ToLumol for chemfiles::Atom {
     type Output = Particle;
     fn to_lumol(self) -> Result<Self::Output, Error> {
-        let name = self.atomic_type()?;
-        let mut particle = Particle::new(name);
+        let mut particle = Particle::new(self.name()?);
+        particle.atom_type = self.atomic_type()?;
         particle.mass = self.mass()?;
         Ok(particle)
     }
@@ -85,10 +85,29 @@ impl ToLumol for chemfiles::Frame {
         let natoms = self.size()? as usize;
 
         let positions = self.positions()?;
+        let velocities = if self.has_velocities()? {
+            Some(self.velocities()?)
+        } else {
+            None
+        };
+
         for i in 0..natoms {
             let atom = topology.atom(i as u64)?;
             let mut particle = atom.to_lumol()?;
             particle.position = Vector3D::new(positions[i][0], positions[i][1], positions[i][2]);
+            if let Some(velocities) = velocities {
+                particle.velocity = Vector3D::new(
+                    velocities[i][0], velocities[i][1], velocities[i][2]
+                );
+            }
+
+            if let Some(residue) = topology.residue_for_atom(i as u64)? {
+                particle.residue = Some(residue.name()?);
+                particle.residue_id = Some(residue.id()?);
+                if let Some(chemfiles::Property::String(chain)) = residue.get("chainid")? {
+                    particle.chain = Some(chain);
+                }
+            }
 
             system.add_molecule(Molecule::new(particle));
         }
@@ -138,6 +157,7 @@ impl<'a> ToChemfiles for ParticleRef<'a> {
     type Output = chemfiles::Atom;
     fn to_chemfiles(&self) -> Result<Self::Output, Error> {
         let mut atom = chemfiles::Atom::new(&**self.name)?;
+        atom.set_atomic_type(&**self.atom_type)?;
         atom.set_mass(*self.mass)?;
         return Ok(atom);
     }
@@ -191,11 +211,50 @@ impl ToChemfiles for System {
         }
 
         let mut topology = chemfiles::Topology::new()?;
-        for particle in self.particles().iter() {
-            let atom = particle.to_chemfiles()?;
+        for (i, particle) in self.particles().iter().enumerate() {
+            let mut atom = particle.to_chemfiles()?;
+            // Custom properties are written as atomic properties
+            for (name, property) in self.properties() {
+                let value = match *property {
+                    Property::Scalar(ref values) => chemfiles::Property::Double(values[i]),
+                    Property::Vector(ref values) => {
+                        let value = values[i];
+                        chemfiles::Property::Vector3D([value[0], value[1], value[2]])
+                    }
+                };
+                atom.set(&**name, value)?;
+            }
             topology.add_atom(&atom)?;
         }
 
+        // Group consecutive particles with the same residue information in
+        // chemfiles residues
+        let particles = self.particles();
+        let mut start = 0;
+        while start < self.size() {
+            let mut end = start + 1;
+            while end < self.size() && particles.residue[end] == particles.residue[start]
+                && particles.residue_id[end] == particles.residue_id[start]
+                && particles.chain[end] == particles.chain[start] {
+                end += 1;
+            }
+
+            if let Some(ref name) = particles.residue[start] {
+                let mut residue = match particles.residue_id[start] {
+                    Some(id) => chemfiles::Residue::with_id(&**name, id)?,
+                    None => chemfiles::Residue::new(&**name)?,
+                };
+                for i in start..end {
+                    residue.add_atom(i as u64)?;
+                }
+                if let Some(ref chain) = particles.chain[start] {
+                    residue.set("chainid", chemfiles::Property::String(chain.clone()))?;
+                }
+                topology.add_residue(&residue)?;
+            }
+            start = end;
+        }
+
         for molecule in self.molecules() {
             for bond in molecule.bonds() {
                 topology.add_bond(bond.i() as u64, bond.j() as u64)?;
@@ -475,7 +534,7 @@ mod tests {
 
     use super::*;
     use std::io::prelude::*;
-    use sys::{Angle, Bond, MoleculeHash};
+    use sys::{Angle, Bond};
 
     static WATER: &'static str = "3
 
@@ -535,9 +594,11 @@ END
         assert_eq!(molecule.particles().name[1], "H");
         assert_eq!(molecule.particles().name[2], "H");
 
-        // This is only a simple regression test on the moltype function. Feel
-        // free to change the value if the molecule type algorithm change.
-        assert_eq!(molecule.hash(), MoleculeHash::new(3988311241583852942));
+        // The molecule type does not depend on the order of the particles
+        let mut water = Molecule::new(Particle::new("H"));
+        water.add_particle_bonded_to(0, Particle::new("O"));
+        water.add_particle_bonded_to(1, Particle::new("H"));
+        assert_eq!(molecule.hash(), water.hash());
     }
 
     #[test]
@@ -559,11 +620,23 @@ END
         assert!(molecule.bonds().contains(&Bond::new(0, 2)));
 
         assert_eq!(system.particles().name[0], "O");
-        assert_eq!(system.particles().name[1], "H");
-        assert_eq!(system.particles().name[2], "H");
-        assert_eq!(system.particles().name[3], "O");
+        assert_eq!(system.particles().name[1], "Hw");
+        assert_eq!(system.particles().name[2], "Hw");
+        assert_eq!(system.particles().name[3], "Ow");
         assert_eq!(system.particles().name[4], "H");
         assert_eq!(system.particles().name[5], "H");
+
+        // The particles types are the elements
+        assert_eq!(system.particles().atom_type[0], "O");
+        assert_eq!(system.particles().atom_type[1], "H");
+        assert_eq!(system.particles().atom_type[2], "H");
+        assert_eq!(system.particles().atom_type[3], "O");
+        assert_eq!(system.particles().atom_type[4], "H");
+        assert_eq!(system.particles().atom_type[5], "H");
+
+        assert_eq!(system.particles().residue[0], Some(String::from("RES")));
+        assert_eq!(system.particles().residue_id[4], Some(5));
+        assert_eq!(system.particles().chain[2], Some(String::from("X")));
     }
 
     #[test]
@@ -578,8 +651,72 @@ END
         assert_eq!(molecule.angles().len(), 18);
         assert_eq!(molecule.dihedrals().len(), 18);
 
-        // This is only a simple regression test on the moltype function. Feel
-        // free to change the value if the molecule type algorithm change.
-        assert_eq!(molecule.hash(), MoleculeHash::new(10634064187773497961));
+        // The molecule type does not depend on the order of the particles
+        let mut propane = Molecule::new(Particle::new("C"));
+        propane.add_particle_bonded_to(0, Particle::new("C"));
+        propane.add_particle_bonded_to(1, Particle::new("C"));
+        for &(carbon, hydrogens) in &[(0, 3), (1, 2), (2, 3)] {
+            for _ in 0..hydrogens {
+                propane.add_particle_bonded_to(carbon, Particle::new("H"));
+            }
+        }
+        assert_eq!(molecule.hash(), propane.hash());
+
+        // But it depends on the bonds between the particles
+        let mut other = propane.clone();
+        other.add_bond(0, 2);
+        assert_ne!(molecule.hash(), other.hash());
+    }
+
+    #[test]
+    fn atom_types() {
+        let mut frame = chemfiles::Frame::new().unwrap();
+        for &(atom_type, x) in &[("HW", 0.0), ("HC", 3.0)] {
+            let mut atom = chemfiles::Atom::new("H").unwrap();
+            atom.set_atomic_type(atom_type).unwrap();
+            frame.add_atom(&atom, [x, 0.0, 0.0], None).unwrap();
+        }
+
+        let system = frame.to_lumol().unwrap();
+        assert_eq!(system.particles().name[0], "H");
+        assert_eq!(system.particles().name[1], "H");
+        assert_eq!(system.particles().atom_type[0], "HW");
+        assert_eq!(system.particles().atom_type[1], "HC");
+        assert_ne!(system.particles().kind[0], system.particles().kind[1]);
+
+        let frame = system.to_chemfiles().unwrap();
+        let topology = frame.topology().unwrap();
+        for (i, &atom_type) in ["HW", "HC"].iter().enumerate() {
+            let atom = topology.atom(i as u64).unwrap();
+            assert_eq!(atom.name().unwrap(), "H");
+            assert_eq!(atom.atomic_type().unwrap(), atom_type);
+        }
+    }
+
+    #[test]
+    fn velocities() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let mut water = Molecule::new(Particle::with_position("H", Vector3D::new(1.0, 0.0, 0.0)));
+        water.add_particle_bonded_to(0, Particle::with_position("O", Vector3D::zero()));
+        water.add_particle_bonded_to(1, Particle::with_position("H", Vector3D::new(0.0, 1.0, 0.0)));
+        system.add_molecule(water);
+        system.particles_mut().velocity[0] = Vector3D::new(1.0, 2.0, 3.0);
+        system.particles_mut().velocity[1] = Vector3D::new(-1.0, 0.5, 0.0);
+        system.particles_mut().velocity[2] = Vector3D::new(0.0, 0.0, -4.0);
+
+        let frame = system.to_chemfiles().unwrap();
+        let read = frame.to_lumol().unwrap();
+        assert_eq!(read.size(), 3);
+        // The particles can be re-ordered when adding bonds
+        for (position, velocity) in soa_zip!(read.particles(), [position, velocity]) {
+            let i = system.particles().position.iter().position(|r| r == position).unwrap();
+            assert_eq!(*velocity, system.particles().velocity[i]);
+        }
+
+        // Frames without velocities give zero velocities
+        let mut frame = chemfiles::Frame::new().unwrap();
+        frame.resize(2).unwrap();
+        let read = frame.to_lumol().unwrap();
+        assert_eq!(read.particles().velocity[1], Vector3D::zero());
     }
 }
@@ -12,6 +12,8 @@ use types::{Matrix3, Vector3D};
 
 use sys::{System, DegreesOfFreedom};
 
+use profiler;
+
 use utils::ThreadLocalVec;
 
 /// The `Compute` trait allow to compute properties of a system, without
@@ -30,79 +32,185 @@ pub struct Forces;
 impl Compute for Forces {
     type Output = Vec<Vector3D>;
     fn compute(&self, system: &System) -> Vec<Vector3D> {
-        let natoms = system.size();
-        let thread_local_forces = ThreadLocalVec::with_size(natoms);
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        add_pairs_forces(system, &mut forces);
+        add_bonded_forces(system, &mut forces);
+        add_long_range_forces(system, &mut forces);
+        add_modifiers_forces(system, &mut forces);
+        spread_virtual_sites_forces(system, &mut forces);
+        return forces;
+    }
+}
 
-        (0..natoms).into_par_iter().for_each(|i| {
-            let mut forces = thread_local_forces.borrow_mut();
-            let mut force_i = Vector3D::zero();
-            for j in (i + 1)..system.size() {
-                let path = system.bond_path(i, j);
-                let d = system.nearest_image(i, j);
-                let dn = d.normalized();
-                let r = d.norm();
-                for potential in system.pair_potentials(i, j) {
-                    let info = potential.restriction().information(path);
-                    if !info.excluded {
-                        let force = info.scaling * potential.force(r) * dn;
-                        force_i += force;
-                        forces[j] -= force;
-                    }
-                }
-            }
-            forces[i] += force_i;
-        });
+/// Compute the forces coming from the bonded interactions (bonds, angles,
+/// dihedral angles, ...) only. These forces usually vary quickly, and are
+/// computed more often than the other in multiple time step integrators.
+pub struct BondedForces;
+impl Compute for BondedForces {
+    type Output = Vec<Vector3D>;
+    fn compute(&self, system: &System) -> Vec<Vector3D> {
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        add_bonded_forces(system, &mut forces);
+        spread_virtual_sites_forces(system, &mut forces);
+        return forces;
+    }
+}
 
-        // At this point all the forces are computed, but the results are
-        // scattered across all thread local Vecs, here we gather them.
-        let mut forces = vec![Vector3D::zero(); natoms];
-        thread_local_forces.sum_into(&mut forces);
+/// Compute the forces coming from the non-bonded interactions only: pair,
+/// electrostatic and global interactions, together with the forces from the
+/// force modifiers. The sum of these forces and the
+/// [`BondedForces`](struct.BondedForces.html) is equal to the
+/// [`Forces`](struct.Forces.html).
+pub struct NonBondedForces;
+impl Compute for NonBondedForces {
+    type Output = Vec<Vector3D>;
+    fn compute(&self, system: &System) -> Vec<Vector3D> {
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        add_pairs_forces(system, &mut forces);
+        add_long_range_forces(system, &mut forces);
+        add_modifiers_forces(system, &mut forces);
+        spread_virtual_sites_forces(system, &mut forces);
+        return forces;
+    }
+}
 
-        for molecule in system.molecules() {
-            for bond in molecule.bonds() {
-                let (i, j) = (bond.i(), bond.j());
-                let d = system.nearest_image(i, j);
-                let dn = d.normalized();
-                let r = d.norm();
-                for potential in system.bond_potentials(i, j) {
-                    let force = potential.force(r) * dn;
-                    forces[i] += force;
+/// Add the forces from pair interactions to `forces`
+fn add_pairs_forces(system: &System, forces: &mut [Vector3D]) {
+    let _timer = profiler::start("pair forces");
+    let natoms = system.size();
+    let thread_local_forces = ThreadLocalVec::with_size(natoms);
+
+    let neighbors = system.neighbor_list();
+    (0..natoms).into_par_iter().for_each(|i| {
+        let mut forces = thread_local_forces.borrow_mut();
+        let mut force_i = Vector3D::zero();
+        for &j in neighbors.neighbors(i) {
+            let path = system.bond_path(i, j);
+            let d = system.nearest_image(i, j);
+            let dn = d.normalized();
+            let r = d.norm();
+            for potential in system.pair_potentials(i, j) {
+                let info = potential.restriction().information(path);
+                if !info.excluded {
+                    let force = info.scaling * potential.force(r) * dn;
+                    force_i += force;
                     forces[j] -= force;
                 }
             }
+        }
+        forces[i] += force_i;
+    });
 
-            for angle in molecule.angles() {
-                let (i, j, k) = (angle.i(), angle.j(), angle.k());
-                let (theta, d1, d2, d3) = system.angle_and_derivatives(i, j, k);
-                for potential in system.angle_potentials(i, j, k) {
-                    let force = potential.force(theta);
-                    forces[i] += force * d1;
-                    forces[j] += force * d2;
-                    forces[k] += force * d3;
-                }
+    // At this point all the forces are computed, but the results are
+    // scattered across all thread local Vecs, here we gather them.
+    thread_local_forces.sum_into(forces);
+}
+
+/// Add the forces from bonded interactions to `forces`
+fn add_bonded_forces(system: &System, forces: &mut [Vector3D]) {
+    let _timer = profiler::start("bonded forces");
+    for molecule in system.molecules() {
+        for bond in molecule.bonds() {
+            let (i, j) = (bond.i(), bond.j());
+            let d = system.nearest_image(i, j);
+            let dn = d.normalized();
+            let r = d.norm();
+            for potential in system.bond_potentials(i, j) {
+                let force = potential.force(r) * dn;
+                forces[i] += force;
+                forces[j] -= force;
+            }
+        }
+
+        for angle in molecule.angles() {
+            let (i, j, k) = (angle.i(), angle.j(), angle.k());
+            let (theta, d1, d2, d3) = system.angle_and_derivatives(i, j, k);
+            for potential in system.angle_potentials(i, j, k) {
+                let force = potential.force(theta);
+                forces[i] += force * d1;
+                forces[j] += force * d2;
+                forces[k] += force * d3;
             }
 
-            for dihedral in molecule.dihedrals() {
-                let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            if let Some(potential) = system.urey_bradley_potential(i, j, k) {
+                let d = system.nearest_image(i, k);
+                let force = potential.force(d.norm()) * d.normalized();
+                forces[i] += force;
+                forces[k] -= force;
+            }
+        }
+
+        for dihedral in molecule.dihedrals() {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let (phi, d1, d2, d3, d4) = system.dihedral_and_derivatives(i, j, k, m);
+            for potential in system.dihedral_potentials(i, j, k, m) {
+                let force = potential.force(phi);
+                forces[i] += force * d1;
+                forces[j] += force * d2;
+                forces[k] += force * d3;
+                forces[m] += force * d4;
+            }
+        }
+
+        for pair in molecule.dihedral_pairs() {
+            let (i, j, k, m, n) = (pair.i(), pair.j(), pair.k(), pair.m(), pair.n());
+            if let Some(cmap) = system.cmap_potential(i, j, k, m, n) {
                 let (phi, d1, d2, d3, d4) = system.dihedral_and_derivatives(i, j, k, m);
-                for potential in system.dihedral_potentials(i, j, k, m) {
-                    let force = potential.force(phi);
-                    forces[i] += force * d1;
-                    forces[j] += force * d2;
-                    forces[k] += force * d3;
-                    forces[m] += force * d4;
-                }
+                let (psi, e1, e2, e3, e4) = system.dihedral_and_derivatives(j, k, m, n);
+                let (force_phi, force_psi) = cmap.force(phi, psi);
+                forces[i] += force_phi * d1;
+                forces[j] += force_phi * d2 + force_psi * e1;
+                forces[k] += force_phi * d3 + force_psi * e2;
+                forces[m] += force_phi * d4 + force_psi * e3;
+                forces[n] += force_psi * e4;
             }
         }
 
-        if let Some(coulomb) = system.coulomb_potential() {
-            coulomb.forces(system, &mut forces);
+        for improper in molecule.impropers() {
+            let (i, j, k, m) = (improper.i(), improper.j(), improper.k(), improper.m());
+            let (phi, d1, d2, d3, d4) = system.dihedral_and_derivatives(i, j, k, m);
+            for potential in system.improper_potentials(i, j, k, m) {
+                let force = potential.force(phi);
+                forces[i] += force * d1;
+                forces[j] += force * d2;
+                forces[k] += force * d3;
+                forces[m] += force * d4;
+            }
         }
+    }
+}
 
+/// Add the forces from electrostatic and global interactions to `forces`
+fn add_long_range_forces(system: &System, forces: &mut [Vector3D]) {
+    if let Some(coulomb) = system.coulomb_potential() {
+        let _timer = profiler::start("coulomb forces");
+        coulomb.forces(system, forces);
+    }
+
+    if !system.global_potentials().is_empty() {
+        let _timer = profiler::start("global forces");
         for global in system.global_potentials() {
-            global.forces(system, &mut forces);
+            global.forces(system, forces);
         }
-        return forces;
+    }
+}
+
+/// Add the forces from the force modifiers to `forces`
+fn add_modifiers_forces(system: &System, forces: &mut [Vector3D]) {
+    if system.force_modifiers().is_empty() {
+        return;
+    }
+
+    let _timer = profiler::start("force modifiers");
+    for modifier in system.force_modifiers() {
+        modifier.modify(system, forces);
+    }
+}
+
+/// Transfer the forces acting on virtual sites to their parents
+fn spread_virtual_sites_forces(system: &System, forces: &mut [Vector3D]) {
+    for site in system.virtual_sites() {
+        site.spread_force(&system.cell, system.particles().position, forces);
     }
 }
 
@@ -111,16 +219,8 @@ pub struct PotentialEnergy;
 impl Compute for PotentialEnergy {
     type Output = f64;
     fn compute(&self, system: &System) -> f64 {
-        let evaluator = system.energy_evaluator();
-
-        let mut energy = evaluator.pairs();
-        energy += evaluator.pairs_tail();
-        energy += evaluator.bonds();
-        energy += evaluator.angles();
-        energy += evaluator.dihedrals();
-        energy += evaluator.coulomb();
-        energy += evaluator.global();
-
+        let _timer = profiler::start("potential energy");
+        let energy = system.energy_evaluator().breakdown().total();
         assert!(energy.is_finite(), "Potential energy is infinite!");
         return energy;
     }
@@ -170,12 +270,16 @@ impl Compute for Temperature {
     }
 }
 
-/// Compute the volume of the system
+/// Compute the volume of the system, or the area of the unit cell for
+/// two-dimensional systems
 pub struct Volume;
 impl Compute for Volume {
     type Output = f64;
     #[inline]
     fn compute(&self, system: &System) -> f64 {
+        if system.dimensions() == 2 {
+            return system.cell.area();
+        }
         return system.cell.volume();
     }
 }
@@ -198,9 +302,10 @@ impl Compute for AtomicVirial {
         assert!(!system.cell.is_infinite(), "Can not compute virial for infinite cell");
 
         // Pair potentials contributions
+        let neighbors = system.neighbor_list();
         let pair_virials = (0..system.size()).into_par_iter().map(|i| {
             let mut local_virial = Matrix3::zero();
-            for j in (i + 1)..system.size() {
+            for &j in neighbors.neighbors(i) {
                 let path = system.bond_path(i, j);
                 for potential in system.pair_potentials(i, j) {
                     let info = potential.restriction().information(path);
@@ -214,14 +319,17 @@ impl Compute for AtomicVirial {
         });
         let mut virial = pair_virials.sum();
 
-        // Tail correction for pair potentials contribution
-        let volume = system.cell.volume();
-        let composition = system.composition();
-        for (i, ni) in composition.all_particles() {
-            for (j, nj) in composition.all_particles() {
-                let two_pi_density = 2.0 * PI * (ni as f64) * (nj as f64) / volume;
-                for potential in system.interactions().pairs((i, j)) {
-                    virial += two_pi_density * potential.tail_virial();
+        // Tail correction for pair potentials contribution, only defined for
+        // three-dimensional systems
+        if system.dimensions() == 3 {
+            let volume = system.cell.volume();
+            let composition = system.composition();
+            for (i, ni) in composition.all_particles() {
+                for (j, nj) in composition.all_particles() {
+                    let two_pi_density = 2.0 * PI * (ni as f64) * (nj as f64) / volume;
+                    for potential in system.interactions().pairs((i, j)) {
+                        virial += two_pi_density * potential.tail_virial();
+                    }
                 }
             }
         }
@@ -235,10 +343,20 @@ impl Compute for AtomicVirial {
                     virial += potential.virial(&r);
                 }
             }
+
+            // Urey-Bradley terms act like bonds between the first and last
+            // particles of the angles
+            for angle in molecule.angles() {
+                let (i, j, k) = (angle.i(), angle.j(), angle.k());
+                if let Some(potential) = system.urey_bradley_potential(i, j, k) {
+                    let r = system.nearest_image(i, k);
+                    virial += potential.virial(&r);
+                }
+            }
         }
 
-        // Angles and dihedrals potentials do not contribute as they only have
-        // an angular part (see DL_POLY 4 manual page 18, or Smith, W., 1993,
+        // Angles, dihedrals, impropers and CMAP potentials do not contribute as they
+        // only have an angular part (see DL_POLY 4 manual page 18, or Smith, W., 1993,
         // CCP5 Information Quarterly, 39, 14. 18, 21, 24).
 
         if let Some(coulomb) = system.coulomb_potential() {
@@ -282,41 +400,48 @@ impl Compute for MolecularVirial {
 
         // Pair potentials contributions, using the molecular virial definition
         // This is defined in Allen & Tildesley in equations 2.54; 2.61; 2.63.
-        let pair_virials = system.molecules().enumerate().par_bridge().map(|(i, molecule_i)| {
+        let centers_of_mass = system.molecules()
+                                    .map(|molecule| molecule.center_of_mass())
+                                    .collect::<Vec<_>>();
+        let neighbors = system.neighbor_list();
+        let pair_virials = (0..system.size()).into_par_iter().map(|part_a| {
             let mut local_virial = Matrix3::zero();
-            let ri = molecule_i.center_of_mass();
+            let i = system.molecule_id(part_a);
 
-            for molecule_j in system.molecules().skip(i + 1) {
-                let rj = molecule_j.center_of_mass();
-                let mut r_ij = ri - rj;
+            for &part_b in neighbors.neighbors(part_a) {
+                let j = system.molecule_id(part_b);
+                if i == j {
+                    continue;
+                }
+
+                let mut r_ij = centers_of_mass[i] - centers_of_mass[j];
                 system.cell.vector_image(&mut r_ij);
 
-                for part_a in molecule_i.indexes() {
-                    for part_b in molecule_j.indexes() {
-                        let path = system.bond_path(part_a, part_b);
-                        let r_ab = system.nearest_image(part_a, part_b);
-                        for potential in system.pair_potentials(part_a, part_b) {
-                            let info = potential.restriction().information(path);
-                            if !info.excluded {
-                                let w_ab = info.scaling * potential.virial(&r_ab);
-                                local_virial += w_ab * (r_ab * r_ij) / r_ab.norm2();
-                            }
-                        }
-                     }
-                 }
+                let path = system.bond_path(part_a, part_b);
+                let r_ab = system.nearest_image(part_a, part_b);
+                for potential in system.pair_potentials(part_a, part_b) {
+                    let info = potential.restriction().information(path);
+                    if !info.excluded {
+                        let w_ab = info.scaling * potential.virial(&r_ab);
+                        local_virial += w_ab * (r_ab * r_ij) / r_ab.norm2();
+                    }
+                }
             }
             return local_virial;
         });
         let mut virial = pair_virials.sum();
 
-        // Tail correction for pair potentials contribution
-        let volume = system.cell.volume();
-        let composition = system.composition();
-        for (i, ni) in composition.all_particles() {
-            for (j, nj) in composition.all_particles() {
-                let two_pi_density = 2.0 * PI * (ni as f64) * (nj as f64) / volume;
-                for potential in system.interactions().pairs((i, j)) {
-                    virial += two_pi_density * potential.tail_virial();
+        // Tail correction for pair potentials contribution, only defined for
+        // three-dimensional systems
+        if system.dimensions() == 3 {
+            let volume = system.cell.volume();
+            let composition = system.composition();
+            for (i, ni) in composition.all_particles() {
+                for (j, nj) in composition.all_particles() {
+                    let two_pi_density = 2.0 * PI * (ni as f64) * (nj as f64) / volume;
+                    for potential in system.interactions().pairs((i, j)) {
+                        virial += two_pi_density * potential.tail_virial();
+                    }
                 }
             }
         }
@@ -330,8 +455,8 @@ impl Compute for MolecularVirial {
                     let w = potential.virial(&r);
                     if w.norm() > 1e-30 {
                         // Use the same sorting as interactions
-                        let name_i = &system.particles().name[i];
-                        let name_j = &system.particles().name[j];
+                        let name_i = &system.particles().atom_type[i];
+                        let name_j = &system.particles().atom_type[j];
                         let (name_i, name_j) = if name_i < name_j {
                             (name_i, name_j)
                         } else {
@@ -344,10 +469,20 @@ impl Compute for MolecularVirial {
                     }
                 }
             }
+
+            for angle in molecule.angles() {
+                let (i, j, k) = (angle.i(), angle.j(), angle.k());
+                if let Some(potential) = system.urey_bradley_potential(i, j, k) {
+                    let r = system.nearest_image(i, k);
+                    if potential.virial(&r).norm() > 1e-30 {
+                        warn_once!("Ignoring non null Urey-Bradley potential in molecular virial")
+                    }
+                }
+            }
         }
 
-        // Angles and dihedrals potentials do not contribute as they only have
-        // an angular part (see DL_POLY 4 manual page 18, or Smith, W., 1993,
+        // Angles, dihedrals, impropers and CMAP potentials do not contribute as they
+        // only have an angular part (see DL_POLY 4 manual page 18, or Smith, W., 1993,
         // CCP5 Information Quarterly, 39, 14. 18, 21, 24).
 
         if let Some(coulomb) = system.coulomb_potential() {
@@ -372,6 +507,7 @@ pub struct Virial;
 impl Compute for Virial {
     type Output = Matrix3;
     fn compute(&self, system: &System) -> Matrix3 {
+        let _timer = profiler::start("virial");
         match system.simulated_degrees_of_freedom {
             DegreesOfFreedom::Molecules => MolecularVirial.compute(system),
             DegreesOfFreedom::Particles | DegreesOfFreedom::Frozen(_) => AtomicVirial.compute(system),
@@ -382,11 +518,12 @@ impl Compute for Virial {
 /// Compute the pressure of the system using the virial definition, at a given
 /// temperature.
 ///
-/// $$ p = \frac{N_f k_B T}{3 V} + \frac{Tr(\underline{W})}{3V} $$
+/// $$ p = \frac{N_f k_B T}{d V} + \frac{Tr(\underline{W})}{d V} $$
 ///
 /// where $N_f$ is the number of degrees of freedom in the system, $k_B$ is the
-/// Boltzman constant, $T$ the temperature, $V$ the simulation volume, $Tr$ is
-/// the matricial trace, and $\underline{W}$ the [`Virial`].
+/// Boltzman constant, $T$ the temperature, $V$ the simulation volume, $d$ the
+/// number of dimensions of the system, $Tr$ is the matricial trace, and
+/// $\underline{W}$ the [`Virial`].
 ///
 /// [`Virial`]: struct.Virial.html
 pub struct PressureAtTemperature {
@@ -402,7 +539,8 @@ impl Compute for PressureAtTemperature {
         let virial = system.virial().trace();
         let volume = system.volume();
         let dof = system.degrees_of_freedom() as f64;
-        return (dof * K_BOLTZMANN * self.temperature + virial) / (3.0 * volume);
+        let dimensions = system.dimensions() as f64;
+        return (dof * K_BOLTZMANN * self.temperature + virial) / (dimensions * volume);
     }
 }
 
@@ -429,12 +567,13 @@ impl Compute for Pressure {
 /// Compute the stress tensor of the system from the virial definition, at the
 /// given temperature.
 ///
-/// $$ \underline{\sigma} = \frac{1}{V} \left(\frac{N_f}{3} k_B T \space
+/// $$ \underline{\sigma} = \frac{1}{V} \left(\frac{N_f}{d} k_B T \space
 ///    \underline{1} + \underline{W} \right) $$
 ///
 /// where $N_f$ is the number of degrees of freedom in the system, $k_B$ is the
-/// Boltzman constant, $T$ the temperature, $V$ the simulation volume, $Tr$ is
-/// the matricial trace, and $\underline{W}$ the [`Virial`].
+/// Boltzman constant, $T$ the temperature, $V$ the simulation volume, $d$ the
+/// number of dimensions of the system, $Tr$ is the matricial trace, and
+/// $\underline{W}$ the [`Virial`].
 pub struct StressAtTemperature {
     /// Temperature for the stress tensor computation
     pub temperature: f64,
@@ -448,7 +587,11 @@ impl Compute for StressAtTemperature {
         let virial = system.virial();
         let volume = system.volume();
         let dof = system.degrees_of_freedom() as f64;
-        let kinetic = dof / 3.0 * K_BOLTZMANN * self.temperature * Matrix3::one();
+        let dimensions = system.dimensions() as f64;
+        let mut kinetic = dof / dimensions * K_BOLTZMANN * self.temperature * Matrix3::one();
+        if system.dimensions() == 2 {
+            kinetic[2][2] = 0.0;
+        }
         return (kinetic + virial) / volume;
     }
 }
@@ -482,8 +625,8 @@ impl Compute for Stress {
 mod test {
     use super::*;
     use consts::K_BOLTZMANN;
-    use energy::{Harmonic, NullPotential, PairInteraction};
-    use sys::System;
+    use energy::{Cmap, Harmonic, NullPotential, PairInteraction};
+    use sys::{ForceModifier, System};
     use utils::system_from_xyz;
     use units;
 
@@ -587,6 +730,230 @@ mod test {
         assert_ulps_eq!(forces_tot.norm2(), 0.0);
     }
 
+    #[test]
+    fn forces_splitting() {
+        let mut system = test_molecular_system();
+        system.add_pair_potential(
+            ("F", "F"),
+            PairInteraction::new(
+                Box::new(Harmonic {
+                    k: units::from(30.0, "kJ/mol/A^2").unwrap(),
+                    x0: units::from(2.5, "A").unwrap(),
+                }),
+                5.0,
+            ),
+        );
+
+        let forces = Forces.compute(&system);
+        let bonded = BondedForces.compute(&system);
+        let non_bonded = NonBondedForces.compute(&system);
+        assert!(bonded.iter().any(|force| force.norm() > 1e-6));
+        assert!(non_bonded.iter().any(|force| force.norm() > 1e-6));
+
+        for i in 0..system.size() {
+            assert_relative_eq!(forces[i], bonded[i] + non_bonded[i], epsilon = 1e-12);
+        }
+    }
+
+    #[derive(Clone)]
+    struct PushFirst;
+    impl ForceModifier for PushFirst {
+        fn modify(&self, _: &System, forces: &mut [Vector3D]) {
+            forces[0] += Vector3D::new(0.0, 0.0, 2.0);
+        }
+    }
+
+    #[test]
+    fn forces_modifiers() {
+        let mut system = test_molecular_system();
+        let forces = Forces.compute(&system);
+        let non_bonded = NonBondedForces.compute(&system);
+        let bonded = BondedForces.compute(&system);
+        let virial = system.virial();
+
+        system.add_force_modifier(Box::new(PushFirst));
+        let modified = Forces.compute(&system);
+        assert_ulps_eq!(modified[0], forces[0] + Vector3D::new(0.0, 0.0, 2.0));
+        assert_ulps_eq!(modified[1], forces[1]);
+
+        let modified = NonBondedForces.compute(&system);
+        assert_ulps_eq!(modified[0], non_bonded[0] + Vector3D::new(0.0, 0.0, 2.0));
+        assert_eq!(BondedForces.compute(&system), bonded);
+
+        // The modifiers do not contribute to the virial
+        assert_eq!(system.virial(), virial);
+    }
+
+    #[test]
+    fn force_urey_bradley() {
+        let mut system = system_from_xyz(
+            "3
+            cell: 10.0
+            F 0.0 0.0 0.0
+            F 1.0 0.0 0.0
+            F 1.0 1.0 0.0
+            ",
+        );
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(1, 2).is_empty());
+        system.add_pair_potential(("F", "F"), PairInteraction::new(Box::new(NullPotential), 0.0));
+        system.add_bond_potential(("F", "F"), Box::new(NullPotential));
+        system.set_urey_bradley_potential(
+            ("F", "F", "F"),
+            Box::new(Harmonic {
+                k: units::from(100.0, "kJ/mol/A^2").unwrap(),
+                x0: units::from(1.0, "A").unwrap(),
+            }),
+        );
+
+        let res = Forces.compute(&system);
+        assert_eq!(res[1], Vector3D::zero());
+        assert_ulps_eq!((res[0] + res[2]).norm2(), 0.0);
+
+        // The force pulls the first and last particles together
+        let norm = units::from(100.0 * (f64::sqrt(2.0) - 1.0), "kJ/mol/A").unwrap();
+        let expected = norm * Vector3D::new(1.0, 1.0, 0.0).normalized();
+        assert_relative_eq!(res[0], expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn force_cosine_angles() {
+        use energy::{CosineAngle, CosineSquaredAngle};
+
+        let mut system = system_from_xyz(
+            "3
+            cell: 10.0
+            F 0.0 0.0 0.0
+            F 1.0 0.0 0.0
+            F 1.5 1.0 0.0
+            ",
+        );
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(1, 2).is_empty());
+        system.add_pair_potential(("F", "F"), PairInteraction::new(Box::new(NullPotential), 0.0));
+        system.add_bond_potential(("F", "F"), Box::new(NullPotential));
+        system.add_angle_potential(("F", "F", "F"), Box::new(CosineAngle { k: 3.0 }));
+        system.add_angle_potential(("F", "F", "F"), Box::new(CosineSquaredAngle::new(5.0, 2.0)));
+
+        let forces = Forces.compute(&system);
+        assert_ulps_eq!((forces[0] + forces[1] + forces[2]).norm2(), 0.0);
+
+        // Compare with finite differences of the energy
+        let eps = 1e-9;
+        let e0 = PotentialEnergy.compute(&system);
+        for i in 0..3 {
+            for alpha in 0..3 {
+                system.particles_mut().position[i][alpha] += eps;
+                let e1 = PotentialEnergy.compute(&system);
+                system.particles_mut().position[i][alpha] -= eps;
+                assert_relative_eq!((e0 - e1) / eps, forces[i][alpha], epsilon = 1e-5);
+            }
+        }
+
+        // The angles potentials are invariant by dilatation, so they do not
+        // contribute to the virial
+        let mut virial = Matrix3::zero();
+        for i in 0..3 {
+            virial += forces[i].tensorial(&system.particles().position[i]);
+        }
+        assert_relative_eq!(virial.trace(), 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn force_impropers() {
+        let mut system = system_from_xyz(
+            "4
+            cell: 10.0
+            F 0.0 0.0 0.0
+            F 1.0 0.0 0.0
+            F -0.5 0.8 0.0
+            F -0.5 -0.8 0.3
+            ",
+        );
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(0, 2).is_empty());
+        assert!(system.add_bond(0, 3).is_empty());
+        system.guess_impropers();
+        system.add_pair_potential(("F", "F"), PairInteraction::new(Box::new(NullPotential), 0.0));
+        system.add_bond_potential(("F", "F"), Box::new(NullPotential));
+        system.add_angle_potential(("F", "F", "F"), Box::new(NullPotential));
+        system.add_improper_potential(
+            ("F", "F", "F", "F"),
+            Box::new(Harmonic {
+                k: units::from(100.0, "kJ/mol/rad^2").unwrap(),
+                x0: 0.0,
+            }),
+        );
+
+        let forces = Forces.compute(&system);
+        assert_ulps_eq!((forces[0] + forces[1] + forces[2] + forces[3]).norm2(), 0.0);
+        assert!(forces[3].norm() > 0.0);
+
+        // Compare with finite differences of the energy
+        let eps = 1e-9;
+        let e0 = PotentialEnergy.compute(&system);
+        for i in 0..4 {
+            for alpha in 0..3 {
+                system.particles_mut().position[i][alpha] += eps;
+                let e1 = PotentialEnergy.compute(&system);
+                system.particles_mut().position[i][alpha] -= eps;
+                assert_relative_eq!((e0 - e1) / eps, forces[i][alpha], epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn force_cmaps() {
+        let mut system = system_from_xyz(
+            "5
+            cell: 10.0
+            F 0.0 0.0 0.0
+            F 1.0 0.0 0.0
+            F 1.3 1.0 0.2
+            F 2.2 1.4 -0.3
+            F 2.5 2.1 0.6
+            ",
+        );
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(1, 2).is_empty());
+        assert!(system.add_bond(2, 3).is_empty());
+        assert!(system.add_bond(3, 4).is_empty());
+        system.add_pair_potential(("F", "F"), PairInteraction::new(Box::new(NullPotential), 0.0));
+        system.add_bond_potential(("F", "F"), Box::new(NullPotential));
+        system.add_angle_potential(("F", "F", "F"), Box::new(NullPotential));
+        system.add_dihedral_potential(("F", "F", "F", "F"), Box::new(NullPotential));
+
+        let size = 24;
+        let delta = 2.0 * PI / size as f64;
+        let mut values = Vec::new();
+        for i in 0..size {
+            let phi = -PI + i as f64 * delta;
+            for j in 0..size {
+                let psi = -PI + j as f64 * delta;
+                let energy = f64::cos(phi) + 0.5 * f64::sin(2.0 * psi) + 0.3 * f64::cos(phi - psi);
+                values.push(units::from(energy, "kJ/mol").unwrap());
+            }
+        }
+        system.set_cmap_potential(("F", "F", "F", "F", "F"), Cmap::new(size, values));
+
+        let forces = Forces.compute(&system);
+        let total = forces[0] + forces[1] + forces[2] + forces[3] + forces[4];
+        assert_ulps_eq!(total.norm2(), 0.0);
+        assert!(forces[4].norm() > 0.0);
+
+        // Compare with finite differences of the energy
+        let eps = 1e-9;
+        let e0 = PotentialEnergy.compute(&system);
+        for i in 0..5 {
+            for alpha in 0..3 {
+                system.particles_mut().position[i][alpha] += eps;
+                let e1 = PotentialEnergy.compute(&system);
+                system.particles_mut().position[i][alpha] -= eps;
+                assert_relative_eq!((e0 - e1) / eps, forces[i][alpha], epsilon = 1e-5);
+            }
+        }
+    }
+
     #[test]
     fn energy_pairs() {
         let system = &test_pairs_system();
@@ -780,4 +1147,25 @@ mod test {
         assert_ulps_eq!(pressure, expected);
         assert_eq!(pressure, system.pressure());
     }
+
+    #[test]
+    fn pressure_two_dimensions() {
+        let system = &mut test_pairs_system();
+        system.set_dimensions(2);
+        let temperature = 550.0;
+        system.simulated_temperature(Some(temperature));
+
+        let force = units::from(30.0, "kJ/mol/A").unwrap();
+        let virial = -force * 1.3;
+        let dof = 4.0;
+        let area = 100.0;
+        assert_eq!(system.volume(), area);
+
+        let expected = (dof * K_BOLTZMANN * temperature + virial) / (2.0 * area);
+        assert_ulps_eq!(system.pressure(), expected);
+
+        let stress = system.stress();
+        assert_eq!(stress[2][2], 0.0);
+        assert_ulps_eq!((stress[0][0] + stress[1][1]) / 2.0, expected);
+    }
 }
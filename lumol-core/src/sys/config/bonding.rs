@@ -2,16 +2,19 @@
 // Copyright (C) Lumol's contributors — BSD license
 
 use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::ops::Range;
 
-use sys::{Angle, Bond, BondDistances, Dihedral};
+use energy::BondPath;
+use sys::{Angle, Bond, BondDistances, Dihedral, DihedralPair, Improper};
+use sys::Constraint;
 use types::Array2;
 
 
 /// The basic building block for a topology. A `Bonding` contains data about
-/// the connectivity (bonds, angles, dihedrals) between particles in a single
-/// molecule.
+/// the connectivity (bonds, angles, dihedrals, impropers) between particles in
+/// a single molecule.
 #[derive(Debug, Clone)]
 pub struct Bonding {
     /// All the bonds in the molecule.
@@ -21,10 +24,25 @@ pub struct Bonding {
     /// All the dihedral angles in the molecule. Rebuilt as needed from the
     /// bond list.
     dihedrals: HashSet<Dihedral>,
+    /// All the pairs of consecutive dihedral angles in the molecule. Rebuilt
+    /// as needed from the bond list.
+    dihedral_pairs: HashSet<DihedralPair>,
+    /// All the improper dihedral angles in the molecule. These are not
+    /// rebuilt from the bonds, and must be added explicitly.
+    impropers: HashSet<Improper>,
+    /// All the holonomic constraints in the molecule. These are not rebuilt
+    /// from the bonds, and must be added explicitly.
+    constraints: Vec<Constraint>,
     /// Matrix of bond distances in the molecule. The item at index `i, j`
     /// encode the bond distance between the particles `i + self.first` and
     /// `j + self.first`
     distances: Array2<BondDistances>,
+    /// Shortest bond path between the particles in the molecule, computed
+    /// from `distances`. The item at index `i * self.size() + j` is the
+    /// shortest path between the particles `i + self.first` and
+    /// `j + self.first`. This is used to speed up the restrictions lookups
+    /// in pair interactions.
+    paths: Vec<BondPath>,
     /// Range of atomic indexes in this molecule.
     range: Range<usize>,
 }
@@ -36,7 +54,11 @@ impl Bonding {
             bonds: HashSet::new(),
             angles: HashSet::new(),
             dihedrals: HashSet::new(),
+            dihedral_pairs: HashSet::new(),
+            impropers: HashSet::new(),
+            constraints: Vec::new(),
             distances: Array2::default((1, 1)),
+            paths: vec![BondPath::SameParticle],
             range: i..i + 1,
         }
     }
@@ -61,23 +83,59 @@ impl Bonding {
         self.range.start <= i && i < self.range.end
     }
 
-    /// Hash the bonds in this molecule
-    pub(crate) fn hash<H: Hasher + Sized>(&self, hasher: &mut H) {
-        let mut bonds = self.bonds.iter()
-            .map(|bond| Bond::new(bond.i() - self.start(), bond.j() - self.start()))
-            .collect::<Vec<_>>();
+    /// Hash the molecular graph of this molecule, using the `atom_types` of
+    /// the particles as labels for the vertices.
+    ///
+    /// The hash does not depend on the order of the particles: the labels are
+    /// iteratively refined with the sorted labels of the bonded neighbors
+    /// (Weisfeiler-Lehman refinement) until the partition of the particles
+    /// is stable, and the sorted list of final labels is hashed.
+    pub(crate) fn hash<H: Hasher + Sized>(&self, atom_types: &[String], hasher: &mut H) {
+        debug_assert_eq!(atom_types.len(), self.size());
+        let mut neighbors = vec![Vec::new(); self.size()];
+        for bond in &self.bonds {
+            let (i, j) = (bond.i() - self.start(), bond.j() - self.start());
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
 
-        bonds.sort_unstable();
-        for bond in &bonds {
-            bond.i().hash(hasher);
-            bond.j().hash(hasher);
+        let mut labels = atom_types.iter().map(|atom_type| {
+            let mut hasher = DefaultHasher::new();
+            atom_type.hash(&mut hasher);
+            hasher.finish()
+        }).collect::<Vec<_>>();
+
+        let mut classes = count_distinct(&labels);
+        loop {
+            let refined = labels.iter().zip(&neighbors).map(|(label, neighbors)| {
+                let mut around = neighbors.iter().map(|&j| labels[j]).collect::<Vec<_>>();
+                around.sort_unstable();
+                let mut hasher = DefaultHasher::new();
+                label.hash(&mut hasher);
+                around.hash(&mut hasher);
+                hasher.finish()
+            }).collect::<Vec<_>>();
+            labels = refined;
+
+            // The refined partition is always finer than the previous one, so
+            // it is stable when the number of classes stays the same.
+            let refined_classes = count_distinct(&labels);
+            if refined_classes == classes {
+                break;
+            }
+            classes = refined_classes;
         }
+
+        labels.sort_unstable();
+        labels.hash(hasher);
     }
 
-    /// Rebuild the full list of angles and dihedral angles from the list of bonds
+    /// Rebuild the full list of angles, dihedral angles and pairs of dihedral
+    /// angles from the list of bonds
     fn rebuild(&mut self) {
         self.angles.clear();
         self.dihedrals.clear();
+        self.dihedral_pairs.clear();
         for bond1 in &self.bonds {
             // Find angles
             for bond2 in &self.bonds {
@@ -122,35 +180,74 @@ impl Bonding {
                 }
             }
         }
+
+        // Find pairs of dihedral angles, by extending the dihedral angles
+        // with one more bond at either end.
+        for dihedral in &self.dihedrals {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let in_dihedral = |particle| {
+                particle == i || particle == j || particle == k || particle == m
+            };
+            for bond in &self.bonds {
+                let pair = if bond.i() == m && !in_dihedral(bond.j()) {
+                    DihedralPair::new(i, j, k, m, bond.j())
+                } else if bond.j() == m && !in_dihedral(bond.i()) {
+                    DihedralPair::new(i, j, k, m, bond.i())
+                } else if bond.i() == i && !in_dihedral(bond.j()) {
+                    DihedralPair::new(bond.j(), i, j, k, m)
+                } else if bond.j() == i && !in_dihedral(bond.i()) {
+                    DihedralPair::new(bond.i(), i, j, k, m)
+                } else {
+                    // This bond is part of the dihedral angle, or closes a
+                    // small cycle.
+                    continue;
+                };
+                let _ = self.dihedral_pairs.insert(pair);
+            }
+        }
         self.rebuild_connections();
     }
 
-    /// Recompute the connectivity matrix from the bonds, angles and dihedrals
-    /// in the system.
+    /// Recompute the connectivity matrix and the shortest bond paths from the
+    /// bonds, angles and dihedrals in the system.
     fn rebuild_connections(&mut self) {
         let n = self.size();
         self.distances = Array2::default((n, n));
 
         let first = self.start();
-        let distances = &mut self.distances;
-        let mut add_distance_term = |i, j, term| {
-            let old_distance = distances[(i - first, j - first)];
-            distances[(i - first, j - first)] = old_distance | term;
-        };
+        {
+            let distances = &mut self.distances;
+            let mut add_distance_term = |i, j, term| {
+                let old_distance = distances[(i - first, j - first)];
+                distances[(i - first, j - first)] = old_distance | term;
+            };
+
+            for bond in &self.bonds {
+                add_distance_term(bond.i(), bond.j(), BondDistances::ONE);
+                add_distance_term(bond.j(), bond.i(), BondDistances::ONE);
+            }
 
-        for bond in &self.bonds {
-            add_distance_term(bond.i(), bond.j(), BondDistances::ONE);
-            add_distance_term(bond.j(), bond.i(), BondDistances::ONE);
-        }
+            for angle in &self.angles {
+                add_distance_term(angle.i(), angle.k(), BondDistances::TWO);
+                add_distance_term(angle.k(), angle.i(), BondDistances::TWO);
+            }
 
-        for angle in &self.angles {
-            add_distance_term(angle.i(), angle.k(), BondDistances::TWO);
-            add_distance_term(angle.k(), angle.i(), BondDistances::TWO);
+            for dihedral in &self.dihedrals {
+                add_distance_term(dihedral.i(), dihedral.m(), BondDistances::THREE);
+                add_distance_term(dihedral.m(), dihedral.i(), BondDistances::THREE);
+            }
         }
 
-        for dihedral in &self.dihedrals {
-            add_distance_term(dihedral.i(), dihedral.m(), BondDistances::THREE);
-            add_distance_term(dihedral.m(), dihedral.i(), BondDistances::THREE);
+        self.paths.clear();
+        for i in 0..n {
+            for j in 0..n {
+                let path = if i == j {
+                    BondPath::SameParticle
+                } else {
+                    shortest_path(self.distances[(i, j)])
+                };
+                self.paths.push(path);
+            }
         }
     }
 
@@ -173,6 +270,16 @@ impl Bonding {
             let _ = self.dihedrals.insert(*dihedral);
         }
 
+        for pair in other.dihedral_pairs() {
+            let _ = self.dihedral_pairs.insert(*pair);
+        }
+
+        for improper in other.impropers() {
+            let _ = self.impropers.insert(*improper);
+        }
+
+        self.constraints.extend_from_slice(other.constraints());
+
         self.rebuild_connections();
     }
 
@@ -180,7 +287,7 @@ impl Bonding {
     pub fn translate_by(&mut self, delta: isize) {
         if delta < 0 {
             // We should not create negative indexes
-            assert!((delta.abs() as usize) < self.start());
+            assert!((delta.abs() as usize) <= self.start());
         }
 
         // The wrapping_add are necessary here, and produce the right result,
@@ -217,6 +324,33 @@ impl Bonding {
             ));
         }
         self.dihedrals = new_dihedrals;
+
+        let mut new_dihedral_pairs = HashSet::new();
+        for pair in &self.dihedral_pairs {
+            let _ = new_dihedral_pairs.insert(DihedralPair::new(
+                pair.i().wrapping_add(delta),
+                pair.j().wrapping_add(delta),
+                pair.k().wrapping_add(delta),
+                pair.m().wrapping_add(delta),
+                pair.n().wrapping_add(delta),
+            ));
+        }
+        self.dihedral_pairs = new_dihedral_pairs;
+
+        let mut new_impropers = HashSet::new();
+        for improper in &self.impropers {
+            let _ = new_impropers.insert(Improper::new(
+                improper.i().wrapping_add(delta),
+                improper.j().wrapping_add(delta),
+                improper.k().wrapping_add(delta),
+                improper.m().wrapping_add(delta),
+            ));
+        }
+        self.impropers = new_impropers;
+
+        for constraint in &mut self.constraints {
+            constraint.map_indexes(|i| i.wrapping_add(delta));
+        }
     }
 
     /// Add a bond between the particles at indexes `i` and `j`. These particles
@@ -253,10 +387,84 @@ impl Bonding {
         }
 
         self.bonds = new_bonds;
+
+        // Remove impropers containing the particle `i`
+        let shift = |index: usize| if index > i { index - 1 } else { index };
+        let mut new_impropers = HashSet::new();
+        for improper in self.impropers() {
+            let (a, b, c, d) = (improper.i(), improper.j(), improper.k(), improper.m());
+            if a == i || b == i || c == i || d == i {
+                continue;
+            }
+            let _ = new_impropers.insert(Improper::new(shift(a), shift(b), shift(c), shift(d)));
+        }
+        self.impropers = new_impropers;
+
+        // Remove distance and angle constraints containing the particle `i`,
+        // and shrink the rigid fragments containing it
+        self.constraints.retain(|constraint| match *constraint {
+            Constraint::Rigid { start, end } => end - start > 1 || start != i,
+            _ => !constraint.contains(i),
+        });
+        for constraint in &mut self.constraints {
+            constraint.map_indexes(shift);
+        }
+
         self.range.end -= 1;
         self.rebuild();
     }
 
+    /// Add an `improper` dihedral angle to this molecule. The particles in
+    /// the improper are assumed to be in the molecule.
+    pub fn add_improper(&mut self, improper: Improper) {
+        assert!(self.contains(improper.i()));
+        assert!(self.contains(improper.j()));
+        assert!(self.contains(improper.k()));
+        assert!(self.contains(improper.m()));
+        let _ = self.impropers.insert(improper);
+    }
+
+    /// Add a holonomic `constraint` to this molecule.
+    ///
+    /// # Panics
+    ///
+    /// If any of the particles in the constraint is not in this molecule, or
+    /// if the constraint is a rigid fragment without any particle.
+    pub fn add_constraint(&mut self, constraint: Constraint) {
+        if let Constraint::Rigid { start, end } = constraint {
+            assert!(start < end, "rigid fragments must contain at least one particle");
+        }
+        assert!(
+            constraint.particles().iter().all(|&i| self.contains(i)),
+            "all the particles in a constraint must be in the same molecule"
+        );
+        self.constraints.push(constraint);
+    }
+
+    /// Add improper dihedral angles for all the particles bonded to exactly
+    /// three other particles, using the bond graph. The central particle is
+    /// the first one in the improper, and the other particles are sorted by
+    /// increasing index.
+    pub fn guess_impropers(&mut self) {
+        for center in self.indexes() {
+            let mut neighbors = self.bonds.iter().filter_map(|bond| {
+                if bond.i() == center {
+                    Some(bond.j())
+                } else if bond.j() == center {
+                    Some(bond.i())
+                } else {
+                    None
+                }
+            }).collect::<Vec<_>>();
+
+            if neighbors.len() == 3 {
+                neighbors.sort_unstable();
+                let improper = Improper::new(center, neighbors[0], neighbors[1], neighbors[2]);
+                let _ = self.impropers.insert(improper);
+            }
+        }
+    }
+
     /// Get the internal list of bonds
     pub fn bonds(&self) -> &HashSet<Bond> {
         &self.bonds
@@ -272,12 +480,37 @@ impl Bonding {
         &self.dihedrals
     }
 
+    /// Get the internal list of pairs of consecutive dihedrals
+    pub fn dihedral_pairs(&self) -> &HashSet<DihedralPair> {
+        &self.dihedral_pairs
+    }
+
+    /// Get the internal list of improper dihedrals
+    pub fn impropers(&self) -> &HashSet<Improper> {
+        &self.impropers
+    }
+
+    /// Get the internal list of holonomic constraints
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
     /// Get the all the possible bond paths the particles `i` and `j` in this molecule
     pub fn bond_distances(&self, i: usize, j: usize) -> BondDistances {
         assert!(self.contains(i) && self.contains(j));
         return self.distances[(i - self.start(), j - self.start())];
     }
 
+    /// Get the shortest bond path between the particles `i` and `j` in this
+    /// molecule. This uses a precomputed value, updated when the bonds in
+    /// this molecule change.
+    #[inline]
+    pub fn bond_path(&self, i: usize, j: usize) -> BondPath {
+        debug_assert!(self.contains(i) && self.contains(j));
+        let start = self.start();
+        return self.paths[(i - start) * self.size() + (j - start)];
+    }
+
     /// Get the indexes of the particles in this molecule. All atoms in the
     /// returned range are inside this molecule.
     pub fn indexes(&self) -> Range<usize> {
@@ -285,10 +518,30 @@ impl Bonding {
     }
 }
 
+/// Count the number of distinct values in `labels`
+fn count_distinct(labels: &[u64]) -> usize {
+    labels.iter().collect::<HashSet<_>>().len()
+}
+
+/// Get the shortest bond path between two different particles from the set
+/// of bond `distances` between them
+fn shortest_path(distances: BondDistances) -> BondPath {
+    if distances.contains(BondDistances::ONE) {
+        BondPath::OneBond
+    } else if distances.contains(BondDistances::TWO) {
+        BondPath::TwoBonds
+    } else if distances.contains(BondDistances::THREE) {
+        BondPath::ThreeBonds
+    } else {
+        BondPath::Far
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use sys::{Angle, Bond, BondDistances, Dihedral};
+    use energy::BondPath;
+    use sys::{Angle, Bond, BondDistances, Dihedral, DihedralPair, Improper};
 
     #[test]
     fn translate_by() {
@@ -319,6 +572,93 @@ mod test {
         assert!(bonding.bonds().contains(&Bond::new(4, 5)));
         assert!(bonding.angles().contains(&Angle::new(3, 4, 5)));
         assert!(bonding.dihedrals().contains(&Dihedral::new(2, 3, 4, 5)));
+
+        bonding.translate_by(-2);
+        assert_eq!(bonding.start(), 0);
+        assert_eq!(bonding.end(), 4);
+        assert!(bonding.bonds().contains(&Bond::new(2, 3)));
+    }
+
+    #[test]
+    fn dihedral_pairs() {
+        // Create a linear chain, with a branch on the third particle
+        //   6 - 0 - 1 - 2 - 3 - 4
+        //               |
+        //               5
+        let mut bonding = Bonding::new(0);
+        for i in 1..7 {
+            bonding.merge_with(Bonding::new(i));
+        }
+        bonding.add_bond(0, 1);
+        bonding.add_bond(1, 2);
+        bonding.add_bond(2, 3);
+        assert_eq!(bonding.dihedral_pairs().len(), 0);
+
+        bonding.add_bond(3, 4);
+        assert_eq!(bonding.dihedral_pairs().len(), 1);
+        assert!(bonding.dihedral_pairs().contains(&DihedralPair::new(0, 1, 2, 3, 4)));
+        assert!(bonding.dihedral_pairs().contains(&DihedralPair::new(4, 3, 2, 1, 0)));
+
+        // The branch only creates dihedral angles with four particles
+        bonding.add_bond(2, 5);
+        assert_eq!(bonding.dihedral_pairs().len(), 1);
+
+        bonding.add_bond(0, 6);
+        assert_eq!(bonding.dihedral_pairs().len(), 3);
+        assert!(bonding.dihedral_pairs().contains(&DihedralPair::new(6, 0, 1, 2, 3)));
+        assert!(bonding.dihedral_pairs().contains(&DihedralPair::new(6, 0, 1, 2, 5)));
+
+        bonding.translate_by(3);
+        assert_eq!(bonding.dihedral_pairs().len(), 3);
+        assert!(bonding.dihedral_pairs().contains(&DihedralPair::new(3, 4, 5, 6, 7)));
+
+        // Small cycles do not create dihedral pairs
+        let mut bonding = Bonding::new(0);
+        for i in 1..4 {
+            bonding.merge_with(Bonding::new(i));
+        }
+        bonding.add_bond(0, 1);
+        bonding.add_bond(1, 2);
+        bonding.add_bond(2, 3);
+        bonding.add_bond(3, 0);
+        assert_eq!(bonding.dihedral_pairs().len(), 0);
+    }
+
+    #[test]
+    fn impropers() {
+        // Create formaldehyde like this
+        //       H              2
+        //       |              |
+        //   O = C - H      1 - 0 - 3
+        let mut bonding = Bonding::new(0);
+        for i in 1..5 {
+            bonding.merge_with(Bonding::new(i));
+        }
+        bonding.add_bond(0, 1);
+        bonding.add_bond(0, 2);
+        bonding.add_bond(0, 3);
+        assert_eq!(bonding.impropers().len(), 0);
+
+        bonding.guess_impropers();
+        assert_eq!(bonding.impropers().len(), 1);
+        assert!(bonding.impropers().contains(&Improper::new(0, 1, 2, 3)));
+
+        // Impropers are kept when adding bonds
+        bonding.add_bond(3, 4);
+        assert!(bonding.impropers().contains(&Improper::new(0, 1, 2, 3)));
+
+        bonding.add_improper(Improper::new(3, 0, 4, 1));
+        assert_eq!(bonding.impropers().len(), 2);
+
+        bonding.translate_by(2);
+        assert!(bonding.impropers().contains(&Improper::new(2, 3, 4, 5)));
+        assert!(bonding.impropers().contains(&Improper::new(5, 2, 6, 3)));
+
+        // Impropers containing the removed particle are removed, and the
+        // other are shifted
+        bonding.remove_particle(6);
+        assert_eq!(bonding.impropers().len(), 1);
+        assert!(bonding.impropers().contains(&Improper::new(2, 3, 4, 5)));
     }
 
     #[test]
@@ -415,6 +755,33 @@ mod test {
         assert_eq!(bonding.dihedrals().len(), 6);
     }
 
+    #[test]
+    fn bond_path() {
+        // Paths are updated when bonds are added, and follow the molecule
+        // when it is translated
+        let mut bonding = Bonding::new(0);
+        for i in 1..5 {
+            bonding.merge_with(Bonding::new(i));
+        }
+        assert_eq!(bonding.bond_path(0, 0), BondPath::SameParticle);
+        assert_eq!(bonding.bond_path(0, 4), BondPath::Far);
+
+        for i in 0..4 {
+            bonding.add_bond(i, i + 1);
+        }
+        assert_eq!(bonding.bond_path(0, 1), BondPath::OneBond);
+        assert_eq!(bonding.bond_path(2, 0), BondPath::TwoBonds);
+        assert_eq!(bonding.bond_path(0, 3), BondPath::ThreeBonds);
+        assert_eq!(bonding.bond_path(4, 0), BondPath::Far);
+
+        bonding.translate_by(3);
+        assert_eq!(bonding.bond_path(3, 4), BondPath::OneBond);
+        assert_eq!(bonding.bond_path(7, 3), BondPath::Far);
+
+        bonding.remove_particle(7);
+        assert_eq!(bonding.bond_path(3, 6), BondPath::ThreeBonds);
+    }
+
     #[test]
     fn cyclic() {
         //   0 -- 1
@@ -432,6 +799,9 @@ mod test {
 
         assert!(bonding.bond_distances(0, 3).contains(BondDistances::ONE));
         assert!(bonding.bond_distances(0, 3).contains(BondDistances::THREE));
+        assert_eq!(bonding.bond_path(0, 3), BondPath::OneBond);
+        assert_eq!(bonding.bond_path(0, 2), BondPath::TwoBonds);
+        assert_eq!(bonding.bond_path(2, 2), BondPath::SameParticle);
 
         assert!(bonding.angles.contains(&Angle::new(0, 3, 2)));
         assert!(bonding.angles.contains(&Angle::new(0, 1, 2)));
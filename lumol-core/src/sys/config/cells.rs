@@ -25,7 +25,11 @@ pub enum CellShape {
 /// An UnitCell defines the system physical boundaries.
 ///
 /// The shape of the cell can be any of the [`CellShape`][CellShape], and will
-/// influence how periodic boundary conditions are applied.
+/// influence how periodic boundary conditions are applied. Finite cells are
+/// periodic along all of their vectors by default, but the periodicity along
+/// each cell vector can be changed with
+/// [`set_periodicity`](struct.UnitCell.html#method.set_periodicity), for
+/// example to simulate a slab periodic in the xy plane and open along z.
 ///
 /// [CellShape]: enum.CellShape.html
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -37,6 +41,12 @@ pub struct UnitCell {
     inv: Matrix3,
     /// Unit cell shape
     shape: CellShape,
+    /// Are the periodic boundary conditions applied along each cell vector?
+    periodic: [bool; 3],
+    /// Half of the smallest distance between opposite faces of the unit cell.
+    /// Vectors shorter than this are always their own minimum image. This is
+    /// cached for performance reason, and MUST be updated as needed.
+    half_width: f64,
 }
 
 impl UnitCell {
@@ -46,27 +56,21 @@ impl UnitCell {
             cell: Matrix3::zero(),
             inv: Matrix3::zero(),
             shape: CellShape::Infinite,
+            periodic: [false; 3],
+            half_width: f64::INFINITY,
         }
     }
     /// Create an orthorhombic unit cell, with side lengths `a, b, c`.
     pub fn ortho(a: f64, b: f64, c: f64) -> UnitCell {
         assert!(a > 0.0 && b > 0.0 && c > 0.0, "Cell lengths must be positive");
         let cell = Matrix3::new([[a, 0.0, 0.0], [0.0, b, 0.0], [0.0, 0.0, c]]);
-        UnitCell {
-            cell: cell,
-            inv: cell.inverse(),
-            shape: CellShape::Orthorhombic,
-        }
+        UnitCell::from_matrix(cell, CellShape::Orthorhombic)
     }
     /// Create a cubic unit cell, with side lengths `length, length, length`.
     pub fn cubic(length: f64) -> UnitCell {
         assert!(length > 0.0, "Cell lengths must be positive");
         let cell = Matrix3::new([[length, 0.0, 0.0], [0.0, length, 0.0], [0.0, 0.0, length]]);
-        UnitCell {
-            cell: cell,
-            inv: cell.inverse(),
-            shape: CellShape::Orthorhombic,
-        }
+        UnitCell::from_matrix(cell, CellShape::Orthorhombic)
     }
     /// Create a triclinic unit cell, with side lengths `a, b, c` and angles
     /// `alpha, beta, gamma`.
@@ -84,11 +88,98 @@ impl UnitCell {
         let c_z = sqrt(c * c - c_y * c_y - c_x * c_x);
 
         let cell = Matrix3::new([[a, b_x, c_x], [0.0, b_y, c_y], [0.0, 0.0, c_z]]);
+        UnitCell::from_matrix(cell, CellShape::Triclinic)
+    }
 
-        UnitCell {
-            cell: cell,
-            inv: cell.inverse(),
-            shape: CellShape::Triclinic,
+    /// Create a truncated octahedron unit cell, with a distance `d` between
+    /// opposite hexagonal faces. This is also the distance between a particle
+    /// and its closest periodic images.
+    ///
+    /// A truncated octahedron has about 77% of the volume of a cube with the
+    /// same distance between periodic images, and is well suited to simulate
+    /// solvated globular molecules with less solvent. The cell is represented
+    /// as a triclinic cell, and positions can be wrapped in the truncated
+    /// octahedron shape using [`wrap_vector_compact`].
+    ///
+    /// [`wrap_vector_compact`]: struct.UnitCell.html#method.wrap_vector_compact
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::UnitCell;
+    /// let cell = UnitCell::truncated_octahedron(10.0);
+    /// assert!(f64::abs(cell.volume() - 4.0 * f64::sqrt(3.0) / 9.0 * 1000.0) < 1e-10);
+    /// ```
+    pub fn truncated_octahedron(d: f64) -> UnitCell {
+        assert!(d > 0.0, "Cell lengths must be positive");
+        let sqrt2 = f64::consts::SQRT_2;
+        let cell = Matrix3::new([
+            [d, d / 3.0, -d / 3.0],
+            [0.0, 2.0 * sqrt2 * d / 3.0, sqrt2 * d / 3.0],
+            [0.0, 0.0, sqrt(6.0) * d / 3.0],
+        ]);
+        UnitCell::from_matrix(cell, CellShape::Triclinic)
+    }
+
+    /// Create a rhombic dodecahedron unit cell, with a distance `d` between
+    /// opposite faces. This is also the distance between a particle and its
+    /// closest periodic images.
+    ///
+    /// A rhombic dodecahedron has about 71% of the volume of a cube with the
+    /// same distance between periodic images, and is well suited to simulate
+    /// solvated globular molecules with less solvent. The cell is represented
+    /// as a triclinic cell, and positions can be wrapped in the rhombic
+    /// dodecahedron shape using [`wrap_vector_compact`].
+    ///
+    /// [`wrap_vector_compact`]: struct.UnitCell.html#method.wrap_vector_compact
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::UnitCell;
+    /// let cell = UnitCell::rhombic_dodecahedron(10.0);
+    /// assert!(f64::abs(cell.volume() - f64::sqrt(2.0) / 2.0 * 1000.0) < 1e-10);
+    /// ```
+    pub fn rhombic_dodecahedron(d: f64) -> UnitCell {
+        assert!(d > 0.0, "Cell lengths must be positive");
+        let cell = Matrix3::new([
+            [d, 0.0, d / 2.0],
+            [0.0, d, d / 2.0],
+            [0.0, 0.0, f64::consts::SQRT_2 * d / 2.0],
+        ]);
+        UnitCell::from_matrix(cell, CellShape::Triclinic)
+    }
+
+    /// Create an unit cell from the cell `matrix` and the corresponding
+    /// `shape`, computing the cached data. The cell is periodic along all
+    /// of its vectors.
+    ///
+    /// # Panics
+    ///
+    /// If the `shape` is `CellShape::Infinite`, use `UnitCell::infinite`
+    /// to create infinite cells.
+    pub fn from_matrix(matrix: Matrix3, shape: CellShape) -> UnitCell {
+        assert!(shape != CellShape::Infinite, "use UnitCell::infinite to create infinite cells");
+        let mut cell = UnitCell {
+            cell: matrix,
+            inv: matrix.inverse(),
+            shape: shape,
+            periodic: [true; 3],
+            half_width: 0.0,
+        };
+        cell.update_half_width();
+        return cell;
+    }
+
+    /// Update the cached half width of the cell, only using the periodic
+    /// directions
+    fn update_half_width(&mut self) {
+        let lengths = self.lengths();
+        self.half_width = f64::INFINITY;
+        for k in 0..3 {
+            if self.periodic[k] {
+                self.half_width = f64::min(self.half_width, 0.5 * lengths[k]);
+            }
         }
     }
 
@@ -104,6 +195,45 @@ impl UnitCell {
         self.shape() == CellShape::Infinite
     }
 
+    /// Get the periodicity of the cell along each of the cell vectors.
+    /// Infinite cells are never periodic.
+    pub fn periodicity(&self) -> [bool; 3] {
+        self.periodic
+    }
+
+    /// Check if periodic boundary conditions are applied along all the cell
+    /// vectors.
+    pub fn is_fully_periodic(&self) -> bool {
+        self.periodic == [true; 3]
+    }
+
+    /// Set the periodicity of this cell along each of the cell vectors. The
+    /// periodic boundary conditions are not applied along the directions
+    /// with `false` periodicity, while the cell vector is still used to
+    /// compute the cell volume.
+    ///
+    /// # Panics
+    ///
+    /// If this cell is infinite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::UnitCell;
+    /// # use lumol_core::types::Vector3D;
+    /// let mut cell = UnitCell::cubic(10.0);
+    /// cell.set_periodicity([true, true, false]);
+    ///
+    /// let mut vector = Vector3D::new(8.0, 8.0, 8.0);
+    /// cell.vector_image(&mut vector);
+    /// assert_eq!(vector, Vector3D::new(-2.0, -2.0, 8.0));
+    /// ```
+    pub fn set_periodicity(&mut self, periodic: [bool; 3]) {
+        assert!(!self.is_infinite(), "can not set the periodicity of an infinite cell");
+        self.periodic = periodic;
+        self.update_half_width();
+    }
+
     /// Get the first length of the cell (i.e. the norm of the first vector of
     /// the cell)
     pub fn a(&self) -> f64 {
@@ -199,12 +329,31 @@ impl UnitCell {
         return volume;
     }
 
+    /// Get the area of the parallelogram defined by the first two vectors of
+    /// the cell. This is the size of the cell in two-dimensional systems.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::UnitCell;
+    /// let cell = UnitCell::ortho(3.0, 4.0, 10.0);
+    /// assert_eq!(cell.area(), 12.0);
+    /// ```
+    pub fn area(&self) -> f64 {
+        match self.shape {
+            CellShape::Infinite => 0.0,
+            CellShape::Orthorhombic => self.a() * self.b(),
+            CellShape::Triclinic => (self.vect_a() ^ self.vect_b()).norm(),
+        }
+    }
+
     /// Scale this unit cell in-place by multiplying the cell matrix by `factor`.
     #[inline]
     pub fn scale_mut(&mut self, factor: Matrix3) {
         assert!(self.shape() != CellShape::Infinite, "can not scale infinite cells");
         self.cell *= factor;
         self.inv = self.cell.inverse();
+        self.update_half_width();
     }
 
     /// Scale this unit cell by multiplying the cell matrix by `s`, and return a
@@ -213,11 +362,54 @@ impl UnitCell {
     pub fn scale(&self, s: Matrix3) -> UnitCell {
         assert!(self.shape() != CellShape::Infinite, "can not scale infinite cells");
         let cell = s * self.cell;
-        UnitCell {
-            cell: cell,
-            inv: cell.inverse(),
-            shape: self.shape,
-        }
+        let mut cell = UnitCell::from_matrix(cell, self.shape);
+        cell.set_periodicity(self.periodic);
+        return cell;
+    }
+
+    /// Set the shift along the x axis of the periodic images across the
+    /// second cell vector, as used by Lees-Edwards boundary conditions to
+    /// simulate a shear flow. The second cell vector is tilted by `offset`
+    /// along the first one, and the cell becomes triclinic. The offset is
+    /// brought back between `-a/2` and `a/2`, which gives the same periodic
+    /// images.
+    ///
+    /// # Panics
+    ///
+    /// If this cell is infinite, or if the first cell vector is not along
+    /// the x axis.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::UnitCell;
+    /// # use lumol_core::types::Vector3D;
+    /// let mut cell = UnitCell::cubic(10.0);
+    /// cell.set_shear_offset(12.0);
+    /// assert_eq!(cell.shear_offset(), 2.0);
+    ///
+    /// // Crossing the cell along y shifts the images along x
+    /// let mut vector = Vector3D::new(2.0, 10.0, 0.0);
+    /// cell.vector_image(&mut vector);
+    /// assert!(vector.norm() < 1e-12);
+    /// ```
+    pub fn set_shear_offset(&mut self, offset: f64) {
+        assert!(!self.is_infinite(), "can not shear an infinite cell");
+        assert!(
+            self.cell[1][0] == 0.0 && self.cell[2][0] == 0.0,
+            "the first cell vector must be along the x axis to shear the cell"
+        );
+        let a = self.cell[0][0];
+        self.cell[0][1] = offset - round(offset / a) * a;
+        self.shape = CellShape::Triclinic;
+        self.inv = self.cell.inverse();
+        self.update_half_width();
+    }
+
+    /// Get the shift along the x axis of the periodic images across the
+    /// second cell vector. This is zero for orthorhombic cells.
+    pub fn shear_offset(&self) -> f64 {
+        self.cell[0][1]
     }
 
     /// Get the reciprocal vector with the given `index`. This vector is null
@@ -260,20 +452,26 @@ impl UnitCell {
 impl UnitCell {
     /// Wrap a vector in the unit cell, obeying the periodic boundary conditions.
     /// For a cubic cell of side length `L`, this produce a vector with all
-    /// components in `[0, L)`.
+    /// components in `[0, L)`. The vector is not changed along non-periodic
+    /// directions.
     pub fn wrap_vector(&self, vect: &mut Vector3D) {
         match self.shape {
             CellShape::Infinite => (),
             CellShape::Orthorhombic => {
-                vect[0] -= floor(vect[0] / self.a()) * self.a();
-                vect[1] -= floor(vect[1] / self.b()) * self.b();
-                vect[2] -= floor(vect[2] / self.c()) * self.c();
+                let lengths = [self.a(), self.b(), self.c()];
+                for k in 0..3 {
+                    if self.periodic[k] {
+                        vect[k] -= floor(vect[k] / lengths[k]) * lengths[k];
+                    }
+                }
             }
             CellShape::Triclinic => {
                 let mut fractional = self.fractional(vect);
-                fractional[0] -= floor(fractional[0]);
-                fractional[1] -= floor(fractional[1]);
-                fractional[2] -= floor(fractional[2]);
+                for k in 0..3 {
+                    if self.periodic[k] {
+                        fractional[k] -= floor(fractional[k]);
+                    }
+                }
                 *vect = self.cartesian(&fractional);
             }
         }
@@ -281,25 +479,73 @@ impl UnitCell {
 
     /// Find the image of a vector in the unit cell, obeying the periodic
     /// boundary conditions. For a cubic cell of side length `L`, this produce a
-    /// vector with all components in `[-L/2, L/2)`.
+    /// vector with all components in `[-L/2, L/2)`. The vector is not changed
+    /// along non-periodic directions.
     pub fn vector_image(&self, vect: &mut Vector3D) {
         match self.shape {
             CellShape::Infinite => (),
             CellShape::Orthorhombic => {
-                vect[0] -= round(vect[0] / self.a()) * self.a();
-                vect[1] -= round(vect[1] / self.b()) * self.b();
-                vect[2] -= round(vect[2] / self.c()) * self.c();
+                let lengths = [self.a(), self.b(), self.c()];
+                for k in 0..3 {
+                    if self.periodic[k] {
+                        vect[k] -= round(vect[k] / lengths[k]) * lengths[k];
+                    }
+                }
             }
             CellShape::Triclinic => {
                 let mut fractional = self.fractional(vect);
-                fractional[0] -= round(fractional[0]);
-                fractional[1] -= round(fractional[1]);
-                fractional[2] -= round(fractional[2]);
+                for k in 0..3 {
+                    if self.periodic[k] {
+                        fractional[k] -= round(fractional[k]);
+                    }
+                }
                 *vect = self.cartesian(&fractional);
+                if vect.norm2() > self.half_width * self.half_width {
+                    // For strongly skewed cells like truncated octahedra,
+                    // one of the neighboring images can be shorter
+                    self.shortest_neighbor_image(vect);
+                }
             }
         }
     }
 
+    /// Replace `vect` by the shortest of its images in the neighboring cells
+    fn shortest_neighbor_image(&self, vect: &mut Vector3D) {
+        let range = |k: usize| if self.periodic[k] { -1..2 } else { 0..1 };
+        let mut shortest = *vect;
+        for i in range(0) {
+            for j in range(1) {
+                for k in range(2) {
+                    let shift = Vector3D::new(i as f64, j as f64, k as f64);
+                    let image = *vect + self.cartesian(&shift);
+                    if image.norm2() < shortest.norm2() {
+                        shortest = image;
+                    }
+                }
+            }
+        }
+        *vect = shortest;
+    }
+
+    /// Wrap a vector in the compact shape of the unit cell, *i.e.* the set of
+    /// points closer to the center of the cell than to any of its periodic
+    /// images. For orthorhombic cells, this is the same as `wrap_vector`. For
+    /// cells created with [`truncated_octahedron`] or
+    /// [`rhombic_dodecahedron`], this wraps the vector inside the
+    /// corresponding polyhedron.
+    ///
+    /// [`truncated_octahedron`]: struct.UnitCell.html#method.truncated_octahedron
+    /// [`rhombic_dodecahedron`]: struct.UnitCell.html#method.rhombic_dodecahedron
+    pub fn wrap_vector_compact(&self, vect: &mut Vector3D) {
+        if self.shape == CellShape::Infinite {
+            return;
+        }
+        let center = self.cartesian(&Vector3D::new(0.5, 0.5, 0.5));
+        let mut image = *vect - center;
+        self.vector_image(&mut image);
+        *vect = center + image;
+    }
+
     /// Get the fractional representation of the `vector` in this cell
     #[inline]
     pub fn fractional(&self, vector: &Vector3D) -> Vector3D {
@@ -463,6 +709,7 @@ mod tests {
         assert_eq!(cell.gamma(), 90.0);
 
         assert_eq!(cell.volume(), 0.0);
+        assert_eq!(cell.area(), 0.0);
     }
 
     #[test]
@@ -505,6 +752,7 @@ mod tests {
         assert_eq!(cell.gamma(), 90.0);
 
         assert_eq!(cell.volume(), 3.0 * 4.0 * 5.0);
+        assert_eq!(cell.area(), 3.0 * 4.0);
     }
 
     #[test]
@@ -525,6 +773,8 @@ mod tests {
         assert_eq!(cell.gamma(), 110.0);
 
         assert_relative_eq!(cell.volume(), 55.410529, epsilon = 1e-6);
+        let area = 3.0 * 4.0 * f64::sin(110f64.to_radians());
+        assert_relative_eq!(cell.area(), area, epsilon = 1e-12);
     }
 
     #[test]
@@ -573,6 +823,37 @@ mod tests {
         cell.scale_mut(2.0 * Matrix3::one());
     }
 
+    #[test]
+    fn shear_offset() {
+        let mut cell = UnitCell::ortho(10.0, 8.0, 6.0);
+        assert_eq!(cell.shear_offset(), 0.0);
+
+        cell.set_shear_offset(3.0);
+        assert_eq!(cell.shape(), CellShape::Triclinic);
+        assert_eq!(cell.shear_offset(), 3.0);
+        assert_ulps_eq!(cell.volume(), 480.0);
+        assert_relative_eq!(cell.lengths(), Vector3D::new(10.0, 8.0, 6.0), epsilon = 1e-12);
+
+        // The images of a particle shift along x when crossing the cell
+        // along y
+        let mut vector = Vector3D::new(3.5, 8.0, 0.0);
+        cell.vector_image(&mut vector);
+        assert_relative_eq!(vector, Vector3D::new(0.5, 0.0, 0.0), epsilon = 1e-12);
+
+        // Large offsets are brought back in the [-a/2, a/2] range
+        cell.set_shear_offset(-17.0);
+        assert_ulps_eq!(cell.shear_offset(), 3.0);
+        cell.set_shear_offset(6.0);
+        assert_ulps_eq!(cell.shear_offset(), -4.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shear_infinite() {
+        let mut cell = UnitCell::infinite();
+        cell.set_shear_offset(2.0);
+    }
+
     #[test]
     fn k_vectors() {
         let cell = UnitCell::ortho(3.0, 4.0, 5.0);
@@ -676,6 +957,154 @@ mod tests {
         assert_ulps_eq!(v[2], res[2], max_ulps = 5);
     }
 
+    /// Brute force search of the minimum image of `vect` in `cell`
+    fn brute_force_image(cell: &UnitCell, vect: Vector3D) -> Vector3D {
+        let mut shortest = vect;
+        for i in -4..5 {
+            for j in -4..5 {
+                for k in -4..5 {
+                    let shift = Vector3D::new(i as f64, j as f64, k as f64);
+                    let image = vect + cell.cartesian(&shift);
+                    if image.norm() < shortest.norm() {
+                        shortest = image;
+                    }
+                }
+            }
+        }
+        return shortest;
+    }
+
+    #[test]
+    fn truncated_octahedron() {
+        let cell = UnitCell::truncated_octahedron(10.0);
+        assert_eq!(cell.shape(), CellShape::Triclinic);
+        assert_ulps_eq!(cell.a(), 10.0);
+        assert_ulps_eq!(cell.b(), 10.0);
+        assert_ulps_eq!(cell.c(), 10.0);
+        let angle = f64::acos(1.0 / 3.0).to_degrees();
+        assert_ulps_eq!(cell.alpha(), angle, epsilon = 1e-12);
+        assert_ulps_eq!(cell.beta(), 180.0 - angle, epsilon = 1e-12);
+        assert_ulps_eq!(cell.gamma(), angle, epsilon = 1e-12);
+        assert_ulps_eq!(cell.volume(), 4.0 * sqrt(3.0) / 9.0 * 1000.0, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn rhombic_dodecahedron() {
+        let cell = UnitCell::rhombic_dodecahedron(10.0);
+        assert_eq!(cell.shape(), CellShape::Triclinic);
+        assert_ulps_eq!(cell.a(), 10.0);
+        assert_ulps_eq!(cell.b(), 10.0);
+        assert_ulps_eq!(cell.c(), 10.0);
+        assert_ulps_eq!(cell.alpha(), 60.0, epsilon = 1e-12);
+        assert_ulps_eq!(cell.beta(), 60.0, epsilon = 1e-12);
+        assert_ulps_eq!(cell.gamma(), 90.0, epsilon = 1e-12);
+        assert_ulps_eq!(cell.volume(), sqrt(2.0) / 2.0 * 1000.0, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn compact_cells_vector_image() {
+        let vectors = [
+            Vector3D::new(4.0, 4.0, 4.0),
+            Vector3D::new(-4.5, 3.2, 0.1),
+            Vector3D::new(7.9, -6.1, 5.3),
+            Vector3D::new(0.3, 9.2, -4.4),
+            Vector3D::new(-12.0, 2.5, 8.8),
+            Vector3D::new(3.3, -4.1, -4.4),
+        ];
+
+        let cells = [
+            UnitCell::truncated_octahedron(10.0),
+            UnitCell::rhombic_dodecahedron(10.0),
+        ];
+        for cell in &cells {
+            for &vector in &vectors {
+                let mut image = vector;
+                cell.vector_image(&mut image);
+                let expected = brute_force_image(cell, vector);
+                assert_ulps_eq!(image.norm(), expected.norm(), epsilon = 1e-12);
+                // The minimum image is never further than the distance
+                // between opposite faces
+                assert!(image.norm() <= 10.0 * sqrt(3.0) / 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_vector_compact() {
+        let cell = UnitCell::truncated_octahedron(10.0);
+        let center = cell.cartesian(&Vector3D::new(0.5, 0.5, 0.5));
+        let vectors = [
+            Vector3D::new(4.0, 4.0, 4.0),
+            Vector3D::new(-4.5, 3.2, 0.1),
+            Vector3D::new(17.9, -6.1, 5.3),
+        ];
+        for &vector in &vectors {
+            let mut wrapped = vector;
+            cell.wrap_vector_compact(&mut wrapped);
+
+            // The wrapped vector is a periodic image of the initial one, and
+            // is closer to the center than any of its periodic images
+            let fractional = cell.fractional(&(wrapped - vector));
+            for i in 0..3 {
+                assert_ulps_eq!(fractional[i], round(fractional[i]), epsilon = 1e-12);
+            }
+            let expected = brute_force_image(&cell, wrapped - center);
+            assert_ulps_eq!((wrapped - center).norm(), expected.norm(), epsilon = 1e-12);
+        }
+
+        // For orthorhombic cells, this is the same as `wrap_vector`
+        let cell = UnitCell::ortho(3.0, 4.0, 5.0);
+        let mut v = Vector3D::new(1.0, 1.5, 6.0);
+        cell.wrap_vector_compact(&mut v);
+        assert_ulps_eq!(v, Vector3D::new(1.0, 1.5, 1.0));
+    }
+
+    #[test]
+    fn partially_periodic() {
+        let mut cell = UnitCell::ortho(3.0, 4.0, 5.0);
+        assert_eq!(cell.periodicity(), [true; 3]);
+        assert!(cell.is_fully_periodic());
+
+        cell.set_periodicity([true, false, true]);
+        assert_eq!(cell.periodicity(), [true, false, true]);
+        assert!(!cell.is_fully_periodic());
+        assert_eq!(cell.volume(), 60.0);
+
+        let mut v = Vector3D::new(4.0, 7.0, -6.0);
+        cell.wrap_vector(&mut v);
+        assert_eq!(v, Vector3D::new(1.0, 7.0, 4.0));
+
+        let mut v = Vector3D::new(2.0, 7.0, -4.0);
+        cell.vector_image(&mut v);
+        assert_eq!(v, Vector3D::new(-1.0, 7.0, 1.0));
+
+        // Scaling the cell keeps the periodicity
+        let scaled = cell.scale(Matrix3::one() * 2.0);
+        assert_eq!(scaled.periodicity(), [true, false, true]);
+
+        // Triclinic cells
+        let mut cell = UnitCell::triclinic(10.0, 10.0, 10.0, 90.0, 90.0, 60.0);
+        cell.set_periodicity([false, true, false]);
+        let shift = cell.cartesian(&Vector3D::new(0.0, 1.0, 0.0));
+        let v = Vector3D::new(1.0, 1.0, -30.0);
+        let mut image = v + 3.0 * shift;
+        cell.vector_image(&mut image);
+        assert_relative_eq!(image, v, epsilon = 1e-12);
+
+        let mut image = v + 3.0 * shift;
+        cell.wrap_vector(&mut image);
+        assert_relative_eq!(cell.fractional(&image)[0], cell.fractional(&v)[0], epsilon = 1e-12);
+        assert_relative_eq!(image[2], -30.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not set the periodicity of an infinite cell")]
+    fn infinite_periodicity() {
+        let mut cell = UnitCell::infinite();
+        assert_eq!(cell.periodicity(), [false; 3]);
+        cell.set_periodicity([true, true, false]);
+    }
+
     #[test]
     fn fractional_cartesian() {
         let cell = UnitCell::cubic(5.0);
@@ -8,12 +8,19 @@ use sys::{ParticleKind, MoleculeHash};
 
 /// The system composition contains the number of particles of each kind
 /// in the system, as well as the number of molecules of each molecule type.
+///
+/// Molecule types are identified by their [`MoleculeHash`], and can also be
+/// given a user-defined name like `"water"` or `"ethanol"`.
+///
+/// [`MoleculeHash`]: struct.MoleculeHash.html
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Composition {
     /// The particles composition, indexes by particles kind
     particles: Vec<usize>,
     /// The molecules compostion, indexes by molecule type
     molecules: BTreeMap<MoleculeHash, usize>,
+    /// The names of the molecule types
+    names: BTreeMap<MoleculeHash, String>,
 }
 
 impl Composition {
@@ -35,6 +42,7 @@ impl Composition {
         Composition {
             particles: Vec::new(),
             molecules: BTreeMap::new(),
+            names: BTreeMap::new(),
         }
     }
 
@@ -233,6 +241,52 @@ impl Composition {
             .filter(|(_, n)| **n != 0)
             .map(|(&m, &n)| (m, n))
     }
+
+    /// Set the `name` of the molecules with the given `hash`, replacing any
+    /// previous name for these molecules.
+    ///
+    /// # Panics
+    ///
+    /// If the same name is already used for another molecule type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{Composition, Molecule, Particle};
+    /// let he = Molecule::new(Particle::new("He")).hash();
+    ///
+    /// let mut composition = Composition::new();
+    /// composition.set_name(he, "helium");
+    ///
+    /// assert_eq!(composition.name(he), Some("helium"));
+    /// assert_eq!(composition.hash("helium"), Some(he));
+    /// assert_eq!(composition.hash("argon"), None);
+    /// ```
+    pub fn set_name<S: Into<String>>(&mut self, hash: MoleculeHash, name: S) {
+        let name = name.into();
+        if let Some(other) = self.hash(&name) {
+            assert!(
+                other == hash,
+                "the '{}' name is already used for another molecule type", name
+            );
+        }
+        let _ = self.names.insert(hash, name);
+    }
+
+    /// Get the name of the molecules with the given `hash`, if any
+    pub fn name(&self, hash: MoleculeHash) -> Option<&str> {
+        self.names.get(&hash).map(|name| name.as_str())
+    }
+
+    /// Get the hash of the molecules with the given `name`, if any
+    pub fn hash(&self, name: &str) -> Option<MoleculeHash> {
+        self.names.iter().find(|&(_, n)| n == name).map(|(&hash, _)| hash)
+    }
+
+    /// Get an iterator over the molecules hashes and names
+    pub fn all_names<'a>(&'a self) -> impl Iterator<Item = (MoleculeHash, &'a str)> + 'a {
+        self.names.iter().map(|(&hash, name)| (hash, name.as_str()))
+    }
 }
 
 #[cfg(test)]
@@ -271,4 +325,29 @@ mod tests {
         assert_eq!(composition.molecules(MoleculeHash::new(10)), 1);
         assert_eq!(composition.molecules(MoleculeHash::new(124)), 0);
     }
+
+    #[test]
+    fn names() {
+        let mut composition = Composition::new();
+        composition.set_name(MoleculeHash::new(22), "water");
+        composition.set_name(MoleculeHash::new(10), "ethanol");
+
+        assert_eq!(composition.name(MoleculeHash::new(22)), Some("water"));
+        assert_eq!(composition.hash("ethanol"), Some(MoleculeHash::new(10)));
+        assert_eq!(composition.name(MoleculeHash::new(124)), None);
+
+        // Names can be changed
+        composition.set_name(MoleculeHash::new(22), "H2O");
+        assert_eq!(composition.name(MoleculeHash::new(22)), Some("H2O"));
+        assert_eq!(composition.hash("water"), None);
+        assert_eq!(composition.all_names().count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "the 'water' name is already used for another molecule type")]
+    fn duplicated_name() {
+        let mut composition = Composition::new();
+        composition.set_name(MoleculeHash::new(22), "water");
+        composition.set_name(MoleculeHash::new(10), "water");
+    }
 }
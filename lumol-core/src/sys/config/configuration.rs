@@ -4,6 +4,7 @@
 //! The Configuration type definition
 
 use std::cmp::{max, min};
+use std::collections::btree_map::{self, BTreeMap};
 use std::marker::PhantomData;
 // use std::iter::DoubleEndedIterator
 
@@ -11,9 +12,10 @@ use types::Vector3D;
 
 use energy::BondPath;
 
-use sys::{BondDistances, Bonding, ParticleKind, UnitCell};
+use sys::{Angle, Bond, Bonding, Dihedral, Improper, ParticleKind, UnitCell};
 use sys::{ParticleSlice, ParticleSliceMut, ParticleVec, ParticlePtr, ParticlePtrMut};
 use sys::{Molecule, MoleculeRef, MoleculeRefMut};
+use sys::{Constraint, Property};
 
 /// Particles permutations:. Indexes are given in the `(old, new)` form.
 pub type Permutations = Vec<(usize, usize)>;
@@ -22,7 +24,8 @@ pub type Permutations = Vec<(usize, usize)>;
 ///
 /// - The unit cell;
 /// - The list of particles in the system;
-/// - The list of molecules in the system.
+/// - The list of molecules in the system;
+/// - The custom per-particle properties.
 #[derive(Clone)]
 pub struct Configuration {
     /// Unit cell of the system
@@ -33,6 +36,8 @@ pub struct Configuration {
     bondings: Vec<Bonding>,
     /// Molecules indexes for all the particles
     molecule_ids: Vec<usize>,
+    /// Custom per-particle properties, indexed by name
+    properties: BTreeMap<String, Property>,
 }
 
 impl Configuration {
@@ -42,6 +47,7 @@ impl Configuration {
             particles: ParticleVec::new(),
             bondings: Vec::new(),
             molecule_ids: Vec::new(),
+            properties: BTreeMap::new(),
             cell: UnitCell::infinite(),
         }
     }
@@ -106,28 +112,83 @@ impl Configuration {
     /// the particle `j`. If the particles are not in the same molecule, the
     /// length is -1. Else, this length is 0 if `i == j`, 1 if there is a bond
     /// between `i` and `j`, etc.
+    ///
+    /// The bond paths are precomputed for each molecule when its bonds
+    /// change, making this function cheap enough to be called in the inner
+    /// loops of energy evaluations.
+    #[inline]
     pub fn bond_path(&self, i: usize, j: usize) -> BondPath {
         assert!(i < self.size() && j < self.size());
-        if !(self.are_in_same_molecule(i, j)) {
+        let molid = self.molecule_ids[i];
+        if molid != self.molecule_ids[j] {
             BondPath::None
-        } else if i == j {
-            BondPath::SameParticle
         } else {
-            let connect = self.molecule(self.molecule_id(i)).bond_distances(i, j);
-            if connect.contains(BondDistances::ONE) {
-                BondPath::OneBond
-            } else if connect.contains(BondDistances::TWO) {
-                BondPath::TwoBonds
-            } else if connect.contains(BondDistances::THREE) {
-                BondPath::ThreeBonds
-            } else if connect.contains(BondDistances::FAR) {
-                BondPath::Far
-            } else {
-                unreachable!();
-            }
+            self.bondings[molid].bond_path(i, j)
         }
     }
 
+    /// Get the bonding information of all the molecules in this configuration
+    pub(crate) fn bondings(&self) -> &[Bonding] {
+        &self.bondings
+    }
+
+    /// Get all the bonds in this configuration, sorted by particle indexes
+    pub fn bonds(&self) -> Vec<Bond> {
+        let mut bonds = self.bondings.iter()
+                                     .flat_map(|bonding| bonding.bonds().iter().cloned())
+                                     .collect::<Vec<_>>();
+        bonds.sort();
+        return bonds;
+    }
+
+    /// Get all the angles in this configuration, sorted by particle indexes.
+    /// The angles are automatically derived from the bonds.
+    pub fn angles(&self) -> Vec<Angle> {
+        let mut angles = self.bondings.iter()
+                                      .flat_map(|bonding| bonding.angles().iter().cloned())
+                                      .collect::<Vec<_>>();
+        angles.sort();
+        return angles;
+    }
+
+    /// Get all the dihedral angles in this configuration, sorted by particle
+    /// indexes. The dihedral angles are automatically derived from the bonds.
+    pub fn dihedrals(&self) -> Vec<Dihedral> {
+        let mut dihedrals = self.bondings.iter()
+                                         .flat_map(|bonding| bonding.dihedrals().iter().cloned())
+                                         .collect::<Vec<_>>();
+        dihedrals.sort();
+        return dihedrals;
+    }
+
+    /// Get all the holonomic constraints in this configuration, in the order
+    /// of the molecules containing them
+    pub fn constraints(&self) -> Vec<Constraint> {
+        self.bondings.iter()
+                     .flat_map(|bonding| bonding.constraints().iter().cloned())
+                     .collect()
+    }
+
+    /// Get the number of degrees of freedom removed by all the holonomic
+    /// constraints in this configuration. Constraints between frozen
+    /// particles only are not counted, since these particles do not have any
+    /// degree of freedom.
+    pub fn constrained_degrees_of_freedom(&self) -> usize {
+        self.constrained_degrees_of_freedom_in(3)
+    }
+
+    /// Get the number of degrees of freedom removed by all the holonomic
+    /// constraints in this configuration, for a system with the given number
+    /// of spatial `dimensions`.
+    pub(crate) fn constrained_degrees_of_freedom_in(&self, dimensions: usize) -> usize {
+        let frozen = &self.particles.frozen;
+        self.bondings.iter()
+                     .flat_map(|bonding| bonding.constraints())
+                     .filter(|constraint| !constraint.particles().iter().all(|&i| frozen[i]))
+                     .map(|constraint| constraint.removed_degrees_of_freedom_in(dimensions))
+                     .sum()
+    }
+
     /// Remove the molecule at index `i`
     pub fn remove_molecule(&mut self, molid: usize) {
         let molecule = self.bondings.remove(molid);
@@ -137,6 +198,9 @@ impl Configuration {
         for _ in 0..size {
             let _ = self.particles.remove(first);
             let _ = self.molecule_ids.remove(first);
+            for property in self.properties.values_mut() {
+                property.remove(first);
+            }
         }
 
         for molecule in self.bondings.iter_mut().skip(molid) {
@@ -218,6 +282,41 @@ impl Configuration {
         return permutations;
     }
 
+    /// Add an improper dihedral angle around the particle at index `center`,
+    /// with the particles at indexes `j`, `k` and `m`. All the particles must
+    /// be in the same molecule.
+    pub fn add_improper(&mut self, center: usize, j: usize, k: usize, m: usize) {
+        assert!(
+            self.are_in_same_molecule(center, j) &&
+            self.are_in_same_molecule(center, k) &&
+            self.are_in_same_molecule(center, m),
+            "all the particles in an improper dihedral angle must be in the same molecule"
+        );
+        let molid = self.molecule_ids[center];
+        self.bondings[molid].add_improper(Improper::new(center, j, k, m));
+    }
+
+    /// Add a holonomic `constraint` between particles in this configuration.
+    /// All the particles in the constraint must be in the same molecule.
+    pub fn add_constraint(&mut self, constraint: Constraint) {
+        let particles = constraint.particles();
+        assert!(!particles.is_empty(), "rigid fragments must contain at least one particle");
+        let molid = self.molecule_ids[particles[0]];
+        assert!(
+            particles.iter().all(|&i| self.molecule_ids[i] == molid),
+            "all the particles in a constraint must be in the same molecule"
+        );
+        self.bondings[molid].add_constraint(constraint);
+    }
+
+    /// Add improper dihedral angles for all the particles bonded to exactly
+    /// three other particles in the configuration.
+    pub fn guess_impropers(&mut self) {
+        for bonding in &mut self.bondings {
+            bonding.guess_impropers();
+        }
+    }
+
     /// Add a molecule to the configuration, putting the new particles at the
     /// end of the particles list
     pub fn add_molecule(&mut self, mut molecule: Molecule) {
@@ -235,6 +334,9 @@ impl Configuration {
         bonding.translate_by(self.particles.len() as isize);
 
         self.molecule_ids.append(&mut vec![self.bondings.len(); bonding.size()]);
+        for property in self.properties.values_mut() {
+            property.extend_default(bonding.size());
+        }
         self.bondings.push(bonding);
         self.particles.append(&mut molecule.particles);
     }
@@ -273,6 +375,45 @@ impl Configuration {
         self.particles.as_mut_slice()
     }
 
+    /// Add a custom per-particle `property` with the given `name` to this
+    /// configuration, replacing any existing property with the same name.
+    ///
+    /// # Panics
+    ///
+    /// If the property does not contain exactly one value for each particle
+    /// in the configuration.
+    pub fn add_property<S: Into<String>>(&mut self, name: S, property: Property) {
+        let name = name.into();
+        assert_eq!(
+            property.len(), self.size(),
+            "the '{}' property must contain one value for each particle", name
+        );
+        let _ = self.properties.insert(name, property);
+    }
+
+    /// Get the custom per-particle property with the given `name`, if any
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    /// Get mutable access to the custom per-particle property with the given
+    /// `name`, if any
+    pub fn property_mut(&mut self, name: &str) -> Option<&mut Property> {
+        self.properties.get_mut(name)
+    }
+
+    /// Remove the custom per-particle property with the given `name` from
+    /// this configuration, and return it
+    pub fn remove_property(&mut self, name: &str) -> Option<Property> {
+        self.properties.remove(name)
+    }
+
+    /// Get an iterator over the names and values of all the custom
+    /// per-particle properties in this configuration
+    pub fn properties(&self) -> btree_map::Iter<String, Property> {
+        self.properties.iter()
+    }
+
     /// Merge the molecules at indexes `first` and `second` into one
     /// molecule. The molecule are merged into the one with the lower molecule
     /// index.
@@ -326,6 +467,10 @@ impl Configuration {
                 let _ = self.molecule_ids.remove(i);
                 self.molecule_ids.insert(new_index, new_molid);
 
+                for property in self.properties.values_mut() {
+                    property.move_value(i, new_index);
+                }
+
                 new_index += 1;
             }
         }
@@ -373,6 +518,18 @@ fn check_molid_sorted(molecule_ids: &[usize]) -> bool {
 
 /// `UnitCell` related functions
 impl Configuration {
+    /// Get the unwrapped positions of the particles, *i.e.* the positions
+    /// the particles would have if their molecules had never been wrapped
+    /// in the unit cell. The number of crossed periodic images is tracked in
+    /// the `image` field of the particles.
+    pub fn unwrapped_positions(&self) -> Vec<Vector3D> {
+        let cell = &self.cell;
+        soa_zip!(&self.particles, [position, image]).map(|(position, image)| {
+            let image = Vector3D::new(image[0] as f64, image[1] as f64, image[2] as f64);
+            position + cell.cartesian(&image)
+        }).collect()
+    }
+
     /// Get the distance between the particles at indexes `i` and `j`
     #[inline]
     pub fn distance(&self, i: usize, j: usize) -> f64 {
@@ -439,6 +596,97 @@ impl Configuration {
     }
 }
 
+/// A lightweight copy of the positions, velocities and unit cell of a
+/// [`Configuration`], used to go back to a previous state of the
+/// configuration, for example after a rejected Monte Carlo move.
+///
+/// Snapshots are created with [`Configuration::snapshot`], and used with
+/// [`Configuration::restore`]. They do not contain the topology or the other
+/// particles data, and can only be restored in a configuration containing the
+/// same number of particles.
+///
+/// [`Configuration`]: struct.Configuration.html
+/// [`Configuration::snapshot`]: struct.Configuration.html#method.snapshot
+/// [`Configuration::restore`]: struct.Configuration.html#method.restore
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell};
+/// use lumol_core::types::Vector3D;
+///
+/// let mut system = System::with_cell(UnitCell::cubic(10.0));
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+///
+/// let snapshot = system.snapshot();
+/// system.particles_mut().position[0] = Vector3D::new(1.0, 2.0, 3.0);
+/// system.cell = UnitCell::cubic(12.0);
+///
+/// system.restore(&snapshot);
+/// assert_eq!(system.particles().position[0], Vector3D::zero());
+/// assert_eq!(system.cell, UnitCell::cubic(10.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Unit cell of the configuration
+    cell: UnitCell,
+    /// Positions of the particles
+    positions: Vec<Vector3D>,
+    /// Periodic images of the particles, used for unwrapped positions
+    images: Vec<[i32; 3]>,
+    /// Velocities of the particles
+    velocities: Vec<Vector3D>,
+}
+
+impl Snapshot {
+    /// Get the unit cell stored in this snapshot
+    pub fn cell(&self) -> &UnitCell {
+        &self.cell
+    }
+
+    /// Get the positions of the particles stored in this snapshot
+    pub fn positions(&self) -> &[Vector3D] {
+        &self.positions
+    }
+
+    /// Get the velocities of the particles stored in this snapshot
+    pub fn velocities(&self) -> &[Vector3D] {
+        &self.velocities
+    }
+}
+
+/// Snapshots related functions
+impl Configuration {
+    /// Create a snapshot of the positions, velocities and unit cell of this
+    /// configuration.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cell: self.cell,
+            positions: self.particles.position.clone(),
+            images: self.particles.image.clone(),
+            velocities: self.particles.velocity.clone(),
+        }
+    }
+
+    /// Restore the positions, velocities and unit cell of this configuration
+    /// from a previous `snapshot`.
+    ///
+    /// # Panics
+    ///
+    /// If the snapshot does not contain the same number of particles as this
+    /// configuration.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        assert_eq!(
+            snapshot.positions.len(), self.size(),
+            "can not restore a snapshot with a different number of particles"
+        );
+        self.cell = snapshot.cell;
+        self.particles.position.copy_from_slice(&snapshot.positions);
+        self.particles.image.copy_from_slice(&snapshot.images);
+        self.particles.velocity.copy_from_slice(&snapshot.velocities);
+    }
+}
+
 /// An iterator over all the molecules in a `Configuration`
 pub struct MoleculeIter<'a> {
     bondings: ::std::slice::Iter<'a, Bonding>,
@@ -563,7 +811,7 @@ impl<'a> DoubleEndedIterator for MoleculeIterMut<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sys::{Angle, Bond, Dihedral, Particle, Molecule};
+    use sys::{Angle, Bond, Constraint, Dihedral, Improper, Particle, Molecule};
     use energy::BondPath;
     use types::Vector3D;
 
@@ -614,6 +862,126 @@ mod tests {
         assert_eq!(configuration.size(), 0);
     }
 
+    #[test]
+    fn bonded_terms() {
+        let mut configuration = Configuration::new();
+        let mut molecule = Molecule::new(particle("C"));
+        molecule.add_particle_bonded_to(0, particle("C"));
+        molecule.add_particle_bonded_to(1, particle("C"));
+        configuration.add_molecule(molecule);
+
+        let mut molecule = Molecule::new(particle("O"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        configuration.add_molecule(molecule);
+
+        assert_eq!(configuration.bonds(), vec![Bond::new(0, 1), Bond::new(1, 2), Bond::new(3, 4)]);
+        assert_eq!(configuration.angles(), vec![Angle::new(0, 1, 2)]);
+        assert!(configuration.dihedrals().is_empty());
+
+        // Adding a bond updates the angles and dihedral angles
+        let _ = configuration.add_bond(2, 3);
+        assert_eq!(configuration.angles(), vec![
+            Angle::new(0, 1, 2), Angle::new(1, 2, 3), Angle::new(2, 3, 4)
+        ]);
+        assert_eq!(configuration.dihedrals(), vec![
+            Dihedral::new(0, 1, 2, 3), Dihedral::new(1, 2, 3, 4)
+        ]);
+    }
+
+    #[test]
+    fn constraints() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        for _ in 0..2 {
+            let mut molecule = Molecule::new(particle("O"));
+            molecule.add_particle_bonded_to(0, particle("H"));
+            configuration.add_molecule(molecule);
+        }
+
+        configuration.add_constraint(Constraint::Distance { i: 1, j: 2, distance: 1.0 });
+        configuration.add_constraint(Constraint::Rigid { start: 3, end: 5 });
+        assert_eq!(configuration.constraints(), vec![
+            Constraint::Distance { i: 1, j: 2, distance: 1.0 },
+            Constraint::Rigid { start: 3, end: 5 },
+        ]);
+        assert_eq!(configuration.constrained_degrees_of_freedom(), 2);
+
+        // Constraints follow the particles when molecules are merged
+        let _ = configuration.add_bond(0, 3);
+        assert_eq!(configuration.constraints(), vec![
+            Constraint::Rigid { start: 1, end: 3 },
+            Constraint::Distance { i: 3, j: 4, distance: 1.0 },
+        ]);
+
+        configuration.remove_molecule(0);
+        assert_eq!(configuration.constraints(), vec![
+            Constraint::Distance { i: 0, j: 1, distance: 1.0 },
+        ]);
+    }
+
+    #[test]
+    fn unwrapped_positions() {
+        let mut configuration = Configuration::new();
+        configuration.cell = UnitCell::cubic(10.0);
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.particles_mut().position[0] = Vector3D::new(12.0, -3.0, 5.0);
+        configuration.particles_mut().position[1] = Vector3D::new(1.0, 2.0, 3.0);
+
+        let cell = configuration.cell;
+        for mut molecule in configuration.molecules_mut() {
+            molecule.wrap(&cell);
+        }
+        assert_eq!(configuration.particles().position[0], Vector3D::new(2.0, 7.0, 5.0));
+        assert_eq!(configuration.particles().image[0], [1, -1, 0]);
+        assert_eq!(configuration.unwrapped_positions(), vec![
+            Vector3D::new(12.0, -3.0, 5.0),
+            Vector3D::new(1.0, 2.0, 3.0),
+        ]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut configuration = Configuration::new();
+        configuration.cell = UnitCell::cubic(10.0);
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.particles_mut().position[1] = Vector3D::new(1.0, 2.0, 3.0);
+        configuration.particles_mut().velocity[0] = Vector3D::new(-1.0, 0.5, 0.0);
+
+        let snapshot = configuration.snapshot();
+        assert_eq!(snapshot.cell(), &UnitCell::cubic(10.0));
+        assert_eq!(snapshot.positions()[1], Vector3D::new(1.0, 2.0, 3.0));
+        assert_eq!(snapshot.velocities()[0], Vector3D::new(-1.0, 0.5, 0.0));
+
+        configuration.cell = UnitCell::cubic(12.0);
+        configuration.particles_mut().position[1] = Vector3D::zero();
+        configuration.particles_mut().velocity[0] = Vector3D::zero();
+        configuration.restore(&snapshot);
+        assert_eq!(configuration.cell, UnitCell::cubic(10.0));
+        assert_eq!(configuration.particles().position[1], Vector3D::new(1.0, 2.0, 3.0));
+        assert_eq!(configuration.particles().velocity[0], Vector3D::new(-1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "can not restore a snapshot with a different number of particles")]
+    fn restore_different_size() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        let snapshot = configuration.snapshot();
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.restore(&snapshot);
+    }
+
+    #[test]
+    #[should_panic(expected = "all the particles in a constraint must be in the same molecule")]
+    fn constraint_different_molecules() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+        configuration.add_constraint(Constraint::Distance { i: 0, j: 1, distance: 1.0 });
+    }
+
     mod iterators {
         use super::super::*;
         use super::particle;
@@ -753,6 +1121,90 @@ mod tests {
         assert_eq!(configuration.bond_path(0, 5), BondPath::None);
     }
 
+    #[test]
+    fn impropers() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+
+        let mut formaldehyde = Molecule::new(particle("C"));
+        formaldehyde.add_particle_bonded_to(0, particle("O"));
+        formaldehyde.add_particle_bonded_to(0, particle("H"));
+        formaldehyde.add_particle_bonded_to(0, particle("H"));
+        configuration.add_molecule(formaldehyde);
+        configuration.add_molecule(Molecule::new(particle("Ar")));
+
+        configuration.guess_impropers();
+        assert_eq!(configuration.molecule(0).impropers().len(), 0);
+        assert_eq!(configuration.molecule(1).impropers().len(), 1);
+        assert!(configuration.molecule(1).impropers().contains(&Improper::new(1, 2, 3, 4)));
+
+        configuration.add_improper(2, 1, 3, 4);
+        assert_eq!(configuration.molecule(1).impropers().len(), 2);
+
+        // Impropers are kept when merging molecules
+        assert!(configuration.add_bond(4, 5).is_empty());
+        assert_eq!(configuration.molecule(1).impropers().len(), 2);
+
+        configuration.remove_molecule(0);
+        assert_eq!(configuration.molecule(0).impropers().len(), 2);
+        assert!(configuration.molecule(0).impropers().contains(&Improper::new(0, 1, 2, 3)));
+        assert!(configuration.molecule(0).impropers().contains(&Improper::new(1, 0, 2, 3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "all the particles in an improper dihedral angle must be in the same molecule")]
+    fn improper_different_molecules() {
+        let mut configuration = Configuration::new();
+        let mut molecule = Molecule::new(particle("C"));
+        molecule.add_particle_bonded_to(0, particle("O"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        configuration.add_molecule(molecule);
+        configuration.add_molecule(Molecule::new(particle("H")));
+
+        configuration.add_improper(0, 1, 2, 3);
+    }
+
+    #[test]
+    fn properties() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("C")));
+        configuration.add_molecule(Molecule::new(particle("H")));
+        configuration.add_molecule(Molecule::new(particle("O")));
+
+        configuration.add_property("order", Property::Scalar(vec![1.0, 2.0, 3.0]));
+        let vectors = vec![Vector3D::new(1.0, 0.0, 0.0); 3];
+        configuration.add_property("dipole", Property::Vector(vectors));
+        assert_eq!(configuration.properties().count(), 2);
+
+        // Values follow the particles when molecules are merged
+        let _ = configuration.add_bond(0, 2);
+        assert_eq!(configuration.particles().name[1], "O");
+        assert_eq!(configuration.property("order"), Some(&Property::Scalar(vec![1.0, 3.0, 2.0])));
+
+        configuration.remove_molecule(1);
+        assert_eq!(configuration.property("order"), Some(&Property::Scalar(vec![1.0, 3.0])));
+
+        configuration.add_molecule(Molecule::new(particle("H")));
+        assert_eq!(configuration.property("order"), Some(&Property::Scalar(vec![1.0, 3.0, 0.0])));
+        let dipole = configuration.property("dipole").and_then(Property::as_vector).unwrap();
+        assert_eq!(dipole[2], Vector3D::zero());
+
+        configuration.property_mut("order").and_then(Property::as_scalar_mut).unwrap()[2] = 4.0;
+        assert_eq!(configuration.property("order"), Some(&Property::Scalar(vec![1.0, 3.0, 4.0])));
+
+        assert!(configuration.remove_property("order").is_some());
+        assert_eq!(configuration.property("order"), None);
+        assert_eq!(configuration.properties().count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the 'order' property must contain one value for each particle")]
+    fn bad_property_size() {
+        let mut configuration = Configuration::new();
+        configuration.add_molecule(Molecule::new(particle("C")));
+        configuration.add_property("order", Property::Scalar(vec![1.0, 2.0]));
+    }
+
     #[test]
     fn add_bond_permutations() {
         let mut configuration = Configuration::new();
@@ -842,14 +1294,18 @@ mod tests {
             configuration.molecule(3).hash()
         );
 
-        // The water molecules
+        // The water molecules, the particles order does not matter
         assert_eq!(
             configuration.molecule(1).hash(),
             configuration.molecule(2).hash()
         );
-        assert_ne!(
+        assert_eq!(
             configuration.molecule(1).hash(),
             configuration.molecule(4).hash()
         );
+        assert_ne!(
+            configuration.molecule(0).hash(),
+            configuration.molecule(1).hash()
+        );
     }
 }
@@ -40,7 +40,7 @@ impl Bond {
 ///
 /// This structure ensure uniqueness of the `Angle` representation by enforcing
 /// `i < k`
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Angle {
     i: usize,
     j: usize,
@@ -83,7 +83,7 @@ impl Angle {
 
 
 /// A `Dihedral` angle formed by the particles at indexes `i`, `j`, `k` and `m`
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Dihedral {
     i: usize,
     j: usize,
@@ -136,6 +136,134 @@ impl Dihedral {
     }
 }
 
+/// An `Improper` dihedral angle formed by the particles at indexes `i`, `j`,
+/// `k` and `m`, where `i` is the central particle, bonded to the three others.
+///
+/// The value of the improper angle is the dihedral angle between the particles
+/// `i`, `j`, `k` and `m`. As changing the order of the particles changes the
+/// value of the angle, the order used at creation is preserved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Improper {
+    i: usize,
+    j: usize,
+    k: usize,
+    m: usize,
+}
+
+impl Improper {
+    /// Create a new Improper dihedral angle around the particle at index
+    /// `center`, with the particles at indexes `first`, `second` and `third`.
+    pub fn new(center: usize, first: usize, second: usize, third: usize) -> Improper {
+        assert_ne!(center, first);
+        assert_ne!(center, second);
+        assert_ne!(center, third);
+        assert_ne!(first, second);
+        assert_ne!(first, third);
+        assert_ne!(second, third);
+        Improper {
+            i: center,
+            j: first,
+            k: second,
+            m: third,
+        }
+    }
+
+    /// Get the central particle in the improper dihedral angle
+    #[inline]
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    /// Get the second particle in the improper dihedral angle
+    #[inline]
+    pub fn j(&self) -> usize {
+        self.j
+    }
+
+    /// Get the third particle in the improper dihedral angle
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Get the fourth particle in the improper dihedral angle
+    #[inline]
+    pub fn m(&self) -> usize {
+        self.m
+    }
+}
+
+/// A `DihedralPair` is formed by two consecutive dihedral angles sharing three
+/// particles: `i, j, k, m` and `j, k, m, n`. This is used for cross-terms
+/// depending on both dihedral angles, such as CMAP corrections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DihedralPair {
+    i: usize,
+    j: usize,
+    k: usize,
+    m: usize,
+    n: usize,
+}
+
+impl DihedralPair {
+    /// Create a new `DihedralPair` from the chain of bonded particles at
+    /// indexes `first`, `second`, `third`, `fourth` and `fifth`
+    pub fn new(
+        first: usize,
+        second: usize,
+        third: usize,
+        fourth: usize,
+        fifth: usize,
+    ) -> DihedralPair {
+        assert_ne!(first, second);
+        assert_ne!(second, third);
+        assert_ne!(third, fourth);
+        assert_ne!(fourth, fifth);
+        let (i, j, k, m, n) = if max(first, second) < max(fourth, fifth) {
+            (first, second, third, fourth, fifth)
+        } else {
+            (fifth, fourth, third, second, first)
+        };
+        DihedralPair {
+            i: i,
+            j: j,
+            k: k,
+            m: m,
+            n: n,
+        }
+    }
+
+    /// Get the first particle in the dihedral pair
+    #[inline]
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    /// Get the second particle in the dihedral pair
+    #[inline]
+    pub fn j(&self) -> usize {
+        self.j
+    }
+
+    /// Get the third particle in the dihedral pair
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Get the fourth particle in the dihedral pair
+    #[inline]
+    pub fn m(&self) -> usize {
+        self.m
+    }
+
+    /// Get the fifth particle in the dihedral pair
+    #[inline]
+    pub fn n(&self) -> usize {
+        self.n
+    }
+}
+
 
 bitflags! {
     /// The `BondDistances` bitflag encode the topological distance between
@@ -194,4 +322,36 @@ mod test {
         assert_eq!(dihedral.k, 6);
         assert_eq!(dihedral.m, 8);
     }
+
+    #[test]
+    fn improper() {
+        let improper = Improper::new(3, 8, 1, 5);
+        assert_eq!(improper.i, 3);
+        assert_eq!(improper.j, 8);
+        assert_eq!(improper.k, 1);
+        assert_eq!(improper.m, 5);
+    }
+
+    #[test]
+    fn dihedral_pair() {
+        let pair = DihedralPair::new(9, 8, 7, 6, 0);
+        assert_eq!(pair.i, 0);
+        assert_eq!(pair.j, 6);
+        assert_eq!(pair.k, 7);
+        assert_eq!(pair.m, 8);
+        assert_eq!(pair.n, 9);
+
+        let pair = DihedralPair::new(0, 1, 2, 3, 4);
+        assert_eq!(pair.i, 0);
+        assert_eq!(pair.j, 1);
+        assert_eq!(pair.k, 2);
+        assert_eq!(pair.m, 3);
+        assert_eq!(pair.n, 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn improper_duplicated() {
+        let _ = Improper::new(3, 8, 1, 8);
+    }
 }
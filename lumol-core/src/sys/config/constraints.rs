@@ -0,0 +1,174 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+/// A holonomic `Constraint` between particles, removing some degrees of
+/// freedom from the system.
+///
+/// Constraints are stored with the molecule containing the particles, and
+/// follow the particles when molecules are added, merged or removed in a
+/// [`Configuration`]. Different constraints should not constrain the same
+/// degrees of freedom, for example a `Distance` constraint between two
+/// particles inside a `Rigid` fragment.
+///
+/// [`Configuration`]: struct.Configuration.html
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::Constraint;
+///
+/// let constraint = Constraint::Distance { i: 0, j: 1, distance: 1.0 };
+/// assert_eq!(constraint.removed_degrees_of_freedom(), 1);
+/// assert!(constraint.contains(1));
+///
+/// let constraint = Constraint::Rigid { start: 0, end: 3 };
+/// assert_eq!(constraint.removed_degrees_of_freedom(), 3);
+/// assert!(!constraint.contains(3));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    /// Fixed `distance` between the particles at indexes `i` and `j`
+    Distance {
+        /// Index of the first particle
+        i: usize,
+        /// Index of the second particle
+        j: usize,
+        /// Value of the constrained distance
+        distance: f64,
+    },
+    /// Fixed `angle` between the particles at indexes `i`, `j` and `k`, with
+    /// `j` at the center of the angle
+    Angle {
+        /// Index of the first particle
+        i: usize,
+        /// Index of the central particle
+        j: usize,
+        /// Index of the third particle
+        k: usize,
+        /// Value of the constrained angle, in radians
+        angle: f64,
+    },
+    /// All the particles with indexes in `start..end` move together as a
+    /// single rigid body
+    Rigid {
+        /// Index of the first particle in the rigid fragment
+        start: usize,
+        /// Index after the last particle in the rigid fragment
+        end: usize,
+    },
+}
+
+impl Constraint {
+    /// Check if the particle at index `particle` is part of this constraint
+    pub fn contains(&self, particle: usize) -> bool {
+        match *self {
+            Constraint::Distance { i, j, .. } => particle == i || particle == j,
+            Constraint::Angle { i, j, k, .. } => particle == i || particle == j || particle == k,
+            Constraint::Rigid { start, end } => start <= particle && particle < end,
+        }
+    }
+
+    /// Get the indexes of the particles in this constraint
+    pub fn particles(&self) -> Vec<usize> {
+        match *self {
+            Constraint::Distance { i, j, .. } => vec![i, j],
+            Constraint::Angle { i, j, k, .. } => vec![i, j, k],
+            Constraint::Rigid { start, end } => (start..end).collect(),
+        }
+    }
+
+    /// Get the number of degrees of freedom removed by this constraint.
+    ///
+    /// A rigid fragment containing `n` particles only keeps 6 degrees of
+    /// freedom (5 if `n = 2`), and thus remove `3 n - 6` degrees of freedom.
+    /// Fragments with more than two particles are assumed to be non-linear.
+    pub fn removed_degrees_of_freedom(&self) -> usize {
+        self.removed_degrees_of_freedom_in(3)
+    }
+
+    /// Get the number of degrees of freedom removed by this constraint in a
+    /// system with the given number of spatial `dimensions`. In two
+    /// dimensions, a rigid fragment containing `n` particles only keeps 3
+    /// degrees of freedom, and thus remove `2 n - 3` degrees of freedom.
+    pub fn removed_degrees_of_freedom_in(&self, dimensions: usize) -> usize {
+        match *self {
+            Constraint::Distance { .. } | Constraint::Angle { .. } => 1,
+            Constraint::Rigid { start, end } => {
+                match (end - start, dimensions) {
+                    (0, _) | (1, _) => 0,
+                    (2, _) => 1,
+                    (n, 2) => 2 * n - 3,
+                    (n, _) => 3 * n - 6,
+                }
+            }
+        }
+    }
+
+    /// Replace the indexes of the particles in this constraint by the result
+    /// of the `map` function. For rigid fragments, `map` is also applied to
+    /// the end of the range.
+    pub(crate) fn map_indexes<F: Fn(usize) -> usize>(&mut self, map: F) {
+        match *self {
+            Constraint::Distance { ref mut i, ref mut j, .. } => {
+                *i = map(*i);
+                *j = map(*j);
+            }
+            Constraint::Angle { ref mut i, ref mut j, ref mut k, .. } => {
+                *i = map(*i);
+                *j = map(*j);
+                *k = map(*k);
+            }
+            Constraint::Rigid { ref mut start, ref mut end } => {
+                *start = map(*start);
+                *end = map(*end);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particles() {
+        let constraint = Constraint::Angle { i: 3, j: 1, k: 4, angle: 1.2 };
+        assert_eq!(constraint.particles(), vec![3, 1, 4]);
+        assert!(constraint.contains(1));
+        assert!(!constraint.contains(2));
+
+        let constraint = Constraint::Rigid { start: 2, end: 5 };
+        assert_eq!(constraint.particles(), vec![2, 3, 4]);
+        assert!(constraint.contains(2));
+        assert!(!constraint.contains(5));
+    }
+
+    #[test]
+    fn removed_degrees_of_freedom() {
+        let constraint = Constraint::Distance { i: 0, j: 1, distance: 1.0 };
+        assert_eq!(constraint.removed_degrees_of_freedom(), 1);
+        let constraint = Constraint::Angle { i: 0, j: 1, k: 2, angle: 1.0 };
+        assert_eq!(constraint.removed_degrees_of_freedom(), 1);
+
+        assert_eq!(Constraint::Rigid { start: 3, end: 4 }.removed_degrees_of_freedom(), 0);
+        assert_eq!(Constraint::Rigid { start: 3, end: 5 }.removed_degrees_of_freedom(), 1);
+        assert_eq!(Constraint::Rigid { start: 3, end: 6 }.removed_degrees_of_freedom(), 3);
+        assert_eq!(Constraint::Rigid { start: 3, end: 8 }.removed_degrees_of_freedom(), 9);
+
+        let constraint = Constraint::Rigid { start: 3, end: 8 };
+        assert_eq!(constraint.removed_degrees_of_freedom_in(2), 7);
+        let constraint = Constraint::Rigid { start: 3, end: 5 };
+        assert_eq!(constraint.removed_degrees_of_freedom_in(2), 1);
+    }
+
+    #[test]
+    fn map_indexes() {
+        let mut constraint = Constraint::Angle { i: 3, j: 1, k: 4, angle: 1.2 };
+        constraint.map_indexes(|i| i + 5);
+        assert_eq!(constraint, Constraint::Angle { i: 8, j: 6, k: 9, angle: 1.2 });
+
+        let mut constraint = Constraint::Rigid { start: 2, end: 5 };
+        constraint.map_indexes(|i| if i > 3 { i - 1 } else { i });
+        assert_eq!(constraint, Constraint::Rigid { start: 2, end: 4 });
+    }
+}
@@ -6,6 +6,9 @@
 mod mass;
 pub use self::mass::get_atomic_mass;
 
+mod radii;
+pub use self::radii::get_covalent_radius;
+
 mod particles;
 pub use self::particles::{Particle, ParticleKind};
 pub use self::particles::{ParticleRef, ParticleRefMut};
@@ -19,15 +22,24 @@ mod cells;
 pub use self::cells::{CellShape, UnitCell};
 
 mod connect;
-pub use self::connect::{Angle, Bond, Dihedral};
+pub use self::connect::{Angle, Bond, Dihedral, DihedralPair, Improper};
 pub use self::connect::BondDistances;
 
+mod constraints;
+pub use self::constraints::Constraint;
+
 mod bonding;
 pub use self::bonding::Bonding;
 
 mod molecules;
 pub use self::molecules::{Molecule, MoleculeRef, MoleculeRefMut, MoleculeHash};
 
+mod template;
+pub use self::template::MoleculeTemplate;
+
+mod properties;
+pub use self::properties::Property;
+
 mod configuration;
-pub use self::configuration::Configuration;
+pub use self::configuration::{Configuration, Snapshot};
 pub use self::configuration::{MoleculeIter, MoleculeIterMut};
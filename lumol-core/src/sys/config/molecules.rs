@@ -3,11 +3,11 @@
 
 use std::ops::Deref;
 use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::hash::Hasher;
 
 use sys::{Particle, ParticleVec, ParticleSlice, ParticleSliceMut};
-use sys::{Bonding, UnitCell};
-use types::Vector3D;
+use sys::{Bonding, Constraint, Improper, UnitCell};
+use types::{Matrix3, Vector3D};
 
 /// A molecule hash allow to identify a molecule from its atoms and bonds, and
 /// to know wether two molecules are the same without checking each atom and
@@ -124,6 +124,25 @@ impl Molecule {
     pub fn add_bond(&mut self, i: usize, j: usize) {
         self.bonding.add_bond(i, j);
     }
+
+    /// Add an improper dihedral angle around the particle at index `center`
+    /// in this molecule, with the particles `j`, `k` and `m`.
+    ///
+    /// # Panics
+    ///
+    /// If any of the particles is not in this molecule.
+    pub fn add_improper(&mut self, center: usize, j: usize, k: usize, m: usize) {
+        self.bonding.add_improper(Improper::new(center, j, k, m));
+    }
+
+    /// Add a holonomic `constraint` between particles in this molecule.
+    ///
+    /// # Panics
+    ///
+    /// If any of the particles in the constraint is not in this molecule.
+    pub fn add_constraint(&mut self, constraint: Constraint) {
+        self.bonding.add_constraint(constraint);
+    }
 }
 
 impl Deref for Molecule {
@@ -155,10 +174,14 @@ impl<'a> MoleculeRef<'a> {
         self.particles
     }
 
-    /// Copies `self` into a new `Molecule`
+    /// Copies `self` into a new `Molecule`. The particles indexes in the new
+    /// molecule start at 0.
     pub fn to_owned(&self) -> Molecule {
+        let mut bonding = self.bonding.clone();
+        let start = bonding.start() as isize;
+        bonding.translate_by(-start);
         Molecule {
-            bonding: self.bonding.clone(),
+            bonding: bonding,
             particles: self.particles.to_vec(),
         }
     }
@@ -207,21 +230,31 @@ impl<'a> MoleculeRefMut<'a> {
         // copied
         ParticleSliceMut {
             name: &mut self.particles.name,
+            atom_type: &mut self.particles.atom_type,
             mass: &mut self.particles.mass,
             kind: &mut self.particles.kind,
             charge: &mut self.particles.charge,
             position: &mut self.particles.position,
             velocity: &mut self.particles.velocity,
+            image: &mut self.particles.image,
+            residue: &mut self.particles.residue,
+            residue_id: &mut self.particles.residue_id,
+            chain: &mut self.particles.chain,
+            frozen: &mut self.particles.frozen,
         }
     }
 
-    /// Copies `self` into a new `Molecule`
+    /// Copies `self` into a new `Molecule`. The particles indexes in the new
+    /// molecule start at 0.
     pub fn to_owned(&self) -> Molecule {
         // This can not be a `ToOwned` implementation, as ToOwned requires
         // `Borrow`, and `Borrow` requires a reference, not a reference
         // wrapper.
+        let mut bonding = self.bonding.clone();
+        let start = bonding.start() as isize;
+        bonding.translate_by(-start);
         Molecule {
-            bonding: self.bonding.clone(),
+            bonding: bonding,
             particles: self.particles.to_vec(),
         }
     }
@@ -244,6 +277,12 @@ macro_rules! impl_on {
 }
 
 impl_on!(Molecule, MoleculeRef<'a>, MoleculeRefMut<'a>, => {
+    /// Return the net charge of a molecule, i.e. the sum of the charges of
+    /// all the particles in the molecule
+    pub fn charge(&self) -> f64 {
+        self.particles.charge.iter().sum()
+    }
+
     /// Return the center-of-mass of a molecule
     ///
     /// # Warning
@@ -261,16 +300,87 @@ impl_on!(Molecule, MoleculeRef<'a>, MoleculeRefMut<'a>, => {
         com / total_mass
     }
 
-    /// Get a hash of this molecule. This is a hash of the particles names (in
-    /// order), and the set of bonds in the molecule. This means that two
-    /// molecules will have the same type if and only if they contains the same
-    /// atoms and the same bonds, **in the same order**.
+    /// Return the inertia tensor of a molecule, computed around its
+    /// center-of-mass.
+    ///
+    /// # Warning
+    ///
+    /// This function does not check for the particles' positions' nearest
+    /// images. To use this function properly, make sure that all particles of
+    /// the molecule are adjacent.
+    pub fn inertia_tensor(&self) -> Matrix3 {
+        let com = self.center_of_mass();
+        let mut inertia = Matrix3::zero();
+        for (&mass, position) in soa_zip!(&self.particles, [mass, position]) {
+            let delta = position - com;
+            inertia += mass * (delta.norm2() * Matrix3::one() - delta.tensorial(&delta));
+        }
+        return inertia;
+    }
+
+    /// Return the principal moments of inertia of a molecule, sorted in
+    /// increasing order, together with the corresponding principal axes,
+    /// stored as the columns of the returned matrix.
+    ///
+    /// # Warning
+    ///
+    /// This function does not check for the particles' positions' nearest
+    /// images. To use this function properly, make sure that all particles of
+    /// the molecule are adjacent.
+    pub fn principal_axes(&self) -> (Vector3D, Matrix3) {
+        self.inertia_tensor().symmetric_eigen()
+    }
+
+    /// Return the angular momentum of a molecule, computed around its
+    /// center-of-mass.
+    ///
+    /// # Warning
+    ///
+    /// This function does not check for the particles' positions' nearest
+    /// images. To use this function properly, make sure that all particles of
+    /// the molecule are adjacent.
+    pub fn angular_momentum(&self) -> Vector3D {
+        let com = self.center_of_mass();
+        let mut momentum = Vector3D::zero();
+        let particles = &self.particles;
+        for (&mass, position, velocity) in soa_zip!(particles, [mass, position, velocity]) {
+            momentum += mass * ((position - com) ^ velocity);
+        }
+        return momentum;
+    }
+
+    /// Return the angular velocity of a molecule around its center-of-mass,
+    /// *i.e.* the solution of $I \omega = L$ with $I$ the inertia tensor and
+    /// $L$ the angular momentum. The components of the angular velocity along
+    /// principal axes with a vanishing moment of inertia (the molecular axis
+    /// of linear molecules, or all axes for single particles) are set to
+    /// zero.
+    ///
+    /// # Warning
+    ///
+    /// This function does not check for the particles' positions' nearest
+    /// images. To use this function properly, make sure that all particles of
+    /// the molecule are adjacent.
+    pub fn angular_velocity(&self) -> Vector3D {
+        let (moments, axes) = self.principal_axes();
+        let momentum = self.angular_momentum();
+        let mut omega = Vector3D::zero();
+        for i in 0..3 {
+            if moments[i] > 1e-12 * moments[2] {
+                let axis = Vector3D::new(axes[0][i], axes[1][i], axes[2][i]);
+                omega += (axis * momentum) / moments[i] * axis;
+            }
+        }
+        return omega;
+    }
+
+    /// Get a hash of this molecule. This is a hash of the molecular graph,
+    /// built from the particles types and the bonds in the molecule. Two
+    /// molecules containing the same atoms and the same bonds will have the
+    /// same type, whatever the order of the particles in the molecules.
     pub fn hash(&self) -> MoleculeHash {
         let mut hasher = DefaultHasher::new();
-        self.bonding.hash(&mut hasher);
-        for name in self.particles().name {
-            name.hash(&mut hasher);
-        }
+        self.bonding.hash(self.particles().atom_type, &mut hasher);
         MoleculeHash(hasher.finish())
     }
 });
@@ -287,9 +397,20 @@ impl_on!(Molecule, MoleculeRefMut<'a>, => {
         let mut com_wrapped = com;
         cell.wrap_vector(&mut com_wrapped);
         let delta = com_wrapped - com;
+        if delta == Vector3D::zero() {
+            return;
+        }
+
+        // The particles are moved by a cell vector, keep track of the
+        // corresponding periodic image to be able to unwrap the positions.
+        let shift = cell.fractional(&delta);
+        let shift = [shift[0].round() as i32, shift[1].round() as i32, shift[2].round() as i32];
         // iterate over all positions and move them accordingly
-        for position in self.particles_mut().position.iter_mut() {
+        for (position, image) in soa_zip!(self.particles_mut(), [mut position, mut image]) {
             *position += delta;
+            for k in 0..3 {
+                image[k] -= shift[k];
+            }
         }
     }
 });
@@ -326,6 +447,115 @@ mod tests {
         assert_eq!(molecule.center_of_mass(), Vector3D::new(0.5, 0.0, 0.0));
     }
 
+    #[test]
+    fn charge() {
+        let mut molecule = Molecule::new(particle("O"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        assert_eq!(molecule.charge(), 0.0);
+
+        molecule.particles_mut().charge[0] = -1.0;
+        molecule.particles_mut().charge[1] = 0.5;
+        assert_eq!(molecule.charge(), -0.5);
+        assert_eq!(molecule.as_ref().charge(), -0.5);
+    }
+
+    #[test]
+    fn hash() {
+        // Ethanol heavy atoms, C-C-O
+        let mut ethanol = Molecule::new(particle("C"));
+        ethanol.add_particle_bonded_to(0, particle("C"));
+        ethanol.add_particle_bonded_to(1, particle("O"));
+
+        let mut reordered = Molecule::new(particle("O"));
+        reordered.add_particle_bonded_to(0, particle("C"));
+        reordered.add_particle_bonded_to(1, particle("C"));
+        assert_eq!(ethanol.hash(), reordered.hash());
+
+        // Dimethyl ether heavy atoms, C-O-C
+        let mut ether = Molecule::new(particle("C"));
+        ether.add_particle_bonded_to(0, particle("O"));
+        ether.add_particle_bonded_to(1, particle("C"));
+        assert_ne!(ethanol.hash(), ether.hash());
+
+        // Linear and branched butane carbon skeletons
+        let mut linear = Molecule::new(particle("C"));
+        for i in 0..3 {
+            linear.add_particle_bonded_to(i, particle("C"));
+        }
+        let mut branched = Molecule::new(particle("C"));
+        for _ in 0..3 {
+            branched.add_particle_bonded_to(0, particle("C"));
+        }
+        assert_ne!(linear.hash(), branched.hash());
+    }
+
+    #[test]
+    fn inertia() {
+        let mut molecule = Molecule::new(particle("O"));
+        molecule.add_particle_bonded_to(0, particle("O"));
+        molecule.add_particle_bonded_to(1, particle("O"));
+        let mass = molecule.particles().mass[0];
+
+        // Linear molecule along the x axis
+        molecule.particles_mut().position[0] = Vector3D::new(-1.0, 2.0, 0.0);
+        molecule.particles_mut().position[1] = Vector3D::new(0.0, 2.0, 0.0);
+        molecule.particles_mut().position[2] = Vector3D::new(1.0, 2.0, 0.0);
+
+        let inertia = molecule.inertia_tensor();
+        let expected = Matrix3::new([
+            [0.0, 0.0, 0.0],
+            [0.0, 2.0 * mass, 0.0],
+            [0.0, 0.0, 2.0 * mass],
+        ]);
+        assert_relative_eq!(inertia, expected, epsilon = 1e-12);
+
+        let (moments, axes) = molecule.principal_axes();
+        let expected = Vector3D::new(0.0, 2.0 * mass, 2.0 * mass);
+        assert_relative_eq!(moments, expected, epsilon = 1e-12);
+        assert_relative_eq!(f64::abs(axes[0][0]), 1.0, epsilon = 1e-12);
+
+        // Planar molecule in the xy plane
+        molecule.particles_mut().position[2] = Vector3D::new(0.0, 3.0, 0.0);
+        let (moments, axes) = molecule.principal_axes();
+        assert_relative_eq!(moments[0] + moments[1], moments[2], epsilon = 1e-12);
+        assert_relative_eq!(f64::abs(axes[2][2]), 1.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn angular_velocity() {
+        let mut molecule = Molecule::new(particle("O"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        molecule.add_particle_bonded_to(0, particle("H"));
+        molecule.particles_mut().position[0] = Vector3D::new(0.0, 0.0, 0.0);
+        molecule.particles_mut().position[1] = Vector3D::new(0.8, 0.6, 0.0);
+        molecule.particles_mut().position[2] = Vector3D::new(-0.8, 0.6, 0.3);
+
+        // Rigid rotation around the center-of-mass, and a translation
+        let omega = Vector3D::new(0.3, -1.2, 0.7);
+        let translation = Vector3D::new(2.0, 0.0, 0.0);
+        let com = molecule.center_of_mass();
+        for i in 0..3 {
+            let position = molecule.particles().position[i];
+            molecule.particles_mut().velocity[i] = (omega ^ (position - com)) + translation;
+        }
+
+        let momentum = molecule.angular_momentum();
+        assert_relative_eq!(momentum, molecule.inertia_tensor() * omega, epsilon = 1e-12);
+        assert_relative_eq!(molecule.angular_velocity(), omega, epsilon = 1e-12);
+
+        // The rotation around the axis of a linear molecule is undefined
+        molecule.particles_mut().position[2] = Vector3D::new(-0.8, -0.6, 0.0);
+        let omega = Vector3D::new(0.0, 0.0, 0.5);
+        let com = molecule.center_of_mass();
+        for i in 0..3 {
+            let position = molecule.particles().position[i];
+            let axis = Vector3D::new(0.8, 0.6, 0.0);
+            molecule.particles_mut().velocity[i] = (omega ^ (position - com)) + 0.4 * axis;
+        }
+        assert_relative_eq!(molecule.angular_velocity(), omega, epsilon = 1e-12);
+    }
+
     #[test]
     fn test_wrap_molecule() {
         let mut molecule = Molecule::new(particle("O"));
@@ -337,6 +567,14 @@ mod tests {
 
         assert_eq!(molecule.particles().position[0], Vector3D::new(3.0, 0.0, 0.0));
         assert_eq!(molecule.particles().position[1], Vector3D::new(5.0, 0.0, 0.0));
-        assert_eq!(molecule.center_of_mass(), Vector3D::new(4.0, 0.0, 0.0))
+        assert_eq!(molecule.center_of_mass(), Vector3D::new(4.0, 0.0, 0.0));
+        assert_eq!(molecule.particles().image[0], [-1, 0, 0]);
+        assert_eq!(molecule.particles().image[1], [-1, 0, 0]);
+
+        molecule.particles_mut().position[0] = Vector3D::new(1.0, -7.0, 12.0);
+        molecule.particles_mut().position[1] = Vector3D::new(1.0, -7.0, 12.0);
+        molecule.wrap(&UnitCell::cubic(5.0));
+        assert_eq!(molecule.particles().position[0], Vector3D::new(1.0, 3.0, 2.0));
+        assert_eq!(molecule.particles().image[0], [-1, -2, 2]);
     }
 }
@@ -5,7 +5,7 @@ use types::Vector3D;
 
 use std::fmt;
 
-/// A particle kind. Particles with the same name will have the same kind. This
+/// A particle kind. Particles with the same type will have the same kind. This
 /// is used for faster potential lookup.
 #[derive(Clone, Copy, Hash, PartialOrd, Ord, PartialEq, Eq, Debug)]
 pub struct ParticleKind(pub u32);
@@ -30,6 +30,10 @@ impl fmt::Display for ParticleKind {
 pub struct Particle {
     /// Particle name.
     pub name: String,
+    /// Particle type, used to associate the particle with interactions. This
+    /// defaults to the particle name, and can be changed to use different
+    /// interactions for particles with the same name.
+    pub atom_type: String,
     /// Particle kind, an index for potentials lookup
     pub kind: ParticleKind,
     /// Particle charge
@@ -40,29 +44,51 @@ pub struct Particle {
     pub position: Vector3D,
     /// Particle velocity, if needed
     pub velocity: Vector3D,
+    /// Number of times the particle was moved to a periodic image along each
+    /// of the unit cell vectors, when wrapping molecules in the unit cell.
+    /// This is used to compute unwrapped positions.
+    pub image: [i32; 3],
+    /// Name of the residue containing this particle, if any
+    pub residue: Option<String>,
+    /// Identifier of the residue containing this particle, if any
+    pub residue_id: Option<u64>,
+    /// Identifier of the chain containing this particle, if any
+    pub chain: Option<String>,
+    /// Is this particle frozen? Frozen particles are held fixed during the
+    /// simulations, and are not moved by integrators, minimizers or Monte
+    /// Carlo moves.
+    pub frozen: bool,
 }
 
 impl Particle {
     /// Create a new `Particle` from a `name`, setting the mass to the atomic
     /// mass if the `name` can be found in the periodic table. The charge,
-    /// position, and velocity are set to 0.
+    /// position, and velocity are set to 0, and the particle is not part of
+    /// any residue or chain. The particle is not frozen.
     pub fn new<S: Into<String>>(name: S) -> Particle {
         Particle::with_position(name, Vector3D::zero())
     }
 
     /// Create a new `Particle` from a `name` and a `position`, setting the
     /// mass to the atomic mass if the `name` can be found in the periodic
-    /// table. The charge and velocity are set to 0.
+    /// table. The particle type is set to the `name`, and the charge and
+    /// velocity are set to 0.
     pub fn with_position<S: Into<String>>(name: S, position: Vector3D) -> Particle {
         let name = name.into();
         let mass = get_atomic_mass(&name).unwrap_or(0.0);
         Particle {
+            atom_type: name.clone(),
             name: name,
             mass: mass,
             charge: 0.0,
             kind: ParticleKind::invalid(),
             position: position,
             velocity: Vector3D::zero(),
+            image: [0, 0, 0],
+            residue: None,
+            residue_id: None,
+            chain: None,
+            frozen: false,
         }
     }
 }
@@ -82,12 +108,17 @@ mod tests {
     fn name() {
         let particle = Particle::new("");
         assert_eq!(particle.name, "");
+        assert_eq!(particle.atom_type, "");
 
         assert_eq!(particle.mass, 0.0);
         assert_eq!(particle.charge, 0.0);
         assert_eq!(particle.kind, ParticleKind::invalid());
         assert_eq!(particle.position, Vector3D::new(0.0, 0.0, 0.0));
         assert_eq!(particle.velocity, Vector3D::new(0.0, 0.0, 0.0));
+        assert_eq!(particle.residue, None);
+        assert_eq!(particle.residue_id, None);
+        assert_eq!(particle.chain, None);
+        assert!(!particle.frozen);
     }
 
     #[test]
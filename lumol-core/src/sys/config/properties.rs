@@ -0,0 +1,164 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use types::Vector3D;
+
+/// Values of a custom per-particle property, with one value for each
+/// particle in a [`Configuration`].
+///
+/// Custom properties can be used by algorithms to store additional data
+/// about the particles, like local order parameters or per-particle
+/// energies. They are kept consistent with the particles when molecules are
+/// added, removed or merged in the configuration, and are written to the
+/// trajectory files.
+///
+/// [`Configuration`]: struct.Configuration.html
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, Property};
+///
+/// let mut system = System::new();
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+///
+/// system.add_property("order", Property::Scalar(vec![0.3, 0.7]));
+/// assert_eq!(system.property("order").and_then(Property::as_scalar), Some(&[0.3, 0.7][..]));
+///
+/// // New particles get a default value
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+/// assert_eq!(system.property("order").unwrap().len(), 3);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Property {
+    /// Scalar property, with one `f64` value for each particle. The default
+    /// value for new particles is 0.
+    Scalar(Vec<f64>),
+    /// Vector property, with one `Vector3D` value for each particle. The
+    /// default value for new particles is the null vector.
+    Vector(Vec<Vector3D>),
+}
+
+impl Property {
+    /// Get the number of values in this property
+    pub fn len(&self) -> usize {
+        match *self {
+            Property::Scalar(ref values) => values.len(),
+            Property::Vector(ref values) => values.len(),
+        }
+    }
+
+    /// Check if this property does not contain any value
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the values of this property if it is a scalar property, or
+    /// `None` otherwise
+    pub fn as_scalar(&self) -> Option<&[f64]> {
+        match *self {
+            Property::Scalar(ref values) => Some(values),
+            Property::Vector(_) => None,
+        }
+    }
+
+    /// Get mutable access to the values of this property if it is a scalar
+    /// property, or `None` otherwise
+    pub fn as_scalar_mut(&mut self) -> Option<&mut [f64]> {
+        match *self {
+            Property::Scalar(ref mut values) => Some(values),
+            Property::Vector(_) => None,
+        }
+    }
+
+    /// Get the values of this property if it is a vector property, or
+    /// `None` otherwise
+    pub fn as_vector(&self) -> Option<&[Vector3D]> {
+        match *self {
+            Property::Vector(ref values) => Some(values),
+            Property::Scalar(_) => None,
+        }
+    }
+
+    /// Get mutable access to the values of this property if it is a vector
+    /// property, or `None` otherwise
+    pub fn as_vector_mut(&mut self) -> Option<&mut [Vector3D]> {
+        match *self {
+            Property::Vector(ref mut values) => Some(values),
+            Property::Scalar(_) => None,
+        }
+    }
+
+    /// Add `n` default values at the end of this property
+    pub(crate) fn extend_default(&mut self, n: usize) {
+        match *self {
+            Property::Scalar(ref mut values) => values.extend(vec![0.0; n]),
+            Property::Vector(ref mut values) => values.extend(vec![Vector3D::zero(); n]),
+        }
+    }
+
+    /// Remove the value at index `i` in this property
+    pub(crate) fn remove(&mut self, i: usize) {
+        match *self {
+            Property::Scalar(ref mut values) => {
+                let _ = values.remove(i);
+            }
+            Property::Vector(ref mut values) => {
+                let _ = values.remove(i);
+            }
+        }
+    }
+
+    /// Move the value at index `from` to index `to`, shifting the values in
+    /// between
+    pub(crate) fn move_value(&mut self, from: usize, to: usize) {
+        match *self {
+            Property::Scalar(ref mut values) => {
+                let value = values.remove(from);
+                values.insert(to, value);
+            }
+            Property::Vector(ref mut values) => {
+                let value = values.remove(from);
+                values.insert(to, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access() {
+        let mut property = Property::Scalar(vec![1.0, 2.0]);
+        assert_eq!(property.len(), 2);
+        assert_eq!(property.as_scalar(), Some(&[1.0, 2.0][..]));
+        assert_eq!(property.as_vector(), None);
+        property.as_scalar_mut().unwrap()[0] = 3.0;
+        assert_eq!(property, Property::Scalar(vec![3.0, 2.0]));
+
+        let mut property = Property::Vector(vec![Vector3D::new(1.0, 2.0, 3.0)]);
+        assert_eq!(property.as_scalar(), None);
+        property.as_vector_mut().unwrap()[0][1] = 5.0;
+        assert_eq!(property.as_vector(), Some(&[Vector3D::new(1.0, 5.0, 3.0)][..]));
+    }
+
+    #[test]
+    fn update() {
+        let mut property = Property::Scalar(vec![1.0, 2.0, 3.0]);
+        property.extend_default(2);
+        assert_eq!(property, Property::Scalar(vec![1.0, 2.0, 3.0, 0.0, 0.0]));
+
+        property.remove(1);
+        assert_eq!(property, Property::Scalar(vec![1.0, 3.0, 0.0, 0.0]));
+
+        property.move_value(0, 2);
+        assert_eq!(property, Property::Scalar(vec![3.0, 0.0, 1.0, 0.0]));
+
+        let mut property = Property::Vector(vec![Vector3D::new(1.0, 0.0, 0.0)]);
+        property.extend_default(1);
+        assert_eq!(property.as_vector().unwrap()[1], Vector3D::zero());
+    }
+}
@@ -0,0 +1,93 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Covalent radii for the elements
+
+/// Covalent radii in A, from Cordero et al. "Covalent radii revisited",
+/// Dalton Trans. (2008) 2832-2838. The low spin values are used for Mn and
+/// Fe, and the sp3 value for C.
+static COVALENT_RADII: &'static [(&'static str, f64)] = &[
+    ("H", 0.31),
+    ("He", 0.28),
+    ("Li", 1.28),
+    ("Be", 0.96),
+    ("B", 0.84),
+    ("C", 0.76),
+    ("N", 0.71),
+    ("O", 0.66),
+    ("F", 0.57),
+    ("Ne", 0.58),
+    ("Na", 1.66),
+    ("Mg", 1.41),
+    ("Al", 1.21),
+    ("Si", 1.11),
+    ("P", 1.07),
+    ("S", 1.05),
+    ("Cl", 1.02),
+    ("Ar", 1.06),
+    ("K", 2.03),
+    ("Ca", 1.76),
+    ("Sc", 1.70),
+    ("Ti", 1.60),
+    ("V", 1.53),
+    ("Cr", 1.39),
+    ("Mn", 1.39),
+    ("Fe", 1.32),
+    ("Co", 1.26),
+    ("Ni", 1.24),
+    ("Cu", 1.32),
+    ("Zn", 1.22),
+    ("Ga", 1.22),
+    ("Ge", 1.20),
+    ("As", 1.19),
+    ("Se", 1.20),
+    ("Br", 1.20),
+    ("Kr", 1.16),
+    ("Rb", 2.20),
+    ("Sr", 1.95),
+    ("Y", 1.90),
+    ("Zr", 1.75),
+    ("Nb", 1.64),
+    ("Mo", 1.54),
+    ("Tc", 1.47),
+    ("Ru", 1.46),
+    ("Rh", 1.42),
+    ("Pd", 1.39),
+    ("Ag", 1.45),
+    ("Cd", 1.44),
+    ("In", 1.42),
+    ("Sn", 1.39),
+    ("Sb", 1.39),
+    ("Te", 1.38),
+    ("I", 1.39),
+    ("Xe", 1.40),
+];
+
+/// Get the covalent radius of the element with the given atomic `name`
+///
+/// # Example
+///
+/// ```
+/// # use lumol_core::sys::get_covalent_radius;
+/// assert_eq!(get_covalent_radius("C"), Some(0.76));
+/// assert_eq!(get_covalent_radius("Ow"), None);
+/// ```
+pub fn get_covalent_radius(name: &str) -> Option<f64> {
+    for (symbol, radius) in COVALENT_RADII {
+        if name == *symbol {
+            return Some(*radius);
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn radius() {
+        assert_eq!(get_covalent_radius("O"), Some(0.66));
+        assert_eq!(get_covalent_radius("HOH"), None);
+    }
+}
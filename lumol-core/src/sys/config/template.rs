@@ -0,0 +1,304 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use sys::{Bonding, Constraint, Molecule, Particle};
+use types::Vector3D;
+
+/// A `MoleculeTemplate` describes a molecule once, with its particles,
+/// charges, geometry and bonds, and can then be used to create as many
+/// copies of this molecule as needed.
+///
+/// The templates are usually used with [`System::add_molecules`] to fill a
+/// system with multiple copies of the same molecule.
+///
+/// [`System::add_molecules`]: struct.System.html#method.add_molecules
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, UnitCell, MoleculeTemplate};
+/// use lumol_core::types::Vector3D;
+///
+/// let water = MoleculeTemplate::new()
+///     .particle("O", Vector3D::new(0.0, 0.0, 0.0), -0.8476)
+///     .particle("H", Vector3D::new(0.8164, 0.5773, 0.0), 0.4238)
+///     .particle("H", Vector3D::new(-0.8164, 0.5773, 0.0), 0.4238)
+///     .bond(0, 1)
+///     .bond(0, 2);
+/// assert_eq!(water.size(), 3);
+///
+/// let mut system = System::with_cell(UnitCell::cubic(20.0));
+/// system.add_molecules(&water, 10);
+/// assert_eq!(system.molecules().count(), 10);
+/// assert_eq!(system.size(), 30);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MoleculeTemplate {
+    /// The particles in the template
+    particles: Vec<Particle>,
+    /// The bonds between particles in the template
+    bonds: Vec<(usize, usize)>,
+    /// The holonomic constraints between particles in the template
+    constraints: Vec<Constraint>,
+    /// Should the molecules created from this template be rigid bodies?
+    rigid: bool,
+}
+
+impl MoleculeTemplate {
+    /// Create a new empty `MoleculeTemplate`
+    pub fn new() -> MoleculeTemplate {
+        MoleculeTemplate {
+            particles: Vec::new(),
+            bonds: Vec::new(),
+            constraints: Vec::new(),
+            rigid: false,
+        }
+    }
+
+    /// Add a particle with the given `name`, `position` and `charge` to this
+    /// template. The mass of the particle is set from the `name` if possible,
+    /// as in [`Particle::new`](struct.Particle.html#method.new).
+    pub fn particle<S>(mut self, name: S, position: Vector3D, charge: f64) -> MoleculeTemplate
+    where
+        S: Into<String>,
+    {
+        let mut particle = Particle::with_position(name, position);
+        particle.charge = charge;
+        self.particles.push(particle);
+        self
+    }
+
+    /// Add a bond between the particles at indexes `i` and `j` in this
+    /// template.
+    ///
+    /// # Panics
+    ///
+    /// If `i` or `j` are not in the template, or if `i == j`.
+    pub fn bond(mut self, i: usize, j: usize) -> MoleculeTemplate {
+        assert!(
+            i < self.particles.len() && j < self.particles.len(),
+            "bond {}-{} refers to a particle outside of the molecule template", i, j
+        );
+        assert_ne!(i, j, "can not add a bond between a particle and itself in molecule template");
+        self.bonds.push((i, j));
+        self
+    }
+
+    /// Constrain the distance between the particles at indexes `i` and `j`
+    /// in this template to its current value.
+    ///
+    /// # Panics
+    ///
+    /// If `i` or `j` are not in the template, or if `i == j`.
+    pub fn constrain_distance(mut self, i: usize, j: usize) -> MoleculeTemplate {
+        self.check_constraint(&[i, j]);
+        let distance = (self.particles[j].position - self.particles[i].position).norm();
+        self.constraints.push(Constraint::Distance { i: i, j: j, distance: distance });
+        self
+    }
+
+    /// Constrain the angle between the particles at indexes `i`, `j` and `k`
+    /// in this template to its current value, with `j` at the center of the
+    /// angle.
+    ///
+    /// # Panics
+    ///
+    /// If `i`, `j` or `k` are not in the template, or if the same particle is
+    /// used twice.
+    pub fn constrain_angle(mut self, i: usize, j: usize, k: usize) -> MoleculeTemplate {
+        self.check_constraint(&[i, j, k]);
+        let r_ji = self.particles[i].position - self.particles[j].position;
+        let r_jk = self.particles[k].position - self.particles[j].position;
+        let angle = f64::acos((r_ji * r_jk) / (r_ji.norm() * r_jk.norm()));
+        self.constraints.push(Constraint::Angle { i: i, j: j, k: k, angle: angle });
+        self
+    }
+
+    /// Make the molecules created from this template move as rigid bodies,
+    /// with all their particles at fixed relative positions.
+    pub fn rigid(mut self) -> MoleculeTemplate {
+        self.rigid = true;
+        self
+    }
+
+    fn check_constraint(&self, particles: &[usize]) {
+        for (n, &i) in particles.iter().enumerate() {
+            assert!(
+                i < self.particles.len(),
+                "constraint refers to the particle {} outside of the molecule template", i
+            );
+            assert!(
+                !particles[..n].contains(&i),
+                "particle {} is used twice in a constraint in molecule template", i
+            );
+        }
+    }
+
+    /// Get the number of particles in this template
+    pub fn size(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Get the total mass of the particles in this template
+    pub fn mass(&self) -> f64 {
+        self.particles.iter().map(|particle| particle.mass).sum()
+    }
+
+    /// Get the geometric center of the particles in this template
+    pub fn center(&self) -> Vector3D {
+        let mut center = Vector3D::zero();
+        for particle in &self.particles {
+            center += particle.position;
+        }
+        return center / self.particles.len() as f64;
+    }
+
+    /// Create a new `Molecule` from this template, with the same geometry.
+    ///
+    /// # Panics
+    ///
+    /// If the template does not contain any particle.
+    pub fn molecule(&self) -> Molecule {
+        assert!(!self.particles.is_empty(), "can not create a molecule from an empty template");
+        let mut molecule = Molecule::new(self.particles[0].clone());
+        for (i, particle) in self.particles.iter().enumerate().skip(1) {
+            molecule.particles.push(particle.clone());
+            molecule.bonding.merge_with(Bonding::new(i));
+        }
+        for &(i, j) in &self.bonds {
+            molecule.add_bond(i, j);
+        }
+        for &constraint in &self.constraints {
+            molecule.add_constraint(constraint);
+        }
+        if self.rigid {
+            molecule.add_constraint(Constraint::Rigid { start: 0, end: self.particles.len() });
+        }
+        return molecule;
+    }
+}
+
+impl From<Molecule> for MoleculeTemplate {
+    /// Create a template with the same particles, geometry, bonds and
+    /// constraints as `molecule`. Impropers are not kept in the template.
+    fn from(molecule: Molecule) -> MoleculeTemplate {
+        let bonding = molecule.bonding;
+        let particles = molecule.particles;
+        let particles = (0..particles.len()).map(|i| Particle {
+            name: particles.name[i].clone(),
+            atom_type: particles.atom_type[i].clone(),
+            kind: particles.kind[i],
+            charge: particles.charge[i],
+            mass: particles.mass[i],
+            position: particles.position[i],
+            velocity: particles.velocity[i],
+            image: particles.image[i],
+            residue: particles.residue[i].clone(),
+            residue_id: particles.residue_id[i],
+            chain: particles.chain[i].clone(),
+            frozen: particles.frozen[i],
+        }).collect();
+
+        MoleculeTemplate {
+            particles: particles,
+            bonds: bonding.bonds().iter().map(|bond| (bond.i(), bond.j())).collect(),
+            constraints: bonding.constraints().to_vec(),
+            rigid: false,
+        }
+    }
+}
+
+impl Default for MoleculeTemplate {
+    fn default() -> MoleculeTemplate {
+        MoleculeTemplate::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::Bond;
+    use std::f64;
+
+    fn water() -> MoleculeTemplate {
+        MoleculeTemplate::new()
+            .particle("O", Vector3D::new(0.0, 0.0, 0.0), -0.8)
+            .particle("H", Vector3D::new(1.0, 0.0, 0.0), 0.4)
+            .particle("H", Vector3D::new(0.0, 1.0, 0.0), 0.4)
+            .bond(0, 1)
+            .bond(0, 2)
+    }
+
+    #[test]
+    fn molecule() {
+        let template = water();
+        assert_eq!(template.size(), 3);
+        assert_ulps_eq!(template.mass(), 15.999 + 2.0 * 1.008);
+        assert_ulps_eq!(template.center(), Vector3D::new(1.0 / 3.0, 1.0 / 3.0, 0.0));
+
+        let molecule = template.molecule();
+        assert_eq!(molecule.size(), 3);
+        assert_eq!(molecule.particles().name[0], "O");
+        assert_eq!(molecule.particles().name[1], "H");
+        assert_eq!(molecule.particles().charge, &[-0.8, 0.4, 0.4]);
+        assert_eq!(molecule.particles().mass[0], 15.999);
+        assert_eq!(molecule.particles().position[1], Vector3D::new(1.0, 0.0, 0.0));
+
+        assert_eq!(molecule.bonds().len(), 2);
+        assert!(molecule.bonds().contains(&Bond::new(0, 1)));
+        assert!(molecule.bonds().contains(&Bond::new(0, 2)));
+        assert_eq!(molecule.angles().len(), 1);
+    }
+
+    #[test]
+    fn constraints() {
+        let molecule = water().constrain_distance(0, 1).constrain_angle(1, 0, 2).molecule();
+        assert_eq!(molecule.constraints().len(), 2);
+        assert_eq!(molecule.constraints()[0], Constraint::Distance { i: 0, j: 1, distance: 1.0 });
+        match molecule.constraints()[1] {
+            Constraint::Angle { i, j, k, angle } => {
+                assert_eq!((i, j, k), (1, 0, 2));
+                assert_ulps_eq!(angle, f64::consts::FRAC_PI_2);
+            }
+            _ => panic!("expected an angle constraint"),
+        }
+
+        let molecule = water().rigid().molecule();
+        assert_eq!(molecule.constraints(), &[Constraint::Rigid { start: 0, end: 3 }]);
+    }
+
+    #[test]
+    fn from_molecule() {
+        let template = MoleculeTemplate::from(water().rigid().molecule());
+        assert_eq!(template.size(), 3);
+        assert_ulps_eq!(template.mass(), 15.999 + 2.0 * 1.008);
+
+        let molecule = template.molecule();
+        assert_eq!(molecule.particles().name[0], "O");
+        assert_eq!(molecule.particles().charge, &[-0.8, 0.4, 0.4]);
+        assert_eq!(molecule.particles().position[2], Vector3D::new(0.0, 1.0, 0.0));
+        assert_eq!(molecule.bonds().len(), 2);
+        assert!(molecule.bonds().contains(&Bond::new(0, 1)));
+        assert!(molecule.bonds().contains(&Bond::new(0, 2)));
+        assert_eq!(molecule.constraints(), &[Constraint::Rigid { start: 0, end: 3 }]);
+        assert_eq!(molecule.as_ref().hash(), water().molecule().as_ref().hash());
+    }
+
+    #[test]
+    #[should_panic(expected = "particle 1 is used twice in a constraint in molecule template")]
+    fn constraint_same_particle() {
+        let _ = water().constrain_angle(1, 0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bond 0-3 refers to a particle outside of the molecule template")]
+    fn bond_out_of_template() {
+        let _ = water().bond(0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not create a molecule from an empty template")]
+    fn empty() {
+        let _ = MoleculeTemplate::new().molecule();
+    }
+}
@@ -11,7 +11,36 @@ use std::f64::consts::PI;
 use rayon::prelude::*;
 
 use energy::BondPath;
-use sys::System;
+use sys::{Composition, System};
+
+/// The potential energy of a system, split in the contributions of the
+/// different classes of interactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EnergyBreakdown {
+    /// Energy of the pair interactions, including the long range tail
+    /// corrections
+    pub pairs: f64,
+    /// Energy of the bond interactions
+    pub bonds: f64,
+    /// Energy of the angle interactions, including Urey-Bradley terms
+    pub angles: f64,
+    /// Energy of the dihedral angles interactions, including CMAP terms
+    pub dihedrals: f64,
+    /// Energy of the improper dihedral angles interactions
+    pub impropers: f64,
+    /// Energy of the electrostatic interactions
+    pub coulomb: f64,
+    /// Energy of the global potentials
+    pub global: f64,
+}
+
+impl EnergyBreakdown {
+    /// Get the total potential energy, i.e. the sum of all the contributions
+    pub fn total(&self) -> f64 {
+        self.pairs + self.bonds + self.angles + self.dihedrals + self.impropers +
+        self.coulomb + self.global
+    }
+}
 
 /// An helper struct to evaluate energy components of a system.
 pub struct EnergyEvaluator<'a> {
@@ -42,10 +71,11 @@ impl<'a> EnergyEvaluator<'a> {
 
     /// Compute the energy of all the pairs in the system
     pub fn pairs(&self) -> f64 {
+        let neighbors = self.system.neighbor_list();
         let energies = (0..self.system.size()).into_par_iter().map(|i| {
             let mut local_energy = 0.0;
 
-            for j in (i + 1)..self.system.size() {
+            for &j in neighbors.neighbors(i) {
                 let r = self.system.nearest_image(i, j).norm();
                 let path = self.system.bond_path(i, j);
                 local_energy += self.pair(path, r, i, j);
@@ -55,15 +85,23 @@ impl<'a> EnergyEvaluator<'a> {
         return energies.sum();
     }
 
-    /// Compute the energy due to long range corrections for the pairs
+    /// Compute the energy due to long range corrections for the pairs. These
+    /// corrections are only used for three-dimensional systems.
     #[inline]
     pub fn pairs_tail(&self) -> f64 {
-        if self.system.cell.is_infinite() {
+        return self.pairs_tail_for(&self.system.composition());
+    }
+
+    /// Compute the energy due to long range corrections for the pairs, using
+    /// the number of particles of each kind in `composition` instead of the
+    /// number of particles in the system. This gives the corrections after
+    /// adding or removing particles, without modifying the system.
+    pub fn pairs_tail_for(&self, composition: &Composition) -> f64 {
+        if self.system.cell.is_infinite() || self.system.dimensions() == 2 {
             return 0.0;
         }
         let mut energy = 0.0;
         let volume = self.system.volume();
-        let composition = self.system.composition();
         for (i, ni) in composition.all_particles() {
             for (j, nj) in composition.all_particles() {
                 let two_pi_density = 2.0 * PI * (ni as f64) * (nj as f64) / volume;
@@ -89,11 +127,10 @@ impl<'a> EnergyEvaluator<'a> {
     /// Compute the energy of all the bonds in the system
     pub fn bonds(&self) -> f64 {
         let mut energy = 0.0;
-        for molecule in self.system.molecules() {
-            for bond in molecule.bonds() {
-                let (i, j) = (bond.i(), bond.j());
-                let r = self.system.nearest_image(i, j).norm();
-                energy += self.bond(r, i, j);
+        for (bond, potentials) in self.system.bond_terms() {
+            let r = self.system.nearest_image(bond.i(), bond.j()).norm();
+            for potential in potentials {
+                energy += potential.energy(r);
             }
         }
         return energy;
@@ -109,14 +146,29 @@ impl<'a> EnergyEvaluator<'a> {
         return energy;
     }
 
-    /// Compute the energy of all the angles in the system
+    /// Compute the Urey-Bradley energy associated with the angle `i, j, k`,
+    /// where `r` is the distance between the particles `i` and `k`
+    #[inline]
+    pub fn urey_bradley(&self, r: f64, i: usize, j: usize, k: usize) -> f64 {
+        match self.system.urey_bradley_potential(i, j, k) {
+            Some(potential) => potential.energy(r),
+            None => 0.0,
+        }
+    }
+
+    /// Compute the energy of all the angles in the system, including the
+    /// Urey-Bradley terms
     pub fn angles(&self) -> f64 {
         let mut energy = 0.0;
-        for molecule in self.system.molecules() {
-            for angle in molecule.angles() {
-                let (i, j, k) = (angle.i(), angle.j(), angle.k());
-                let theta = self.system.angle(i, j, k);
-                energy += self.angle(theta, i, j, k);
+        for (angle, potentials) in self.system.angle_terms() {
+            let (i, j, k) = (angle.i(), angle.j(), angle.k());
+            let theta = self.system.angle(i, j, k);
+            for potential in potentials {
+                energy += potential.energy(theta);
+            }
+            if self.system.urey_bradley_potential(i, j, k).is_some() {
+                let r = self.system.nearest_image(i, k).norm();
+                energy += self.urey_bradley(r, i, j, k);
             }
         }
         return energy;
@@ -133,19 +185,115 @@ impl<'a> EnergyEvaluator<'a> {
         return energy;
     }
 
-    /// Compute the energy of all the dihedral angles in the system
+    /// Compute the energy of all the dihedral angles in the system, including
+    /// the CMAP corrections for pairs of dihedral angles
     pub fn dihedrals(&self) -> f64 {
         let mut energy = 0.0;
+        for (dihedral, potentials) in self.system.dihedral_terms() {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let phi = self.system.dihedral(i, j, k, m);
+            for potential in potentials {
+                energy += potential.energy(phi);
+            }
+        }
+
         for molecule in self.system.molecules() {
-            for dihedral in molecule.dihedrals() {
-                let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            for pair in molecule.dihedral_pairs() {
+                let (i, j, k, m, n) = (pair.i(), pair.j(), pair.k(), pair.m(), pair.n());
+                if let Some(cmap) = self.system.cmap_potential(i, j, k, m, n) {
+                    let phi = self.system.dihedral(i, j, k, m);
+                    let psi = self.system.dihedral(j, k, m, n);
+                    energy += cmap.energy(phi, psi);
+                }
+            }
+        }
+        return energy;
+    }
+
+    /// Compute the energy associated with the improper dihedral angle `i, j,
+    /// k, m` at angle `phi`
+    #[inline]
+    pub fn improper(&self, phi: f64, i: usize, j: usize, k: usize, m: usize) -> f64 {
+        let mut energy = 0.0;
+        for potential in self.system.improper_potentials(i, j, k, m) {
+            energy += potential.energy(phi);
+        }
+        return energy;
+    }
+
+    /// Compute the energy of all the improper dihedral angles in the system
+    pub fn impropers(&self) -> f64 {
+        let mut energy = 0.0;
+        for (improper, potentials) in self.system.improper_terms() {
+            let (i, j, k, m) = (improper.i(), improper.j(), improper.k(), improper.m());
+            let phi = self.system.dihedral(i, j, k, m);
+            for potential in potentials {
+                energy += potential.energy(phi);
+            }
+        }
+        return energy;
+    }
+
+    /// Compute the energy of the bonds in the molecule at `molecule_id`
+    pub fn molecule_bonds(&self, molecule_id: usize) -> f64 {
+        let mut energy = 0.0;
+        for bond in self.system.molecule(molecule_id).bonds() {
+            let r = self.system.nearest_image(bond.i(), bond.j()).norm();
+            energy += self.bond(r, bond.i(), bond.j());
+        }
+        return energy;
+    }
+
+    /// Compute the energy of the angles in the molecule at `molecule_id`,
+    /// including the Urey-Bradley terms
+    pub fn molecule_angles(&self, molecule_id: usize) -> f64 {
+        let mut energy = 0.0;
+        for angle in self.system.molecule(molecule_id).angles() {
+            let (i, j, k) = (angle.i(), angle.j(), angle.k());
+            let theta = self.system.angle(i, j, k);
+            energy += self.angle(theta, i, j, k);
+            if self.system.urey_bradley_potential(i, j, k).is_some() {
+                let r = self.system.nearest_image(i, k).norm();
+                energy += self.urey_bradley(r, i, j, k);
+            }
+        }
+        return energy;
+    }
+
+    /// Compute the energy of the dihedral angles in the molecule at
+    /// `molecule_id`, including the CMAP corrections
+    pub fn molecule_dihedrals(&self, molecule_id: usize) -> f64 {
+        let mut energy = 0.0;
+        let molecule = self.system.molecule(molecule_id);
+        for dihedral in molecule.dihedrals() {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let phi = self.system.dihedral(i, j, k, m);
+            energy += self.dihedral(phi, i, j, k, m);
+        }
+
+        for pair in molecule.dihedral_pairs() {
+            let (i, j, k, m, n) = (pair.i(), pair.j(), pair.k(), pair.m(), pair.n());
+            if let Some(cmap) = self.system.cmap_potential(i, j, k, m, n) {
                 let phi = self.system.dihedral(i, j, k, m);
-                energy += self.dihedral(phi, i, j, k, m);
+                let psi = self.system.dihedral(j, k, m, n);
+                energy += cmap.energy(phi, psi);
             }
         }
         return energy;
     }
 
+    /// Compute the energy of the improper dihedral angles in the molecule at
+    /// `molecule_id`
+    pub fn molecule_impropers(&self, molecule_id: usize) -> f64 {
+        let mut energy = 0.0;
+        for improper in self.system.molecule(molecule_id).impropers() {
+            let (i, j, k, m) = (improper.i(), improper.j(), improper.k(), improper.m());
+            let phi = self.system.dihedral(i, j, k, m);
+            energy += self.improper(phi, i, j, k, m);
+        }
+        return energy;
+    }
+
     /// Compute the energy of the electrostatic interactions
     #[inline]
     pub fn coulomb(&self) -> f64 {
@@ -165,13 +313,26 @@ impl<'a> EnergyEvaluator<'a> {
         }
         return energy;
     }
+
+    /// Compute all the components of the potential energy of the system
+    pub fn breakdown(&self) -> EnergyBreakdown {
+        EnergyBreakdown {
+            pairs: self.pairs() + self.pairs_tail(),
+            bonds: self.bonds(),
+            angles: self.angles(),
+            dihedrals: self.dihedrals(),
+            impropers: self.impropers(),
+            coulomb: self.coulomb(),
+            global: self.global(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use energy::{Harmonic, LennardJones, NullPotential, PairInteraction};
-    use sys::{System, UnitCell};
+    use energy::{Cmap, Harmonic, LennardJones, NullPotential, PairInteraction};
+    use sys::{Molecule, Particle, System, UnitCell};
     use utils::system_from_xyz;
     use units;
 
@@ -247,6 +408,17 @@ mod tests {
         assert_eq!(evaluator.pairs_tail(), 0.0);
     }
 
+    #[test]
+    fn pairs_tail_for() {
+        let mut system = testing_system();
+        let mut composition = system.composition();
+        composition.add_particle(system.particles().kind[0]);
+        let tail = EnergyEvaluator::new(&system).pairs_tail_for(&composition);
+
+        system.add_molecule(Molecule::new(Particle::with_position("F", [5.0, 5.0, 5.0].into())));
+        assert_ulps_eq!(EnergyEvaluator::new(&system).pairs_tail(), tail);
+    }
+
     #[test]
     fn bonds() {
         let system = testing_system();
@@ -261,10 +433,126 @@ mod tests {
         assert_ulps_eq!(evaluator.angles(), units::from(400.0, "kJ/mol").unwrap());
     }
 
+    #[test]
+    fn urey_bradley() {
+        let mut system = testing_system();
+        system.set_urey_bradley_potential(
+            ("F", "F", "F"),
+            Box::new(Harmonic {
+                k: units::from(100.0, "kJ/mol/A^2").unwrap(),
+                x0: units::from(1.0, "A").unwrap(),
+            }),
+        );
+        let evaluator = EnergyEvaluator::new(&system);
+
+        // The 1-3 distance is sqrt(2) A for both angles
+        let r = f64::sqrt(2.0);
+        let expected = units::from(50.0 * (r - 1.0) * (r - 1.0), "kJ/mol").unwrap();
+        assert_ulps_eq!(evaluator.urey_bradley(r, 0, 1, 2), expected);
+        assert_relative_eq!(
+            evaluator.angles(),
+            units::from(400.0, "kJ/mol").unwrap() + 2.0 * expected,
+            epsilon = 1e-12
+        );
+    }
+
     #[test]
     fn dihedrals() {
         let system = testing_system();
         let evaluator = EnergyEvaluator::new(&system);
         assert_ulps_eq!(evaluator.dihedrals(), units::from(1250.0, "kJ/mol").unwrap(), max_ulps = 15);
     }
+
+    #[test]
+    fn impropers() {
+        let mut system = testing_system();
+        let evaluator = EnergyEvaluator::new(&system);
+        assert_eq!(evaluator.impropers(), 0.0);
+
+        system.add_improper(1, 0, 2, 3);
+        system.add_improper_potential(
+            ("F", "F", "F", "F"),
+            Box::new(Harmonic {
+                k: units::from(100.0, "kJ/mol/deg^2").unwrap(),
+                x0: units::from(10.0, "deg").unwrap(),
+            }),
+        );
+        let evaluator = EnergyEvaluator::new(&system);
+        // The improper angle is 0 as all the particles are in the same plane
+        assert_ulps_eq!(evaluator.impropers(), units::from(5000.0, "kJ/mol").unwrap(), max_ulps = 15);
+    }
+
+    #[test]
+    fn molecule_terms() {
+        let mut system = testing_system();
+        system.add_improper(1, 0, 2, 3);
+        system.add_improper_potential(
+            ("F", "F", "F", "F"),
+            Box::new(Harmonic {
+                k: units::from(100.0, "kJ/mol/deg^2").unwrap(),
+                x0: units::from(10.0, "deg").unwrap(),
+            }),
+        );
+        system.add_molecule(Molecule::new(Particle::with_position("F", [5.0, 5.0, 5.0].into())));
+
+        // All the bonded terms are in the first molecule
+        let evaluator = EnergyEvaluator::new(&system);
+        assert_ulps_eq!(evaluator.molecule_bonds(0), evaluator.bonds());
+        assert_ulps_eq!(evaluator.molecule_angles(0), evaluator.angles());
+        assert_ulps_eq!(evaluator.molecule_dihedrals(0), evaluator.dihedrals());
+        assert_ulps_eq!(evaluator.molecule_impropers(0), evaluator.impropers());
+
+        assert_eq!(evaluator.molecule_bonds(1), 0.0);
+        assert_eq!(evaluator.molecule_angles(1), 0.0);
+        assert_eq!(evaluator.molecule_dihedrals(1), 0.0);
+        assert_eq!(evaluator.molecule_impropers(1), 0.0);
+    }
+
+    #[test]
+    fn cmaps() {
+        let mut system = system_from_xyz(
+            "5
+            cell: 10.0
+            F 0.0 0.0 0.0
+            F 1.0 0.0 0.0
+            F 1.0 1.0 0.0
+            F 2.0 1.0 0.0
+            F 2.0 2.0 0.0
+            ",
+        );
+        assert!(system.add_bond(0, 1).is_empty());
+        assert!(system.add_bond(1, 2).is_empty());
+        assert!(system.add_bond(2, 3).is_empty());
+        assert!(system.add_bond(3, 4).is_empty());
+        system.add_dihedral_potential(("F", "F", "F", "F"), Box::new(NullPotential));
+
+        let evaluator = EnergyEvaluator::new(&system);
+        assert_eq!(evaluator.dihedrals(), 0.0);
+
+        // The first dihedral angle is trans (phi = π), and the second one is
+        // cis (psi = 0)
+        let mut values = vec![0.0; 16];
+        values[2] = units::from(7.0, "kJ/mol").unwrap();
+        system.set_cmap_potential(("F", "F", "F", "F", "F"), Cmap::new(4, values));
+
+        let evaluator = EnergyEvaluator::new(&system);
+        assert_relative_eq!(evaluator.dihedrals(), units::from(7.0, "kJ/mol").unwrap(), epsilon = 1e-10);
+    }
+
+    #[test]
+    fn breakdown() {
+        let system = testing_system();
+        let evaluator = EnergyEvaluator::new(&system);
+        let breakdown = evaluator.breakdown();
+        assert_eq!(breakdown.pairs, evaluator.pairs() + evaluator.pairs_tail());
+        assert_ulps_eq!(breakdown.bonds, units::from(150.0, "kJ/mol").unwrap());
+        assert_ulps_eq!(breakdown.angles, units::from(400.0, "kJ/mol").unwrap());
+        assert_eq!(breakdown.dihedrals, evaluator.dihedrals());
+        assert_eq!(breakdown.impropers, 0.0);
+        assert_eq!(breakdown.coulomb, 0.0);
+        assert_eq!(breakdown.global, 0.0);
+
+        assert_ulps_eq!(breakdown.total(), system.potential_energy());
+        assert_eq!(system.energy_breakdown(), breakdown);
+    }
 }
@@ -8,7 +8,7 @@ use std::cmp::{max, min};
 use std::collections::BTreeMap;
 use std::f64;
 
-use energy::{AnglePotential, BondPotential, DihedralPotential, PairInteraction};
+use energy::{AnglePotential, BondPotential, Cmap, DihedralPotential, PairInteraction};
 use energy::{CoulombicPotential, GlobalPotential};
 use sys::ParticleKind as Kind;
 
@@ -16,6 +16,8 @@ pub type PairKind = (Kind, Kind);
 pub type BondKind = (Kind, Kind);
 pub type AngleKind = (Kind, Kind, Kind);
 pub type DihedralKind = (Kind, Kind, Kind, Kind);
+pub type ImproperKind = (Kind, Kind, Kind, Kind);
+pub type CmapKind = (Kind, Kind, Kind, Kind, Kind);
 
 /// Normalize pair indexes to get a canonical representation
 #[inline]
@@ -55,6 +57,34 @@ fn normalize_dihedral((i, j, k, m): DihedralKind) -> DihedralKind {
     }
 }
 
+/// Normalize improper indexes to get a canonical representation. The central
+/// particle is kept first, and the other particles are sorted.
+#[inline]
+fn normalize_improper((i, j, k, m): ImproperKind) -> ImproperKind {
+    let mut others = [j, k, m];
+    others.sort();
+    (i, others[0], others[1], others[2])
+}
+
+/// Potentials acting on a given angle
+#[derive(Clone)]
+struct AngleInteractions {
+    /// Potentials depending on the angle value
+    potentials: Vec<Box<AnglePotential>>,
+    /// Urey-Bradley potential, acting on the distance between the first and
+    /// the last particles of the angle
+    urey_bradley: Option<Box<BondPotential>>,
+}
+
+impl AngleInteractions {
+    fn new() -> AngleInteractions {
+        AngleInteractions {
+            potentials: Vec::new(),
+            urey_bradley: None,
+        }
+    }
+}
+
 /// The `Interaction` type hold all data about the potentials in the system.
 ///
 /// Its main role is to store and provide access
@@ -64,10 +94,15 @@ pub struct Interactions {
     pairs: BTreeMap<PairKind, Vec<PairInteraction>>,
     /// Bond potentials
     bonds: BTreeMap<BondKind, Vec<Box<BondPotential>>>,
-    /// Angle potentials
-    angles: BTreeMap<AngleKind, Vec<Box<AnglePotential>>>,
+    /// Angle potentials, with optional Urey-Bradley terms
+    angles: BTreeMap<AngleKind, AngleInteractions>,
     /// Dihedral angles potentials
     dihedrals: BTreeMap<DihedralKind, Vec<Box<DihedralPotential>>>,
+    /// Improper dihedral angles potentials
+    impropers: BTreeMap<ImproperKind, Vec<Box<DihedralPotential>>>,
+    /// CMAP corrections for pairs of dihedral angles. The corrections are
+    /// stored for both orders of the particles.
+    cmaps: BTreeMap<CmapKind, Cmap>,
     /// Coulombic potential solver
     pub coulomb: Option<Box<CoulombicPotential>>,
     /// Global potentials
@@ -82,6 +117,8 @@ impl Interactions {
             bonds: BTreeMap::new(),
             angles: BTreeMap::new(),
             dihedrals: BTreeMap::new(),
+            impropers: BTreeMap::new(),
+            cmaps: BTreeMap::new(),
             coulomb: None,
             globals: Vec::new(),
         }
@@ -104,8 +141,17 @@ impl Interactions {
     /// Add the `potential` angle interaction for the given `angle`
     pub fn add_angle(&mut self, angle: AngleKind, potential: Box<AnglePotential>) {
         let kind = normalize_angle(angle);
-        let angles = self.angles.entry(kind).or_insert(Vec::new());
-        angles.push(potential);
+        let angles = self.angles.entry(kind).or_insert_with(AngleInteractions::new);
+        angles.potentials.push(potential);
+    }
+
+    /// Set the Urey-Bradley `potential` for the given `angle`. This potential
+    /// acts on the distance between the first and the last particles of the
+    /// angle, and replaces any previous Urey-Bradley potential for this angle.
+    pub fn set_urey_bradley(&mut self, angle: AngleKind, potential: Box<BondPotential>) {
+        let kind = normalize_angle(angle);
+        let angles = self.angles.entry(kind).or_insert_with(AngleInteractions::new);
+        angles.urey_bradley = Some(potential);
     }
 
     /// Add the `potential` dihedral interaction for the dihedral angle `(i, j,
@@ -115,6 +161,37 @@ impl Interactions {
         let dihedrals = self.dihedrals.entry(kind).or_insert(Vec::new());
         dihedrals.push(potential);
     }
+
+    /// Add the `potential` improper dihedral interaction for the improper
+    /// `(i, j, k, m)`, where `i` is the central particle
+    pub fn add_improper(&mut self, improper: ImproperKind, potential: Box<DihedralPotential>) {
+        let kind = normalize_improper(improper);
+        let impropers = self.impropers.entry(kind).or_insert(Vec::new());
+        impropers.push(potential);
+    }
+
+    /// Set the `cmap` correction for the pair of dihedral angles formed by
+    /// the particles `(i, j, k, m, n)`. This replaces any previous correction
+    /// for these particles.
+    ///
+    /// # Panics
+    ///
+    /// If the particles are the same in reverse order (`i == n` and `j ==
+    /// m`) and the `cmap` is not symmetric. In this case, there is no way to
+    /// know which dihedral angle is `phi` and which one is `psi`.
+    pub fn set_cmap(&mut self, kind: CmapKind, cmap: Cmap) {
+        let (i, j, k, m, n) = kind;
+        if i == n && j == m {
+            assert!(
+                cmap.is_symmetric(),
+                "CMAP correction must be symmetric for palindromic particles"
+            );
+        }
+        // Using the particles in reverse order exchanges the two dihedral
+        // angles, and the correction map must be transposed.
+        let _ = self.cmaps.insert((n, m, k, j, i), cmap.transposed());
+        let _ = self.cmaps.insert(kind, cmap);
+    }
 }
 
 impl Interactions {
@@ -133,7 +210,15 @@ impl Interactions {
     /// Get all angle interactions corresponding to the `angle`
     pub fn angles(&self, angle: AngleKind) -> &[Box<AnglePotential>] {
         let kind = normalize_angle(angle);
-        self.angles.get(&kind).map_or(&[], |angles| &**angles)
+        self.angles.get(&kind).map_or(&[], |angles| &*angles.potentials)
+    }
+
+    /// Get the Urey-Bradley interaction corresponding to the `angle`, if any
+    pub fn urey_bradley(&self, angle: AngleKind) -> Option<&BondPotential> {
+        let kind = normalize_angle(angle);
+        self.angles.get(&kind)
+                   .and_then(|angles| angles.urey_bradley.as_ref())
+                   .map(|potential| &**potential)
     }
 
     /// Get all dihedral interactions corresponding to the `dihedral`
@@ -142,6 +227,18 @@ impl Interactions {
         self.dihedrals.get(&kind).map_or(&[], |dihedrals| &**dihedrals)
     }
 
+    /// Get all improper dihedral interactions corresponding to the `improper`
+    pub fn impropers(&self, improper: ImproperKind) -> &[Box<DihedralPotential>] {
+        let kind = normalize_improper(improper);
+        self.impropers.get(&kind).map_or(&[], |impropers| &**impropers)
+    }
+
+    /// Get the CMAP correction for the pair of dihedral angles formed by the
+    /// particles `(i, j, k, m, n)`, if any
+    pub fn cmap(&self, kind: CmapKind) -> Option<&Cmap> {
+        self.cmaps.get(&kind)
+    }
+
     /// Get maximum cutoff from `coulomb`, `pairs` and `global` interactons.
     pub fn maximum_cutoff(&self) -> Option<f64> {
         // Coulomb potential, return cutoff
@@ -164,9 +261,7 @@ impl Interactions {
         let mut maximum_cutoff = f64::max(global_cutoff, coulomb_cutoff);
 
         // Pair interactions, return maximum cutoff
-        let pairs_cutoff = self.pairs.values()
-                               .flat_map(|i| i.iter().map(|pair| pair.cutoff()))
-                               .fold(f64::NAN, f64::max);
+        let pairs_cutoff = self.pairs_cutoff().unwrap_or(f64::NAN);
 
         maximum_cutoff = f64::max(maximum_cutoff, pairs_cutoff);
         if maximum_cutoff.is_nan() {
@@ -175,15 +270,29 @@ impl Interactions {
             Some(maximum_cutoff)
         }
     }
+
+    /// Get maximum cutoff from the `pairs` interactions only.
+    pub fn pairs_cutoff(&self) -> Option<f64> {
+        let cutoff = self.pairs.values()
+                         .flat_map(|i| i.iter().map(|pair| pair.cutoff()))
+                         .fold(f64::NAN, f64::max);
+        if cutoff.is_nan() {
+            None
+        } else {
+            Some(cutoff)
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use energy::{NullPotential, PairInteraction, Wolf};
+    use energy::{Cmap, NullPotential, PairInteraction, Wolf};
     use sys::ParticleKind as Kind;
 
+    use std::f64::consts::PI;
+
     #[test]
     fn normalizing_pairs() {
         assert_eq!(normalize_pair((Kind(0), Kind(1))), normalize_pair((Kind(1), Kind(0))));
@@ -307,6 +416,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn normalizing_impropers() {
+        assert_eq!(
+            normalize_improper((Kind(1), Kind(3), Kind(0), Kind(2))),
+            (Kind(1), Kind(0), Kind(2), Kind(3))
+        );
+        assert_eq!(
+            normalize_improper((Kind(1), Kind(2), Kind(3), Kind(0))),
+            normalize_improper((Kind(1), Kind(0), Kind(3), Kind(2)))
+        );
+        assert_ne!(
+            normalize_improper((Kind(1), Kind(2), Kind(3), Kind(0))),
+            normalize_improper((Kind(0), Kind(1), Kind(2), Kind(3)))
+        );
+    }
+
     #[test]
     fn pairs() {
         let mut interactions = Interactions::new();
@@ -360,6 +485,23 @@ mod test {
         assert_eq!(interactions.angles((Kind(55), Kind(55), Kind(55))).len(), 0);
     }
 
+    #[test]
+    fn urey_bradley() {
+        let mut interactions = Interactions::new();
+        assert!(interactions.urey_bradley((Kind(0), Kind(1), Kind(2))).is_none());
+
+        interactions.set_urey_bradley((Kind(0), Kind(1), Kind(2)), Box::new(NullPotential));
+        assert!(interactions.urey_bradley((Kind(0), Kind(1), Kind(2))).is_some());
+        assert!(interactions.urey_bradley((Kind(2), Kind(1), Kind(0))).is_some());
+        assert!(interactions.urey_bradley((Kind(1), Kind(0), Kind(2))).is_none());
+        // Setting the Urey-Bradley term does not add an angle potential
+        assert_eq!(interactions.angles((Kind(0), Kind(1), Kind(2))).len(), 0);
+
+        interactions.add_angle((Kind(0), Kind(1), Kind(2)), Box::new(NullPotential));
+        assert_eq!(interactions.angles((Kind(0), Kind(1), Kind(2))).len(), 1);
+        assert!(interactions.urey_bradley((Kind(0), Kind(1), Kind(2))).is_some());
+    }
+
     #[test]
     fn dihedrals() {
         let mut interactions = Interactions::new();
@@ -384,6 +526,69 @@ mod test {
         assert_eq!(interactions.dihedrals((Kind(55), Kind(55), Kind(55), Kind(55))).len(), 0);
     }
 
+    #[test]
+    fn impropers() {
+        let mut interactions = Interactions::new();
+
+        interactions.add_improper((Kind(0), Kind(1), Kind(2), Kind(3)), Box::new(NullPotential));
+        assert_eq!(interactions.impropers((Kind(0), Kind(1), Kind(2), Kind(3))).len(), 1);
+        assert_eq!(interactions.impropers((Kind(0), Kind(3), Kind(1), Kind(2))).len(), 1);
+        assert_eq!(interactions.impropers((Kind(1), Kind(0), Kind(2), Kind(3))).len(), 0);
+        // Impropers and dihedrals are separated
+        assert_eq!(interactions.dihedrals((Kind(0), Kind(1), Kind(2), Kind(3))).len(), 0);
+
+        // 'out of bounds' kinds
+        assert_eq!(interactions.impropers((Kind(55), Kind(55), Kind(55), Kind(55))).len(), 0);
+    }
+
+    #[test]
+    fn cmaps() {
+        let mut interactions = Interactions::new();
+
+        let mut values = vec![0.0; 16];
+        values[1] = 3.0;
+        let cmap = Cmap::new(4, values);
+        let (phi, psi) = (-PI, -PI / 2.0);
+        assert_eq!(cmap.energy(phi, psi), 3.0);
+
+        interactions.set_cmap((Kind(0), Kind(1), Kind(2), Kind(3), Kind(4)), cmap);
+        let cmap = interactions.cmap((Kind(0), Kind(1), Kind(2), Kind(3), Kind(4))).unwrap();
+        assert_eq!(cmap.energy(phi, psi), 3.0);
+
+        // The angles are exchanged when using the reverse order
+        let cmap = interactions.cmap((Kind(4), Kind(3), Kind(2), Kind(1), Kind(0))).unwrap();
+        assert_eq!(cmap.energy(psi, phi), 3.0);
+        assert_eq!(cmap.energy(phi, psi), 0.0);
+
+        assert!(interactions.cmap((Kind(0), Kind(1), Kind(2), Kind(4), Kind(3))).is_none());
+    }
+
+    #[test]
+    fn palindromic_cmaps() {
+        let mut interactions = Interactions::new();
+
+        let mut values = vec![0.0; 16];
+        values[1] = 3.0;
+        values[4] = 3.0;
+        let cmap = Cmap::new(4, values);
+        let (phi, psi) = (-PI, -PI / 2.0);
+
+        interactions.set_cmap((Kind(0), Kind(1), Kind(2), Kind(1), Kind(0)), cmap);
+        let cmap = interactions.cmap((Kind(0), Kind(1), Kind(2), Kind(1), Kind(0))).unwrap();
+        assert_eq!(cmap.energy(phi, psi), 3.0);
+        assert_eq!(cmap.energy(psi, phi), 3.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "CMAP correction must be symmetric for palindromic particles")]
+    fn non_symmetric_palindromic_cmap() {
+        let mut interactions = Interactions::new();
+        let mut values = vec![0.0; 16];
+        values[1] = 3.0;
+        let cmap = Cmap::new(4, values);
+        interactions.set_cmap((Kind(0), Kind(1), Kind(2), Kind(1), Kind(0)), cmap);
+    }
+
     #[test]
     fn test_maximum_cutoff() {
         let mut interactions = Interactions::new();
@@ -416,4 +621,21 @@ mod test {
         interactions.globals.push(Box::new(Wolf::new(1.0)));
         assert_eq!(interactions.maximum_cutoff(), Some(15.0));
     }
+
+    #[test]
+    fn test_pairs_cutoff() {
+        let mut interactions = Interactions::new();
+        assert_eq!(interactions.pairs_cutoff(), None);
+
+        // Global and coulomb potentials are ignored
+        interactions.globals.push(Box::new(Wolf::new(15.0)));
+        interactions.coulomb = Some(Box::new(Wolf::new(12.0)));
+        assert_eq!(interactions.pairs_cutoff(), None);
+
+        let pair = PairInteraction::new(Box::new(NullPotential), 10.0);
+        interactions.add_pair((Kind(0), Kind(1)), pair);
+        let pair = PairInteraction::new(Box::new(NullPotential), 8.0);
+        interactions.add_pair((Kind(1), Kind(1)), pair);
+        assert_eq!(interactions.pairs_cutoff(), Some(10.0));
+    }
 }
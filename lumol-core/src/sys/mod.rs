@@ -8,13 +8,28 @@ pub use self::config::*;
 
 mod system;
 pub use self::system::System;
-pub use self::system::DegreesOfFreedom;
+pub use self::system::{DegreesOfFreedom, NeutralityPolicy, ValidationError};
 
 mod interactions;
 use self::interactions::Interactions;
 
+mod cell_list;
+pub use self::cell_list::CellList;
+
+mod neighbors;
+pub use self::neighbors::{NeighborList, SharedNeighborList};
+
+mod virtual_sites;
+pub use self::virtual_sites::VirtualSite;
+
+mod modifiers;
+pub use self::modifiers::{ForceModifier, BoxCloneForceModifier};
+
+mod superpose;
+pub use self::superpose::{rmsd, superpose};
+
 mod energy;
-pub use self::energy::EnergyEvaluator;
+pub use self::energy::{EnergyBreakdown, EnergyEvaluator};
 
 mod cache;
 pub use self::cache::EnergyCache;
@@ -24,3 +39,4 @@ pub use self::chfl::{OpenMode, Trajectory, TrajectoryBuilder, Error as Trajector
 pub use self::chfl::read_molecule;
 
 pub mod compute;
+pub mod build;
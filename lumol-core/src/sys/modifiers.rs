@@ -0,0 +1,69 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Force modifiers are user-provided hooks adding custom forces to the
+//! particles during the force computation.
+use sys::System;
+use types::Vector3D;
+
+/// A hook called each time the forces acting on a [System][System] are
+/// computed, to add custom forces to the particles.
+///
+/// Force modifiers are called after all the interactions in the system, and
+/// before spreading the forces acting on virtual sites on the particles
+/// defining them. This allows to add custom biases, couplings to external
+/// fields or to other software, without having to modify the integrators or
+/// the propagators. The modifier can use the simulation step stored in the
+/// system to add time-dependent forces.
+///
+/// The forces from the modifiers do not need to derive from an energy: they
+/// are not included in the potential energy, the virial or the pressure of
+/// the system, and they are not used by Monte Carlo simulations. Conservative
+/// external potentials should use a [GlobalPotential][GlobalPotential]
+/// instead.
+///
+/// The modifiers are called with a shared reference, and must use inner
+/// mutability (for example with a `Mutex`) to update some internal state.
+///
+/// [System]: struct.System.html
+/// [GlobalPotential]: ../energy/trait.GlobalPotential.html
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{ForceModifier, System, Particle, Molecule, UnitCell};
+/// use lumol_core::types::{Vector3D, Matrix3};
+///
+/// /// A constant force acting on all the particles
+/// #[derive(Clone)]
+/// struct ConstantForce {
+///     force: Vector3D,
+/// }
+///
+/// impl ForceModifier for ConstantForce {
+///     fn modify(&self, _: &System, forces: &mut [Vector3D]) {
+///         for force in forces {
+///             *force += self.force;
+///         }
+///     }
+/// }
+///
+/// let mut system = System::with_cell(UnitCell::cubic(10.0));
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+/// system.add_molecule(Molecule::new(Particle::new("Ar")));
+///
+/// let force = Vector3D::new(0.0, 0.0, -1.0);
+/// system.add_force_modifier(Box::new(ConstantForce { force: force }));
+///
+/// assert_eq!(system.forces(), vec![force; 2]);
+/// // The forces from the modifiers do not contribute to the virial
+/// assert_eq!(system.virial(), Matrix3::zero());
+/// ```
+pub trait ForceModifier: BoxCloneForceModifier + Send + Sync {
+    /// Add the custom forces acting on the particles in the `system` to
+    /// `forces`. The `forces` already contain the forces coming from the
+    /// interactions, with one entry for each particle in the system.
+    fn modify(&self, system: &System, forces: &mut [Vector3D]);
+}
+
+impl_box_clone!(ForceModifier, BoxCloneForceModifier, box_clone_force_modifier);
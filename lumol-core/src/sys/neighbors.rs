@@ -0,0 +1,282 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Verlet neighbor lists, used to speed up the computation of pair
+//! interactions.
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use rayon::prelude::*;
+
+use sys::{CellList, Configuration, UnitCell};
+use types::Vector3D;
+
+/// A Verlet neighbor list, storing all the pairs of particles closer than a
+/// given cutoff distance plus a skin distance.
+///
+/// The list remains valid as long as no particle moved by more than half the
+/// skin distance since the last update: no pair of particles can then get
+/// closer than the cutoff distance without being in the list. The list must
+/// be updated when this is no longer the case, or when the unit cell, the
+/// number of particles or the cutoff distance change. Using a larger skin
+/// distance reduces the number of updates, but increases the number of pairs
+/// in the list. The list is built in linear time using a
+/// [`CellList`](struct.CellList.html).
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell, NeighborList};
+///
+/// let mut system = System::with_cell(UnitCell::cubic(20.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [3.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [10.0, 0.0, 0.0].into())));
+///
+/// let mut neighbors = NeighborList::new(1.0);
+/// assert!(neighbors.needs_update(&system, 5.0));
+///
+/// neighbors.update(&system, 5.0);
+/// assert!(!neighbors.needs_update(&system, 5.0));
+/// assert_eq!(neighbors.neighbors(0), &[1]);
+/// assert!(neighbors.neighbors(1).is_empty());
+/// assert!(neighbors.neighbors(2).is_empty());
+///
+/// // Moving a particle by more than half of the skin distance invalidates
+/// // the list
+/// system.particles_mut().position[2][0] = 8.0;
+/// assert!(neighbors.needs_update(&system, 5.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct NeighborList {
+    /// Skin distance, added to the cutoff distance when building the list
+    skin: f64,
+    /// Cutoff distance used for the last update, or `None` if the list was
+    /// never updated
+    cutoff: Option<f64>,
+    /// Unit cell used for the last update
+    cell: UnitCell,
+    /// Positions of the particles at the last update
+    positions: Vec<Vector3D>,
+    /// For each particle `i`, the list of particles `j > i` closer than the
+    /// cutoff plus the skin distance at the last update
+    neighbors: Vec<Vec<usize>>,
+}
+
+impl NeighborList {
+    /// Create a new empty `NeighborList` using the given `skin` distance.
+    ///
+    /// # Panics
+    ///
+    /// If the `skin` distance is negative.
+    pub fn new(skin: f64) -> NeighborList {
+        assert!(skin >= 0.0, "the skin distance of a neighbor list can not be negative");
+        NeighborList {
+            skin: skin,
+            cutoff: None,
+            cell: UnitCell::infinite(),
+            positions: Vec::new(),
+            neighbors: Vec::new(),
+        }
+    }
+
+    /// Get the skin distance of this neighbor list
+    pub fn skin(&self) -> f64 {
+        self.skin
+    }
+
+    /// Check if this list needs to be updated before being used with the
+    /// `configuration` and the given `cutoff` distance.
+    pub fn needs_update(&self, configuration: &Configuration, cutoff: f64) -> bool {
+        if self.cutoff != Some(cutoff) || self.cell != configuration.cell {
+            return true;
+        }
+
+        let positions = configuration.particles().position;
+        if positions.len() != self.positions.len() {
+            return true;
+        }
+
+        let max_displacement2 = 0.25 * self.skin * self.skin;
+        for (old, new) in self.positions.iter().zip(positions) {
+            let mut displacement = new - old;
+            configuration.cell.vector_image(&mut displacement);
+            if displacement.norm2() > max_displacement2 {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Update the list with all the pairs of particles in the
+    /// `configuration` closer than `cutoff` plus the skin distance.
+    pub fn update(&mut self, configuration: &Configuration, cutoff: f64) {
+        let positions = configuration.particles().position;
+        let cell = &configuration.cell;
+        let range = cutoff + self.skin;
+        let range2 = range * range;
+
+        let cells = CellList::new(configuration, range);
+        self.neighbors = (0..positions.len()).into_par_iter().map(|i| {
+            let mut neighbors = cells.neighbors(i);
+            neighbors.retain(|&j| {
+                let mut r_ij = positions[j] - positions[i];
+                cell.vector_image(&mut r_ij);
+                r_ij.norm2() < range2
+            });
+            neighbors
+        }).collect();
+
+        self.cutoff = Some(cutoff);
+        self.cell = configuration.cell;
+        self.positions = positions.to_vec();
+    }
+
+    /// Get the neighbors `j > i` of the particle `i` in this list.
+    pub fn neighbors(&self, i: usize) -> &[usize] {
+        &self.neighbors[i]
+    }
+
+    /// Get the total number of pairs in this list
+    pub fn pairs(&self) -> usize {
+        self.neighbors.iter().map(|neighbors| neighbors.len()).sum()
+    }
+}
+
+/// Default skin distance for neighbor lists, in A
+const DEFAULT_SKIN: f64 = 1.0;
+
+/// Thread-safe neighbor list, updated on demand from a shared reference.
+///
+/// This is used by the `System` for the pair interactions, and by the
+/// potentials that need their own neighbor list with a different cutoff
+/// distance, like the real-space part of coulombic potential solvers.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::{System, Molecule, Particle, UnitCell, SharedNeighborList};
+///
+/// let mut system = System::with_cell(UnitCell::cubic(20.0));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("Ar", [3.0, 0.0, 0.0].into())));
+///
+/// let neighbors = SharedNeighborList::new(0.5);
+/// assert_eq!(neighbors.get(&system, 2.0).pairs(), 0);
+/// assert_eq!(neighbors.get(&system, 4.0).pairs(), 1);
+/// ```
+pub struct SharedNeighborList(RwLock<NeighborList>);
+
+impl SharedNeighborList {
+    /// Create a new empty `SharedNeighborList` with the given `skin`
+    /// distance.
+    ///
+    /// # Panics
+    ///
+    /// If the `skin` distance is negative.
+    pub fn new(skin: f64) -> SharedNeighborList {
+        SharedNeighborList(RwLock::new(NeighborList::new(skin)))
+    }
+
+    /// Get the neighbor list for the `configuration` and the given `cutoff`
+    /// distance, updating it first if needed.
+    pub fn get(&self, configuration: &Configuration, cutoff: f64) -> RwLockReadGuard<NeighborList> {
+        let needs_update = self.read().needs_update(configuration, cutoff);
+        if needs_update {
+            self.write().update(configuration, cutoff);
+        }
+        return self.read();
+    }
+
+    /// Get read access to the underlying neighbor list
+    fn read(&self) -> RwLockReadGuard<NeighborList> {
+        // The lock should never be poisonned, because any panic will unwind
+        // and finish the simulation.
+        self.0.read().expect("neighbor list lock is poisonned")
+    }
+
+    /// Get write access to the underlying neighbor list
+    fn write(&self) -> RwLockWriteGuard<NeighborList> {
+        // The lock should never be poisonned, because any panic will unwind
+        // and finish the simulation.
+        self.0.write().expect("neighbor list lock is poisonned")
+    }
+}
+
+impl Default for SharedNeighborList {
+    /// Create a new empty `SharedNeighborList`, using a skin distance of 1 A
+    fn default() -> SharedNeighborList {
+        SharedNeighborList::new(DEFAULT_SKIN)
+    }
+}
+
+impl Clone for SharedNeighborList {
+    fn clone(&self) -> SharedNeighborList {
+        SharedNeighborList(RwLock::new(self.read().clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::UnitCell;
+    use utils::system_from_xyz;
+
+    #[test]
+    fn update() {
+        let mut system = system_from_xyz(
+            "4
+            cell: 10.0
+            Ar 0.0 0.0 0.0
+            Ar 2.0 0.0 0.0
+            Ar 9.9 0.0 0.0
+            Ar 5.0 5.0 5.0
+            ",
+        );
+
+        let mut list = NeighborList::new(0.5);
+        assert!(list.needs_update(&system, 2.0));
+        list.update(&system, 2.0);
+        assert!(!list.needs_update(&system, 2.0));
+
+        // Periodic boundary conditions are used
+        assert_eq!(list.neighbors(0), &[1, 2]);
+        assert_eq!(list.neighbors(1), &[2]);
+        assert!(list.neighbors(2).is_empty());
+        assert!(list.neighbors(3).is_empty());
+        assert_eq!(list.pairs(), 3);
+
+        // Pairs inside the skin are also in the list
+        list.update(&system, 1.7);
+        assert_eq!(list.neighbors(0), &[1, 2]);
+        assert_eq!(list.neighbors(1), &[2]);
+        list.update(&system, 1.4);
+        assert_eq!(list.neighbors(0), &[2]);
+        assert!(list.neighbors(1).is_empty());
+
+        // Changing the cutoff invalidates the list
+        assert!(list.needs_update(&system, 2.0));
+        list.update(&system, 2.0);
+
+        // Small displacements do not invalidate the list, even across
+        // periodic boundaries
+        system.particles_mut().position[2][0] = 0.1;
+        system.particles_mut().position[3][1] = 5.2;
+        assert!(!list.needs_update(&system, 2.0));
+
+        // Larger displacements do
+        system.particles_mut().position[3][1] = 5.3;
+        assert!(list.needs_update(&system, 2.0));
+        system.particles_mut().position[3][1] = 5.0;
+        assert!(!list.needs_update(&system, 2.0));
+
+        // As well as changing the cell
+        system.cell = UnitCell::cubic(11.0);
+        assert!(list.needs_update(&system, 2.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "the skin distance of a neighbor list can not be negative")]
+    fn negative_skin() {
+        let _ = NeighborList::new(-1.0);
+    }
+}
@@ -0,0 +1,231 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Structural comparison of sets of positions: root mean square deviation
+//! and optimal superposition with the Kabsch algorithm.
+use types::{Matrix3, Vector3D};
+
+/// Compute the root mean square deviation between `positions` and
+/// `reference`, without moving any of them.
+///
+/// # Panics
+///
+/// If `positions` and `reference` do not have the same size, or if they are
+/// empty.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::rmsd;
+/// use lumol_core::types::Vector3D;
+///
+/// let positions = [Vector3D::new(0.0, 0.0, 0.0), Vector3D::new(1.0, 0.0, 0.0)];
+/// let reference = [Vector3D::new(0.0, 0.0, 1.0), Vector3D::new(1.0, 0.0, 1.0)];
+/// assert_eq!(rmsd(&positions, &reference), 1.0);
+/// ```
+pub fn rmsd(positions: &[Vector3D], reference: &[Vector3D]) -> f64 {
+    check_sizes(positions, reference);
+    let sum = positions.iter().zip(reference).map(|(a, b)| (a - b).norm2()).sum::<f64>();
+    return f64::sqrt(sum / positions.len() as f64);
+}
+
+/// Move `positions` to superpose them as well as possible with `reference`,
+/// using a rigid body translation and rotation. The optimal rotation is
+/// found with the [Kabsch algorithm][Kabsch], and never includes a
+/// reflection. This function returns the root mean square deviation between
+/// the moved positions and the reference.
+///
+/// [Kabsch]: https://en.wikipedia.org/wiki/Kabsch_algorithm
+///
+/// # Panics
+///
+/// If `positions` and `reference` do not have the same size, or if they are
+/// empty.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::superpose;
+/// use lumol_core::types::{Matrix3, Vector3D};
+///
+/// let reference = [
+///     Vector3D::new(0.0, 0.0, 0.0),
+///     Vector3D::new(1.0, 0.0, 0.0),
+///     Vector3D::new(0.0, 2.0, 0.0),
+/// ];
+///
+/// let rotation = Matrix3::rotation(&Vector3D::new(1.0, 1.0, 0.0), 0.7);
+/// let mut positions = reference.iter()
+///                              .map(|&r| rotation * r + Vector3D::new(3.0, 0.0, 1.0))
+///                              .collect::<Vec<_>>();
+///
+/// let rmsd = superpose(&mut positions, &reference);
+/// assert!(rmsd < 1e-12);
+/// assert!((positions[2] - reference[2]).norm() < 1e-12);
+/// ```
+pub fn superpose(positions: &mut [Vector3D], reference: &[Vector3D]) -> f64 {
+    check_sizes(positions, reference);
+    let center = geometric_center(positions);
+    let reference_center = geometric_center(reference);
+
+    let mut correlation = Matrix3::zero();
+    for (position, target) in positions.iter().zip(reference) {
+        correlation += (target - reference_center).tensorial(&(position - center));
+    }
+
+    let rotation = kabsch_rotation(&correlation);
+    for position in positions.iter_mut() {
+        *position = rotation * (*position - center) + reference_center;
+    }
+
+    return rmsd(positions, reference);
+}
+
+fn check_sizes(positions: &[Vector3D], reference: &[Vector3D]) {
+    assert_eq!(
+        positions.len(), reference.len(),
+        "positions and reference must have the same size"
+    );
+    assert!(!positions.is_empty(), "can not compare empty sets of positions");
+}
+
+fn geometric_center(positions: &[Vector3D]) -> Vector3D {
+    let sum = positions.iter().fold(Vector3D::zero(), |sum, &position| sum + position);
+    return sum / positions.len() as f64;
+}
+
+/// Get the proper rotation `R` maximizing `Tr(R^T A)`, with `A` the
+/// `correlation` matrix between the reference and the positions.
+///
+/// Using the singular value decomposition `A = U S V^T`, the right singular
+/// vectors `v_i` are the eigenvectors of `A^T A` and the left singular
+/// vectors are `u_i = A v_i / s_i`. The rotation maps the two vectors `v_i`
+/// with the largest singular values to the corresponding `u_i`, and the
+/// third one is chosen to build a right-handed basis, which removes the
+/// reflections.
+fn kabsch_rotation(correlation: &Matrix3) -> Matrix3 {
+    let (values, vectors) = (correlation.transposed() * correlation).symmetric_eigen();
+    let singular = |i: usize| f64::sqrt(f64::max(values[i], 0.0));
+    let column = |i: usize| Vector3D::new(vectors[0][i], vectors[1][i], vectors[2][i]);
+
+    if singular(2) == 0.0 {
+        // All the positions or all the reference are on the same point
+        return Matrix3::one();
+    }
+
+    let v2 = column(2);
+    let u2 = (correlation * v2).normalized();
+    let (v1, u1) = if singular(1) > 1e-8 * singular(2) {
+        let v1 = column(1);
+        let u1 = correlation * v1;
+        (v1, (u1 - (u1 * u2) * u2).normalized())
+    } else {
+        // Linear systems: any rotation around the axis is as good
+        (orthogonal(&v2), orthogonal(&u2))
+    };
+    let v0 = v1 ^ v2;
+    let u0 = u1 ^ u2;
+
+    return u0.tensorial(&v0) + u1.tensorial(&v1) + u2.tensorial(&v2);
+}
+
+/// Get an unit vector orthogonal to the unit vector `vector`
+fn orthogonal(vector: &Vector3D) -> Vector3D {
+    let trial = if vector[0].abs() < 0.9 {
+        Vector3D::new(1.0, 0.0, 0.0)
+    } else {
+        Vector3D::new(0.0, 1.0, 0.0)
+    };
+    return (trial - (trial * vector) * *vector).normalized();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions() -> Vec<Vector3D> {
+        vec![
+            Vector3D::new(0.1, -0.3, 0.4),
+            Vector3D::new(1.2, 0.5, -0.2),
+            Vector3D::new(-0.7, 1.1, 0.3),
+            Vector3D::new(0.4, 0.2, 1.6),
+            Vector3D::new(-1.0, -0.8, -0.5),
+        ]
+    }
+
+    fn moved(positions: &[Vector3D]) -> Vec<Vector3D> {
+        let rotation = Matrix3::rotation(&Vector3D::new(0.3, -1.0, 0.5), 2.1);
+        let translation = Vector3D::new(4.0, -2.0, 1.5);
+        positions.iter().map(|&r| rotation * r + translation).collect()
+    }
+
+    #[test]
+    fn test_rmsd() {
+        let reference = positions();
+        assert_eq!(rmsd(&reference, &reference), 0.0);
+
+        let shifted = reference.iter()
+                               .map(|&r| r + Vector3D::new(0.0, 3.0, 4.0))
+                               .collect::<Vec<_>>();
+        assert_relative_eq!(rmsd(&shifted, &reference), 5.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn rigid_motion() {
+        let reference = positions();
+        let mut positions = moved(&reference);
+        assert!(rmsd(&positions, &reference) > 1.0);
+
+        let rmsd = superpose(&mut positions, &reference);
+        assert!(rmsd < 1e-10);
+        for (position, target) in positions.iter().zip(&reference) {
+            assert_relative_eq!(*position, *target, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn planar_and_linear() {
+        let reference = vec![
+            Vector3D::new(0.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(0.0, 1.5, 0.0),
+        ];
+        let mut positions = moved(&reference);
+        assert!(superpose(&mut positions, &reference) < 1e-10);
+
+        let reference = vec![
+            Vector3D::new(0.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(2.5, 0.0, 0.0),
+        ];
+        let mut positions = moved(&reference);
+        assert!(superpose(&mut positions, &reference) < 1e-10);
+
+        let reference = vec![Vector3D::new(1.0, 2.0, 3.0)];
+        let mut positions = vec![Vector3D::new(-1.0, 0.0, 3.0)];
+        assert_eq!(superpose(&mut positions, &reference), 0.0);
+    }
+
+    #[test]
+    fn no_reflection() {
+        let reference = positions();
+        let mut mirror = reference.iter()
+                                  .map(|&r| Vector3D::new(-r[0], r[1], r[2]))
+                                  .collect::<Vec<_>>();
+        let before = mirror.clone();
+        let rmsd = superpose(&mut mirror, &reference);
+        assert!(rmsd > 0.1);
+
+        // The superposition is a proper rotation, preserving the chirality
+        let chirality = |r: &[Vector3D]| ((r[1] - r[0]) ^ (r[2] - r[0])) * (r[3] - r[0]);
+        assert_relative_eq!(chirality(&mirror[..]), chirality(&before[..]), epsilon = 1e-10);
+    }
+
+    #[test]
+    #[should_panic(expected = "positions and reference must have the same size")]
+    fn different_sizes() {
+        let reference = positions();
+        let mut moved = moved(&reference);
+        let _ = superpose(&mut moved[..3], &reference[..4]);
+    }
+}
@@ -1,16 +1,31 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
-use std::collections::BTreeMap;
-use std::ops::{Deref, DerefMut};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error;
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::RwLockReadGuard;
 
 use types::{Matrix3, Vector3D};
 
-use energy::{AnglePotential, BondPotential, DihedralPotential, PairInteraction};
+use energy::{AnglePotential, BondPotential, Cmap, DihedralPotential, PairInteraction};
 use energy::{CoulombicPotential, GlobalPotential};
 
-use sys::{Composition, EnergyEvaluator, Interactions};
-use sys::{Configuration, Molecule, ParticleKind, UnitCell};
+use sys::{Composition, EnergyBreakdown, EnergyEvaluator, Interactions};
+use sys::{Angle, Bond, Dihedral, Improper};
+use sys::{Configuration, Molecule, MoleculeHash, MoleculeTemplate, ParticleKind};
+use sys::{Property, UnitCell};
+use sys::{CellList, NeighborList, SharedNeighborList, VirtualSite};
+use sys::ForceModifier;
+use sys::get_covalent_radius;
+
+/// Tolerance added to the sum of covalent radii when guessing bonds, in A
+const BOND_TOLERANCE: f64 = 0.45;
+/// Minimal distance between two particles for them to be bonded, in A
+const MIN_BOND_LENGTH: f64 = 0.4;
+/// Particles closer than this distance are considered to overlap, in A
+const OVERLAP_DISTANCE: f64 = 0.5;
 
 /// The number of degrees of freedom simulated in a given system
 #[derive(Clone, PartialEq, Debug)]
@@ -24,6 +39,120 @@ pub enum DegreesOfFreedom {
     Frozen(usize),
 }
 
+/// What to do with a system which is not electrically neutral, when using a
+/// coulombic potential.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NeutralityPolicy {
+    /// Emit a warning and use the system as is
+    Warn,
+    /// Return an error
+    Error,
+    /// Remove the net charge by shifting the charges of all the charged
+    /// particles by the same amount
+    Neutralize,
+}
+
+/// Possible problems found when validating a system with
+/// [`System::validate`](struct.System.html#method.validate).
+#[derive(Clone, PartialEq, Debug)]
+pub enum ValidationError {
+    /// The position of the particle at index `particle` is not finite
+    NonFinitePosition {
+        /// Index of the particle
+        particle: usize,
+    },
+    /// The particles at indexes `i` and `j` overlap
+    Overlap {
+        /// Index of the first particle
+        i: usize,
+        /// Index of the second particle
+        j: usize,
+        /// Distance between the particles
+        distance: f64,
+    },
+    /// The particle at index `particle` is outside of the xy plane, or moves
+    /// outside of it, in a two-dimensional system
+    OutOfPlane {
+        /// Index of the particle
+        particle: usize,
+    },
+    /// The bond between the particles at indexes `i` and `j` is longer than
+    /// half of the unit cell, and the minimal image convention will not give
+    /// the right bond length
+    LongBond {
+        /// Index of the first particle
+        i: usize,
+        /// Index of the second particle
+        j: usize,
+        /// Length of the bond
+        length: f64,
+    },
+    /// No pair potential is defined between these particle types
+    MissingPair(String, String),
+    /// No bond potential is defined for these particle types
+    MissingBond(String, String),
+    /// No angle potential is defined for these particle types
+    MissingAngle(String, String, String),
+    /// No dihedral angle potential is defined for these particle types
+    MissingDihedral(String, String, String, String),
+    /// No improper dihedral angle potential is defined for these particle
+    /// types, with the central particle first
+    MissingImproper(String, String, String, String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ValidationError::NonFinitePosition { particle } => {
+                write!(fmt, "the position of particle {} is not finite", particle)
+            }
+            ValidationError::Overlap { i, j, distance } => {
+                write!(fmt, "particles {} and {} overlap, at a distance of {} A", i, j, distance)
+            }
+            ValidationError::OutOfPlane { particle } => write!(
+                fmt, "particle {} is outside of the xy plane in a two-dimensional system",
+                particle
+            ),
+            ValidationError::LongBond { i, j, length } => write!(
+                fmt, "the bond between particles {} and {} is longer than half the cell ({} A)",
+                i, j, length
+            ),
+            ValidationError::MissingPair(ref a, ref b) => {
+                write!(fmt, "no potential defined for the pair ({}, {})", a, b)
+            }
+            ValidationError::MissingBond(ref a, ref b) => {
+                write!(fmt, "no potential defined for the bond ({}, {})", a, b)
+            }
+            ValidationError::MissingAngle(ref a, ref b, ref c) => {
+                write!(fmt, "no potential defined for the angle ({}, {}, {})", a, b, c)
+            }
+            ValidationError::MissingDihedral(ref a, ref b, ref c, ref d) => write!(
+                fmt, "no potential defined for the dihedral angle ({}, {}, {}, {})", a, b, c, d
+            ),
+            ValidationError::MissingImproper(ref a, ref b, ref c, ref d) => write!(
+                fmt, "no potential defined for the improper dihedral angle ({}, {}, {}, {})",
+                a, b, c, d
+            ),
+        }
+    }
+}
+
+impl error::Error for ValidationError {
+    fn description(&self) -> &str {
+        match *self {
+            ValidationError::NonFinitePosition { .. } => "non finite position",
+            ValidationError::Overlap { .. } => "overlapping particles",
+            ValidationError::OutOfPlane { .. } => "particle outside of the xy plane",
+            ValidationError::LongBond { .. } => "bond longer than half the cell",
+            ValidationError::MissingPair(..) => "missing pair potential",
+            ValidationError::MissingBond(..) => "missing bond potential",
+            ValidationError::MissingAngle(..) => "missing angle potential",
+            ValidationError::MissingDihedral(..) => "missing dihedral angle potential",
+            ValidationError::MissingImproper(..) => "missing improper dihedral angle potential",
+        }
+    }
+}
+
 /// The `System` type hold all the data about a simulated system.
 ///
 /// This data contains:
@@ -42,15 +171,31 @@ pub struct System {
     configuration: Configuration,
     /// All the interactions in this system
     interactions: Interactions,
-    /// Association particles names to particle kinds
+    /// Association particles types to particle kinds
     kinds: BTreeMap<String, ParticleKind>,
     /// Externally managed temperature for the system
     external_temperature: Option<f64>,
+    /// Neighbor list for the pair interactions
+    neighbors: SharedNeighborList,
+    /// Virtual sites in the system
+    virtual_sites: Vec<VirtualSite>,
+    /// Hooks adding custom forces to the particles
+    force_modifiers: Vec<Box<ForceModifier>>,
+    /// User-defined names of the molecule types in the system
+    molecule_names: BTreeMap<MoleculeHash, String>,
     /// Number of degrees of freedom simulated in the system. This default to
     /// `DegreesOfFreedom::Particles`, and is set in the simulation setup.
     pub simulated_degrees_of_freedom: DegreesOfFreedom,
+    /// Number of spatial dimensions of the system, either 2 or 3
+    dimensions: usize,
     /// The current simulation step
     pub step: u64,
+    /// Energy of the additional degrees of freedom of the propagator (heat
+    /// bath, barostat, …), such that the sum of this energy and the total
+    /// energy of the system is conserved. This is set by the propagator
+    /// during a simulation, and is `None` if the propagator does not have a
+    /// conserved quantity.
+    pub extended_energy: Option<f64>,
 }
 
 impl System {
@@ -73,29 +218,387 @@ impl System {
             kinds: BTreeMap::new(),
             interactions: Interactions::new(),
             step: 0,
+            extended_energy: None,
             external_temperature: None,
+            neighbors: SharedNeighborList::default(),
+            virtual_sites: Vec::new(),
+            force_modifiers: Vec::new(),
+            molecule_names: BTreeMap::new(),
             simulated_degrees_of_freedom: DegreesOfFreedom::Particles,
+            dimensions: 3,
         }
     }
 
-    fn get_kind(&mut self, name: &str) -> ParticleKind {
-        if let Some(&kind) = self.kinds.get(name) {
+    fn get_kind(&mut self, atom_type: &str) -> ParticleKind {
+        if let Some(&kind) = self.kinds.get(atom_type) {
             return kind;
         } else {
             let kind = ParticleKind(self.kinds.len() as u32);
-            let _ = self.kinds.insert(String::from(name), kind);
+            let _ = self.kinds.insert(String::from(atom_type), kind);
             kind
         }
     }
 
     /// Add a molecule to the system
     pub fn add_molecule(&mut self, mut molecule: Molecule) {
-        for (kind, name) in soa_zip!(molecule.particles_mut(), [mut kind, name]) {
-            *kind = self.get_kind(name);
+        for (kind, atom_type) in soa_zip!(molecule.particles_mut(), [mut kind, atom_type]) {
+            *kind = self.get_kind(atom_type);
         }
         self.configuration.add_molecule(molecule);
     }
 
+    /// Add a molecule to the system, after checking that all the interactions
+    /// it needs are defined: pair potentials between the particles in the
+    /// molecule and all the particles in the system, and potentials for all
+    /// the bonds, angles, dihedral angles and improper dihedral angles in the
+    /// molecule. If some interactions are missing, the molecule is not added
+    /// and all the missing interactions are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, Molecule, Particle, ValidationError};
+    /// # use lumol_core::energy::{PairInteraction, NullPotential};
+    /// let mut system = System::new();
+    /// let result = system.try_add_molecule(Molecule::new(Particle::new("Ar")));
+    /// assert_eq!(result, Err(vec![ValidationError::MissingPair("Ar".into(), "Ar".into())]));
+    /// assert_eq!(system.size(), 0);
+    ///
+    /// let pair = PairInteraction::new(Box::new(NullPotential), 10.0);
+    /// system.add_pair_potential(("Ar", "Ar"), pair);
+    /// assert!(system.try_add_molecule(Molecule::new(Particle::new("Ar"))).is_ok());
+    /// assert_eq!(system.size(), 1);
+    /// ```
+    pub fn try_add_molecule(&mut self, molecule: Molecule) -> Result<(), Vec<ValidationError>> {
+        self.add_molecule(molecule);
+        let molid = self.molecules().count() - 1;
+
+        let mut errors = Vec::new();
+        self.validate_interactions(molid..molid + 1, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            let _ = self.remove_molecule(molid);
+            Err(errors)
+        }
+    }
+
+    /// Add `n` copies of the molecule described by `template` to the system.
+    ///
+    /// The copies keep the orientation of the template, and their geometric
+    /// centers are placed on a regular grid filling the unit cell.
+    ///
+    /// # Panics
+    ///
+    /// If the unit cell is infinite, or if the template is empty.
+    pub fn add_molecules(&mut self, template: &MoleculeTemplate, n: usize) {
+        assert!(
+            !self.cell.is_infinite(),
+            "can not add molecules from a template in an infinite cell"
+        );
+        let molecule = template.molecule();
+        let center = template.center();
+
+        // Use the smallest grid with at least n points
+        let mut size = 1;
+        while size * size * size < n {
+            size += 1;
+        }
+
+        for index in 0..n {
+            let fractional = Vector3D::new(
+                ((index / (size * size)) as f64 + 0.5) / size as f64,
+                ((index / size % size) as f64 + 0.5) / size as f64,
+                ((index % size) as f64 + 0.5) / size as f64,
+            );
+            let delta = self.cell.cartesian(&fractional) - center;
+
+            let mut molecule = molecule.clone();
+            for position in molecule.particles_mut().position {
+                *position += delta;
+            }
+            self.add_molecule(molecule);
+        }
+    }
+
+    /// Replicate the system `nx`, `ny` and `nz` times along the first, second
+    /// and third cell vectors, and scale the unit cell accordingly.
+    ///
+    /// All the molecules are copied with their bonds, particle kinds,
+    /// charges and velocities, and shifted by the corresponding combination
+    /// of cell vectors. The virtual sites and the custom per-particle
+    /// properties are replicated too, but the restraints are not.
+    ///
+    /// # Panics
+    ///
+    /// If the unit cell is infinite, or if `nx`, `ny` or `nz` is zero.
+    pub fn replicate(&mut self, nx: usize, ny: usize, nz: usize) {
+        assert!(!self.cell.is_infinite(), "can not replicate a system with an infinite cell");
+        assert!(
+            nx > 0 && ny > 0 && nz > 0,
+            "the system must be replicated at least once along each cell vector"
+        );
+
+        let size = self.size();
+        let molecules = self.molecules().map(|molecule| molecule.to_owned()).collect::<Vec<_>>();
+        let virtual_sites = self.virtual_sites.clone();
+
+        let mut replica = 0;
+        for i in 0..nx {
+            for j in 0..ny {
+                for k in 0..nz {
+                    if i == 0 && j == 0 && k == 0 {
+                        continue;
+                    }
+                    replica += 1;
+
+                    let shift = self.cell.cartesian(&Vector3D::new(i as f64, j as f64, k as f64));
+                    for molecule in &molecules {
+                        let mut molecule = molecule.clone();
+                        for position in molecule.particles_mut().position {
+                            *position += shift;
+                        }
+                        self.add_molecule(molecule);
+                    }
+
+                    for site in &virtual_sites {
+                        let mut site = site.clone();
+                        site.translate_by((replica * size) as isize);
+                        self.virtual_sites.push(site);
+                    }
+                }
+            }
+        }
+
+        let total = self.size();
+        let properties = self.properties()
+                             .map(|(name, property)| (name.clone(), property.clone()))
+                             .collect::<Vec<_>>();
+        for (name, property) in properties {
+            let property = match property {
+                Property::Scalar(values) => {
+                    Property::Scalar(values.iter().cloned().cycle().take(total).collect())
+                }
+                Property::Vector(values) => {
+                    Property::Vector(values.iter().cloned().cycle().take(total).collect())
+                }
+            };
+            self.add_property(name, property);
+        }
+
+        let factor = Matrix3::new([
+            [nx as f64, 0.0, 0.0],
+            [0.0, ny as f64, 0.0],
+            [0.0, 0.0, nz as f64],
+        ]);
+        self.cell.scale_mut(factor);
+    }
+
+    /// Add all the molecules and virtual sites of the `other` system to this
+    /// system, after the particles already in it.
+    ///
+    /// The particles keep their positions, and their kinds are set from their
+    /// types in this system. The molecule names of the `other` system are
+    /// added to the ones of this system. The unit cell, the interactions and
+    /// the custom per-particle properties of the `other` system are not used.
+    ///
+    /// # Panics
+    ///
+    /// If the same molecule name is used for different molecule types in the
+    /// two systems.
+    pub fn merge(&mut self, other: &System) {
+        for (&hash, name) in &other.molecule_names {
+            self.set_molecule_name(hash, name.clone());
+        }
+        let offset = self.size() as isize;
+        for molecule in other.molecules() {
+            self.add_molecule(molecule.to_owned());
+        }
+        for site in &other.virtual_sites {
+            let mut site = site.clone();
+            site.translate_by(offset);
+            self.virtual_sites.push(site);
+        }
+    }
+
+    /// Remove the molecule at index `molid` from this system, and return it.
+    ///
+    /// The particles after the removed molecule are moved to fill the gap,
+    /// and the indexes of the particles, molecules and virtual sites after it
+    /// are shifted accordingly. The virtual sites of the removed molecule are
+    /// removed with it. The particles kinds are kept, so the interactions
+    /// stay valid and the molecule can be added back later with
+    /// [`add_molecule`](#method.add_molecule).
+    ///
+    /// # Panics
+    ///
+    /// If `molid` is not a valid molecule index, or if a virtual site depends
+    /// on particles both inside and outside of the removed molecule.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, Molecule, Particle};
+    /// let mut system = System::new();
+    /// system.add_molecule(Molecule::new(Particle::new("Ar")));
+    /// system.add_molecule(Molecule::new(Particle::new("Kr")));
+    /// system.add_molecule(Molecule::new(Particle::new("Xe")));
+    ///
+    /// let krypton = system.remove_molecule(1);
+    /// assert_eq!(krypton.particles().name[0], "Kr");
+    ///
+    /// assert_eq!(system.size(), 2);
+    /// assert_eq!(system.particles().name[1], "Xe");
+    /// assert_eq!(system.molecule_id(1), 1);
+    /// ```
+    pub fn remove_molecule(&mut self, molid: usize) -> Molecule {
+        let count = self.molecules().count();
+        assert!(
+            molid < count,
+            "can not remove molecule {}, the system only contains {} molecules", molid, count
+        );
+
+        let (molecule, start, end) = {
+            let molecule = self.molecule(molid);
+            let indexes = molecule.indexes();
+            (molecule.to_owned(), indexes.start, indexes.end)
+        };
+        let inside = |i: usize| start <= i && i < end;
+
+        let mut removed = Vec::new();
+        for (index, site) in self.virtual_sites.iter().enumerate() {
+            let parents = site.parents().iter().filter(|&&i| inside(i)).count();
+            if inside(site.site()) && parents == 3 {
+                removed.push(index);
+            } else {
+                assert!(
+                    !inside(site.site()) && parents == 0,
+                    "can not remove molecule {}, the virtual site {} depends on particles \
+                     inside and outside of this molecule", molid, site.site()
+                );
+            }
+        }
+
+        for &index in removed.iter().rev() {
+            let _ = self.virtual_sites.remove(index);
+        }
+        let size = end - start;
+        for site in &mut self.virtual_sites {
+            site.map_indexes(|i| if i >= end { i - size } else { i });
+        }
+
+        self.configuration.remove_molecule(molid);
+        return molecule;
+    }
+
+    /// Guess the bonds in the system from the distances between particles.
+    ///
+    /// Two particles are considered bonded if the distance between them
+    /// (using the minimum image convention) is smaller than the sum of their
+    /// covalent radii plus 0.45 A, and bigger than 0.4 A. The covalent radius
+    /// is taken from the particle type if it is an element, and from the
+    /// particle name otherwise. Particles for which neither the type nor the
+    /// name is an element with a known covalent radius are never bonded.
+    ///
+    /// Adding bonds merge the corresponding molecules, which can change the
+    /// indexes of the particles in the system.
+    pub fn guess_bonds(&mut self) {
+        let radii = soa_zip!(self.particles(), [atom_type, name]).map(|(atom_type, name)| {
+            get_covalent_radius(atom_type).or_else(|| get_covalent_radius(name))
+        }).collect::<Vec<_>>();
+        let max_radius = radii.iter().filter_map(|&radius| radius).fold(0.0, f64::max);
+        if max_radius == 0.0 {
+            return;
+        }
+
+        let cells = CellList::new(&self.configuration, 2.0 * max_radius + BOND_TOLERANCE);
+        let mut bonds = Vec::new();
+        for i in 0..self.size() {
+            let radius_i = match radii[i] {
+                Some(radius) => radius,
+                None => continue,
+            };
+            for j in cells.neighbors(i) {
+                if let Some(radius_j) = radii[j] {
+                    let distance = self.distance(i, j);
+                    let max_distance = radius_i + radius_j + BOND_TOLERANCE;
+                    if distance > MIN_BOND_LENGTH && distance < max_distance {
+                        bonds.push((i, j));
+                    }
+                }
+            }
+        }
+
+        while let Some((i, j)) = bonds.pop() {
+            let permutations = self.add_bond(i, j);
+            // Update the indexes of the remaining bonds, stopping at the
+            // first matching permutation for each particle
+            let permute = |i: usize| {
+                permutations.iter()
+                            .find(|permutation| permutation.0 == i)
+                            .map_or(i, |permutation| permutation.1)
+            };
+            for bond in &mut bonds {
+                *bond = (permute(bond.0), permute(bond.1));
+            }
+        }
+    }
+
+    /// Add a virtual `site` to the system, and set the position and velocity
+    /// of the corresponding particle from its parents. The particle of the
+    /// virtual site must be massless.
+    ///
+    /// The virtual sites use the indexes of the particles when they are
+    /// added, and are not updated if the particles are moved to a different
+    /// index, for example when adding bonds or removing molecules.
+    ///
+    /// # Panics
+    ///
+    /// If the virtual site or its parents are not in the system, if the
+    /// virtual site particle has a non-zero mass, or if this particle is
+    /// already a virtual site.
+    pub fn add_virtual_site(&mut self, site: VirtualSite) {
+        let index = site.site();
+        assert!(
+            index < self.size() && site.parents().iter().all(|&i| i < self.size()),
+            "virtual site {} or its parents are not in the system", index
+        );
+        assert!(
+            self.particles().mass[index] == 0.0,
+            "the particle of a virtual site must be massless, got a mass of {} for particle {}",
+            self.particles().mass[index], index
+        );
+        assert!(
+            self.virtual_sites.iter().all(|other| other.site() != index),
+            "particle {} is already a virtual site", index
+        );
+        self.virtual_sites.push(site);
+        self.update_virtual_sites();
+    }
+
+    /// Get the virtual sites in this system
+    pub fn virtual_sites(&self) -> &[VirtualSite] {
+        &self.virtual_sites
+    }
+
+    /// Update the positions and velocities of all the virtual sites in the
+    /// system from the positions and velocities of their parents. This
+    /// should be called every time the particles are moved.
+    pub fn update_virtual_sites(&mut self) {
+        for site in &self.virtual_sites {
+            let (position, velocity) = {
+                let cell = &self.configuration.cell;
+                let particles = self.configuration.particles();
+                (
+                    site.position(cell, particles.position),
+                    site.velocity(cell, particles.position, particles.velocity),
+                )
+            };
+            self.configuration.particles_mut().position[site.site()] = position;
+            self.configuration.particles_mut().velocity[site.site()] = velocity;
+        }
+    }
+
     /// Get the composition in particles and molecules of the configuration
     pub fn composition(&self) -> Composition {
         let mut composition = Composition::new();
@@ -105,9 +608,55 @@ impl System {
         for molecule in self.molecules() {
             composition.add_molecule(molecule.hash());
         }
+        for (&hash, name) in &self.molecule_names {
+            composition.set_name(hash, name.clone());
+        }
         return composition;
     }
 
+    /// Set the `name` of the molecules with the given `hash` in this system.
+    /// This name can then be used to refer to this molecule type in input
+    /// files and outputs.
+    ///
+    /// # Panics
+    ///
+    /// If the same name is already used for another molecule type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, Molecule, Particle};
+    /// let mut system = System::new();
+    /// let argon = Molecule::new(Particle::new("Ar"));
+    /// let hash = argon.hash();
+    /// system.add_molecule(argon);
+    ///
+    /// system.set_molecule_name(hash, "argon");
+    /// assert_eq!(system.molecule_name(hash), Some("argon"));
+    /// assert_eq!(system.molecule_hash("argon"), Some(hash));
+    /// assert_eq!(system.composition().name(hash), Some("argon"));
+    /// ```
+    pub fn set_molecule_name<S: Into<String>>(&mut self, hash: MoleculeHash, name: S) {
+        let name = name.into();
+        if let Some(other) = self.molecule_hash(&name) {
+            assert!(
+                other == hash,
+                "the '{}' name is already used for another molecule type", name
+            );
+        }
+        let _ = self.molecule_names.insert(hash, name);
+    }
+
+    /// Get the name of the molecules with the given `hash`, if any
+    pub fn molecule_name(&self, hash: MoleculeHash) -> Option<&str> {
+        self.molecule_names.get(&hash).map(|name| name.as_str())
+    }
+
+    /// Get the hash of the molecules with the given `name`, if any
+    pub fn molecule_hash(&self, name: &str) -> Option<MoleculeHash> {
+        self.molecule_names.iter().find(|&(_, n)| n == name).map(|(&hash, _)| hash)
+    }
+
     /// Use an external temperature for all the system properties. Calling this
     /// with `Some(temperature)` will replace all the computation of the
     /// temperature from the velocities with the given values. Calling it with
@@ -120,6 +669,53 @@ impl System {
         }
         self.external_temperature = temperature;
     }
+
+    /// Get the number of spatial dimensions of this system, either 2 or 3.
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Set the number of spatial dimensions of this system to 2 or 3. Two
+    /// dimensional systems live in the xy plane: all the particles should
+    /// have a zero `z` position and velocity, and the first two vectors of
+    /// the unit cell must be in the xy plane. The cell periodicity along its
+    /// third vector is removed when using two dimensions, and is not
+    /// restored when going back to three dimensions.
+    ///
+    /// In two dimensions, each particle only has two degrees of freedom,
+    /// the volume of the system is the area of the cell and the pressure is
+    /// computed accordingly. Long range corrections for pair potentials are
+    /// not used.
+    ///
+    /// # Panics
+    ///
+    /// If `dimensions` is neither 2 nor 3, or if the first two cell vectors
+    /// are not in the xy plane when using two dimensions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, UnitCell};
+    /// let mut system = System::with_cell(UnitCell::ortho(10.0, 20.0, 10.0));
+    /// system.set_dimensions(2);
+    ///
+    /// assert_eq!(system.dimensions(), 2);
+    /// assert_eq!(system.volume(), 200.0);
+    /// assert_eq!(system.cell.periodicity(), [true, true, false]);
+    /// ```
+    pub fn set_dimensions(&mut self, dimensions: usize) {
+        assert!(dimensions == 2 || dimensions == 3, "systems must have 2 or 3 dimensions");
+        if dimensions == 2 && !self.cell.is_infinite() {
+            let matrix = self.cell.matrix();
+            assert!(
+                matrix[2][0] == 0.0 && matrix[2][1] == 0.0,
+                "the first two cell vectors must be in the xy plane for two-dimensional systems"
+            );
+            let periodic = self.cell.periodicity();
+            self.cell.set_periodicity([periodic[0], periodic[1], false]);
+        }
+        self.dimensions = dimensions;
+    }
 }
 
 /// Functions related to interactions
@@ -162,6 +758,19 @@ impl System {
         self.interactions.add_angle((kind_i, kind_j, kind_k), potential)
     }
 
+    /// Set the Urey-Bradley `potential` for the angle `(i, j, k)`. This
+    /// potential acts on the distance between the particles `i` and `k`.
+    pub fn set_urey_bradley_potential(
+        &mut self,
+        (i, j, k): (&str, &str, &str),
+        potential: Box<BondPotential>,
+    ) {
+        let kind_i = self.get_kind(i);
+        let kind_j = self.get_kind(j);
+        let kind_k = self.get_kind(k);
+        self.interactions.set_urey_bradley((kind_i, kind_j, kind_k), potential)
+    }
+
     /// Add the `potential` dihedral interaction for the dihedral angle `(i, j,
     /// k, m)`
     pub fn add_dihedral_potential(
@@ -176,6 +785,42 @@ impl System {
         self.interactions.add_dihedral((kind_i, kind_j, kind_k, kind_m), potential)
     }
 
+    /// Add the `potential` improper dihedral interaction for the improper
+    /// dihedral angle `(i, j, k, m)`, where `i` is the central particle.
+    pub fn add_improper_potential(
+        &mut self,
+        (i, j, k, m): (&str, &str, &str, &str),
+        potential: Box<DihedralPotential>,
+    ) {
+        let kind_i = self.get_kind(i);
+        let kind_j = self.get_kind(j);
+        let kind_k = self.get_kind(k);
+        let kind_m = self.get_kind(m);
+        self.interactions.add_improper((kind_i, kind_j, kind_k, kind_m), potential)
+    }
+
+    /// Set the `cmap` correction for the pairs of consecutive dihedral angles
+    /// formed by the particles `(i, j, k, m, n)`. The first angle of the map
+    /// is the dihedral angle `(i, j, k, m)`, and the second one is the
+    /// dihedral angle `(j, k, m, n)`.
+    ///
+    /// # Panics
+    ///
+    /// If the particles names are the same in reverse order and the `cmap`
+    /// is not symmetric.
+    pub fn set_cmap_potential(
+        &mut self,
+        (i, j, k, m, n): (&str, &str, &str, &str, &str),
+        cmap: Cmap,
+    ) {
+        let kind_i = self.get_kind(i);
+        let kind_j = self.get_kind(j);
+        let kind_k = self.get_kind(k);
+        let kind_m = self.get_kind(m);
+        let kind_n = self.get_kind(n);
+        self.interactions.set_cmap((kind_i, kind_j, kind_k, kind_m, kind_n), cmap)
+    }
+
     /// Set the coulombic interaction for all pairs to `potential`
     pub fn set_coulomb_potential(&mut self, potential: Box<CoulombicPotential>) {
         if let Some(cutoff) = potential.cutoff() {
@@ -195,6 +840,19 @@ impl System {
         self.interactions.globals.push(potential);
     }
 
+    /// Add the `modifier` to the list of hooks adding custom forces to the
+    /// particles each time the forces are computed. See the
+    /// [`ForceModifier`](trait.ForceModifier.html) documentation for more
+    /// information.
+    pub fn add_force_modifier(&mut self, modifier: Box<ForceModifier>) {
+        self.force_modifiers.push(modifier);
+    }
+
+    /// Get all the force modifiers in this system
+    pub fn force_modifiers(&self) -> &[Box<ForceModifier>] {
+        &self.force_modifiers
+    }
+
     /// Get the list of pair potential acting between the particles at indexes
     /// `i` and `j`.
     pub fn pair_potentials(&self, i: usize, j: usize) -> &[PairInteraction] {
@@ -203,8 +861,8 @@ impl System {
         let pairs = self.interactions.pairs((kind_i, kind_j));
         if pairs.is_empty() {
             // Use the same sorting as interactions
-            let name_i = &self.particles().name[i];
-            let name_j = &self.particles().name[j];
+            let name_i = &self.particles().atom_type[i];
+            let name_j = &self.particles().atom_type[j];
             let (name_i, name_j) = if name_i < name_j {
                 (name_i, name_j)
             } else {
@@ -220,6 +878,115 @@ impl System {
         &self.interactions
     }
 
+    /// Get the particle types of the bond between the particles at indexes
+    /// `i` and `j`, using the same sorting as the interactions
+    fn bond_type(&self, i: usize, j: usize) -> (&str, &str) {
+        let name_i = &*self.particles().atom_type[i];
+        let name_j = &*self.particles().atom_type[j];
+        if name_i < name_j {
+            (name_i, name_j)
+        } else {
+            (name_j, name_i)
+        }
+    }
+
+    /// Get the particle types of the angle between the particles at indexes
+    /// `i`, `j` and `k`, using the same sorting as the interactions
+    fn angle_type(&self, i: usize, j: usize, k: usize) -> (&str, &str, &str) {
+        let name_i = &*self.particles().atom_type[i];
+        let name_j = &*self.particles().atom_type[j];
+        let name_k = &*self.particles().atom_type[k];
+        if name_i < name_k {
+            (name_i, name_j, name_k)
+        } else {
+            (name_k, name_j, name_i)
+        }
+    }
+
+    /// Get the particle types of the dihedral angle between the particles at
+    /// indexes `i`, `j`, `k` and `m`, using the same sorting as the
+    /// interactions
+    fn dihedral_type(&self, i: usize, j: usize, k: usize, m: usize) -> (&str, &str, &str, &str) {
+        let name_i = &*self.particles().atom_type[i];
+        let name_j = &*self.particles().atom_type[j];
+        let name_k = &*self.particles().atom_type[k];
+        let name_m = &*self.particles().atom_type[m];
+        let max_ij = ::std::cmp::max(name_i, name_j);
+        let max_km = ::std::cmp::max(name_k, name_m);
+        if max_ij == max_km {
+            if ::std::cmp::min(name_i, name_j) < ::std::cmp::min(name_k, name_m) {
+                (name_i, name_j, name_k, name_m)
+            } else {
+                (name_m, name_k, name_j, name_i)
+            }
+        } else if max_ij < max_km {
+            (name_i, name_j, name_k, name_m)
+        } else {
+            (name_m, name_k, name_j, name_i)
+        }
+    }
+
+    /// Get the particle types of all the bonds in this system. Each bond type
+    /// is sorted in the same way as the interactions, and can be used to
+    /// check which bond potentials are needed for this system.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, MoleculeTemplate};
+    /// # use lumol_core::types::Vector3D;
+    /// let water = MoleculeTemplate::new()
+    ///     .particle("O", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+    ///     .particle("H", Vector3D::new(1.0, 0.0, 0.0), 0.0)
+    ///     .particle("H", Vector3D::new(0.0, 1.0, 0.0), 0.0)
+    ///     .bond(0, 1)
+    ///     .bond(0, 2);
+    ///
+    /// let mut system = System::new();
+    /// system.add_molecules(&water, 10);
+    ///
+    /// let bonds = system.bond_types();
+    /// assert_eq!(bonds.len(), 1);
+    /// assert!(bonds.contains(&("H".into(), "O".into())));
+    ///
+    /// let angles = system.angle_types();
+    /// assert_eq!(angles.len(), 1);
+    /// assert!(angles.contains(&("H".into(), "O".into(), "H".into())));
+    /// ```
+    pub fn bond_types(&self) -> BTreeSet<(String, String)> {
+        let mut types = BTreeSet::new();
+        for bond in self.bonds() {
+            let (name_i, name_j) = self.bond_type(bond.i(), bond.j());
+            let _ = types.insert((name_i.into(), name_j.into()));
+        }
+        return types;
+    }
+
+    /// Get the particle types of all the angles in this system. Each angle
+    /// type is sorted in the same way as the interactions, and can be used to
+    /// check which angle potentials are needed for this system.
+    pub fn angle_types(&self) -> BTreeSet<(String, String, String)> {
+        let mut types = BTreeSet::new();
+        for angle in self.angles() {
+            let (name_i, name_j, name_k) = self.angle_type(angle.i(), angle.j(), angle.k());
+            let _ = types.insert((name_i.into(), name_j.into(), name_k.into()));
+        }
+        return types;
+    }
+
+    /// Get the particle types of all the dihedral angles in this system. Each
+    /// dihedral type is sorted in the same way as the interactions, and can
+    /// be used to check which dihedral potentials are needed for this system.
+    pub fn dihedral_types(&self) -> BTreeSet<(String, String, String, String)> {
+        let mut types = BTreeSet::new();
+        for dihedral in self.dihedrals() {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let (name_i, name_j, name_k, name_m) = self.dihedral_type(i, j, k, m);
+            let _ = types.insert((name_i.into(), name_j.into(), name_k.into(), name_m.into()));
+        }
+        return types;
+    }
+
     /// Get the list of bonded potential acting between the particles at indexes
     /// `i` and `j`.
     pub fn bond_potentials(&self, i: usize, j: usize) -> &[Box<BondPotential>] {
@@ -227,14 +994,7 @@ impl System {
         let kind_j = self.particles().kind[j];
         let bonds = self.interactions.bonds((kind_i, kind_j));
         if bonds.is_empty() {
-            // Use the same sorting as interactions
-            let name_i = &self.particles().name[i];
-            let name_j = &self.particles().name[j];
-            let (name_i, name_j) = if name_i < name_j {
-                (name_i, name_j)
-            } else {
-                (name_j, name_i)
-            };
+            let (name_i, name_j) = self.bond_type(i, j);
             warn_once!("No potential defined for the bond ({}, {})", name_i, name_j);
         }
         return bonds;
@@ -247,21 +1007,23 @@ impl System {
         let kind_j = self.particles().kind[j];
         let kind_k = self.particles().kind[k];
         let angles = self.interactions.angles((kind_i, kind_j, kind_k));
-        if angles.is_empty() {
-            // Use the same sorting as interactions
-            let name_i = &self.particles().name[i];
-            let name_j = &self.particles().name[j];
-            let name_k = &self.particles().name[k];
-            let (name_i, name_j, name_k) = if name_i < name_k {
-                (name_i, name_j, name_k)
-            } else {
-                (name_k, name_j, name_i)
-            };
+        let urey_bradley = self.interactions.urey_bradley((kind_i, kind_j, kind_k));
+        if angles.is_empty() && urey_bradley.is_none() {
+            let (name_i, name_j, name_k) = self.angle_type(i, j, k);
             warn_once!("No potential defined for the angle ({}, {}, {})", name_i, name_j, name_k);
         }
         return angles;
     }
 
+    /// Get the Urey-Bradley interaction acting between the particles at
+    /// indexes `i` and `k` in the angle `i`, `j`, `k`, if any.
+    pub fn urey_bradley_potential(&self, i: usize, j: usize, k: usize) -> Option<&BondPotential> {
+        let kind_i = self.particles().kind[i];
+        let kind_j = self.particles().kind[j];
+        let kind_k = self.particles().kind[k];
+        self.interactions.urey_bradley((kind_i, kind_j, kind_k))
+    }
+
     /// Get the list of dihedral angles interaction acting between the particles
     /// at indexes `i`, `j`, `k` and `m`.
     pub fn dihedral_potentials(
@@ -277,29 +1039,144 @@ impl System {
         let kind_m = self.particles().kind[m];
         let dihedrals = self.interactions.dihedrals((kind_i, kind_j, kind_k, kind_m));
         if dihedrals.is_empty() {
-            // Use the same sorting as interactions
-            let name_i = &self.particles().name[i];
-            let name_j = &self.particles().name[j];
-            let name_k = &self.particles().name[k];
-            let name_m = &self.particles().name[m];
-            let max_ij = ::std::cmp::max(name_i, name_j);
-            let max_km = ::std::cmp::max(name_k, name_m);
-            let (name_i, name_j, name_k, name_m) = if max_ij == max_km {
-                if ::std::cmp::min(name_i, name_j) < ::std::cmp::min(name_k, name_m) {
-                    (name_i, name_j, name_k, name_m)
-                } else {
-                    (name_m, name_k, name_j, name_i)
-                }
-            } else if max_ij < max_km {
-                (name_i, name_j, name_k, name_m)
-            } else {
-                (name_m, name_k, name_j, name_i)
-            };
+            let (name_i, name_j, name_k, name_m) = self.dihedral_type(i, j, k, m);
             warn_once!("No potential defined for the dihedral angle ({}, {}, {}, {})", name_i, name_j, name_k, name_m);
         }
         return dihedrals;
     }
 
+    /// Get the list of improper dihedral angles interaction acting between the
+    /// particles at indexes `i`, `j`, `k` and `m`, where `i` is the central
+    /// particle.
+    pub fn improper_potentials(
+        &self,
+        i: usize,
+        j: usize,
+        k: usize,
+        m: usize,
+    ) -> &[Box<DihedralPotential>] {
+        let kind_i = self.particles().kind[i];
+        let kind_j = self.particles().kind[j];
+        let kind_k = self.particles().kind[k];
+        let kind_m = self.particles().kind[m];
+        let impropers = self.interactions.impropers((kind_i, kind_j, kind_k, kind_m));
+        if impropers.is_empty() {
+            // Use the same sorting as interactions
+            let mut others = [
+                &self.particles().atom_type[j],
+                &self.particles().atom_type[k],
+                &self.particles().atom_type[m],
+            ];
+            others.sort();
+            let name_i = &self.particles().atom_type[i];
+            warn_once!(
+                "No potential defined for the improper dihedral angle ({}, {}, {}, {})",
+                name_i, others[0], others[1], others[2]
+            );
+        }
+        return impropers;
+    }
+
+    /// Get the CMAP correction acting on the pair of dihedral angles formed by
+    /// the particles at indexes `i`, `j`, `k`, `m` and `n`, if any. Most
+    /// pairs of dihedral angles do not have a CMAP correction, so no warning
+    /// is emitted when the correction is missing.
+    pub fn cmap_potential(
+        &self,
+        i: usize,
+        j: usize,
+        k: usize,
+        m: usize,
+        n: usize,
+    ) -> Option<&Cmap> {
+        let kind_i = self.particles().kind[i];
+        let kind_j = self.particles().kind[j];
+        let kind_k = self.particles().kind[k];
+        let kind_m = self.particles().kind[m];
+        let kind_n = self.particles().kind[n];
+        self.interactions.cmap((kind_i, kind_j, kind_k, kind_m, kind_n))
+    }
+
+    /// Get an iterator over all the bonds in the system, together with the
+    /// potentials acting on them. Bonds without any potential are also
+    /// included, with an empty list of potentials.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, Molecule, Particle};
+    /// # use lumol_core::energy::Harmonic;
+    /// let mut system = System::new();
+    /// let mut molecule = Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into()));
+    /// molecule.add_particle_bonded_to(0, Particle::with_position("H", [1.0, 0.0, 0.0].into()));
+    /// system.add_molecule(molecule);
+    /// system.add_bond_potential(("O", "H"), Box::new(Harmonic { k: 100.0, x0: 1.1 }));
+    ///
+    /// let mut energy = 0.0;
+    /// for (bond, potentials) in system.bond_terms() {
+    ///     let r = system.distance(bond.i(), bond.j());
+    ///     energy += potentials.iter().map(|potential| potential.energy(r)).sum::<f64>();
+    /// }
+    /// assert!(f64::abs(energy - 0.5 * 100.0 * 0.1 * 0.1) < 1e-12);
+    /// ```
+    pub fn bond_terms<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Bond, &'a [Box<BondPotential>])> + 'a {
+        self.configuration.bondings()
+            .iter()
+            .flat_map(|bonding| bonding.bonds().iter())
+            .map(move |&bond| (bond, self.bond_potentials(bond.i(), bond.j())))
+    }
+
+    /// Get an iterator over all the angles in the system, together with the
+    /// potentials acting on them. Angles without any potential are also
+    /// included, with an empty list of potentials. The Urey-Bradley terms
+    /// can be accessed with [`urey_bradley_potential`].
+    ///
+    /// [`urey_bradley_potential`]: #method.urey_bradley_potential
+    pub fn angle_terms<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Angle, &'a [Box<AnglePotential>])> + 'a {
+        self.configuration.bondings()
+            .iter()
+            .flat_map(|bonding| bonding.angles().iter())
+            .map(move |&angle| {
+                let potentials = self.angle_potentials(angle.i(), angle.j(), angle.k());
+                (angle, potentials)
+            })
+    }
+
+    /// Get an iterator over all the dihedral angles in the system, together
+    /// with the potentials acting on them. Dihedral angles without any
+    /// potential are also included, with an empty list of potentials.
+    pub fn dihedral_terms<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Dihedral, &'a [Box<DihedralPotential>])> + 'a {
+        self.configuration.bondings()
+            .iter()
+            .flat_map(|bonding| bonding.dihedrals().iter())
+            .map(move |&dihedral| {
+                let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+                (dihedral, self.dihedral_potentials(i, j, k, m))
+            })
+    }
+
+    /// Get an iterator over all the improper dihedral angles in the system,
+    /// together with the potentials acting on them. Improper dihedral angles
+    /// without any potential are also included, with an empty list of
+    /// potentials.
+    pub fn improper_terms<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Improper, &'a [Box<DihedralPotential>])> + 'a {
+        self.configuration.bondings()
+            .iter()
+            .flat_map(|bonding| bonding.impropers().iter())
+            .map(move |&improper| {
+                let (i, j, k, m) = (improper.i(), improper.j(), improper.k(), improper.m());
+                (improper, self.improper_potentials(i, j, k, m))
+            })
+    }
+
     /// Get the coulombic interaction for the system
     pub fn coulomb_potential(&self) -> Option<&CoulombicPotential> {
         self.interactions.coulomb.as_ref().map(|coulomb| &**coulomb)
@@ -314,6 +1191,181 @@ impl System {
     pub fn maximum_cutoff(&self) -> Option<f64> {
         self.interactions.maximum_cutoff()
     }
+
+    /// Get the neighbor list for the pair interactions in this system. The
+    /// list is updated before being returned if any particle moved by more
+    /// than half the skin distance since the last update, or if the unit
+    /// cell, the number of particles or the pair potentials cutoff changed.
+    pub fn neighbor_list(&self) -> RwLockReadGuard<NeighborList> {
+        let cutoff = self.interactions.pairs_cutoff().unwrap_or(0.0);
+        return self.neighbors.get(&self.configuration, cutoff);
+    }
+
+    /// Set the skin distance used by the neighbor list for the pair
+    /// interactions. The default skin distance is 1 A.
+    ///
+    /// # Panics
+    ///
+    /// If the `skin` distance is negative.
+    pub fn set_neighbor_list_skin(&mut self, skin: f64) {
+        self.neighbors = SharedNeighborList::new(skin);
+    }
+
+    /// Check this system for common problems before running a simulation:
+    /// particles with non finite positions, overlapping particles (closer
+    /// than 0.5 A), bonds longer than half of the unit cell, particles
+    /// outside of the xy plane in two-dimensional systems, and particle
+    /// types, bonds, angles or dihedral angles without any potential. All the
+    /// problems found are returned together.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let positions = self.particles().position;
+        for (i, position) in positions.iter().enumerate() {
+            if !(position[0].is_finite() && position[1].is_finite() && position[2].is_finite()) {
+                errors.push(ValidationError::NonFinitePosition { particle: i });
+            }
+        }
+
+        if self.dimensions == 2 {
+            let velocities = self.particles().velocity;
+            for (i, (position, velocity)) in positions.iter().zip(velocities).enumerate() {
+                if position[2] != 0.0 || velocity[2] != 0.0 {
+                    errors.push(ValidationError::OutOfPlane { particle: i });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            self.validate_geometry(&mut errors);
+        }
+        let molecules = self.molecules().count();
+        self.validate_interactions(0..molecules, &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check for overlapping particles and bonds longer than half of the
+    /// unit cell. All the positions must be finite.
+    fn validate_geometry(&self, errors: &mut Vec<ValidationError>) {
+        // Virtual sites are usually very close to their parents
+        let mut is_site = vec![false; self.size()];
+        for site in &self.virtual_sites {
+            is_site[site.site()] = true;
+        }
+
+        if self.size() > 1 {
+            let cells = CellList::new(self, OVERLAP_DISTANCE);
+            for i in (0..self.size()).filter(|&i| !is_site[i]) {
+                for j in cells.neighbors(i).into_iter().filter(|&j| !is_site[j]) {
+                    let distance = self.distance(i, j);
+                    if distance < OVERLAP_DISTANCE {
+                        errors.push(ValidationError::Overlap { i: i, j: j, distance: distance });
+                    }
+                }
+            }
+        }
+
+        let lengths = self.cell.lengths();
+        let periodicity = self.cell.periodicity();
+        let half_width = (0..3).filter(|&k| periodicity[k])
+                               .map(|k| 0.5 * lengths[k])
+                               .fold(f64::INFINITY, f64::min);
+        let positions = self.particles().position;
+        for bond in self.bonds() {
+            let (i, j) = (bond.i(), bond.j());
+            let length = (positions[j] - positions[i]).norm();
+            if length > half_width {
+                errors.push(ValidationError::LongBond { i: i, j: j, length: length });
+            }
+        }
+    }
+
+    /// Check for particle types, bonds, angles and dihedral angles without
+    /// any associated potential in the molecules at indexes `molecules`.
+    /// Pair potentials are checked between the particles in these molecules
+    /// and all the particles in the system.
+    fn validate_interactions(&self, molecules: Range<usize>, errors: &mut Vec<ValidationError>) {
+        let bondings = &self.configuration.bondings()[molecules];
+        let all_types = self.particles().atom_type;
+        let atom_types = all_types.iter().collect::<BTreeSet<_>>();
+        let checked_types = bondings.iter()
+                                    .flat_map(|bonding| bonding.indexes())
+                                    .map(|i| &all_types[i])
+                                    .collect::<BTreeSet<_>>();
+        for &type_i in &atom_types {
+            for &type_j in atom_types.iter().filter(|&&type_j| type_i <= type_j) {
+                if !checked_types.contains(type_i) && !checked_types.contains(type_j) {
+                    continue;
+                }
+                let missing = match (self.kinds.get(type_i), self.kinds.get(type_j)) {
+                    (Some(&kind_i), Some(&kind_j)) => {
+                        self.interactions.pairs((kind_i, kind_j)).is_empty()
+                    }
+                    _ => true,
+                };
+                if missing {
+                    errors.push(ValidationError::MissingPair(type_i.clone(), type_j.clone()));
+                }
+            }
+        }
+
+        let kind = |i: usize| self.particles().kind[i];
+
+        let mut missing = BTreeSet::new();
+        for bond in bondings.iter().flat_map(|bonding| bonding.bonds()) {
+            let (i, j) = (bond.i(), bond.j());
+            if self.interactions.bonds((kind(i), kind(j))).is_empty() {
+                let _ = missing.insert(self.bond_type(i, j));
+            }
+        }
+        for (a, b) in missing {
+            errors.push(ValidationError::MissingBond(a.into(), b.into()));
+        }
+
+        let mut missing = BTreeSet::new();
+        for angle in bondings.iter().flat_map(|bonding| bonding.angles()) {
+            let (i, j, k) = (angle.i(), angle.j(), angle.k());
+            let kinds = (kind(i), kind(j), kind(k));
+            if self.interactions.angles(kinds).is_empty() &&
+               self.interactions.urey_bradley(kinds).is_none() {
+                let _ = missing.insert(self.angle_type(i, j, k));
+            }
+        }
+        for (a, b, c) in missing {
+            errors.push(ValidationError::MissingAngle(a.into(), b.into(), c.into()));
+        }
+
+        let mut missing = BTreeSet::new();
+        for dihedral in bondings.iter().flat_map(|bonding| bonding.dihedrals()) {
+            let (i, j, k, m) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            if self.interactions.dihedrals((kind(i), kind(j), kind(k), kind(m))).is_empty() {
+                let _ = missing.insert(self.dihedral_type(i, j, k, m));
+            }
+        }
+        for (a, b, c, d) in missing {
+            errors.push(ValidationError::MissingDihedral(a.into(), b.into(), c.into(), d.into()));
+        }
+
+        let mut missing = BTreeSet::new();
+        for improper in bondings.iter().flat_map(|bonding| bonding.impropers()) {
+            let (i, j, k, m) = (improper.i(), improper.j(), improper.k(), improper.m());
+            if self.interactions.impropers((kind(i), kind(j), kind(k), kind(m))).is_empty() {
+                let atom_types = self.particles().atom_type;
+                let mut others = [&atom_types[j], &atom_types[k], &atom_types[m]];
+                others.sort();
+                let _ = missing.insert((
+                    atom_types[i].clone(), others[0].clone(), others[1].clone(), others[2].clone()
+                ));
+            }
+        }
+        for (a, b, c, d) in missing {
+            errors.push(ValidationError::MissingImproper(a, b, c, d));
+        }
+    }
 }
 
 use sys::compute::{KineticEnergy, PotentialEnergy, TotalEnergy};
@@ -326,15 +1378,88 @@ use sys::compute::Volume;
 
 /// Functions to get physical properties of a system.
 impl System {
-    /// Get the number of degrees of freedom in the system
+    /// Get the number of degrees of freedom in the system. When simulating
+    /// particles, the degrees of freedom removed by holonomic constraints,
+    /// frozen particles and virtual sites are not counted, as well as the
+    /// additional frozen degrees of freedom (for example the center-of-mass
+    /// motion) in `DegreesOfFreedom::Frozen`. Each particle or molecule
+    /// contributes one degree of freedom per spatial dimension.
     pub fn degrees_of_freedom(&self) -> usize {
+        // Virtual sites and frozen particles do not have any degree of freedom
+        let frozen_particles = self.particles().frozen.iter().filter(|&&frozen| frozen).count();
+        let particles = self.size() - self.virtual_sites.len() - frozen_particles;
+        let constrained = self.constrained_degrees_of_freedom_in(self.dimensions);
         match self.simulated_degrees_of_freedom {
-            DegreesOfFreedom::Particles => 3 * self.size(),
-            DegreesOfFreedom::Frozen(frozen) => 3 * self.size() - frozen,
-            DegreesOfFreedom::Molecules => 3 * self.molecules().count(),
+            DegreesOfFreedom::Particles => {
+                (self.dimensions * particles).saturating_sub(constrained)
+            }
+            DegreesOfFreedom::Frozen(frozen) => {
+                (self.dimensions * particles).saturating_sub(constrained + frozen)
+            }
+            DegreesOfFreedom::Molecules => {
+                let molecules = self.molecules().filter(|molecule| {
+                    !molecule.particles().frozen.iter().any(|&frozen| frozen)
+                });
+                self.dimensions * molecules.count()
+            }
         }
     }
 
+    /// Get the net charge of the system, i.e. the sum of the charges of all
+    /// the particles.
+    pub fn total_charge(&self) -> f64 {
+        self.particles().charge.iter().sum()
+    }
+
+    /// Check if the system is electrically neutral, and apply the given
+    /// `policy` if it is not. Systems with a net charge smaller than 1e-6 in
+    /// absolute value are considered neutral. This function returns the net
+    /// charge as an error if the system is not neutral and the policy is
+    /// `NeutralityPolicy::Error`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::sys::{System, Molecule, Particle, NeutralityPolicy};
+    /// let mut system = System::new();
+    /// system.add_molecule(Molecule::new(Particle::new("Na")));
+    /// system.add_molecule(Molecule::new(Particle::new("Cl")));
+    /// system.particles_mut().charge[0] = 1.0;
+    /// system.particles_mut().charge[1] = -0.5;
+    /// assert_eq!(system.total_charge(), 0.5);
+    ///
+    /// assert_eq!(system.check_neutrality(NeutralityPolicy::Error), Err(0.5));
+    ///
+    /// assert_eq!(system.check_neutrality(NeutralityPolicy::Neutralize), Ok(()));
+    /// assert_eq!(system.particles().charge[0], 0.75);
+    /// assert_eq!(system.particles().charge[1], -0.75);
+    /// ```
+    pub fn check_neutrality(&mut self, policy: NeutralityPolicy) -> Result<(), f64> {
+        let charge = self.total_charge();
+        if charge.abs() < 1e-6 {
+            return Ok(());
+        }
+
+        match policy {
+            NeutralityPolicy::Warn => {
+                warn!("System is not neutral and has a net charge of {:+}", charge);
+            }
+            NeutralityPolicy::Error => return Err(charge),
+            NeutralityPolicy::Neutralize => {
+                let charged = self.particles().charge.iter().filter(|&&q| q != 0.0).count();
+                let shift = charge / charged as f64;
+                for q in self.particles_mut().charge.iter_mut().filter(|q| **q != 0.0) {
+                    *q -= shift;
+                }
+                info!(
+                    "Removed a net charge of {:+} by shifting the charges of {} particles",
+                    charge, charged
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Get the kinetic energy of the system.
     pub fn kinetic_energy(&self) -> f64 {
         KineticEnergy.compute(self)
@@ -345,11 +1470,25 @@ impl System {
         PotentialEnergy.compute(self)
     }
 
+    /// Get the potential energy of the system, split in the contributions
+    /// of the different classes of interactions.
+    pub fn energy_breakdown(&self) -> EnergyBreakdown {
+        self.energy_evaluator().breakdown()
+    }
+
     /// Get the total energy of the system.
     pub fn total_energy(&self) -> f64 {
         TotalEnergy.compute(self)
     }
 
+    /// Get the energy conserved by the propagator of the current simulation,
+    /// i.e. the sum of the total energy of the system and of the
+    /// [`extended_energy`](#structfield.extended_energy). This is `None` if
+    /// the propagator does not have a conserved quantity.
+    pub fn conserved_energy(&self) -> Option<f64> {
+        self.extended_energy.map(|energy| self.total_energy() + energy)
+    }
+
     /// Get the temperature of the system.
     pub fn temperature(&self) -> f64 {
         match self.external_temperature {
@@ -358,7 +1497,8 @@ impl System {
         }
     }
 
-    /// Get the volume of the system.
+    /// Get the volume of the system. For two-dimensional systems, this is the
+    /// area of the unit cell.
     pub fn volume(&self) -> f64 {
         Volume.compute(self)
     }
@@ -415,8 +1555,12 @@ impl DerefMut for System {
 
 #[cfg(test)]
 mod tests {
-    use super::System;
-    use sys::{Molecule, Particle, ParticleKind};
+    use super::{DegreesOfFreedom, NeutralityPolicy, System, ValidationError};
+    use energy::{Harmonic, NullPotential, PairInteraction};
+    use sys::{Angle, Bond, Dihedral, Molecule, MoleculeTemplate, Particle, ParticleKind};
+    use sys::{Constraint, Property};
+    use sys::{UnitCell, VirtualSite};
+    use types::{Matrix3, Vector3D};
 
     #[test]
     #[should_panic]
@@ -471,6 +1615,110 @@ mod tests {
         assert_eq!(composition.particles(ParticleKind(3)), 1);
     }
 
+    #[test]
+    fn bonded_terms() {
+        let mut system = System::new();
+        let mut molecule = Molecule::new(Particle::new("C"));
+        molecule.add_particle_bonded_to(0, Particle::new("C"));
+        molecule.add_particle_bonded_to(1, Particle::new("C"));
+        molecule.add_particle_bonded_to(2, Particle::new("H"));
+        system.add_molecule(molecule);
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+
+        system.add_bond_potential(("C", "C"), Box::new(Harmonic { k: 1.0, x0: 1.0 }));
+        system.add_angle_potential(("C", "C", "C"), Box::new(Harmonic { k: 1.0, x0: 1.0 }));
+        system.add_angle_potential(("C", "C", "C"), Box::new(Harmonic { k: 2.0, x0: 1.0 }));
+
+        let bonds = system.bond_terms().collect::<Vec<_>>();
+        assert_eq!(bonds.len(), 3);
+        for (bond, potentials) in bonds {
+            if bond == Bond::new(2, 3) {
+                assert!(potentials.is_empty());
+            } else {
+                assert_eq!(potentials.len(), 1);
+            }
+        }
+
+        let angles = system.angle_terms().collect::<Vec<_>>();
+        assert_eq!(angles.len(), 2);
+        for (angle, potentials) in angles {
+            if angle == Angle::new(0, 1, 2) {
+                assert_eq!(potentials.len(), 2);
+            } else {
+                assert!(potentials.is_empty());
+            }
+        }
+
+        let dihedrals = system.dihedral_terms().collect::<Vec<_>>();
+        assert_eq!(dihedrals.len(), 1);
+        assert_eq!(dihedrals[0].0, Dihedral::new(0, 1, 2, 3));
+        assert!(dihedrals[0].1.is_empty());
+
+        assert_eq!(system.improper_terms().count(), 0);
+    }
+
+    #[test]
+    fn bonded_types() {
+        let mut system = System::new();
+        let mut molecule = Molecule::new(Particle::new("C"));
+        molecule.add_particle_bonded_to(0, Particle::new("O"));
+        molecule.add_particle_bonded_to(1, Particle::new("H"));
+        molecule.add_particle_bonded_to(0, Particle::new("H"));
+        system.add_molecule(molecule);
+
+        let bonds = system.bond_types();
+        assert_eq!(bonds.len(), 3);
+        assert!(bonds.contains(&("C".into(), "O".into())));
+        assert!(bonds.contains(&("H".into(), "O".into())));
+        assert!(bonds.contains(&("C".into(), "H".into())));
+
+        let angles = system.angle_types();
+        assert_eq!(angles.len(), 2);
+        assert!(angles.contains(&("C".into(), "O".into(), "H".into())));
+        assert!(angles.contains(&("H".into(), "C".into(), "O".into())));
+
+        let dihedrals = system.dihedral_types();
+        assert_eq!(dihedrals.len(), 1);
+        assert!(dihedrals.contains(&("H".into(), "C".into(), "O".into(), "H".into())));
+    }
+
+    #[test]
+    fn molecule_names() {
+        let mut system = System::new();
+        let water = MoleculeTemplate::new()
+            .particle("O", Vector3D::zero(), 0.0)
+            .particle("H", Vector3D::new(1.0, 0.0, 0.0), 0.0)
+            .particle("H", Vector3D::new(0.0, 1.0, 0.0), 0.0)
+            .bond(0, 1)
+            .bond(0, 2);
+        system.add_molecules(&water, 3);
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+
+        let water = water.molecule().hash();
+        let argon = Molecule::new(Particle::new("Ar")).hash();
+        system.set_molecule_name(water, "water");
+        assert_eq!(system.molecule_hash("water"), Some(water));
+        assert_eq!(system.molecule_name(argon), None);
+
+        let composition = system.composition();
+        assert_eq!(composition.molecules(composition.hash("water").unwrap()), 3);
+
+        let mut other = System::new();
+        other.add_molecule(Molecule::new(Particle::new("Ar")));
+        other.set_molecule_name(argon, "argon");
+        system.merge(&other);
+        assert_eq!(system.molecule_name(argon), Some("argon"));
+        assert_eq!(system.molecule_name(water), Some("water"));
+    }
+
+    #[test]
+    #[should_panic(expected = "the 'water' name is already used for another molecule type")]
+    fn duplicated_molecule_name() {
+        let mut system = System::new();
+        system.set_molecule_name(Molecule::new(Particle::new("O")).hash(), "water");
+        system.set_molecule_name(Molecule::new(Particle::new("Ar")).hash(), "water");
+    }
+
     #[test]
     fn missing_interaction() {
         let mut system = System::new();
@@ -483,4 +1731,469 @@ mod tests {
         assert_eq!(system.angle_potentials(0, 0, 0).len(), 0);
         assert_eq!(system.dihedral_potentials(0, 0, 0, 0).len(), 0);
     }
+
+    #[test]
+    fn neighbor_list() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [3.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 4.5, 0.0].into())));
+        system.set_neighbor_list_skin(0.5);
+
+        // Without pair potentials, only the pairs inside the skin distance
+        // are in the list
+        assert_eq!(system.neighbor_list().pairs(), 0);
+
+        let pair = PairInteraction::new(Box::new(NullPotential), 3.5);
+        system.add_pair_potential(("He", "He"), pair);
+        assert_eq!(system.neighbor_list().neighbors(0), &[1]);
+
+        // The list is updated when the particles move
+        system.particles_mut().position[2][1] = 3.5;
+        assert_eq!(system.neighbor_list().neighbors(0), &[1, 2]);
+        assert!(system.neighbor_list().neighbors(1).is_empty());
+    }
+
+    #[test]
+    fn atom_types() {
+        let mut system = System::new();
+        let mut water_hydrogen = Particle::new("H");
+        water_hydrogen.atom_type = String::from("HW");
+        system.add_molecule(Molecule::new(water_hydrogen));
+        system.add_molecule(Molecule::new(Particle::new("H")));
+        system.add_molecule(Molecule::new(Particle::new("C")));
+
+        // Kinds are associated with the particles types, not the names
+        assert_ne!(system.particles().kind[0], system.particles().kind[1]);
+
+        let pair = PairInteraction::new(Box::new(NullPotential), 3.0);
+        system.add_pair_potential(("HW", "C"), pair);
+        assert_eq!(system.pair_potentials(0, 2).len(), 1);
+        assert_eq!(system.pair_potentials(1, 2).len(), 0);
+    }
+
+    #[test]
+    fn add_molecules() {
+        let template = MoleculeTemplate::new()
+            .particle("O", Vector3D::new(1.0, 0.0, 0.0), -0.8)
+            .particle("H", Vector3D::new(2.0, 0.0, 0.0), 0.4)
+            .particle("H", Vector3D::new(0.0, 0.0, 0.0), 0.4)
+            .bond(0, 1)
+            .bond(0, 2);
+
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecules(&template, 5);
+        assert_eq!(system.size(), 15);
+        assert_eq!(system.molecules().count(), 5);
+        assert_eq!(system.particles().kind[0], system.particles().kind[3]);
+        assert_eq!(system.particles().charge[4], 0.4);
+        assert_eq!(system.molecule(1).bonds().len(), 2);
+
+        // Molecules are centered on a 2x2x2 grid
+        assert_eq!(system.particles().position[0], Vector3D::new(2.5, 2.5, 2.5));
+        assert_eq!(system.particles().position[1], Vector3D::new(3.5, 2.5, 2.5));
+        assert_eq!(system.particles().position[3], Vector3D::new(2.5, 2.5, 7.5));
+        assert_eq!(system.particles().position[13], Vector3D::new(8.5, 2.5, 2.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "can not add molecules from a template in an infinite cell")]
+    fn add_molecules_infinite_cell() {
+        let template = MoleculeTemplate::new().particle("Ar", Vector3D::zero(), 0.0);
+        let mut system = System::new();
+        system.add_molecules(&template, 5);
+    }
+
+    #[test]
+    fn replicate() {
+        let mut system = System::with_cell(UnitCell::ortho(10.0, 12.0, 14.0));
+        let mut molecule = Molecule::new(Particle::with_position("O", [1.0, 1.0, 1.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [2.0, 1.0, 1.0].into()));
+        system.add_molecule(molecule);
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 5.0, 5.0].into())));
+        system.particles_mut().charge[0] = -0.5;
+        system.add_property("order", Property::Scalar(vec![1.0, 2.0, 3.0]));
+
+        system.replicate(2, 1, 3);
+        assert_eq!(system.size(), 18);
+        assert_eq!(system.molecules().count(), 12);
+        assert_eq!(system.cell.lengths(), Vector3D::new(20.0, 12.0, 42.0));
+
+        // The molecules are shifted by the cell vectors, keeping their bonds
+        // and properties
+        assert_eq!(system.particles().position[3], Vector3D::new(1.0, 1.0, 15.0));
+        assert_eq!(system.particles().position[4], Vector3D::new(2.0, 1.0, 15.0));
+        assert_eq!(system.particles().position[5], Vector3D::new(5.0, 5.0, 19.0));
+        assert_eq!(system.particles().position[9], Vector3D::new(11.0, 1.0, 1.0));
+        assert_eq!(system.particles().charge[9], -0.5);
+        assert_eq!(system.particles().kind[9], system.particles().kind[0]);
+        assert_eq!(system.molecule(6).bonds().len(), 1);
+        assert_eq!(system.molecule(7).size(), 1);
+        let order = system.property("order").and_then(Property::as_scalar).unwrap();
+        assert_eq!(order.len(), 18);
+        assert_eq!(order[10], 2.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not replicate a system with an infinite cell")]
+    fn replicate_infinite_cell() {
+        let mut system = System::new();
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        system.replicate(2, 2, 2);
+    }
+
+    #[test]
+    fn remove_molecule() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let water = MoleculeTemplate::new()
+            .particle("O", Vector3D::new(0.0, 0.0, 0.0), -1.0)
+            .particle("H", Vector3D::new(0.8, 0.6, 0.0), 0.5)
+            .particle("H", Vector3D::new(-0.8, 0.6, 0.0), 0.5)
+            .particle("M", Vector3D::new(0.0, 0.2, 0.0), 0.0)
+            .bond(0, 1)
+            .bond(0, 2);
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        system.add_molecules(&water, 2);
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        for &start in &[1, 5] {
+            system.particles_mut().mass[start + 3] = 0.0;
+            let parents = [start, start + 1, start + 2];
+            system.add_virtual_site(VirtualSite::new(start + 3, parents, 0.1, 0.1));
+        }
+        let argon = system.particles().kind[0];
+
+        let molecule = system.remove_molecule(1);
+        assert_eq!(molecule.size(), 4);
+        assert_eq!(molecule.bonds().len(), 2);
+        assert_eq!(molecule.indexes(), 0..4);
+        assert_eq!(molecule.hash(), water.molecule().hash());
+
+        assert_eq!(system.size(), 6);
+        assert_eq!(system.molecules().count(), 3);
+        assert_eq!(system.molecule(1).indexes(), 1..5);
+        assert_eq!(system.molecule(2).indexes(), 5..6);
+        assert_eq!(system.molecule_id(5), 2);
+        assert!(system.molecule(1).bonds().contains(&Bond::new(1, 2)));
+
+        // The virtual site of the removed molecule is removed, and the other
+        // one is shifted
+        assert_eq!(system.virtual_sites().len(), 1);
+        assert_eq!(system.virtual_sites()[0].site(), 4);
+        assert_eq!(system.virtual_sites()[0].parents(), [1, 2, 3]);
+
+        // The kinds are kept when adding the molecule back
+        system.add_molecule(molecule);
+        assert_eq!(system.size(), 10);
+        assert_eq!(system.molecule(3).indexes(), 6..10);
+        assert_eq!(system.particles().kind[5], argon);
+        assert_eq!(system.particles().kind[6], system.particles().kind[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not remove molecule 3, the system only contains 2 molecules")]
+    fn remove_molecule_out_of_bounds() {
+        let mut system = System::new();
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        let _ = system.remove_molecule(3);
+    }
+
+    #[test]
+    fn merge() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [1.0, 0.0, 0.0].into())));
+        let pair = PairInteraction::new(Box::new(NullPotential), 3.0);
+        system.add_pair_potential(("Ar", "Ar"), pair);
+
+        let mut other = System::new();
+        let mut molecule = Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [0.8, 0.6, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [-0.8, 0.6, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::new("M"));
+        other.add_molecule(molecule);
+        other.add_molecule(Molecule::new(Particle::with_position("Ar", [2.0, 0.0, 0.0].into())));
+        other.add_virtual_site(VirtualSite::new(3, [0, 1, 2], 0.25, 0.25));
+
+        system.merge(&other);
+        assert_eq!(system.size(), 6);
+        assert_eq!(system.molecules().count(), 3);
+        assert_eq!(system.cell, UnitCell::cubic(10.0));
+        assert_eq!(system.molecule(1).bonds().len(), 3);
+        assert_eq!(system.particles().position[5], Vector3D::new(2.0, 0.0, 0.0));
+
+        // Kinds are set from the types in this system
+        assert_eq!(system.particles().kind[5], system.particles().kind[0]);
+        assert_eq!(system.pair_potentials(0, 5).len(), 1);
+
+        // Virtual sites are translated
+        assert_eq!(system.virtual_sites().len(), 1);
+        assert_eq!(system.virtual_sites()[0].site(), 4);
+        assert_eq!(system.virtual_sites()[0].parents(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn guess_bonds() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        // A water molecule split across the periodic boundaries
+        system.add_molecule(Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 5.0, 5.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("H", [0.8, 0.6, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("H", [9.2, 0.6, 0.0].into())));
+        // Particles with unknown covalent radius
+        system.add_molecule(Molecule::new(Particle::with_position("Ow", [5.0, 5.0, 6.0].into())));
+
+        system.guess_bonds();
+        assert_eq!(system.molecules().count(), 3);
+
+        let water = system.molecule(0);
+        assert_eq!(water.size(), 3);
+        assert_eq!(water.bonds().len(), 2);
+        assert_eq!(water.particles().name[0], "O");
+        assert_eq!(system.particles().name[3], "Ar");
+        assert_eq!(system.particles().name[4], "Ow");
+    }
+
+    #[test]
+    fn virtual_sites() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let mut molecule = Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [0.8, 0.6, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [-0.8, 0.6, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::new("M"));
+        system.add_molecule(molecule);
+        system.particles_mut().velocity[1] = Vector3D::new(0.0, 1.0, 0.0);
+
+        system.add_virtual_site(VirtualSite::new(3, [0, 1, 2], 0.25, 0.25));
+        assert_eq!(system.virtual_sites().len(), 1);
+        assert_eq!(system.degrees_of_freedom(), 9);
+        assert_ulps_eq!(system.particles().position[3], Vector3D::new(0.0, 0.3, 0.0));
+        assert_ulps_eq!(system.particles().velocity[3], Vector3D::new(0.0, 0.25, 0.0));
+
+        system.particles_mut().position[0] = Vector3D::new(0.0, -1.0, 0.0);
+        system.update_virtual_sites();
+        let expected = Vector3D::new(0.0, -0.2, 0.0);
+        assert_relative_eq!(system.particles().position[3], expected, epsilon = 1e-12);
+
+        // Forces on the virtual site are transfered to the parents
+        let pair = PairInteraction::new(Box::new(Harmonic { k: 1.0, x0: 0.0 }), 4.0);
+        system.add_pair_potential(("M", "M"), pair);
+        system.add_molecule(Molecule::new(Particle::with_position("M", [0.0, 2.55, 0.0].into())));
+        let forces = system.forces();
+        assert_eq!(forces[3], Vector3D::zero());
+        assert_relative_eq!(forces[0] + forces[1] + forces[2], -forces[4], epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "the particle of a virtual site must be massless")]
+    fn virtual_sites_massive() {
+        let mut system = System::new();
+        system.add_molecule(Molecule::new(Particle::new("O")));
+        system.add_molecule(Molecule::new(Particle::new("H")));
+        system.add_molecule(Molecule::new(Particle::new("H")));
+        system.add_molecule(Molecule::new(Particle::new("O")));
+        system.add_virtual_site(VirtualSite::new(3, [0, 1, 2], 0.25, 0.25));
+    }
+
+    #[test]
+    fn frozen_degrees_of_freedom() {
+        let mut system = System::new();
+        let mut molecule = Molecule::new(Particle::new("O"));
+        molecule.add_particle_bonded_to(0, Particle::new("H"));
+        molecule.add_particle_bonded_to(0, Particle::new("H"));
+        system.add_molecule(molecule.clone());
+        system.add_molecule(molecule);
+        assert_eq!(system.degrees_of_freedom(), 18);
+
+        system.particles_mut().frozen[4] = true;
+        assert_eq!(system.degrees_of_freedom(), 15);
+
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Frozen(2);
+        assert_eq!(system.degrees_of_freedom(), 13);
+
+        // Molecules containing a frozen particle are frozen
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Molecules;
+        assert_eq!(system.degrees_of_freedom(), 3);
+    }
+
+    #[test]
+    fn constrained_degrees_of_freedom() {
+        let template = MoleculeTemplate::new()
+            .particle("O", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("H", Vector3D::new(1.0, 0.0, 0.0), 0.0)
+            .particle("H", Vector3D::new(0.0, 1.0, 0.0), 0.0)
+            .bond(0, 1)
+            .bond(0, 2);
+
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let constrained = template.clone().constrain_distance(0, 1).constrain_distance(0, 2);
+        system.add_molecules(&constrained, 2);
+        system.add_molecules(&template.rigid(), 1);
+        assert_eq!(system.constraints().len(), 5);
+        assert_eq!(system.constraints()[2], Constraint::Distance { i: 3, j: 4, distance: 1.0 });
+        assert_eq!(system.constraints()[4], Constraint::Rigid { start: 6, end: 9 });
+
+        assert_eq!(system.degrees_of_freedom(), 27 - 4 - 3);
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Frozen(2);
+        assert_eq!(system.degrees_of_freedom(), 27 - 4 - 3 - 2);
+
+        // Constraints are removed with their molecule
+        let _ = system.remove_molecule(0);
+        assert_eq!(system.constraints().len(), 3);
+        assert_eq!(system.constraints()[0], Constraint::Distance { i: 0, j: 1, distance: 1.0 });
+        assert_eq!(system.constraints()[2], Constraint::Rigid { start: 3, end: 6 });
+
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Particles;
+        assert_eq!(system.degrees_of_freedom(), 18 - 2 - 3);
+
+        // Constraints between frozen particles do not remove anything
+        for i in 3..6 {
+            system.particles_mut().frozen[i] = true;
+        }
+        assert_eq!(system.degrees_of_freedom(), 9 - 2);
+
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Frozen(100);
+        assert_eq!(system.degrees_of_freedom(), 0);
+    }
+
+    #[test]
+    fn validate() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let mut molecule = Molecule::new(Particle::with_position("O", Vector3D::zero()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [1.0, 0.0, 0.0].into()));
+        system.add_molecule(molecule);
+        system.add_molecule(Molecule::new(Particle::with_position("O", [0.0, 3.0, 0.0].into())));
+
+        let errors = system.validate().unwrap_err();
+        assert_eq!(errors, vec![
+            ValidationError::MissingPair("H".into(), "H".into()),
+            ValidationError::MissingPair("H".into(), "O".into()),
+            ValidationError::MissingPair("O".into(), "O".into()),
+            ValidationError::MissingBond("H".into(), "O".into()),
+        ]);
+
+        for &(a, b) in &[("H", "H"), ("H", "O"), ("O", "O")] {
+            system.add_pair_potential((a, b), PairInteraction::new(Box::new(NullPotential), 3.0));
+        }
+        system.add_bond_potential(("O", "H"), Box::new(NullPotential));
+        assert_eq!(system.validate(), Ok(()));
+
+        // Overlapping particles and long bonds
+        system.particles_mut().position[2] = Vector3D::new(0.2, 0.1, 0.0);
+        system.particles_mut().position[1] = Vector3D::new(6.0, 0.0, 0.0);
+        let errors = system.validate().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        match errors[0] {
+            ValidationError::Overlap { i: 0, j: 2, distance } => {
+                assert_ulps_eq!(distance, f64::sqrt(0.05));
+            }
+            _ => panic!("expected an overlap, got {:?}", errors[0]),
+        }
+        assert_eq!(errors[1], ValidationError::LongBond { i: 0, j: 1, length: 6.0 });
+
+        system.particles_mut().position[1] = Vector3D::new(::std::f64::NAN, 0.0, 0.0);
+        assert_eq!(system.validate(), Err(vec![
+            ValidationError::NonFinitePosition { particle: 1 }
+        ]));
+    }
+
+    #[test]
+    fn try_add_molecule() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_pair_potential(("Ar", "Ar"), PairInteraction::new(Box::new(NullPotential), 3.0));
+        assert!(system.try_add_molecule(Molecule::new(Particle::new("Ar"))).is_ok());
+
+        let mut water = Molecule::new(Particle::with_position("O", Vector3D::zero()));
+        water.add_particle_bonded_to(0, Particle::with_position("H", [1.0, 0.0, 0.0].into()));
+        water.add_particle_bonded_to(0, Particle::with_position("H", [0.0, 1.0, 0.0].into()));
+        for &(a, b) in &[("H", "H"), ("H", "O"), ("O", "O"), ("Ar", "H")] {
+            system.add_pair_potential((a, b), PairInteraction::new(Box::new(NullPotential), 3.0));
+        }
+
+        let errors = system.try_add_molecule(water.clone()).unwrap_err();
+        assert_eq!(errors, vec![
+            ValidationError::MissingPair("Ar".into(), "O".into()),
+            ValidationError::MissingBond("H".into(), "O".into()),
+            ValidationError::MissingAngle("H".into(), "O".into(), "H".into()),
+        ]);
+        assert_eq!(system.size(), 1);
+        assert_eq!(system.molecules().count(), 1);
+
+        system.add_pair_potential(("Ar", "O"), PairInteraction::new(Box::new(NullPotential), 3.0));
+        system.add_bond_potential(("O", "H"), Box::new(NullPotential));
+        system.add_angle_potential(("H", "O", "H"), Box::new(NullPotential));
+        assert!(system.try_add_molecule(water).is_ok());
+        assert_eq!(system.size(), 4);
+        assert_eq!(system.validate(), Ok(()));
+    }
+
+    #[test]
+    fn neutrality() {
+        let mut system = System::new();
+        system.add_molecule(Molecule::new(Particle::new("Na")));
+        system.add_molecule(Molecule::new(Particle::new("Cl")));
+        system.add_molecule(Molecule::new(Particle::new("Ar")));
+        assert_eq!(system.total_charge(), 0.0);
+        assert_eq!(system.check_neutrality(NeutralityPolicy::Error), Ok(()));
+
+        system.particles_mut().charge[0] = 1.0;
+        system.particles_mut().charge[1] = -2.0;
+        assert_eq!(system.total_charge(), -1.0);
+        assert_eq!(system.molecule(1).charge(), -2.0);
+
+        assert_eq!(system.check_neutrality(NeutralityPolicy::Warn), Ok(()));
+        assert_eq!(system.total_charge(), -1.0);
+        assert_eq!(system.check_neutrality(NeutralityPolicy::Error), Err(-1.0));
+
+        assert_eq!(system.check_neutrality(NeutralityPolicy::Neutralize), Ok(()));
+        assert_eq!(system.total_charge(), 0.0);
+        assert_eq!(system.particles().charge[0], 1.5);
+        assert_eq!(system.particles().charge[1], -1.5);
+        // Uncharged particles are not modified
+        assert_eq!(system.particles().charge[2], 0.0);
+    }
+
+    #[test]
+    fn two_dimensions() {
+        let mut system = System::with_cell(UnitCell::ortho(10.0, 20.0, 30.0));
+        let mut molecule = Molecule::new(Particle::with_position("O", Vector3D::zero()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [1.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [0.0, 1.0, 0.0].into()));
+        molecule.add_constraint(Constraint::Rigid { start: 0, end: 3 });
+        system.add_molecule(molecule);
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 5.0, 0.0].into())));
+        assert_eq!(system.dimensions(), 3);
+        assert_eq!(system.degrees_of_freedom(), 4 * 3 - 3);
+        assert_eq!(system.volume(), 6000.0);
+
+        system.set_dimensions(2);
+        assert_eq!(system.dimensions(), 2);
+        assert_eq!(system.cell.periodicity(), [true, true, false]);
+        assert_eq!(system.volume(), 200.0);
+        // The rigid molecule keeps 3 degrees of freedom in 2D
+        assert_eq!(system.degrees_of_freedom(), 4 * 2 - 3);
+
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Molecules;
+        assert_eq!(system.degrees_of_freedom(), 2 * 2);
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Particles;
+
+        let pairs = [("Ar", "Ar"), ("Ar", "H"), ("Ar", "O"), ("H", "H"), ("H", "O"), ("O", "O")];
+        for &(a, b) in &pairs {
+            system.add_pair_potential((a, b), PairInteraction::new(Box::new(NullPotential), 3.0));
+        }
+        system.add_bond_potential(("O", "H"), Box::new(NullPotential));
+        system.add_angle_potential(("H", "O", "H"), Box::new(NullPotential));
+        assert_eq!(system.validate(), Ok(()));
+
+        system.particles_mut().velocity[3] = Vector3D::new(0.0, 0.0, 1.0);
+        assert_eq!(system.validate(), Err(vec![ValidationError::OutOfPlane { particle: 3 }]));
+    }
+
+    #[test]
+    #[should_panic(expected = "the first two cell vectors must be in the xy plane")]
+    fn two_dimensions_tilted_cell() {
+        let mut cell = UnitCell::cubic(10.0);
+        cell.scale_mut(Matrix3::rotation(&Vector3D::new(1.0, 0.0, 0.0), 0.5));
+        let mut system = System::with_cell(cell);
+        system.set_dimensions(2);
+    }
 }
@@ -0,0 +1,277 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Virtual interaction sites, used in water models like TIP4P and TIP5P.
+use sys::UnitCell;
+use types::Vector3D;
+
+/// A virtual site is a massless particle whose position is constructed from
+/// the positions of three parent particles.
+///
+/// With $i$, $j$ and $k$ the parent particles, the position of the virtual
+/// site is
+///
+/// $$ \vec r = \vec r_i + a \ \vec r_{ij} + b \ \vec r_{ik}
+///             + c \ (\vec r_{ij} \times \vec r_{ik}) $$
+///
+/// where $\vec r_{ij}$ and $\vec r_{ik}$ are computed using the minimum image
+/// convention. The virtual site can interact with other particles, and the
+/// forces acting on it are redistributed to the parent particles.
+///
+/// # Examples
+///
+/// ```
+/// use lumol_core::sys::VirtualSite;
+///
+/// // The M site of TIP4P/2005, for a water molecule with the oxygen at index
+/// // 0, the hydrogens at indexes 1 and 2 and the M site at index 3
+/// let site = VirtualSite::new(3, [0, 1, 2], 0.13193828, 0.13193828);
+/// assert_eq!(site.site(), 3);
+/// assert_eq!(site.parents(), [0, 1, 2]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct VirtualSite {
+    /// Index of the virtual site
+    site: usize,
+    /// Indexes of the parent particles
+    parents: [usize; 3],
+    /// Weight of the first parent-parent vector
+    a: f64,
+    /// Weight of the second parent-parent vector
+    b: f64,
+    /// Weight of the cross product of the parent-parent vectors
+    c: f64,
+}
+
+impl VirtualSite {
+    /// Create a new virtual site at index `site`, in the plane of the
+    /// `parents` particles, using the weights `a` and `b` for the vectors
+    /// between the first parent and the two others. This can be used for the
+    /// M site of TIP4P water models.
+    ///
+    /// # Panics
+    ///
+    /// If the `site` is one of the `parents`, or if the same parent is used
+    /// twice.
+    pub fn new(site: usize, parents: [usize; 3], a: f64, b: f64) -> VirtualSite {
+        VirtualSite::out_of_plane(site, parents, a, b, 0.0)
+    }
+
+    /// Create a new virtual site at index `site`, outside of the plane of the
+    /// `parents` particles. The weights `a` and `b` are used for the vectors
+    /// between the first parent and the two others, and `c` for the cross
+    /// product of these vectors. This can be used for the lone pairs sites of
+    /// TIP5P water models.
+    ///
+    /// # Panics
+    ///
+    /// If the `site` is one of the `parents`, or if the same parent is used
+    /// twice.
+    pub fn out_of_plane(site: usize, parents: [usize; 3], a: f64, b: f64, c: f64) -> VirtualSite {
+        assert!(
+            !parents.contains(&site),
+            "a virtual site can not be one of its own parents"
+        );
+        assert!(
+            parents[0] != parents[1] && parents[0] != parents[2] && parents[1] != parents[2],
+            "the parents of a virtual site must be different particles"
+        );
+        VirtualSite {
+            site: site,
+            parents: parents,
+            a: a,
+            b: b,
+            c: c,
+        }
+    }
+
+    /// Get the index of the virtual site
+    pub fn site(&self) -> usize {
+        self.site
+    }
+
+    /// Get the indexes of the parent particles of this virtual site
+    pub fn parents(&self) -> [usize; 3] {
+        self.parents
+    }
+
+    /// Translate the indexes of the virtual site and its parents by `delta`
+    pub fn translate_by(&mut self, delta: isize) {
+        self.site = (self.site as isize + delta) as usize;
+        for parent in &mut self.parents {
+            *parent = (*parent as isize + delta) as usize;
+        }
+    }
+
+    /// Replace the indexes of the virtual site and its parents by the result
+    /// of the `map` function
+    pub(crate) fn map_indexes<F: Fn(usize) -> usize>(&mut self, map: F) {
+        self.site = map(self.site);
+        for parent in &mut self.parents {
+            *parent = map(*parent);
+        }
+    }
+
+    /// Get the vectors between the first parent and the two others, using
+    /// the minimum image convention
+    fn parent_vectors(&self, cell: &UnitCell, positions: &[Vector3D]) -> (Vector3D, Vector3D) {
+        let (i, j, k) = (self.parents[0], self.parents[1], self.parents[2]);
+        let mut r_ij = positions[j] - positions[i];
+        let mut r_ik = positions[k] - positions[i];
+        cell.vector_image(&mut r_ij);
+        cell.vector_image(&mut r_ik);
+        return (r_ij, r_ik);
+    }
+
+    /// Compute the position of this virtual site from the `positions` of the
+    /// particles
+    pub fn position(&self, cell: &UnitCell, positions: &[Vector3D]) -> Vector3D {
+        let (r_ij, r_ik) = self.parent_vectors(cell, positions);
+        let i = self.parents[0];
+        return positions[i] + self.a * r_ij + self.b * r_ik + self.c * (r_ij ^ r_ik);
+    }
+
+    /// Compute the velocity of this virtual site from the `positions` and
+    /// `velocities` of the particles
+    pub fn velocity(
+        &self,
+        cell: &UnitCell,
+        positions: &[Vector3D],
+        velocities: &[Vector3D],
+    ) -> Vector3D {
+        let (r_ij, r_ik) = self.parent_vectors(cell, positions);
+        let (i, j, k) = (self.parents[0], self.parents[1], self.parents[2]);
+        let v_ij = velocities[j] - velocities[i];
+        let v_ik = velocities[k] - velocities[i];
+        let cross = (v_ij ^ r_ik) + (r_ij ^ v_ik);
+        return velocities[i] + self.a * v_ij + self.b * v_ik + self.c * cross;
+    }
+
+    /// Redistribute the force acting on this virtual site to the parent
+    /// particles, using the `positions` of the particles. The force on the
+    /// virtual site is set to zero.
+    pub fn spread_force(&self, cell: &UnitCell, positions: &[Vector3D], forces: &mut [Vector3D]) {
+        let (r_ij, r_ik) = self.parent_vectors(cell, positions);
+        let (i, j, k) = (self.parents[0], self.parents[1], self.parents[2]);
+
+        let force = forces[self.site];
+        let force_j = self.a * force + self.c * (r_ik ^ force);
+        let force_k = self.b * force + self.c * (force ^ r_ij);
+
+        forces[i] += force - force_j - force_k;
+        forces[j] += force_j;
+        forces[k] += force_k;
+        forces[self.site] = Vector3D::zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions() -> Vec<Vector3D> {
+        vec![
+            Vector3D::new(0.0, 0.0, 0.0),
+            Vector3D::new(0.8, 0.6, 0.0),
+            Vector3D::new(-0.8, 0.6, 0.0),
+            Vector3D::zero(),
+        ]
+    }
+
+    #[test]
+    fn position() {
+        let cell = UnitCell::cubic(10.0);
+        let site = VirtualSite::new(3, [0, 1, 2], 0.25, 0.25);
+        assert_ulps_eq!(site.position(&cell, &positions()), Vector3D::new(0.0, 0.3, 0.0));
+
+        // Periodic boundary conditions are used for the parents
+        let mut positions = positions();
+        positions[2][0] += 10.0;
+        assert_ulps_eq!(site.position(&cell, &positions), Vector3D::new(0.0, 0.3, 0.0));
+
+        let site = VirtualSite::out_of_plane(3, [0, 1, 2], 0.0, 0.0, 1.0);
+        assert_ulps_eq!(site.position(&cell, &positions), Vector3D::new(0.0, 0.0, 0.96));
+    }
+
+    #[test]
+    fn velocity() {
+        let cell = UnitCell::cubic(10.0);
+        let velocities = vec![
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(1.0, 2.0, 0.0),
+            Vector3D::zero(),
+        ];
+        let site = VirtualSite::out_of_plane(3, [0, 1, 2], 0.5, 0.25, 0.1);
+        let velocity = site.velocity(&cell, &positions(), &velocities);
+
+        // Compare with finite differences of the position
+        let dt = 1e-6;
+        let moved = positions().iter()
+                               .zip(&velocities)
+                               .map(|(&r, &v)| r + dt * v)
+                               .collect::<Vec<_>>();
+        let finite = (site.position(&cell, &moved) - site.position(&cell, &positions())) / dt;
+        for k in 0..3 {
+            assert_relative_eq!(velocity[k], finite[k], epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn spread_force() {
+        let cell = UnitCell::cubic(10.0);
+        let positions = positions();
+        let site = VirtualSite::out_of_plane(3, [0, 1, 2], 0.3, 0.2, 0.5);
+
+        let force = Vector3D::new(0.3, -1.2, 0.7);
+        let mut forces = vec![Vector3D::zero(); 4];
+        forces[3] = force;
+        site.spread_force(&cell, &positions, &mut forces);
+        assert_eq!(forces[3], Vector3D::zero());
+
+        // The total force is conserved
+        let total = forces.iter().fold(Vector3D::zero(), |total, &force| total + force);
+        assert_relative_eq!(total, force, epsilon = 1e-12);
+
+        // The work done by the parent forces for a small displacement is the
+        // same as the work done by the virtual site force
+        let displacements = vec![
+            Vector3D::new(0.1, 0.2, -0.3),
+            Vector3D::new(-0.2, 0.1, 0.4),
+            Vector3D::new(0.3, -0.1, 0.2),
+            Vector3D::zero(),
+        ];
+        let eps = 1e-6;
+        let moved = positions.iter()
+                             .zip(&displacements)
+                             .map(|(&r, &d)| r + eps * d)
+                             .collect::<Vec<_>>();
+        let delta = site.position(&cell, &moved) - site.position(&cell, &positions);
+        let virtual_work = force * delta;
+        let parents_work = (0..3).map(|i| forces[i] * (eps * displacements[i])).sum::<f64>();
+        assert_relative_eq!(virtual_work, parents_work, max_relative = 1e-5);
+    }
+
+    #[test]
+    fn translate_by() {
+        let mut site = VirtualSite::new(3, [0, 1, 2], 0.2, 0.2);
+        site.translate_by(5);
+        assert_eq!(site.site(), 8);
+        assert_eq!(site.parents(), [5, 6, 7]);
+        site.translate_by(-2);
+        assert_eq!(site.site(), 6);
+        assert_eq!(site.parents(), [3, 4, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "a virtual site can not be one of its own parents")]
+    fn site_in_parents() {
+        let _ = VirtualSite::new(1, [0, 1, 2], 0.2, 0.2);
+    }
+
+    #[test]
+    #[should_panic(expected = "the parents of a virtual site must be different particles")]
+    fn same_parents() {
+        let _ = VirtualSite::new(3, [0, 1, 1], 0.2, 0.2);
+    }
+}
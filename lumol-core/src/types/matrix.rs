@@ -298,6 +298,95 @@ impl Matrix3 {
             self[0][2] * self[0][2] + self[1][2] * self[1][2] + self[2][2] * self[2][2]
         )
     }
+
+    /// Compute the eigenvalues and eigenvectors of a symmetric matrix, using
+    /// the [Jacobi eigenvalue algorithm][Wiki]. This function returns the
+    /// eigenvalues sorted in increasing order, and a matrix containing the
+    /// corresponding normalized eigenvectors as columns. Only the upper
+    /// triangle of the matrix is used.
+    ///
+    /// [Wiki]: https://en.wikipedia.org/wiki/Jacobi_eigenvalue_algorithm
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Matrix3, Vector3D};
+    /// let matrix = Matrix3::new([
+    ///     [2.0, 1.0, 0.0],
+    ///     [1.0, 2.0, 0.0],
+    ///     [0.0, 0.0, 5.0]
+    /// ]);
+    ///
+    /// let (values, vectors) = matrix.symmetric_eigen();
+    /// assert!(f64::abs(values[0] - 1.0) < 1e-12);
+    /// assert!(f64::abs(values[1] - 3.0) < 1e-12);
+    /// assert!(f64::abs(values[2] - 5.0) < 1e-12);
+    ///
+    /// let first = Vector3D::new(vectors[0][0], vectors[1][0], vectors[2][0]);
+    /// assert!((matrix * first - values[0] * first).norm() < 1e-12);
+    /// ```
+    pub fn symmetric_eigen(&self) -> (Vector3D, Matrix3) {
+        let mut matrix = *self;
+        for i in 0..3 {
+            for j in 0..i {
+                matrix[i][j] = matrix[j][i];
+            }
+        }
+
+        let mut vectors = Matrix3::one();
+        for _ in 0..50 {
+            let off_diagonal = matrix[0][1] * matrix[0][1] + matrix[0][2] * matrix[0][2]
+                + matrix[1][2] * matrix[1][2];
+            if off_diagonal == 0.0 {
+                break;
+            }
+
+            for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+                if matrix[p][q] == 0.0 {
+                    continue;
+                }
+                // Find the rotation zeroing the (p, q) element
+                let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+                let t = theta.signum() / (theta.abs() + f64::sqrt(theta * theta + 1.0));
+                let cos = 1.0 / f64::sqrt(t * t + 1.0);
+                let sin = t * cos;
+
+                for k in 0..3 {
+                    let (kp, kq) = (matrix[k][p], matrix[k][q]);
+                    matrix[k][p] = cos * kp - sin * kq;
+                    matrix[k][q] = sin * kp + cos * kq;
+                }
+                for k in 0..3 {
+                    let (pk, qk) = (matrix[p][k], matrix[q][k]);
+                    matrix[p][k] = cos * pk - sin * qk;
+                    matrix[q][k] = sin * pk + cos * qk;
+                }
+                for k in 0..3 {
+                    let (kp, kq) = (vectors[k][p], vectors[k][q]);
+                    vectors[k][p] = cos * kp - sin * kq;
+                    vectors[k][q] = sin * kp + cos * kq;
+                }
+            }
+        }
+
+        let mut order = [0, 1, 2];
+        order.sort_by(|&i, &j| {
+            matrix[i][i].partial_cmp(&matrix[j][j]).expect("got NaN eigenvalue")
+        });
+
+        let values = Vector3D::new(
+            matrix[order[0]][order[0]],
+            matrix[order[1]][order[1]],
+            matrix[order[2]][order[2]],
+        );
+        let mut sorted = Matrix3::zero();
+        for i in 0..3 {
+            for j in 0..3 {
+                sorted[i][j] = vectors[i][order[j]];
+            }
+        }
+        return (values, sorted);
+    }
 }
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
@@ -796,4 +885,37 @@ mod tests {
         assert_eq!(Matrix3::zero().norm(), 0.0);
         assert_eq!(Matrix3::one().norm(), f64::sqrt(3.0));
     }
+
+    #[test]
+    fn symmetric_eigen() {
+        let (values, vectors) = Matrix3::one().symmetric_eigen();
+        assert_eq!(values, Vector3D::new(1.0, 1.0, 1.0));
+        assert_eq!(vectors, Matrix3::one());
+
+        let matrix = Matrix3::new([
+            [4.0, -2.0, 1.0],
+            [-2.0, 3.0, 0.5],
+            [1.0, 0.5, -1.0],
+        ]);
+        let (values, vectors) = matrix.symmetric_eigen();
+        assert!(values[0] <= values[1] && values[1] <= values[2]);
+        assert_relative_eq!(values[0] + values[1] + values[2], matrix.trace(), epsilon = 1e-12);
+
+        // The eigenvectors are orthonormal
+        assert_relative_eq!(vectors.transposed() * vectors, Matrix3::one(), epsilon = 1e-12);
+        for i in 0..3 {
+            let vector = Vector3D::new(vectors[0][i], vectors[1][i], vectors[2][i]);
+            assert_relative_eq!(matrix * vector, values[i] * vector, epsilon = 1e-12);
+        }
+
+        // Degenerate eigenvalues
+        let matrix = Matrix3::new([
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+        ]);
+        let (values, vectors) = matrix.symmetric_eigen();
+        assert_relative_eq!(values, Vector3D::new(0.0, 0.0, 3.0), epsilon = 1e-12);
+        assert_relative_eq!(vectors.transposed() * vectors, Matrix3::one(), epsilon = 1e-12);
+    }
 }
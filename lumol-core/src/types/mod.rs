@@ -1,7 +1,7 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
 
-//! This module provides complexe numbers; 3D vectors and matrix; and
+//! This module provides complexe numbers; 3D vectors, matrix and quaternions; and
 //! multidimensional arrays for use in all other modules.
 mod vectors;
 pub use self::vectors::Vector3D;
@@ -12,5 +12,8 @@ pub use self::matrix::Matrix3;
 mod complex;
 pub use self::complex::Complex;
 
+mod quaternion;
+pub use self::quaternion::Quaternion;
+
 mod arrays;
 pub use self::arrays::{Array2, Array3};
@@ -0,0 +1,288 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Quaternion type
+use std::ops::Mul;
+
+use types::{Matrix3, Vector3D};
+
+/// Quaternion, with a scalar part and a vector part. Unit quaternions are
+/// used to represent the orientation of rigid bodies, without the
+/// singularities of Euler angles.
+///
+/// ```
+/// # use lumol_core::types::{Quaternion, Vector3D};
+/// use std::f64::consts::PI;
+///
+/// let rotation = Quaternion::rotation(&Vector3D::new(0.0, 0.0, 1.0), PI / 2.0);
+/// let rotated = rotation.rotate(&Vector3D::new(1.0, 0.0, 0.0));
+/// assert!((rotated - Vector3D::new(0.0, 1.0, 0.0)).norm() < 1e-12);
+///
+/// // Composition of rotations uses the product of quaternions
+/// let half_turn = rotation * rotation;
+/// let rotated = half_turn.rotate(&Vector3D::new(1.0, 0.0, 0.0));
+/// assert!((rotated - Vector3D::new(-1.0, 0.0, 0.0)).norm() < 1e-12);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    /// Scalar part of the quaternion
+    w: f64,
+    /// Vector part of the quaternion
+    v: Vector3D,
+}
+
+impl Quaternion {
+    /// Create a new `Quaternion` from its scalar part `w` and the components
+    /// `x`, `y` and `z` of its vector part.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Quaternion, Vector3D};
+    /// let q = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+    /// assert_eq!(q.scalar(), 1.0);
+    /// assert_eq!(q.vector(), Vector3D::new(2.0, 3.0, 4.0));
+    /// ```
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion {
+            w: w,
+            v: Vector3D::new(x, y, z),
+        }
+    }
+
+    /// Create the identity quaternion, representing no rotation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Quaternion, Matrix3};
+    /// let q = Quaternion::one();
+    /// assert_eq!(q.rotation_matrix(), Matrix3::one());
+    /// ```
+    pub fn one() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Create the unit quaternion representing a rotation of `angle` (in
+    /// radians) around the `axis`, following the right-hand rule.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Quaternion, Vector3D};
+    /// use std::f64::consts::PI;
+    /// let q = Quaternion::rotation(&Vector3D::new(0.0, 0.0, 2.0), PI);
+    /// assert!(q.scalar().abs() < 1e-12);
+    /// assert_eq!(q.vector(), Vector3D::new(0.0, 0.0, 1.0));
+    /// ```
+    pub fn rotation(axis: &Vector3D, angle: f64) -> Quaternion {
+        Quaternion {
+            w: f64::cos(0.5 * angle),
+            v: f64::sin(0.5 * angle) * axis.normalized(),
+        }
+    }
+
+    /// Create the unit quaternion representing the same rotation as the
+    /// rotation `matrix`. The matrix should be orthogonal, with a determinant
+    /// of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Quaternion, Vector3D};
+    /// let q = Quaternion::rotation(&Vector3D::new(1.0, -2.0, 0.5), 2.5);
+    /// let matrix = q.rotation_matrix();
+    /// let p = Quaternion::from_rotation_matrix(&matrix);
+    /// assert!((p.scalar() - q.scalar()).abs() < 1e-12);
+    /// assert!((p.vector() - q.vector()).norm() < 1e-12);
+    /// ```
+    pub fn from_rotation_matrix(matrix: &Matrix3) -> Quaternion {
+        let m = matrix;
+        let trace = m.trace();
+        // Use the largest of the four components to get the best precision
+        let q = if trace > m[0][0] && trace > m[1][1] && trace > m[2][2] {
+            let s = 2.0 * f64::sqrt(1.0 + trace);
+            Quaternion::new(
+                0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * f64::sqrt(1.0 + m[0][0] - m[1][1] - m[2][2]);
+            Quaternion::new(
+                (m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * f64::sqrt(1.0 + m[1][1] - m[0][0] - m[2][2]);
+            Quaternion::new(
+                (m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s
+            )
+        } else {
+            let s = 2.0 * f64::sqrt(1.0 + m[2][2] - m[0][0] - m[1][1]);
+            Quaternion::new(
+                (m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s
+            )
+        };
+
+        // Use a positive scalar part, both quaternions represent the same
+        // rotation
+        if q.w < 0.0 {
+            return Quaternion { w: -q.w, v: -q.v };
+        } else {
+            return q;
+        }
+    }
+
+    /// Get the scalar part of this quaternion
+    pub fn scalar(&self) -> f64 {
+        self.w
+    }
+
+    /// Get the vector part of this quaternion
+    pub fn vector(&self) -> Vector3D {
+        self.v
+    }
+
+    /// Get the norm of this quaternion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::Quaternion;
+    /// let q = Quaternion::new(1.0, 1.0, -1.0, 1.0);
+    /// assert_eq!(q.norm(), 2.0);
+    /// ```
+    pub fn norm(&self) -> f64 {
+        f64::sqrt(self.w * self.w + self.v.norm2())
+    }
+
+    /// Get the unit quaternion with the same direction as this one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::Quaternion;
+    /// let q = Quaternion::new(1.0, 1.0, -1.0, 1.0);
+    /// assert_eq!(q.normalized(), Quaternion::new(0.5, 0.5, -0.5, 0.5));
+    /// ```
+    pub fn normalized(&self) -> Quaternion {
+        let norm = self.norm();
+        Quaternion {
+            w: self.w / norm,
+            v: self.v / norm,
+        }
+    }
+
+    /// Get the conjugate of this quaternion. For unit quaternions, the
+    /// conjugate represents the inverse rotation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::Quaternion;
+    /// let q = Quaternion::new(1.0, 2.0, -3.0, 4.0);
+    /// assert_eq!(q.conj(), Quaternion::new(1.0, -2.0, 3.0, -4.0));
+    /// ```
+    pub fn conj(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    /// Get the rotation matrix corresponding to this unit quaternion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lumol_core::types::{Quaternion, Vector3D};
+    /// let q = Quaternion::rotation(&Vector3D::new(1.0, 1.0, 0.0), 0.3);
+    /// let vector = Vector3D::new(-1.0, 4.0, 2.0);
+    /// let rotated = q.rotation_matrix() * vector;
+    /// assert!((rotated - q.rotate(&vector)).norm() < 1e-12);
+    /// ```
+    pub fn rotation_matrix(&self) -> Matrix3 {
+        let (w, x, y, z) = (self.w, self.v[0], self.v[1], self.v[2]);
+        Matrix3::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ])
+    }
+
+    /// Rotate the `vector` with the rotation represented by this unit
+    /// quaternion.
+    pub fn rotate(&self, vector: &Vector3D) -> Vector3D {
+        let t = 2.0 * (self.v ^ *vector);
+        return *vector + self.w * t + (self.v ^ t);
+    }
+}
+
+impl Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * other.w - self.v * other.v,
+            v: self.w * other.v + other.w * self.v + (self.v ^ other.v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn rotation() {
+        let axis = Vector3D::new(0.3, -1.0, 0.5);
+        let q = Quaternion::rotation(&axis, 1.2);
+        assert_ulps_eq!(q.norm(), 1.0);
+
+        // The axis is not changed by the rotation
+        assert_relative_eq!(q.rotate(&axis), axis, epsilon = 1e-12);
+
+        // Rotations follow the right-hand rule
+        let q = Quaternion::rotation(&Vector3D::new(1.0, 0.0, 0.0), PI / 2.0);
+        let rotated = q.rotate(&Vector3D::new(0.0, 1.0, 0.0));
+        assert_relative_eq!(rotated, Vector3D::new(0.0, 0.0, 1.0), epsilon = 1e-12);
+
+        let matrix = q.rotation_matrix();
+        assert_relative_eq!(matrix.determinant(), 1.0, epsilon = 1e-12);
+        let vector = Vector3D::new(2.0, -3.0, 0.7);
+        assert_relative_eq!(matrix * vector, q.rotate(&vector), epsilon = 1e-12);
+        assert_relative_eq!(q.conj().rotate(&q.rotate(&vector)), vector, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn product() {
+        let first = Quaternion::rotation(&Vector3D::new(0.3, -1.0, 0.5), 1.2);
+        let second = Quaternion::rotation(&Vector3D::new(1.0, 2.0, -0.1), -2.3);
+        let vector = Vector3D::new(2.0, -3.0, 0.7);
+
+        let rotated = (second * first).rotate(&vector);
+        assert_relative_eq!(rotated, second.rotate(&first.rotate(&vector)), epsilon = 1e-12);
+
+        let identity = first * first.conj();
+        assert_relative_eq!(identity.scalar(), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(identity.vector(), Vector3D::zero(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn from_rotation_matrix() {
+        let axes = [
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+            Vector3D::new(0.3, -1.0, 0.5),
+        ];
+        for axis in &axes {
+            for &angle in &[0.0, 0.5, -2.0, PI, 3.0] {
+                let q = Quaternion::rotation(axis, angle);
+                let p = Quaternion::from_rotation_matrix(&q.rotation_matrix());
+                // q and -q represent the same rotation
+                let sign = if q.vector() * p.vector() < 0.0 { -1.0 } else { 1.0 };
+                assert_relative_eq!(p.scalar(), sign * q.scalar(), epsilon = 1e-12);
+                assert_relative_eq!(p.vector(), sign * q.vector(), epsilon = 1e-12);
+            }
+        }
+    }
+}
@@ -8,6 +8,7 @@ use std::result;
 
 // use chemfiles;
 
+use lumol::sim::CheckpointError;
 use lumol::sim::output::CustomOutputError;
 use lumol::TrajectoryError;
 use lumol::units::ParseError;
@@ -77,6 +78,17 @@ impl From<(CustomOutputError, PathBuf)> for Error {
     }
 }
 
+impl From<(CheckpointError, PathBuf)> for Error {
+    fn from((err, path): (CheckpointError, PathBuf)) -> Error {
+        match err {
+            CheckpointError::Io(err) => Error::Io(err, path),
+            CheckpointError::Invalid(message) => Error::Config(
+                format!("invalid checkpoint file '{}': {}", path.display(), message)
+            ),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
         use std::error::Error as StdError;
@@ -4,7 +4,10 @@ use toml::value::{Table, Value};
 
 use lumol::energy::{AnglePotential, DihedralPotential};
 use lumol::energy::{CosineHarmonic, Harmonic, Morse, NullPotential, Torsion};
+use lumol::energy::{CosineAngle, CosineSquaredAngle, Opls, RyckaertBellemans};
+use lumol::energy::{CharmmDihedral, Cmap, NonBondedAngle};
 use lumol::sys::System;
+use lumol::units;
 
 use super::Input;
 use FromToml;
@@ -42,7 +45,20 @@ impl Input {
                 Error::from("The third atom name is not a string in angle potential")
             )?;
 
-            let potential = read_angle_potential(angle)?;
+            if let Some(urey_bradley) = angle.get("urey-bradley") {
+                let table = urey_bradley.as_table().ok_or(
+                    Error::from("'urey-bradley' must be a table in angle potential")
+                )?;
+                let potential = Harmonic::from_toml(table)?;
+                system.set_urey_bradley_potential((a, b, c), Box::new(potential));
+
+                // Only the atoms and the Urey-Bradley term are given
+                if angle.len() == 2 {
+                    continue;
+                }
+            }
+
+            let potential = read_angle_potential(angle, &["atoms", "urey-bradley"], "angle potential")?;
             system.add_angle_potential((a, b, c), potential);
         }
         Ok(())
@@ -85,23 +101,196 @@ impl Input {
                 Error::from("The fourth atom name is not a string in dihedral potential")
             )?;
 
-            let potential = read_dihedral_potential(dihedral)?;
+            let potential = read_dihedral_potential(dihedral, "dihedral potential")?;
             system.add_dihedral_potential((a, b, c, d), potential);
         }
         Ok(())
     }
+
+    /// Read the "impropers" section from the potential configuration.
+    pub(crate) fn read_impropers(&self, system: &mut System) -> Result<()> {
+        let impropers = match self.config.get("impropers") {
+            Some(impropers) => impropers,
+            None => return Ok(()),
+        };
+
+        let impropers = impropers.as_array().ok_or(
+            Error::from("The 'impropers' section must be an array")
+        )?;
+
+        for improper in impropers {
+            let improper = improper.as_table().ok_or(
+                Error::from("improper potential entry must be a table")
+            )?;
+
+            let atoms = extract::slice("atoms", improper, "improper potential")?;
+            if atoms.len() != 4 {
+                return Err(Error::from(format!(
+                    "Wrong size for 'atoms' array in improper potential. Should be 4, is {}",
+                    atoms.len()
+                )));
+            }
+
+            let a = atoms[0].as_str().ok_or(
+                Error::from("The first atom name is not a string in improper potential")
+            )?;
+            let b = atoms[1].as_str().ok_or(
+                Error::from("The second atom name is not a string in improper potential")
+            )?;
+            let c = atoms[2].as_str().ok_or(
+                Error::from("The third atom name is not a string in improper potential")
+            )?;
+            let d = atoms[3].as_str().ok_or(
+                Error::from("The fourth atom name is not a string in improper potential")
+            )?;
+
+            let potential = read_dihedral_potential(improper, "improper potential")?;
+            system.add_improper_potential((a, b, c, d), potential);
+        }
+        Ok(())
+    }
+
+    /// Read the "cmaps" section from the potential configuration.
+    pub(crate) fn read_cmaps(&self, system: &mut System) -> Result<()> {
+        let cmaps = match self.config.get("cmaps") {
+            Some(cmaps) => cmaps,
+            None => return Ok(()),
+        };
+
+        let cmaps = cmaps.as_array().ok_or(Error::from("The 'cmaps' section must be an array"))?;
+
+        for cmap in cmaps {
+            let cmap = cmap.as_table().ok_or(Error::from("CMAP entry must be a table"))?;
+
+            let atoms = extract::slice("atoms", cmap, "CMAP")?;
+            if atoms.len() != 5 {
+                return Err(Error::from(format!(
+                    "Wrong size for 'atoms' array in CMAP. Should be 5, is {}",
+                    atoms.len()
+                )));
+            }
+
+            let mut names = Vec::new();
+            for atom in atoms {
+                let name = atom.as_str().ok_or(
+                    Error::from("The atoms names must be strings in CMAP")
+                )?;
+                names.push(name);
+            }
+
+            let unit = extract::str("unit", cmap, "CMAP")?;
+            let values = extract::slice("values", cmap, "CMAP")?;
+            let size = (values.len() as f64).sqrt().round() as usize;
+            if size < 2 || size * size != values.len() {
+                return Err(Error::from(format!(
+                    "The number of values in CMAP must be the square of the grid size, got {}",
+                    values.len()
+                )));
+            }
+
+            let mut energies = Vec::with_capacity(values.len());
+            for value in values {
+                let value = match *value {
+                    Value::Integer(value) => value as f64,
+                    Value::Float(value) => value,
+                    _ => return Err(Error::from("The values must be numbers in CMAP")),
+                };
+                energies.push(units::from(value, unit)?);
+            }
+
+            let cmap = Cmap::new(size, energies);
+            if names[0] == names[4] && names[1] == names[3] && !cmap.is_symmetric() {
+                return Err(Error::from(
+                    "The values must be symmetric in CMAP with the same atoms in reverse order"
+                ));
+            }
+
+            system.set_cmap_potential(
+                (names[0], names[1], names[2], names[3], names[4]),
+                cmap,
+            );
+        }
+        Ok(())
+    }
+
+    /// Read the "nonbonded-angles" section from the potential configuration.
+    pub(crate) fn read_nonbonded_angles(&self, system: &mut System) -> Result<()> {
+        let angles = match self.config.get("nonbonded-angles") {
+            Some(angles) => angles,
+            None => return Ok(()),
+        };
+
+        let angles = angles.as_array().ok_or(
+            Error::from("The 'nonbonded-angles' section must be an array")
+        )?;
+
+        for angle in angles {
+            let angle = angle.as_table().ok_or(
+                Error::from("Non-bonded angle potential entry must be a table")
+            )?;
+
+            let atoms = extract::slice("atoms", angle, "non-bonded angle potential")?;
+            if atoms.len() != 3 {
+                return Err(Error::from(format!(
+                    "Wrong size for 'atoms' array in non-bonded angle potential. Should be 3, is {}",
+                    atoms.len()
+                )));
+            }
+
+            let a = atoms[0].as_str().ok_or(
+                Error::from("The first atom name is not a string in non-bonded angle potential")
+            )?;
+            let b = atoms[1].as_str().ok_or(
+                Error::from("The second atom name is not a string in non-bonded angle potential")
+            )?;
+            let c = atoms[2].as_str().ok_or(
+                Error::from("The third atom name is not a string in non-bonded angle potential")
+            )?;
+
+            let cutoff = extract::str("cutoff", angle, "non-bonded angle potential")?;
+            let cutoff = units::from_str(cutoff)?;
+            if cutoff <= 0.0 {
+                return Err(Error::from("'cutoff' must be positive in non-bonded angle potential"));
+            }
+
+            let gamma = if angle.get("gamma").is_some() {
+                let gamma = extract::str("gamma", angle, "non-bonded angle potential")?;
+                units::from_str(gamma)?
+            } else {
+                0.0
+            };
+            if gamma < 0.0 {
+                return Err(Error::from("'gamma' can not be negative in non-bonded angle potential"));
+            }
+
+            let potential = read_angle_potential(
+                angle,
+                &["atoms", "cutoff", "gamma"],
+                "non-bonded angle potential",
+            )?;
+            let potential = NonBondedAngle::new((a, b, c), potential, cutoff, gamma);
+            system.add_global_potential(Box::new(potential));
+        }
+        Ok(())
+    }
 }
 
-fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
-    let potentials = angle.keys().cloned().filter(|key| key != "atoms").collect::<Vec<_>>();
+/// Read a potential for angles, using the given `context` in error messages.
+/// All the keys in the table except the ones in `keys` are potential types.
+fn read_angle_potential(angle: &Table, keys: &[&str], context: &str) -> Result<Box<AnglePotential>> {
+    let potentials = angle.keys()
+                          .cloned()
+                          .filter(|key| !keys.contains(&&**key))
+                          .collect::<Vec<_>>();
 
     if potentials.is_empty() {
-        return Err(Error::from("Missing potential type in angle potential"));
+        return Err(Error::from(format!("Missing potential type in {}", context)));
     }
 
     if potentials.len() > 1 {
         return Err(Error::from(format!(
-            "Got more than one potential type in angle potential: {}",
+            "Got more than one potential type in {}: {}",
+            context,
             potentials.join(" and ")
         )));
     }
@@ -112,6 +301,8 @@ fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
             "null" => Ok(Box::new(NullPotential::from_toml(table)?)),
             "harmonic" => Ok(Box::new(Harmonic::from_toml(table)?)),
             "cosine-harmonic" => Ok(Box::new(CosineHarmonic::from_toml(table)?)),
+            "cosine" => Ok(Box::new(CosineAngle::from_toml(table)?)),
+            "cosine-squared" => Ok(Box::new(CosineSquaredAngle::from_toml(table)?)),
             "morse" => Ok(Box::new(Morse::from_toml(table)?)),
             other => Err(Error::from(format!("Unknown potential type '{}'", other))),
         }
@@ -120,27 +311,44 @@ fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
     }
 }
 
-fn read_dihedral_potential(dihedral: &Table) -> Result<Box<DihedralPotential>> {
+/// Read a potential for dihedral angles or improper dihedral angles, using
+/// the given `context` in error messages.
+fn read_dihedral_potential(dihedral: &Table, context: &str) -> Result<Box<DihedralPotential>> {
     let potentials = dihedral.keys().cloned().filter(|key| key != "atoms").collect::<Vec<_>>();
 
     if potentials.is_empty() {
-        return Err(Error::from("Missing potential type in dihedral potential"));
+        return Err(Error::from(format!("Missing potential type in {}", context)));
     }
 
     if potentials.len() > 1 {
         return Err(Error::from(format!(
-            "Got more than one potential type in dihedral potential: {}",
+            "Got more than one potential type in {}: {}",
+            context,
             potentials.join(" and ")
         )));
     }
 
     let key = &*potentials[0];
+    if let ("torsion", &Value::Array(ref terms)) = (key, &dihedral[key]) {
+        // Multiple torsion terms for the same dihedral angle
+        let mut torsions = Vec::new();
+        for term in terms {
+            let table = term.as_table().ok_or(
+                Error::from("'torsion' potential must be a table or an array of tables")
+            )?;
+            torsions.push(Torsion::from_toml(table)?);
+        }
+        return Ok(Box::new(CharmmDihedral { terms: torsions }));
+    }
+
     if let Value::Table(ref table) = dihedral[key] {
         match key {
             "null" => Ok(Box::new(NullPotential::from_toml(table)?)),
             "harmonic" => Ok(Box::new(Harmonic::from_toml(table)?)),
             "cosine-harmonic" => Ok(Box::new(CosineHarmonic::from_toml(table)?)),
             "torsion" => Ok(Box::new(Torsion::from_toml(table)?)),
+            "opls" => Ok(Box::new(Opls::from_toml(table)?)),
+            "ryckaert-bellemans" => Ok(Box::new(RyckaertBellemans::from_toml(table)?)),
             "morse" => Ok(Box::new(Morse::from_toml(table)?)),
             other => Err(Error::from(format!("Unknown potential type '{}'", other))),
         }
@@ -1,9 +1,11 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
-use toml::Value;
+use toml::value::{Table, Value};
+
+use std::collections::BTreeMap;
 
 use lumol::energy::{CoulombicPotential, Ewald, SharedEwald, Wolf};
-use lumol::sys::System;
+use lumol::sys::{NeutralityPolicy, System};
 
 use super::Input;
 use super::read_restriction;
@@ -21,7 +23,10 @@ impl Input {
 
         let coulomb = coulomb.as_table().ok_or(Error::from("The 'coulomb' section must be a table"))?;
 
-        let solvers = coulomb.keys().cloned().filter(|key| key != "restriction").collect::<Vec<_>>();
+        let solvers = coulomb.keys()
+                             .cloned()
+                             .filter(|key| key != "restriction" && key != "neutrality")
+                             .collect::<Vec<_>>();
 
         if solvers.len() != 1 {
             return Err(Error::from(
@@ -44,6 +49,13 @@ impl Input {
                 potential.set_restriction(restriction);
             }
 
+            let policy = read_neutrality(coulomb)?;
+            if let Err(charge) = system.check_neutrality(policy) {
+                return Err(Error::from(
+                    format!("System is not neutral and has a net charge of {:+}", charge),
+                ));
+            }
+
             system.set_coulomb_potential(potential);
             Ok(())
         } else {
@@ -51,18 +63,42 @@ impl Input {
         }
     }
 
-    /// Read the "charges" from the potential configuration.
+    /// Read the "charges" from the potential configuration, and set them on
+    /// the particles of the `system`.
     pub(crate) fn read_charges(&self, system: &mut System) -> Result<()> {
+        for (atom_type, charge) in self.charges()? {
+            let mut nchanged = 0;
+            for particle in system.particles_mut() {
+                if *particle.atom_type == atom_type {
+                    *particle.charge = charge;
+                    nchanged += 1;
+                }
+            }
+
+            if nchanged == 0 {
+                warn!(
+                    "No particle with type '{}' was found while setting the charges", atom_type
+                );
+            } else {
+                info!("Charge set to {:+} for {} {} particles", charge, nchanged, atom_type);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the charges from the potential configuration, indexed by particle
+    /// type.
+    pub(crate) fn charges(&self) -> Result<BTreeMap<String, f64>> {
+        let mut result = BTreeMap::new();
         let charges = match self.config.get("charges") {
             Some(charges) => charges,
-            None => return Ok(()),
+            None => return Ok(result),
         };
 
         let charges = charges.as_table().ok_or(
             Error::from("The 'charges' section must be a table")
         )?;
 
-        let mut total_charge = 0.0;
         for (name, charge) in charges.iter() {
             let charge = match *charge {
                 Value::Integer(val) => val as f64,
@@ -71,26 +107,24 @@ impl Input {
                     return Err(Error::from("Charges must be numbers"));
                 }
             };
-
-            let mut nchanged = 0;
-            for particle in system.particles_mut() {
-                if particle.name == name {
-                    *particle.charge = charge;
-                    nchanged += 1;
-                    total_charge += charge;
-                }
-            }
-
-            if nchanged == 0 {
-                warn!("No particle with name '{}' was found while setting the charges", name);
-            } else {
-                info!("Charge set to {:+} for {} {} particles", charge, nchanged, name);
-            }
+            let _ = result.insert(name.clone(), charge);
         }
+        Ok(result)
+    }
+}
 
-        if total_charge.abs() > 1e-6 {
-            warn!("System is not neutral and have a net charge of {:+}", total_charge);
+/// Read the policy to use with non neutral systems from the "coulomb" section
+fn read_neutrality(coulomb: &Table) -> Result<NeutralityPolicy> {
+    match coulomb.get("neutrality") {
+        None => Ok(NeutralityPolicy::Warn),
+        Some(&Value::String(ref policy)) => {
+            match &**policy {
+                "warn" => Ok(NeutralityPolicy::Warn),
+                "error" => Ok(NeutralityPolicy::Error),
+                "neutralize" => Ok(NeutralityPolicy::Neutralize),
+                other => Err(Error::from(format!("Unknown neutrality policy '{}'", other))),
+            }
         }
-        Ok(())
+        Some(_) => Err(Error::from("'neutrality' must be a string in coulomb section")),
     }
 }
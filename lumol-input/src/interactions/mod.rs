@@ -17,6 +17,9 @@ mod toml;
 mod pairs;
 mod angles;
 mod coulomb;
+mod restraints;
+mod walls;
+mod virtual_sites;
 
 /// Input file for reading interactions
 pub struct Input {
@@ -52,9 +55,15 @@ impl Input {
         self.read_bonds(system)?;
         self.read_angles(system)?;
         self.read_dihedrals(system)?;
+        self.read_impropers(system)?;
+        self.read_cmaps(system)?;
+        self.read_nonbonded_angles(system)?;
         // charges must be read before coulomb
         self.read_charges(system)?;
         self.read_coulomb(system)?;
+        self.read_restraints(system)?;
+        self.read_walls(system)?;
+        self.read_virtual_sites(system)?;
         Ok(())
     }
 }
@@ -97,3 +106,116 @@ fn read_restriction(config: &Table) -> Result<Option<PairRestriction>> {
         _ => Err(Error::from("Restriction must be a table or a string")),
     }
 }
+
+/// Get the indexes of the particles selected by the `key` in `config`. The
+/// selection can be a particle name, an array of particle names, an array of
+/// particle indexes, or a table selecting particles by residue and chain.
+pub(crate) fn read_selection(
+    key: &str,
+    config: &Table,
+    system: &System,
+    context: &str
+) -> Result<Vec<usize>> {
+    let selection = config.get(key).ok_or(
+        Error::from(format!("Missing '{}' key in {}", key, context))
+    )?;
+
+    let names = match *selection {
+        Value::String(ref name) => vec![name.clone()],
+        Value::Array(ref array) => {
+            if array.iter().all(|value| value.is_integer()) {
+                let mut indexes = Vec::new();
+                for value in array {
+                    let index = value.as_integer().expect("Unreachable");
+                    if index < 0 || index as usize >= system.size() {
+                        return Err(Error::from(format!(
+                            "Particle index {} is out of bounds in '{}' in {}",
+                            index, key, context
+                        )));
+                    }
+                    indexes.push(index as usize);
+                }
+                return Ok(indexes);
+            }
+
+            let mut names = Vec::new();
+            for value in array {
+                let name = value.as_str().ok_or(Error::from(format!(
+                    "'{}' must contain particle names or indexes in {}",
+                    key, context
+                )))?;
+                names.push(String::from(name));
+            }
+            names
+        }
+        Value::Table(ref table) => return read_residue_selection(key, table, system, context),
+        _ => {
+            return Err(Error::from(format!(
+                "'{}' must be a string, an array or a table in {}",
+                key, context
+            )))
+        }
+    };
+
+    let particles = system.particles();
+    let indexes = (0..system.size()).filter(|&i| names.iter().any(|name| particles.name[i] == *name))
+                                    .collect();
+    return Ok(indexes);
+}
+
+/// Get the indexes of the particles selected by residue name, residue id
+/// and/or chain in the `selection` table. Only the particles matching all
+/// the criteria are selected.
+fn read_residue_selection(
+    key: &str,
+    selection: &Table,
+    system: &System,
+    context: &str
+) -> Result<Vec<usize>> {
+    if selection.is_empty() {
+        return Err(Error::from(format!("Empty '{}' table in {}", key, context)));
+    }
+
+    let mut residue = None;
+    let mut residue_id = None;
+    let mut chain = None;
+    for (name, value) in selection {
+        match &**name {
+            "residue" => {
+                residue = Some(value.as_str().ok_or(Error::from(format!(
+                    "'residue' must be a string in '{}' in {}", key, context
+                )))?);
+            }
+            "residue-id" => {
+                let id = value.as_integer().ok_or(Error::from(format!(
+                    "'residue-id' must be an integer in '{}' in {}", key, context
+                )))?;
+                if id < 0 {
+                    return Err(Error::from(format!(
+                        "'residue-id' can not be negative in '{}' in {}", key, context
+                    )));
+                }
+                residue_id = Some(id as u64);
+            }
+            "chain" => {
+                chain = Some(value.as_str().ok_or(Error::from(format!(
+                    "'chain' must be a string in '{}' in {}", key, context
+                )))?);
+            }
+            other => {
+                return Err(Error::from(format!(
+                    "Unknown key '{}' in '{}' in {}, expected 'residue', 'residue-id' or 'chain'",
+                    other, key, context
+                )))
+            }
+        }
+    }
+
+    let particles = system.particles();
+    let indexes = (0..system.size()).filter(|&i| {
+        residue.map_or(true, |name| particles.residue[i].as_ref().map_or(false, |r| r == name)) &&
+        residue_id.map_or(true, |id| particles.residue_id[i] == Some(id)) &&
+        chain.map_or(true, |name| particles.chain[i].as_ref().map_or(false, |c| c == name))
+    }).collect();
+    return Ok(indexes);
+}
@@ -1,14 +1,17 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
+use std::collections::{BTreeMap, BTreeSet};
+
 use toml::value::{Table, Value};
 
 use lumol::sys::System;
 use lumol::units;
 
-use lumol::energy::{BondPotential, PairInteraction, PairPotential};
-use lumol::energy::{BornMayerHuggins, Buckingham, Gaussian, Morse};
-use lumol::energy::{Harmonic, LennardJones, NullPotential, Mie};
-use lumol::energy::TableComputation;
+use lumol::energy::{BondPotential, PairInteraction, PairPotential, PairRestriction};
+use lumol::energy::{BornMayerHuggins, Buckingham, Fene, Gaussian, Morse, Quartic};
+use lumol::energy::{Harmonic, LennardJones, NullPotential, Mie, MixingRule};
+use lumol::energy::{CappedBond, LennardJones1264, Potential, SoftRepulsive};
+use lumol::energy::{ExprPotential, TableComputation};
 
 use super::Input;
 use super::read_restriction;
@@ -16,10 +19,13 @@ use {FromToml, FromTomlWithData};
 use error::{Error, Result};
 use extract;
 
-/// Global settings for the pair interactions
+/// Global settings for the pair interactions. The cutoff, tail correction
+/// and restriction can be overridden in each pair interaction.
 struct GlobalInformation<'a> {
     cutoff: Option<&'a Value>,
     tail: Option<bool>,
+    restriction: Option<PairRestriction>,
+    mixing: Option<MixingRule>,
 }
 
 impl<'a> GlobalInformation<'a> {
@@ -39,15 +45,37 @@ impl<'a> GlobalInformation<'a> {
                     })
                     .map_or(Ok(None), |tail| tail.map(Some))?;
 
+                let restriction = read_restriction(global)?;
+
+                let mixing = match global.get("mixing") {
+                    Some(mixing) => {
+                        let mixing = mixing.as_str().ok_or(
+                            Error::from("The 'mixing' value must be a string")
+                        )?;
+                        match mixing {
+                            "lorentz-berthelot" => Some(MixingRule::LorentzBerthelot),
+                            "geometric" => Some(MixingRule::Geometric),
+                            other => {
+                                return Err(Error::from(format!("Unknown mixing rule '{}'", other)))
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
                 Ok(GlobalInformation {
                     cutoff: cutoff,
                     tail: tail,
+                    restriction: restriction,
+                    mixing: mixing,
                 })
             }
             None => {
                 Ok(GlobalInformation {
                     cutoff: None,
                     tail: None,
+                    restriction: None,
+                    mixing: None,
                 })
             }
         }
@@ -66,6 +94,11 @@ impl Input {
             Error::from("The 'pairs' section must be an array")
         )?;
 
+        let global = GlobalInformation::read(&self.config)?;
+        // Pairs with an explicit potential, and Lennard-Jones parameters for
+        // pairs of identical particles, used for mixing rules
+        let mut defined = BTreeSet::new();
+        let mut lennard_jones = BTreeMap::new();
         for pair in pairs {
             let pair = pair.as_table().ok_or(
                 Error::from("Pair potential entry must be a table")
@@ -97,7 +130,6 @@ impl Input {
                 potential
             };
 
-            let global = GlobalInformation::read(&self.config)?;
             let cutoff = match pair.get("cutoff") {
                 Some(cutoff) => cutoff,
                 None => {
@@ -107,23 +139,7 @@ impl Input {
                 }
             };
 
-            let mut interaction = match *cutoff {
-                Value::String(ref cutoff) => {
-                    let cutoff = units::from_str(cutoff)?;
-                    PairInteraction::new(potential, cutoff)
-                }
-                Value::Table(ref table) => {
-                    let shifted = table.get("shifted").ok_or(
-                        Error::from("'cutoff' table can only contain 'shifted' key")
-                    )?;
-                    let cutoff = shifted.as_str().ok_or(
-                        Error::from("'cutoff.shifted' value must be a string")
-                    )?;
-                    let cutoff = units::from_str(cutoff)?;
-                    PairInteraction::shifted(potential, cutoff)
-                }
-                _ => return Err(Error::from("'cutoff' must be a string or a table")),
-            };
+            let mut interaction = read_pair_interaction(potential, cutoff)?;
 
             let tail = pair.get("tail_correction")
                 .map(|tail| {
@@ -139,11 +155,44 @@ impl Input {
                 }
             }
 
-            if let Some(restriction) = read_restriction(pair)? {
+            let restriction = read_restriction(pair)?.or(global.restriction);
+            if let Some(restriction) = restriction {
                 interaction.set_restriction(restriction);
             }
 
             system.add_pair_potential((a, b), interaction);
+
+            if a == b {
+                if let Some(&Value::Table(ref lj)) = pair.get("lj") {
+                    let _ = lennard_jones.insert(a, LennardJones::from_toml(lj)?);
+                }
+            }
+            let _ = defined.insert(if a < b { (a, b) } else { (b, a) });
+        }
+
+        if let Some(mixing) = global.mixing {
+            // Use the mixing rule to create the missing Lennard-Jones
+            // potentials between different particles
+            for (&a, lj_a) in &lennard_jones {
+                for (&b, lj_b) in lennard_jones.range(a..).skip(1) {
+                    if defined.contains(&(a, b)) {
+                        continue;
+                    }
+
+                    let cutoff = global.cutoff.as_ref().ok_or(
+                        Error::from("Missing 'cutoff' value in global section for mixing rules")
+                    )?;
+                    let potential = Box::new(lj_a.mix(lj_b, mixing));
+                    let mut interaction = read_pair_interaction(potential, cutoff)?;
+                    if let Some(true) = global.tail {
+                        interaction.enable_tail_corrections();
+                    }
+                    if let Some(restriction) = global.restriction {
+                        interaction.set_restriction(restriction);
+                    }
+                    system.add_pair_potential((a, b), interaction);
+                }
+            }
         }
         Ok(())
     }
@@ -180,7 +229,24 @@ impl Input {
                 Error::from("The second atom name is not a string in pair potential")
             )?;
 
-            let potential = read_bond_potential(bond)?;
+            let mut potential = read_bond_potential(bond)?;
+            if let Some(max_force) = bond.get("max-force") {
+                let max_force = max_force.as_str().ok_or(
+                    Error::from("'max-force' must be a string in bond potential")
+                )?;
+                let max_force = units::from_str(max_force)?;
+                if max_force <= 0.0 {
+                    return Err(Error::from("'max-force' must be positive in bond potential"));
+                }
+
+                let reference = read_bond_reference(bond)?;
+                if !(potential.force(reference).abs() <= max_force) {
+                    return Err(Error::from(
+                        "'max-force' is smaller than the force at equilibrium in bond potential"
+                    ));
+                }
+                potential = Box::new(CappedBond::new(potential, reference, max_force));
+            }
             system.add_bond_potential((a, b), potential);
         }
         Ok(())
@@ -217,11 +283,14 @@ fn read_pair_potential(pair: &Table) -> Result<Box<PairPotential>> {
             "null" => Ok(Box::new(NullPotential::from_toml(table)?)),
             "harmonic" => Ok(Box::new(Harmonic::from_toml(table)?)),
             "lj" => Ok(Box::new(LennardJones::from_toml(table)?)),
+            "lj-12-6-4" => Ok(Box::new(LennardJones1264::from_toml(table)?)),
             "buckingham" => Ok(Box::new(Buckingham::from_toml(table)?)),
             "born" => Ok(Box::new(BornMayerHuggins::from_toml(table)?)),
             "morse" => Ok(Box::new(Morse::from_toml(table)?)),
             "gaussian" => Ok(Box::new(Gaussian::from_toml(table)?)),
+            "soft-repulsive" => Ok(Box::new(SoftRepulsive::from_toml(table)?)),
             "mie" => Ok(Box::new(Mie::from_toml(table)?)),
+            "custom" => Ok(Box::new(ExprPotential::from_toml(table)?)),
             other => Err(Error::from(format!("Unknown potential type '{}'", other))),
         }
     } else {
@@ -230,7 +299,10 @@ fn read_pair_potential(pair: &Table) -> Result<Box<PairPotential>> {
 }
 
 fn read_bond_potential(pair: &Table) -> Result<Box<BondPotential>> {
-    let potentials = pair.keys().cloned().filter(|k| k != "atoms").collect::<Vec<_>>();
+    let potentials = pair.keys()
+                         .cloned()
+                         .filter(|k| k != "atoms" && k != "max-force")
+                         .collect::<Vec<_>>();
 
     if potentials.is_empty() {
         return Err(Error::from("Missing potential type in bond potential"));
@@ -249,6 +321,8 @@ fn read_bond_potential(pair: &Table) -> Result<Box<BondPotential>> {
             "null" => Ok(Box::new(NullPotential::from_toml(table)?)),
             "harmonic" => Ok(Box::new(Harmonic::from_toml(table)?)),
             "morse" => Ok(Box::new(Morse::from_toml(table)?)),
+            "fene" => Ok(Box::new(Fene::from_toml(table)?)),
+            "quartic" => Ok(Box::new(Quartic::from_toml(table)?)),
             other => Err(Error::from(format!("Unknown potential type '{}'", other))),
         }
     } else {
@@ -256,6 +330,42 @@ fn read_bond_potential(pair: &Table) -> Result<Box<BondPotential>> {
     }
 }
 
+/// Get the reference value used to search for the force caps of a bond
+/// potential: the equilibrium value 'x0' for potentials having one, and zero
+/// for the other potentials.
+fn read_bond_reference(bond: &Table) -> Result<f64> {
+    let potential = bond.values().filter_map(|value| value.as_table()).next();
+    match potential.and_then(|potential| potential.get("x0")) {
+        Some(&Value::String(ref x0)) => Ok(units::from_str(x0)?),
+        _ => Ok(0.0),
+    }
+}
+
+/// Create a pair interaction for the `potential`, using the `cutoff` value
+/// from the input.
+fn read_pair_interaction(
+    potential: Box<PairPotential>,
+    cutoff: &Value,
+) -> Result<PairInteraction> {
+    match *cutoff {
+        Value::String(ref cutoff) => {
+            let cutoff = units::from_str(cutoff)?;
+            Ok(PairInteraction::new(potential, cutoff))
+        }
+        Value::Table(ref table) => {
+            let shifted = table.get("shifted").ok_or(
+                Error::from("'cutoff' table can only contain 'shifted' key")
+            )?;
+            let cutoff = shifted.as_str().ok_or(
+                Error::from("'cutoff.shifted' value must be a string")
+            )?;
+            let cutoff = units::from_str(cutoff)?;
+            Ok(PairInteraction::shifted(potential, cutoff))
+        }
+        _ => Err(Error::from("'cutoff' must be a string or a table")),
+    }
+}
+
 /// ***************************************************************************
 
 fn read_pair_computation(
@@ -0,0 +1,120 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use toml::value::Table;
+
+use std::f64;
+
+use lumol::energy::{DistanceRestraint, PositionRestraints};
+use lumol::sys::System;
+use lumol::units;
+
+use super::{Input, read_selection};
+use error::{Error, Result};
+use extract;
+
+impl Input {
+    /// Read the "restraints" section from the potential configuration.
+    pub(crate) fn read_restraints(&self, system: &mut System) -> Result<()> {
+        let restraints = match self.config.get("restraints") {
+            Some(restraints) => restraints,
+            None => return Ok(()),
+        };
+
+        let restraints = restraints.as_array().ok_or(
+            Error::from("The 'restraints' section must be an array")
+        )?;
+
+        let mut positions = PositionRestraints::new();
+        for restraint in restraints {
+            let restraint = restraint.as_table().ok_or(
+                Error::from("Restraint entry must be a table")
+            )?;
+
+            match extract::typ(restraint, "restraint")? {
+                "position" => read_position_restraint(restraint, system, &mut positions)?,
+                "distance" => {
+                    let restraint = read_distance_restraint(restraint, system)?;
+                    system.add_global_potential(Box::new(restraint));
+                }
+                other => return Err(Error::from(format!("Unknown restraint type '{}'", other))),
+            }
+        }
+
+        if !positions.is_empty() {
+            info!("Using position restraints for {} particles", positions.len());
+            system.add_global_potential(Box::new(positions));
+        }
+        Ok(())
+    }
+}
+
+/// Read a single position restraint, and add the corresponding tethers to
+/// `restraints`. The reference positions are the current positions of the
+/// particles in the `system`.
+fn read_position_restraint(
+    config: &Table,
+    system: &System,
+    restraints: &mut PositionRestraints,
+) -> Result<()> {
+    let k = extract::str("k", config, "position restraint")?;
+    let k = units::from_str(k)?;
+    let selection = read_selection("selection", config, system, "position restraint")?;
+    if selection.is_empty() {
+        warn!("No particle selected in position restraint");
+    }
+
+    let positions = system.particles().position;
+    for i in selection {
+        restraints.add(i, positions[i], k);
+    }
+    Ok(())
+}
+
+/// Read a distance restraint between two groups of particles. The restraint
+/// is harmonic if only 'r0' is given, and flat-bottomed if 'lower' and
+/// 'upper' are given instead.
+fn read_distance_restraint(config: &Table, system: &System) -> Result<DistanceRestraint> {
+    let k = extract::str("k", config, "distance restraint")?;
+    let k = units::from_str(k)?;
+
+    let (lower, upper) = if config.get("r0").is_some() {
+        if config.get("lower").is_some() || config.get("upper").is_some() {
+            return Err(Error::from(
+                "Can not use 'r0' together with 'lower' or 'upper' in distance restraint"
+            ));
+        }
+        let r0 = extract::str("r0", config, "distance restraint")?;
+        let r0 = units::from_str(r0)?;
+        (r0, r0)
+    } else {
+        let lower = extract::str("lower", config, "distance restraint")?;
+        let upper = extract::str("upper", config, "distance restraint")?;
+        (units::from_str(lower)?, units::from_str(upper)?)
+    };
+
+    if lower > upper {
+        return Err(Error::from("'lower' must be smaller than 'upper' in distance restraint"));
+    }
+
+    let linear = if config.get("linear").is_some() {
+        let linear = extract::str("linear", config, "distance restraint")?;
+        units::from_str(linear)?
+    } else {
+        f64::INFINITY
+    };
+
+    let first = read_selection("first", config, system, "distance restraint")?;
+    let second = read_selection("second", config, system, "distance restraint")?;
+    if first.is_empty() || second.is_empty() {
+        return Err(Error::from("Empty selection in distance restraint"));
+    }
+
+    Ok(DistanceRestraint {
+        first: first,
+        second: second,
+        lower: lower,
+        upper: upper,
+        k: k,
+        linear: linear,
+    })
+}
@@ -10,9 +10,12 @@ use FromTomlWithRefData;
 use error::{Error, Result};
 use extract;
 
-use lumol::energy::{BornMayerHuggins, Buckingham, Gaussian, Morse, Torsion};
+use lumol::energy::{BornMayerHuggins, Buckingham, Fene, Gaussian, Morse, Quartic, Torsion};
 use lumol::energy::{CosineHarmonic, Harmonic, LennardJones, NullPotential, Mie};
+use lumol::energy::{LennardJones1264, LennardJones93, SoftRepulsive};
+use lumol::energy::{CosineAngle, CosineSquaredAngle, Opls, RyckaertBellemans};
 use lumol::energy::{Ewald, Wolf};
+use lumol::energy::ExprPotential;
 use lumol::energy::{PairPotential, TableComputation};
 use lumol::units;
 use lumol::sys::Configuration;
@@ -47,6 +50,30 @@ impl FromToml for LennardJones {
     }
 }
 
+impl FromToml for LennardJones93 {
+    fn from_toml(table: &Table) -> Result<LennardJones93> {
+        let sigma = extract::str("sigma", table, "9-3 Lennard-Jones potential")?;
+        let epsilon = extract::str("epsilon", table, "9-3 Lennard-Jones potential")?;
+        Ok(LennardJones93 {
+            sigma: units::from_str(sigma)?,
+            epsilon: units::from_str(epsilon)?,
+        })
+    }
+}
+
+impl FromToml for LennardJones1264 {
+    fn from_toml(table: &Table) -> Result<LennardJones1264> {
+        let sigma = extract::str("sigma", table, "12-6-4 Lennard-Jones potential")?;
+        let epsilon = extract::str("epsilon", table, "12-6-4 Lennard-Jones potential")?;
+        let c4 = extract::str("C4", table, "12-6-4 Lennard-Jones potential")?;
+        Ok(LennardJones1264 {
+            sigma: units::from_str(sigma)?,
+            epsilon: units::from_str(epsilon)?,
+            c4: units::from_str(c4)?,
+        })
+    }
+}
+
 impl FromToml for Mie {
     fn from_toml(table: &Table) -> Result<Mie> {
         let sigma = extract::str("sigma", table, "Mie potential")?;
@@ -75,6 +102,23 @@ impl FromToml for CosineHarmonic {
     }
 }
 
+impl FromToml for CosineAngle {
+    fn from_toml(table: &Table) -> Result<CosineAngle> {
+        let k = extract::str("k", table, "cosine potential")?;
+        Ok(CosineAngle {
+            k: units::from_str(k)?,
+        })
+    }
+}
+
+impl FromToml for CosineSquaredAngle {
+    fn from_toml(table: &Table) -> Result<CosineSquaredAngle> {
+        let k = extract::str("k", table, "cosine squared potential")?;
+        let x0 = extract::str("x0", table, "cosine squared potential")?;
+        Ok(CosineSquaredAngle::new(units::from_str(k)?, units::from_str(x0)?))
+    }
+}
+
 impl FromToml for Torsion {
     fn from_toml(table: &Table) -> Result<Torsion> {
         let n = extract::uint("n", table, "torsion potential")?;
@@ -88,6 +132,32 @@ impl FromToml for Torsion {
     }
 }
 
+impl FromToml for Opls {
+    fn from_toml(table: &Table) -> Result<Opls> {
+        let v1 = extract::str("V1", table, "OPLS potential")?;
+        let v2 = extract::str("V2", table, "OPLS potential")?;
+        let v3 = extract::str("V3", table, "OPLS potential")?;
+        let v4 = extract::str("V4", table, "OPLS potential")?;
+        Ok(Opls {
+            v1: units::from_str(v1)?,
+            v2: units::from_str(v2)?,
+            v3: units::from_str(v3)?,
+            v4: units::from_str(v4)?,
+        })
+    }
+}
+
+impl FromToml for RyckaertBellemans {
+    fn from_toml(table: &Table) -> Result<RyckaertBellemans> {
+        let mut c = [0.0; 6];
+        for (n, value) in c.iter_mut().enumerate() {
+            let key = format!("C{}", n);
+            *value = units::from_str(extract::str(&key, table, "Ryckaert-Bellemans potential")?)?;
+        }
+        Ok(RyckaertBellemans { c: c })
+    }
+}
+
 impl FromToml for Buckingham {
     fn from_toml(table: &Table) -> Result<Buckingham> {
         let a = extract::str("A", table, "Buckingham potential")?;
@@ -146,6 +216,62 @@ impl FromToml for Gaussian {
     }
 }
 
+impl FromToml for SoftRepulsive {
+    fn from_toml(table: &Table) -> Result<SoftRepulsive> {
+        let a = units::from_str(extract::str("A", table, "soft repulsive potential")?)?;
+        let rc = units::from_str(extract::str("rc", table, "soft repulsive potential")?)?;
+
+        if rc <= 0.0 {
+            Err(Error::from("'rc' parameter has to be positive in soft repulsive potential"))
+        } else {
+            Ok(SoftRepulsive::new(a, rc))
+        }
+    }
+}
+
+impl FromToml for Fene {
+    fn from_toml(table: &Table) -> Result<Fene> {
+        let k = units::from_str(extract::str("k", table, "FENE potential")?)?;
+        let r0 = units::from_str(extract::str("r0", table, "FENE potential")?)?;
+
+        if r0 <= 0.0 {
+            Err(Error::from("'r0' parameter has to be positive in FENE potential"))
+        } else {
+            Ok(Fene { k: k, r0: r0 })
+        }
+    }
+}
+
+impl FromToml for Quartic {
+    fn from_toml(table: &Table) -> Result<Quartic> {
+        let x0 = extract::str("x0", table, "quartic potential")?;
+        let k2 = extract::str("k2", table, "quartic potential")?;
+        let k3 = extract::str("k3", table, "quartic potential")?;
+        let k4 = extract::str("k4", table, "quartic potential")?;
+        Ok(Quartic {
+            x0: units::from_str(x0)?,
+            k2: units::from_str(k2)?,
+            k3: units::from_str(k3)?,
+            k4: units::from_str(k4)?,
+        })
+    }
+}
+
+impl FromToml for ExprPotential {
+    fn from_toml(table: &Table) -> Result<ExprPotential> {
+        let energy = extract::str("energy", table, "custom potential")?;
+        let force = if table.contains_key("force") {
+            Some(extract::str("force", table, "custom potential")?)
+        } else {
+            None
+        };
+
+        ExprPotential::new(energy, force).map_err(|err| {
+            Error::from(format!("Invalid expression in custom potential: {}", err))
+        })
+    }
+}
+
 impl FromTomlWithData for TableComputation {
     type Data = Box<PairPotential>;
 
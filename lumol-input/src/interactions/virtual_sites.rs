@@ -0,0 +1,108 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use toml::value::Table;
+
+use lumol::sys::{System, VirtualSite};
+
+use super::Input;
+use error::{Error, Result};
+use extract;
+
+impl Input {
+    /// Read the "virtual-sites" section from the potential configuration.
+    pub(crate) fn read_virtual_sites(&self, system: &mut System) -> Result<()> {
+        let sites = match self.config.get("virtual-sites") {
+            Some(sites) => sites,
+            None => return Ok(()),
+        };
+
+        let sites = sites.as_array().ok_or(
+            Error::from("The 'virtual-sites' section must be an array")
+        )?;
+
+        for site in sites {
+            let site = site.as_table().ok_or(
+                Error::from("Virtual site entry must be a table")
+            )?;
+            read_virtual_site(site, system)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a single virtual site description, and add the corresponding virtual
+/// sites to all the molecules in the `system` containing it.
+fn read_virtual_site(config: &Table, system: &mut System) -> Result<()> {
+    let site_type = extract::str("site", config, "virtual site")?;
+    let parents = extract::slice("parents", config, "virtual site")?;
+    if parents.len() != 3 {
+        return Err(Error::from("'parents' must contain three particle types in virtual site"));
+    }
+    let mut parent_types = Vec::new();
+    for parent in parents {
+        let parent = parent.as_str().ok_or(
+            Error::from("'parents' must contain three particle types in virtual site")
+        )?;
+        parent_types.push(parent);
+    }
+
+    let a = extract::number("a", config, "virtual site")?;
+    let b = extract::number("b", config, "virtual site")?;
+    let c = if config.contains_key("c") {
+        extract::number("c", config, "virtual site")?
+    } else {
+        0.0
+    };
+
+    let mut sites = Vec::new();
+    for molecule in system.molecules() {
+        let types = molecule.particles().atom_type;
+        let start = molecule.start();
+        for (site, atom_type) in types.iter().enumerate() {
+            if atom_type != site_type {
+                continue;
+            }
+
+            // Use the first unused particle with the right type for each
+            // parent of the virtual site
+            let mut parents = Vec::new();
+            for &parent_type in &parent_types {
+                let parent = (0..types.len()).find(|&i| {
+                    types[i] == parent_type && i != site && !parents.contains(&i)
+                });
+                match parent {
+                    Some(parent) => parents.push(parent),
+                    None => {
+                        return Err(Error::from(format!(
+                            "Missing parent particle '{}' for virtual site '{}' in molecule",
+                            parent_type, site_type
+                        )))
+                    }
+                }
+            }
+
+            let mass = molecule.particles().mass[site];
+            if mass != 0.0 {
+                return Err(Error::from(format!(
+                    "Virtual site particles must be massless, but '{}' has a mass of {}",
+                    site_type, mass
+                )));
+            }
+
+            sites.push(VirtualSite::out_of_plane(
+                start + site,
+                [start + parents[0], start + parents[1], start + parents[2]],
+                a, b, c
+            ));
+        }
+    }
+
+    if sites.is_empty() {
+        warn!("No particle with type '{}' found for virtual site", site_type);
+    }
+
+    for site in sites {
+        system.add_virtual_site(site);
+    }
+    Ok(())
+}
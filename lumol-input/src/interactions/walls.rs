@@ -0,0 +1,97 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use toml::value::{Table, Value};
+
+use lumol::energy::{Harmonic, LennardJones93, NullPotential, Wall, WallPotential};
+use lumol::sys::System;
+use lumol::types::Vector3D;
+use lumol::units;
+
+use super::{Input, read_selection};
+use FromToml;
+use error::{Error, Result};
+use extract;
+
+impl Input {
+    /// Read the "walls" section from the potential configuration.
+    pub(crate) fn read_walls(&self, system: &mut System) -> Result<()> {
+        let walls = match self.config.get("walls") {
+            Some(walls) => walls,
+            None => return Ok(()),
+        };
+
+        let walls = walls.as_array().ok_or(Error::from("The 'walls' section must be an array"))?;
+
+        for wall in walls {
+            let wall = wall.as_table().ok_or(Error::from("Wall entry must be a table"))?;
+
+            let (axis, direction) = read_axis(extract::str("axis", wall, "wall")?)?;
+            let position = units::from_str(extract::str("position", wall, "wall")?)?;
+            let cutoff = units::from_str(extract::str("cutoff", wall, "wall")?)?;
+            let potential = read_wall_potential(wall)?;
+
+            let particles = read_selection("selection", wall, system, "wall")?;
+            if particles.is_empty() {
+                warn!("No particle selected in wall");
+            }
+
+            // The wall goes through the point at `position` on the axis
+            let mut origin = Vector3D::zero();
+            origin[axis] = position;
+            let mut normal = Vector3D::zero();
+            normal[axis] = direction;
+
+            let wall = Wall::new(particles, origin, normal, potential, cutoff);
+            system.add_global_potential(Box::new(wall));
+        }
+        Ok(())
+    }
+}
+
+/// Get the index of the axis perpendicular to a wall, and the direction of
+/// the normal along this axis from the axis name. The particles are on the
+/// positive side of the wall for 'x' or '+x', and on the negative side for
+/// '-x'.
+fn read_axis(axis: &str) -> Result<(usize, f64)> {
+    match axis {
+        "x" | "+x" => Ok((0, 1.0)),
+        "y" | "+y" => Ok((1, 1.0)),
+        "z" | "+z" => Ok((2, 1.0)),
+        "-x" => Ok((0, -1.0)),
+        "-y" => Ok((1, -1.0)),
+        "-z" => Ok((2, -1.0)),
+        other => Err(Error::from(format!("Unknown axis '{}' in wall", other))),
+    }
+}
+
+fn read_wall_potential(wall: &Table) -> Result<Box<WallPotential>> {
+    let potentials = wall.keys()
+                         .cloned()
+                         .filter(|key| {
+                             key != "selection" && key != "axis" && key != "position" && key != "cutoff"
+                         })
+                         .collect::<Vec<_>>();
+
+    if potentials.is_empty() {
+        return Err(Error::from("Missing potential type in wall"));
+    }
+
+    if potentials.len() > 1 {
+        return Err(Error::from(format!(
+            "Got more than one potential type in wall: {}",
+            potentials.join(" and ")
+        )));
+    }
+
+    let key = &*potentials[0];
+    if let Value::Table(ref table) = wall[key] {
+        match key {
+            "null" => Ok(Box::new(NullPotential::from_toml(table)?)),
+            "harmonic" => Ok(Box::new(Harmonic::from_toml(table)?)),
+            "lj-9-3" => Ok(Box::new(LennardJones93::from_toml(table)?)),
+            other => Err(Error::from(format!("Unknown potential type '{}'", other))),
+        }
+    } else {
+        Err(Error::from(format!("'{}' potential must be a table", key)))
+    }
+}
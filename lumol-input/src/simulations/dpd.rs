@@ -0,0 +1,39 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use toml::value::Table;
+
+use lumol::sim::DissipativeParticleDynamics;
+use lumol::units;
+
+use FromToml;
+use error::{Error, Result};
+use extract;
+
+impl FromToml for DissipativeParticleDynamics {
+    fn from_toml(config: &Table) -> Result<DissipativeParticleDynamics> {
+        let timestep = extract::str("timestep", config, "DPD propagator")?;
+        let timestep = units::from_str(timestep)?;
+        let temperature = extract::str("temperature", config, "DPD propagator")?;
+        let temperature = units::from_str(temperature)?;
+        let friction = extract::str("friction", config, "DPD propagator")?;
+        let friction = units::from_str(friction)?;
+        let cutoff = extract::str("cutoff", config, "DPD propagator")?;
+        let cutoff = units::from_str(cutoff)?;
+
+        let mut dpd = DissipativeParticleDynamics::new(timestep, temperature, friction, cutoff);
+        if config.contains_key("lambda") {
+            let lambda = extract::number("lambda", config, "DPD propagator")?;
+            if lambda < 0.0 || lambda > 1.0 {
+                return Err(Error::from("'lambda' must be between 0 and 1 in DPD propagator"));
+            }
+            dpd.set_lambda(lambda);
+        }
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "DPD propagator")?;
+            dpd.seed(seed);
+        }
+
+        Ok(dpd)
+    }
+}
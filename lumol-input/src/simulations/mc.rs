@@ -1,10 +1,11 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use toml::value::Table;
 
 use lumol::sim::mc::*;
-use lumol::sys::read_molecule;
+use lumol::sys::{read_molecule, MoleculeHash, MoleculeTemplate};
 use lumol::units;
 
 use FromTomlWithData;
@@ -12,9 +13,18 @@ use error::{Error, Result};
 use extract;
 use simulations::get_input_path;
 
+/// Charges and masses of the particles in the simulation, indexed by particle
+/// type, used for the molecules inserted in the system.
+pub(crate) type ParticleProperties = (BTreeMap<String, f64>, BTreeMap<String, f64>);
+
+/// Data needed to read the Monte Carlo moves: the path to the input file, the
+/// user-defined molecule names in the system, and the properties of the
+/// particles.
+pub(crate) type MovesData = (PathBuf, BTreeMap<String, MoleculeHash>, ParticleProperties);
+
 impl FromTomlWithData for MonteCarlo {
-    type Data = PathBuf;
-    fn from_toml(config: &Table, root: PathBuf) -> Result<MonteCarlo> {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<MonteCarlo> {
         let temperature = extract::str("temperature", config, "Monte Carlo propagator")?;
         let temperature = units::from_str(temperature)?;
 
@@ -44,12 +54,7 @@ impl FromTomlWithData for MonteCarlo {
                 None
             };
 
-            let mc_move: Box<MCMove> = match extract::typ(mc_move, "Monte Carlo move")? {
-                "Translate" => Box::new(Translate::from_toml(mc_move, root.clone())?),
-                "Rotate" => Box::new(Rotate::from_toml(mc_move, root.clone())?),
-                "Resize" => Box::new(Resize::from_toml(mc_move, root.clone())?),
-                other => return Err(Error::from(format!("Unknown Monte Carlo move '{}'", other))),
-            };
+            let mc_move = read_move(mc_move, Some(temperature), data.clone())?;
 
             match target_acceptance {
                 Some(ta) => {
@@ -73,49 +78,325 @@ impl FromTomlWithData for MonteCarlo {
     }
 }
 
+/// Read a single Monte Carlo move from the `config` table. The moves
+/// depending on the temperature can only be used if `temperature` is not
+/// `None`.
+pub(crate) fn read_move(
+    config: &Table,
+    temperature: Option<f64>,
+    data: MovesData,
+) -> Result<Box<MCMove>> {
+    let typ = extract::typ(config, "Monte Carlo move")?;
+    let no_temperature = || {
+        Error::from(format!("'{}' move can not be used without a temperature", typ))
+    };
+
+    let mc_move: Box<MCMove> = match typ {
+        "Translate" => Box::new(Translate::from_toml(config, data)?),
+        "Rotate" => Box::new(Rotate::from_toml(config, data)?),
+        "Torsion" => Box::new(Torsion::from_toml(config, data)?),
+        "Resize" => Box::new(Resize::from_toml(config, data)?),
+        "AnisotropicResize" => Box::new(AnisotropicResize::from_toml(config, data)?),
+        "HybridMD" => {
+            let temperature = temperature.ok_or_else(&no_temperature)?;
+            Box::new(HybridMD::from_toml(config, temperature)?)
+        }
+        "Regrow" => {
+            let temperature = temperature.ok_or_else(&no_temperature)?;
+            Box::new(Regrow::from_toml(config, (temperature, data))?)
+        }
+        "PartialRegrow" => Box::new(PartialRegrow::from_toml(config, data)?),
+        "Reptation" => Box::new(Reptation::from_toml(config, data)?),
+        "AggregationVolumeBias" => Box::new(AggregationVolumeBias::from_toml(config, data)?),
+        "GeometricCluster" => {
+            let temperature = temperature.ok_or_else(&no_temperature)?;
+            Box::new(GeometricCluster::new(temperature))
+        }
+        "InsertMolecule" => Box::new(InsertMolecule::from_toml(config, data)?),
+        "DeleteMolecule" => Box::new(DeleteMolecule::from_toml(config, data)?),
+        "CavityInsertMolecule" => Box::new(CavityInsertMolecule::from_toml(config, data)?),
+        "CavityDeleteMolecule" => Box::new(CavityDeleteMolecule::from_toml(config, data)?),
+        other => return Err(Error::from(format!("Unknown Monte Carlo move '{}'", other))),
+    };
+    return Ok(mc_move);
+}
+
 impl FromTomlWithData for Translate {
-    type Data = PathBuf;
-    fn from_toml(config: &Table, root: PathBuf) -> Result<Translate> {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<Translate> {
         let delta = extract::str("delta", config, "Translate move")?;
         let delta = units::from_str(delta)?;
-
-        if config.get("molecule").is_some() {
-            let molfile = extract::str("molecule", config, "Translate move")?;
-            let molfile = get_input_path(root, molfile);
-            let hash = read_molecule(molfile)?.as_ref().hash();
-            Ok(Translate::new(delta, hash))
-        } else {
-            Ok(Translate::new(delta, None))
-        }
+        let hash = read_molecule_hash(config, data, "Translate move")?;
+        Ok(Translate::new(delta, hash))
     }
 }
 
 impl FromTomlWithData for Rotate {
-    type Data = PathBuf;
-    fn from_toml(config: &Table, root: PathBuf) -> Result<Rotate> {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<Rotate> {
         let delta = extract::str("delta", config, "Rotate move")?;
         let delta = units::from_str(delta)?;
+        let hash = read_molecule_hash(config, data, "Rotate move")?;
+        Ok(Rotate::new(delta, hash))
+    }
+}
 
-        if config.get("molecule").is_some() {
-            let molfile = extract::str("molecule", config, "Rotate move")?;
-            let molfile = get_input_path(root, molfile);
-            let hash = read_molecule(molfile)?.as_ref().hash();
-            Ok(Rotate::new(delta, hash))
-        } else {
-            Ok(Rotate::new(delta, None))
+impl FromTomlWithData for Torsion {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<Torsion> {
+        let delta = extract::str("delta", config, "Torsion move")?;
+        let delta = units::from_str(delta)?;
+        if delta <= 0.0 {
+            return Err(Error::from("'delta' must be positive in Torsion move"));
         }
+        let hash = read_molecule_hash(config, data, "Torsion move")?;
+        Ok(Torsion::new(delta, hash))
     }
 }
 
 impl FromTomlWithData for Resize {
-    type Data = PathBuf;
-    fn from_toml(config: &Table, _: PathBuf) -> Result<Resize> {
+    type Data = MovesData;
+    fn from_toml(config: &Table, _: MovesData) -> Result<Resize> {
         let pressure = extract::str("pressure", config, "Resize move")?;
         let pressure = units::from_str(pressure)?;
 
-        let delta = extract::str("delta", config, "Resize move")?;
+        let sampling = if config.contains_key("sampling") {
+            extract::str("sampling", config, "Resize move")?
+        } else {
+            "linear"
+        };
+
+        match sampling {
+            "linear" => {
+                let delta = extract::str("delta", config, "Resize move")?;
+                let delta = units::from_str(delta)?;
+                Ok(Resize::new(pressure, delta))
+            }
+            "logarithmic" => {
+                let delta = extract::number("delta", config, "Resize move")?;
+                if delta <= 0.0 {
+                    return Err(Error::from("'delta' must be positive in Resize move"));
+                }
+                Ok(Resize::logarithmic(pressure, delta))
+            }
+            other => Err(Error::from(format!("Unknown sampling '{}' in Resize move", other))),
+        }
+    }
+}
+
+impl FromTomlWithData for AnisotropicResize {
+    type Data = MovesData;
+    fn from_toml(config: &Table, _: MovesData) -> Result<AnisotropicResize> {
+        let pressure = extract::str("pressure", config, "AnisotropicResize move")?;
+        let pressure = units::from_str(pressure)?;
+
+        let delta = extract::str("delta", config, "AnisotropicResize move")?;
+        let delta = units::from_str(delta)?;
+        if delta <= 0.0 {
+            return Err(Error::from("'delta' must be positive in AnisotropicResize move"));
+        }
+
+        let mut resize = AnisotropicResize::new(pressure, delta);
+        if config.contains_key("angle_delta") {
+            let angle_delta = extract::str("angle_delta", config, "AnisotropicResize move")?;
+            let angle_delta = units::from_str(angle_delta)?;
+            if angle_delta <= 0.0 {
+                return Err(Error::from(
+                    "'angle_delta' must be positive in AnisotropicResize move"
+                ));
+            }
+            resize.set_angle_delta(angle_delta);
+        }
+        Ok(resize)
+    }
+}
+
+impl FromTomlWithData for HybridMD {
+    type Data = f64;
+    fn from_toml(config: &Table, temperature: f64) -> Result<HybridMD> {
+        let timestep = extract::str("timestep", config, "HybridMD move")?;
+        let timestep = units::from_str(timestep)?;
+        let steps = extract::uint("steps", config, "HybridMD move")?;
+        if steps == 0 {
+            return Err(Error::from("'steps' must be positive in HybridMD move"));
+        }
+        Ok(HybridMD::new(temperature, timestep, steps as usize))
+    }
+}
+
+impl FromTomlWithData for InsertMolecule {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<InsertMolecule> {
+        let (template, chemical_potential) = read_exchange(config, data, "InsertMolecule move")?;
+        Ok(InsertMolecule::new(&template, chemical_potential))
+    }
+}
+
+impl FromTomlWithData for DeleteMolecule {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<DeleteMolecule> {
+        let (template, chemical_potential) = read_exchange(config, data, "DeleteMolecule move")?;
+        Ok(DeleteMolecule::new(&template, chemical_potential))
+    }
+}
+
+impl FromTomlWithData for CavityInsertMolecule {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<CavityInsertMolecule> {
+        let context = "CavityInsertMolecule move";
+        let (template, chemical_potential) = read_exchange(config, data, context)?;
+        let radius = read_cavity_radius(config, context)?;
+        Ok(CavityInsertMolecule::new(&template, chemical_potential, radius))
+    }
+}
+
+impl FromTomlWithData for CavityDeleteMolecule {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<CavityDeleteMolecule> {
+        let context = "CavityDeleteMolecule move";
+        let (template, chemical_potential) = read_exchange(config, data, context)?;
+        let radius = read_cavity_radius(config, context)?;
+        Ok(CavityDeleteMolecule::new(&template, chemical_potential, radius))
+    }
+}
+
+impl FromTomlWithData for Regrow {
+    type Data = (f64, MovesData);
+    fn from_toml(config: &Table, data: (f64, MovesData)) -> Result<Regrow> {
+        let (temperature, data) = data;
+        let trials = extract::uint("trials", config, "Regrow move")?;
+        if trials == 0 {
+            return Err(Error::from("'trials' must be positive in Regrow move"));
+        }
+        let hash = read_molecule_hash(config, data, "Regrow move")?;
+        Ok(Regrow::new(temperature, trials as usize, hash))
+    }
+}
+
+impl FromTomlWithData for PartialRegrow {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<PartialRegrow> {
+        let delta = extract::str("delta", config, "PartialRegrow move")?;
         let delta = units::from_str(delta)?;
+        if delta < 0.0 {
+            return Err(Error::from("'delta' must be positive or zero in PartialRegrow move"));
+        }
+
+        let angle_delta = extract::str("angle_delta", config, "PartialRegrow move")?;
+        let angle_delta = units::from_str(angle_delta)?;
+        if angle_delta < 0.0 {
+            return Err(Error::from(
+                "'angle_delta' must be positive or zero in PartialRegrow move"
+            ));
+        }
+
+        let dihedral_delta = extract::str("dihedral_delta", config, "PartialRegrow move")?;
+        let dihedral_delta = units::from_str(dihedral_delta)?;
+        if dihedral_delta <= 0.0 {
+            return Err(Error::from("'dihedral_delta' must be positive in PartialRegrow move"));
+        }
 
-        Ok(Resize::new(pressure, delta))
+        let hash = read_molecule_hash(config, data, "PartialRegrow move")?;
+        Ok(PartialRegrow::new(delta, angle_delta, dihedral_delta, hash))
     }
 }
+
+impl FromTomlWithData for Reptation {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<Reptation> {
+        let hash = read_molecule_hash(config, data, "Reptation move")?;
+        Ok(Reptation::new(hash))
+    }
+}
+
+impl FromTomlWithData for AggregationVolumeBias {
+    type Data = MovesData;
+    fn from_toml(config: &Table, data: MovesData) -> Result<AggregationVolumeBias> {
+        let context = "AggregationVolumeBias move";
+        let inner = units::from_str(extract::str("inner", config, context)?)?;
+        if inner < 0.0 {
+            return Err(Error::from(
+                "'inner' must be positive or zero in AggregationVolumeBias move"
+            ));
+        }
+        let outer = units::from_str(extract::str("outer", config, context)?)?;
+        if outer <= inner {
+            return Err(Error::from(
+                "'outer' must be larger than 'inner' in AggregationVolumeBias move"
+            ));
+        }
+
+        let hash = read_molecule_hash(config, data, context)?;
+        let mut avbmc = AggregationVolumeBias::new(inner, outer, hash);
+        if config.get("inward_probability").is_some() {
+            let probability = extract::number("inward_probability", config, context)?;
+            if probability <= 0.0 || probability >= 1.0 {
+                return Err(Error::from(
+                    "'inward_probability' must be between 0 and 1 in AggregationVolumeBias move"
+                ));
+            }
+            avbmc.set_inward_probability(probability);
+        }
+        Ok(avbmc)
+    }
+}
+
+/// Read the template of the exchanged molecules from the file in the
+/// `molecule` key, and the associated `chemical_potential`. The particles in
+/// the template use the same charges and masses as the particles with the
+/// same type in the simulation.
+fn read_exchange(
+    config: &Table,
+    data: MovesData,
+    context: &str,
+) -> Result<(MoleculeTemplate, f64)> {
+    let chemical_potential = extract::str("chemical_potential", config, context)?;
+    let chemical_potential = units::from_str(chemical_potential)?;
+
+    let (root, _, (charges, masses)) = data;
+    let molecule = extract::str("molecule", config, context)?;
+    let molfile = get_input_path(root, molecule);
+    let mut molecule = read_molecule(molfile)?;
+    for particle in molecule.particles_mut() {
+        if let Some(&charge) = charges.get(particle.atom_type.as_str()) {
+            *particle.charge = charge;
+        }
+        if let Some(&mass) = masses.get(particle.atom_type.as_str()) {
+            *particle.mass = mass;
+        }
+    }
+    let template = MoleculeTemplate::from(molecule);
+    Ok((template, chemical_potential))
+}
+
+/// Read the cavity radius of cavity-biased insertion and deletion moves
+fn read_cavity_radius(config: &Table, context: &str) -> Result<f64> {
+    let radius = extract::str("radius", config, context)?;
+    let radius = units::from_str(radius)?;
+    if radius <= 0.0 {
+        return Err(Error::from(format!("'radius' must be positive in {}", context)));
+    }
+    Ok(radius)
+}
+
+/// Read the hash of the molecules a move applies to, from the optional
+/// `molecule` key. This key can either contain a molecule name defined in the
+/// system, or a path to a file containing the molecule.
+fn read_molecule_hash(
+    config: &Table,
+    data: MovesData,
+    context: &str,
+) -> Result<Option<MoleculeHash>> {
+    if config.get("molecule").is_none() {
+        return Ok(None);
+    }
+
+    let (root, names, _) = data;
+    let molecule = extract::str("molecule", config, context)?;
+    if let Some(&hash) = names.get(molecule) {
+        return Ok(Some(hash));
+    }
+
+    let molfile = get_input_path(root, molecule);
+    return Ok(Some(read_molecule(molfile)?.as_ref().hash()));
+}
@@ -1,17 +1,23 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
+use std::path::PathBuf;
+use toml::Value;
 use toml::value::Table;
 
 use alternator::Alternator;
 use lumol::sim::md::*;
+use lumol::sys::System;
+use lumol::types::Vector3D;
 use lumol::units;
 
-use {FromToml, FromTomlWithData};
+use {FromToml, FromTomlWithData, FromTomlWithRefData};
 use error::{Error, Result};
 use extract;
+use interactions::read_selection;
 
-impl FromToml for MolecularDynamics {
-    fn from_toml(config: &Table) -> Result<MolecularDynamics> {
+impl FromTomlWithRefData for MolecularDynamics {
+    type Data = System;
+    fn from_toml(config: &Table, system: &System) -> Result<MolecularDynamics> {
         // Get the timestep of the simulation
         let timestep = extract::str("timestep", config, "molecular dynamics propagator")?;
         let timestep = units::from_str(timestep)?;
@@ -29,9 +35,20 @@ impl FromToml for MolecularDynamics {
                 "AnisoBerendsenBarostat" => {
                     Box::new(AnisoBerendsenBarostat::from_toml(integrator, timestep)?)
                 }
+                "ParrinelloRahmanBarostat" => {
+                    Box::new(ParrinelloRahmanBarostat::from_toml(integrator, timestep)?)
+                }
+                "MTK" => Box::new(MTKIntegrator::from_toml(integrator, timestep)?),
                 "Verlet" => Box::new(Verlet::from_toml(integrator, timestep)?),
                 "VelocityVerlet" => Box::new(VelocityVerlet::from_toml(integrator, timestep)?),
                 "LeapFrog" => Box::new(LeapFrog::from_toml(integrator, timestep)?),
+                "Langevin" => Box::new(Langevin::from_toml(integrator, timestep)?),
+                "RESPA" => Box::new(RESPA::from_toml(integrator, timestep)?),
+                "RigidBodyVerlet" => Box::new(RigidBodyVerlet::from_toml(integrator, timestep)?),
+                "SLLOD" => Box::new(SLLOD::from_toml(integrator, timestep)?),
+                "AdaptiveTimestep" => {
+                    Box::new(AdaptiveTimestep::from_toml(integrator, timestep)?)
+                }
                 other => return Err(Error::from(format!("Unknown integrator '{}'", other))),
             };
 
@@ -41,16 +58,10 @@ impl FromToml for MolecularDynamics {
         }
 
         if let Some(thermostat) = config.get("thermostat") {
-            let thermostat = thermostat.as_table().ok_or(
+            let config = thermostat.as_table().ok_or(
                 Error::from("'thermostat' must be a table in molecular dynamics")
             )?;
-
-            let thermostat: Box<Thermostat> = match extract::typ(thermostat, "thermostat")? {
-                "Berendsen" => Box::new(BerendsenThermostat::from_toml(thermostat)?),
-                "Rescale" => Box::new(RescaleThermostat::from_toml(thermostat)?),
-                other => return Err(Error::from(format!("Unknown thermostat type '{}'", other))),
-            };
-            md.set_thermostat(thermostat);
+            md.set_thermostat(read_thermostat(config, system)?);
         }
 
         if let Some(controls) = config.get("controls") {
@@ -71,6 +82,12 @@ impl FromToml for MolecularDynamics {
                         Box::new(Alternator::<RemoveRotation>::from_toml(control)?)
                     }
                     "Rewrap" => Box::new(Alternator::<Rewrap>::from_toml(control)?),
+                    "ConstantVelocityPulling" => {
+                        Box::new(read_pulling(control, system, timestep)?)
+                    }
+                    "Metadynamics" => Box::new(read_metadynamics(control, system, timestep)?),
+                    "UmbrellaSampling" => Box::new(read_umbrella(control, system, timestep)?),
+                    "MullerPlathe" => Box::new(MullerPlathe::from_toml(control, timestep)?),
                     other => return Err(Error::from(format!("Unknown control '{}'", other))),
                 };
                 md.add_control(control);
@@ -102,6 +119,130 @@ impl FromTomlWithData for LeapFrog {
     }
 }
 
+impl FromTomlWithData for RigidBodyVerlet {
+    type Data = f64;
+    fn from_toml(_: &Table, timestep: f64) -> Result<RigidBodyVerlet> {
+        Ok(RigidBodyVerlet::new(timestep))
+    }
+}
+
+impl FromTomlWithData for RESPA {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<RESPA> {
+        let inner_steps = extract::uint("inner_steps", config, "RESPA integrator")?;
+        if inner_steps == 0 {
+            return Err(Error::from("'inner_steps' must be a positive integer in RESPA integrator"));
+        }
+        Ok(RESPA::new(timestep, inner_steps as usize))
+    }
+}
+
+impl FromTomlWithData for SLLOD {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<SLLOD> {
+        let shear_rate = extract::str("shear_rate", config, "SLLOD integrator")?;
+        let shear_rate = units::from_str(shear_rate)?;
+        Ok(SLLOD::new(timestep, shear_rate))
+    }
+}
+
+impl FromTomlWithData for Langevin {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<Langevin> {
+        let temperature = extract::str("temperature", config, "Langevin integrator")?;
+        let temperature = units::from_str(temperature)?;
+        let friction = extract::str("friction", config, "Langevin integrator")?;
+        let friction = units::from_str(friction)?;
+        let mut langevin = Langevin::new(timestep, temperature, friction);
+
+        if let Some(frictions) = config.get("frictions") {
+            let frictions = frictions.as_table().ok_or(
+                Error::from("'frictions' must be a table in Langevin integrator")
+            )?;
+            for (atom_type, friction) in frictions {
+                let friction = friction.as_str().ok_or(
+                    Error::from("'frictions' values must be strings in Langevin integrator")
+                )?;
+                langevin.set_friction(atom_type, units::from_str(friction)?);
+            }
+        }
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "Langevin integrator")?;
+            langevin.seed(seed);
+        }
+
+        Ok(langevin)
+    }
+}
+
+impl FromTomlWithData for AdaptiveTimestep {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<AdaptiveTimestep> {
+        let integrator: Box<Integrator> = if let Some(integrator) = config.get("integrator") {
+            let integrator = integrator.as_table().ok_or(
+                Error::from("'integrator' must be a table in adaptive timestep integrator")
+            )?;
+            match extract::typ(integrator, "adaptive timestep integrator")? {
+                "VelocityVerlet" => Box::new(VelocityVerlet::from_toml(integrator, timestep)?),
+                "LeapFrog" => Box::new(LeapFrog::from_toml(integrator, timestep)?),
+                "Langevin" => Box::new(Langevin::from_toml(integrator, timestep)?),
+                "RESPA" => Box::new(RESPA::from_toml(integrator, timestep)?),
+                "RigidBodyVerlet" => Box::new(RigidBodyVerlet::from_toml(integrator, timestep)?),
+                other => {
+                    return Err(Error::from(format!(
+                        "Unsupported integrator '{}' in adaptive timestep integrator", other
+                    )))
+                }
+            }
+        } else {
+            Box::new(VelocityVerlet::new(timestep))
+        };
+
+        let displacement = config.contains_key("max_displacement");
+        let energy = config.contains_key("max_energy_drift");
+        let criterion = if displacement && energy {
+            return Err(Error::from(
+                "Can not have both 'max_displacement' and 'max_energy_drift' in adaptive \
+                timestep integrator"
+            ));
+        } else if displacement {
+            let max = extract::str("max_displacement", config, "adaptive timestep integrator")?;
+            TimestepCriterion::Displacement(units::from_str(max)?)
+        } else if energy {
+            let max = extract::str("max_energy_drift", config, "adaptive timestep integrator")?;
+            TimestepCriterion::EnergyDrift(units::from_str(max)?)
+        } else {
+            return Err(Error::from(
+                "Missing 'max_displacement' or 'max_energy_drift' key in adaptive timestep \
+                integrator"
+            ));
+        };
+        match criterion {
+            TimestepCriterion::Displacement(max) | TimestepCriterion::EnergyDrift(max) => {
+                if max <= 0.0 {
+                    return Err(Error::from(
+                        "The maximal error must be positive in adaptive timestep integrator"
+                    ));
+                }
+            }
+        }
+
+        let min = extract::str("min_timestep", config, "adaptive timestep integrator")?;
+        let min = units::from_str(min)?;
+        let max = extract::str("max_timestep", config, "adaptive timestep integrator")?;
+        let max = units::from_str(max)?;
+        if min <= 0.0 || max < min {
+            return Err(Error::from(
+                "'min_timestep' must be positive and smaller than 'max_timestep' in adaptive \
+                timestep integrator"
+            ));
+        }
+
+        Ok(AdaptiveTimestep::new(integrator, criterion, timestep, min, max))
+    }
+}
+
 impl FromTomlWithData for BerendsenBarostat {
     type Data = f64;
     fn from_toml(config: &Table, timestep: f64) -> Result<BerendsenBarostat> {
@@ -118,23 +259,516 @@ impl FromTomlWithData for AnisoBerendsenBarostat {
         let pressure = extract::str("pressure", config, "anisotropic Berendsen barostat")?;
         let pressure = units::from_str(pressure)?;
         let tau = extract::number("timestep", config, "anisotropic Berendsen barostat")?;
-        Ok(AnisoBerendsenBarostat::hydrostatic(timestep, pressure, tau))
+        let mut barostat = AnisoBerendsenBarostat::hydrostatic(timestep, pressure, tau);
+
+        let coupling = match config.get("coupling") {
+            None => "anisotropic",
+            Some(coupling) => {
+                coupling.as_str().ok_or(
+                    Error::from("'coupling' must be a string in anisotropic Berendsen barostat")
+                )?
+            }
+        };
+        if coupling != "surface-tension" && config.contains_key("surface_tension") {
+            return Err(Error::from(
+                "'surface_tension' can only be used with 'surface-tension' coupling in \
+                anisotropic Berendsen barostat"
+            ));
+        }
+
+        let coupling = match coupling {
+            "anisotropic" => PressureCoupling::Anisotropic,
+            "semi-isotropic" => PressureCoupling::SemiIsotropic,
+            "surface-tension" => {
+                let tension = extract::str(
+                    "surface_tension", config, "anisotropic Berendsen barostat"
+                )?;
+                PressureCoupling::SurfaceTension(units::from_str(tension)?)
+            }
+            other => {
+                return Err(Error::from(format!(
+                    "Unknown coupling '{}' in anisotropic Berendsen barostat, expected \
+                    'anisotropic', 'semi-isotropic' or 'surface-tension'", other
+                )))
+            }
+        };
+        barostat.set_coupling(coupling);
+        Ok(barostat)
+    }
+}
+
+impl FromTomlWithData for ParrinelloRahmanBarostat {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<ParrinelloRahmanBarostat> {
+        let pressure = extract::str("pressure", config, "Parrinello-Rahman barostat")?;
+        let pressure = units::from_str(pressure)?;
+        let tau = extract::number("timestep", config, "Parrinello-Rahman barostat")?;
+        Ok(ParrinelloRahmanBarostat::hydrostatic(timestep, pressure, tau))
+    }
+}
+
+impl FromTomlWithData for MTKIntegrator {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<MTKIntegrator> {
+        let temperature = extract::str("temperature", config, "MTK integrator")?;
+        let temperature = units::from_str(temperature)?;
+        let pressure = extract::str("pressure", config, "MTK integrator")?;
+        let pressure = units::from_str(pressure)?;
+        let tau_thermostat = extract::number("thermostat_timestep", config, "MTK integrator")?;
+        let tau_barostat = extract::number("barostat_timestep", config, "MTK integrator")?;
+        let mut mtk = MTKIntegrator::new(
+            timestep, temperature, pressure, tau_thermostat, tau_barostat
+        );
+
+        if config.contains_key("chain_length") {
+            let length = extract::uint("chain_length", config, "MTK integrator")?;
+            if length == 0 {
+                return Err(Error::from(
+                    "'chain_length' must be a positive integer in MTK integrator"
+                ));
+            }
+            mtk.set_chain_length(length as usize);
+        }
+
+        Ok(mtk)
+    }
+}
+
+/// Read a constant velocity pulling control for the `system`, using the
+/// integrator `timestep`.
+fn read_pulling(
+    config: &Table,
+    system: &System,
+    timestep: f64,
+) -> Result<ConstantVelocityPulling> {
+    let context = "constant velocity pulling";
+    let particles = read_selection("selection", config, system, context)?;
+    if particles.is_empty() {
+        return Err(Error::from(
+            "'selection' does not match any particle in constant velocity pulling"
+        ));
+    }
+
+    let direction = extract::slice("direction", config, context)?;
+    let direction = direction.iter().map(|value| {
+        value.as_float().or(value.as_integer().map(|value| value as f64))
+    }).collect::<Option<Vec<_>>>();
+    let direction = match direction {
+        Some(ref direction) if direction.len() == 3 => {
+            Vector3D::new(direction[0], direction[1], direction[2])
+        }
+        _ => {
+            return Err(Error::from(
+                "'direction' must be an array of three numbers in constant velocity pulling"
+            ))
+        }
+    };
+    if direction.norm2() == 0.0 {
+        return Err(Error::from(
+            "'direction' can not be the zero vector in constant velocity pulling"
+        ));
+    }
+
+    let k = extract::str("k", config, context)?;
+    let k = units::from_str(k)?;
+    if k < 0.0 {
+        return Err(Error::from("'k' must be positive in constant velocity pulling"));
+    }
+    let velocity = extract::str("velocity", config, context)?;
+    let velocity = units::from_str(velocity)?;
+
+    let mut pulling = ConstantVelocityPulling::new(particles, direction, k, velocity, timestep);
+    if let Some(output) = config.get("output") {
+        let path = output.as_str().ok_or(
+            Error::from("'output' must be a string in constant velocity pulling")
+        )?;
+        try_io!(pulling.set_output(path), PathBuf::from(path));
+    }
+    Ok(pulling)
+}
+
+/// Read a metadynamics control for the `system`, using the integrator
+/// `timestep`.
+fn read_metadynamics(config: &Table, system: &System, timestep: f64) -> Result<Metadynamics> {
+    let height = extract::str("height", config, "metadynamics")?;
+    let height = units::from_str(height)?;
+    if height <= 0.0 {
+        return Err(Error::from("'height' must be positive in metadynamics"));
+    }
+    let pace = extract::uint("pace", config, "metadynamics")?;
+    if pace == 0 {
+        return Err(Error::from("'pace' must be a positive integer in metadynamics"));
+    }
+
+    let mut metadynamics = Metadynamics::new(height, pace, timestep);
+    let variables = extract::slice("variables", config, "metadynamics")?;
+    if variables.is_empty() {
+        return Err(Error::from("'variables' can not be empty in metadynamics"));
+    }
+    for variable in variables {
+        let variable = variable.as_table().ok_or(
+            Error::from("'variables' must be an array of tables in metadynamics")
+        )?;
+        let width = read_quantity("width", variable, "collective variable")?;
+        if width <= 0.0 {
+            return Err(Error::from("'width' must be positive in collective variable"));
+        }
+        metadynamics.add_variable(read_collective_variable(variable, system)?, width);
+    }
+
+    if config.contains_key("bias_factor") {
+        let bias_factor = extract::number("bias_factor", config, "metadynamics")?;
+        if bias_factor <= 1.0 {
+            return Err(Error::from("'bias_factor' must be larger than 1 in metadynamics"));
+        }
+        let temperature = extract::str("temperature", config, "metadynamics")?;
+        let temperature = units::from_str(temperature)?;
+        if temperature <= 0.0 {
+            return Err(Error::from("'temperature' must be positive in metadynamics"));
+        }
+        metadynamics.set_well_tempered(bias_factor, temperature);
+    }
+
+    if let Some(output) = config.get("output") {
+        let path = output.as_str().ok_or(
+            Error::from("'output' must be a string in metadynamics")
+        )?;
+        try_io!(metadynamics.set_output(path), PathBuf::from(path));
+    }
+    Ok(metadynamics)
+}
+
+/// Read an umbrella sampling control for the `system`, using the integrator
+/// `timestep`.
+fn read_umbrella(config: &Table, system: &System, timestep: f64) -> Result<UmbrellaSampling> {
+    let variable = extract::table("variable", config, "umbrella sampling")?;
+    let variable = read_collective_variable(variable, system)?;
+    let center = read_quantity("center", config, "umbrella sampling")?;
+    let k = read_quantity("k", config, "umbrella sampling")?;
+    if k < 0.0 {
+        return Err(Error::from("'k' must be positive in umbrella sampling"));
+    }
+
+    let mut umbrella = UmbrellaSampling::new(variable, center, k, timestep);
+    if let Some(output) = config.get("output") {
+        let path = output.as_str().ok_or(
+            Error::from("'output' must be a string in umbrella sampling")
+        )?;
+        try_io!(umbrella.set_output(path), PathBuf::from(path));
+    }
+    if let Some(metadata) = config.get("metadata") {
+        let path = metadata.as_str().ok_or(
+            Error::from("'metadata' must be a string in umbrella sampling")
+        )?;
+        if !config.contains_key("output") {
+            return Err(Error::from("'metadata' requires an 'output' file in umbrella sampling"));
+        }
+        umbrella.set_metadata(path);
+    }
+    Ok(umbrella)
+}
+
+impl FromTomlWithData for MullerPlathe {
+    type Data = f64;
+    fn from_toml(config: &Table, timestep: f64) -> Result<MullerPlathe> {
+        let quantity = match extract::str("quantity", config, "Müller-Plathe exchange")? {
+            "momentum" => ExchangedQuantity::Momentum,
+            "energy" => ExchangedQuantity::Energy,
+            other => {
+                return Err(Error::from(format!(
+                    "Unknown exchanged quantity '{}' in Müller-Plathe exchange", other
+                )))
+            }
+        };
+
+        let slabs = extract::uint("slabs", config, "Müller-Plathe exchange")?;
+        if slabs < 2 || slabs % 2 != 0 {
+            return Err(Error::from(
+                "'slabs' must be a positive even integer in Müller-Plathe exchange"
+            ));
+        }
+        let every = extract::uint("every", config, "Müller-Plathe exchange")?;
+        if every == 0 {
+            return Err(Error::from(
+                "'every' must be a positive integer in Müller-Plathe exchange"
+            ));
+        }
+
+        Ok(MullerPlathe::new(quantity, slabs as usize, every, timestep))
+    }
+}
+
+/// Read a quantity at the given `key` in the `config` table, either as a
+/// string with units, or as a number for quantities without units.
+fn read_quantity(key: &str, config: &Table, context: &str) -> Result<f64> {
+    match config.get(key) {
+        Some(&Value::String(ref value)) => Ok(units::from_str(value)?),
+        Some(&Value::Integer(value)) => Ok(value as f64),
+        Some(&Value::Float(value)) => Ok(value),
+        Some(_) => {
+            Err(Error::from(format!("'{}' must be a number or a string in {}", key, context)))
+        }
+        None => Err(Error::from(format!("Missing '{}' key in {}", key, context))),
+    }
+}
+
+/// Read a collective variable for the `system` from the `config` table.
+fn read_collective_variable(config: &Table, system: &System) -> Result<Box<CollectiveVariable>> {
+    let variable: Box<CollectiveVariable> = match extract::typ(config, "collective variable")? {
+        "Distance" => {
+            let context = "distance collective variable";
+            let particles = read_selection("particles", config, system, context)?;
+            if particles.len() != 2 || particles[0] == particles[1] {
+                return Err(Error::from(format!(
+                    "'particles' must contain two different particles in {}", context
+                )));
+            }
+            Box::new(DistanceVariable::new(particles[0], particles[1]))
+        }
+        "Angle" => {
+            let context = "angle collective variable";
+            let particles = read_selection("particles", config, system, context)?;
+            if particles.len() != 3 || particles[0] == particles[1] ||
+               particles[1] == particles[2] || particles[0] == particles[2] {
+                return Err(Error::from(format!(
+                    "'particles' must contain three different particles in {}", context
+                )));
+            }
+            Box::new(AngleVariable::new(particles[0], particles[1], particles[2]))
+        }
+        "CoordinationNumber" => {
+            let context = "coordination number collective variable";
+            let first = read_selection("first", config, system, context)?;
+            let second = read_selection("second", config, system, context)?;
+            if first.is_empty() || second.is_empty() {
+                return Err(Error::from(format!(
+                    "'first' and 'second' can not be empty in {}", context
+                )));
+            }
+            let r0 = extract::str("r0", config, context)?;
+            let r0 = units::from_str(r0)?;
+            if r0 <= 0.0 {
+                return Err(Error::from(format!("'r0' must be positive in {}", context)));
+            }
+
+            let mut n = 6;
+            if config.contains_key("n") {
+                n = extract::uint("n", config, context)?;
+            }
+            let mut m = 12;
+            if config.contains_key("m") {
+                m = extract::uint("m", config, context)?;
+            }
+            if n == 0 || m == 0 || n == m {
+                return Err(Error::from(format!(
+                    "'n' and 'm' must be different positive integers in {}", context
+                )));
+            }
+            Box::new(CoordinationNumber::new(first, second, r0, n as u32, m as u32))
+        }
+        other => return Err(Error::from(format!("Unknown collective variable '{}'", other))),
+    };
+    return Ok(variable);
+}
+
+/// Read a thermostat from the `config` table, wrapping it in a
+/// `ScheduledThermostat` if it follows a temperature schedule.
+fn read_thermostat(config: &Table, system: &System) -> Result<Box<Thermostat>> {
+    let mut thermostat: Box<Thermostat> = match extract::typ(config, "thermostat")? {
+        "Berendsen" => Box::new(BerendsenThermostat::from_toml(config)?),
+        "Rescale" => Box::new(RescaleThermostat::from_toml(config)?),
+        "NoseHoover" => Box::new(NoseHooverChainsThermostat::from_toml(config)?),
+        "CSVR" => Box::new(CSVRThermostat::from_toml(config)?),
+        "Groups" => Box::new(GroupThermostat::from_toml(config, system)?),
+        other => return Err(Error::from(format!("Unknown thermostat type '{}'", other))),
+    };
+
+    if let Some(schedule) = config.get("schedule") {
+        let schedule = schedule.as_table().ok_or(
+            Error::from("'schedule' must be a table in thermostat")
+        )?;
+        let schedule = TemperatureSchedule::from_toml(schedule)?;
+        thermostat = Box::new(ScheduledThermostat::new(thermostat, schedule));
+    }
+    Ok(thermostat)
+}
+
+impl FromTomlWithRefData for GroupThermostat {
+    type Data = System;
+    fn from_toml(config: &Table, system: &System) -> Result<GroupThermostat> {
+        let groups = extract::slice("groups", config, "group thermostat")?;
+        if groups.is_empty() {
+            return Err(Error::from("'groups' can not be empty in group thermostat"));
+        }
+
+        let mut thermostat = GroupThermostat::new();
+        let mut selected = vec![false; system.size()];
+        for group in groups {
+            let group = group.as_table().ok_or(
+                Error::from("'groups' must be an array of tables in group thermostat")
+            )?;
+            if extract::typ(group, "thermostat group")? == "Groups" {
+                return Err(Error::from("Group thermostats can not be nested"));
+            }
+
+            let particles = read_selection("selection", group, system, "thermostat group")?;
+            if particles.is_empty() {
+                warn!("'selection' in thermostat group does not match any particle");
+            }
+            for &i in &particles {
+                if selected[i] {
+                    return Err(Error::from(format!(
+                        "Particle {} is selected multiple times in group thermostat", i
+                    )));
+                }
+                selected[i] = true;
+            }
+            thermostat.add_group(particles, read_thermostat(group, system)?);
+        }
+        Ok(thermostat)
+    }
+}
+
+/// Get the target temperature of a thermostat. When the thermostat follows a
+/// temperature schedule, this is the initial temperature of the schedule.
+fn thermostat_temperature(config: &Table, context: &str) -> Result<f64> {
+    if let Some(schedule) = config.get("schedule") {
+        if config.contains_key("temperature") {
+            return Err(Error::from(
+                format!("Can not have both 'temperature' and 'schedule' in {}", context)
+            ));
+        }
+        let schedule = schedule.as_table().ok_or(
+            Error::from("'schedule' must be a table in thermostat")
+        )?;
+        Ok(TemperatureSchedule::from_toml(schedule)?.temperature(0))
+    } else {
+        let temperature = extract::str("temperature", config, context)?;
+        Ok(units::from_str(temperature)?)
+    }
+}
+
+/// Read a positive temperature at the given `key` in the `config`
+fn positive_temperature(key: &str, config: &Table, context: &str) -> Result<f64> {
+    let temperature = extract::str(key, config, context)?;
+    let temperature = units::from_str(temperature)?;
+    if temperature < 0.0 {
+        return Err(Error::from(format!("'{}' must be positive in {}", key, context)));
+    }
+    Ok(temperature)
+}
+
+impl FromToml for TemperatureSchedule {
+    fn from_toml(config: &Table) -> Result<TemperatureSchedule> {
+        match extract::typ(config, "temperature schedule")? {
+            "Linear" => {
+                let context = "linear temperature schedule";
+                let start = positive_temperature("start", config, context)?;
+                let end = positive_temperature("end", config, context)?;
+                let steps = extract::uint("steps", config, context)?;
+                Ok(TemperatureSchedule::Linear { start: start, end: end, steps: steps })
+            }
+            "Exponential" => {
+                let context = "exponential temperature schedule";
+                let start = positive_temperature("start", config, context)?;
+                let end = positive_temperature("end", config, context)?;
+                let tau = extract::number("tau", config, context)?;
+                if tau <= 0.0 {
+                    return Err(Error::from(format!("'tau' must be positive in {}", context)));
+                }
+                Ok(TemperatureSchedule::Exponential { start: start, end: end, tau: tau })
+            }
+            "Piecewise" => {
+                let context = "piecewise temperature schedule";
+                let points = config.get("points").ok_or(
+                    Error::from(format!("Missing 'points' key in {}", context))
+                )?;
+                let points = points.as_array().ok_or(
+                    Error::from(format!("'points' must be an array of tables in {}", context))
+                )?;
+                if points.is_empty() {
+                    return Err(Error::from(format!("'points' can not be empty in {}", context)));
+                }
+
+                let mut schedule = Vec::new();
+                for point in points {
+                    let point = point.as_table().ok_or(
+                        Error::from(format!("'points' must be an array of tables in {}", context))
+                    )?;
+                    let step = extract::uint("step", point, context)?;
+                    let temperature = positive_temperature("temperature", point, context)?;
+                    if let Some(&(previous, _)) = schedule.last() {
+                        if step <= previous {
+                            return Err(Error::from(format!(
+                                "'points' must be sorted by increasing step in {}", context
+                            )));
+                        }
+                    }
+                    schedule.push((step, temperature));
+                }
+                Ok(TemperatureSchedule::Piecewise(schedule))
+            }
+            other => Err(Error::from(format!("Unknown temperature schedule '{}'", other))),
+        }
     }
 }
 
 impl FromToml for BerendsenThermostat {
     fn from_toml(config: &Table) -> Result<BerendsenThermostat> {
-        let temperature = extract::str("temperature", config, "Berendsen thermostat")?;
-        let temperature = units::from_str(temperature)?;
+        let temperature = thermostat_temperature(config, "Berendsen thermostat")?;
         let tau = extract::number("timestep", config, "Berendsen thermostat")?;
         Ok(BerendsenThermostat::new(temperature, tau))
     }
 }
 
+impl FromToml for CSVRThermostat {
+    fn from_toml(config: &Table) -> Result<CSVRThermostat> {
+        let temperature = thermostat_temperature(config, "CSVR thermostat")?;
+        let tau = extract::number("timestep", config, "CSVR thermostat")?;
+        let mut thermostat = CSVRThermostat::new(temperature, tau);
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "CSVR thermostat")?;
+            thermostat.seed(seed);
+        }
+
+        Ok(thermostat)
+    }
+}
+
+impl FromToml for NoseHooverChainsThermostat {
+    fn from_toml(config: &Table) -> Result<NoseHooverChainsThermostat> {
+        let temperature = thermostat_temperature(config, "Nosé-Hoover thermostat")?;
+        let tau = extract::number("timestep", config, "Nosé-Hoover thermostat")?;
+        let mut thermostat = NoseHooverChainsThermostat::new(temperature, tau);
+
+        if config.contains_key("chain_length") {
+            let length = extract::uint("chain_length", config, "Nosé-Hoover thermostat")?;
+            if length == 0 {
+                return Err(Error::from(
+                    "'chain_length' must be a positive integer in Nosé-Hoover thermostat"
+                ));
+            }
+            thermostat.set_chain_length(length as usize);
+        }
+
+        if config.contains_key("suzuki_yoshida") {
+            let steps = extract::uint("suzuki_yoshida", config, "Nosé-Hoover thermostat")?;
+            if steps != 1 && steps != 3 && steps != 5 && steps != 7 {
+                return Err(Error::from(
+                    "'suzuki_yoshida' must be 1, 3, 5 or 7 in Nosé-Hoover thermostat"
+                ));
+            }
+            thermostat.set_suzuki_yoshida_steps(steps as usize);
+        }
+
+        Ok(thermostat)
+    }
+}
+
 impl FromToml for RescaleThermostat {
     fn from_toml(config: &Table) -> Result<RescaleThermostat> {
-        let temperature = extract::str("temperature", config, "Berendsen thermostat")?;
-        let temperature = units::from_str(temperature)?;
+        let temperature = thermostat_temperature(config, "Berendsen thermostat")?;
 
         if let Some(tolerance) = config.get("tolerance") {
             let tolerance = tolerance.as_str().ok_or(
@@ -15,21 +15,33 @@ impl FromToml for Minimization {
 
         let minimizer: Box<Minimizer> = match extract::typ(minimizer, "minimizer")? {
             "SteepestDescent" => Box::new(SteepestDescent::from_toml(minimizer)?),
+            "ConjugateGradient" => Box::new(ConjugateGradient::from_toml(minimizer)?),
             other => return Err(Error::from(format!("Unknown minimizer '{}'", other))),
         };
 
-        let tolerance = if let Some(tolerance) = config.get("tolerance") {
+        if let Some(tolerance) = config.get("tolerance") {
             let tolerance = tolerance.as_table().ok_or(
                 Error::from("'tolerance' must be a table in minimization propagator")
             )?;
-            Tolerance::from_toml(tolerance)?
+            let mut minimization = Minimization::new(minimizer, Tolerance::from_toml(tolerance)?);
+            if tolerance.contains_key("max_step") {
+                let max_step = extract::str("max_step", tolerance, "minimization tolerance")?;
+                let max_step = units::from_str(max_step)?;
+                if max_step < 0.0 {
+                    return Err(Error::from(
+                        "'max_step' must be positive in minimization tolerance"
+                    ));
+                }
+                minimization.set_max_step(max_step);
+            }
+            Ok(minimization)
         } else {
-            Tolerance {
+            let tolerance = Tolerance {
                 energy: units::from(1e-5, "kJ/mol").expect("bad unit"),
                 force2: units::from(1e-5, "kJ^2/mol^2/A^2").expect("bad unit"),
-            }
-        };
-        Ok(Minimization::new(minimizer, tolerance))
+            };
+            Ok(Minimization::new(minimizer, tolerance))
+        }
     }
 }
 
@@ -51,3 +63,19 @@ impl FromToml for SteepestDescent {
         Ok(SteepestDescent::new())
     }
 }
+
+impl FromToml for ConjugateGradient {
+    fn from_toml(config: &Table) -> Result<ConjugateGradient> {
+        let mut conjugate_gradient = ConjugateGradient::new();
+        if config.contains_key("restart") {
+            let restart = extract::uint("restart", config, "conjugate gradient minimizer")?;
+            if restart == 0 {
+                return Err(Error::from(
+                    "'restart' must be strictly positive in conjugate gradient minimizer"
+                ));
+            }
+            conjugate_gradient.set_restart(restart);
+        }
+        Ok(conjugate_gradient)
+    }
+}
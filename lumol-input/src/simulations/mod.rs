@@ -22,6 +22,11 @@ mod simulations;
 mod min;
 mod md;
 mod mc;
+mod dpd;
+mod remd;
+mod parallel_tempering;
+mod transition_matrix;
+mod wang_landau;
 
 pub use self::logging::setup_default_logger;
 
@@ -69,7 +74,7 @@ impl Input {
     pub fn read(&self) -> Result<Config> {
         self.setup_logging()?;
         let system = self.read_system()?;
-        let simulation = self.read_simulation()?;
+        let simulation = self.read_simulation(&system)?;
         let nsteps = self.read_nsteps()?;
 
         Ok(Config {
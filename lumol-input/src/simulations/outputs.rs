@@ -1,14 +1,19 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use toml::Value;
 use toml::value::Table;
 
 use lumol::sim::output::Output;
 use lumol::sim::output::{TrajectoryOutput, PropertiesOutput, EnergyOutput};
 use lumol::sim::output::{ForcesOutput, CellOutput, CustomOutput, StressOutput};
+use lumol::sim::output::{ProfilesOutput, WidomOutput};
+use lumol::sys::{read_molecule, MoleculeTemplate};
+use lumol::units;
 
 use super::Input;
-use FromToml;
+use simulations::get_input_path;
+use {FromToml, FromTomlWithData};
 use error::{Error, Result};
 use extract;
 
@@ -17,46 +22,59 @@ impl Input {
     pub(crate) fn read_outputs(&self) -> Result<Vec<(Box<Output>, u64)>> {
         let config = self.simulation_table()?;
         if let Some(outputs) = config.get("outputs") {
-            let outputs = outputs.as_array().ok_or(
-                Error::from("'outputs' must be an array of tables in simulation")
-            )?;
-
-            let mut result = Vec::new();
-            for output in outputs {
-                let output = output.as_table().ok_or(
-                    Error::from("'outputs' must be an array of tables in simulation")
-                )?;
-
-                let frequency = match output.get("frequency") {
-                    Some(frequency) => {
-                        frequency.as_integer().ok_or(
-                            Error::from("'frequency' must be an integer in output")
-                        )? as u64
-                    }
-                    None => 1,
-                };
-
-                let typ = extract::typ(output, "output")?;
-                let output: Box<Output> = match &*typ.to_lowercase() {
-                    "trajectory" => Box::new(TrajectoryOutput::from_toml(output)?),
-                    "properties" => Box::new(PropertiesOutput::from_toml(output)?),
-                    "energy" => Box::new(EnergyOutput::from_toml(output)?),
-                    "stress" => Box::new(StressOutput::from_toml(output)?),
-                    "forces" => Box::new(ForcesOutput::from_toml(output)?),
-                    "cell" => Box::new(CellOutput::from_toml(output)?),
-                    "custom" => Box::new(CustomOutput::from_toml(output)?),
-                    other => return Err(Error::from(format!("Unknown output type '{}'", other))),
-                };
-
-                result.push((output, frequency));
-            }
-            Ok(result)
+            read_outputs(outputs, &self.path, "simulation")
         } else {
             Ok(Vec::new())
         }
     }
 }
 
+/// Read an array of `outputs` tables, and the corresponding frequencies.
+/// `root` is the path to the input file, and `context` is used in error
+/// messages.
+pub(crate) fn read_outputs(
+    outputs: &Value,
+    root: &Path,
+    context: &str,
+) -> Result<Vec<(Box<Output>, u64)>> {
+    let outputs = outputs.as_array().ok_or(
+        Error::from(format!("'outputs' must be an array of tables in {}", context))
+    )?;
+
+    let mut result = Vec::new();
+    for output in outputs {
+        let output = output.as_table().ok_or(
+            Error::from(format!("'outputs' must be an array of tables in {}", context))
+        )?;
+
+        let frequency = match output.get("frequency") {
+            Some(frequency) => {
+                frequency.as_integer().ok_or(
+                    Error::from("'frequency' must be an integer in output")
+                )? as u64
+            }
+            None => 1,
+        };
+
+        let typ = extract::typ(output, "output")?;
+        let output: Box<Output> = match &*typ.to_lowercase() {
+            "trajectory" => Box::new(TrajectoryOutput::from_toml(output)?),
+            "properties" => Box::new(PropertiesOutput::from_toml(output)?),
+            "energy" => Box::new(EnergyOutput::from_toml(output)?),
+            "stress" => Box::new(StressOutput::from_toml(output)?),
+            "forces" => Box::new(ForcesOutput::from_toml(output)?),
+            "cell" => Box::new(CellOutput::from_toml(output)?),
+            "custom" => Box::new(CustomOutput::from_toml(output)?),
+            "profiles" => Box::new(ProfilesOutput::from_toml(output)?),
+            "widom" => Box::new(WidomOutput::from_toml(output, root.to_owned())?),
+            other => return Err(Error::from(format!("Unknown output type '{}'", other))),
+        };
+
+        result.push((output, frequency));
+    }
+    Ok(result)
+}
+
 fn get_file(config: &Table) -> Result<&str> {
     let file = config.get("file").ok_or(
         Error::from("Missing 'file' key in output")
@@ -121,3 +139,41 @@ impl FromToml for CustomOutput {
         Ok(output)
     }
 }
+
+impl FromToml for ProfilesOutput {
+    fn from_toml(config: &Table) -> Result<ProfilesOutput> {
+        let path = get_file(config)?;
+        let slabs = extract::uint("slabs", config, "profiles output")?;
+        if slabs == 0 {
+            return Err(Error::from("'slabs' must be a positive integer in profiles output"));
+        }
+        let output = try_io!(ProfilesOutput::new(path, slabs as usize), PathBuf::from(path));
+        Ok(output)
+    }
+}
+
+impl FromTomlWithData for WidomOutput {
+    type Data = PathBuf;
+    fn from_toml(config: &Table, root: PathBuf) -> Result<WidomOutput> {
+        let path = get_file(config)?;
+        let molecule = extract::str("molecule", config, "Widom output")?;
+        let template = MoleculeTemplate::from(read_molecule(get_input_path(root, molecule))?);
+
+        let temperature = extract::str("temperature", config, "Widom output")?;
+        let temperature = units::from_str(temperature)?;
+        if temperature <= 0.0 {
+            return Err(Error::from("'temperature' must be positive in Widom output"));
+        }
+
+        let insertions = extract::uint("insertions", config, "Widom output")?;
+        if insertions == 0 {
+            return Err(Error::from("'insertions' must be a positive integer in Widom output"));
+        }
+
+        let output = try_io!(
+            WidomOutput::new(path, &template, temperature, insertions as usize),
+            PathBuf::from(path)
+        );
+        Ok(output)
+    }
+}
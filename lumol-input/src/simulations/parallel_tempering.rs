@@ -0,0 +1,116 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use std::path::{Path, PathBuf};
+use toml::Value;
+use toml::value::Table;
+
+use lumol::sim::{MonteCarlo, ParallelTempering};
+use lumol::units;
+
+use FromTomlWithData;
+use error::{Error, Result};
+use extract;
+use super::mc::MovesData;
+use super::outputs::read_outputs;
+
+impl FromTomlWithData for ParallelTempering {
+    type Data = MovesData;
+    fn from_toml(
+        config: &Table,
+        data: MovesData,
+    ) -> Result<ParallelTempering> {
+        let temperatures = extract::slice("temperatures", config, "parallel tempering")?;
+        let temperatures = temperatures.iter().map(|temperature| {
+            let temperature = temperature.as_str().ok_or(
+                Error::from("'temperatures' must be an array of strings in parallel tempering")
+            )?;
+            Ok((temperature, units::from_str(temperature)?))
+        }).collect::<Result<Vec<(&str, f64)>>>()?;
+        if temperatures.len() < 2 {
+            return Err(Error::from(
+                "'temperatures' must contain at least two temperatures in parallel tempering"
+            ));
+        }
+        if temperatures.windows(2).any(|pair| pair[0].1 >= pair[1].1) {
+            return Err(Error::from(
+                "'temperatures' must be sorted in increasing order in parallel tempering"
+            ));
+        }
+        if config.contains_key("temperature") {
+            return Err(Error::from(
+                "'temperature' can not be used in parallel tempering, use 'temperatures' instead"
+            ));
+        }
+
+        let every = extract::uint("every", config, "parallel tempering")?;
+        if every == 0 {
+            return Err(Error::from("'every' must be a positive integer in parallel tempering"));
+        }
+
+        let seed = if config.contains_key("seed") {
+            Some(extract::uint("seed", config, "parallel tempering")?)
+        } else {
+            None
+        };
+
+        let mut pt = ParallelTempering::new(every);
+        for (i, &(temperature, _)) in temperatures.iter().enumerate() {
+            // Create the Monte Carlo propagator with the temperature of this
+            // replica, and a different seed for each replica
+            let mut replica = config.clone();
+            let _ = replica.insert("temperature".into(), Value::String(temperature.into()));
+            let mut mc = MonteCarlo::from_toml(&replica, data.clone())?;
+            mc.seed(seed.unwrap_or(42).wrapping_add(i as u64 + 1));
+            pt.add_replica(mc);
+        }
+
+        if let Some(seed) = seed {
+            pt.seed(seed);
+        }
+
+        if let Some(output) = config.get("output") {
+            let path = output.as_str().ok_or(
+                Error::from("'output' must be a string in parallel tempering")
+            )?;
+            try_io!(pt.set_output(path), PathBuf::from(path));
+        }
+
+        if let Some(outputs) = config.get("outputs") {
+            for i in 0..temperatures.len() {
+                let outputs = replica_outputs(outputs, i);
+                for (output, frequency) in read_outputs(&outputs, &data.0, "parallel tempering")? {
+                    pt.add_output(i, output, frequency);
+                }
+            }
+        }
+
+        Ok(pt)
+    }
+}
+
+/// Get the `outputs` for the replica at index `i`, adding this index to the
+/// output files names: `energy.dat` becomes `energy-3.dat` for `i = 3`.
+fn replica_outputs(outputs: &Value, i: usize) -> Value {
+    let mut outputs = outputs.clone();
+    if let Some(outputs) = outputs.as_array_mut() {
+        for output in outputs.iter_mut().filter_map(Value::as_table_mut) {
+            let file = match output.get("file").and_then(Value::as_str) {
+                Some(file) => replica_path(file, i),
+                None => continue,
+            };
+            let _ = output.insert("file".into(), Value::String(file));
+        }
+    }
+    return outputs;
+}
+
+/// Add the replica index `i` to the file name in `path`, before the extension
+fn replica_path(path: &str, i: usize) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, i, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, i),
+    };
+    return path.with_file_name(name).to_string_lossy().into_owned();
+}
@@ -1,22 +1,54 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
-use lumol::sim::{Minimization, MolecularDynamics, MonteCarlo, Propagator};
+use lumol::sim::{DissipativeParticleDynamics, Minimization, MolecularDynamics, MonteCarlo};
+use lumol::sim::{ParallelTempering, ReplicaExchange, TransitionMatrix, WangLandau};
+use lumol::sim::Propagator;
+use lumol::sys::System;
 
 use super::Input;
-use {FromToml, FromTomlWithData};
+use super::mc::MovesData;
+use {FromToml, FromTomlWithData, FromTomlWithRefData};
 use error::{Error, Result};
 use extract;
 
 impl Input {
-    /// Get the the simulation propagator.
-    pub(crate) fn read_propagator(&self) -> Result<Box<Propagator>> {
+    /// Get the the simulation propagator for the `system`.
+    pub(crate) fn read_propagator(&self, system: &System) -> Result<Box<Propagator>> {
         let config = self.simulation_table()?;
         let propagator = extract::table("propagator", config, "simulation")?;
         match extract::typ(propagator, "propagator")? {
-            "MolecularDynamics" => Ok(Box::new(MolecularDynamics::from_toml(propagator)?)),
-            "MonteCarlo" => Ok(Box::new(MonteCarlo::from_toml(propagator, self.path.clone())?)),
+            "MolecularDynamics" => {
+                Ok(Box::new(MolecularDynamics::from_toml(propagator, system)?))
+            }
+            "MonteCarlo" => {
+                let data = self.read_moves_data(system)?;
+                Ok(Box::new(MonteCarlo::from_toml(propagator, data)?))
+            }
             "Minimization" => Ok(Box::new(Minimization::from_toml(propagator)?)),
+            "DissipativeParticleDynamics" => {
+                Ok(Box::new(DissipativeParticleDynamics::from_toml(propagator)?))
+            }
+            "ReplicaExchange" => Ok(Box::new(ReplicaExchange::from_toml(propagator, system)?)),
+            "ParallelTempering" => {
+                let data = self.read_moves_data(system)?;
+                Ok(Box::new(ParallelTempering::from_toml(propagator, data)?))
+            }
+            "WangLandau" => {
+                let data = self.read_moves_data(system)?;
+                Ok(Box::new(WangLandau::from_toml(propagator, data)?))
+            }
+            "TransitionMatrix" => {
+                let data = self.read_moves_data(system)?;
+                Ok(Box::new(TransitionMatrix::from_toml(propagator, data)?))
+            }
             other => Err(Error::from(format!("Unknown propagator type '{}'", other))),
         }
     }
+
+    /// Get the data needed to read the Monte Carlo moves for the `system`
+    fn read_moves_data(&self, system: &System) -> Result<MovesData> {
+        let names = self.read_molecule_names()?;
+        let properties = self.read_particle_properties(system)?;
+        Ok((self.path.clone(), names, properties))
+    }
 }
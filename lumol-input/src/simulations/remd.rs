@@ -0,0 +1,73 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use std::path::PathBuf;
+use toml::value::Table;
+
+use lumol::sim::{MolecularDynamics, ReplicaExchange};
+use lumol::sys::System;
+use lumol::units;
+
+use FromTomlWithRefData;
+use error::{Error, Result};
+use extract;
+
+impl FromTomlWithRefData for ReplicaExchange {
+    type Data = System;
+    fn from_toml(config: &Table, system: &System) -> Result<ReplicaExchange> {
+        let temperatures = extract::slice("temperatures", config, "replica exchange")?;
+        let temperatures = temperatures.iter().map(|temperature| {
+            let temperature = temperature.as_str().ok_or(
+                Error::from("'temperatures' must be an array of strings in replica exchange")
+            )?;
+            Ok(units::from_str(temperature)?)
+        }).collect::<Result<Vec<f64>>>()?;
+        if temperatures.len() < 2 {
+            return Err(Error::from(
+                "'temperatures' must contain at least two temperatures in replica exchange"
+            ));
+        }
+        if temperatures.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::from(
+                "'temperatures' must be sorted in increasing order in replica exchange"
+            ));
+        }
+
+        if !config.contains_key("thermostat") {
+            return Err(Error::from("Missing 'thermostat' key in replica exchange"));
+        }
+        if let Some(controls) = config.get("controls").and_then(|controls| controls.as_array()) {
+            let outputs = controls.iter().filter_map(|control| control.as_table())
+                                         .any(|control| control.contains_key("output"));
+            if outputs {
+                return Err(Error::from(
+                    "controls with an 'output' file can not be used in replica exchange"
+                ));
+            }
+        }
+
+        let every = extract::uint("every", config, "replica exchange")?;
+        if every == 0 {
+            return Err(Error::from("'every' must be a positive integer in replica exchange"));
+        }
+
+        let mut remd = ReplicaExchange::new(every);
+        for temperature in temperatures {
+            let md = MolecularDynamics::from_toml(config, system)?;
+            remd.add_replica(md, temperature);
+        }
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "replica exchange")?;
+            remd.seed(seed);
+        }
+
+        if let Some(output) = config.get("output") {
+            let path = output.as_str().ok_or(
+                Error::from("'output' must be a string in replica exchange")
+            )?;
+            try_io!(remd.set_output(path), PathBuf::from(path));
+        }
+
+        Ok(remd)
+    }
+}
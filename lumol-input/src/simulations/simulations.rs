@@ -1,24 +1,89 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
-use lumol::sim::Simulation;
+use std::path::PathBuf;
+
+use lumol::sim::{Checkpoint, Simulation};
+use lumol::sys::System;
 use toml::value::Table;
 
 use super::Input;
+use simulations::get_input_path;
 use error::{Error, Result};
 use extract;
 
 impl Input {
-    /// Get the the simulation.
-    pub fn read_simulation(&self) -> Result<Simulation> {
-        let propagator = self.read_propagator()?;
+    /// Get the the simulation of the `system`. The system is used to resolve
+    /// the selections of particles in the simulation.
+    pub fn read_simulation(&self, system: &System) -> Result<Simulation> {
+        let propagator = self.read_propagator(system)?;
         let mut simulation = Simulation::new(propagator);
         for (output, frequency) in self.read_outputs()? {
             simulation.add_output_with_frequency(output, frequency);
         }
 
+        if let Some((path, frequency)) = self.read_checkpoint()? {
+            simulation.set_checkpoint(path, frequency);
+        }
+
+        if let Some(checkpoint) = self.read_restart()? {
+            simulation.restart(checkpoint);
+        }
+
+        if self.read_profile()? {
+            simulation.enable_profiling();
+        }
+
         Ok(simulation)
     }
 
+    /// Check if the simulation should measure the time spent in its
+    /// different parts.
+    fn read_profile(&self) -> Result<bool> {
+        let simulation = self.simulation_table()?;
+        match simulation.get("profile") {
+            Some(profile) => profile.as_bool().ok_or(
+                Error::from("'profile' should be a boolean value in simulation")
+            ),
+            None => Ok(false),
+        }
+    }
+
+    /// Get the path and the frequency of the checkpoints, if any.
+    fn read_checkpoint(&self) -> Result<Option<(PathBuf, u64)>> {
+        let simulation = self.simulation_table()?;
+        let checkpoint = match simulation.get("checkpoint") {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+
+        let checkpoint = checkpoint.as_table().ok_or(
+            Error::from("'checkpoint' must be a table in simulation")
+        )?;
+        let path = extract::str("file", checkpoint, "checkpoint")?;
+        let frequency = extract::uint("frequency", checkpoint, "checkpoint")?;
+        if frequency == 0 {
+            return Err(Error::from("'frequency' must be positive in checkpoint"));
+        }
+
+        Ok(Some((PathBuf::from(path), frequency)))
+    }
+
+    /// Get the checkpoint to restart the simulation from, if any.
+    fn read_restart(&self) -> Result<Option<Checkpoint>> {
+        let simulation = self.simulation_table()?;
+        let restart = match simulation.get("restart") {
+            Some(restart) => restart,
+            None => return Ok(None),
+        };
+
+        let restart = restart.as_str().ok_or(
+            Error::from("'restart' must be a string in simulation")
+        )?;
+        let path = get_input_path(&self.path, restart);
+        let checkpoint = Checkpoint::read(&path).map_err(|err| Error::from((err, path)))?;
+        Ok(Some(checkpoint))
+    }
+
     /// Get the number of steps in the simulation.
     pub(crate) fn read_nsteps(&self) -> Result<usize> {
         let simulation = self.simulation_table()?;
@@ -29,6 +94,9 @@ impl Input {
         let nsteps = nsteps.as_integer().ok_or(
             Error::from("'nsteps' key must be an integer")
         )?;
+        if nsteps < 0 {
+            return Err(Error::from("'nsteps' key must be positive or zero"));
+        }
 
         Ok(nsteps as usize)
     }
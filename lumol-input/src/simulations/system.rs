@@ -1,5 +1,6 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
+use std::collections::BTreeMap;
 use toml::value::{Table, Value};
 
 use lumol::sys::*;
@@ -9,7 +10,9 @@ use lumol::units;
 use {Input, InteractionsInput};
 use error::{Error, Result};
 use extract;
+use interactions::read_selection;
 use simulations::get_input_path;
+use super::mc::ParticleProperties;
 
 impl Input {
     /// Get the the simulated system.
@@ -46,6 +49,25 @@ impl Input {
             trajectory.read()?
         };
 
+        let guess_impropers = if let Some(guess_impropers) = config.get("guess_impropers") {
+            guess_impropers.as_bool().ok_or(
+                Error::from("'guess_impropers' should be a boolean value in system")
+            )?
+        } else {
+            false
+        };
+
+        if guess_impropers {
+            system.guess_impropers();
+        }
+
+        for (name, hash) in self.read_molecule_names()? {
+            system.set_molecule_name(hash, name);
+        }
+
+        self.read_periodicity(&mut system)?;
+        self.read_dimensions(&mut system)?;
+        self.read_frozen(&mut system)?;
         self.read_potentials(&mut system)?;
         self.init_velocities(&mut system)?;
 
@@ -116,6 +138,99 @@ impl Input {
         }
     }
 
+    /// Get the user-defined molecule names, associating each name with the
+    /// hash of the molecule in the corresponding file.
+    pub(crate) fn read_molecule_names(&self) -> Result<BTreeMap<String, MoleculeHash>> {
+        let config = self.system_table()?;
+        let mut names = BTreeMap::new();
+        if let Some(molecules) = config.get("molecules") {
+            let molecules = molecules.as_table().ok_or(
+                Error::from("'molecules' must be a table in system")
+            )?;
+
+            for name in molecules.keys() {
+                let molfile = extract::str(name, molecules, "system molecules")?;
+                let molfile = get_input_path(&self.path, molfile);
+                let hash = read_molecule(molfile)?.as_ref().hash();
+                if let Some((other, _)) = names.iter().find(|&(_, &h)| h == hash) {
+                    return Err(Error::from(format!(
+                        "'{}' and '{}' refer to the same molecule type in system molecules",
+                        other, name
+                    )));
+                }
+                let _ = names.insert(name.clone(), hash);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Get the charges and masses of the particles, indexed by particle type,
+    /// to use in the molecules inserted in the `system`. The charges come from
+    /// the `charges` section of the potentials, and the masses from the
+    /// particles already in the `system`.
+    pub(crate) fn read_particle_properties(&self, system: &System) -> Result<ParticleProperties> {
+        let charges = match self.read_interactions()? {
+            Some(input) => input.charges()?,
+            None => BTreeMap::new(),
+        };
+
+        let mut masses = BTreeMap::new();
+        for particle in system.particles() {
+            let _ = masses.insert(particle.atom_type.clone(), *particle.mass);
+        }
+        Ok((charges, masses))
+    }
+
+    fn read_periodicity(&self, system: &mut System) -> Result<()> {
+        let config = self.system_table()?;
+        if let Some(periodic) = config.get("periodic") {
+            let periodic = periodic.as_array().ok_or(
+                Error::from("'periodic' must be an array of 3 booleans in system")
+            )?;
+            if periodic.len() != 3 || periodic.iter().any(|value| !value.is_bool()) {
+                return Err(Error::from("'periodic' must be an array of 3 booleans in system"));
+            }
+
+            if system.cell.is_infinite() {
+                return Err(Error::from("can not set the periodicity of an infinite cell"));
+            }
+
+            let periodic = [
+                periodic[0].as_bool().expect("Unreachable"),
+                periodic[1].as_bool().expect("Unreachable"),
+                periodic[2].as_bool().expect("Unreachable"),
+            ];
+            system.cell.set_periodicity(periodic);
+        }
+        Ok(())
+    }
+
+    fn read_dimensions(&self, system: &mut System) -> Result<()> {
+        let config = self.system_table()?;
+        if config.get("dimensions").is_some() {
+            let dimensions = extract::uint("dimensions", config, "system")?;
+            if dimensions != 2 && dimensions != 3 {
+                return Err(Error::from("'dimensions' must be 2 or 3 in system"));
+            }
+            system.set_dimensions(dimensions as usize);
+        }
+        Ok(())
+    }
+
+    fn read_frozen(&self, system: &mut System) -> Result<()> {
+        let config = self.system_table()?;
+        if config.get("frozen").is_some() {
+            let frozen = read_selection("frozen", config, system, "system")?;
+            if frozen.is_empty() {
+                warn!("'frozen' selection does not match any particle in system");
+            }
+            for i in frozen {
+                system.particles_mut().frozen[i] = true;
+            }
+        }
+        Ok(())
+    }
+
     fn init_velocities(&self, system: &mut System) -> Result<()> {
         let config = self.system_table()?;
 
@@ -125,10 +240,45 @@ impl Input {
             )?;
 
             if velocities.get("init").is_some() {
-                let temperature = extract::str("init", velocities, "velocities initializer")?;
-                let temperature = units::from_str(temperature)?;
-                let mut velocities = BoltzmannVelocities::new(temperature);
-                velocities.init(system);
+                let init = extract::str("init", velocities, "velocities initializer")?;
+                if init == "from-file" {
+                    return read_file_velocities(velocities, system);
+                }
+
+                let temperature = units::from_str(init)?;
+                let mut initializer = BoltzmannVelocities::new(temperature);
+
+                if velocities.get("seed").is_some() {
+                    let seed = extract::uint("seed", velocities, "velocities initializer")?;
+                    initializer.seed(seed);
+                }
+
+                if let Some(zero) = velocities.get("zero_molecular_momentum") {
+                    let zero = zero.as_bool().ok_or(Error::from(
+                        "'zero_molecular_momentum' must be a boolean in velocities initializer"
+                    ))?;
+                    initializer.zero_molecular_momentum(zero);
+                }
+
+                if velocities.get("groups").is_some() {
+                    let groups = extract::slice("groups", velocities, "velocities initializer")?;
+                    for group in groups {
+                        let group = group.as_table().ok_or(Error::from(
+                            "'groups' must be an array of tables in velocities initializer"
+                        ))?;
+                        let particles = read_selection(
+                            "selection", group, system, "velocities group"
+                        )?;
+                        if particles.is_empty() {
+                            warn!("'selection' in velocities group does not match any particle");
+                        }
+                        let temperature = extract::str("init", group, "velocities group")?;
+                        let temperature = units::from_str(temperature)?;
+                        initializer.group(particles, temperature);
+                    }
+                }
+
+                initializer.init(system);
             } else {
                 warn!("'velocities' key does nothing in this input file");
             }
@@ -138,22 +288,29 @@ impl Input {
     }
 
     fn read_potentials(&self, system: &mut System) -> Result<()> {
+        if let Some(input) = self.read_interactions()? {
+            input.read(system)?;
+        } else {
+            warn!("No potentials found in input file");
+        }
+        Ok(())
+    }
+
+    /// Get the interactions input from the `potentials` key, if any.
+    fn read_interactions(&self) -> Result<Option<InteractionsInput>> {
         let config = self.system_table()?;
         if let Some(potentials) = config.get("potentials") {
             if let Some(potentials) = potentials.as_str() {
                 let path = get_input_path(&self.path, potentials);
-                let input = InteractionsInput::new(path)?;
-                input.read(system)?;
+                Ok(Some(InteractionsInput::new(path)?))
             } else if let Some(potentials) = potentials.as_table() {
-                let input = InteractionsInput::from_toml(potentials.clone())?;
-                input.read(system)?;
+                Ok(Some(InteractionsInput::from_toml(potentials.clone())?))
             } else {
-                return Err(Error::from("'potentials' must be a string or a table in system"));
+                Err(Error::from("'potentials' must be a string or a table in system"))
             }
         } else {
-            warn!("No potentials found in input file");
+            Ok(None)
         }
-        Ok(())
     }
 }
 
@@ -166,3 +323,18 @@ fn get_cell_number(value: &Value) -> Result<f64> {
         Err(Error::from("Values must be numbers in 'cell' array"))
     }
 }
+
+/// Keep the velocities read from the configuration file, warning about the
+/// options of the Boltzmann initializer which do not apply here.
+fn read_file_velocities(config: &Table, system: &System) -> Result<()> {
+    for key in &["seed", "groups", "zero_molecular_momentum"] {
+        if config.get(*key).is_some() {
+            warn!("'{}' is ignored when reading velocities from the configuration file", key);
+        }
+    }
+
+    if system.particles().velocity.iter().all(|velocity| velocity.norm2() == 0.0) {
+        warn!("no velocities found in the configuration file, all velocities are zero");
+    }
+    Ok(())
+}
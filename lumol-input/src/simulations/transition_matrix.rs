@@ -0,0 +1,86 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use std::path::PathBuf;
+use toml::value::Table;
+
+use lumol::sim::TransitionMatrix;
+use lumol::units;
+
+use FromTomlWithData;
+use error::{Error, Result};
+use extract;
+use super::mc::{read_move, MovesData};
+
+impl FromTomlWithData for TransitionMatrix {
+    type Data = MovesData;
+    fn from_toml(
+        config: &Table,
+        data: MovesData,
+    ) -> Result<TransitionMatrix> {
+        let molecules = extract::table("molecules", config, "transition matrix Monte Carlo")?;
+        let min = extract::uint("min", molecules, "transition matrix molecules")?;
+        let max = extract::uint("max", molecules, "transition matrix molecules")?;
+        if min >= max {
+            return Err(Error::from(
+                "'max' must be larger than 'min' in transition matrix molecules"
+            ));
+        }
+
+        let temperature = extract::str("temperature", config, "transition matrix Monte Carlo")?;
+        let temperature = units::from_str(temperature)?;
+        if temperature <= 0.0 {
+            return Err(Error::from(
+                "'temperature' must be positive in transition matrix Monte Carlo"
+            ));
+        }
+
+        let mut tmmc = TransitionMatrix::new(min as usize, max as usize, temperature);
+
+        if config.contains_key("bias_frequency") {
+            let frequency = extract::uint(
+                "bias_frequency", config, "transition matrix Monte Carlo"
+            )?;
+            if frequency == 0 {
+                return Err(Error::from(
+                    "'bias_frequency' must be positive in transition matrix Monte Carlo"
+                ));
+            }
+            tmmc.set_update_frequency(frequency);
+        }
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "transition matrix Monte Carlo")?;
+            tmmc.seed(seed);
+        }
+
+        if let Some(output) = config.get("output") {
+            let path = output.as_str().ok_or(
+                Error::from("'output' must be a string in transition matrix Monte Carlo")
+            )?;
+            try_io!(tmmc.set_output(path), PathBuf::from(path));
+        }
+
+        let moves = extract::slice("moves", config, "transition matrix Monte Carlo")?;
+        for mc_move in moves {
+            let mc_move = mc_move.as_table().ok_or(
+                Error::from("All moves must be tables in transition matrix Monte Carlo")
+            )?;
+
+            let frequency = if mc_move.get("frequency").is_some() {
+                extract::number("frequency", mc_move, "Monte Carlo move")?
+            } else {
+                1.0
+            };
+
+            if mc_move.contains_key("target_acceptance") {
+                return Err(Error::from(
+                    "'target_acceptance' can not be used in transition matrix Monte Carlo"
+                ));
+            }
+
+            tmmc.add(read_move(mc_move, Some(temperature), data.clone())?, frequency);
+        }
+
+        Ok(tmmc)
+    }
+}
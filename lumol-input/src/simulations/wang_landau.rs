@@ -0,0 +1,146 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use std::path::PathBuf;
+use toml::value::Table;
+
+use lumol::sim::WangLandau;
+use lumol::units;
+
+use FromTomlWithData;
+use error::{Error, Result};
+use extract;
+use super::mc::{read_move, MovesData};
+
+impl FromTomlWithData for WangLandau {
+    type Data = MovesData;
+    fn from_toml(
+        config: &Table,
+        data: MovesData,
+    ) -> Result<WangLandau> {
+        let (mut wang_landau, temperature) = match (config.get("energy"), config.get("molecules")) {
+            (Some(_), Some(_)) => {
+                return Err(Error::from(
+                    "only one of 'energy' or 'molecules' can be used in Wang-Landau sampling"
+                ));
+            }
+            (None, None) => {
+                return Err(Error::from(
+                    "missing 'energy' or 'molecules' key in Wang-Landau sampling"
+                ));
+            }
+            (Some(_), None) => {
+                let energy = extract::table("energy", config, "Wang-Landau sampling")?;
+                let min = units::from_str(extract::str("min", energy, "Wang-Landau energy")?)?;
+                let max = units::from_str(extract::str("max", energy, "Wang-Landau energy")?)?;
+                if min >= max {
+                    return Err(Error::from(
+                        "'max' must be larger than 'min' in Wang-Landau energy"
+                    ));
+                }
+                let bins = extract::uint("bins", energy, "Wang-Landau energy")?;
+                if bins == 0 {
+                    return Err(Error::from("'bins' must be positive in Wang-Landau energy"));
+                }
+                (WangLandau::energy(min, max, bins as usize), None)
+            }
+            (None, Some(_)) => {
+                let molecules = extract::table("molecules", config, "Wang-Landau sampling")?;
+                let min = extract::uint("min", molecules, "Wang-Landau molecules")?;
+                let max = extract::uint("max", molecules, "Wang-Landau molecules")?;
+                if min >= max {
+                    return Err(Error::from(
+                        "'max' must be larger than 'min' in Wang-Landau molecules"
+                    ));
+                }
+                let temperature = extract::str("temperature", config, "Wang-Landau sampling")?;
+                let temperature = units::from_str(temperature)?;
+                if temperature <= 0.0 {
+                    return Err(Error::from(
+                        "'temperature' must be positive in Wang-Landau sampling"
+                    ));
+                }
+                let wang_landau = WangLandau::molecules(min as usize, max as usize, temperature);
+                (wang_landau, Some(temperature))
+            }
+        };
+
+        if temperature.is_none() && config.contains_key("temperature") {
+            return Err(Error::from(
+                "'temperature' can not be used in Wang-Landau sampling of the energy"
+            ));
+        }
+
+        if config.contains_key("schedule") {
+            let schedule = extract::table("schedule", config, "Wang-Landau sampling")?;
+            let initial = extract::number("initial", schedule, "Wang-Landau schedule")?;
+            let reduction = extract::number("reduction", schedule, "Wang-Landau schedule")?;
+            let last = extract::number("final", schedule, "Wang-Landau schedule")?;
+            if initial <= 0.0 || last <= 0.0 {
+                return Err(Error::from(
+                    "'initial' and 'final' must be positive in Wang-Landau schedule"
+                ));
+            }
+            if reduction <= 0.0 || reduction >= 1.0 {
+                return Err(Error::from(
+                    "'reduction' must be between 0 and 1 in Wang-Landau schedule"
+                ));
+            }
+            wang_landau.set_schedule(initial, reduction, last);
+        }
+
+        if config.contains_key("flatness") {
+            let flatness = extract::number("flatness", config, "Wang-Landau sampling")?;
+            if flatness <= 0.0 || flatness >= 1.0 {
+                return Err(Error::from(
+                    "'flatness' must be between 0 and 1 in Wang-Landau sampling"
+                ));
+            }
+            wang_landau.set_flatness(flatness);
+        }
+
+        if config.contains_key("check_frequency") {
+            let frequency = extract::uint("check_frequency", config, "Wang-Landau sampling")?;
+            if frequency == 0 {
+                return Err(Error::from(
+                    "'check_frequency' must be positive in Wang-Landau sampling"
+                ));
+            }
+            wang_landau.set_check_frequency(frequency);
+        }
+
+        if config.contains_key("seed") {
+            let seed = extract::uint("seed", config, "Wang-Landau sampling")?;
+            wang_landau.seed(seed);
+        }
+
+        if let Some(output) = config.get("output") {
+            let path = output.as_str().ok_or(
+                Error::from("'output' must be a string in Wang-Landau sampling")
+            )?;
+            try_io!(wang_landau.set_output(path), PathBuf::from(path));
+        }
+
+        let moves = extract::slice("moves", config, "Wang-Landau sampling")?;
+        for mc_move in moves {
+            let mc_move = mc_move.as_table().ok_or(
+                Error::from("All moves must be tables in Wang-Landau sampling")
+            )?;
+
+            let frequency = if mc_move.get("frequency").is_some() {
+                extract::number("frequency", mc_move, "Monte Carlo move")?
+            } else {
+                1.0
+            };
+
+            if mc_move.contains_key("target_acceptance") {
+                return Err(Error::from(
+                    "'target_acceptance' can not be used in Wang-Landau sampling"
+                ));
+            }
+
+            wang_landau.add(read_move(mc_move, temperature, data.clone())?, frequency);
+        }
+
+        Ok(wang_landau)
+    }
+}
@@ -0,0 +1,31 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Check the molecules inserted by Monte Carlo moves read from the input
+extern crate lumol_input;
+
+use std::path::Path;
+
+use lumol_input::Input;
+
+#[test]
+fn inserted_charges() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("simulation")
+        .join("good")
+        .join("mc-charges.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+    let initial = config.system.molecules().count();
+    config.simulation.run(&mut config.system, 10);
+    assert!(config.system.molecules().count() > initial);
+
+    // The inserted molecules use the charges from the input
+    for particle in config.system.particles() {
+        match particle.name.as_str() {
+            "O" => assert_eq!(*particle.charge, -0.35),
+            "C" => assert_eq!(*particle.charge, 0.7),
+            other => panic!("unexpected particle {}", other),
+        }
+    }
+}
@@ -163,6 +163,15 @@ impl Drop for TestsCleanup {
             "custom.dat",
             "stress.dat",
             "forces.xyz",
+            "exchanges.dat",
+            "profiles.dat",
+            "widom.dat",
+            "energy-0.dat",
+            "energy-1.dat",
+            "energy-2.dat",
+            "energy-3.dat",
+            "dos.dat",
+            "tmmc.dat",
         ];
 
         for file in REMOVE {
@@ -0,0 +1,541 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Checkpoints store the complete state of a simulation, to be able to
+//! restart it exactly after an interruption.
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::io::prelude::*;
+use std::path::Path;
+
+use rand::{RngCore, SeedableRng, XorShiftRng};
+
+use core::{CellShape, Matrix3, System, UnitCell, Vector3D};
+
+/// Possible errors when reading or restoring a checkpoint
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// Any IO error
+    Io(io::Error),
+    /// The checkpoint file is not valid, or does not match the simulation
+    Invalid(String),
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> CheckpointError {
+        CheckpointError::Io(error)
+    }
+}
+
+impl From<String> for CheckpointError {
+    fn from(error: String) -> CheckpointError {
+        CheckpointError::Invalid(error)
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            CheckpointError::Io(ref err) => write!(fmt, "{}", err)?,
+            CheckpointError::Invalid(ref err) => write!(fmt, "{}", err)?,
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for CheckpointError {
+    fn description(&self) -> &str {
+        match *self {
+            CheckpointError::Io(ref err) => err.description(),
+            CheckpointError::Invalid(ref err) => err,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            CheckpointError::Io(ref err) => Some(err),
+            CheckpointError::Invalid(_) => None,
+        }
+    }
+}
+
+/// A `Checkpoint` contains the state of a simulation: the positions,
+/// velocities and unit cell of the system, together with the internal state
+/// of the propagator (thermostats and barostats variables, random number
+/// generators, *etc.*).
+///
+/// The data is organized in named sections, each containing named arrays of
+/// floating point values. Checkpoints are written to text files, storing the
+/// exact binary representation of all the values, so that a simulation
+/// restarted from a checkpoint follows exactly the same trajectory as the
+/// initial simulation.
+///
+/// The state of the random number generators is stored without advancing
+/// them, so writing a checkpoint does not change the trajectory of the
+/// simulation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    /// Values stored in this section
+    values: BTreeMap<String, Vec<f64>>,
+    /// Sub-sections of this section
+    sections: BTreeMap<String, Checkpoint>,
+}
+
+impl Checkpoint {
+    /// Create a new empty checkpoint
+    pub fn new() -> Checkpoint {
+        Checkpoint {
+            values: BTreeMap::new(),
+            sections: BTreeMap::new(),
+        }
+    }
+
+    /// Get the section with the given `name`, creating it if needed.
+    ///
+    /// # Panics
+    ///
+    /// If the `name` contains a dot or a whitespace.
+    pub fn section_mut(&mut self, name: &str) -> &mut Checkpoint {
+        check_name(name);
+        self.sections.entry(name.into()).or_insert_with(Checkpoint::new)
+    }
+
+    /// Get the section with the given `name`
+    pub fn section(&self, name: &str) -> Result<&Checkpoint, CheckpointError> {
+        self.sections.get(name).ok_or_else(|| {
+            CheckpointError::from(format!("missing '{}' section in checkpoint", name))
+        })
+    }
+
+    /// Store the `values` with the given `key`, replacing any previous
+    /// values with the same key.
+    ///
+    /// # Panics
+    ///
+    /// If the `key` contains a dot or a whitespace.
+    pub fn set_values(&mut self, key: &str, values: &[f64]) {
+        check_name(key);
+        let _ = self.values.insert(key.into(), values.to_vec());
+    }
+
+    /// Store a single `value` with the given `key`
+    pub fn set_value(&mut self, key: &str, value: f64) {
+        self.set_values(key, &[value]);
+    }
+
+    /// Store the `vectors` with the given `key`
+    pub fn set_vectors(&mut self, key: &str, vectors: &[Vector3D]) {
+        let values = vectors.iter().flat_map(|vector| vec![vector[0], vector[1], vector[2]])
+                                   .collect::<Vec<_>>();
+        self.set_values(key, &values);
+    }
+
+    /// Store the `matrix` with the given `key`
+    pub fn set_matrix(&mut self, key: &str, matrix: &Matrix3) {
+        let mut values = Vec::with_capacity(9);
+        for i in 0..3 {
+            for j in 0..3 {
+                values.push(matrix[i][j]);
+            }
+        }
+        self.set_values(key, &values);
+    }
+
+    /// Store the state of the random number generator `rng` with the given
+    /// `key`. The generator itself is not modified.
+    pub fn save_rng(&mut self, key: &str, rng: &XorShiftRng) {
+        let seed = xorshift_state(rng);
+        let values = seed.iter().map(|&byte| f64::from(byte)).collect::<Vec<_>>();
+        self.set_values(key, &values);
+    }
+
+    /// Get the values stored with the given `key`
+    pub fn values(&self, key: &str) -> Result<&[f64], CheckpointError> {
+        match self.values.get(key) {
+            Some(values) => Ok(values),
+            None => Err(CheckpointError::from(format!("missing '{}' value in checkpoint", key))),
+        }
+    }
+
+    /// Get the single value stored with the given `key`
+    pub fn value(&self, key: &str) -> Result<f64, CheckpointError> {
+        let values = self.values(key)?;
+        if values.len() != 1 {
+            return Err(invalid_size(key));
+        }
+        return Ok(values[0]);
+    }
+
+    /// Get the vectors stored with the given `key`
+    pub fn vectors(&self, key: &str) -> Result<Vec<Vector3D>, CheckpointError> {
+        let values = self.values(key)?;
+        if values.len() % 3 != 0 {
+            return Err(invalid_size(key));
+        }
+        let vectors = values.chunks(3)
+                            .map(|chunk| Vector3D::new(chunk[0], chunk[1], chunk[2]))
+                            .collect();
+        return Ok(vectors);
+    }
+
+    /// Get the matrix stored with the given `key`
+    pub fn matrix(&self, key: &str) -> Result<Matrix3, CheckpointError> {
+        let values = self.values(key)?;
+        if values.len() != 9 {
+            return Err(invalid_size(key));
+        }
+        let mut matrix = Matrix3::zero();
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = values[3 * i + j];
+            }
+        }
+        return Ok(matrix);
+    }
+
+    /// Get a random number generator in the state stored with the given
+    /// `key` by `save_rng`.
+    pub fn rng(&self, key: &str) -> Result<XorShiftRng, CheckpointError> {
+        let values = self.values(key)?;
+        if values.len() != 16 {
+            return Err(invalid_size(key));
+        }
+        let mut seed = [0; 16];
+        for (byte, &value) in seed.iter_mut().zip(values) {
+            *byte = value as u8;
+        }
+        return Ok(XorShiftRng::from_seed(seed));
+    }
+
+    /// Store the state of the `system` in the `system` section: the current
+    /// step, the unit cell, and the positions, velocities and periodic images
+    /// of all the particles.
+    pub fn save_system(&mut self, system: &System) {
+        let section = self.section_mut("system");
+        section.set_value("step", system.step as f64);
+
+        let shape = match system.cell.shape() {
+            CellShape::Infinite => 0.0,
+            CellShape::Orthorhombic => 1.0,
+            CellShape::Triclinic => 2.0,
+        };
+        section.set_value("shape", shape);
+        section.set_matrix("cell", &system.cell.matrix());
+        let periodicity = system.cell.periodicity().iter()
+                                .map(|&periodic| if periodic { 1.0 } else { 0.0 })
+                                .collect::<Vec<_>>();
+        section.set_values("periodicity", &periodicity);
+
+        let particles = system.particles();
+        section.set_vectors("positions", particles.position);
+        section.set_vectors("velocities", particles.velocity);
+        let images = particles.image.iter()
+                                    .flat_map(|image| image.iter().map(|&i| f64::from(i)))
+                                    .collect::<Vec<_>>();
+        section.set_values("images", &images);
+    }
+
+    /// Restore the state of the `system` from the `system` section. The
+    /// system must contain the same particles as the system used to create
+    /// the checkpoint.
+    pub fn restore_system(&self, system: &mut System) -> Result<(), CheckpointError> {
+        let section = self.section("system")?;
+        let positions = section.vectors("positions")?;
+        let velocities = section.vectors("velocities")?;
+        let images = section.values("images")?;
+        if positions.len() != system.size() {
+            return Err(CheckpointError::from(format!(
+                "the checkpoint contains {} particles, but the system contains {}",
+                positions.len(), system.size()
+            )));
+        }
+        if velocities.len() != system.size() {
+            return Err(invalid_size("velocities"));
+        }
+        if images.len() != 3 * system.size() {
+            return Err(invalid_size("images"));
+        }
+
+        let matrix = section.matrix("cell")?;
+        let periodicity = section.values("periodicity")?;
+        if periodicity.len() != 3 {
+            return Err(invalid_size("periodicity"));
+        }
+        let shape = match section.value("shape")? as u8 {
+            0 => CellShape::Infinite,
+            1 => CellShape::Orthorhombic,
+            2 => CellShape::Triclinic,
+            other => {
+                let message = format!("invalid cell shape {} in checkpoint", other);
+                return Err(CheckpointError::from(message));
+            }
+        };
+        let cell = if shape == CellShape::Infinite {
+            UnitCell::infinite()
+        } else {
+            let mut cell = UnitCell::from_matrix(matrix, shape);
+            cell.set_periodicity([
+                periodicity[0] != 0.0, periodicity[1] != 0.0, periodicity[2] != 0.0
+            ]);
+            cell
+        };
+
+        system.cell = cell;
+        system.step = section.value("step")? as u64;
+        for (i, (position, velocity, image)) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity, mut image]
+        ).enumerate() {
+            *position = positions[i];
+            *velocity = velocities[i];
+            *image = [images[3 * i] as i32, images[3 * i + 1] as i32, images[3 * i + 2] as i32];
+        }
+        return Ok(());
+    }
+
+    /// Write this checkpoint to the file at `path`, replacing the file if
+    /// it already exists.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "# Lumol checkpoint")?;
+        self.write_section(&mut file, "")?;
+        file.flush()?;
+        return Ok(());
+    }
+
+    /// Write the values in this section and all the sub-sections, using the
+    /// given `prefix` for the keys.
+    fn write_section<W: Write>(&self, file: &mut W, prefix: &str) -> Result<(), io::Error> {
+        for (key, values) in &self.values {
+            write!(file, "{}{}", prefix, key)?;
+            for value in values {
+                write!(file, " {:016x}", value.to_bits())?;
+            }
+            writeln!(file)?;
+        }
+
+        for (name, section) in &self.sections {
+            if section.values.is_empty() && section.sections.is_empty() {
+                // Empty sections are written as their name followed by a dot
+                writeln!(file, "{}{}.", prefix, name)?;
+            } else {
+                section.write_section(file, &format!("{}{}.", prefix, name))?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Read a checkpoint from the file at `path`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Checkpoint, CheckpointError> {
+        let file = BufReader::new(File::open(path)?);
+        let mut checkpoint = Checkpoint::new();
+        for line in file.lines() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            let mut splitted = line.split_whitespace();
+            let path = splitted.next().expect("empty lines are skipped");
+            let mut values = Vec::new();
+            for value in splitted {
+                let bits = u64::from_str_radix(value, 16).map_err(|_| {
+                    CheckpointError::from(format!("invalid value '{}' in checkpoint", value))
+                })?;
+                values.push(f64::from_bits(bits));
+            }
+
+            let empty_section = path.ends_with('.');
+            let mut names = if empty_section {
+                path[..path.len() - 1].split('.').collect::<Vec<_>>()
+            } else {
+                path.split('.').collect::<Vec<_>>()
+            };
+            let key = if empty_section {
+                None
+            } else {
+                names.pop()
+            };
+            if names.iter().chain(&key).any(|name| name.is_empty()) ||
+               (empty_section && !values.is_empty()) {
+                return Err(CheckpointError::from(format!("invalid key '{}' in checkpoint", path)));
+            }
+
+            let mut section = &mut checkpoint;
+            for name in names {
+                section = section.section_mut(name);
+            }
+            if let Some(key) = key {
+                section.set_values(key, &values);
+            }
+        }
+        return Ok(checkpoint);
+    }
+}
+
+/// Check that `name` can be used as a key or a section name
+fn check_name(name: &str) {
+    assert!(
+        !name.is_empty() && !name.contains('.') && !name.contains(char::is_whitespace),
+        "invalid name '{}' in checkpoint, names can not be empty or contain dots and spaces",
+        name
+    );
+}
+
+/// Get the error for values with the wrong size
+fn invalid_size(key: &str) -> CheckpointError {
+    CheckpointError::from(format!("wrong number of values for '{}' in checkpoint", key))
+}
+
+/// Get the seed creating a xorshift generator in the same state as `rng`,
+/// without advancing `rng`.
+///
+/// The state of a xorshift generator is not accessible, but after four
+/// steps it is made of the last four generated numbers. We use a copy of the
+/// generator to get this state, and then invert the four steps.
+fn xorshift_state(rng: &XorShiftRng) -> [u8; 16] {
+    let mut copy = rng.clone();
+    let mut state = [0u32; 4];
+    for value in &mut state {
+        *value = copy.next_u32();
+    }
+
+    for _ in 0..4 {
+        // One step goes from (x, y, z, w) to (y, z, w, w ^ (w >> 19) ^ t ^
+        // (t >> 8)), with t = x ^ (x << 11)
+        let (y, z, w, next) = (state[0], state[1], state[2], state[3]);
+        let t = invert_right_xorshift(next ^ w ^ (w >> 19), 8);
+        let x = invert_left_xorshift(t, 11);
+        state = [x, y, z, w];
+    }
+
+    let mut seed = [0; 16];
+    for (bytes, value) in seed.chunks_mut(4).zip(&state) {
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+    }
+    return seed;
+}
+
+/// Get `x` from `y = x ^ (x >> shift)`
+fn invert_right_xorshift(y: u32, shift: u32) -> u32 {
+    let mut x = y;
+    for _ in 0..(32 / shift) {
+        x = y ^ (x >> shift);
+    }
+    return x;
+}
+
+/// Get `x` from `y = x ^ (x << shift)`
+fn invert_left_xorshift(y: u32, shift: u32) -> u32 {
+    let mut x = y;
+    for _ in 0..(32 / shift) {
+        x = y ^ (x << shift);
+    }
+    return x;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use rand::Rng;
+    use core::{Molecule, Particle};
+
+    #[test]
+    fn values() {
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.set_value("value", 3.0);
+        checkpoint.section_mut("first").set_values("values", &[1.0, -2.5, 1e-300]);
+        checkpoint.section_mut("first").section_mut("0").set_vectors(
+            "vectors", &[Vector3D::new(0.1, 0.2, 0.3)]
+        );
+
+        assert_ulps_eq!(checkpoint.value("value").unwrap(), 3.0);
+        let first = checkpoint.section("first").unwrap();
+        assert_eq!(first.values("values").unwrap().len(), 3);
+        assert!(first.value("values").is_err());
+        assert!(first.values("value").is_err());
+        assert!(checkpoint.section("second").is_err());
+
+        let vectors = first.section("0").unwrap().vectors("vectors").unwrap();
+        assert_eq!(vectors, vec![Vector3D::new(0.1, 0.2, 0.3)]);
+    }
+
+    #[test]
+    fn rng() {
+        let mut rng = XorShiftRng::from_seed([
+            0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+            0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+        ]);
+        let _ = rng.gen::<u64>();
+        let mut copy = rng.clone();
+
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.save_rng("rng", &rng);
+
+        let mut restored = checkpoint.rng("rng").unwrap();
+        for _ in 0..10 {
+            let value = copy.gen::<u64>();
+            // Saving the state does not advance the generator
+            assert_eq!(rng.gen::<u64>(), value);
+            assert_eq!(restored.gen::<u64>(), value);
+        }
+    }
+
+    #[test]
+    fn file() {
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.set_values("values", &[0.1, -1.0 / 3.0, 4e78]);
+        checkpoint.section_mut("integrator").set_value("heat", 0.7);
+        checkpoint.section_mut("controls").section_mut("2").set_values("empty", &[]);
+        let _ = checkpoint.section_mut("controls").section_mut("3");
+
+        let tempfile = NamedTempFile::new().unwrap();
+        checkpoint.write(tempfile.path()).unwrap();
+        let restored = Checkpoint::read(tempfile.path()).unwrap();
+        assert_eq!(checkpoint, restored);
+
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Lumol checkpoint");
+        assert_eq!(lines[2], "controls.2.empty");
+        assert_eq!(lines[3], "controls.3.");
+        assert_eq!(lines[4], "integrator.heat 3fe6666666666666");
+    }
+
+    #[test]
+    fn system() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 1.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [3.0, 1.0, 0.0].into())));
+        system.particles_mut().velocity[1] = Vector3D::new(0.1, 0.2, -1.0 / 3.0);
+        system.particles_mut().image[0] = [1, 0, -2];
+        system.cell.set_shear_offset(1.2);
+        system.cell.set_periodicity([true, true, false]);
+        system.step = 42;
+
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.save_system(&system);
+
+        let mut restored = System::with_cell(UnitCell::cubic(5.0));
+        restored.add_molecule(Molecule::new(Particle::new("Ar")));
+        restored.add_molecule(Molecule::new(Particle::new("Ar")));
+        checkpoint.restore_system(&mut restored).unwrap();
+
+        assert_eq!(restored.step, 42);
+        assert_eq!(restored.cell, system.cell);
+        assert_eq!(restored.particles().position, system.particles().position);
+        assert_eq!(restored.particles().velocity, system.particles().velocity);
+        assert_eq!(restored.particles().image, system.particles().image);
+
+        restored.add_molecule(Molecule::new(Particle::new("Ar")));
+        assert!(checkpoint.restore_system(&mut restored).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Dissipative particle dynamics, a momentum conserving stochastic dynamics
+//! for coarse-grained simulations of soft matter.
+use rand::XorShiftRng;
+use rand::SeedableRng;
+use rand::distributions::{Normal, Distribution};
+
+use core::consts::K_BOLTZMANN;
+use core::{CellList, DegreesOfFreedom, System, Vector3D};
+
+use checkpoint::{Checkpoint, CheckpointError};
+use md::check_no_constraints;
+use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
+
+/// Dissipative particle dynamics (DPD) propagator [1].
+///
+/// In addition to the conservative forces coming from the interactions in
+/// the system, all the pairs of particles closer than a cutoff distance
+/// $r_c$ interact through a dissipative and a random force:
+///
+/// $$ F^D_{ij} = - \gamma w(r_{ij})^2 (e_{ij} \cdot v_{ij}) e_{ij} $$
+/// $$ F^R_{ij} = \sigma w(r_{ij}) \theta_{ij} / \sqrt{\Delta t} e_{ij} $$
+///
+/// where $w(r) = 1 - r / r_c$, $e_{ij}$ is the unit vector going from $j$ to
+/// $i$, $v_{ij} = v_i - v_j$, and $\theta_{ij}$ is a gaussian random number
+/// with zero mean and unit variance. The amplitude of the random force
+/// follows the fluctuation-dissipation theorem, $\sigma^2 = 2 \gamma k_B T$,
+/// and the system samples the canonical ensemble at the temperature $T$.
+/// Both forces act along the line joining the particles, conserving the
+/// total momentum and thus the hydrodynamic behavior of the system.
+///
+/// The standard soft conservative force of DPD, $F^C_{ij} = a w(r_{ij})
+/// e_{ij}$, is given by the [`SoftRepulsive`] pair potential with a maximal
+/// energy of $a r_c / 2$ and the same cutoff $r_c$.
+///
+/// The equations of motion are integrated with the modified velocity-Verlet
+/// algorithm from [1], where the dissipative forces are evaluated with
+/// velocities predicted using an empirical parameter $\lambda$. The default
+/// value $\lambda = 0.5$ corresponds to the usual velocity-Verlet algorithm.
+///
+/// [1] R.D. Groot and P.B. Warren, J. Chem. Phys. 107, 4423 (1997); doi:
+/// 10.1063/1.474784
+///
+/// [`SoftRepulsive`]: ../../lumol_core/energy/struct.SoftRepulsive.html
+pub struct DissipativeParticleDynamics {
+    /// Timestep for the integration
+    timestep: f64,
+    /// Target temperature
+    temperature: f64,
+    /// Friction coefficient of the dissipative forces, in mass per time
+    /// units
+    friction: f64,
+    /// Cutoff distance of the dissipative and random forces
+    cutoff: f64,
+    /// Parameter used to predict the velocities at the next step
+    lambda: f64,
+    /// Storing the accelerations
+    accelerations: Vec<Vector3D>,
+    /// Normal distribution for the random forces
+    dist: Normal,
+    /// Random number generator for the random forces
+    rng: XorShiftRng,
+}
+
+impl DissipativeParticleDynamics {
+    /// Create a new DPD propagator with a timestep of `timestep`, at the
+    /// given `temperature`, with the dissipative and random forces acting
+    /// between all the pairs of particles closer than `cutoff` with the
+    /// given `friction` coefficient.
+    pub fn new(
+        timestep: f64,
+        temperature: f64,
+        friction: f64,
+        cutoff: f64,
+    ) -> DissipativeParticleDynamics {
+        assert!(temperature >= 0.0, "The temperature must be positive in DPD propagator.");
+        assert!(friction >= 0.0, "The friction must be positive in DPD propagator.");
+        assert!(cutoff > 0.0, "The cutoff must be positive in DPD propagator.");
+        DissipativeParticleDynamics {
+            timestep: timestep,
+            temperature: temperature,
+            friction: friction,
+            cutoff: cutoff,
+            lambda: 0.5,
+            accelerations: Vec::new(),
+            dist: Normal::new(0.0, 1.0),
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+        }
+    }
+
+    /// Set the `lambda` parameter used to predict the velocities at the
+    /// next step. Groot and Warren found the best temperature control with
+    /// `lambda = 0.65` for their model.
+    pub fn set_lambda(&mut self, lambda: f64) {
+        assert!(
+            lambda >= 0.0 && lambda <= 1.0,
+            "The lambda parameter must be between 0 and 1 in DPD propagator."
+        );
+        self.lambda = lambda;
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Add the dissipative and random forces acting on the particles in the
+    /// `system` to `forces`, using the given `velocities` for the particles.
+    fn add_pair_forces(
+        &mut self,
+        system: &System,
+        velocities: &[Vector3D],
+        forces: &mut [Vector3D],
+    ) {
+        let kt = K_BOLTZMANN * self.temperature;
+        let sigma = f64::sqrt(2.0 * self.friction * kt / self.timestep);
+        let masses = system.particles().mass;
+
+        let cells = CellList::new(system, self.cutoff);
+        for i in 0..system.size() {
+            // Virtual sites do not take part in the dynamics
+            if masses[i] == 0.0 {
+                continue;
+            }
+            for j in cells.neighbors(i) {
+                if masses[j] == 0.0 {
+                    continue;
+                }
+
+                let rij = system.nearest_image(i, j);
+                let r = rij.norm();
+                if r >= self.cutoff || r == 0.0 {
+                    continue;
+                }
+                let e = rij / r;
+                let w = 1.0 - r / self.cutoff;
+                let vij = velocities[i] - velocities[j];
+                let theta = self.dist.sample(&mut self.rng);
+
+                let force = (sigma * w * theta - self.friction * w * w * (e * vij)) * e;
+                forces[i] += force;
+                forces[j] -= force;
+            }
+        }
+    }
+
+    /// Compute the accelerations of all the particles in the `system`, using
+    /// the given `velocities` for the dissipative forces.
+    fn update_accelerations(&mut self, system: &System, velocities: &[Vector3D]) {
+        let mut forces = system.forces();
+        self.add_pair_forces(system, velocities, &mut forces);
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = if mass == 0.0 || frozen { Vector3D::zero() } else { force / mass };
+        }
+    }
+}
+
+impl Propagator for DissipativeParticleDynamics {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        TemperatureStrategy::Velocities
+    }
+
+    fn degrees_of_freedom(&self, _: &System) -> DegreesOfFreedom {
+        DegreesOfFreedom::Particles
+    }
+
+    fn setup(&mut self, system: &System) {
+        check_no_constraints(system, "dissipative particle dynamics propagator");
+        self.accelerations = vec![Vector3D::zero(); system.size()];
+        let velocities = system.particles().velocity.to_vec();
+        self.update_accelerations(system, &velocities);
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+
+        // Update velocities at t + ∆t/2 and positions at t + ∆t
+        for (position, velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity], &self.accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+            *position += velocity * dt;
+        }
+        system.update_virtual_sites();
+
+        // Predict the velocities at t + ∆t, and use them to update the
+        // accelerations
+        let lambda = self.lambda;
+        let predicted = system.particles().velocity.iter()
+                                                   .zip(&self.accelerations)
+                                                   .map(|(&v, &a)| v + (lambda - 0.5) * dt * a)
+                                                   .collect::<Vec<_>>();
+        self.update_accelerations(system, &predicted);
+
+        // Update velocities at t + ∆t
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("accelerations", &self.accelerations);
+        checkpoint.save_rng("rng", &self.rng);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.accelerations = checkpoint.vectors("accelerations")?;
+        self.rng = checkpoint.rng("rng")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::energy::{PairInteraction, SoftRepulsive};
+    use core::{Constraint, Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    let position = Vector3D::new(i as f64, j as f64, k as f64) * 2.5;
+                    let particle = Particle::with_position("Ar", position);
+                    system.add_molecule(Molecule::new(particle));
+                }
+            }
+        }
+
+        let pair = PairInteraction::new(Box::new(SoftRepulsive::new(1.0, 3.0)), 3.0);
+        system.add_pair_potential(("Ar", "Ar"), pair);
+        return system;
+    }
+
+    fn momentum(system: &System) -> Vector3D {
+        let particles = system.particles();
+        let mut momentum = Vector3D::zero();
+        for (&mass, &velocity) in particles.mass.iter().zip(particles.velocity) {
+            momentum += mass * velocity;
+        }
+        return momentum;
+    }
+
+    #[test]
+    fn momentum_conservation() {
+        let mut system = testing_system();
+        let mut dpd = DissipativeParticleDynamics::new(1.0, 300.0, 0.5, 3.0);
+        dpd.set_lambda(0.65);
+        dpd.setup(&system);
+        for _ in 0..200 {
+            dpd.propagate(&mut system);
+        }
+
+        // The random forces heat up the system
+        assert!(system.kinetic_energy() > 0.0);
+        assert_relative_eq!(momentum(&system), Vector3D::zero(), epsilon = 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "The dissipative particle dynamics propagator can not enforce")]
+    fn constraints() {
+        let mut system = testing_system();
+        system.add_constraint(Constraint::Rigid { start: 0, end: 1 });
+        let mut dpd = DissipativeParticleDynamics::new(1.0, 300.0, 0.5, 3.0);
+        dpd.setup(&system);
+    }
+
+    #[test]
+    fn reproducible() {
+        let mut first = testing_system();
+        let mut second = testing_system();
+
+        let mut dpd = DissipativeParticleDynamics::new(1.0, 300.0, 0.5, 3.0);
+        dpd.seed(2018);
+        dpd.setup(&first);
+        for _ in 0..10 {
+            dpd.propagate(&mut first);
+        }
+
+        let mut dpd = DissipativeParticleDynamics::new(1.0, 300.0, 0.5, 3.0);
+        dpd.seed(2018);
+        dpd.setup(&second);
+        for _ in 0..10 {
+            dpd.propagate(&mut second);
+        }
+
+        assert_eq!(first.particles().position, second.particles().position);
+    }
+}
@@ -38,22 +38,33 @@ extern crate approx;
 
 extern crate caldyn;
 extern crate rand;
+extern crate rayon;
 extern crate num_traits as num;
 
 mod propagator;
 pub use self::propagator::Propagator;
 pub use self::propagator::TemperatureStrategy;
 
+mod checkpoint;
+pub use self::checkpoint::{Checkpoint, CheckpointError};
+
+mod rng;
+
 pub mod output;
 pub mod md;
 pub mod mc;
 pub mod min;
+pub mod dpd;
 
 mod simulations;
-pub use self::mc::MonteCarlo;
-pub use self::md::MolecularDynamics;
+pub use self::dpd::DissipativeParticleDynamics;
+pub use self::mc::{MonteCarlo, ParallelTempering, TransitionMatrix, WangLandau};
+pub use self::md::{MolecularDynamics, ReplicaExchange};
 pub use self::min::Minimization;
-pub use self::simulations::Simulation;
+pub use self::simulations::{Simulation, SinglePoint};
 
 mod velocities;
 pub use self::velocities::{InitVelocities, BoltzmannVelocities, UniformVelocities};
+
+mod normal_modes;
+pub use self::normal_modes::{hessian, NormalModes};
@@ -5,6 +5,22 @@
 mod monte_carlo;
 pub use self::monte_carlo::{MonteCarlo, MoveCounter};
 
+mod parallel_tempering;
+pub use self::parallel_tempering::ParallelTempering;
+
+mod wang_landau;
+pub use self::wang_landau::WangLandau;
+
+mod transition_matrix;
+pub use self::transition_matrix::TransitionMatrix;
+
 mod moves;
 pub use self::moves::{MCDegreeOfFreedom, MCMove};
-pub use self::moves::{Resize, Rotate, Translate};
+pub use self::moves::{AnisotropicResize, HybridMD, Resize, Rotate, Translate};
+pub use self::moves::{InsertMolecule, DeleteMolecule};
+pub use self::moves::{CavityInsertMolecule, CavityDeleteMolecule};
+pub(crate) use self::moves::MoleculeInserter;
+pub use self::moves::{PartialRegrow, Regrow, Torsion};
+pub use self::moves::Reptation;
+pub use self::moves::GeometricCluster;
+pub use self::moves::AggregationVolumeBias;
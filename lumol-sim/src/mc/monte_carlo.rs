@@ -2,12 +2,13 @@
 // Copyright (C) Lumol's contributors — BSD license
 
 //! Metropolis Monte Carlo propagator implementation
-use rand::{self, Rng, SeedableRng};
+use rand::{self, Rng, SeedableRng, XorShiftRng};
 
 use core::consts::K_BOLTZMANN;
 use core::{DegreesOfFreedom, EnergyCache, System};
 
 use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
 use super::{MCDegreeOfFreedom, MCMove};
 
 /// Metropolis Monte Carlo propagator
@@ -23,7 +24,7 @@ pub struct MonteCarlo {
     update_frequency: u64,
     /// Random number generator for the simulation. All random state will be
     /// taken from this.
-    rng: Box<rand::RngCore>,
+    rng: Box<rand::RngCore + Send>,
     /// Cache for faster energy computation
     cache: EnergyCache,
     /// Flag checking if the moves frequencies has been converted to
@@ -34,7 +35,7 @@ pub struct MonteCarlo {
 impl MonteCarlo {
     /// Create a new Monte Carlo propagator at temperature `T`.
     pub fn new(temperature: f64) -> MonteCarlo {
-        let rng = Box::new(rand::XorShiftRng::from_seed([
+        let rng = Box::new(XorShiftRng::from_seed([
             0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
             0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
         ]));
@@ -43,7 +44,7 @@ impl MonteCarlo {
 
     /// Create a Monte Carlo propagator at temperature `T`, using the `rng`
     /// random number generator.
-    pub fn from_rng(temperature: f64, rng: Box<rand::RngCore>) -> MonteCarlo {
+    pub fn from_rng(temperature: f64, rng: Box<rand::RngCore + Send>) -> MonteCarlo {
         assert!(temperature >= 0.0, "Monte Carlo temperature must be positive");
         MonteCarlo {
             beta: 1.0 / (K_BOLTZMANN * temperature),
@@ -114,6 +115,17 @@ impl MonteCarlo {
         self.beta = 1.0 / (temperature * K_BOLTZMANN);
     }
 
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Box::new(rng_from_seed(seed));
+    }
+
+    /// Initialize the energy cache with the `system`. This must be called
+    /// when the system was modified outside of this propagator.
+    pub(crate) fn init_cache(&mut self, system: &System) {
+        self.cache.init(system);
+    }
+
     fn normalize_frequencies(&mut self) {
         assert_eq!(self.frequencies.len(), self.moves.len());
         if self.frequencies.is_empty() {
@@ -168,11 +180,18 @@ impl Propagator for MonteCarlo {
                 let composition = system.composition();
                 for (hash, _) in composition.all_molecules() {
                     if !hashes.contains(&hash) {
-                        warn!(
-                            "the molecules with hash {:?} are not simulated by \
-                             this set of Monte Carlo moves",
-                            hash
-                        )
+                        match composition.name(hash) {
+                            Some(name) => warn!(
+                                "the '{}' molecules are not simulated by this \
+                                 set of Monte Carlo moves",
+                                name
+                            ),
+                            None => warn!(
+                                "the molecules with hash {:?} are not simulated by \
+                                 this set of Monte Carlo moves",
+                                hash
+                            ),
+                        }
                     }
                 }
                 DegreesOfFreedom::Molecules
@@ -0,0 +1,406 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+
+use std::collections::BTreeSet;
+use std::f64;
+use std::f64::consts::PI;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::{is_frozen, select_molecule};
+
+use core::{EnergyCache, System, MoleculeHash, Vector3D};
+
+/// Aggregation-volume-bias Monte Carlo move (AVBMC), moving molecules
+/// directly in and out of the bonded region of another molecule [1].
+///
+/// The bonded region of a target molecule is the spherical shell between the
+/// `inner` and `outer` radii around its center-of-mass, with volume $V_{in}$.
+/// The rest of the cell, with volume $V_{out} = V - V_{in}$, is the non-bonded
+/// region. A random target molecule is selected, and with probability $p$
+/// a random molecule outside of the bonded region of the target is
+/// translated to a random position inside it. Otherwise, a random molecule
+/// inside the bonded region is translated to a random position outside of
+/// it. Moves into the bonded region are accepted with the probability
+///
+/// $$ P_{acc} = \min[1, \frac{(1-p) V_{in} N_{out}}{p V_{out} (N_{in}+1)} e^{-\beta\Delta U}] $$
+///
+/// and moves out of the bonded region with the probability
+///
+/// $$ P_{acc} = \min[1, \frac{p V_{out} N_{in}}{(1-p) V_{in} (N_{out}+1)} e^{-\beta\Delta U}] $$
+///
+/// where $N_{in}$ and $N_{out}$ are the number of molecules inside and
+/// outside of the bonded region of the target before the move. This makes
+/// the formation and breaking of clusters much faster than with small random
+/// displacements in associating fluids, where the bonded region is small
+/// compared to the cell volume. The orientation of the moved molecule does
+/// not change.
+///
+/// [1] B. Chen and J. I. Siepmann, J. Phys. Chem. B 104, 8725 (2000).
+pub struct AggregationVolumeBias {
+    /// Hash of the molecules to move. `None` means all molecules.
+    hash: Option<MoleculeHash>,
+    /// Inner radius of the bonded region
+    inner: f64,
+    /// Outer radius of the bonded region
+    outer: f64,
+    /// Probability to move a molecule into the bonded region
+    inward: f64,
+    /// Index of the molecule to move
+    molid: usize,
+    /// New positions of the particles in the moved molecule
+    newpos: Vec<Vector3D>,
+    /// Logarithm of the ratio of the probabilities of proposing the reverse
+    /// and the forward move
+    ln_ratio: f64,
+}
+
+impl AggregationVolumeBias {
+    /// Create a new `AggregationVolumeBias` move, using a bonded region
+    /// between the `inner` and `outer` radii. This move will apply to the
+    /// molecules with the given `hash`, or all molecules if `hash` is
+    /// `None`. Moves into and out of the bonded region are attempted with the
+    /// same probability.
+    pub fn new<H: Into<Option<MoleculeHash>>>(
+        inner: f64,
+        outer: f64,
+        hash: H,
+    ) -> AggregationVolumeBias {
+        assert!(inner >= 0.0, "inner radius must be positive in AggregationVolumeBias move");
+        assert!(
+            outer > inner,
+            "outer radius must be larger than inner radius in AggregationVolumeBias move"
+        );
+        AggregationVolumeBias {
+            hash: hash.into(),
+            inner: inner,
+            outer: outer,
+            inward: 0.5,
+            molid: usize::MAX,
+            newpos: Vec::new(),
+            ln_ratio: 0.0,
+        }
+    }
+
+    /// Set the probability to attempt a move into the bonded region of the
+    /// target molecule. The default value is 0.5.
+    pub fn set_inward_probability(&mut self, probability: f64) {
+        assert!(
+            0.0 < probability && probability < 1.0,
+            "inward probability must be between 0 and 1 in AggregationVolumeBias move"
+        );
+        self.inward = probability;
+    }
+
+    /// Get the volume of the bonded region
+    fn bonded_volume(&self) -> f64 {
+        4.0 / 3.0 * PI * (self.outer.powi(3) - self.inner.powi(3))
+    }
+
+    /// Check if a molecule at distance `r` of the target is in its bonded
+    /// region
+    fn is_bonded(&self, r: f64) -> bool {
+        self.inner < r && r < self.outer
+    }
+
+    /// Check that the bonded region fits inside the cell of the `system`
+    fn fits_in_cell(&self, system: &System) -> bool {
+        let lengths = system.cell.lengths();
+        let min = f64::min(lengths[0], f64::min(lengths[1], lengths[2]));
+        return 2.0 * self.outer < min;
+    }
+}
+
+/// Get a random vector uniformly distributed in the spherical shell between
+/// the `inner` and `outer` radii.
+fn random_in_shell(inner: f64, outer: f64, rng: &mut RngCore) -> Vector3D {
+    let inner3 = inner * inner * inner;
+    let outer3 = outer * outer * outer;
+    let r = f64::cbrt(inner3 + rng.gen::<f64>() * (outer3 - inner3));
+
+    let z = rng.gen_range(-1.0, 1.0);
+    let phi = rng.gen_range(0.0, 2.0 * PI);
+    let sin_theta = f64::sqrt(1.0 - z * z);
+    return r * Vector3D::new(sin_theta * f64::cos(phi), sin_theta * f64::sin(phi), z);
+}
+
+impl MCMove for AggregationVolumeBias {
+    fn describe(&self) -> &str {
+        "aggregation-volume-bias translation"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        match self.hash {
+            Some(hash) => {
+                let mut all = BTreeSet::new();
+                let _ = all.insert(hash);
+                MCDegreeOfFreedom::Molecules(all)
+            }
+            None => MCDegreeOfFreedom::AllMolecules,
+        }
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(
+            system.cell.is_fully_periodic() && !system.cell.is_infinite(),
+            "Can not use AggregationVolumeBias move without a fully periodic cell."
+        );
+        assert!(
+            self.fits_in_cell(system),
+            "The outer radius must be smaller than half of the cell in AggregationVolumeBias move."
+        );
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.dimensions() == 2 {
+            warn_once!("Can not use aggregation-volume-bias moves in two-dimensional systems.");
+            return false;
+        }
+
+        if !self.fits_in_cell(system) {
+            warn_once!(
+                "The cell is too small for the bonded region of AggregationVolumeBias move."
+            );
+            return false;
+        }
+
+        let target = if let Some(id) = select_molecule(system, self.hash, rng) {
+            id
+        } else {
+            warn!("Can not perform AVBMC move: no molecule of this type in the system.");
+            return false;
+        };
+
+        // Sort the other molecules depending on their position relative to
+        // the target
+        let center = system.molecule(target).center_of_mass();
+        let mut inside = Vec::new();
+        let mut outside = Vec::new();
+        for (i, molecule) in system.molecules().enumerate() {
+            if i == target || is_frozen(&molecule) {
+                continue;
+            }
+            if let Some(hash) = self.hash {
+                if molecule.hash() != hash {
+                    continue;
+                }
+            }
+
+            let r = system.cell.distance(&center, &molecule.center_of_mass());
+            if self.is_bonded(r) {
+                inside.push(i);
+            } else {
+                outside.push(i);
+            }
+        }
+
+        let bonded_volume = self.bonded_volume();
+        let other_volume = system.volume() - bonded_volume;
+        let (n_in, n_out) = (inside.len() as f64, outside.len() as f64);
+        let new_center = if rng.gen::<f64>() < self.inward {
+            self.molid = match rng.choose(&outside) {
+                Some(&molid) => molid,
+                None => return false,
+            };
+            let ratio = (1.0 - self.inward) * bonded_volume * n_out;
+            let ratio = ratio / (self.inward * other_volume * (n_in + 1.0));
+            self.ln_ratio = f64::ln(ratio);
+            center + random_in_shell(self.inner, self.outer, rng)
+        } else {
+            self.molid = match rng.choose(&inside) {
+                Some(&molid) => molid,
+                None => return false,
+            };
+            let ratio = self.inward * other_volume * n_in;
+            let ratio = ratio / ((1.0 - self.inward) * bonded_volume * (n_out + 1.0));
+            self.ln_ratio = f64::ln(ratio);
+            // Uniform position in the cell, outside of the bonded region
+            loop {
+                let fractional = Vector3D::new(rng.gen(), rng.gen(), rng.gen());
+                let position = system.cell.cartesian(&fractional);
+                if !self.is_bonded(system.cell.distance(&center, &position)) {
+                    break position;
+                }
+            }
+        };
+
+        let molecule = system.molecule(self.molid);
+        let delta = new_center - molecule.center_of_mass();
+        self.newpos = molecule.particles().position.to_vec();
+        for newpos in &mut self.newpos {
+            *newpos += delta;
+        }
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        let delta = cache.move_molecule_cost(system, self.molid, &self.newpos);
+        return beta * delta - self.ln_ratio;
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let cell = system.cell;
+        let mut molecule = system.molecule_mut(self.molid);
+        for (position, newpos) in soa_zip!(molecule.particles_mut(), [mut position], &self.newpos) {
+            *position = *newpos;
+        }
+        // Move molecule such that its center-of-mass is inside the simulation
+        // cell.
+        molecule.wrap(&cell)
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do.
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{LennardJones, PairInteraction};
+    use core::{Molecule, Particle, UnitCell};
+    use core::units;
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x71, 0xa4, 0x2e, 0xd9, 0x0b, 0x5c, 0xf3, 0x86,
+            0x3a, 0xe7, 0x14, 0x9f, 0x68, 0xc1, 0x25, 0xbd,
+        ])
+    }
+
+    #[test]
+    fn shell() {
+        let mut rng = rng();
+        for _ in 0..1000 {
+            let r = random_in_shell(2.0, 3.0, &mut rng).norm();
+            assert!(2.0 <= r && r <= 3.0);
+        }
+    }
+
+    fn testing_system(distance: f64) -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        let position = Vector3D::new(0.0, 0.0, distance);
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", position)));
+
+        let lj = LennardJones {
+            sigma: units::from(3.4, "A").unwrap(),
+            epsilon: units::from(1.0, "kJ/mol").unwrap(),
+        };
+        system.add_pair_potential(("Ar", "Ar"), PairInteraction::new(Box::new(lj), 8.0));
+        return system;
+    }
+
+    /// Check the cost of all the possible moves in a system with two
+    /// molecules, and return the logarithm of the proposal probabilities
+    /// ratio.
+    fn check_cost(system: &mut System, avbmc: &mut AggregationVolumeBias) -> f64 {
+        let mut cache = EnergyCache::new();
+        cache.init(system);
+        avbmc.setup(system);
+
+        let mut rng = rng();
+        let mut ln_ratio = None;
+        for _ in 0..20 {
+            if !avbmc.prepare(system, &mut rng) {
+                continue;
+            }
+            ln_ratio = Some(avbmc.ln_ratio);
+
+            let old_energy = system.potential_energy();
+            let old = system.particles().position.to_vec();
+            let cost = avbmc.cost(system, 1.0, &mut cache);
+            avbmc.apply(system);
+            let delta = system.potential_energy() - old_energy;
+            assert_relative_eq!(cost, delta - avbmc.ln_ratio, epsilon = 1e-9);
+            system.particles_mut().position.copy_from_slice(&old);
+        }
+        return ln_ratio.expect("no move was performed");
+    }
+
+    #[test]
+    fn cost() {
+        let bonded_volume = 4.0 / 3.0 * PI * (125.0 - 27.0);
+        let other_volume = 8000.0 - bonded_volume;
+
+        // The two molecules are bonded, only moves out of the bonded region
+        // are possible
+        let mut system = testing_system(4.0);
+        let mut avbmc = AggregationVolumeBias::new(3.0, 5.0, None);
+        avbmc.set_inward_probability(0.7);
+        let ln_ratio = check_cost(&mut system, &mut avbmc);
+        let expected = f64::ln(0.7 * other_volume / (0.3 * bonded_volume));
+        assert_relative_eq!(ln_ratio, expected, epsilon = 1e-12);
+        let distance = system.cell.distance(&avbmc.newpos[0], &Vector3D::zero());
+        let other = Vector3D::new(0.0, 0.0, 4.0);
+        let other_distance = system.cell.distance(&avbmc.newpos[0], &other);
+        assert!(!avbmc.is_bonded(distance) || !avbmc.is_bonded(other_distance));
+
+        // The two molecules are not bonded, only moves into the bonded region
+        // are possible
+        let mut system = testing_system(10.0);
+        let mut avbmc = AggregationVolumeBias::new(3.0, 5.0, None);
+        avbmc.set_inward_probability(0.7);
+        let ln_ratio = check_cost(&mut system, &mut avbmc);
+        let expected = f64::ln(0.3 * bonded_volume / (0.7 * other_volume));
+        assert_relative_eq!(ln_ratio, expected, epsilon = 1e-12);
+        let distance = system.cell.distance(&avbmc.newpos[0], &Vector3D::zero());
+        let other = Vector3D::new(0.0, 0.0, 10.0);
+        let other_distance = system.cell.distance(&avbmc.newpos[0], &other);
+        assert!(avbmc.is_bonded(distance) || avbmc.is_bonded(other_distance));
+    }
+
+    #[test]
+    fn ideal_gas() {
+        // Without interactions, the fraction of configurations where two
+        // molecules are bonded is the fraction of volume of the bonded region
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [5.0, 5.0, 5.0].into())));
+
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let mut avbmc = AggregationVolumeBias::new(1.0, 4.0, None);
+        avbmc.set_inward_probability(0.7);
+        avbmc.setup(&system);
+
+        let mut rng = rng();
+        let steps = 20_000;
+        let mut bonded = 0;
+        for _ in 0..steps {
+            if avbmc.prepare(&mut system, &mut rng) {
+                let cost = avbmc.cost(&system, 1.0, &mut cache);
+                if cost <= 0.0 || rng.gen::<f64>() < f64::exp(-cost) {
+                    avbmc.apply(&mut system);
+                    cache.update(&system);
+                } else {
+                    avbmc.restore(&mut system);
+                }
+            }
+
+            let positions = system.particles().position;
+            if avbmc.is_bonded(system.cell.distance(&positions[0], &positions[1])) {
+                bonded += 1;
+            }
+        }
+
+        let expected = avbmc.bonded_volume() / system.volume();
+        assert_relative_eq!(bonded as f64 / steps as f64, expected, epsilon = 0.03);
+    }
+
+    #[test]
+    #[should_panic(expected = "The outer radius must be smaller than half of the cell")]
+    fn too_small_cell() {
+        let system = System::with_cell(UnitCell::cubic(8.0));
+        let mut avbmc = AggregationVolumeBias::new(1.0, 4.0, None);
+        avbmc.setup(&system);
+    }
+}
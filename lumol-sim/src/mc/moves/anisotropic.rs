@@ -0,0 +1,310 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+use rand::distributions::{Range, Distribution};
+
+use std::f64;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::scale_molecules;
+
+use core::{Configuration, EnergyCache, System, Matrix3, Snapshot, UnitCell};
+
+/// Monte Carlo move that changes the shape of the simulation cell, by
+/// changing a single cell length or a single cell angle at the time.
+///
+/// Contrary to the isotropic [`Resize`](struct.Resize.html) move, the cell
+/// lengths (and optionally the cell angles) fluctuate independently of each
+/// other at constant external pressure. This allows the simulation cell to
+/// adapt to the structure of solids, and to compute the elastic response of
+/// the system from the fluctuations of the cell. The cell parameters are
+/// sampled uniformly, and the move is accepted with the same criterion as
+/// the `Resize` move.
+///
+/// The centers of mass of the molecules are moved with the cell, keeping the
+/// same fractional coordinates. Changing a cell angle transforms the cell to
+/// a triclinic cell. In two-dimensional systems, only the first two cell
+/// lengths and the `gamma` angle are changed.
+pub struct AnisotropicResize {
+    /// Target pressure
+    pressure: f64,
+    /// Maximal change of a cell length
+    delta: f64,
+    /// Sampling range for cell lengths changes
+    range: Range<f64>,
+    /// Maximal change of a cell angle, `None` if the angles are fixed
+    angle_delta: Option<f64>,
+    /// Configuration before applying changes to the simulation cell
+    previous: Snapshot,
+    /// Largest cutoff diameter of potentials in `Interactions`
+    maximum_cutoff: Option<f64>,
+}
+
+impl AnisotropicResize {
+    /// Create a new `AnisotropicResize` move, with target pressure `pressure`
+    /// and maximal change of a cell length of `delta`. The cell angles are
+    /// kept fixed.
+    pub fn new(pressure: f64, delta: f64) -> AnisotropicResize {
+        assert!(delta > 0.0, "delta must be positive in AnisotropicResize move");
+        AnisotropicResize {
+            pressure: pressure,
+            delta: delta,
+            range: Range::new(-delta, delta),
+            angle_delta: None,
+            previous: Configuration::new().snapshot(),
+            maximum_cutoff: None,
+        }
+    }
+
+    /// Also change the cell angles in this move, with a maximal change of
+    /// `delta` (in radians) for a single angle.
+    ///
+    /// # Panics
+    ///
+    /// If `delta` is not positive.
+    pub fn set_angle_delta(&mut self, delta: f64) {
+        assert!(delta > 0.0, "angle delta must be positive in AnisotropicResize move");
+        self.angle_delta = Some(delta);
+    }
+
+    /// Get the new unit cell after changing the cell length with index
+    /// `length` in `cell` by `delta`, or `None` if the new length is not
+    /// positive.
+    fn change_length(cell: &UnitCell, length: usize, delta: f64) -> Option<UnitCell> {
+        let old = [cell.a(), cell.b(), cell.c()][length];
+        if old + delta <= 0.0 {
+            return None;
+        }
+        let mut scaling = Matrix3::one();
+        scaling[length][length] = (old + delta) / old;
+        let mut cell = *cell;
+        cell.scale_mut(scaling);
+        return Some(cell);
+    }
+
+    /// Get the new unit cell after changing the cell angle with index
+    /// `angle` in `cell` by `delta`, or `None` if the new cell is not valid.
+    fn change_angle(cell: &UnitCell, angle: usize, delta: f64) -> Option<UnitCell> {
+        let mut angles = [cell.alpha(), cell.beta(), cell.gamma()];
+        angles[angle] += delta.to_degrees();
+        if angles[angle] <= 0.0 || angles[angle] >= 180.0 {
+            return None;
+        }
+
+        // Check that the three angles can be used to build a cell, i.e. that
+        // the cell volume is positive.
+        let cos = [
+            angles[0].to_radians().cos(),
+            angles[1].to_radians().cos(),
+            angles[2].to_radians().cos(),
+        ];
+        let volume2 = 1.0 - cos[0] * cos[0] - cos[1] * cos[1] - cos[2] * cos[2]
+            + 2.0 * cos[0] * cos[1] * cos[2];
+        if volume2 <= 0.0 {
+            return None;
+        }
+
+        let mut new = UnitCell::triclinic(
+            cell.a(), cell.b(), cell.c(), angles[0], angles[1], angles[2]
+        );
+        new.set_periodicity(cell.periodicity());
+        return Some(new);
+    }
+}
+
+impl MCMove for AnisotropicResize {
+    fn describe(&self) -> &str {
+        "anisotropic resizing of the cell"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::AllMolecules
+    }
+
+    fn setup(&mut self, system: &System) {
+        if system.cell.is_infinite() {
+            panic!("Cannot use `AnisotropicResize` move with infinite simulation cell.")
+        }
+        self.maximum_cutoff = system.maximum_cutoff();
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        // Select the cell parameter to change, the lengths first and then
+        // the angles. Only the first two lengths and the last angle are
+        // changed in two-dimensional systems.
+        let dimensions = system.dimensions();
+        let parameters = if self.angle_delta.is_some() {
+            if dimensions == 2 { 3 } else { 6 }
+        } else {
+            dimensions
+        };
+        let parameter = rng.gen_range(0, parameters);
+
+        let new_cell = if parameter < dimensions {
+            let delta = self.range.sample(rng);
+            AnisotropicResize::change_length(&system.cell, parameter, delta)
+        } else {
+            let angle = if dimensions == 2 { 2 } else { parameter - 3 };
+            let angle_delta = self.angle_delta.expect("missing angle delta");
+            let delta = rng.gen_range(-angle_delta, angle_delta);
+            AnisotropicResize::change_angle(&system.cell, angle, delta)
+        };
+
+        let new_cell = match new_cell {
+            Some(cell) => cell,
+            None => return false,
+        };
+
+        if let Some(maximum_cutoff) = self.maximum_cutoff {
+            let lengths = new_cell.lengths();
+            if lengths.iter().take(dimensions).any(|&d| 0.5 * d <= maximum_cutoff) {
+                panic!(
+                    "Tried to decrease the cell size in Monte Carlo AnisotropicResize \
+                     move but the new size is smaller than the interactions cut off \
+                     radius. You can try to increase the cell size or the number \
+                     of particles."
+                );
+            }
+        }
+
+        self.previous = system.snapshot();
+        system.cell = new_cell;
+        scale_molecules(system, self.previous.cell());
+        true
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        let delta_energy = cache.move_all_molecules_cost(system);
+        let new_volume = system.volume();
+        let old_volume = if system.dimensions() == 2 {
+            self.previous.cell().area()
+        } else {
+            self.previous.cell().volume()
+        };
+        let delta_volume = new_volume - old_volume;
+        beta * (delta_energy + self.pressure * delta_volume)
+            - (system.molecules().count() as f64) * f64::ln(new_volume / old_volume)
+    }
+
+    fn apply(&mut self, _: &mut System) {
+        // Nothing to do.
+    }
+
+    fn restore(&mut self, system: &mut System) {
+        system.restore(&self.previous);
+    }
+
+    fn update_amplitude(&mut self, scaling_factor: Option<f64>) {
+        if let Some(s) = scaling_factor {
+            self.delta *= s;
+            self.range = Range::new(-self.delta, self.delta);
+            if let Some(ref mut angle_delta) = self.angle_delta {
+                if (s * *angle_delta).to_degrees() <= 90.0 {
+                    *angle_delta *= s;
+                } else {
+                    warn_once!(
+                        "Tried to increase the maximum amplitude for cell angles to more than 90°."
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, Particle};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::ortho(20.0, 22.0, 24.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [1.0, 1.0, 1.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [1.0, 1.0, 3.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 0.1 }), 5.0);
+        system.add_pair_potential(("Ar", "Ar"), pair);
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x5e, 0x21, 0xc7, 0x90, 0x3a, 0x4f, 0xd1, 0x68,
+            0x0b, 0x7c, 0xe2, 0x15, 0x96, 0x33, 0xaf, 0x48,
+        ])
+    }
+
+    #[test]
+    fn lengths() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+        let old_lengths = system.cell.lengths();
+
+        let mut resize = AnisotropicResize::new(1e-3, 1.0);
+        resize.setup(&system);
+        let mut rng = rng();
+        for _ in 0..10 {
+            assert!(resize.prepare(&mut system, &mut rng));
+
+            // A single length changed, and the cell is still orthorhombic
+            let lengths = system.cell.lengths();
+            let changed = (0..3).filter(|&i| lengths[i] != old_lengths[i]).count();
+            assert_eq!(changed, 1);
+            assert_eq!(system.cell.gamma(), 90.0);
+
+            let delta_energy = system.potential_energy() - old_energy;
+            let new_volume = system.volume();
+            let old_volume = 20.0 * 22.0 * 24.0;
+            let expected = 3.0 * (delta_energy + 1e-3 * (new_volume - old_volume))
+                - 2.0 * f64::ln(new_volume / old_volume);
+            let cost = resize.cost(&system, 3.0, &mut cache);
+            assert_relative_eq!(cost, expected, max_relative = 1e-9);
+
+            resize.restore(&mut system);
+            assert_eq!(system.cell.lengths(), old_lengths);
+        }
+    }
+
+    #[test]
+    fn angles() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+
+        let mut resize = AnisotropicResize::new(1e-3, 1e-6);
+        resize.set_angle_delta(f64::to_radians(5.0));
+        resize.setup(&system);
+
+        let mut rng = rng();
+        let mut changed = false;
+        for _ in 0..20 {
+            assert!(resize.prepare(&mut system, &mut rng));
+            let angles = [system.cell.alpha(), system.cell.beta(), system.cell.gamma()];
+            if angles.iter().any(|&angle| f64::abs(angle - 90.0) > 1e-9) {
+                changed = true;
+                let delta_energy = system.potential_energy() - old_energy;
+                let _ = resize.cost(&system, 3.0, &mut cache);
+                cache.update(&mut system);
+                assert_relative_eq!(
+                    cache.energy() - old_energy, delta_energy, epsilon = 1e-9
+                );
+                break;
+            }
+            resize.restore(&mut system);
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    #[should_panic(expected = "angle delta must be positive in AnisotropicResize move")]
+    fn negative_angle_delta() {
+        let mut resize = AnisotropicResize::new(1e-3, 1.0);
+        resize.set_angle_delta(-1.0);
+    }
+}
@@ -0,0 +1,292 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+
+use std::f64;
+use std::ops::Range;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::{is_frozen, select_molecule};
+
+use core::consts::K_BOLTZMANN;
+use core::{EnergyCache, EnergyEvaluator, System, Vector3D};
+
+/// Geometric cluster Monte Carlo move, reflecting a cluster of molecules
+/// through a random pivot point [1, 2].
+///
+/// A random pivot point is chosen in the simulation cell, and a random seed
+/// molecule is reflected through this pivot. Each molecule interacting with
+/// a reflected molecule is then added to the cluster and reflected in turn,
+/// with a probability `1 - exp(-β ΔE)`, where `ΔE` is the pair energy
+/// change between the two molecules when only the first one is reflected.
+/// The cluster grows until no more molecules are added. Entire groups of
+/// neighboring molecules move together over large distances, which makes
+/// this move efficient for dense colloidal or ionic systems where single
+/// molecule displacements are almost always rejected.
+///
+/// The pair interactions are fully accounted for when building the cluster,
+/// and the move is then accepted with a Metropolis criterion on the change
+/// of the coulombic and global energies. Without coulombic and global
+/// potentials, the move is always accepted. The temperature of the move must
+/// be the same as the temperature of the Monte Carlo propagator.
+///
+/// The point reflection inverts the molecules, which is only correct if
+/// their energy does not depend on the chirality. The move is rejected if a
+/// molecule containing frozen particles would join the cluster.
+///
+/// [1] C. Dress and W. Krauth, J. Phys. A: Math. Gen. 28, L597 (1995).
+///
+/// [2] J. Liu and E. Luijten, Phys. Rev. Lett. 92, 035504 (2004).
+pub struct GeometricCluster {
+    /// Inverse temperature of the move
+    beta: f64,
+    /// Positions of all the particles before the move
+    previous: Vec<Vector3D>,
+    /// Change in the pair energy between the cluster and the other
+    /// molecules
+    pairs_delta: f64,
+}
+
+impl GeometricCluster {
+    /// Create a new `GeometricCluster` move at the given `temperature`.
+    pub fn new(temperature: f64) -> GeometricCluster {
+        assert!(temperature > 0.0, "temperature must be positive in GeometricCluster move");
+        GeometricCluster {
+            beta: 1.0 / (K_BOLTZMANN * temperature),
+            previous: Vec::new(),
+            pairs_delta: 0.0,
+        }
+    }
+}
+
+/// Reflect the `position` through the `pivot` point
+fn reflect(pivot: Vector3D, position: Vector3D) -> Vector3D {
+    2.0 * pivot - position
+}
+
+/// Compute the change in the pair energy between the particles in `first`
+/// and the particles in `second`, when the particles in `first` move from
+/// `old` to `new` positions and the particles in `second` stay at their
+/// `old` positions.
+fn pairs_delta(
+    system: &System,
+    evaluator: &EnergyEvaluator,
+    (old, new): (&[Vector3D], &[Vector3D]),
+    first: Range<usize>,
+    second: Range<usize>,
+) -> f64 {
+    let mut delta = 0.0;
+    for i in first {
+        for j in second.clone() {
+            let path = system.bond_path(i, j);
+            let old_r = system.cell.distance(&old[i], &old[j]);
+            let new_r = system.cell.distance(&new[i], &old[j]);
+            delta += evaluator.pair(path, new_r, i, j) - evaluator.pair(path, old_r, i, j);
+        }
+    }
+    return delta;
+}
+
+impl MCMove for GeometricCluster {
+    fn describe(&self) -> &str {
+        "geometric cluster"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::AllMolecules
+    }
+
+    fn setup(&mut self, system: &System) {
+        if system.cell.is_infinite() {
+            panic!("Cannot use `GeometricCluster` move with infinite simulation cell.")
+        }
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        let seed = if let Some(id) = select_molecule(system, None, rng) {
+            id
+        } else {
+            warn!("Can not perform geometric cluster move: no molecule in the system.");
+            return false;
+        };
+
+        // Random pivot in the cell, staying in the xy plane for
+        // two-dimensional systems.
+        let z = if system.dimensions() == 2 { 0.0 } else { rng.gen() };
+        let pivot = system.cell.cartesian(&Vector3D::new(rng.gen(), rng.gen(), z));
+
+        let old = system.particles().position.to_vec();
+        let mut new = old.clone();
+        let mut in_cluster = vec![false; system.molecules().count()];
+        {
+            let molecules = system.molecules().map(|m| m.indexes()).collect::<Vec<_>>();
+            let evaluator = system.energy_evaluator();
+
+            in_cluster[seed] = true;
+            for i in molecules[seed].clone() {
+                new[i] = reflect(pivot, old[i]);
+            }
+
+            let mut stack = vec![seed];
+            while let Some(current) = stack.pop() {
+                for (other, indexes) in molecules.iter().enumerate() {
+                    if in_cluster[other] {
+                        continue;
+                    }
+
+                    let delta = pairs_delta(
+                        system,
+                        &evaluator,
+                        (&old, &new),
+                        molecules[current].clone(),
+                        indexes.clone(),
+                    );
+                    if delta > 0.0 && rng.gen::<f64>() < 1.0 - f64::exp(-self.beta * delta) {
+                        if is_frozen(&system.molecule(other)) {
+                            trace!("    --> frozen molecule in the cluster");
+                            return false;
+                        }
+                        in_cluster[other] = true;
+                        for i in indexes.clone() {
+                            new[i] = reflect(pivot, old[i]);
+                        }
+                        stack.push(other);
+                    }
+                }
+            }
+
+            // Pair energy change between the final cluster and the other
+            // molecules
+            self.pairs_delta = 0.0;
+            for (_, indexes) in molecules.iter().enumerate().filter(|&(i, _)| in_cluster[i]) {
+                for (_, others) in molecules.iter().enumerate().filter(|&(i, _)| !in_cluster[i]) {
+                    self.pairs_delta += pairs_delta(
+                        system, &evaluator, (&old, &new), indexes.clone(), others.clone()
+                    );
+                }
+            }
+        }
+
+        system.particles_mut().position.copy_from_slice(&new);
+        let cell = system.cell;
+        for (molid, _) in in_cluster.iter().enumerate().filter(|&(_, &inside)| inside) {
+            // Move the molecules such that their center-of-mass is inside the
+            // simulation cell.
+            system.molecule_mut(molid).wrap(&cell);
+        }
+        self.previous = old;
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        // The pair energy change is already accounted for when building the
+        // cluster, only keep the coulombic and global energy change.
+        let delta = cache.move_all_molecules_cost(system);
+        return beta * (delta - self.pairs_delta);
+    }
+
+    fn apply(&mut self, _: &mut System) {
+        // Nothing to do.
+    }
+
+    fn restore(&mut self, system: &mut System) {
+        system.particles_mut().position.copy_from_slice(&self.previous);
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn temperature(&self) -> Option<f64> {
+        Some(1.0 / (K_BOLTZMANN * self.beta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{LennardJones, PairInteraction, Wolf};
+    use core::{Molecule, Particle, UnitCell};
+    use core::units;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(12.0));
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    let position = Vector3D::new(i as f64, j as f64, k as f64) * 3.5;
+                    let mut particle = Particle::with_position("Na", position);
+                    particle.charge = if (i + j + k) % 2 == 0 { 1.0 } else { -1.0 };
+                    system.add_molecule(Molecule::new(particle));
+                }
+            }
+        }
+
+        let lj = PairInteraction::new(Box::new(LennardJones {
+            sigma: units::from(3.0, "A").unwrap(),
+            epsilon: units::from(2.0, "kJ/mol").unwrap(),
+        }), 5.0);
+        system.add_pair_potential(("Na", "Na"), lj);
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x3d, 0x92, 0x0f, 0xe4, 0x57, 0xa1, 0x6c, 0x28,
+            0xbb, 0x13, 0x70, 0xc5, 0x49, 0xde, 0x86, 0x0a,
+        ])
+    }
+
+    #[test]
+    fn pairs() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let temperature = units::from(300.0, "K").unwrap();
+        let mut cluster = GeometricCluster::new(temperature);
+        cluster.setup(&system);
+        let mut rng = rng();
+        for _ in 0..10 {
+            let old_energy = system.potential_energy();
+            assert!(cluster.prepare(&mut system, &mut rng));
+            let new_energy = system.potential_energy();
+            assert_relative_eq!(cluster.pairs_delta, new_energy - old_energy, epsilon = 1e-9);
+
+            // Only pair interactions, the move is always accepted
+            let cost = cluster.cost(&system, 1.0, &mut cache);
+            assert_relative_eq!(cost, 0.0, epsilon = 1e-9);
+            cluster.apply(&mut system);
+            cache.update(&mut system);
+            assert_relative_eq!(cache.energy(), new_energy, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn coulomb() {
+        let mut system = testing_system();
+        system.set_coulomb_potential(Box::new(Wolf::new(5.0)));
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let temperature = units::from(300.0, "K").unwrap();
+        let mut cluster = GeometricCluster::new(temperature);
+        cluster.setup(&system);
+        let mut rng = rng();
+        for _ in 0..10 {
+            let old_positions = system.particles().position.to_vec();
+            let old_coulomb = system.energy_evaluator().coulomb();
+            assert!(cluster.prepare(&mut system, &mut rng));
+            let new_coulomb = system.energy_evaluator().coulomb();
+
+            let cost = cluster.cost(&system, 3.0, &mut cache);
+            assert_relative_eq!(cost, 3.0 * (new_coulomb - old_coulomb), epsilon = 1e-9);
+
+            cluster.restore(&mut system);
+            assert_eq!(system.particles().position, &old_positions[..]);
+        }
+    }
+}
@@ -0,0 +1,834 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+use rand::{Rng, RngCore};
+use rand::distributions::{Normal, Distribution};
+
+use std::collections::BTreeSet;
+use std::f64::consts::PI;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::{is_frozen, select_molecule};
+
+use core::consts::PLANCK;
+use core::{CellList, Configuration};
+use core::{EnergyCache, System, Molecule, MoleculeHash, MoleculeTemplate};
+use core::{Matrix3, Quaternion, Vector3D};
+
+/// Get the cube of the thermal de Broglie wavelength of a molecule with the
+/// given `mass`, at the inverse temperature `beta`.
+fn thermal_volume(mass: f64, beta: f64) -> f64 {
+    let wavelength = PLANCK * f64::sqrt(beta / (2.0 * PI * mass));
+    return wavelength * wavelength * wavelength;
+}
+
+/// Count the molecules with the given `hash` which can be removed from the
+/// `system`, i.e. which do not contain frozen particles.
+fn count_molecules(system: &System, hash: MoleculeHash) -> usize {
+    system.molecules().filter(|m| m.hash() == hash && !is_frozen(m)).count()
+}
+
+fn molecule_dof(hash: MoleculeHash) -> MCDegreeOfFreedom {
+    let mut all = BTreeSet::new();
+    let _ = all.insert(hash);
+    MCDegreeOfFreedom::Molecules(all)
+}
+
+/// Creation of copies of a molecule at random positions and orientations in
+/// the system, used to insert new molecules in the system.
+pub(crate) struct MoleculeInserter {
+    /// Molecule to insert, with the center-of-mass at the origin
+    molecule: Molecule,
+    /// Normal distribution, for generation of the orientation
+    normal: Normal,
+}
+
+impl MoleculeInserter {
+    /// Create a new `MoleculeInserter` for the molecule described by
+    /// `template`.
+    pub(crate) fn new(template: &MoleculeTemplate) -> MoleculeInserter {
+        let mut molecule = template.molecule();
+        let com = molecule.as_ref().center_of_mass();
+        for position in molecule.particles_mut().position {
+            *position -= com;
+        }
+        MoleculeInserter {
+            molecule: molecule,
+            normal: Normal::new(0.0, 1.0),
+        }
+    }
+
+    /// Get the hash of the inserted molecules
+    pub(crate) fn hash(&self) -> MoleculeHash {
+        self.molecule.as_ref().hash()
+    }
+
+    /// Get a new copy of the molecule, with the center-of-mass at a random
+    /// position in the unit cell of the `system` and a random orientation.
+    pub(crate) fn random_molecule(&self, system: &System, rng: &mut RngCore) -> Molecule {
+        let z = if system.dimensions() == 2 { 0.0 } else { rng.gen() };
+        let fractional = Vector3D::new(rng.gen(), rng.gen(), z);
+        let center = system.cell.cartesian(&fractional);
+        return self.molecule_at(system, center, rng);
+    }
+
+    /// Get a new copy of the molecule, with the center-of-mass at `center`
+    /// and a random orientation.
+    pub(crate) fn molecule_at(
+        &self,
+        system: &System,
+        center: Vector3D,
+        rng: &mut RngCore,
+    ) -> Molecule {
+        let rotation = self.random_rotation(system, rng);
+
+        let mut molecule = self.molecule.clone();
+        for position in molecule.particles_mut().position {
+            *position = center + rotation * (*position);
+        }
+        return molecule;
+    }
+
+    /// Get a random rotation matrix, uniformly distributed over all the
+    /// possible orientations.
+    fn random_rotation(&self, system: &System, rng: &mut RngCore) -> Matrix3 {
+        if system.dimensions() == 2 {
+            // Two-dimensional molecules can only rotate around the z axis
+            let angle = rng.gen_range(0.0, 2.0 * PI);
+            return Matrix3::rotation(&Vector3D::new(0.0, 0.0, 1.0), angle);
+        }
+        // Normalized quaternions with normally distributed components are
+        // uniformly distributed over all the rotations.
+        let quaternion = Quaternion::new(
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+        );
+        return quaternion.normalized().rotation_matrix();
+    }
+}
+
+/// Monte Carlo move inserting a new molecule in the system, for simulations
+/// in the grand-canonical (μVT) ensemble.
+///
+/// The molecule is created from a template, with a random position in the
+/// unit cell and a random orientation. The move is accepted with the
+/// probability
+///
+/// $$ P_{acc} = \min\left[1, \frac{V}{\Lambda^3 (N + 1)} e^{\beta (\mu - \Delta U)}\right] $$
+///
+/// where $N$ is the number of molecules of this type before the insertion,
+/// $\Lambda$ the thermal de Broglie wavelength of the molecule and $\mu$ the
+/// chemical potential. The chemical potential uses the ideal gas of
+/// particles with the mass of the molecule as reference: the contribution of
+/// the rotational and internal degrees of freedom of the molecule must be
+/// included in $\mu$.
+///
+/// This move must be used together with a
+/// [`DeleteMolecule`](struct.DeleteMolecule.html) move using the same
+/// template and chemical potential, and with the same frequency, to ensure
+/// detailed balance.
+pub struct InsertMolecule {
+    /// Creation of the molecules to insert
+    inserter: MoleculeInserter,
+    /// Hash of the inserted molecules
+    hash: MoleculeHash,
+    /// Mass of the inserted molecules
+    mass: f64,
+    /// Chemical potential of the inserted molecules
+    chemical_potential: f64,
+    /// Index of the inserted molecule
+    molid: usize,
+}
+
+impl InsertMolecule {
+    /// Create a new `InsertMolecule` move, inserting copies of the molecule
+    /// described by `template` with the given `chemical_potential`.
+    pub fn new(template: &MoleculeTemplate, chemical_potential: f64) -> InsertMolecule {
+        let inserter = MoleculeInserter::new(template);
+        InsertMolecule {
+            hash: inserter.hash(),
+            inserter: inserter,
+            mass: template.mass(),
+            chemical_potential: chemical_potential,
+            molid: usize::MAX,
+        }
+    }
+}
+
+impl MCMove for InsertMolecule {
+    fn describe(&self) -> &str {
+        "molecule insertion"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        molecule_dof(self.hash)
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Can not insert molecules in a system with an infinite cell."
+        );
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        let molecule = self.inserter.random_molecule(system, rng);
+        system.add_molecule(molecule);
+        self.molid = system.molecules().count() - 1;
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        // The new molecule is already in the system
+        let count = count_molecules(system, self.hash) as f64;
+        let volume = system.volume();
+        let delta = cache.add_molecule_cost(system, self.molid);
+        let acceptance = volume / (thermal_volume(self.mass, beta) * count);
+        return beta * (delta - self.chemical_potential) - f64::ln(acceptance);
+    }
+
+    fn apply(&mut self, _: &mut System) {
+        // Nothing to do, the molecule was added in `prepare`
+    }
+
+    fn restore(&mut self, system: &mut System) {
+        let _ = system.remove_molecule(self.molid);
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn molecules_change(&self) -> isize {
+        1
+    }
+
+    fn exchanged_molecules(&self) -> Option<MoleculeHash> {
+        Some(self.hash)
+    }
+}
+
+/// Monte Carlo move deleting a random molecule from the system, for
+/// simulations in the grand-canonical (μVT) ensemble.
+///
+/// The move is accepted with the probability
+///
+/// $$ P_{acc} = \min\left[1, \frac{\Lambda^3 N}{V} e^{-\beta (\mu + \Delta U)}\right] $$
+///
+/// where $N$ is the number of molecules of this type before the deletion,
+/// $\Lambda$ the thermal de Broglie wavelength of the molecule and $\mu$ the
+/// chemical potential. Molecules containing frozen particles are never
+/// deleted.
+///
+/// This move must be used together with an
+/// [`InsertMolecule`](struct.InsertMolecule.html) move using the same
+/// template and chemical potential, and with the same frequency, to ensure
+/// detailed balance.
+pub struct DeleteMolecule {
+    /// Hash of the deleted molecules
+    hash: MoleculeHash,
+    /// Mass of the deleted molecules
+    mass: f64,
+    /// Chemical potential of the deleted molecules
+    chemical_potential: f64,
+    /// Index of the molecule to delete
+    molid: usize,
+}
+
+impl DeleteMolecule {
+    /// Create a new `DeleteMolecule` move, deleting molecules matching the
+    /// molecule described by `template`, with the given `chemical_potential`.
+    pub fn new(template: &MoleculeTemplate, chemical_potential: f64) -> DeleteMolecule {
+        DeleteMolecule {
+            hash: template.molecule().as_ref().hash(),
+            mass: template.mass(),
+            chemical_potential: chemical_potential,
+            molid: usize::MAX,
+        }
+    }
+}
+
+impl MCMove for DeleteMolecule {
+    fn describe(&self) -> &str {
+        "molecule deletion"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        molecule_dof(self.hash)
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Can not delete molecules in a system with an infinite cell."
+        );
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        // An empty system is expected in grand-canonical simulations, so
+        // there is no warning here
+        match select_molecule(system, Some(self.hash), rng) {
+            Some(molid) => {
+                self.molid = molid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        let count = count_molecules(system, self.hash) as f64;
+        let volume = system.volume();
+        let delta = cache.remove_molecule_cost(system, self.molid);
+        let acceptance = thermal_volume(self.mass, beta) * count / volume;
+        return beta * (delta + self.chemical_potential) - f64::ln(acceptance);
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let _ = system.remove_molecule(self.molid);
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn molecules_change(&self) -> isize {
+        -1
+    }
+
+    fn exchanged_molecules(&self) -> Option<MoleculeHash> {
+        Some(self.hash)
+    }
+}
+
+/// Cavities in a configuration, defined as the empty bins of a cell list.
+struct Cavities {
+    /// Number of bins along each cell vector
+    bins: [usize; 3],
+    /// Positions of the empty bins, sorted
+    empty: Vec<[usize; 3]>,
+}
+
+impl Cavities {
+    /// Find the cavities in the `configuration`, using a cell list with bins
+    /// at least as wide as `radius`.
+    fn new(configuration: &Configuration, radius: f64) -> Cavities {
+        let cells = CellList::new(configuration, radius);
+        let bins = cells.bins();
+        let mut empty = Vec::new();
+        for a in 0..bins[0] {
+            for b in 0..bins[1] {
+                for c in 0..bins[2] {
+                    if cells.particles_in([a, b, c]).is_empty() {
+                        empty.push([a, b, c]);
+                    }
+                }
+            }
+        }
+        Cavities {
+            bins: bins,
+            empty: empty,
+        }
+    }
+
+    /// Get the fraction of the cell volume occupied by the cavities
+    fn probability(&self) -> f64 {
+        let total = self.bins[0] * self.bins[1] * self.bins[2];
+        return self.empty.len() as f64 / total as f64;
+    }
+
+    /// Check if the point at the given `fractional` coordinates is inside a
+    /// cavity.
+    fn contains(&self, fractional: Vector3D) -> bool {
+        let mut bin = [0; 3];
+        for k in 0..3 {
+            let value = fractional[k] - f64::floor(fractional[k]);
+            bin[k] = usize::min((value * self.bins[k] as f64) as usize, self.bins[k] - 1);
+        }
+        return self.empty.binary_search(&bin).is_ok();
+    }
+
+    /// Get the fractional coordinates of a point distributed uniformly in
+    /// the cavities, or `None` if there is no cavity.
+    fn random_point(&self, rng: &mut RngCore) -> Option<Vector3D> {
+        if self.empty.is_empty() {
+            return None;
+        }
+        let bin = self.empty[rng.gen_range(0, self.empty.len())];
+        let mut fractional = Vector3D::zero();
+        for k in 0..3 {
+            fractional[k] = (bin[k] as f64 + rng.gen::<f64>()) / self.bins[k] as f64;
+        }
+        return Some(fractional);
+    }
+}
+
+/// Check that cavity-biased moves can be used with the `system`
+fn check_cavity_system(system: &System) {
+    assert!(
+        !system.cell.is_infinite(),
+        "Can not use cavity-biased moves in a system with an infinite cell."
+    );
+    assert!(
+        system.cell.periodicity() == [true, true, true],
+        "Can not use cavity-biased moves in a partially periodic system."
+    );
+}
+
+/// Monte Carlo move inserting a new molecule in a cavity of the system, for
+/// simulations of dense fluids in the grand-canonical (μVT) ensemble [1].
+///
+/// The cell is divided in bins using a [`CellList`] with bins at least as
+/// wide as a cavity `radius`, and the empty bins are used as cavities. The
+/// center-of-mass of the new molecule is placed at a random position in a
+/// random cavity, with a random orientation. The move is accepted with the
+/// probability
+///
+/// $$ P_{acc} = \min[1, \frac{P_{cav} V}{\Lambda^3 (N + 1)} e^{\beta (\mu - \Delta U)}] $$
+///
+/// where $P_{cav}$ is the fraction of the volume occupied by the cavities
+/// before the insertion, and all the other quantities are the same as for
+/// [`InsertMolecule`](struct.InsertMolecule.html). In dense fluids, random
+/// insertions nearly always overlap with existing molecules, while
+/// insertions in the cavities are accepted much more often.
+///
+/// This move must be used together with a
+/// [`CavityDeleteMolecule`](struct.CavityDeleteMolecule.html) move using the
+/// same template, chemical potential and radius, and with the same
+/// frequency, to ensure detailed balance.
+///
+/// [1] M. Mezei, Mol. Phys. 40, 901 (1980).
+///
+/// [`CellList`]: ../../lumol_core/sys/struct.CellList.html
+pub struct CavityInsertMolecule {
+    /// Creation of the molecules to insert
+    inserter: MoleculeInserter,
+    /// Hash of the inserted molecules
+    hash: MoleculeHash,
+    /// Mass of the inserted molecules
+    mass: f64,
+    /// Chemical potential of the inserted molecules
+    chemical_potential: f64,
+    /// Minimal width of the bins used to find cavities
+    radius: f64,
+    /// Fraction of the volume occupied by the cavities before the insertion
+    probability: f64,
+    /// Index of the inserted molecule
+    molid: usize,
+}
+
+impl CavityInsertMolecule {
+    /// Create a new `CavityInsertMolecule` move, inserting copies of the
+    /// molecule described by `template` with the given `chemical_potential`
+    /// in cavities of size `radius`.
+    pub fn new(
+        template: &MoleculeTemplate,
+        chemical_potential: f64,
+        radius: f64,
+    ) -> CavityInsertMolecule {
+        assert!(radius > 0.0, "The cavity radius must be positive in cavity-biased insertion.");
+        let inserter = MoleculeInserter::new(template);
+        CavityInsertMolecule {
+            hash: inserter.hash(),
+            inserter: inserter,
+            mass: template.mass(),
+            chemical_potential: chemical_potential,
+            radius: radius,
+            probability: 0.0,
+            molid: usize::MAX,
+        }
+    }
+}
+
+impl MCMove for CavityInsertMolecule {
+    fn describe(&self) -> &str {
+        "cavity-biased molecule insertion"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        molecule_dof(self.hash)
+    }
+
+    fn setup(&mut self, system: &System) {
+        check_cavity_system(system);
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.dimensions() == 2 {
+            warn_once!("Can not use cavity-biased insertion in two-dimensional systems.");
+            return false;
+        }
+
+        let cavities = Cavities::new(system, self.radius);
+        let fractional = match cavities.random_point(rng) {
+            Some(fractional) => fractional,
+            None => return false,
+        };
+
+        let center = system.cell.cartesian(&fractional);
+        let molecule = self.inserter.molecule_at(system, center, rng);
+        system.add_molecule(molecule);
+        self.molid = system.molecules().count() - 1;
+        self.probability = cavities.probability();
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        // The new molecule is already in the system
+        let count = count_molecules(system, self.hash) as f64;
+        let volume = self.probability * system.volume();
+        let delta = cache.add_molecule_cost(system, self.molid);
+        let acceptance = volume / (thermal_volume(self.mass, beta) * count);
+        return beta * (delta - self.chemical_potential) - f64::ln(acceptance);
+    }
+
+    fn apply(&mut self, _: &mut System) {
+        // Nothing to do, the molecule was added in `prepare`
+    }
+
+    fn restore(&mut self, system: &mut System) {
+        let _ = system.remove_molecule(self.molid);
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn molecules_change(&self) -> isize {
+        1
+    }
+
+    fn exchanged_molecules(&self) -> Option<MoleculeHash> {
+        Some(self.hash)
+    }
+}
+
+/// Monte Carlo move deleting a random molecule from the system, reverse of
+/// the [`CavityInsertMolecule`](struct.CavityInsertMolecule.html) move.
+///
+/// The cavities are found in the system without the deleted molecule, in
+/// the same way as for cavity-biased insertions. If the center-of-mass of
+/// the molecule is not inside a cavity, the molecule could not have been
+/// inserted by a cavity-biased insertion and the move is rejected. Otherwise
+/// the move is accepted with the probability
+///
+/// $$ P_{acc} = \min\left[1, \frac{\Lambda^3 N}{P_{cav} V} e^{-\beta (\mu + \Delta U)}\right] $$
+///
+/// where $P_{cav}$ is the fraction of the volume occupied by the cavities
+/// after the deletion, and all the other quantities are the same as for
+/// [`DeleteMolecule`](struct.DeleteMolecule.html).
+pub struct CavityDeleteMolecule {
+    /// Hash of the deleted molecules
+    hash: MoleculeHash,
+    /// Mass of the deleted molecules
+    mass: f64,
+    /// Chemical potential of the deleted molecules
+    chemical_potential: f64,
+    /// Minimal width of the bins used to find cavities
+    radius: f64,
+    /// Fraction of the volume occupied by the cavities after the deletion
+    probability: f64,
+    /// Index of the molecule to delete
+    molid: usize,
+}
+
+impl CavityDeleteMolecule {
+    /// Create a new `CavityDeleteMolecule` move, deleting molecules matching
+    /// the molecule described by `template`, with the given
+    /// `chemical_potential` and cavities of size `radius`.
+    pub fn new(
+        template: &MoleculeTemplate,
+        chemical_potential: f64,
+        radius: f64,
+    ) -> CavityDeleteMolecule {
+        assert!(radius > 0.0, "The cavity radius must be positive in cavity-biased deletion.");
+        CavityDeleteMolecule {
+            hash: template.molecule().as_ref().hash(),
+            mass: template.mass(),
+            chemical_potential: chemical_potential,
+            radius: radius,
+            probability: 0.0,
+            molid: usize::MAX,
+        }
+    }
+}
+
+impl MCMove for CavityDeleteMolecule {
+    fn describe(&self) -> &str {
+        "cavity-biased molecule deletion"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        molecule_dof(self.hash)
+    }
+
+    fn setup(&mut self, system: &System) {
+        check_cavity_system(system);
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.dimensions() == 2 {
+            warn_once!("Can not use cavity-biased deletion in two-dimensional systems.");
+            return false;
+        }
+
+        self.molid = match select_molecule(system, Some(self.hash), rng) {
+            Some(molid) => molid,
+            None => return false,
+        };
+
+        let mut configuration = Configuration::clone(system);
+        configuration.remove_molecule(self.molid);
+        let cavities = Cavities::new(&configuration, self.radius);
+
+        let center = system.molecule(self.molid).center_of_mass();
+        if !cavities.contains(system.cell.fractional(&center)) {
+            // The reverse insertion is not possible
+            return false;
+        }
+        self.probability = cavities.probability();
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        let count = count_molecules(system, self.hash) as f64;
+        let volume = self.probability * system.volume();
+        let delta = cache.remove_molecule_cost(system, self.molid);
+        let acceptance = thermal_volume(self.mass, beta) * count / volume;
+        return beta * (delta + self.chemical_potential) - f64::ln(acceptance);
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let _ = system.remove_molecule(self.molid);
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn molecules_change(&self) -> isize {
+        -1
+    }
+
+    fn exchanged_molecules(&self) -> Option<MoleculeHash> {
+        Some(self.hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{LennardJones, PairInteraction};
+    use core::{Particle, UnitCell};
+    use core::consts::K_BOLTZMANN;
+    use core::units;
+
+    fn argon() -> MoleculeTemplate {
+        MoleculeTemplate::new().particle("Ar", Vector3D::zero(), 0.0)
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 4.0].into())));
+
+        let lj = LennardJones {
+            sigma: units::from(3.4, "A").unwrap(),
+            epsilon: units::from(1.0, "kJ/mol").unwrap(),
+        };
+        system.add_pair_potential(("Ar", "Ar"), PairInteraction::new(Box::new(lj), 8.0));
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x3f, 0x1c, 0x6a, 0x92, 0x05, 0xd4, 0x7e, 0x21,
+            0xb8, 0x43, 0x0f, 0x9c, 0x66, 0xe1, 0x2a, 0x75,
+        ])
+    }
+
+    #[test]
+    fn insert() {
+        let mut system = testing_system();
+        let beta = 1.0 / (K_BOLTZMANN * units::from(300.0, "K").unwrap());
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+
+        let mu = units::from(-10.0, "kJ/mol").unwrap();
+        let mut insert = InsertMolecule::new(&argon(), mu);
+        insert.setup(&system);
+        assert!(insert.prepare(&mut system, &mut rng()));
+        assert_eq!(system.molecules().count(), 3);
+
+        let cost = insert.cost(&system, beta, &mut cache);
+        let delta = system.potential_energy() - old_energy;
+        let mass = system.particles().mass[0];
+        let expected = beta * (delta - mu) - f64::ln(8000.0 / (3.0 * thermal_volume(mass, beta)));
+        assert_relative_eq!(cost, expected, max_relative = 1e-9);
+
+        insert.apply(&mut system);
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), system.potential_energy(), max_relative = 1e-9);
+
+        // Restoring removes the new molecule
+        assert!(insert.prepare(&mut system, &mut rng()));
+        assert_eq!(system.molecules().count(), 4);
+        insert.restore(&mut system);
+        assert_eq!(system.molecules().count(), 3);
+    }
+
+    #[test]
+    fn delete() {
+        let mut system = testing_system();
+        let beta = 1.0 / (K_BOLTZMANN * units::from(300.0, "K").unwrap());
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+
+        let mu = units::from(-10.0, "kJ/mol").unwrap();
+        let mut delete = DeleteMolecule::new(&argon(), mu);
+        delete.setup(&system);
+        assert!(delete.prepare(&mut system, &mut rng()));
+        assert_eq!(system.molecules().count(), 2);
+
+        let cost = delete.cost(&system, beta, &mut cache);
+        delete.apply(&mut system);
+        assert_eq!(system.molecules().count(), 1);
+
+        let delta = system.potential_energy() - old_energy;
+        let mass = system.particles().mass[0];
+        let expected = beta * (delta + mu) - f64::ln(2.0 * thermal_volume(mass, beta) / 8000.0);
+        assert_relative_eq!(cost, expected, max_relative = 1e-9);
+
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), system.potential_energy(), epsilon = 1e-9);
+
+        // No molecule can be deleted from an empty system
+        assert!(delete.prepare(&mut system, &mut rng()));
+        delete.apply(&mut system);
+        assert!(!delete.prepare(&mut system, &mut rng()));
+    }
+
+    #[test]
+    fn thermal_wavelength() {
+        // The thermal wavelength of argon at 300 K is 0.1595 A
+        let beta = 1.0 / (K_BOLTZMANN * units::from(300.0, "K").unwrap());
+        let volume = thermal_volume(39.948, beta);
+        assert_relative_eq!(f64::cbrt(volume), 0.1595, epsilon = 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not insert molecules in a system with an infinite cell.")]
+    fn infinite_cell() {
+        let system = System::new();
+        let mut insert = InsertMolecule::new(&argon(), 0.0);
+        insert.setup(&system);
+    }
+
+    #[test]
+    fn cavities() {
+        let system = testing_system();
+        // The cell list uses 2 bins along each direction, and the two
+        // particles are in the bin at the origin
+        let cavities = Cavities::new(&system, 5.0);
+        assert_eq!(cavities.bins, [2, 2, 2]);
+        assert_eq!(cavities.empty.len(), 7);
+        assert_eq!(cavities.probability(), 0.875);
+
+        assert!(!cavities.contains(Vector3D::new(0.1, 0.1, 0.1)));
+        assert!(cavities.contains(Vector3D::new(0.6, 0.1, 0.1)));
+        assert!(cavities.contains(Vector3D::new(-0.4, 0.1, 0.1)));
+        assert!(!cavities.contains(Vector3D::new(1.1, 0.1, 0.1)));
+
+        let mut rng = rng();
+        for _ in 0..100 {
+            let point = cavities.random_point(&mut rng).unwrap();
+            assert!(cavities.contains(point));
+        }
+    }
+
+    #[test]
+    fn cavity_insert() {
+        let mut system = testing_system();
+        let beta = 1.0 / (K_BOLTZMANN * units::from(300.0, "K").unwrap());
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+        let cavities = Cavities::new(&system, 5.0);
+
+        let mu = units::from(-10.0, "kJ/mol").unwrap();
+        let mut insert = CavityInsertMolecule::new(&argon(), mu, 5.0);
+        insert.setup(&system);
+        assert!(insert.prepare(&mut system, &mut rng()));
+        assert_eq!(system.molecules().count(), 3);
+        let position = system.particles().position[2];
+        assert!(cavities.contains(system.cell.fractional(&position)));
+
+        let cost = insert.cost(&system, beta, &mut cache);
+        let delta = system.potential_energy() - old_energy;
+        let mass = system.particles().mass[0];
+        let volume = 0.875 * 8000.0;
+        let expected = beta * (delta - mu) - f64::ln(volume / (3.0 * thermal_volume(mass, beta)));
+        assert_relative_eq!(cost, expected, max_relative = 1e-9);
+
+        insert.restore(&mut system);
+        assert_eq!(system.molecules().count(), 2);
+    }
+
+    #[test]
+    fn cavity_delete() {
+        let mut system = testing_system();
+        let particle = Particle::with_position("Ar", [10.0, 10.0, 10.0].into());
+        system.add_molecule(Molecule::new(particle));
+        let beta = 1.0 / (K_BOLTZMANN * units::from(300.0, "K").unwrap());
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+        let old_energy = system.potential_energy();
+
+        let mu = units::from(-10.0, "kJ/mol").unwrap();
+        let mut delete = CavityDeleteMolecule::new(&argon(), mu, 5.0);
+        delete.setup(&system);
+
+        // Only the last molecule is in a cavity once removed, the first two
+        // are in the same bin.
+        let mut rng = rng();
+        let mut rejected = 0;
+        while !delete.prepare(&mut system, &mut rng) {
+            rejected += 1;
+            assert!(rejected < 100);
+        }
+        assert_eq!(delete.molid, 2);
+
+        let cost = delete.cost(&system, beta, &mut cache);
+        delete.apply(&mut system);
+        assert_eq!(system.molecules().count(), 2);
+
+        let delta = system.potential_energy() - old_energy;
+        let mass = system.particles().mass[0];
+        let volume = 0.875 * 8000.0;
+        let expected = beta * (delta + mu) - f64::ln(3.0 * thermal_volume(mass, beta) / volume);
+        assert_relative_eq!(cost, expected, max_relative = 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not use cavity-biased moves in a partially periodic system.")]
+    fn cavity_partially_periodic() {
+        let mut system = testing_system();
+        system.cell.set_periodicity([true, true, false]);
+        let mut insert = CavityInsertMolecule::new(&argon(), 0.0, 5.0);
+        insert.setup(&system);
+    }
+}
@@ -0,0 +1,276 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::RngCore;
+use rand::distributions::{Normal, Distribution};
+
+use super::{MCDegreeOfFreedom, MCMove};
+
+use core::consts::K_BOLTZMANN;
+use core::{Configuration, EnergyCache, System, Snapshot, Vector3D};
+use md::check_no_constraints;
+
+/// Hybrid Monte Carlo move, using a short molecular dynamics trajectory as
+/// the trial move [1].
+///
+/// The velocities of all the particles are drawn from a Maxwell-Boltzmann
+/// distribution at the move temperature, and the system is propagated for a
+/// few steps of NVE molecular dynamics with the velocity-Verlet algorithm.
+/// The resulting configuration is accepted or rejected using the change in
+/// total energy (potential and kinetic) along the trajectory. Because the
+/// velocity-Verlet algorithm is time-reversible and conserves the volume in
+/// phase space, this move samples the Boltzmann distribution exactly, even
+/// with large timesteps.
+///
+/// The temperature of the move must be the same as the temperature of the
+/// Monte Carlo propagator. This move can not be used with holonomic
+/// constraints.
+///
+/// [1] Duane, S. et al. *Hybrid Monte Carlo*, Phys. Lett. B **195** (1987).
+pub struct HybridMD {
+    /// Temperature used to draw the velocities
+    temperature: f64,
+    /// Timestep of the molecular dynamics trajectory
+    timestep: f64,
+    /// Number of molecular dynamics steps in a trajectory
+    steps: usize,
+    /// Configuration before running the trajectory
+    previous: Snapshot,
+    /// Velocities drawn at the beginning of the trajectory
+    velocities: Vec<Vector3D>,
+    /// Kinetic energy difference between the end and the beginning of the
+    /// trajectory
+    delta_kinetic: f64,
+    /// Normal distribution for the velocities
+    dist: Normal,
+}
+
+impl HybridMD {
+    /// Create a new `HybridMD` move, drawing velocities at the given
+    /// `temperature`, and running trajectories of `steps` steps with the
+    /// given `timestep`.
+    pub fn new(temperature: f64, timestep: f64, steps: usize) -> HybridMD {
+        assert!(temperature >= 0.0, "temperature must be positive in HybridMD move");
+        assert!(timestep > 0.0, "timestep must be positive in HybridMD move");
+        assert!(steps > 0, "the number of steps must be positive in HybridMD move");
+        HybridMD {
+            temperature: temperature,
+            timestep: timestep,
+            steps: steps,
+            previous: Configuration::new().snapshot(),
+            velocities: Vec::new(),
+            delta_kinetic: 0.0,
+            dist: Normal::new(0.0, 1.0),
+        }
+    }
+
+    /// Get the current timestep of the molecular dynamics trajectories
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    /// Draw new velocities for all the particles in the `system` from a
+    /// Maxwell-Boltzmann distribution.
+    fn draw_velocities(&mut self, system: &mut System, rng: &mut RngCore) {
+        let planar = system.dimensions() == 2;
+        let sigma = f64::sqrt(K_BOLTZMANN * self.temperature);
+        for (&mass, &frozen, velocity) in soa_zip!(
+            system.particles_mut(), [mass, frozen, mut velocity]
+        ) {
+            // Massless particles (virtual sites) and frozen particles do not
+            // get any velocity
+            if mass == 0.0 || frozen {
+                *velocity = Vector3D::zero();
+                continue;
+            }
+
+            let sigma = sigma / f64::sqrt(mass);
+            let x = sigma * self.dist.sample(rng);
+            let y = sigma * self.dist.sample(rng);
+            let z = if planar { 0.0 } else { sigma * self.dist.sample(rng) };
+            *velocity = Vector3D::new(x, y, z);
+        }
+    }
+
+    /// Run the molecular dynamics trajectory on `system`, using the
+    /// velocity-Verlet algorithm.
+    fn trajectory(&self, system: &mut System) {
+        let dt = self.timestep;
+        let inverse_masses = system.particles().mass.iter()
+            .zip(system.particles().frozen)
+            .map(|(&mass, &frozen)| if mass == 0.0 || frozen { 0.0 } else { 1.0 / mass })
+            .collect::<Vec<_>>();
+
+        let mut forces = system.forces();
+        for _ in 0..self.steps {
+            for (position, velocity, &inverse_mass, force) in soa_zip!(
+                system.particles_mut(), [mut position, mut velocity], &inverse_masses, &forces
+            ) {
+                *velocity += 0.5 * dt * inverse_mass * force;
+                *position += velocity * dt;
+            }
+            system.update_virtual_sites();
+
+            forces = system.forces();
+            for (velocity, &inverse_mass, force) in soa_zip!(
+                system.particles_mut(), [mut velocity], &inverse_masses, &forces
+            ) {
+                *velocity += 0.5 * dt * inverse_mass * force;
+            }
+        }
+    }
+}
+
+impl MCMove for HybridMD {
+    fn describe(&self) -> &str {
+        "hybrid molecular dynamics"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::Particles
+    }
+
+    fn setup(&mut self, system: &System) {
+        check_no_constraints(system, "HybridMD move");
+    }
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.size() == 0 {
+            warn!("Can not run HybridMD move: there is no particle in the system.");
+            return false;
+        }
+
+        self.previous = system.snapshot();
+        self.draw_velocities(system, rng);
+        self.velocities = system.particles().velocity.to_vec();
+
+        let initial = system.kinetic_energy();
+        self.trajectory(system);
+        self.delta_kinetic = system.kinetic_energy() - initial;
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        // All the particles moved, the cache needs to be fully recomputed
+        // if the move is accepted.
+        let delta_potential = system.potential_energy() - cache.energy();
+        cache.unused();
+        return beta * (delta_potential + self.delta_kinetic);
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        // Move molecules such that their center-of-mass is inside the
+        // simulation cell.
+        let cell = system.cell;
+        for mut molecule in system.molecules_mut() {
+            molecule.wrap(&cell);
+        }
+    }
+
+    fn restore(&mut self, system: &mut System) {
+        system.restore(&self.previous);
+        // Keep the velocities drawn from the Maxwell-Boltzmann distribution,
+        // which are still distributed correctly for the initial positions.
+        for (velocity, initial) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.velocities
+        ) {
+            *velocity = *initial;
+        }
+    }
+
+    fn update_amplitude(&mut self, scaling_factor: Option<f64>) {
+        if let Some(s) = scaling_factor {
+            self.timestep *= s;
+        }
+    }
+
+    fn temperature(&self) -> Option<f64> {
+        Some(self.temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Constraint, Molecule, Particle, UnitCell};
+    use core::units;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 1.0 }), 8.0);
+        system.add_pair_potential(("He", "He"), pair);
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x1a, 0x8e, 0x73, 0x5c, 0xf1, 0x02, 0x4d, 0xb6,
+            0x93, 0x37, 0xe6, 0xa0, 0x58, 0x71, 0x0c, 0x2f,
+        ])
+    }
+
+    #[test]
+    fn accepted() {
+        let mut system = testing_system();
+        let temperature = units::from(300.0, "K").unwrap();
+        let beta = 1.0 / (K_BOLTZMANN * temperature);
+
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut hybrid = HybridMD::new(temperature, units::from(0.1, "fs").unwrap(), 10);
+        hybrid.setup(&system);
+        assert!(hybrid.prepare(&mut system, &mut rng()));
+        assert!(system.particles().position[0] != Vector3D::zero());
+
+        // The total energy is conserved along a short trajectory
+        let cost = hybrid.cost(&system, beta, &mut cache);
+        assert!(f64::abs(cost) < 1e-3);
+
+        hybrid.apply(&mut system);
+        cache.update(&mut system);
+        assert_relative_eq!(cache.energy(), system.potential_energy(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn rejected() {
+        let mut system = testing_system();
+        let temperature = units::from(300.0, "K").unwrap();
+        let mut hybrid = HybridMD::new(temperature, units::from(1.0, "fs").unwrap(), 5);
+        hybrid.setup(&system);
+        assert!(hybrid.prepare(&mut system, &mut rng()));
+        let velocities = hybrid.velocities.clone();
+
+        hybrid.restore(&mut system);
+        let particles = system.particles();
+        assert_eq!(particles.position[0], Vector3D::zero());
+        assert_eq!(particles.position[1], Vector3D::new(0.0, 0.0, 2.0));
+        // The new velocities are kept
+        assert_eq!(particles.velocity, &velocities[..]);
+        assert!(particles.velocity[0] != Vector3D::zero());
+    }
+
+    #[test]
+    #[should_panic(expected = "The HybridMD move can not enforce the holonomic constraints")]
+    fn constraints() {
+        let mut system = testing_system();
+        system.add_constraint(Constraint::Rigid { start: 0, end: 1 });
+        let mut hybrid = HybridMD::new(300.0, 1.0, 5);
+        hybrid.setup(&system);
+    }
+
+    #[test]
+    fn amplitude() {
+        let mut hybrid = HybridMD::new(300.0, 1.0, 5);
+        hybrid.update_amplitude(Some(0.5));
+        assert_ulps_eq!(hybrid.timestep(), 0.5);
+        hybrid.update_amplitude(None);
+        assert_ulps_eq!(hybrid.timestep(), 0.5);
+    }
+}
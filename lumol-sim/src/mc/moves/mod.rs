@@ -10,7 +10,8 @@
 //! In all this module, beta refers to the Boltzmann factor 1/(kB T)
 use rand::{RngCore, Rng};
 use std::collections::BTreeSet;
-use core::{EnergyCache, System, MoleculeHash};
+use std::usize;
+use core::{EnergyCache, System, MoleculeHash, MoleculeRef, UnitCell};
 
 /// Possible degrees of freedom simulated by a given Monte Carlo move
 #[derive(Clone, PartialEq, Debug)]
@@ -43,7 +44,7 @@ impl MCDegreeOfFreedom {
 
 /// The `MCMove` trait correspond to the set of methods used in Monte Carlo
 /// simulations.
-pub trait MCMove {
+pub trait MCMove: Send {
     /// Give a short description of this move
     fn describe(&self) -> &str;
 
@@ -83,11 +84,34 @@ pub trait MCMove {
 
     /// Update the sample range for displacements.
     fn update_amplitude(&mut self, scaling_factor: Option<f64>);
+
+    /// Get the change in the number of molecules in the system if the
+    /// prepared move is accepted. The default implementation is for moves
+    /// which do not insert or delete molecules.
+    fn molecules_change(&self) -> isize {
+        0
+    }
+
+    /// Get the hash of the molecules inserted or deleted by this move. The
+    /// default implementation is for moves which do not insert or delete
+    /// molecules.
+    fn exchanged_molecules(&self) -> Option<MoleculeHash> {
+        None
+    }
+
+    /// Get the temperature used by this move to generate or accept the new
+    /// configurations, for moves which do not only rely on the cost given
+    /// to the propagator. The default implementation is for moves without
+    /// such temperature.
+    fn temperature(&self) -> Option<f64> {
+        None
+    }
 }
 
 /// Select a random molecule in the system using `rng` as random number
 /// generator. If `hash` is `None`, any molecule can be chosen. If `hash` is
-/// `Some(hash)`, then a molecule with matching hash is selected.
+/// `Some(hash)`, then a molecule with matching hash is selected. Molecules
+/// containing frozen particles are never selected.
 ///
 /// This function returns `None` if no matching molecule was found, and
 /// `Some(molid)` with `molid` the index of the molecule if a molecule was
@@ -97,7 +121,15 @@ fn select_molecule(system: &System, hash: Option<MoleculeHash>, rng: &mut RngCor
         // Pick a random molecule with matching moltype
         let mols = system.molecules()
             .enumerate()
-            .filter(|(_, m)| m.hash() == hash)
+            .filter(|(_, m)| m.hash() == hash && !is_frozen(m))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        return rng.choose(&mols).cloned();
+    } else if system.particles().frozen.iter().any(|&frozen| frozen) {
+        // Pick a random molecule without frozen particles
+        let mols = system.molecules()
+            .enumerate()
+            .filter(|(_, m)| !is_frozen(m))
             .map(|(i, _)| i)
             .collect::<Vec<_>>();
         return rng.choose(&mols).cloned();
@@ -111,11 +143,107 @@ fn select_molecule(system: &System, hash: Option<MoleculeHash>, rng: &mut RngCor
     }
 }
 
+/// Check if the `molecule` contains any frozen particle
+fn is_frozen(molecule: &MoleculeRef) -> bool {
+    molecule.particles().frozen.iter().any(|&frozen| frozen)
+}
+
+/// Move the centers of mass of the molecules in `system` after a change of
+/// the unit cell from `previous` to `system.cell`, keeping the same
+/// fractional coordinates for the centers of mass. Molecules containing
+/// frozen particles are not moved.
+fn scale_molecules(system: &mut System, previous: &UnitCell) {
+    let cell = system.cell;
+    for mut molecule in system.molecules_mut() {
+        if is_frozen(&molecule.as_ref()) {
+            continue;
+        }
+
+        // We don't want to change the intramolecular distances so we
+        // compute the translation vector of the center-of-mass (com) of a
+        // molecule and apply it to all its particles. Note that to do
+        // this, the com of a molecule *always* has to reside inside the
+        // simulation cell.
+        let old_com = molecule.as_ref().center_of_mass();
+        let frac_com = previous.fractional(&old_com);
+        let delta_com = cell.cartesian(&frac_com) - old_com;
+        for position in molecule.particles_mut().position.iter_mut() {
+            *position += delta_com;
+        }
+    }
+}
+
+/// Get the indexes of the particles in the molecule at `molid`, relative to
+/// the start of the molecule, in the order of the chain backbone. This
+/// returns `None` if the molecule is not a linear chain.
+fn chain_order(system: &System, molid: usize) -> Option<Vec<usize>> {
+    let molecule = system.molecule(molid);
+    let start = molecule.indexes().start;
+    let size = molecule.size();
+    if molecule.bonds().len() != size - 1 {
+        return None;
+    }
+
+    let mut neighbors = vec![Vec::new(); size];
+    for bond in molecule.bonds() {
+        neighbors[bond.i() - start].push(bond.j() - start);
+        neighbors[bond.j() - start].push(bond.i() - start);
+    }
+    if neighbors.iter().any(|list| list.len() > 2) {
+        return None;
+    }
+
+    // Start from the first chain end, to always get the same order
+    let mut current = neighbors.iter().position(|list| list.len() == 1)?;
+    let mut previous = usize::MAX;
+    let mut order = vec![current];
+    while let Some(&next) = neighbors[current].iter().find(|&&next| next != previous) {
+        order.push(next);
+        previous = current;
+        current = next;
+    }
+
+    if order.len() == size {
+        Some(order)
+    } else {
+        None
+    }
+}
+
 mod translate;
 pub use self::translate::Translate;
 
 mod rotate;
 pub use self::rotate::Rotate;
 
+mod torsion;
+pub use self::torsion::Torsion;
+
 mod resize;
 pub use self::resize::Resize;
+
+mod anisotropic;
+pub use self::anisotropic::AnisotropicResize;
+
+mod hybrid;
+pub use self::hybrid::HybridMD;
+
+mod exchange;
+pub use self::exchange::{InsertMolecule, DeleteMolecule};
+pub use self::exchange::{CavityInsertMolecule, CavityDeleteMolecule};
+pub(crate) use self::exchange::MoleculeInserter;
+
+mod regrow;
+pub use self::regrow::Regrow;
+
+mod partial_regrow;
+pub use self::partial_regrow::PartialRegrow;
+
+mod reptation;
+pub use self::reptation::Reptation;
+
+mod cluster;
+pub use self::cluster::GeometricCluster;
+
+mod aggregation;
+pub use self::aggregation::AggregationVolumeBias;
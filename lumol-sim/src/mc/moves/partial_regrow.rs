@@ -0,0 +1,372 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+
+use std::f64;
+use std::f64::consts::PI;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::{chain_order, select_molecule};
+
+use core::{EnergyCache, System, MoleculeHash, Vector3D};
+
+/// Monte Carlo move regrowing a terminal part of a linear chain molecule,
+/// using internal coordinates.
+///
+/// One end of the chain and a random number of particles at this end are
+/// selected, while the rest of the molecule stays fixed. The position of
+/// each selected particle is described by its bond length, bond angle and
+/// dihedral angle relative to the three preceding particles along the
+/// chain. These internal coordinates are changed by random amounts, and the
+/// end of the chain is rebuilt from them. The move is accepted using the
+/// energy difference and the Jacobian of the transformation from cartesian
+/// to internal coordinates.
+///
+/// Setting the maximal change of the bond lengths (or the bond angles) to
+/// zero keeps them fixed, which can be used with models having rigid bonds
+/// or angles. Only molecules containing at least four particles are
+/// changed, and this move can only be used in three-dimensional systems.
+pub struct PartialRegrow {
+    /// Hash of molecule to regrow. `None` means all molecules.
+    hash: Option<MoleculeHash>,
+    /// Maximal change of the bond lengths
+    bond_delta: f64,
+    /// Maximal change of the bond angles
+    angle_delta: f64,
+    /// Maximal change of the dihedral angles
+    dihedral_delta: f64,
+    /// Index of the molecule to regrow
+    molid: usize,
+    /// New positions of the particles in the regrown molecule
+    newpos: Vec<Vector3D>,
+    /// Logarithm of the ratio of the Jacobians of the new and old
+    /// configurations
+    log_jacobian: f64,
+}
+
+impl PartialRegrow {
+    /// Create a new `PartialRegrow` move, changing the bond lengths by at
+    /// most `bond_delta`, the bond angles by at most `angle_delta` and the
+    /// dihedral angles by at most `dihedral_delta`. The angles are given in
+    /// radians. This move will apply to the molecules with the given `hash`,
+    /// or all molecules if `hash` is `None`.
+    ///
+    /// # Panics
+    ///
+    /// If `bond_delta` or `angle_delta` are negative, or if `dihedral_delta`
+    /// is not positive.
+    pub fn new<H: Into<Option<MoleculeHash>>>(
+        bond_delta: f64,
+        angle_delta: f64,
+        dihedral_delta: f64,
+        hash: H,
+    ) -> PartialRegrow {
+        assert!(bond_delta >= 0.0, "bond delta must be positive or zero in PartialRegrow move");
+        assert!(angle_delta >= 0.0, "angle delta must be positive or zero in PartialRegrow move");
+        assert!(dihedral_delta > 0.0, "dihedral delta must be positive in PartialRegrow move");
+        PartialRegrow {
+            hash: hash.into(),
+            bond_delta: bond_delta,
+            angle_delta: angle_delta,
+            dihedral_delta: dihedral_delta,
+            molid: usize::MAX,
+            newpos: Vec::new(),
+            log_jacobian: 0.0,
+        }
+    }
+}
+
+/// Local frame used to convert between cartesian and internal coordinates
+/// of a particle bonded to `a`, with `b` bonded to `a` and `c` bonded to `b`.
+struct Frame {
+    origin: Vector3D,
+    /// Unit vector from `b` to `a`
+    bond: Vector3D,
+    /// Unit vector in the plane of `a`, `b` and `c`, orthogonal to `bond`
+    plane: Vector3D,
+    /// Unit vector normal to the plane of `a`, `b` and `c`
+    normal: Vector3D,
+}
+
+impl Frame {
+    /// Create the local frame for the particles at positions `a`, `b` and
+    /// `c`, or `None` if these particles are aligned.
+    fn new(a: Vector3D, b: Vector3D, c: Vector3D) -> Option<Frame> {
+        let bond = (a - b).normalized();
+        let normal = (b - c) ^ bond;
+        if normal.norm() < 1e-9 {
+            return None;
+        }
+        let normal = normal.normalized();
+        Some(Frame {
+            origin: a,
+            bond: bond,
+            plane: normal ^ bond,
+            normal: normal,
+        })
+    }
+
+    /// Get the bond length, bond angle and dihedral angle of the particle at
+    /// `position` in this frame
+    fn internal(&self, position: Vector3D) -> (f64, f64, f64) {
+        let rij = position - self.origin;
+        let r = rij.norm();
+        let theta = f64::acos(f64::max(-1.0, f64::min(1.0, -(rij * self.bond) / r)));
+        let phi = f64::atan2(rij * self.normal, rij * self.plane);
+        return (r, theta, phi);
+    }
+
+    /// Get the position of the particle with bond length `r`, bond angle
+    /// `theta` and dihedral angle `phi` in this frame
+    fn cartesian(&self, r: f64, theta: f64, phi: f64) -> Vector3D {
+        let (sin, cos) = theta.sin_cos();
+        return self.origin - r * cos * self.bond
+            + r * sin * (phi.cos() * self.plane + phi.sin() * self.normal);
+    }
+}
+
+impl MCMove for PartialRegrow {
+    fn describe(&self) -> &str {
+        "partial regrowth"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::Particles
+    }
+
+    fn setup(&mut self, _: &System) {}
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.dimensions() == 2 {
+            warn_once!("Can not use partial regrowth in two-dimensional systems.");
+            return false;
+        }
+
+        if let Some(id) = select_molecule(system, self.hash, rng) {
+            self.molid = id;
+        } else {
+            warn!("Can not regrow molecule: no molecule of this type in the system.");
+            return false;
+        }
+
+        if system.molecule(self.molid).size() < 4 {
+            warn_once!(
+                "Can not use partial regrowth with molecules containing less than 4 particles."
+            );
+            return false;
+        }
+
+        let mut order = match chain_order(system, self.molid) {
+            Some(order) => order,
+            None => {
+                warn_once!(
+                    "Can not use partial regrowth with molecules which are not linear chains."
+                );
+                return false;
+            }
+        };
+        // Randomly select the end of the chain to regrow
+        if rng.gen() {
+            order.reverse();
+        }
+        // Keep at least three particles, to define the internal coordinates
+        let first = rng.gen_range(3, order.len());
+
+        let positions = system.molecule(self.molid).particles().position;
+        self.newpos = positions.to_vec();
+        self.log_jacobian = 0.0;
+        for k in first..order.len() {
+            let (a, b, c) = (order[k - 1], order[k - 2], order[k - 3]);
+            let old_frame = Frame::new(positions[a], positions[b], positions[c]);
+            let new_frame = Frame::new(self.newpos[a], self.newpos[b], self.newpos[c]);
+            let (old_frame, new_frame) = match (old_frame, new_frame) {
+                (Some(old), Some(new)) => (old, new),
+                _ => return false,
+            };
+
+            let (r, theta, phi) = old_frame.internal(positions[order[k]]);
+            let new_r = r + self.bond_delta * (2.0 * rng.gen::<f64>() - 1.0);
+            let new_theta = theta + self.angle_delta * (2.0 * rng.gen::<f64>() - 1.0);
+            let new_phi = phi + self.dihedral_delta * (2.0 * rng.gen::<f64>() - 1.0);
+            if new_r <= 0.0 || new_theta <= 0.0 || new_theta >= PI {
+                return false;
+            }
+
+            self.newpos[order[k]] = new_frame.cartesian(new_r, new_theta, new_phi);
+            self.log_jacobian += 2.0 * f64::ln(new_r / r) + f64::ln(new_theta.sin() / theta.sin());
+        }
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        let delta = cache.deform_molecule_cost(system, self.molid, &self.newpos);
+        return beta * delta - self.log_jacobian;
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let mut molecule = system.molecule_mut(self.molid);
+        for (position, newpos) in soa_zip!(molecule.particles_mut(), [mut position], &self.newpos) {
+            *position = *newpos;
+        }
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, scaling_factor: Option<f64>) {
+        if let Some(s) = scaling_factor {
+            if s * self.dihedral_delta > PI || s * self.angle_delta > PI {
+                warn_once!(
+                    "Tried to increase the maximum amplitude for angles to more than 180°."
+                );
+                return;
+            }
+            self.bond_delta *= s;
+            self.angle_delta *= s;
+            self.dihedral_delta *= s;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, LennardJones, PairInteraction, PairRestriction};
+    use core::{MoleculeTemplate, UnitCell};
+    use core::units;
+
+    fn testing_system() -> System {
+        let butane = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(1.5, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(2.0, 1.4, 0.0), 0.0)
+            .particle("C", Vector3D::new(3.5, 1.4, 0.2), 0.0)
+            .particle("C", Vector3D::new(4.0, 2.8, 0.4), 0.0)
+            .bond(0, 1)
+            .bond(1, 2)
+            .bond(2, 3)
+            .bond(3, 4);
+
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(butane.molecule());
+
+        let mut lj = PairInteraction::new(Box::new(LennardJones {
+            sigma: units::from(3.0, "A").unwrap(),
+            epsilon: units::from(0.5, "kJ/mol").unwrap(),
+        }), 8.0);
+        lj.set_restriction(PairRestriction::Exclude13);
+        system.add_pair_potential(("C", "C"), lj);
+
+        system.add_bond_potential(("C", "C"), Box::new(Harmonic {
+            x0: 1.5,
+            k: units::from(500.0, "kJ/mol/A^2").unwrap(),
+        }));
+        system.add_angle_potential(("C", "C", "C"), Box::new(Harmonic {
+            x0: f64::to_radians(114.0),
+            k: units::from(500.0, "kJ/mol/rad^2").unwrap(),
+        }));
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x8a, 0x1f, 0x64, 0xd3, 0x20, 0xbe, 0x57, 0x09,
+            0xf1, 0x4c, 0x92, 0x3e, 0xa7, 0x6d, 0x15, 0xc8,
+        ])
+    }
+
+    #[test]
+    fn frame() {
+        let frame = Frame::new(
+            Vector3D::new(1.5, 0.0, 0.0),
+            Vector3D::new(0.0, 0.0, 0.0),
+            Vector3D::new(-0.5, 1.4, 0.0),
+        ).unwrap();
+        let position = Vector3D::new(2.0, -1.0, 1.2);
+        let (r, theta, phi) = frame.internal(position);
+        assert_relative_eq!(r, f64::sqrt(0.25 + 1.0 + 1.44), epsilon = 1e-12);
+        let new = frame.cartesian(r, theta, phi);
+        for i in 0..3 {
+            assert_relative_eq!(new[i], position[i], epsilon = 1e-12);
+        }
+
+        assert!(Frame::new(
+            Vector3D::new(2.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(0.0, 0.0, 0.0),
+        ).is_none());
+    }
+
+    #[test]
+    fn fixed_bonds() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut regrow = PartialRegrow::new(0.0, 0.0, f64::to_radians(60.0), None);
+        regrow.setup(&system);
+        let mut rng = rng();
+        for _ in 0..10 {
+            if !regrow.prepare(&mut system, &mut rng) {
+                continue;
+            }
+            // Bond lengths and angles are not changed
+            assert_relative_eq!(regrow.log_jacobian, 0.0, epsilon = 1e-9);
+            let old_bonds = system.energy_evaluator().bonds();
+            let old_angles = system.energy_evaluator().angles();
+
+            let old_energy = system.potential_energy();
+            let cost = regrow.cost(&system, 1.0, &mut cache);
+            regrow.apply(&mut system);
+            cache.update(&mut system);
+            assert_relative_eq!(cost, system.potential_energy() - old_energy, epsilon = 1e-9);
+            assert_relative_eq!(cache.energy(), system.potential_energy(), epsilon = 1e-9);
+
+            assert_relative_eq!(system.energy_evaluator().bonds(), old_bonds, epsilon = 1e-9);
+            assert_relative_eq!(system.energy_evaluator().angles(), old_angles, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn regrow() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let angle_delta = f64::to_radians(10.0);
+        let mut regrow = PartialRegrow::new(0.1, angle_delta, f64::to_radians(60.0), None);
+        regrow.setup(&system);
+        let mut rng = rng();
+        let mut moved = 0;
+        for _ in 0..10 {
+            let old_positions = system.particles().position.to_vec();
+            if !regrow.prepare(&mut system, &mut rng) {
+                continue;
+            }
+            moved += 1;
+
+            // The first particles of the chain are kept at their positions
+            let kept = (0..5).filter(|&i| regrow.newpos[i] == old_positions[i]).count();
+            assert!(kept >= 3);
+
+            let old_energy = system.potential_energy();
+            let cost = regrow.cost(&system, 1.0, &mut cache);
+            regrow.apply(&mut system);
+            cache.update(&mut system);
+            let delta = system.potential_energy() - old_energy;
+            assert_relative_eq!(cost, delta - regrow.log_jacobian, epsilon = 1e-9);
+        }
+        assert!(moved > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "dihedral delta must be positive in PartialRegrow move")]
+    fn zero_dihedral_delta() {
+        let _ = PartialRegrow::new(0.1, 0.1, 0.0, None);
+    }
+}
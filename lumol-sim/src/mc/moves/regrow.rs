@@ -0,0 +1,518 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+use rand::distributions::{Normal, Distribution};
+
+use std::collections::VecDeque;
+use std::f64;
+use std::f64::consts::PI;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::select_molecule;
+
+use core::consts::K_BOLTZMANN;
+use core::{EnergyCache, EnergyEvaluator, System, MoleculeHash, Vector3D};
+
+/// Configurational-bias Monte Carlo move, regrowing a part of a flexible
+/// molecule segment by segment [1].
+///
+/// A random particle of the molecule is chosen as the root of the growth,
+/// and the particles are ordered following the bonds starting from this
+/// root. A random number of particles is kept at their positions, and the
+/// other particles are removed and grown again one at the time, at a fixed
+/// distance of the already grown particle they are bonded to. For each
+/// particle, `trials` positions are generated in random directions, and one
+/// of them is selected with a probability proportional to its Boltzmann
+/// factor. The move is accepted using the ratio of the Rosenbluth weights of
+/// the new and old configurations, which makes it possible to sample dense
+/// systems of chain molecules.
+///
+/// The trial energies include the pairs interactions and the bonds, angles
+/// and dihedral angles potentials. The other energy terms (impropers,
+/// Urey-Bradley, CMAP, electrostatic and global potentials) are only used in
+/// the acceptance criterion. The temperature of the move must be the same as
+/// the temperature of the Monte Carlo propagator. Molecules with holonomic
+/// constraints or virtual sites are not supported.
+///
+/// [1] Siepmann, J. I. & Frenkel, D. *Configurational bias Monte Carlo: a
+/// new sampling scheme for flexible chains*, Mol. Phys. **75** (1992).
+pub struct Regrow {
+    /// Hash of molecule to regrow. `None` means all molecules.
+    hash: Option<MoleculeHash>,
+    /// Inverse temperature of the move
+    beta: f64,
+    /// Number of trial positions for each particle
+    trials: usize,
+    /// Index of the molecule to regrow
+    molid: usize,
+    /// New positions of the particles in the regrown molecule
+    newpos: Vec<Vector3D>,
+    /// Logarithm of the ratio of the Rosenbluth weights of the new and old
+    /// configurations
+    log_weights: f64,
+    /// Energy difference between the new and old configurations, as computed
+    /// during the growth
+    delta_trial: f64,
+    /// Normal distribution, for generation of the growth directions
+    normal: Normal,
+}
+
+impl Regrow {
+    /// Create a new `Regrow` move at the given `temperature`, using `trials`
+    /// trial positions for each regrown particle. This move will apply to the
+    /// molecules with the given `hash`, or all molecules if `hash` is `None`.
+    pub fn new<H: Into<Option<MoleculeHash>>>(temperature: f64, trials: usize, hash: H) -> Regrow {
+        assert!(temperature > 0.0, "temperature must be positive in Regrow move");
+        assert!(trials > 0, "the number of trials must be positive in Regrow move");
+        Regrow {
+            hash: hash.into(),
+            beta: 1.0 / (K_BOLTZMANN * temperature),
+            trials: trials,
+            molid: usize::MAX,
+            newpos: Vec::new(),
+            log_weights: 0.0,
+            delta_trial: 0.0,
+            normal: Normal::new(0.0, 1.0),
+        }
+    }
+
+    /// Get a random unit vector, uniformly distributed on the unit sphere
+    /// (or on the unit circle in the xy plane for two-dimensional systems).
+    fn random_direction(&self, system: &System, rng: &mut RngCore) -> Vector3D {
+        if system.dimensions() == 2 {
+            let angle = rng.gen_range(0.0, 2.0 * PI);
+            return Vector3D::new(f64::cos(angle), f64::sin(angle), 0.0);
+        }
+        // Getting values from a 3D normal distribution gives an uniform
+        // distribution on the unit sphere.
+        return Vector3D::new(
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+        ).normalized();
+    }
+
+    /// Grow the particles of the molecule in `growth` order, starting from
+    /// the `positions` of the kept particles, and return the logarithm of the
+    /// Rosenbluth weight and the energy of the grown particles.
+    ///
+    /// If `old` is `true`, the current positions of the particles are used
+    /// as the first trial position and always selected, to compute the
+    /// Rosenbluth weight of the old configuration. Else the selected trial
+    /// positions are written in `positions`.
+    fn grow(
+        &self,
+        system: &System,
+        growth: &Growth,
+        positions: &mut [Vector3D],
+        old: bool,
+        rng: &mut RngCore,
+    ) -> (f64, f64) {
+        let mut placed = vec![true; positions.len()];
+        for &(particle, _, _) in &growth.segments {
+            placed[particle] = false;
+        }
+
+        let mut log_weight = 0.0;
+        let mut energy = 0.0;
+        let mut trials = Vec::with_capacity(self.trials);
+        let mut energies = Vec::with_capacity(self.trials);
+        for &(particle, parent, length) in &growth.segments {
+            trials.clear();
+            if old {
+                trials.push(positions[particle]);
+            }
+            while trials.len() < self.trials {
+                let direction = self.random_direction(system, rng);
+                trials.push(positions[parent] + length * direction);
+            }
+
+            energies.clear();
+            for trial in &trials {
+                energies.push(growth.energy(system, positions, &placed, particle, trial));
+            }
+
+            // Use the log-sum-exp trick to prevent overflow in the weights
+            let max = energies.iter()
+                .map(|&e| -self.beta * e)
+                .fold(f64::NEG_INFINITY, f64::max);
+            if max == f64::NEG_INFINITY {
+                // All the trial positions have infinite energy
+                return (f64::NEG_INFINITY, f64::INFINITY);
+            }
+            let boltzmann = energies.iter()
+                .map(|&e| f64::exp(-self.beta * e - max))
+                .collect::<Vec<_>>();
+            let sum = boltzmann.iter().sum::<f64>();
+            log_weight += max + f64::ln(sum / self.trials as f64);
+
+            let selected = if old {
+                0
+            } else {
+                let mut target = sum * rng.gen::<f64>();
+                let mut selected = boltzmann.len() - 1;
+                for (i, &weight) in boltzmann.iter().enumerate() {
+                    if target < weight {
+                        selected = i;
+                        break;
+                    }
+                    target -= weight;
+                }
+                selected
+            };
+
+            positions[particle] = trials[selected];
+            placed[particle] = true;
+            energy += energies[selected];
+        }
+        return (log_weight, energy);
+    }
+}
+
+impl MCMove for Regrow {
+    fn describe(&self) -> &str {
+        "configurational-bias regrowth"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::Particles
+    }
+
+    fn setup(&mut self, _: &System) {}
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if let Some(id) = select_molecule(system, self.hash, rng) {
+            self.molid = id;
+        } else {
+            warn!("Can not regrow molecule: no molecule of this type in the system.");
+            return false;
+        }
+
+        let size = system.molecule(self.molid).size();
+        if size < 2 {
+            warn_once!("Can not regrow molecules containing a single particle.");
+            return false;
+        }
+
+        let root = rng.gen_range(0, size);
+        let growth = match Growth::new(system, self.molid, root) {
+            Some(growth) => growth,
+            None => {
+                warn_once!("Can not regrow molecules with particles not connected by bonds.");
+                return false;
+            }
+        };
+        let kept = rng.gen_range(1, size);
+        let growth = growth.keep(kept);
+
+        let old_positions = system.molecule(self.molid).particles().position.to_vec();
+
+        let mut positions = old_positions.clone();
+        let (new_weight, new_energy) = self.grow(system, &growth, &mut positions, false, rng);
+        self.newpos = positions;
+
+        let mut positions = old_positions;
+        let (old_weight, old_energy) = self.grow(system, &growth, &mut positions, true, rng);
+
+        self.log_weights = new_weight - old_weight;
+        self.delta_trial = new_energy - old_energy;
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        if self.log_weights == f64::NEG_INFINITY {
+            // The move will be rejected, there is no need to compute the
+            // energy and to update the cache
+            return f64::INFINITY;
+        }
+        let delta = cache.deform_molecule_cost(system, self.molid, &self.newpos);
+        // The trial energies are already accounted for in the weights
+        return beta * (delta - self.delta_trial) - self.log_weights;
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let mut molecule = system.molecule_mut(self.molid);
+        for (position, newpos) in soa_zip!(molecule.particles_mut(), [mut position], &self.newpos) {
+            *position = *newpos;
+        }
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+
+    fn temperature(&self) -> Option<f64> {
+        Some(1.0 / (K_BOLTZMANN * self.beta))
+    }
+}
+
+/// Order in which the particles of a molecule are grown
+struct Growth {
+    /// Index of the molecule in the system
+    molid: usize,
+    /// Index of the first particle of the molecule in the system
+    start: usize,
+    /// Index of the last particle of the molecule in the system
+    end: usize,
+    /// Particles to grow, with the particle they are bonded to and the bond
+    /// length. The indexes are relative to the start of the molecule.
+    segments: Vec<(usize, usize, f64)>,
+}
+
+impl Growth {
+    /// Order the particles of the molecule at `molid` in `system` following
+    /// the bonds, starting at the `root` particle. This returns `None` if
+    /// some particles can not be reached using the bonds.
+    fn new(system: &System, molid: usize, root: usize) -> Option<Growth> {
+        let molecule = system.molecule(molid);
+        let indexes = molecule.indexes();
+        let (start, size) = (indexes.start, indexes.end - indexes.start);
+        let positions = molecule.particles().position;
+
+        // Sort the neighbors to get the same growth order for the old and new
+        // configurations.
+        let mut neighbors = vec![Vec::new(); size];
+        for bond in molecule.bonds() {
+            neighbors[bond.i() - start].push(bond.j() - start);
+            neighbors[bond.j() - start].push(bond.i() - start);
+        }
+        for list in &mut neighbors {
+            list.sort();
+        }
+
+        let mut visited = vec![false; size];
+        visited[root] = true;
+        let mut segments = vec![(root, usize::MAX, 0.0)];
+        let mut queue = VecDeque::new();
+        queue.push_back(root);
+        while let Some(current) = queue.pop_front() {
+            for &next in &neighbors[current] {
+                if !visited[next] {
+                    visited[next] = true;
+                    let length = (positions[next] - positions[current]).norm();
+                    segments.push((next, current, length));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if segments.len() != size {
+            return None;
+        }
+
+        Some(Growth {
+            molid: molid,
+            start: start,
+            end: start + size,
+            segments: segments,
+        })
+    }
+
+    /// Only grow the particles after the first `kept` ones
+    fn keep(mut self, kept: usize) -> Growth {
+        let _ = self.segments.drain(..kept);
+        self
+    }
+
+    /// Get the energy of the molecule `particle` at `position`, interacting
+    /// with the particles outside of the molecule and the `placed` particles
+    /// in the molecule.
+    fn energy(
+        &self,
+        system: &System,
+        positions: &[Vector3D],
+        placed: &[bool],
+        particle: usize,
+        position: &Vector3D,
+    ) -> f64 {
+        let evaluator = EnergyEvaluator::new(system);
+        let i = self.start + particle;
+        let system_positions = system.particles().position;
+
+        let mut energy = 0.0;
+        for j in (0..self.start).chain(self.end..system.size()) {
+            let r = system.cell.distance(&system_positions[j], position);
+            energy += evaluator.pair(system.bond_path(i, j), r, i, j);
+        }
+
+        let contains = |j: usize| j == i || placed[j - self.start];
+        let position_of = |j: usize| if j == i { *position } else { positions[j - self.start] };
+        for j in (self.start..self.end).filter(|&j| j != i && contains(j)) {
+            let r = system.cell.distance(&position_of(j), position);
+            energy += evaluator.pair(system.bond_path(i, j), r, i, j);
+        }
+
+        let molecule = system.molecule(self.molid);
+        for bond in molecule.bonds() {
+            let (a, b) = (bond.i(), bond.j());
+            if (a == i || b == i) && contains(a) && contains(b) {
+                let r = system.cell.distance(&position_of(a), &position_of(b));
+                energy += evaluator.bond(r, a, b);
+            }
+        }
+
+        for angle in molecule.angles() {
+            let (a, b, c) = (angle.i(), angle.j(), angle.k());
+            if (a == i || b == i || c == i) && contains(a) && contains(b) && contains(c) {
+                let theta = system.cell.angle(&position_of(a), &position_of(b), &position_of(c));
+                energy += evaluator.angle(theta, a, b, c);
+            }
+        }
+
+        for dihedral in molecule.dihedrals() {
+            let (a, b, c, d) = (dihedral.i(), dihedral.j(), dihedral.k(), dihedral.m());
+            let involved = a == i || b == i || c == i || d == i;
+            if involved && contains(a) && contains(b) && contains(c) && contains(d) {
+                let phi = system.cell.dihedral(
+                    &position_of(a), &position_of(b), &position_of(c), &position_of(d)
+                );
+                energy += evaluator.dihedral(phi, a, b, c, d);
+            }
+        }
+
+        return energy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, LennardJones, PairInteraction, PairRestriction};
+    use core::{Molecule, MoleculeTemplate, UnitCell};
+    use core::units;
+
+    fn testing_system() -> System {
+        let butane = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(1.5, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(2.0, 1.4, 0.0), 0.0)
+            .particle("C", Vector3D::new(3.5, 1.4, 0.3), 0.0)
+            .bond(0, 1)
+            .bond(1, 2)
+            .bond(2, 3);
+
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(butane.molecule());
+        let mut other: Molecule = butane.molecule();
+        for position in other.particles_mut().position {
+            *position += Vector3D::new(0.0, 0.0, 4.0);
+        }
+        system.add_molecule(other);
+
+        let mut lj = PairInteraction::new(Box::new(LennardJones {
+            sigma: units::from(3.0, "A").unwrap(),
+            epsilon: units::from(0.5, "kJ/mol").unwrap(),
+        }), 8.0);
+        lj.set_restriction(PairRestriction::Exclude13);
+        system.add_pair_potential(("C", "C"), lj);
+
+        system.add_bond_potential(("C", "C"), Box::new(Harmonic {
+            x0: 1.5,
+            k: units::from(500.0, "kJ/mol/A^2").unwrap(),
+        }));
+        system.add_angle_potential(("C", "C", "C"), Box::new(Harmonic {
+            x0: f64::to_radians(114.0),
+            k: units::from(500.0, "kJ/mol/rad^2").unwrap(),
+        }));
+        system.add_dihedral_potential(("C", "C", "C", "C"), Box::new(Harmonic {
+            x0: f64::to_radians(180.0),
+            k: units::from(10.0, "kJ/mol/rad^2").unwrap(),
+        }));
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0x72, 0x0d, 0xb3, 0x46, 0xe9, 0x18, 0x5a, 0xc4,
+            0x2f, 0x81, 0x67, 0xda, 0x0c, 0x9e, 0x35, 0xb1,
+        ])
+    }
+
+    #[test]
+    fn regrow() {
+        let mut system = testing_system();
+        let temperature = units::from(300.0, "K").unwrap();
+        let beta = 1.0 / (K_BOLTZMANN * temperature);
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut regrow = Regrow::new(temperature, 8, None);
+        regrow.setup(&system);
+        let mut rng = rng();
+        for _ in 0..20 {
+            let old_energy = system.potential_energy();
+            let old_positions = system.particles().position.to_vec();
+            assert!(regrow.prepare(&mut system, &mut rng));
+            {
+                let molecule = system.molecule(regrow.molid);
+                let start = molecule.indexes().start;
+
+                // At least one particle is kept, and the bond lengths do not
+                // change
+                let kept = (0..4).filter(|&i| regrow.newpos[i] == old_positions[start + i]);
+                assert!(kept.count() >= 1);
+                for bond in molecule.bonds() {
+                    let (i, j) = (bond.i() - start, bond.j() - start);
+                    let old = (old_positions[start + i] - old_positions[start + j]).norm();
+                    let new = (regrow.newpos[i] - regrow.newpos[j]).norm();
+                    assert_relative_eq!(old, new, epsilon = 1e-9);
+                }
+            }
+
+            // All the energy terms are included in the trial energies
+            let cost = regrow.cost(&system, beta, &mut cache);
+            assert_relative_eq!(cost, -regrow.log_weights, epsilon = 1e-6);
+
+            regrow.apply(&mut system);
+            cache.update(&mut system);
+            let delta = system.potential_energy() - old_energy;
+            assert_relative_eq!(delta, regrow.delta_trial, epsilon = 1e-6);
+            assert_relative_eq!(cache.energy(), system.potential_energy(), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn growth_order() {
+        let system = testing_system();
+        let growth = Growth::new(&system, 1, 2).unwrap();
+        assert_eq!(growth.start, 4);
+        assert_eq!(growth.end, 8);
+        let order = growth.segments.iter().map(|s| s.0).collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+        assert_eq!(growth.segments[1].1, 2);
+        assert_eq!(growth.segments[3].1, 1);
+
+        let growth = growth.keep(2);
+        let order = growth.segments.iter().map(|s| s.0).collect::<Vec<_>>();
+        assert_eq!(order, vec![3, 0]);
+
+        // Check that the trial energy of a particle with all the other
+        // particles placed is the energy of this particle
+        let positions = system.molecule(0).particles().position.to_vec();
+        let growth = Growth::new(&system, 0, 0).unwrap();
+        let energy = growth.energy(&system, &positions, &[true; 4], 3, &positions[3]);
+        let evaluator = system.energy_evaluator();
+        let mut expected = 0.0;
+        for j in (0..8).filter(|&j| j != 3) {
+            expected += evaluator.pair(system.bond_path(3, j), system.distance(3, j), 3, j);
+        }
+        expected += evaluator.bond(system.distance(2, 3), 2, 3);
+        expected += evaluator.angle(system.angle(1, 2, 3), 1, 2, 3);
+        expected += evaluator.dihedral(system.dihedral(0, 1, 2, 3), 0, 1, 2, 3);
+        assert_relative_eq!(energy, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "the number of trials must be positive in Regrow move")]
+    fn no_trials() {
+        let _ = Regrow::new(300.0, 0, None);
+    }
+}
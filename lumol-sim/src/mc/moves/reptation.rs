@@ -0,0 +1,221 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+use rand::distributions::{Normal, Distribution};
+
+use std::f64;
+use std::f64::consts::PI;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::{chain_order, select_molecule};
+
+use core::{EnergyCache, System, MoleculeHash, Vector3D};
+
+/// Monte Carlo move for the reptation (or slithering snake) of linear chain
+/// molecules.
+///
+/// One of the two ends of the chain is selected at random. The bead at the
+/// other end is removed, all the beads move by one position along the chain
+/// backbone, and a new bead is added at the selected end in a random
+/// direction. The bond length of the new bead is the bond length of the
+/// removed bead, which makes the move reversible. The chain slithers along
+/// its own contour, which relaxes the conformation of chains in dense
+/// polymer melts much faster than translations and rotations.
+///
+/// The particles keep their identity, and only their positions change. This
+/// move is meant for homopolymers, where all the beads are equivalent. It is
+/// valid for any linear chain, but the acceptance will be very low if the
+/// beads are not all the same.
+pub struct Reptation {
+    /// Hash of molecule to move. `None` means all molecules.
+    hash: Option<MoleculeHash>,
+    /// Index of the molecule to move
+    molid: usize,
+    /// New positions of the particles in the moved molecule
+    newpos: Vec<Vector3D>,
+    /// Normal distribution, for generation of the new bond direction
+    normal: Normal,
+}
+
+impl Reptation {
+    /// Create a new `Reptation` move. This move will apply to the molecules
+    /// with the given `hash`, or all molecules if `hash` is `None`.
+    pub fn new<H: Into<Option<MoleculeHash>>>(hash: H) -> Reptation {
+        Reptation {
+            hash: hash.into(),
+            molid: usize::MAX,
+            newpos: Vec::new(),
+            normal: Normal::new(0.0, 1.0),
+        }
+    }
+
+    /// Get a random unit vector, uniformly distributed on the unit sphere
+    /// (or on the unit circle in the xy plane for two-dimensional systems).
+    fn random_direction(&self, system: &System, rng: &mut RngCore) -> Vector3D {
+        if system.dimensions() == 2 {
+            let angle = rng.gen_range(0.0, 2.0 * PI);
+            return Vector3D::new(f64::cos(angle), f64::sin(angle), 0.0);
+        }
+        return Vector3D::new(
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+            self.normal.sample(rng),
+        ).normalized();
+    }
+}
+
+impl MCMove for Reptation {
+    fn describe(&self) -> &str {
+        "reptation"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::Particles
+    }
+
+    fn setup(&mut self, _: &System) {}
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if let Some(id) = select_molecule(system, self.hash, rng) {
+            self.molid = id;
+        } else {
+            warn!("Can not move molecule by reptation: no molecule of this type in the system.");
+            return false;
+        }
+
+        if system.molecule(self.molid).size() < 2 {
+            warn_once!("Can not use reptation with molecules containing a single particle.");
+            return false;
+        }
+
+        let mut order = match chain_order(system, self.molid) {
+            Some(order) => order,
+            None => {
+                warn_once!("Can not use reptation with molecules which are not linear chains.");
+                return false;
+            }
+        };
+        // Randomly select the end of the chain where the new bead is added
+        if rng.gen() {
+            order.reverse();
+        }
+
+        let positions = system.molecule(self.molid).particles().position;
+        let last = order.len() - 1;
+        let length = (positions[order[1]] - positions[order[0]]).norm();
+        let direction = self.random_direction(system, rng);
+
+        self.newpos = positions.to_vec();
+        for k in 0..last {
+            self.newpos[order[k]] = positions[order[k + 1]];
+        }
+        self.newpos[order[last]] = positions[order[last]] + length * direction;
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        return beta * cache.deform_molecule_cost(system, self.molid, &self.newpos);
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let mut molecule = system.molecule_mut(self.molid);
+        for (position, newpos) in soa_zip!(molecule.particles_mut(), [mut position], &self.newpos) {
+            *position = *newpos;
+        }
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, _: Option<f64>) {
+        // Nothing to do
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, PairInteraction, PairRestriction};
+    use core::{MoleculeTemplate, UnitCell};
+
+    fn testing_system() -> System {
+        // A chain where the particles are not in the backbone order
+        let chain = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(1.5, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(2.0, 1.4, 0.0), 0.0)
+            .particle("C", Vector3D::new(3.5, 1.4, 0.3), 0.0)
+            .bond(0, 1)
+            .bond(0, 2)
+            .bond(2, 3);
+
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(chain.molecule());
+
+        let mut pair = PairInteraction::new(Box::new(Harmonic { x0: 3.0, k: 0.1 }), 8.0);
+        pair.set_restriction(PairRestriction::Exclude12);
+        system.add_pair_potential(("C", "C"), pair);
+        system.add_bond_potential(("C", "C"), Box::new(Harmonic { x0: 1.5, k: 0.5 }));
+        return system;
+    }
+
+    #[test]
+    fn chain() {
+        let system = testing_system();
+        assert_eq!(chain_order(&system, 0), Some(vec![1, 0, 2, 3]));
+
+        let mut system = testing_system();
+        let _ = system.add_bond(1, 3);
+        assert_eq!(chain_order(&system, 0), None);
+    }
+
+    #[test]
+    fn reptation() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut reptation = Reptation::new(None);
+        reptation.setup(&system);
+        let mut rng = XorShiftRng::from_seed([
+            0x1a, 0x6e, 0x93, 0x2c, 0xf0, 0x47, 0xb5, 0x08,
+            0xd9, 0x34, 0x7b, 0xa2, 0x5f, 0xc6, 0x11, 0x8d,
+        ]);
+        for _ in 0..10 {
+            let old_energy = system.potential_energy();
+            let old_positions = system.particles().position.to_vec();
+            assert!(reptation.prepare(&mut system, &mut rng));
+
+            // All the beads except one took the position of a neighbor
+            let moved = reptation.newpos.iter().filter(|p| !old_positions.contains(p)).count();
+            assert_eq!(moved, 1);
+
+            let cost = reptation.cost(&system, 1.0, &mut cache);
+            reptation.apply(&mut system);
+            cache.update(&mut system);
+            let new_energy = system.potential_energy();
+            assert_relative_eq!(cost, new_energy - old_energy, epsilon = 1e-9);
+            assert_relative_eq!(cache.energy(), new_energy, epsilon = 1e-9);
+
+            // The bond lengths are preserved
+            let order = chain_order(&system, 0).unwrap();
+            let positions = system.particles().position;
+            let mut lengths = (0..3)
+                .map(|k| (positions[order[k + 1]] - positions[order[k]]).norm())
+                .collect::<Vec<_>>();
+            let mut old_lengths = (0..3)
+                .map(|k| (old_positions[order[k + 1]] - old_positions[order[k]]).norm())
+                .collect::<Vec<_>>();
+            lengths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            old_lengths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for (new, old) in lengths.iter().zip(&old_lengths) {
+                assert_relative_eq!(new, old, epsilon = 1e-9);
+            }
+        }
+    }
+}
@@ -5,20 +5,30 @@ use rand::RngCore;
 use rand::distributions::{Range, Distribution};
 
 use std::f64;
-use std::mem;
 
 use super::{MCDegreeOfFreedom, MCMove};
-
-use core::{Configuration, EnergyCache, System, Matrix3};
-
-/// Monte Carlo move that changes the size of the simulation cell
+use super::scale_molecules;
+
+use core::{Configuration, EnergyCache, System, Matrix3, Snapshot};
+
+/// Monte Carlo move that changes the size of the simulation cell.
+///
+/// The cell is scaled isotropically, and the centers of mass of the molecules
+/// are scaled with it. The volume change can be sampled uniformly in volume
+/// (with [`Resize::new`](#method.new)), or uniformly in the logarithm of the
+/// volume (with [`Resize::logarithmic`](#method.logarithmic)). The latter
+/// gives volume changes proportional to the volume, which is more efficient
+/// when the volume changes a lot during the simulation.
 pub struct Resize {
-    /// Delta for translation of the box length
+    /// Maximal change of the volume, or of the logarithm of the volume when
+    /// using logarithmic sampling
     delta: f64,
+    /// Are the volume changes sampled uniformly in ln(V)?
+    logarithmic: bool,
     /// Sampling range for volume scaling
     range: Range<f64>,
     /// Configuration before applying changes to the simulation cell
-    previous: Configuration,
+    previous: Snapshot,
     /// target pressure
     pressure: f64,
     /// largest cutoff diameter of potentials in `Interactions`
@@ -32,12 +42,21 @@ impl Resize {
         assert!(delta > 0.0, "delta must be positive in Resize move");
         Resize {
             delta: delta,
+            logarithmic: false,
             range: Range::new(-delta, delta),
-            previous: Configuration::new(),
+            previous: Configuration::new().snapshot(),
             pressure: pressure,
             maximum_cutoff: None,
         }
     }
+
+    /// Create a new `Resize` move, with target pressure `pressure`, sampling
+    /// the logarithm of the volume with a maximum displacement of `delta`.
+    pub fn logarithmic(pressure: f64, delta: f64) -> Resize {
+        let mut resize = Resize::new(pressure, delta);
+        resize.logarithmic = true;
+        return resize;
+    }
 }
 
 impl MCMove for Resize {
@@ -64,17 +83,29 @@ impl MCMove for Resize {
         let delta = self.range.sample(rng);
 
         // Store the previous configuration
-        self.previous = (**system).clone();
+        self.previous = system.snapshot();
 
         let volume = system.volume();
-        let scaling_factor = f64::cbrt((volume + delta) / volume);
-        // Change the simulation cell
-        system.cell.scale_mut(Matrix3::one() * scaling_factor);
+        let ratio = if self.logarithmic {
+            f64::exp(delta)
+        } else {
+            (volume + delta) / volume
+        };
+        // Change the simulation cell, keeping the third cell vector unchanged
+        // for two-dimensional systems.
+        let scaling = if system.dimensions() == 2 {
+            let factor = f64::sqrt(ratio);
+            Matrix3::new([[factor, 0.0, 0.0], [0.0, factor, 0.0], [0.0, 0.0, 1.0]])
+        } else {
+            Matrix3::one() * f64::cbrt(ratio)
+        };
+        system.cell.scale_mut(scaling);
         // Check the radius of the smallest inscribed sphere and compare to the
         // cut off distance.
         // Abort simulation when box gets smaller than twice the cutoff radius.
         if let Some(maximum_cutoff) = self.maximum_cutoff {
-            if system.cell.lengths().iter().any(|&d| 0.5 * d <= maximum_cutoff) {
+            let lengths = system.cell.lengths();
+            if lengths.iter().take(system.dimensions()).any(|&d| 0.5 * d <= maximum_cutoff) {
                 panic!(
                     "Tried to decrease the cell size in Monte Carlo Resize move \
                      but the new size is smaller than the interactions cut off \
@@ -84,31 +115,28 @@ impl MCMove for Resize {
             }
         };
 
-        let cell = system.cell;
-        for mut molecule in system.molecules_mut() {
-            // We don't want to change the intramolecular distances so we
-            // compute the translation vector of the center-of-mass (com) of a
-            // molecule and apply it to all its particles. Note that to do
-            // this, the com of a molecule *always* has to reside inside the
-            // simulation cell.
-            let old_com = molecule.as_ref().center_of_mass();
-            let frac_com = self.previous.cell.fractional(&old_com);
-            let delta_com = cell.cartesian(&frac_com) - old_com;
-            for position in molecule.particles_mut().position.iter_mut() {
-                *position += delta_com;
-            }
-        }
+        scale_molecules(system, self.previous.cell());
         true
     }
 
     fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
         let delta_energy = cache.move_all_molecules_cost(system);
         let new_volume = system.volume();
-        let old_volume = self.previous.cell.volume();
+        let old_volume = if system.dimensions() == 2 {
+            self.previous.cell().area()
+        } else {
+            self.previous.cell().volume()
+        };
         let delta_volume = new_volume - old_volume;
+        // Sampling uniformly in ln(V) adds a factor V to the probability of
+        // the new configuration
+        let mut count = system.molecules().count() as f64;
+        if self.logarithmic {
+            count += 1.0;
+        }
         // Build and return the cost function.
         beta * (delta_energy + self.pressure * delta_volume)
-            - (system.molecules().count() as f64) * f64::ln(new_volume / old_volume)
+            - count * f64::ln(new_volume / old_volume)
     }
 
     fn apply(&mut self, _: &mut System) {
@@ -116,8 +144,7 @@ impl MCMove for Resize {
     }
 
     fn restore(&mut self, system: &mut System) {
-        // Exchange configurations
-        mem::swap(&mut **system, &mut self.previous)
+        system.restore(&self.previous);
     }
 
     fn update_amplitude(&mut self, scaling_factor: Option<f64>) {
@@ -127,3 +154,60 @@ impl MCMove for Resize {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 0.1 }), 5.0);
+        system.add_pair_potential(("Ar", "Ar"), pair);
+        return system;
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([
+            0xa4, 0x19, 0x7b, 0x3e, 0x58, 0xc2, 0x01, 0x9d,
+            0x6f, 0xe7, 0x22, 0x84, 0x3b, 0x90, 0x5c, 0x17,
+        ])
+    }
+
+    fn check_cost(mut resize: Resize, count: f64) {
+        let mut system = testing_system();
+        let old_energy = system.potential_energy();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let beta = 3.0;
+        let pressure = 1e-3;
+        resize.setup(&system);
+        assert!(resize.prepare(&mut system, &mut rng()));
+
+        let new_volume = system.volume();
+        let delta_energy = system.potential_energy() - old_energy;
+        let expected = beta * (delta_energy + pressure * (new_volume - 8000.0))
+            - count * f64::ln(new_volume / 8000.0);
+        assert_relative_eq!(resize.cost(&system, beta, &mut cache), expected, max_relative = 1e-9);
+
+        resize.restore(&mut system);
+        assert_eq!(system.volume(), 8000.0);
+    }
+
+    #[test]
+    fn linear() {
+        check_cost(Resize::new(1e-3, 100.0), 2.0);
+    }
+
+    #[test]
+    fn logarithmic() {
+        check_cost(Resize::logarithmic(1e-3, 0.01), 3.0);
+    }
+}
@@ -71,13 +71,18 @@ impl MCMove for Rotate {
             return false;
         }
 
-        // Getting values from a 3D normal distribution gives an uniform
-        // distribution on the unit sphere.
-        let axis = Vector3D::new(
-            self.axis_rng.sample(rng),
-            self.axis_rng.sample(rng),
-            self.axis_rng.sample(rng),
-        ).normalized();
+        let axis = if system.dimensions() == 2 {
+            // Two-dimensional molecules can only rotate around the z axis
+            Vector3D::new(0.0, 0.0, 1.0)
+        } else {
+            // Getting values from a 3D normal distribution gives an uniform
+            // distribution on the unit sphere.
+            Vector3D::new(
+                self.axis_rng.sample(rng),
+                self.axis_rng.sample(rng),
+                self.axis_rng.sample(rng),
+            ).normalized()
+        };
         let theta = self.range.sample(rng);
 
         // store positions of selected molecule
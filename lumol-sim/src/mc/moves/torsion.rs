@@ -0,0 +1,287 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{Rng, RngCore};
+use rand::distributions::{Range, Distribution};
+
+use std::f64;
+use std::usize;
+
+use super::{MCDegreeOfFreedom, MCMove};
+use super::select_molecule;
+
+use core::{EnergyCache, System, MoleculeHash, Matrix3, Vector3D};
+
+/// Monte Carlo move rotating a part of a flexible molecule around one of its
+/// bonds.
+///
+/// A random rotatable bond is selected in the molecule, and the smaller of
+/// the two parts of the molecule on each side of this bond is rotated
+/// around the bond axis by a random angle. A bond is rotatable if it is not
+/// part of a ring and if there are at least two particles on each side of
+/// the bond. This move changes the dihedral angles around the bond, while
+/// keeping all the bond lengths and bond angles fixed.
+pub struct Torsion {
+    /// Hash of molecule to change. `None` means all molecules.
+    hash: Option<MoleculeHash>,
+    /// Index of the molecule to change
+    molid: usize,
+    /// New positions of the particles in the changed molecule
+    newpos: Vec<Vector3D>,
+    /// Maximum value of the rotation angle
+    theta: f64,
+    /// Range distribution, for generation of the angle
+    range: Range<f64>,
+}
+
+impl Torsion {
+    /// Create a new `Torsion` move, with maximum rotation angle of `theta`.
+    /// This move will apply to the molecules with the given `hash`, or all
+    /// molecules if `hash` is `None`.
+    pub fn new<H: Into<Option<MoleculeHash>>>(theta: f64, hash: H) -> Torsion {
+        assert!(theta > 0.0, "theta must be positive in Torsion move");
+        Torsion {
+            hash: hash.into(),
+            molid: usize::MAX,
+            newpos: Vec::new(),
+            theta: theta,
+            range: Range::new(-theta, theta),
+        }
+    }
+}
+
+/// A bond around which a part of a molecule can rotate. All the indexes are
+/// relative to the start of the molecule.
+#[derive(Debug, PartialEq)]
+struct RotatableBond {
+    /// Particle on the rotation axis, on the side of the rotated particles
+    center: usize,
+    /// Other particle on the rotation axis
+    other: usize,
+    /// Particles rotated around the bond
+    moved: Vec<usize>,
+}
+
+/// Get all the rotatable bonds in the molecule at `molid`
+fn rotatable_bonds(system: &System, molid: usize) -> Vec<RotatableBond> {
+    let molecule = system.molecule(molid);
+    let start = molecule.indexes().start;
+    let size = molecule.size();
+
+    let mut neighbors = vec![Vec::new(); size];
+    for bond in molecule.bonds() {
+        neighbors[bond.i() - start].push(bond.j() - start);
+        neighbors[bond.j() - start].push(bond.i() - start);
+    }
+
+    let mut rotatable = Vec::new();
+    for bond in molecule.bonds() {
+        let (i, j) = (bond.i() - start, bond.j() - start);
+
+        // Find all the particles connected to j without using the i-j bond
+        let mut visited = vec![false; size];
+        visited[j] = true;
+        let mut stack = vec![j];
+        while let Some(current) = stack.pop() {
+            for &next in &neighbors[current] {
+                if !visited[next] && !(current == j && next == i) {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        if visited[i] {
+            // The bond is part of a ring
+            continue;
+        }
+
+        let side_j = visited.iter().filter(|&&inside| inside).count();
+        if side_j < 2 || size - side_j < 2 {
+            continue;
+        }
+
+        // Rotate the smallest part of the molecule
+        let (center, other, inside) = if 2 * side_j <= size {
+            (j, i, true)
+        } else {
+            (i, j, false)
+        };
+        let moved = (0..size).filter(|&k| visited[k] == inside).collect();
+        rotatable.push(RotatableBond {
+            center: center,
+            other: other,
+            moved: moved,
+        });
+    }
+    return rotatable;
+}
+
+impl MCMove for Torsion {
+    fn describe(&self) -> &str {
+        "torsion rotation"
+    }
+
+    fn degrees_of_freedom(&self) -> MCDegreeOfFreedom {
+        MCDegreeOfFreedom::Particles
+    }
+
+    fn setup(&mut self, _: &System) {}
+
+    fn prepare(&mut self, system: &mut System, rng: &mut RngCore) -> bool {
+        if system.dimensions() == 2 {
+            warn_once!("Can not use torsion rotations in two-dimensional systems.");
+            return false;
+        }
+
+        if let Some(id) = select_molecule(system, self.hash, rng) {
+            self.molid = id;
+        } else {
+            warn!("Can not rotate torsion: no molecule of this type in the system.");
+            return false;
+        }
+
+        let bonds = rotatable_bonds(system, self.molid);
+        let bond = match rng.choose(&bonds) {
+            Some(bond) => bond,
+            None => {
+                warn_once!(
+                    "Can not use torsion rotations with molecules without rotatable bonds."
+                );
+                return false;
+            }
+        };
+
+        self.newpos = system.molecule(self.molid).particles().position.to_vec();
+        let center = self.newpos[bond.center];
+        let axis = (center - self.newpos[bond.other]).normalized();
+        let rotation = Matrix3::rotation(&axis, self.range.sample(rng));
+        for &k in &bond.moved {
+            self.newpos[k] = center + rotation * (self.newpos[k] - center);
+        }
+        return true;
+    }
+
+    fn cost(&self, system: &System, beta: f64, cache: &mut EnergyCache) -> f64 {
+        return beta * cache.deform_molecule_cost(system, self.molid, &self.newpos);
+    }
+
+    fn apply(&mut self, system: &mut System) {
+        let mut molecule = system.molecule_mut(self.molid);
+        for (position, newpos) in soa_zip!(molecule.particles_mut(), [mut position], &self.newpos) {
+            *position = *newpos;
+        }
+    }
+
+    fn restore(&mut self, _: &mut System) {
+        // Nothing to do
+    }
+
+    fn update_amplitude(&mut self, scaling_factor: Option<f64>) {
+        if let Some(s) = scaling_factor {
+            if (s * self.theta).abs().to_degrees() <= 180.0 {
+                self.theta *= s;
+                self.range = Range::new(-self.theta, self.theta);
+            } else {
+                warn_once!(
+                    "Tried to increase the maximum amplitude for torsions to more than 180°."
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use core::energy::{Harmonic, LennardJones, PairInteraction, PairRestriction};
+    use core::{MoleculeTemplate, UnitCell};
+    use core::units;
+
+    fn testing_system() -> System {
+        let pentane = MoleculeTemplate::new()
+            .particle("C", Vector3D::new(0.0, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(1.5, 0.0, 0.0), 0.0)
+            .particle("C", Vector3D::new(2.0, 1.4, 0.0), 0.0)
+            .particle("C", Vector3D::new(3.5, 1.4, 0.2), 0.0)
+            .particle("C", Vector3D::new(4.0, 2.8, 0.4), 0.0)
+            .bond(0, 1)
+            .bond(1, 2)
+            .bond(2, 3)
+            .bond(3, 4);
+
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(pentane.molecule());
+
+        let mut lj = PairInteraction::new(Box::new(LennardJones {
+            sigma: units::from(3.0, "A").unwrap(),
+            epsilon: units::from(0.5, "kJ/mol").unwrap(),
+        }), 8.0);
+        lj.set_restriction(PairRestriction::Exclude13);
+        system.add_pair_potential(("C", "C"), lj);
+
+        system.add_bond_potential(("C", "C"), Box::new(Harmonic {
+            x0: 1.5,
+            k: units::from(500.0, "kJ/mol/A^2").unwrap(),
+        }));
+        system.add_angle_potential(("C", "C", "C"), Box::new(Harmonic {
+            x0: f64::to_radians(114.0),
+            k: units::from(500.0, "kJ/mol/rad^2").unwrap(),
+        }));
+        return system;
+    }
+
+    #[test]
+    fn rotatable() {
+        let system = testing_system();
+        let bonds = rotatable_bonds(&system, 0);
+        assert_eq!(bonds.len(), 2);
+        assert!(bonds.contains(&RotatableBond { center: 1, other: 2, moved: vec![0, 1] }));
+        assert!(bonds.contains(&RotatableBond { center: 3, other: 2, moved: vec![3, 4] }));
+
+        // Bonds in a ring can not rotate
+        let mut system = testing_system();
+        let _ = system.add_bond(1, 3);
+        let bonds = rotatable_bonds(&system, 0);
+        assert!(bonds.is_empty());
+    }
+
+    #[test]
+    fn torsion() {
+        let mut system = testing_system();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut torsion = Torsion::new(f64::to_radians(90.0), None);
+        torsion.setup(&system);
+        let mut rng = XorShiftRng::from_seed([
+            0x47, 0xe2, 0x19, 0x8c, 0x5d, 0xa0, 0x3b, 0xf6,
+            0x72, 0x0e, 0xc9, 0x64, 0x2a, 0xd5, 0x81, 0x1f,
+        ]);
+        for _ in 0..10 {
+            let old_energy = system.potential_energy();
+            let old_bonds = system.energy_evaluator().bonds();
+            let old_angles = system.energy_evaluator().angles();
+            assert!(torsion.prepare(&mut system, &mut rng));
+
+            let cost = torsion.cost(&system, 1.0, &mut cache);
+            torsion.apply(&mut system);
+            cache.update(&mut system);
+            let new_energy = system.potential_energy();
+            assert_relative_eq!(cost, new_energy - old_energy, epsilon = 1e-9);
+            assert_relative_eq!(cache.energy(), new_energy, epsilon = 1e-9);
+
+            // Bond lengths and angles are not changed
+            assert_relative_eq!(system.energy_evaluator().bonds(), old_bonds, epsilon = 1e-9);
+            assert_relative_eq!(system.energy_evaluator().angles(), old_angles, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "theta must be positive in Torsion move")]
+    fn negative_theta() {
+        let _ = Torsion::new(-1.0, None);
+    }
+}
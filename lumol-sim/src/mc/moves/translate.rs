@@ -85,12 +85,12 @@ impl MCMove for Translate {
             return false;
         }
 
-        // Create random displacement vector.
-        let delta = Vector3D::new(
-            self.range.sample(rng),
-            self.range.sample(rng),
-            self.range.sample(rng)
-        );
+        // Create random displacement vector, staying in the xy plane for
+        // two-dimensional systems.
+        let x = self.range.sample(rng);
+        let y = self.range.sample(rng);
+        let z = if system.dimensions() == 2 { 0.0 } else { self.range.sample(rng) };
+        let delta = Vector3D::new(x, y, z);
 
         // Generate displaced coordinates
         // Note that this may move a particles' center-of-mass (com) out of
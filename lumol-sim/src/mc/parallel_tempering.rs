@@ -0,0 +1,508 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Parallel tempering (temperature replica exchange) Monte Carlo
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rayon::prelude::*;
+
+use core::consts::K_BOLTZMANN;
+use core::{DegreesOfFreedom, System};
+
+use checkpoint::{Checkpoint, CheckpointError};
+use output::Output;
+use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
+use super::MonteCarlo;
+
+/// A single replica of the system
+struct Replica {
+    /// Identifier of this replica, i.e. the index of the temperature it
+    /// started at
+    id: usize,
+    /// The system of this replica
+    system: System,
+}
+
+/// Parallel tempering Monte Carlo propagator, also known as temperature
+/// replica exchange Monte Carlo.
+///
+/// Multiple copies of the system, called replicas, are simulated in
+/// parallel with Monte Carlo propagators at different temperatures, each one
+/// in a separate thread. Every `every` steps, exchanges of configurations
+/// between neighboring temperatures $T_i$ and $T_j$ are attempted, and
+/// accepted with the Metropolis probability
+///
+/// $$ P = \min \left(1, \exp \left[ \left( \frac{1}{k_B T_i} - \frac{1}{k_B
+/// T_j} \right) (U_i - U_j) \right] \right) $$
+///
+/// where $U_i$ and $U_j$ are the potential energies of the replicas.
+/// Exchanges between even and odd pairs of neighbors are attempted
+/// alternately.
+///
+/// The Monte Carlo propagators always stay at the same temperature, and only
+/// the configurations are exchanged. This means that moves depending on the
+/// temperature (such as [`Regrow`] or [`HybridMD`]) can be used, as long as
+/// they are created with the temperature of their propagator.
+///
+/// The system given to the simulation is always the one at the lowest
+/// temperature, so that all the outputs of the simulation sample the
+/// canonical ensemble at this temperature. Outputs for the systems at each
+/// temperature can be added with [`add_output`](#method.add_output), and the
+/// sequence of exchanges can be written to a file with
+/// [`set_output`](#method.set_output) to follow each replica through the
+/// temperatures.
+///
+/// [`Regrow`]: struct.Regrow.html
+/// [`HybridMD`]: struct.HybridMD.html
+pub struct ParallelTempering {
+    /// Replicas, ordered by increasing temperature
+    replicas: Vec<Replica>,
+    /// Monte Carlo propagators, ordered by increasing temperature
+    propagators: Vec<MonteCarlo>,
+    /// Outputs of the systems at each temperature, with their frequency
+    outputs: Vec<Vec<(Box<Output>, u64)>>,
+    /// Number of steps between exchange attempts
+    every: u64,
+    /// Number of steps since the beginning of the simulation
+    steps: u64,
+    /// Index of the first neighbor pair for the next exchange attempt,
+    /// alternating between 0 and 1
+    offset: usize,
+    /// Number of attempted exchanges between the temperatures `i` and `i + 1`
+    attempted: Vec<u64>,
+    /// Number of accepted exchanges between the temperatures `i` and `i + 1`
+    accepted: Vec<u64>,
+    /// Random number generator for the exchanges
+    rng: XorShiftRng,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl ParallelTempering {
+    /// Create a new parallel tempering propagator, attempting exchanges
+    /// every `every` steps. Replicas are added with
+    /// [`add_replica`](#method.add_replica).
+    pub fn new(every: u64) -> ParallelTempering {
+        assert!(every > 0, "The exchange frequency must be positive in parallel tempering.");
+        ParallelTempering {
+            replicas: Vec::new(),
+            propagators: Vec::new(),
+            outputs: Vec::new(),
+            every: every,
+            steps: 0,
+            offset: 0,
+            attempted: Vec::new(),
+            accepted: Vec::new(),
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+            output: None,
+        }
+    }
+
+    /// Add a replica simulated with the Monte Carlo `propagator`, at the
+    /// temperature of this propagator.
+    ///
+    /// The random number generator of each propagator should use a
+    /// different seed, to prevent correlations between the replicas.
+    ///
+    /// # Panics
+    ///
+    /// If the temperature of the `propagator` is not larger than the
+    /// temperature of the previous replica.
+    pub fn add_replica(&mut self, propagator: MonteCarlo) {
+        if let Some(last) = self.propagators.last() {
+            assert!(
+                propagator.temperature() > last.temperature(),
+                "The replicas must be added by increasing temperature in parallel tempering."
+            );
+        }
+
+        self.replicas.push(Replica {
+            id: self.replicas.len(),
+            system: System::new(),
+        });
+        self.propagators.push(propagator);
+        self.outputs.push(Vec::new());
+    }
+
+    /// Add an `output` for the system at the temperature of the replica
+    /// with index `replica`, written every `frequency` steps.
+    ///
+    /// # Panics
+    ///
+    /// If there is no replica with the index `replica`.
+    pub fn add_output(&mut self, replica: usize, output: Box<Output>, frequency: u64) {
+        assert!(
+            replica < self.outputs.len(),
+            "Invalid replica index {} in parallel tempering output.", replica
+        );
+        self.outputs[replica].push((output, frequency));
+    }
+
+    /// Set the seed of the random number generator used for the exchanges.
+    /// The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Write the exchanges to the file at `path`. The file is replaced if
+    /// it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the identifiers of the replicas currently simulated at each
+    /// temperature. The identifier of a replica is the index of the
+    /// temperature it started at.
+    pub fn replicas(&self) -> Vec<usize> {
+        self.replicas.iter().map(|replica| replica.id).collect()
+    }
+
+    /// Get the acceptance ratio of the exchanges between the temperatures
+    /// `i` and `i + 1`, for all `i`.
+    pub fn acceptance(&self) -> Vec<f64> {
+        self.attempted.iter().zip(&self.accepted).map(|(&attempted, &accepted)| {
+            if attempted == 0 {
+                0.0
+            } else {
+                accepted as f64 / attempted as f64
+            }
+        }).collect()
+    }
+
+    /// Attempt exchanges between the replicas, the system at the lowest
+    /// temperature being stored in the first replica.
+    fn exchange(&mut self, step: u64) {
+        let mut energies = self.replicas.iter()
+                                        .map(|replica| replica.system.potential_energy())
+                                        .collect::<Vec<_>>();
+
+        let mut i = self.offset;
+        while i + 1 < self.replicas.len() {
+            let j = i + 1;
+            let beta_i = 1.0 / (K_BOLTZMANN * self.propagators[i].temperature());
+            let beta_j = 1.0 / (K_BOLTZMANN * self.propagators[j].temperature());
+            let delta = (beta_i - beta_j) * (energies[i] - energies[j]);
+
+            self.attempted[i] += 1;
+            if delta >= 0.0 || self.rng.gen::<f64>() < f64::exp(delta) {
+                self.accepted[i] += 1;
+                self.replicas.swap(i, j);
+                energies.swap(i, j);
+                // The energy caches of the propagators no longer describe
+                // their systems
+                for &k in &[i, j] {
+                    self.propagators[k].init_cache(&self.replicas[k].system);
+                }
+            }
+            i += 2;
+        }
+        self.offset = 1 - self.offset;
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let mut line = step.to_string();
+            for replica in &self.replicas {
+                line.push_str(&format!(" {}", replica.id));
+            }
+            if let Err(err) = writeln!(file, "{}", line) {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+impl Propagator for ParallelTempering {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        self.propagators[0].temperature_strategy()
+    }
+
+    fn degrees_of_freedom(&self, system: &System) -> DegreesOfFreedom {
+        self.propagators[0].degrees_of_freedom(system)
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(self.replicas.len() > 1, "Parallel tempering needs at least two replicas.");
+        self.steps = 0;
+        self.offset = 0;
+        self.attempted = vec![0; self.replicas.len() - 1];
+        self.accepted = vec![0; self.replicas.len() - 1];
+
+        // All the replicas start from the same configuration
+        let replicas = self.replicas.iter_mut().zip(&mut self.propagators).zip(&mut self.outputs);
+        for ((replica, propagator), outputs) in replicas {
+            replica.system = system.clone();
+            propagator.setup(&replica.system);
+            for &mut (ref mut output, _) in outputs {
+                output.setup(&replica.system);
+            }
+        }
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let temperatures = self.propagators.iter()
+                                               .map(|mc| mc.temperature().to_string())
+                                               .collect::<Vec<_>>()
+                                               .join(" ");
+            let result = writeln!(file, "# Parallel tempering").and_then(|_| {
+                writeln!(file, "# Temperatures(K): {}", temperatures)
+            }).and_then(|_| {
+                writeln!(file, "# Step Replicas at each temperature")
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        // The system at the lowest temperature is stored in the first
+        // replica while propagating and exchanging the replicas.
+        let step = system.step;
+        mem::swap(system, &mut self.replicas[0].system);
+        for replica in &mut self.replicas {
+            replica.system.step = step;
+        }
+
+        self.replicas.par_iter_mut().zip(self.propagators.par_iter_mut()).for_each(
+            |(replica, propagator)| propagator.propagate(&mut replica.system)
+        );
+
+        self.steps += 1;
+        if self.steps % self.every == 0 {
+            self.exchange(step);
+        }
+
+        for (replica, outputs) in self.replicas.iter().zip(&mut self.outputs) {
+            for &mut (ref mut output, frequency) in outputs {
+                if step % frequency == 0 {
+                    output.write(&replica.system);
+                }
+            }
+        }
+
+        mem::swap(system, &mut self.replicas[0].system);
+    }
+
+    fn finish(&mut self, system: &System) {
+        let replicas = self.replicas.iter().zip(&mut self.propagators).zip(&mut self.outputs);
+        for (i, ((replica, propagator), outputs)) in replicas.enumerate() {
+            // The system at the lowest temperature is owned by the simulation
+            let system = if i == 0 { system } else { &replica.system };
+            propagator.finish(system);
+            for &mut (ref mut output, _) in outputs {
+                output.finish(system);
+            }
+        }
+
+        let acceptance = self.acceptance().iter()
+                                          .map(|acceptance| acceptance.to_string())
+                                          .collect::<Vec<_>>()
+                                          .join(" ");
+        info!("Acceptance ratios of parallel tempering exchanges: {}", acceptance);
+        if let Some((ref mut file, ref path)) = self.output {
+            let result = writeln!(file, "# Acceptance: {}", acceptance).and_then(|_| file.flush());
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("steps", self.steps as f64);
+        checkpoint.set_value("offset", self.offset as f64);
+        let attempted = self.attempted.iter().map(|&n| n as f64).collect::<Vec<_>>();
+        let accepted = self.accepted.iter().map(|&n| n as f64).collect::<Vec<_>>();
+        checkpoint.set_values("attempted", &attempted);
+        checkpoint.set_values("accepted", &accepted);
+        checkpoint.save_rng("rng", &self.rng);
+
+        let ids = self.replicas.iter().map(|replica| replica.id as f64).collect::<Vec<_>>();
+        checkpoint.set_values("replicas", &ids);
+        // The system at the lowest temperature is saved by the simulation
+        for (i, replica) in self.replicas.iter().enumerate().skip(1) {
+            checkpoint.section_mut(&i.to_string()).save_system(&replica.system);
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let ids = checkpoint.values("replicas")?;
+        let attempted = checkpoint.values("attempted")?;
+        let accepted = checkpoint.values("accepted")?;
+        let n_replicas = self.replicas.len();
+        if ids.len() != n_replicas || attempted.len() != n_replicas - 1 ||
+           accepted.len() != n_replicas - 1 {
+            return Err(CheckpointError::from(format!(
+                "expected {} replicas in parallel tempering checkpoint", n_replicas
+            )));
+        }
+
+        let mut sorted = ids.iter().map(|&id| id as usize).collect::<Vec<_>>();
+        sorted.sort();
+        if sorted != (0..n_replicas).collect::<Vec<_>>() {
+            return Err(CheckpointError::from(
+                "invalid replica identifiers in parallel tempering checkpoint".into()
+            ));
+        }
+
+        self.steps = checkpoint.value("steps")? as u64;
+        self.offset = checkpoint.value("offset")? as usize;
+        self.attempted = attempted.iter().map(|&n| n as u64).collect();
+        self.accepted = accepted.iter().map(|&n| n as u64).collect();
+        self.rng = checkpoint.rng("rng")?;
+
+        // All the replicas start from the system at the lowest temperature,
+        // which was restored before calling `setup`
+        let first = self.replicas[0].system.clone();
+        let replicas = self.replicas.iter_mut().zip(&mut self.propagators).zip(ids);
+        for (i, ((replica, propagator), &id)) in replicas.enumerate() {
+            replica.id = id as usize;
+            replica.system = first.clone();
+            if i != 0 {
+                checkpoint.section(&i.to_string())?.restore_system(&mut replica.system)?;
+            }
+            propagator.init_cache(&replica.system);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+    use core::energy::{Harmonic, PairInteraction};
+    use mc::Translate;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 1.0 }), 8.0);
+        system.add_pair_potential(("He", "He"), pair);
+        return system;
+    }
+
+    fn replica(temperature: f64) -> MonteCarlo {
+        let mut mc = MonteCarlo::new(temperature);
+        mc.add(Box::new(Translate::new(0.5, None)), 1.0);
+        mc.seed(temperature as u64);
+        return mc;
+    }
+
+    /// Output counting the number of times it was written
+    struct CountOutput(Arc<AtomicUsize>);
+
+    impl Output for CountOutput {
+        fn write(&mut self, _: &System) {
+            let _ = self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn exchanges() {
+        let mut system = testing_system();
+        let mut pt = ParallelTempering::new(2);
+        pt.add_replica(replica(300.0));
+        pt.add_replica(replica(400.0));
+        pt.add_replica(replica(500.0));
+        pt.setup(&system);
+
+        for _ in 0..200 {
+            pt.propagate(&mut system);
+            system.step += 1;
+        }
+        pt.finish(&system);
+
+        let mut replicas = pt.replicas();
+        replicas.sort();
+        assert_eq!(replicas, vec![0, 1, 2]);
+
+        assert_eq!(pt.attempted, vec![50, 50]);
+        for acceptance in pt.acceptance() {
+            assert!(acceptance >= 0.0 && acceptance <= 1.0);
+        }
+    }
+
+    #[test]
+    fn identical_energies() {
+        // Without interactions, the potential energy is always zero
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+
+        let mut pt = ParallelTempering::new(1);
+        pt.add_replica(replica(300.0));
+        pt.add_replica(replica(400.0));
+        pt.setup(&system);
+
+        // Exchanges between replicas with the same energy are always
+        // accepted, and there is no odd pair with two replicas
+        pt.propagate(&mut system);
+        assert_eq!(pt.replicas(), vec![1, 0]);
+        pt.propagate(&mut system);
+        assert_eq!(pt.replicas(), vec![1, 0]);
+        pt.propagate(&mut system);
+        assert_eq!(pt.replicas(), vec![0, 1]);
+        assert_eq!(pt.acceptance(), vec![1.0]);
+    }
+
+    #[test]
+    fn outputs() {
+        let mut system = testing_system();
+        let mut pt = ParallelTempering::new(1);
+        pt.add_replica(replica(300.0));
+        pt.add_replica(replica(400.0));
+
+        let low = Arc::new(AtomicUsize::new(0));
+        let high = Arc::new(AtomicUsize::new(0));
+        pt.add_output(0, Box::new(CountOutput(low.clone())), 1);
+        pt.add_output(1, Box::new(CountOutput(high.clone())), 5);
+
+        let tempfile = NamedTempFile::new().unwrap();
+        pt.set_output(tempfile.path()).unwrap();
+
+        pt.setup(&system);
+        for _ in 0..10 {
+            pt.propagate(&mut system);
+            system.step += 1;
+        }
+        pt.finish(&system);
+
+        assert_eq!(low.load(Ordering::SeqCst), 10);
+        assert_eq!(high.load(Ordering::SeqCst), 2);
+
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Parallel tempering");
+        assert_eq!(lines[2], "# Step Replicas at each temperature");
+        // Header, one line per exchange attempt and acceptance
+        assert_eq!(lines.len(), 3 + 10 + 1);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The replicas must be added by increasing temperature in parallel tempering."
+    )]
+    fn temperature_order() {
+        let mut pt = ParallelTempering::new(1);
+        pt.add_replica(replica(300.0));
+        pt.add_replica(replica(200.0));
+    }
+}
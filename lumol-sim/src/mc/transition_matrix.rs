@@ -0,0 +1,473 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Transition matrix Monte Carlo in the grand-canonical ensemble
+use std::f64;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use core::consts::K_BOLTZMANN;
+use core::{DegreesOfFreedom, EnergyCache, System};
+
+use checkpoint::{Checkpoint, CheckpointError};
+use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
+use super::{MCDegreeOfFreedom, MCMove};
+
+/// Transition matrix Monte Carlo (TMMC) for grand-canonical simulations [1].
+///
+/// TMMC estimates the probability distribution $\Pi(N)$ of the number of
+/// molecules $N$ in the grand-canonical ensemble, for $N$ between a minimal
+/// and a maximal value. For each attempted move from $N$ to $N'$, the
+/// unbiased acceptance probability $a = \min(1, e^{-c})$ of the move is
+/// added to the collection matrix $C(N \to N')$, and $1 - a$ is added to
+/// $C(N \to N)$. The transition probabilities are then
+///
+/// $$ P(N \to N') = \frac{C(N \to N')}{\sum_M C(N \to M)} $$
+///
+/// and the distribution follows from detailed balance:
+///
+/// $$ \ln \Pi(N + 1) - \ln \Pi(N) = \ln \frac{P(N \to N + 1)}{P(N + 1 \to N)} $$
+///
+/// Every `update_frequency` steps, the bias $\eta(N) = - \ln \Pi(N)$ is
+/// computed from the collection matrix, and moves are accepted with the
+/// biased probability
+///
+/// $$ P_{acc} = \min \left(1, e^{-c} e^{\eta(N') - \eta(N)} \right) $$
+///
+/// which flattens the distribution of $N$ over the sampled range. Since the
+/// collection matrix only uses the unbiased acceptance probabilities, it does
+/// not depend on the bias, and all the steps of the simulation contribute to
+/// the estimate of $\Pi(N)$. Moves leading outside of the range of $N$ are
+/// always rejected.
+///
+/// This requires to use [`InsertMolecule`] and [`DeleteMolecule`] moves
+/// together with displacement moves. The estimate of $\ln \Pi(N)$ and the
+/// collection matrix are written to the file given to
+/// [`set_output`](#method.set_output) each time the bias is updated and at
+/// the end of the simulation. $\Pi(N)$ at another chemical potential $\mu'$
+/// can be obtained by histogram reweighting: $\ln \Pi(N; \mu') = \ln \Pi(N;
+/// \mu) + \beta N (\mu' - \mu)$ up to a constant.
+///
+/// [1] J. R. Errington, J. Chem. Phys. 118, 9915 (2003).
+///
+/// [`InsertMolecule`]: struct.InsertMolecule.html
+/// [`DeleteMolecule`]: struct.DeleteMolecule.html
+pub struct TransitionMatrix {
+    /// Minimal number of molecules
+    min: usize,
+    /// Maximal number of molecules
+    max: usize,
+    /// Inverse temperature of the simulation
+    beta: f64,
+    /// List of possible Monte Carlo moves
+    moves: Vec<Box<MCMove>>,
+    /// Frequencies of the Monte Carlo moves
+    frequencies: Vec<f64>,
+    /// Cumulative frequencies of the Monte Carlo moves
+    cumulative: Vec<f64>,
+    /// Collection matrix: `collection[i]` contains the collected transitions
+    /// from `min + i` molecules to `min + i - 1`, `min + i` and `min + i + 1`
+    /// molecules.
+    collection: Vec<[f64; 3]>,
+    /// Bias of each number of molecules
+    bias: Vec<f64>,
+    /// Number of steps between updates of the bias
+    update_frequency: u64,
+    /// Number of steps since the last update of the bias
+    steps: u64,
+    /// Index of the current number of molecules
+    current: usize,
+    /// Random number generator for the moves
+    rng: XorShiftRng,
+    /// Cache for faster energy computation
+    cache: EnergyCache,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl TransitionMatrix {
+    /// Create a new transition matrix propagator sampling between `min` and
+    /// `max` molecules (both included), using the moves at the given
+    /// `temperature`.
+    pub fn new(min: usize, max: usize, temperature: f64) -> TransitionMatrix {
+        assert!(
+            min < max,
+            "The molecules range must not be empty in transition matrix Monte Carlo."
+        );
+        assert!(
+            temperature > 0.0,
+            "The temperature must be positive in transition matrix Monte Carlo."
+        );
+        let size = max - min + 1;
+        TransitionMatrix {
+            min: min,
+            max: max,
+            beta: 1.0 / (K_BOLTZMANN * temperature),
+            moves: Vec::new(),
+            frequencies: Vec::new(),
+            cumulative: Vec::new(),
+            collection: vec![[0.0; 3]; size],
+            bias: vec![0.0; size],
+            update_frequency: 10_000,
+            steps: 0,
+            current: 0,
+            rng: XorShiftRng::from_seed([
+                0x3f, 0x91, 0x0c, 0xd4, 0x6b, 0x25, 0xe8, 0x57,
+                0xa2, 0x1d, 0x7e, 0xc3, 0x48, 0xb6, 0x0f, 0x9a,
+            ]),
+            cache: EnergyCache::new(),
+            output: None,
+        }
+    }
+
+    /// Add the `mcmove` Monte Carlo move to this propagator, with frequency
+    /// `frequency`.
+    pub fn add(&mut self, mcmove: Box<MCMove>, frequency: f64) {
+        self.moves.push(mcmove);
+        self.frequencies.push(frequency);
+    }
+
+    /// Set the number of steps between updates of the bias. The default
+    /// value is 10000.
+    pub fn set_update_frequency(&mut self, frequency: u64) {
+        assert!(
+            frequency > 0,
+            "The update frequency must be positive in transition matrix Monte Carlo."
+        );
+        self.update_frequency = frequency;
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Write the distribution of the number of molecules and the collection
+    /// matrix to the file at `path`. The file is replaced if it already
+    /// exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the current estimate of the logarithm of the probability
+    /// distribution $\ln \Pi(N)$ from the collection matrix, for all the
+    /// numbers of molecules between the minimal and maximal values. The
+    /// distribution is shifted to have $\ln \Pi(N_{min}) = 0$. If a
+    /// transition was never attempted, the corresponding ratio of
+    /// probabilities is taken to be 1.
+    pub fn ln_pi(&self) -> Vec<f64> {
+        let probabilities = self.collection.iter().map(|transitions| {
+            let sum = transitions.iter().sum::<f64>();
+            if sum == 0.0 {
+                [0.0; 3]
+            } else {
+                [transitions[0] / sum, transitions[1] / sum, transitions[2] / sum]
+            }
+        }).collect::<Vec<_>>();
+
+        let mut ln_pi = vec![0.0; self.collection.len()];
+        for i in 1..ln_pi.len() {
+            let forward = probabilities[i - 1][2];
+            let backward = probabilities[i][0];
+            ln_pi[i] = ln_pi[i - 1];
+            if forward > 0.0 && backward > 0.0 {
+                ln_pi[i] += f64::ln(forward / backward);
+            }
+        }
+        return ln_pi;
+    }
+
+    /// Get the current bias $\eta(N)$ for all the numbers of molecules
+    /// between the minimal and maximal values.
+    pub fn bias(&self) -> &[f64] {
+        &self.bias
+    }
+
+    /// Write the current estimate of the distribution and the collection
+    /// matrix to the output file, if any.
+    fn write(&mut self) {
+        let min = self.min;
+        let ln_pi = self.ln_pi();
+        let collection = &self.collection;
+        if let Some((ref mut file, ref path)) = self.output {
+            // Write the whole file again
+            let result = file.seek(io::SeekFrom::Start(0)).and_then(|_| {
+                file.get_ref().set_len(0)
+            }).and_then(|_| {
+                writeln!(file, "# Transition matrix Monte Carlo")?;
+                writeln!(file, "# Molecules ln(Pi) C(N->N-1) C(N->N) C(N->N+1)")
+            }).and_then(|_| {
+                for (i, transitions) in collection.iter().enumerate() {
+                    writeln!(
+                        file, "{} {} {} {} {}",
+                        min + i, ln_pi[i], transitions[0], transitions[1], transitions[2]
+                    )?;
+                }
+                file.flush()
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Update the bias using the current collection matrix
+    fn update_bias(&mut self) {
+        self.bias = self.ln_pi().iter().map(|ln_pi| -ln_pi).collect();
+        info!("Updated transition matrix Monte Carlo bias");
+        self.write();
+    }
+
+    /// Add a transition from the current number of molecules to the number
+    /// of molecules changed by `change`, with the unbiased `acceptance`
+    /// probability, to the collection matrix.
+    fn collect(&mut self, change: isize, acceptance: f64) {
+        let transitions = &mut self.collection[self.current];
+        transitions[1] += 1.0 - acceptance;
+        match change {
+            -1 => transitions[0] += acceptance,
+            0 => transitions[1] += acceptance,
+            1 => transitions[2] += acceptance,
+            _ => {
+                warn_once!(
+                    "Moves changing the number of molecules by more than one are \
+                    not collected in transition matrix Monte Carlo."
+                );
+            }
+        }
+    }
+
+    /// Attempt the Monte Carlo move at index `i`, and update the current
+    /// number of molecules if the move is accepted.
+    fn attempt(&mut self, i: usize, system: &mut System) {
+        trace!("Selected move is '{}'", self.moves[i].describe());
+        if !self.moves[i].prepare(system, &mut self.rng) {
+            trace!("    --> Can not perform the move");
+            self.collect(0, 0.0);
+            return;
+        }
+
+        let change = self.moves[i].molecules_change();
+        let molecules = (self.min + self.current) as isize + change;
+        if molecules < self.min as isize || molecules > self.max as isize {
+            trace!("    --> Move is outside of the sampled range");
+            self.moves[i].restore(system);
+            self.collect(0, 0.0);
+            return;
+        }
+        let new = molecules as usize - self.min;
+
+        let cost = self.moves[i].cost(system, self.beta, &mut self.cache);
+        let acceptance = f64::min(1.0, f64::exp(-cost));
+        self.collect(change, acceptance);
+
+        let cost = cost - (self.bias[new] - self.bias[self.current]);
+        trace!("    --> Move cost is {}", cost);
+        if cost <= 0.0 || self.rng.gen::<f64>() < f64::exp(-cost) {
+            trace!("    --> Move was accepted");
+            self.moves[i].apply(system);
+            self.cache.update(system);
+            self.current = new;
+        } else {
+            trace!("    --> Move was rejected");
+            self.moves[i].restore(system);
+        }
+    }
+}
+
+impl Propagator for TransitionMatrix {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        TemperatureStrategy::External(1.0 / (K_BOLTZMANN * self.beta))
+    }
+
+    fn degrees_of_freedom(&self, _: &System) -> DegreesOfFreedom {
+        let mut dof = MCDegreeOfFreedom::Particles;
+        if let Some(first) = self.moves.first() {
+            dof = first.degrees_of_freedom();
+            for other in &self.moves[1..] {
+                dof = dof.combine(other.degrees_of_freedom());
+            }
+        }
+
+        match dof {
+            MCDegreeOfFreedom::Particles => DegreesOfFreedom::Particles,
+            MCDegreeOfFreedom::AllMolecules | MCDegreeOfFreedom::Molecules(_) => {
+                DegreesOfFreedom::Molecules
+            }
+        }
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !self.moves.is_empty(),
+            "No move in the transition matrix Monte Carlo simulation."
+        );
+        let sum = self.frequencies.iter().sum::<f64>();
+        let mut cumulative = 0.0;
+        self.cumulative = self.frequencies.iter().map(|frequency| {
+            cumulative += frequency / sum;
+            cumulative
+        }).collect();
+        let last = self.cumulative.len() - 1;
+        self.cumulative[last] = 1.0;
+
+        self.cache.init(system);
+        for mcmove in &mut self.moves {
+            mcmove.setup(system);
+        }
+
+        let molecules = system.molecules().count();
+        assert!(
+            self.min <= molecules && molecules <= self.max,
+            "The initial system must be inside the sampled range in transition matrix Monte Carlo."
+        );
+        self.current = molecules - self.min;
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        let probability: f64 = self.rng.gen();
+        let i = self.cumulative.iter()
+                               .position(|&frequency| probability <= frequency)
+                               .expect("Could not find a move in transition matrix moves list");
+        self.attempt(i, system);
+
+        self.steps += 1;
+        if self.steps % self.update_frequency == 0 {
+            self.update_bias();
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        self.write();
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        let collection = self.collection.iter()
+                                        .flat_map(|transitions| transitions.iter().cloned())
+                                        .collect::<Vec<_>>();
+        checkpoint.set_values("collection", &collection);
+        checkpoint.set_values("bias", &self.bias);
+        checkpoint.set_value("steps", self.steps as f64);
+        checkpoint.save_rng("rng", &self.rng);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let collection = checkpoint.values("collection")?;
+        let bias = checkpoint.values("bias")?;
+        if collection.len() != 3 * self.bias.len() || bias.len() != self.bias.len() {
+            return Err(CheckpointError::from(format!(
+                "expected {} numbers of molecules in transition matrix checkpoint",
+                self.bias.len()
+            )));
+        }
+
+        self.collection = collection.chunks(3).map(|chunk| {
+            [chunk[0], chunk[1], chunk[2]]
+        }).collect();
+        self.bias = bias.to_vec();
+        self.steps = checkpoint.value("steps")? as u64;
+        self.rng = checkpoint.rng("rng")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    use core::consts::PLANCK;
+    use core::{MoleculeTemplate, UnitCell, Vector3D};
+    use core::units;
+    use mc::{DeleteMolecule, InsertMolecule};
+
+    #[test]
+    fn ideal_gas() {
+        let temperature = units::from(300.0, "K").unwrap();
+        let beta = 1.0 / (K_BOLTZMANN * temperature);
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let mass = helium.mass();
+        let system_volume = 1000.0;
+
+        // Chemical potential for an average of 2 molecules in the system
+        let wavelength = PLANCK * f64::sqrt(beta / (2.0 * PI * mass));
+        let thermal_volume = wavelength * wavelength * wavelength;
+        let mu = f64::ln(2.0 * thermal_volume / system_volume) / beta;
+
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let mut tmmc = TransitionMatrix::new(0, 6, temperature);
+        tmmc.add(Box::new(InsertMolecule::new(&helium, mu)), 1.0);
+        tmmc.add(Box::new(DeleteMolecule::new(&helium, mu)), 1.0);
+        tmmc.set_update_frequency(1000);
+        tmmc.set_output(tempfile.path()).unwrap();
+        tmmc.setup(&system);
+
+        for _ in 0..50_000 {
+            tmmc.propagate(&mut system);
+            assert_eq!(system.molecules().count(), tmmc.current);
+        }
+
+        // Every step is collected in the matrix
+        let collected = tmmc.collection.iter().map(|c| c.iter().sum::<f64>()).sum::<f64>();
+        assert_relative_eq!(collected, 50_000.0, epsilon = 1e-6);
+        // The bias is the opposite of the last estimate of ln(Pi)
+        assert_eq!(tmmc.bias()[0], 0.0);
+
+        // The distribution of the number of molecules is a Poisson
+        // distribution with an average of 2
+        let ln_pi = tmmc.ln_pi();
+        let mut factorial = 1.0;
+        for n in 1..7 {
+            factorial *= n as f64;
+            let expected = n as f64 * f64::ln(2.0) - f64::ln(factorial);
+            assert_relative_eq!(ln_pi[n], expected, epsilon = 0.2);
+        }
+
+        tmmc.finish(&system);
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Transition matrix Monte Carlo");
+        assert_eq!(lines[1], "# Molecules ln(Pi) C(N->N-1) C(N->N) C(N->N+1)");
+        assert_eq!(lines.len(), 2 + 7);
+        assert!(lines[2].starts_with("0 0 0 "));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        // Very high chemical potential, all insertions are accepted
+        let mut tmmc = TransitionMatrix::new(0, 3, 300.0);
+        tmmc.add(Box::new(InsertMolecule::new(&helium, 1e6)), 1.0);
+        tmmc.setup(&system);
+
+        for _ in 0..10 {
+            tmmc.propagate(&mut system);
+        }
+        assert_eq!(system.molecules().count(), 3);
+        assert_eq!(tmmc.collection[0], [0.0, 0.0, 1.0]);
+        assert_eq!(tmmc.collection[3], [0.0, 7.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The initial system must be inside the sampled range")]
+    fn outside_range() {
+        let system = System::with_cell(UnitCell::cubic(10.0));
+        let mut tmmc = TransitionMatrix::new(2, 4, 300.0);
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        tmmc.add(Box::new(InsertMolecule::new(&helium, 0.0)), 1.0);
+        tmmc.setup(&system);
+    }
+}
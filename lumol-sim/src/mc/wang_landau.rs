@@ -0,0 +1,699 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Wang-Landau flat histogram sampling of the density of states
+use std::f64;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use core::consts::K_BOLTZMANN;
+use core::{DegreesOfFreedom, EnergyCache, System};
+use core::units;
+
+use checkpoint::{Checkpoint, CheckpointError};
+use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
+use super::{MCDegreeOfFreedom, MCMove};
+
+/// Macroscopic variable used to build the density of states
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Macrovariable {
+    /// Potential energy between `min` and `max`, divided in `bins` bins
+    Energy {
+        min: f64,
+        max: f64,
+        bins: usize,
+    },
+    /// Number of molecules between `min` and `max`, both included, with
+    /// moves at the given `temperature`
+    Molecules {
+        min: usize,
+        max: usize,
+        temperature: f64,
+    },
+}
+
+impl Macrovariable {
+    /// Get the number of bins of this macrovariable
+    fn bins(&self) -> usize {
+        match *self {
+            Macrovariable::Energy { bins, .. } => bins,
+            Macrovariable::Molecules { min, max, .. } => max - min + 1,
+        }
+    }
+
+    /// Get the bin containing the given `energy` or number of `molecules`,
+    /// or `None` if the value is outside of the range of this macrovariable.
+    fn bin(&self, energy: f64, molecules: isize) -> Option<usize> {
+        match *self {
+            Macrovariable::Energy { min, max, bins } => {
+                if energy < min || energy >= max {
+                    return None;
+                }
+                let bin = ((energy - min) / (max - min) * bins as f64) as usize;
+                return Some(usize::min(bin, bins - 1));
+            }
+            Macrovariable::Molecules { min, max, .. } => {
+                if molecules < min as isize || molecules > max as isize {
+                    return None;
+                }
+                return Some(molecules as usize - min);
+            }
+        }
+    }
+
+    /// Get the value of the macrovariable at the center of the bin `i`
+    fn value(&self, i: usize) -> f64 {
+        match *self {
+            Macrovariable::Energy { min, max, bins } => {
+                min + (i as f64 + 0.5) * (max - min) / bins as f64
+            }
+            Macrovariable::Molecules { min, .. } => (min + i) as f64,
+        }
+    }
+}
+
+/// Wang-Landau sampling of the density of states [1].
+///
+/// The Wang-Landau algorithm builds an estimate of the logarithm of the
+/// density of states $\ln g(X)$ as a function of a macroscopic variable $X$,
+/// which can be the potential energy or the number of molecules in the
+/// system. The range of $X$ is divided in bins, and Monte Carlo moves from
+/// $X_{old}$ to $X_{new}$ are accepted with the probability
+///
+/// $$ P_{acc} = \min \left(1, e^{-c} \frac{g(X_{old})}{g(X_{new})} \right) $$
+///
+/// where $c$ is the usual cost of the Monte Carlo move. Moves leading
+/// outside of the range of $X$ are always rejected. After each step, $\ln g$
+/// is increased by $\ln f$ in the current bin, and the corresponding
+/// histogram is incremented. Every `check_frequency` steps, if the histogram
+/// is flat (i.e. if all the bins are visited at least `flatness` times the
+/// average number of visits), the histogram is reset and $\ln f$ is
+/// multiplied by a `reduction` factor. The simulation has converged when
+/// $\ln f$ is smaller than a final value, and $\ln g$ is no longer updated
+/// after that.
+///
+/// When sampling the energy, the moves are used with $\beta = 0$, and
+/// $\ln g(E)$ is the logarithm of the configurational density of states.
+/// Only moves that do not insert or delete molecules, and that do not use
+/// their own temperature (such as [`Regrow`], [`GeometricCluster`] or
+/// [`HybridMD`]) can be used.
+///
+/// When sampling the number of molecules, the moves are used at the
+/// temperature of the simulation, and the converged $\ln g(N)$ is the
+/// logarithm of the probability distribution of the number of molecules in
+/// the grand-canonical ensemble, up to a constant. This requires to use
+/// [`InsertMolecule`] and [`DeleteMolecule`] moves together with
+/// displacement moves. Only the molecules inserted and deleted by these
+/// moves are counted, and all of them must use the same molecule type.
+///
+/// The current estimate of $\ln g$ is written to the file given to
+/// [`set_output`](#method.set_output) each time $\ln f$ is reduced and at
+/// the end of the simulation, shifted to have a minimal value of zero.
+///
+/// [1] F. Wang and D. P. Landau, Phys. Rev. Lett. 86, 2050 (2001).
+///
+/// [`InsertMolecule`]: struct.InsertMolecule.html
+/// [`DeleteMolecule`]: struct.DeleteMolecule.html
+/// [`Regrow`]: struct.Regrow.html
+/// [`GeometricCluster`]: struct.GeometricCluster.html
+/// [`HybridMD`]: struct.HybridMD.html
+pub struct WangLandau {
+    /// Macroscopic variable used for the density of states
+    variable: Macrovariable,
+    /// List of possible Monte Carlo moves
+    moves: Vec<Box<MCMove>>,
+    /// Frequencies of the Monte Carlo moves
+    frequencies: Vec<f64>,
+    /// Cumulative frequencies of the Monte Carlo moves
+    cumulative: Vec<f64>,
+    /// Logarithm of the density of states in each bin
+    ln_g: Vec<f64>,
+    /// Number of visits in each bin since the last reduction of `ln_f`
+    histogram: Vec<u64>,
+    /// Current value of the logarithm of the modification factor
+    ln_f: f64,
+    /// Final value of `ln_f`, the simulation is converged when `ln_f` is
+    /// smaller than this value
+    final_ln_f: f64,
+    /// Factor used to reduce `ln_f` each time the histogram is flat
+    reduction: f64,
+    /// Ratio between the minimal and the average values of the histogram
+    /// for the histogram to be considered flat
+    flatness: f64,
+    /// Number of steps between flatness checks
+    check_frequency: u64,
+    /// Number of steps since the last reduction of `ln_f`
+    steps: u64,
+    /// Bin of the current state of the system
+    current: usize,
+    /// Random number generator for the moves
+    rng: XorShiftRng,
+    /// Cache for faster energy computation
+    cache: EnergyCache,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl WangLandau {
+    fn new(variable: Macrovariable) -> WangLandau {
+        WangLandau {
+            variable: variable,
+            moves: Vec::new(),
+            frequencies: Vec::new(),
+            cumulative: Vec::new(),
+            ln_g: vec![0.0; variable.bins()],
+            histogram: vec![0; variable.bins()],
+            ln_f: 1.0,
+            final_ln_f: 1e-8,
+            reduction: 0.5,
+            flatness: 0.8,
+            check_frequency: 10_000,
+            steps: 0,
+            current: 0,
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+            cache: EnergyCache::new(),
+            output: None,
+        }
+    }
+
+    /// Create a new Wang-Landau propagator for the density of states of the
+    /// potential energy, between `min` and `max` and divided in `bins`
+    /// bins.
+    pub fn energy(min: f64, max: f64, bins: usize) -> WangLandau {
+        assert!(min < max, "The energy range must not be empty in Wang-Landau sampling.");
+        assert!(bins > 0, "The number of bins must be positive in Wang-Landau sampling.");
+        let variable = Macrovariable::Energy {
+            min: min,
+            max: max,
+            bins: bins,
+        };
+        return WangLandau::new(variable);
+    }
+
+    /// Create a new Wang-Landau propagator for the distribution of the
+    /// number of molecules between `min` and `max` (both included), using
+    /// the moves at the given `temperature`.
+    pub fn molecules(min: usize, max: usize, temperature: f64) -> WangLandau {
+        assert!(min < max, "The molecules range must not be empty in Wang-Landau sampling.");
+        assert!(temperature > 0.0, "The temperature must be positive in Wang-Landau sampling.");
+        let variable = Macrovariable::Molecules {
+            min: min,
+            max: max,
+            temperature: temperature,
+        };
+        return WangLandau::new(variable);
+    }
+
+    /// Add the `mcmove` Monte Carlo move to this propagator, with frequency
+    /// `frequency`.
+    ///
+    /// # Panics
+    ///
+    /// When sampling the energy, if the move uses its own temperature.
+    pub fn add(&mut self, mcmove: Box<MCMove>, frequency: f64) {
+        if let Macrovariable::Energy { .. } = self.variable {
+            assert!(
+                mcmove.temperature().is_none(),
+                "The '{}' move uses its own temperature, and can not be used in \
+                 Wang-Landau sampling of the energy.",
+                mcmove.describe()
+            );
+        }
+        self.moves.push(mcmove);
+        self.frequencies.push(frequency);
+    }
+
+    /// Set the schedule of the modification factor: $\ln f$ starts at
+    /// `initial`, is multiplied by `reduction` each time the histogram is
+    /// flat, and the simulation is converged when $\ln f$ is smaller than
+    /// `last`. The default schedule uses an initial value of 1, a reduction
+    /// of 0.5 and a final value of 1e-8.
+    pub fn set_schedule(&mut self, initial: f64, reduction: f64, last: f64) {
+        assert!(initial > 0.0, "The initial ln(f) must be positive in Wang-Landau sampling.");
+        assert!(last > 0.0, "The final ln(f) must be positive in Wang-Landau sampling.");
+        assert!(
+            0.0 < reduction && reduction < 1.0,
+            "The reduction factor must be between 0 and 1 in Wang-Landau sampling."
+        );
+        self.ln_f = initial;
+        self.reduction = reduction;
+        self.final_ln_f = last;
+    }
+
+    /// Set the `flatness` criterion of the histogram: the histogram is flat
+    /// if all its values are larger than `flatness` times the average value.
+    /// The default value is 0.8.
+    pub fn set_flatness(&mut self, flatness: f64) {
+        assert!(
+            0.0 < flatness && flatness < 1.0,
+            "The flatness must be between 0 and 1 in Wang-Landau sampling."
+        );
+        self.flatness = flatness;
+    }
+
+    /// Set the number of steps between checks of the histogram flatness.
+    /// The default value is 10000.
+    pub fn set_check_frequency(&mut self, frequency: u64) {
+        assert!(frequency > 0, "The check frequency must be positive in Wang-Landau sampling.");
+        self.check_frequency = frequency;
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Write the density of states to the file at `path`. The file is
+    /// replaced if it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the current estimate of the logarithm of the density of states
+    /// in each bin.
+    pub fn ln_g(&self) -> &[f64] {
+        &self.ln_g
+    }
+
+    /// Get the current value of the logarithm of the modification factor.
+    pub fn ln_f(&self) -> f64 {
+        self.ln_f
+    }
+
+    /// Check if the simulation has converged, i.e. if $\ln f$ is smaller
+    /// than its final value.
+    pub fn converged(&self) -> bool {
+        self.ln_f < self.final_ln_f
+    }
+
+    /// Check if the histogram is flat
+    fn is_flat(&self) -> bool {
+        let min = self.histogram.iter().cloned().min().unwrap_or(0);
+        let sum = self.histogram.iter().sum::<u64>();
+        let average = sum as f64 / self.histogram.len() as f64;
+        return min > 0 && min as f64 >= self.flatness * average;
+    }
+
+    /// Write the current estimate of the density of states to the output
+    /// file, if any.
+    fn write(&mut self) {
+        let variable = self.variable;
+        let ln_f = self.ln_f;
+        let ln_g = &self.ln_g;
+        let min = ln_g.iter().cloned().fold(f64::INFINITY, f64::min);
+        if let Some((ref mut file, ref path)) = self.output {
+            // Write the whole file again
+            let result = file.seek(io::SeekFrom::Start(0)).and_then(|_| {
+                file.get_ref().set_len(0)
+            }).and_then(|_| {
+                writeln!(file, "# Wang-Landau density of states, ln(f) = {}", ln_f)
+            }).and_then(|_| match variable {
+                Macrovariable::Energy { .. } => writeln!(file, "# Energy(kJ/mol) ln(g)"),
+                Macrovariable::Molecules { .. } => writeln!(file, "# Molecules ln(g)"),
+            }).and_then(|_| {
+                for (i, value) in ln_g.iter().enumerate() {
+                    let x = match variable {
+                        Macrovariable::Energy { .. } => {
+                            units::to(variable.value(i), "kJ/mol").expect("bad unit")
+                        }
+                        Macrovariable::Molecules { .. } => variable.value(i),
+                    };
+                    writeln!(file, "{} {}", x, value - min)?;
+                }
+                file.flush()
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Update the density of states and the histogram with the current
+    /// state, and reduce `ln_f` if the histogram is flat.
+    fn update(&mut self) {
+        if self.converged() {
+            return;
+        }
+
+        self.ln_g[self.current] += self.ln_f;
+        self.histogram[self.current] += 1;
+        self.steps += 1;
+
+        if self.steps % self.check_frequency == 0 && self.is_flat() {
+            self.ln_f *= self.reduction;
+            self.steps = 0;
+            for count in &mut self.histogram {
+                *count = 0;
+            }
+            info!("Wang-Landau histogram is flat, reducing ln(f) to {}", self.ln_f);
+            if self.converged() {
+                info!("Wang-Landau sampling has converged");
+            }
+            self.write();
+        }
+    }
+
+    /// Attempt the Monte Carlo move at index `i`, and update the current
+    /// bin if the move is accepted.
+    fn attempt(&mut self, i: usize, system: &mut System) {
+        let mcmove = &mut self.moves[i];
+        trace!("Selected move is '{}'", mcmove.describe());
+
+        if !mcmove.prepare(system, &mut self.rng) {
+            trace!("    --> Can not perform the move");
+            return;
+        }
+
+        let change = mcmove.molecules_change();
+        let (cost, new) = match self.variable {
+            Macrovariable::Energy { .. } => {
+                if change != 0 {
+                    warn_once!(
+                        "Can not insert or delete molecules in Wang-Landau sampling of the energy."
+                    );
+                    mcmove.restore(system);
+                    return;
+                }
+                let cost = mcmove.cost(system, 0.0, &mut self.cache);
+                let energy = match self.cache.last_cost() {
+                    Some(delta) => self.cache.energy() + delta,
+                    // The move changed the system in `prepare` without using
+                    // the cache
+                    None => system.potential_energy(),
+                };
+                (cost, self.variable.bin(energy, 0))
+            }
+            Macrovariable::Molecules { temperature, .. } => {
+                let beta = 1.0 / (K_BOLTZMANN * temperature);
+                let cost = mcmove.cost(system, beta, &mut self.cache);
+                let molecules = self.variable.value(self.current) as isize + change;
+                (cost, self.variable.bin(0.0, molecules))
+            }
+        };
+
+        let new = match new {
+            Some(new) => new,
+            None => {
+                trace!("    --> Move is outside of the sampled range");
+                mcmove.restore(system);
+                return;
+            }
+        };
+
+        let cost = cost + self.ln_g[new] - self.ln_g[self.current];
+        trace!("    --> Move cost is {}", cost);
+        if cost <= 0.0 || self.rng.gen::<f64>() < f64::exp(-cost) {
+            trace!("    --> Move was accepted");
+            mcmove.apply(system);
+            self.cache.update(system);
+            self.current = new;
+        } else {
+            trace!("    --> Move was rejected");
+            mcmove.restore(system);
+        }
+    }
+}
+
+impl Propagator for WangLandau {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        match self.variable {
+            Macrovariable::Energy { .. } => TemperatureStrategy::None,
+            Macrovariable::Molecules { temperature, .. } => {
+                TemperatureStrategy::External(temperature)
+            }
+        }
+    }
+
+    fn degrees_of_freedom(&self, _: &System) -> DegreesOfFreedom {
+        let mut dof = MCDegreeOfFreedom::Particles;
+        if let Some(first) = self.moves.first() {
+            dof = first.degrees_of_freedom();
+            for other in &self.moves[1..] {
+                dof = dof.combine(other.degrees_of_freedom());
+            }
+        }
+
+        match dof {
+            MCDegreeOfFreedom::Particles => DegreesOfFreedom::Particles,
+            MCDegreeOfFreedom::AllMolecules | MCDegreeOfFreedom::Molecules(_) => {
+                DegreesOfFreedom::Molecules
+            }
+        }
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(!self.moves.is_empty(), "No move in the Wang-Landau simulation.");
+        let sum = self.frequencies.iter().sum::<f64>();
+        let mut cumulative = 0.0;
+        self.cumulative = self.frequencies.iter().map(|frequency| {
+            cumulative += frequency / sum;
+            cumulative
+        }).collect();
+        let last = self.cumulative.len() - 1;
+        self.cumulative[last] = 1.0;
+
+        self.cache.init(system);
+        for mcmove in &mut self.moves {
+            mcmove.setup(system);
+        }
+
+        // Only count the molecules of the type inserted and deleted by the
+        // moves
+        let mut exchanged = None;
+        for mcmove in &self.moves {
+            if let Some(hash) = mcmove.exchanged_molecules() {
+                assert!(
+                    exchanged.is_none() || exchanged == Some(hash),
+                    "All the moves inserting or deleting molecules must use the same \
+                     molecule type in Wang-Landau sampling."
+                );
+                exchanged = Some(hash);
+            }
+        }
+        let molecules = match exchanged {
+            Some(hash) => system.molecules().filter(|molecule| molecule.hash() == hash).count(),
+            None => system.molecules().count(),
+        };
+
+        let molecules = molecules as isize;
+        self.current = self.variable.bin(self.cache.energy(), molecules).expect(
+            "The initial system must be inside the sampled range in Wang-Landau sampling."
+        );
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        let probability: f64 = self.rng.gen();
+        let i = self.cumulative.iter()
+                               .position(|&frequency| probability <= frequency)
+                               .expect("Could not find a move in Wang-Landau moves list");
+        self.attempt(i, system);
+        self.update();
+    }
+
+    fn finish(&mut self, _: &System) {
+        if self.converged() {
+            info!("Wang-Landau sampling converged");
+        } else {
+            info!("Wang-Landau sampling did not converge, final ln(f) is {}", self.ln_f);
+        }
+        self.write();
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        let histogram = self.histogram.iter().map(|&n| n as f64).collect::<Vec<_>>();
+        checkpoint.set_values("ln_g", &self.ln_g);
+        checkpoint.set_values("histogram", &histogram);
+        checkpoint.set_value("ln_f", self.ln_f);
+        checkpoint.set_value("steps", self.steps as f64);
+        checkpoint.save_rng("rng", &self.rng);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let ln_g = checkpoint.values("ln_g")?;
+        let histogram = checkpoint.values("histogram")?;
+        if ln_g.len() != self.ln_g.len() || histogram.len() != self.histogram.len() {
+            return Err(CheckpointError::from(format!(
+                "expected {} bins in Wang-Landau checkpoint", self.ln_g.len()
+            )));
+        }
+
+        self.ln_g = ln_g.to_vec();
+        self.histogram = histogram.iter().map(|&n| n as u64).collect();
+        self.ln_f = checkpoint.value("ln_f")?;
+        self.steps = checkpoint.value("steps")? as u64;
+        self.rng = checkpoint.rng("rng")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use std::f64::consts::PI;
+
+    use core::consts::PLANCK;
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, MoleculeTemplate, Particle, UnitCell, Vector3D};
+    use mc::{DeleteMolecule, GeometricCluster, InsertMolecule, Translate};
+
+    #[test]
+    fn bins() {
+        let energy = Macrovariable::Energy { min: -1.0, max: 1.0, bins: 4 };
+        assert_eq!(energy.bins(), 4);
+        assert_eq!(energy.bin(-1.0, 0), Some(0));
+        assert_eq!(energy.bin(-0.2, 0), Some(1));
+        assert_eq!(energy.bin(0.9, 0), Some(3));
+        assert_eq!(energy.bin(1.0, 0), None);
+        assert_eq!(energy.bin(-1.1, 0), None);
+        assert_eq!(energy.value(0), -0.75);
+        assert_eq!(energy.value(3), 0.75);
+
+        let molecules = Macrovariable::Molecules { min: 2, max: 5, temperature: 300.0 };
+        assert_eq!(molecules.bins(), 4);
+        assert_eq!(molecules.bin(0.0, 2), Some(0));
+        assert_eq!(molecules.bin(0.0, 5), Some(3));
+        assert_eq!(molecules.bin(0.0, 1), None);
+        assert_eq!(molecules.bin(0.0, 6), None);
+        assert_eq!(molecules.bin(0.0, -1), None);
+        assert_eq!(molecules.value(3), 5.0);
+    }
+
+    #[test]
+    fn energy() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.0, k: 1.0 }), 4.0);
+        system.add_pair_potential(("He", "He"), pair);
+
+        let tempfile = NamedTempFile::new().unwrap();
+        // Bins edges are far from the minimal and maximal energies
+        let mut wang_landau = WangLandau::energy(-4.5, 3.5, 8);
+        wang_landau.add(Box::new(Translate::new(1.0, None)), 1.0);
+        wang_landau.set_check_frequency(1_000_000);
+        wang_landau.set_output(tempfile.path()).unwrap();
+        wang_landau.setup(&system);
+
+        for _ in 0..500 {
+            wang_landau.propagate(&mut system);
+            let energy = system.potential_energy();
+            assert_eq!(wang_landau.variable.bin(energy, 0), Some(wang_landau.current));
+        }
+
+        // No reduction of ln(f) happened
+        assert_eq!(wang_landau.ln_f(), 1.0);
+        assert_eq!(wang_landau.histogram.iter().sum::<u64>(), 500);
+        assert_relative_eq!(wang_landau.ln_g().iter().sum::<f64>(), 500.0);
+
+        wang_landau.finish(&system);
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Wang-Landau density of states, ln(f) = 1");
+        assert_eq!(lines[1], "# Energy(kJ/mol) ln(g)");
+        assert_eq!(lines.len(), 2 + 8);
+    }
+
+    #[test]
+    fn ideal_gas() {
+        let temperature = units::from(300.0, "K").unwrap();
+        let beta = 1.0 / (K_BOLTZMANN * temperature);
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let mass = helium.mass();
+        let system_volume = 1000.0;
+
+        // Chemical potential for an average of 2 molecules in the system
+        let wavelength = PLANCK * f64::sqrt(beta / (2.0 * PI * mass));
+        let thermal_volume = wavelength * wavelength * wavelength;
+        let mu = f64::ln(2.0 * thermal_volume / system_volume) / beta;
+
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        let mut wang_landau = WangLandau::molecules(0, 4, temperature);
+        wang_landau.add(Box::new(InsertMolecule::new(&helium, mu)), 1.0);
+        wang_landau.add(Box::new(DeleteMolecule::new(&helium, mu)), 1.0);
+        wang_landau.set_check_frequency(100);
+        wang_landau.set_schedule(1.0, 0.5, 1e-3);
+        wang_landau.setup(&system);
+
+        let mut steps = 0;
+        while !wang_landau.converged() {
+            wang_landau.propagate(&mut system);
+            steps += 1;
+            assert!(steps < 10_000_000, "Wang-Landau sampling did not converge");
+        }
+        assert!(system.molecules().count() <= 4);
+
+        // The distribution of the number of molecules is a Poisson
+        // distribution with an average of 2
+        let ln_g = wang_landau.ln_g();
+        let mut factorial = 1.0;
+        for n in 1..5 {
+            factorial *= n as f64;
+            let expected = n as f64 * f64::ln(2.0) - f64::ln(factorial);
+            assert_relative_eq!(ln_g[n] - ln_g[0], expected, epsilon = 0.3);
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The initial system must be inside the sampled range in Wang-Landau sampling."
+    )]
+    fn outside_range() {
+        let system = System::with_cell(UnitCell::cubic(10.0));
+        let mut wang_landau = WangLandau::molecules(2, 4, 300.0);
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        wang_landau.add(Box::new(InsertMolecule::new(&helium, 0.0)), 1.0);
+        wang_landau.setup(&system);
+    }
+
+    #[test]
+    fn count_exchanged_molecules() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        for &x in &[2.0, 4.0, 6.0] {
+            system.add_molecule(Molecule::new(Particle::with_position("Ar", [x, 0.0, 0.0].into())));
+        }
+
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let mut wang_landau = WangLandau::molecules(0, 2, 300.0);
+        wang_landau.add(Box::new(InsertMolecule::new(&helium, 0.0)), 1.0);
+        wang_landau.add(Box::new(DeleteMolecule::new(&helium, 0.0)), 1.0);
+        wang_landau.setup(&system);
+        // Only the helium molecule is counted
+        assert_eq!(wang_landau.current, 1);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "All the moves inserting or deleting molecules must use the same molecule type"
+    )]
+    fn different_exchanged_molecules() {
+        let system = System::with_cell(UnitCell::cubic(10.0));
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let argon = MoleculeTemplate::new().particle("Ar", Vector3D::zero(), 0.0);
+        let mut wang_landau = WangLandau::molecules(0, 2, 300.0);
+        wang_landau.add(Box::new(InsertMolecule::new(&helium, 0.0)), 1.0);
+        wang_landau.add(Box::new(DeleteMolecule::new(&argon, 0.0)), 1.0);
+        wang_landau.setup(&system);
+    }
+
+    #[test]
+    #[should_panic(expected = "move uses its own temperature")]
+    fn energy_temperature_move() {
+        let mut wang_landau = WangLandau::energy(-1.0, 1.0, 4);
+        wang_landau.add(Box::new(GeometricCluster::new(300.0)), 1.0);
+    }
+}
@@ -0,0 +1,258 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Adaptive timestep for molecular dynamics integrators
+use core::{System, Vector3D};
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+use super::Integrator;
+
+/// Maximal growth of the timestep between two steps
+const MAX_GROWTH: f64 = 1.1;
+/// Maximal shrinking of the timestep between two steps
+const MAX_SHRINK: f64 = 0.5;
+
+/// Error criterion used by [`AdaptiveTimestep`] to control the timestep.
+///
+/// [`AdaptiveTimestep`]: struct.AdaptiveTimestep.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimestepCriterion {
+    /// Keep the maximal displacement of the particles during one step close
+    /// to the given distance.
+    Displacement(f64),
+    /// Keep the change of total energy per particle during one step close to
+    /// the given energy. This criterion only makes sense with energy
+    /// conserving integrators, and needs to compute the energy of the system
+    /// at every step.
+    EnergyDrift(f64),
+}
+
+/// Integrator wrapper adapting the timestep of another integrator during the
+/// simulation.
+///
+/// After each step, the error from the [`TimestepCriterion`] is compared to
+/// the target value, and the timestep is shrunk or grown accordingly for the
+/// next step, while staying between a minimal and a maximal value. The
+/// timestep can shrink quickly, but only grows slowly. This is useful during
+/// equilibration phases with steep potentials, for example when starting
+/// from overlapping particles.
+///
+/// Changing the timestep breaks the reversibility and the symplecticity of
+/// the integration, so the timestep should be fixed again for production
+/// runs. The wrapped integrator must support changing its timestep, which
+/// is the case for [`VelocityVerlet`], [`LeapFrog`], [`Langevin`], [`RESPA`]
+/// and [`RigidBodyVerlet`].
+///
+/// [`TimestepCriterion`]: enum.TimestepCriterion.html
+/// [`VelocityVerlet`]: struct.VelocityVerlet.html
+/// [`LeapFrog`]: struct.LeapFrog.html
+/// [`Langevin`]: struct.Langevin.html
+/// [`RESPA`]: struct.RESPA.html
+/// [`RigidBodyVerlet`]: struct.RigidBodyVerlet.html
+pub struct AdaptiveTimestep {
+    /// The wrapped integrator
+    integrator: Box<Integrator>,
+    /// Criterion used to control the timestep
+    criterion: TimestepCriterion,
+    /// Current timestep
+    timestep: f64,
+    /// Minimal timestep
+    min: f64,
+    /// Maximal timestep
+    max: f64,
+    /// Positions of the particles before the current step
+    positions: Vec<Vector3D>,
+    /// Potential energy at the end of the previous step, if any
+    potential: Option<f64>,
+}
+
+impl AdaptiveTimestep {
+    /// Wrap the `integrator` to adapt its timestep using the given
+    /// `criterion`, starting from `timestep` and keeping the timestep
+    /// between `min` and `max`.
+    ///
+    /// # Panics
+    ///
+    /// If the `integrator` does not support changing its timestep, if the
+    /// bounds are not positive or not ordered, or if the target of the
+    /// criterion is not positive.
+    pub fn new(
+        mut integrator: Box<Integrator>,
+        criterion: TimestepCriterion,
+        timestep: f64,
+        min: f64,
+        max: f64,
+    ) -> AdaptiveTimestep {
+        assert!(min > 0.0, "The minimal timestep must be positive in adaptive timestep");
+        assert!(min <= max, "The minimal timestep must be smaller than the maximal one");
+        match criterion {
+            TimestepCriterion::Displacement(target) | TimestepCriterion::EnergyDrift(target) => {
+                assert!(target > 0.0, "The target of the adaptive timestep must be positive");
+            }
+        }
+
+        let timestep = timestep.max(min).min(max);
+        integrator.set_timestep(timestep);
+        AdaptiveTimestep {
+            integrator: integrator,
+            criterion: criterion,
+            timestep: timestep,
+            min: min,
+            max: max,
+            positions: Vec::new(),
+            potential: None,
+        }
+    }
+
+    /// Get the current timestep
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    /// Update the timestep to get closer to the `target` error, using the
+    /// `error` of the last step. The error is assumed to scale as the
+    /// timestep to the given `order`.
+    fn adapt(&mut self, error: f64, target: f64, order: f64) {
+        let factor = if error > 0.0 {
+            f64::powf(target / error, 1.0 / order)
+        } else {
+            MAX_GROWTH
+        };
+        let factor = factor.max(MAX_SHRINK).min(MAX_GROWTH);
+        self.timestep = (factor * self.timestep).max(self.min).min(self.max);
+        self.integrator.set_timestep(self.timestep);
+    }
+}
+
+impl Integrator for AdaptiveTimestep {
+    fn setup(&mut self, system: &System) {
+        self.potential = None;
+        self.integrator.setup(system);
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        match self.criterion {
+            TimestepCriterion::Displacement(target) => {
+                self.positions.clear();
+                self.positions.extend_from_slice(system.particles().position);
+                self.integrator.integrate(system);
+
+                let displacement = system.particles().position.iter()
+                                                               .zip(&self.positions)
+                                                               .map(|(&a, &b)| (a - b).norm2())
+                                                               .fold(0.0, f64::max);
+                self.adapt(displacement.sqrt(), target, 1.0);
+            }
+            TimestepCriterion::EnergyDrift(target) => {
+                // Thermostats and controls only change the velocities
+                // between steps, so the potential energy from the end of the
+                // previous step can be re-used.
+                let potential = match self.potential {
+                    Some(potential) => potential,
+                    None => system.potential_energy(),
+                };
+                let before = potential + system.kinetic_energy();
+                self.integrator.integrate(system);
+                let potential = system.potential_energy();
+                let after = potential + system.kinetic_energy();
+                self.potential = Some(potential);
+
+                let drift = f64::abs(after - before) / system.size() as f64;
+                // The energy error of second order integrators scales as the
+                // square of the timestep
+                self.adapt(drift, target, 2.0);
+            }
+        }
+    }
+
+    fn conserved_energy(&self, system: &System) -> Option<f64> {
+        self.integrator.conserved_energy(system)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("timestep", self.timestep);
+        // An empty list of values means that the potential energy is unknown
+        let potential = self.potential.iter().cloned().collect::<Vec<_>>();
+        checkpoint.set_values("potential", &potential);
+        self.integrator.save_checkpoint(checkpoint.section_mut("integrator"));
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.timestep = checkpoint.value("timestep")?;
+        self.integrator.set_timestep(self.timestep);
+        self.potential = checkpoint.values("potential")?.first().cloned();
+        self.integrator.restore_checkpoint(checkpoint.section("integrator")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, Particle, UnitCell};
+    use md::{VelocityVerlet, Verlet};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 1.0 }), 8.0);
+        system.add_pair_potential(("He", "He"), pair);
+        return system;
+    }
+
+    #[test]
+    fn displacement() {
+        let mut system = testing_system();
+        let integrator = Box::new(VelocityVerlet::new(1.0));
+        let criterion = TimestepCriterion::Displacement(0.01);
+        let mut adaptive = AdaptiveTimestep::new(integrator, criterion, 1.0, 0.001, 2.0);
+        adaptive.setup(&system);
+
+        for _ in 0..200 {
+            adaptive.integrate(&mut system);
+            assert!(adaptive.timestep() >= 0.001);
+            assert!(adaptive.timestep() <= 2.0);
+        }
+        assert!(adaptive.timestep() < 0.2);
+
+        // Particles moving slowly without interactions use the largest
+        // timestep
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.particles_mut().velocity[0] = Vector3D::new(0.0, 0.0, 1e-3);
+
+        let integrator = Box::new(VelocityVerlet::new(1.0));
+        let mut adaptive = AdaptiveTimestep::new(integrator, criterion, 1.0, 0.001, 2.0);
+        adaptive.setup(&system);
+        for _ in 0..20 {
+            adaptive.integrate(&mut system);
+        }
+        assert_eq!(adaptive.timestep(), 2.0);
+    }
+
+    #[test]
+    fn energy_drift() {
+        let mut system = testing_system();
+        let integrator = Box::new(VelocityVerlet::new(1.0));
+        let criterion = TimestepCriterion::EnergyDrift(1e-6);
+        let mut adaptive = AdaptiveTimestep::new(integrator, criterion, 1.0, 0.001, 2.0);
+        adaptive.setup(&system);
+
+        for _ in 0..200 {
+            adaptive.integrate(&mut system);
+        }
+        assert!(adaptive.timestep() < 0.2);
+        assert!(adaptive.timestep() >= 0.001);
+    }
+
+    #[test]
+    #[should_panic(expected = "This integrator does not support changing the timestep")]
+    fn unsupported_integrator() {
+        let integrator = Box::new(Verlet::new(1.0));
+        let criterion = TimestepCriterion::Displacement(0.01);
+        let _ = AdaptiveTimestep::new(integrator, criterion, 1.0, 0.001, 2.0);
+    }
+}
@@ -0,0 +1,195 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Nosé-Hoover chains, shared by the thermostats and the integrators coupling
+//! the system to a heat bath.
+use core::consts::K_BOLTZMANN;
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+/// A chain of Nosé-Hoover thermostats acting on some degrees of freedom.
+///
+/// The equations of motion of the chain are integrated with the reversible
+/// scheme from Martyna, Tuckerman, Tobias and Klein (Mol. Phys. 87, 1117
+/// (1996)), using a Suzuki-Yoshida factorization of the chain propagator.
+/// The masses of the thermostats are `Q_1 = N_f k_B T tau^2` for the first
+/// thermostat in the chain, and `Q_i = k_B T tau^2` for the other ones, where
+/// `N_f` is the number of thermostatted degrees of freedom. All the times
+/// (`tau` and the time steps) must be given in the same units.
+pub(crate) struct NoseHooverChain {
+    /// Target temperature
+    temperature: f64,
+    /// Relaxation time of the chain
+    tau: f64,
+    /// Weights of the Suzuki-Yoshida factorization
+    weights: Vec<f64>,
+    /// Positions of the thermostats in the chain
+    positions: Vec<f64>,
+    /// Velocities of the thermostats in the chain
+    velocities: Vec<f64>,
+    /// Masses of the thermostats in the chain
+    masses: Vec<f64>,
+}
+
+impl NoseHooverChain {
+    /// Create a new chain at the given `temperature` and with a relaxation
+    /// time of `tau`. The chain contains three thermostats, and is integrated
+    /// with three Suzuki-Yoshida steps.
+    pub fn new(temperature: f64, tau: f64) -> NoseHooverChain {
+        let mut chain = NoseHooverChain {
+            temperature: temperature,
+            tau: tau,
+            weights: Vec::new(),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            masses: Vec::new(),
+        };
+        chain.set_length(3);
+        chain.set_suzuki_yoshida_steps(3);
+        return chain;
+    }
+
+    /// Set the target temperature of the chain
+    pub fn set_temperature(&mut self, temperature: f64) {
+        self.temperature = temperature;
+    }
+
+    /// Set the number of thermostats in the chain to `length`, and reset the
+    /// state of the chain.
+    pub fn set_length(&mut self, length: usize) {
+        assert!(length > 0, "The chain length must be positive in Nosé-Hoover thermostat.");
+        self.positions = vec![0.0; length];
+        self.velocities = vec![0.0; length];
+        self.masses = vec![0.0; length];
+    }
+
+    /// Save the positions and velocities of the thermostats in the chain in
+    /// the `checkpoint`.
+    pub fn save_checkpoint(&self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_values("positions", &self.positions);
+        checkpoint.set_values("velocities", &self.velocities);
+    }
+
+    /// Restore the positions and velocities of the thermostats in the chain
+    /// from the `checkpoint`.
+    pub fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let positions = checkpoint.values("positions")?;
+        let velocities = checkpoint.values("velocities")?;
+        if positions.len() != self.positions.len() || velocities.len() != self.velocities.len() {
+            return Err(CheckpointError::from(format!(
+                "expected a Nosé-Hoover chain of length {} in checkpoint, got {}",
+                self.positions.len(), positions.len()
+            )));
+        }
+        self.positions = positions.to_vec();
+        self.velocities = velocities.to_vec();
+        Ok(())
+    }
+
+    /// Set the number of Suzuki-Yoshida steps used to integrate the chain.
+    /// The possible values are 1, 3, 5 and 7, giving respectively a second,
+    /// fourth, fourth and sixth order integration.
+    pub fn set_suzuki_yoshida_steps(&mut self, steps: usize) {
+        self.weights = match steps {
+            1 => vec![1.0],
+            3 => {
+                let w = 1.0 / (2.0 - f64::cbrt(2.0));
+                vec![w, 1.0 - 2.0 * w, w]
+            }
+            5 => {
+                let w = 1.0 / (4.0 - f64::cbrt(4.0));
+                vec![w, w, 1.0 - 4.0 * w, w, w]
+            }
+            7 => {
+                let w1 = 0.784513610477560;
+                let w2 = 0.235573213359357;
+                let w3 = -1.17767998417887;
+                let w4 = 1.0 - 2.0 * (w1 + w2 + w3);
+                vec![w1, w2, w3, w4, w3, w2, w1]
+            }
+            _ => panic!(
+                "The number of Suzuki-Yoshida steps must be 1, 3, 5 or 7 in Nosé-Hoover \
+                thermostat, got {}", steps
+            ),
+        };
+    }
+
+    /// Get the energy of the thermostats in the chain, when coupled to `dof`
+    /// degrees of freedom.
+    pub fn energy(&self, dof: f64) -> f64 {
+        let kt = K_BOLTZMANN * self.temperature;
+        let mass = kt * self.tau * self.tau;
+
+        let mut energy = 0.5 * dof * mass * self.velocities[0] * self.velocities[0];
+        energy += dof * kt * self.positions[0];
+        for (position, velocity) in self.positions.iter().zip(&self.velocities).skip(1) {
+            energy += 0.5 * mass * velocity * velocity + kt * position;
+        }
+        return energy;
+    }
+
+    /// Propagate the chain for a time `delta`, when coupled to `dof` degrees
+    /// of freedom with `kinetic` as twice their kinetic energy. This function
+    /// returns the factor by which the velocities of the degrees of freedom
+    /// should be scaled.
+    pub fn propagate(&mut self, kinetic: f64, dof: f64, delta: f64) -> f64 {
+        if dof == 0.0 {
+            return 1.0;
+        }
+        self.update_masses(dof);
+
+        let length = self.velocities.len();
+        let mut kinetic = kinetic;
+        let mut scale = 1.0;
+        for weight in self.weights.clone() {
+            let delta = weight * delta;
+            for i in (0..length).rev() {
+                self.half_kick(i, delta, kinetic, dof);
+            }
+
+            let factor = f64::exp(-delta * self.velocities[0]);
+            scale *= factor;
+            kinetic *= factor * factor;
+            for (position, velocity) in self.positions.iter_mut().zip(&self.velocities) {
+                *position += delta * velocity;
+            }
+
+            for i in 0..length {
+                self.half_kick(i, delta, kinetic, dof);
+            }
+        }
+        return scale;
+    }
+
+    fn update_masses(&mut self, dof: f64) {
+        let mass = K_BOLTZMANN * self.temperature * self.tau * self.tau;
+        for (i, value) in self.masses.iter_mut().enumerate() {
+            *value = if i == 0 { dof * mass } else { mass };
+        }
+    }
+
+    /// Get the force acting on the thermostat `i` in the chain, using
+    /// `kinetic` as twice the kinetic energy of the degrees of freedom.
+    fn force(&self, i: usize, kinetic: f64, dof: f64) -> f64 {
+        let kt = K_BOLTZMANN * self.temperature;
+        if i == 0 {
+            (kinetic - dof * kt) / self.masses[0]
+        } else {
+            let velocity = self.velocities[i - 1];
+            (self.masses[i - 1] * velocity * velocity - kt) / self.masses[i]
+        }
+    }
+
+    /// Update the velocity of the thermostat `i` for half of the `delta`
+    /// time step, including the friction from the next thermostat.
+    fn half_kick(&mut self, i: usize, delta: f64, kinetic: f64, dof: f64) {
+        let force = self.force(i, kinetic, dof);
+        if i + 1 < self.velocities.len() {
+            let friction = f64::exp(-0.25 * delta * self.velocities[i + 1]);
+            self.velocities[i] *= friction * friction;
+            self.velocities[i] += 0.5 * delta * friction * force;
+        } else {
+            self.velocities[i] += 0.5 * delta * force;
+        }
+    }
+}
@@ -0,0 +1,267 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Collective variables, describing the state of a system with a few
+//! numbers, for enhanced sampling methods.
+use core::{System, Vector3D};
+
+/// A collective variable is a function of the positions of the particles in
+/// a system, used to describe a slow process like a chemical reaction or a
+/// conformational change.
+pub trait CollectiveVariable: Send {
+    /// Get the value of this collective variable for the `system`
+    fn value(&self, system: &System) -> f64;
+
+    /// Get the gradient of this collective variable with respect to the
+    /// positions of the particles in the `system`. Only the particles with a
+    /// non-zero contribution are included, as `(index, gradient)` pairs. The
+    /// same particle can be included multiple times, in which case the
+    /// contributions should be added together.
+    fn gradient(&self, system: &System) -> Vec<(usize, Vector3D)>;
+}
+
+/// Distance between two particles, using the nearest periodic image.
+pub struct DistanceVariable {
+    /// Indexes of the two particles
+    i: usize,
+    j: usize,
+}
+
+impl DistanceVariable {
+    /// Create a new collective variable for the distance between the
+    /// particles at indexes `i` and `j`.
+    pub fn new(i: usize, j: usize) -> DistanceVariable {
+        assert!(i != j, "The particles must be different in distance collective variable.");
+        DistanceVariable { i: i, j: j }
+    }
+}
+
+impl CollectiveVariable for DistanceVariable {
+    fn value(&self, system: &System) -> f64 {
+        system.distance(self.i, self.j)
+    }
+
+    fn gradient(&self, system: &System) -> Vec<(usize, Vector3D)> {
+        let rij = system.nearest_image(self.i, self.j);
+        let r = rij.norm();
+        if r == 0.0 {
+            return Vec::new();
+        }
+        let gradient = rij / r;
+        return vec![(self.i, gradient), (self.j, -gradient)];
+    }
+}
+
+/// Angle between three particles, in radians.
+pub struct AngleVariable {
+    /// Indexes of the three particles, `j` being the particle at the center
+    /// of the angle.
+    i: usize,
+    j: usize,
+    k: usize,
+}
+
+impl AngleVariable {
+    /// Create a new collective variable for the angle between the particles
+    /// at indexes `i`, `j` and `k`, `j` being the particle at the center of
+    /// the angle.
+    pub fn new(i: usize, j: usize, k: usize) -> AngleVariable {
+        assert!(
+            i != j && j != k && i != k,
+            "The particles must be different in angle collective variable."
+        );
+        AngleVariable { i: i, j: j, k: k }
+    }
+}
+
+impl CollectiveVariable for AngleVariable {
+    fn value(&self, system: &System) -> f64 {
+        system.angle(self.i, self.j, self.k)
+    }
+
+    fn gradient(&self, system: &System) -> Vec<(usize, Vector3D)> {
+        let (_, d1, d2, d3) = system.angle_and_derivatives(self.i, self.j, self.k);
+        return vec![(self.i, d1), (self.j, d2), (self.k, d3)];
+    }
+}
+
+/// Coordination number between two groups of particles.
+///
+/// The coordination number is defined as a sum over all the pairs of
+/// particles $(i, j)$ with $i$ in the first group and $j$ in the second
+/// group of a switching function of their distance:
+///
+/// $$ s = \sum_{i, j} \frac{1 - (r_{ij} / r_0)^n}{1 - (r_{ij} / r_0)^m} $$
+///
+/// The switching function goes smoothly from 1 for short distances to 0 for
+/// distances larger than $r_0$. Pairs made of the same particle twice are
+/// not included in the sum.
+pub struct CoordinationNumber {
+    /// Indexes of the particles in the first group
+    first: Vec<usize>,
+    /// Indexes of the particles in the second group
+    second: Vec<usize>,
+    /// Distance where the switching function goes from 1 to 0
+    r0: f64,
+    /// Exponent of the numerator of the switching function
+    n: i32,
+    /// Exponent of the denominator of the switching function
+    m: i32,
+}
+
+impl CoordinationNumber {
+    /// Create a new coordination number collective variable between the
+    /// `first` and `second` groups of particles, using the distance `r0`
+    /// and the exponents `n` and `m` in the switching function. Usual
+    /// values for the exponents are `n = 6` and `m = 12`.
+    pub fn new(
+        first: Vec<usize>,
+        second: Vec<usize>,
+        r0: f64,
+        n: u32,
+        m: u32,
+    ) -> CoordinationNumber {
+        assert!(r0 > 0.0, "The distance must be positive in coordination number.");
+        assert!(n > 0 && m > 0, "The exponents must be positive in coordination number.");
+        assert!(n != m, "The exponents must be different in coordination number.");
+        CoordinationNumber {
+            first: first,
+            second: second,
+            r0: r0,
+            n: n as i32,
+            m: m as i32,
+        }
+    }
+
+    /// Get the value of the switching function and of its derivative at
+    /// the distance `r`.
+    fn switching(&self, r: f64) -> (f64, f64) {
+        let x = r / self.r0;
+        let (n, m) = (f64::from(self.n), f64::from(self.m));
+        if f64::abs(x - 1.0) < 1e-8 {
+            // Use the limit of the function and its derivative for x = 1,
+            // where both the numerator and the denominator go to zero.
+            return (n / m, n * (n - m) / (2.0 * m * self.r0));
+        }
+
+        let numerator = 1.0 - x.powi(self.n);
+        let denominator = 1.0 - x.powi(self.m);
+        let value = numerator / denominator;
+        let derivative = (-n * x.powi(self.n - 1) * denominator
+                          + m * x.powi(self.m - 1) * numerator)
+                          / (denominator * denominator);
+        return (value, derivative / self.r0);
+    }
+}
+
+impl CollectiveVariable for CoordinationNumber {
+    fn value(&self, system: &System) -> f64 {
+        let mut value = 0.0;
+        for &i in &self.first {
+            for &j in &self.second {
+                if i != j {
+                    value += self.switching(system.distance(i, j)).0;
+                }
+            }
+        }
+        return value;
+    }
+
+    fn gradient(&self, system: &System) -> Vec<(usize, Vector3D)> {
+        let mut gradient = Vec::new();
+        for &i in &self.first {
+            for &j in &self.second {
+                if i == j {
+                    continue;
+                }
+                let rij = system.nearest_image(i, j);
+                let r = rij.norm();
+                if r == 0.0 {
+                    continue;
+                }
+                let (_, derivative) = self.switching(r);
+                let gradient_ij = derivative * rij / r;
+                gradient.push((i, gradient_ij));
+                gradient.push((j, -gradient_ij));
+            }
+        }
+        return gradient;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("H", [1.0, 0.2, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("H", [-0.3, 0.9, 0.4].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("H", [9.0, 0.5, 9.5].into())));
+        return system;
+    }
+
+    /// Check the gradient of the `variable` against finite differences
+    fn check_gradient(variable: &CollectiveVariable, system: &System) {
+        let mut analytic = vec![Vector3D::zero(); system.size()];
+        for (i, gradient) in variable.gradient(system) {
+            analytic[i] += gradient;
+        }
+
+        let eps = 1e-6;
+        for i in 0..system.size() {
+            for k in 0..3 {
+                let mut system = system.clone();
+                system.particles_mut().position[i][k] += eps;
+                let forward = variable.value(&system);
+                system.particles_mut().position[i][k] -= 2.0 * eps;
+                let backward = variable.value(&system);
+                let numeric = (forward - backward) / (2.0 * eps);
+                assert_relative_eq!(analytic[i][k], numeric, epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn distance() {
+        let system = testing_system();
+        let distance = DistanceVariable::new(0, 3);
+        assert_ulps_eq!(distance.value(&system), f64::sqrt(1.5));
+        check_gradient(&distance, &system);
+    }
+
+    #[test]
+    fn angle() {
+        let system = testing_system();
+        let angle = AngleVariable::new(1, 0, 2);
+        assert_ulps_eq!(angle.value(&system), system.angle(1, 0, 2));
+        check_gradient(&angle, &system);
+    }
+
+    #[test]
+    fn coordination_number() {
+        let system = testing_system();
+        let coordination = CoordinationNumber::new(vec![0], vec![1, 2, 3], 1.1, 6, 12);
+        let value = coordination.value(&system);
+        assert!(value > 1.0 && value < 3.0);
+        check_gradient(&coordination, &system);
+
+        // The same particle is never paired with itself
+        let coordination = CoordinationNumber::new(vec![1, 2], vec![1, 2], 1.1, 6, 12);
+        let expected = 2.0 * coordination.switching(system.distance(1, 2)).0;
+        assert_ulps_eq!(coordination.value(&system), expected);
+        check_gradient(&coordination, &system);
+    }
+
+    #[test]
+    fn switching_limit() {
+        let coordination = CoordinationNumber::new(vec![0], vec![1], 2.0, 6, 12);
+        let (value, derivative) = coordination.switching(2.0);
+        let (before, _) = coordination.switching(2.0 - 1e-5);
+        let (after, _) = coordination.switching(2.0 + 1e-5);
+        assert_relative_eq!(value, 0.5, epsilon = 1e-12);
+        assert_relative_eq!(derivative, (after - before) / 2e-5, epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,558 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! SHAKE, RATTLE and SETTLE algorithms, enforcing the holonomic constraints
+//! of the configuration in the integrators.
+use core::{Constraint, Matrix3, System, UnitCell, Vector3D};
+
+use super::integrators::inverse_mass;
+
+/// Relative tolerance on the constrained distances
+const TOLERANCE: f64 = 1e-10;
+/// Maximal number of iterations of the SHAKE and RATTLE algorithms
+const MAX_ITERATIONS: usize = 1000;
+
+/// A fixed distance between two particles
+struct FixedDistance {
+    /// Index of the first particle
+    i: usize,
+    /// Index of the second particle
+    j: usize,
+    /// Square of the constrained distance
+    distance2: f64,
+}
+
+/// Three particles at fixed relative positions, forming an isosceles
+/// triangle with two particles of the same mass at the base, like in rigid
+/// 3-sites water models.
+struct RigidTriangle {
+    /// Index of the particle at the apex of the triangle
+    apex: usize,
+    /// Indexes of the two particles at the base of the triangle
+    base: (usize, usize),
+    /// Mass of the particle at the apex
+    apex_mass: f64,
+    /// Mass of each one of the particles at the base
+    base_mass: f64,
+    /// Distance between the apex and the center of mass
+    ra: f64,
+    /// Distance between the base and the center of mass
+    rb: f64,
+    /// Half of the distance between the two particles at the base
+    rc: f64,
+}
+
+/// Solver for the holonomic constraints of a system, using the SHAKE
+/// algorithm (J. Comput. Phys. 23, 327 (1977)) for the positions and the
+/// RATTLE algorithm (J. Comput. Phys. 52, 24 (1983)) for the velocities.
+///
+/// All the constraints are expressed as fixed distances between pairs of
+/// particles. Angle constraints fix the distance between the two outer
+/// particles, using the constrained lengths of the two bonds if they exist
+/// and their lengths at setup otherwise. Rigid fragments fix the distances
+/// between all the pairs of particles in the fragment.
+///
+/// Rigid isosceles triangles with two particles of the same mass at the
+/// base, such as rigid 3-sites water molecules, are detected automatically
+/// and solved analytically with the SETTLE algorithm (J. Comput. Chem. 13,
+/// 952 (1992)) instead of the iterative ones. These triangles come either
+/// from rigid fragments with three particles, or from an angle constraint
+/// together with distance constraints on the two bonds of the angle.
+pub(crate) struct ConstraintSolver {
+    /// The constrained distances
+    distances: Vec<FixedDistance>,
+    /// The rigid triangles solved with SETTLE
+    triangles: Vec<RigidTriangle>,
+    /// Inverse masses of the particles, zero for frozen particles
+    inverse_masses: Vec<f64>,
+    /// Positions of the particles before the unconstrained update
+    previous: Vec<Vector3D>,
+}
+
+impl ConstraintSolver {
+    /// Create a new solver, without any constraint
+    pub fn new() -> ConstraintSolver {
+        ConstraintSolver {
+            distances: Vec::new(),
+            triangles: Vec::new(),
+            inverse_masses: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Get the constraints and the masses of the particles from the `system`
+    pub fn setup(&mut self, system: &System) {
+        self.inverse_masses = soa_zip!(system.particles(), [mass, frozen])
+            .map(|(&mass, &frozen)| inverse_mass(mass, frozen))
+            .collect();
+
+        self.distances.clear();
+        self.triangles.clear();
+        let constraints = system.constraints();
+
+        // Find the rigid triangles made of one angle and two bonds constraints
+        let mut settled = vec![false; constraints.len()];
+        for (index, constraint) in constraints.iter().enumerate() {
+            if let Constraint::Angle { i, j, k, angle } = *constraint {
+                let bond_ij = distance_constraint(&constraints, i, j);
+                let bond_jk = distance_constraint(&constraints, j, k);
+                if let (Some((bond_ij, r_ij)), Some((bond_jk, r_jk))) = (bond_ij, bond_jk) {
+                    let r_ik = third_side(r_ij, r_jk, angle);
+                    if let Some(triangle) = self.triangle((i, j, k), (r_ij, r_jk, r_ik)) {
+                        self.triangles.push(triangle);
+                        settled[index] = true;
+                        settled[bond_ij] = true;
+                        settled[bond_jk] = true;
+                    }
+                }
+            }
+        }
+
+        for (constraint, &settled) in constraints.iter().zip(&settled) {
+            if settled {
+                continue;
+            }
+
+            match *constraint {
+                Constraint::Distance { i, j, distance } => self.add(i, j, distance),
+                Constraint::Angle { i, j, k, angle } => {
+                    let r_ij = bond_length(&constraints, system, i, j);
+                    let r_jk = bond_length(&constraints, system, j, k);
+                    self.add(i, k, third_side(r_ij, r_jk, angle));
+                }
+                Constraint::Rigid { start, end } => {
+                    if end - start == 3 {
+                        let (i, j, k) = (start, start + 1, start + 2);
+                        let distances = (
+                            system.distance(i, j), system.distance(j, k), system.distance(i, k)
+                        );
+                        if let Some(triangle) = self.triangle((i, j, k), distances) {
+                            self.triangles.push(triangle);
+                            continue;
+                        }
+                    }
+
+                    for i in start..end {
+                        for j in (i + 1)..end {
+                            self.add(i, j, system.distance(i, j));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Try to create a rigid triangle from the particles `(i, j, k)` with
+    /// the distances `(r_ij, r_jk, r_ik)` between them. This returns `None`
+    /// if the triangle is not isosceles with two particles of the same mass
+    /// at the base, or if some of the particles can not move.
+    fn triangle(
+        &self,
+        (i, j, k): (usize, usize, usize),
+        (r_ij, r_jk, r_ik): (f64, f64, f64),
+    ) -> Option<RigidTriangle> {
+        if [i, j, k].iter().any(|&particle| self.inverse_masses[particle] == 0.0) {
+            return None;
+        }
+
+        // Try all the particles as the apex of the triangle
+        let candidates = [
+            (j, (i, k), r_ij, r_jk, r_ik),
+            (i, (j, k), r_ij, r_ik, r_jk),
+            (k, (i, j), r_ik, r_jk, r_ij),
+        ];
+        for &(apex, base, first, second, base_length) in &candidates {
+            let same_side = f64::abs(first - second) < 1e-6 * first;
+            let same_mass = self.inverse_masses[base.0] == self.inverse_masses[base.1];
+            if !same_side || !same_mass {
+                continue;
+            }
+
+            let side = 0.5 * (first + second);
+            let rc = 0.5 * base_length;
+            let height = f64::sqrt(side * side - rc * rc);
+            let apex_mass = 1.0 / self.inverse_masses[apex];
+            let base_mass = 1.0 / self.inverse_masses[base.0];
+            let ra = 2.0 * base_mass * height / (apex_mass + 2.0 * base_mass);
+            return Some(RigidTriangle {
+                apex: apex,
+                base: base,
+                apex_mass: apex_mass,
+                base_mass: base_mass,
+                ra: ra,
+                rb: height - ra,
+                rc: rc,
+            });
+        }
+        return None;
+    }
+
+    /// Check if there are no constraints to enforce
+    fn is_empty(&self) -> bool {
+        self.distances.is_empty() && self.triangles.is_empty()
+    }
+
+    /// Add a constraint on the `distance` between the particles `i` and
+    /// `j`. The constraint is ignored if both particles can not move.
+    fn add(&mut self, i: usize, j: usize, distance: f64) {
+        if self.inverse_masses[i] + self.inverse_masses[j] != 0.0 {
+            self.distances.push(FixedDistance {
+                i: i,
+                j: j,
+                distance2: distance * distance,
+            });
+        }
+    }
+
+    /// Store the positions of the particles in the `system`, before an
+    /// unconstrained update of the positions.
+    pub fn store_positions(&mut self, system: &System) {
+        if self.is_empty() {
+            return;
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(system.particles().position);
+    }
+
+    /// Move the particles in the `system` to satisfy the constraints, using
+    /// the SETTLE and SHAKE algorithms. The corrections are along the
+    /// constrained vectors at the positions saved by `store_positions`, and
+    /// the velocities are corrected accordingly for a position update over a
+    /// time `dt`.
+    pub fn constrain_positions(&mut self, system: &mut System, dt: f64) {
+        if self.is_empty() {
+            return;
+        }
+
+        let cell = system.cell;
+        let particles = system.particles_mut();
+        for triangle in &self.triangles {
+            triangle.constrain_positions(
+                &cell, &self.previous, &mut *particles.position, &mut *particles.velocity, dt
+            );
+        }
+
+        for _ in 0..MAX_ITERATIONS {
+            let mut converged = true;
+            for constraint in &self.distances {
+                let (i, j) = (constraint.i, constraint.j);
+                let mut r_ij = particles.position[j] - particles.position[i];
+                cell.vector_image(&mut r_ij);
+
+                let delta = constraint.distance2 - r_ij.norm2();
+                if f64::abs(delta) < 2.0 * TOLERANCE * constraint.distance2 {
+                    continue;
+                }
+                converged = false;
+
+                let mut reference = self.previous[j] - self.previous[i];
+                cell.vector_image(&mut reference);
+                let (w_i, w_j) = (self.inverse_masses[i], self.inverse_masses[j]);
+                let g = delta / (2.0 * (w_i + w_j) * (reference * r_ij));
+
+                particles.position[i] -= g * w_i * reference;
+                particles.position[j] += g * w_j * reference;
+                particles.velocity[i] -= g * w_i / dt * reference;
+                particles.velocity[j] += g * w_j / dt * reference;
+            }
+
+            if converged {
+                return;
+            }
+        }
+        warn_once!("SHAKE did not converge in {} iterations", MAX_ITERATIONS);
+    }
+
+    /// Remove the components of the velocities of the particles in the
+    /// `system` along the constraints, using the SETTLE and RATTLE
+    /// algorithms. `dt` is the timestep used to set the tolerance on the
+    /// velocities.
+    pub fn constrain_velocities(&mut self, system: &mut System, dt: f64) {
+        if self.is_empty() {
+            return;
+        }
+
+        let cell = system.cell;
+        let particles = system.particles_mut();
+        for triangle in &self.triangles {
+            triangle.constrain_velocities(&cell, &*particles.position, &mut *particles.velocity);
+        }
+
+        for _ in 0..MAX_ITERATIONS {
+            let mut converged = true;
+            for constraint in &self.distances {
+                let (i, j) = (constraint.i, constraint.j);
+                let mut r_ij = particles.position[j] - particles.position[i];
+                cell.vector_image(&mut r_ij);
+                let v_ij = particles.velocity[j] - particles.velocity[i];
+
+                let dot = r_ij * v_ij;
+                if f64::abs(dot) * dt < TOLERANCE * constraint.distance2 {
+                    continue;
+                }
+                converged = false;
+
+                let (w_i, w_j) = (self.inverse_masses[i], self.inverse_masses[j]);
+                let k = dot / ((w_i + w_j) * constraint.distance2);
+                particles.velocity[i] += k * w_i * r_ij;
+                particles.velocity[j] -= k * w_j * r_ij;
+            }
+
+            if converged {
+                return;
+            }
+        }
+        warn_once!("RATTLE did not converge in {} iterations", MAX_ITERATIONS);
+    }
+}
+
+impl RigidTriangle {
+    /// Move the particles of this triangle to their constrained positions,
+    /// using the `previous` positions to get the directions of the
+    /// constraint forces. The velocities are corrected accordingly for a
+    /// position update over a time `dt`.
+    ///
+    /// The new positions are found analytically in a frame with the `z` axis
+    /// normal to the previous plane of the triangle, by rotating the
+    /// reference triangle to match the `z` coordinates of the unconstrained
+    /// positions, and then around the `z` axis to conserve the angular
+    /// momentum.
+    fn constrain_positions(
+        &self,
+        cell: &UnitCell,
+        previous: &[Vector3D],
+        positions: &mut [Vector3D],
+        velocities: &mut [Vector3D],
+        dt: f64,
+    ) {
+        let (a, (b, c)) = (self.apex, self.base);
+        let image = |mut vector: Vector3D| {
+            cell.vector_image(&mut vector);
+            vector
+        };
+
+        // Previous positions of the base, relative to the apex
+        let b0 = image(previous[b] - previous[a]);
+        let c0 = image(previous[c] - previous[a]);
+        // Unconstrained positions, relative to the center of mass
+        let b1 = image(positions[b] - positions[a]);
+        let c1 = image(positions[c] - positions[a]);
+        let center = self.base_mass * (b1 + c1) / (self.apex_mass + 2.0 * self.base_mass);
+        let (a1, b1, c1) = (-center, b1 - center, c1 - center);
+
+        let n0 = (b0 ^ c0).normalized();
+        let n1 = (a1 ^ n0).normalized();
+        let n2 = n0 ^ n1;
+        let project = |vector: Vector3D| Vector3D::new(vector * n1, vector * n2, vector * n0);
+        let (b0, c0) = (project(b0), project(c0));
+        let (a1, b1, c1) = (project(a1), project(b1), project(c1));
+
+        // Rotate the reference triangle to match the z coordinates
+        let sin_phi = a1[2] / self.ra;
+        let cos_phi = f64::sqrt(1.0 - sin_phi * sin_phi);
+        let sin_psi = (b1[2] - c1[2]) / (2.0 * self.rc * cos_phi);
+        let cos_psi = f64::sqrt(1.0 - sin_psi * sin_psi);
+
+        let ya2 = self.ra * cos_phi;
+        let xb2 = -self.rc * cos_psi;
+        let yb2 = -self.rb * cos_phi - self.rc * sin_psi * sin_phi;
+        let yc2 = -self.rb * cos_phi + self.rc * sin_psi * sin_phi;
+
+        // Rotate around the z axis to conserve the angular momentum
+        let alpha = xb2 * (b0[0] - c0[0]) + b0[1] * yb2 + c0[1] * yc2;
+        let beta = xb2 * (c0[1] - b0[1]) + b0[0] * yb2 + c0[0] * yc2;
+        let gamma = b0[0] * b1[1] - b1[0] * b0[1] + c0[0] * c1[1] - c1[0] * c0[1];
+        let norm2 = alpha * alpha + beta * beta;
+        let sin_theta = (alpha * gamma - beta * f64::sqrt(norm2 - gamma * gamma)) / norm2;
+        let cos_theta = f64::sqrt(1.0 - sin_theta * sin_theta);
+
+        let a3 = Vector3D::new(-ya2 * sin_theta, ya2 * cos_theta, a1[2]);
+        let b3 = Vector3D::new(
+            xb2 * cos_theta - yb2 * sin_theta, xb2 * sin_theta + yb2 * cos_theta, b1[2]
+        );
+        let c3 = Vector3D::new(
+            -xb2 * cos_theta - yc2 * sin_theta, -xb2 * sin_theta + yc2 * cos_theta, c1[2]
+        );
+
+        let to_lab = |vector: Vector3D| vector[0] * n1 + vector[1] * n2 + vector[2] * n0;
+        for &(i, delta) in &[(a, to_lab(a3 - a1)), (b, to_lab(b3 - b1)), (c, to_lab(c3 - c1))] {
+            positions[i] += delta;
+            velocities[i] += delta / dt;
+        }
+    }
+
+    /// Remove the components of the velocities of the particles of this
+    /// triangle along the three sides, by solving exactly the linear system
+    /// for the three constraint forces.
+    fn constrain_velocities(
+        &self,
+        cell: &UnitCell,
+        positions: &[Vector3D],
+        velocities: &mut [Vector3D],
+    ) {
+        let (a, (b, c)) = (self.apex, self.base);
+        let sides = [(a, b), (a, c), (b, c)];
+        let inverse_mass = |i: usize| {
+            if i == a { 1.0 / self.apex_mass } else { 1.0 / self.base_mass }
+        };
+
+        let mut vectors = [Vector3D::zero(); 3];
+        let mut rhs = Vector3D::zero();
+        for (n, &(i, j)) in sides.iter().enumerate() {
+            let mut r_ij = positions[j] - positions[i];
+            cell.vector_image(&mut r_ij);
+            vectors[n] = r_ij;
+            rhs[n] = r_ij * (velocities[j] - velocities[i]);
+        }
+
+        // The force along the side `m` changes the velocity of its particles
+        // `i` and `j` by `k_m w_i r_m` and `-k_m w_j r_m` respectively.
+        let mut matrix = Matrix3::zero();
+        for (n, &(i, j)) in sides.iter().enumerate() {
+            for (m, &(p, q)) in sides.iter().enumerate() {
+                let effect = |particle: usize| {
+                    if particle == p {
+                        inverse_mass(p)
+                    } else if particle == q {
+                        -inverse_mass(q)
+                    } else {
+                        0.0
+                    }
+                };
+                matrix[n][m] = (effect(i) - effect(j)) * (vectors[n] * vectors[m]);
+            }
+        }
+
+        let k = matrix.inverse() * rhs;
+        for (m, &(p, q)) in sides.iter().enumerate() {
+            velocities[p] += k[m] * inverse_mass(p) * vectors[m];
+            velocities[q] -= k[m] * inverse_mass(q) * vectors[m];
+        }
+    }
+}
+
+/// Get the index and the value of the distance constraint between the
+/// particles `i` and `j`, if any.
+fn distance_constraint(constraints: &[Constraint], i: usize, j: usize) -> Option<(usize, f64)> {
+    for (index, constraint) in constraints.iter().enumerate() {
+        if let Constraint::Distance { i: a, j: b, distance } = *constraint {
+            if (a == i && b == j) || (a == j && b == i) {
+                return Some((index, distance));
+            }
+        }
+    }
+    return None;
+}
+
+/// Get the length of the side opposite to the `angle` in a triangle, with
+/// the two other sides of lengths `r_ij` and `r_jk`.
+fn third_side(r_ij: f64, r_jk: f64, angle: f64) -> f64 {
+    f64::sqrt(r_ij * r_ij + r_jk * r_jk - 2.0 * r_ij * r_jk * f64::cos(angle))
+}
+
+/// Get the length of the bond between the particles `i` and `j`, using the
+/// distance constraint on this bond if any, and the current distance in the
+/// `system` otherwise.
+fn bond_length(constraints: &[Constraint], system: &System, i: usize, j: usize) -> f64 {
+    match distance_constraint(constraints, i, j) {
+        Some((_, distance)) => distance,
+        None => system.distance(i, j),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+
+    fn water() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        let oxygen = Particle::with_position("O", Vector3D::new(0.0, 0.0, 0.0));
+        let mut molecule = Molecule::new(oxygen);
+        for &x in &[0.8, -0.8] {
+            let hydrogen = Particle::with_position("H", Vector3D::new(x, 0.6, 0.0));
+            molecule.add_particle_bonded_to(0, hydrogen);
+        }
+        system.add_molecule(molecule);
+        return system;
+    }
+
+    #[test]
+    fn detect_triangles() {
+        let mut system = water();
+        system.add_constraint(Constraint::Rigid { start: 0, end: 3 });
+        let mut solver = ConstraintSolver::new();
+        solver.setup(&system);
+        assert_eq!(solver.triangles.len(), 1);
+        assert!(solver.distances.is_empty());
+
+        let mut system = water();
+        system.add_constraint(Constraint::Distance { i: 0, j: 1, distance: 1.0 });
+        system.add_constraint(Constraint::Distance { i: 0, j: 2, distance: 1.0 });
+        system.add_constraint(Constraint::Angle { i: 1, j: 0, k: 2, angle: 1.8 });
+        solver.setup(&system);
+        assert_eq!(solver.triangles.len(), 1);
+        assert_eq!(solver.triangles[0].apex, 0);
+        assert!(solver.distances.is_empty());
+
+        // Bonds with different lengths use SHAKE
+        let mut system = water();
+        system.add_constraint(Constraint::Distance { i: 0, j: 1, distance: 1.0 });
+        system.add_constraint(Constraint::Distance { i: 0, j: 2, distance: 1.1 });
+        system.add_constraint(Constraint::Angle { i: 1, j: 0, k: 2, angle: 1.8 });
+        solver.setup(&system);
+        assert!(solver.triangles.is_empty());
+        assert_eq!(solver.distances.len(), 3);
+    }
+
+    #[test]
+    fn settle() {
+        let mut system = water();
+        system.add_constraint(Constraint::Rigid { start: 0, end: 3 });
+        let mut settle = ConstraintSolver::new();
+        settle.setup(&system);
+
+        // Solve the same constraints with SHAKE and RATTLE
+        let mut shake = ConstraintSolver::new();
+        shake.inverse_masses = settle.inverse_masses.clone();
+        for &(i, j) in &[(0, 1), (0, 2), (1, 2)] {
+            shake.add(i, j, system.distance(i, j));
+        }
+        let mut reference = system.clone();
+
+        settle.store_positions(&system);
+        shake.store_positions(&reference);
+        let deltas = [
+            Vector3D::new(0.01, -0.02, 0.03),
+            Vector3D::new(-0.03, 0.05, 0.02),
+            Vector3D::new(0.04, 0.01, -0.05),
+        ];
+        for (i, &delta) in deltas.iter().enumerate() {
+            system.particles_mut().position[i] += delta;
+            reference.particles_mut().position[i] += delta;
+        }
+
+        settle.constrain_positions(&mut system, 1.0);
+        shake.constrain_positions(&mut reference, 1.0);
+        assert_relative_eq!(system.distance(0, 1), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(system.distance(0, 2), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(system.distance(1, 2), 1.6, epsilon = 1e-12);
+        for i in 0..3 {
+            let position = system.particles().position[i];
+            assert_relative_eq!(position, reference.particles().position[i], epsilon = 1e-8);
+            let velocity = system.particles().velocity[i];
+            assert_relative_eq!(velocity, reference.particles().velocity[i], epsilon = 1e-8);
+        }
+
+        for (i, &delta) in deltas.iter().enumerate() {
+            system.particles_mut().velocity[i] = delta;
+            reference.particles_mut().velocity[i] = delta;
+        }
+        settle.constrain_velocities(&mut system, 1.0);
+        shake.constrain_velocities(&mut reference, 1.0);
+        for i in 0..3 {
+            let velocity = system.particles().velocity[i];
+            assert_relative_eq!(velocity, reference.particles().velocity[i], epsilon = 1e-8);
+        }
+    }
+}
@@ -4,13 +4,21 @@
 //! While running a simulation, we often want to have control over some
 //! simulation parameters: the temperature, the pressure, etc. This is the goal
 //! of the control algorithms, all implementing of the `Control` trait.
+use rand::XorShiftRng;
+use rand::SeedableRng;
+use rand::distributions::{ChiSquared, Distribution, Normal};
+
 use core::System;
 use core::{Matrix3, Vector3D};
+use core::consts::K_BOLTZMANN;
+
+use checkpoint::{Checkpoint, CheckpointError};
+use rng::rng_from_seed;
 
-use velocities;
+use super::chains::NoseHooverChain;
 
 /// Trait for controlling some parameters in a system during a simulation.
-pub trait Control {
+pub trait Control: Send {
     /// Function called once at the beginning of the simulation, which allow
     /// for some setup of the control algorithm if needed.
     fn setup(&mut self, _: &System) {}
@@ -20,10 +28,63 @@ pub trait Control {
 
     /// Function called once at the end of the simulation.
     fn finish(&mut self, _: &System) {}
+
+    /// Get the number of degrees of freedom removed from the `system` by
+    /// this control, for example by removing the global translation. These
+    /// degrees of freedom are not used when computing the temperature.
+    fn removed_degrees_of_freedom(&self, _: &System) -> usize {
+        0
+    }
+
+    /// Save the internal state of this control in the `checkpoint`, to be
+    /// able to restart the simulation exactly. The default implementation
+    /// does nothing, which is fine for controls without internal state.
+    fn save_checkpoint(&mut self, _: &mut Checkpoint) {}
+
+    /// Restore the internal state of this control from the `checkpoint`.
+    /// This function is called after `setup` when restarting a simulation.
+    fn restore_checkpoint(&mut self, _: &Checkpoint) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
 
 /// Trait for controls usable as thermostats
-pub trait Thermostat: Control {}
+pub trait Thermostat: Control {
+    /// Set the target temperature of this thermostat
+    fn set_temperature(&mut self, temperature: f64);
+
+    /// Get the factor used to scale the velocities of a set of particles
+    /// with `dof` degrees of freedom and a total `kinetic` energy, updating
+    /// the internal state of the thermostat if needed. This is used to apply
+    /// the thermostat to a subset of the particles only.
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64;
+
+    /// Get the contribution of this thermostat to the conserved energy of
+    /// the simulation, i.e. the energy of the thermostat degrees of freedom
+    /// or the energy exchanged with the heat bath. The default
+    /// implementation returns `None` for thermostats without a conserved
+    /// quantity.
+    fn conserved_energy(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Scale all the velocities in the `system` with the factor given by the
+/// `thermostat`.
+fn scale_velocities<T: Thermostat + ?Sized>(thermostat: &mut T, system: &mut System) {
+    let kinetic = system.kinetic_energy();
+    let dof = system.degrees_of_freedom() as f64;
+    let factor = thermostat.scaling_factor(kinetic, dof);
+    for velocity in system.particles_mut().velocity {
+        *velocity *= factor;
+    }
+}
+
+/// Get the instant temperature of a set of particles with `dof` degrees of
+/// freedom and a total `kinetic` energy.
+fn instant_temperature(kinetic: f64, dof: f64) -> f64 {
+    2.0 * kinetic / (dof * K_BOLTZMANN)
+}
 
 /// Velocity rescaling thermostat.
 ///
@@ -60,15 +121,29 @@ impl RescaleThermostat {
 
 impl Control for RescaleThermostat {
     fn control(&mut self, system: &mut System) {
-        let instant_temperature = system.temperature();
+        scale_velocities(self, system);
+    }
+}
+
+impl Thermostat for RescaleThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        assert!(temperature >= 0.0, "The temperature must be positive in thermostats.");
+        self.temperature = temperature;
+    }
+
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64 {
+        if kinetic == 0.0 || dof == 0.0 {
+            return 1.0;
+        }
+        let instant_temperature = instant_temperature(kinetic, dof);
         if f64::abs(instant_temperature - self.temperature) > self.tol {
-            velocities::scale(system, self.temperature);
+            f64::sqrt(self.temperature / instant_temperature)
+        } else {
+            1.0
         }
     }
 }
 
-impl Thermostat for RescaleThermostat {}
-
 /// Berendsen thermostat.
 ///
 /// The Berendsen thermostat sets the simulation temperature by exponentially
@@ -99,15 +174,616 @@ impl BerendsenThermostat {
 
 impl Control for BerendsenThermostat {
     fn control(&mut self, system: &mut System) {
-        let instant_temperature = system.temperature();
-        let factor =
-            f64::sqrt(1.0 + 1.0 / self.tau * (self.temperature / instant_temperature - 1.0));
-        for velocity in system.particles_mut().velocity {
-            *velocity *= factor;
+        scale_velocities(self, system);
+    }
+}
+
+impl Thermostat for BerendsenThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        assert!(temperature >= 0.0, "The temperature must be positive in thermostats.");
+        self.temperature = temperature;
+    }
+
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64 {
+        if kinetic == 0.0 || dof == 0.0 {
+            return 1.0;
+        }
+        let instant_temperature = instant_temperature(kinetic, dof);
+        f64::sqrt(1.0 + 1.0 / self.tau * (self.temperature / instant_temperature - 1.0))
+    }
+}
+
+/// Nosé-Hoover chains thermostat.
+///
+/// This thermostat couples the particles to a chain of additional degrees of
+/// freedom, and samples the canonical ensemble [1]. Using a chain of
+/// thermostats instead of a single one fixes the lack of ergodicity of the
+/// original Nosé-Hoover algorithm for small or stiff systems. The equations of
+/// motion of the chain are integrated with the reversible scheme from [2],
+/// using a Suzuki-Yoshida factorization of the chain propagator.
+///
+/// The masses of the thermostats are `Q_1 = N_f k_B T tau^2` for the first
+/// thermostat in the chain, and `Q_i = k_B T tau^2` for the other ones; where
+/// `N_f` is the number of degrees of freedom in the system and `tau` the
+/// relaxation time of the thermostat.
+///
+/// [1] G.J. Martyna, M.L. Klein and M. Tuckerman, J. Chem. Phys. 97, 2635
+/// (1992); doi: 10.1063/1.463940
+///
+/// [2] G.J. Martyna, et al. Mol. Phys. 87, 1117 (1996); doi:
+/// 10.1080/00268979600100761
+pub struct NoseHooverChainsThermostat {
+    /// The chain of thermostats, with times expressed in units of the
+    /// integrator timestep.
+    chain: NoseHooverChain,
+    /// Number of degrees of freedom coupled to the chain at the last step
+    dof: f64,
+}
+
+impl NoseHooverChainsThermostat {
+    /// Create a new `NoseHooverChainsThermostat` acting at temperature `T`,
+    /// with a relaxation time of `tau` times the integrator timestep. The
+    /// chain contains three thermostats, and is integrated with three
+    /// Suzuki-Yoshida steps.
+    pub fn new(temperature: f64, tau: f64) -> NoseHooverChainsThermostat {
+        assert!(
+            temperature > 0.0,
+            "The temperature must be positive in Nosé-Hoover thermostat."
+        );
+        assert!(tau > 0.0, "The timestep must be positive in Nosé-Hoover thermostat.");
+        NoseHooverChainsThermostat {
+            chain: NoseHooverChain::new(temperature, tau),
+            dof: 0.0,
+        }
+    }
+
+    /// Set the number of thermostats in the chain to `length`, and reset the
+    /// state of the chain. A chain of length 1 is the original Nosé-Hoover
+    /// thermostat.
+    pub fn set_chain_length(&mut self, length: usize) {
+        self.chain.set_length(length);
+    }
+
+    /// Set the number of Suzuki-Yoshida steps used to integrate the chain at
+    /// each timestep. The possible values are 1, 3, 5 and 7, giving
+    /// respectively a second, fourth, fourth and sixth order integration.
+    pub fn set_suzuki_yoshida_steps(&mut self, steps: usize) {
+        self.chain.set_suzuki_yoshida_steps(steps);
+    }
+
+    /// Get the energy of the thermostats in the chain. The sum of this energy
+    /// and the total energy of the `system` is conserved during the
+    /// simulation, and can be used to check the stability of the integration.
+    pub fn energy(&self, system: &System) -> f64 {
+        self.chain.energy(system.degrees_of_freedom() as f64)
+    }
+}
+
+impl Control for NoseHooverChainsThermostat {
+    fn control(&mut self, system: &mut System) {
+        scale_velocities(self, system);
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("dof", self.dof);
+        self.chain.save_checkpoint(checkpoint.section_mut("chain"));
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.dof = checkpoint.value("dof")?;
+        self.chain.restore_checkpoint(checkpoint.section("chain")?)
+    }
+}
+
+impl Thermostat for NoseHooverChainsThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        assert!(
+            temperature > 0.0,
+            "The temperature must be positive in Nosé-Hoover thermostat."
+        );
+        self.chain.set_temperature(temperature);
+    }
+
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64 {
+        self.dof = dof;
+        self.chain.propagate(2.0 * kinetic, dof, 1.0)
+    }
+
+    fn conserved_energy(&self) -> Option<f64> {
+        Some(self.chain.energy(self.dof))
+    }
+}
+
+/// Stochastic velocity rescaling thermostat.
+///
+/// This thermostat, also known as the Bussi-Donadio-Parrinello or CSVR
+/// thermostat, rescales all the velocities like the Berendsen thermostat, but
+/// adds a stochastic term to the evolution of the kinetic energy, so that the
+/// kinetic energy follows the canonical distribution [1].
+///
+/// The energy given to the system by the thermostat is tracked, and the sum
+/// of the system total energy and the thermostat [`energy`](#method.energy)
+/// is a conserved quantity which can be used to check the simulation.
+///
+/// [1] G. Bussi, D. Donadio and M. Parrinello, J. Chem. Phys. 126, 014101
+/// (2007); doi: 10.1063/1.2408420
+pub struct CSVRThermostat {
+    /// Target temperature
+    temperature: f64,
+    /// Relaxation time of the thermostat, expressed as a multiplicative factor
+    /// of the integrator timestep.
+    tau: f64,
+    /// Energy exchanged with the heat bath
+    energy: f64,
+    /// Normal distribution for the stochastic term
+    dist: Normal,
+    /// Random number generator for the stochastic term
+    rng: XorShiftRng,
+}
+
+impl CSVRThermostat {
+    /// Create a new `CSVRThermostat` acting at temperature `T`, with a
+    /// relaxation time of `tau` times the integrator timestep.
+    pub fn new(temperature: f64, tau: f64) -> CSVRThermostat {
+        assert!(temperature >= 0.0, "The temperature must be positive in thermostats.");
+        assert!(tau >= 0.0, "The timestep must be positive in CSVR thermostat.");
+        CSVRThermostat {
+            temperature: temperature,
+            tau: tau,
+            energy: 0.0,
+            dist: Normal::new(0.0, 1.0),
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+        }
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Get the energy removed from the system by the thermostat since the
+    /// beginning of the simulation. The sum of this energy and the total
+    /// energy of the system is conserved during the simulation.
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+
+    /// Get the sum of the squares of `n` independent normal random numbers.
+    /// Non-integer values of `n` are allowed, and use the corresponding
+    /// chi-squared distribution.
+    fn sum_of_squares(&mut self, n: f64) -> f64 {
+        if n <= 0.0 {
+            0.0
+        } else {
+            ChiSquared::new(n).sample(&mut self.rng)
         }
     }
 }
-impl Thermostat for BerendsenThermostat {}
+
+impl Control for CSVRThermostat {
+    fn control(&mut self, system: &mut System) {
+        scale_velocities(self, system);
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("energy", self.energy);
+        checkpoint.save_rng("rng", &self.rng);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.energy = checkpoint.value("energy")?;
+        self.rng = checkpoint.rng("rng")?;
+        Ok(())
+    }
+}
+
+impl Thermostat for CSVRThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        assert!(temperature >= 0.0, "The temperature must be positive in thermostats.");
+        self.temperature = temperature;
+    }
+
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64 {
+        if dof == 0.0 || kinetic == 0.0 {
+            return 1.0;
+        }
+
+        let target = 0.5 * dof * K_BOLTZMANN * self.temperature;
+        let ratio = target / (dof * kinetic);
+        let damping = f64::exp(-1.0 / self.tau);
+
+        let r1 = self.dist.sample(&mut self.rng);
+        let squares = self.sum_of_squares(dof - 1.0);
+        let mut factor = damping;
+        factor += (1.0 - damping) * (squares + r1 * r1) * ratio;
+        factor += 2.0 * r1 * f64::sqrt(damping * (1.0 - damping) * ratio);
+        let mut factor = f64::sqrt(factor);
+        // The sign of the factor is needed for reversibility in the rare
+        // cases where the kinetic energy goes through zero
+        if r1 + f64::sqrt(damping / ((1.0 - damping) * ratio)) < 0.0 {
+            factor = -factor;
+        }
+
+        self.energy += kinetic * (1.0 - factor * factor);
+        return factor;
+    }
+
+    fn conserved_energy(&self) -> Option<f64> {
+        Some(self.energy)
+    }
+}
+
+/// Evolution of the target temperature of a thermostat during a simulation,
+/// used for example for simulated annealing. All the durations are expressed
+/// in number of steps.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemperatureSchedule {
+    /// Constant temperature
+    Constant(f64),
+    /// Linear ramp from the `start` to the `end` temperature during `steps`
+    /// steps. The temperature stays at `end` after the ramp.
+    Linear {
+        /// Initial temperature
+        start: f64,
+        /// Final temperature
+        end: f64,
+        /// Duration of the ramp
+        steps: u64,
+    },
+    /// Exponential decay from the `start` to the `end` temperature, with a
+    /// characteristic time of `tau` steps: `T(n) = end + (start - end)
+    /// exp(-n / tau)`.
+    Exponential {
+        /// Initial temperature
+        start: f64,
+        /// Final temperature
+        end: f64,
+        /// Characteristic time of the decay
+        tau: f64,
+    },
+    /// Piecewise linear schedule, going through the given `(step,
+    /// temperature)` points, sorted by step. The temperature is constant
+    /// before the first point and after the last one.
+    Piecewise(Vec<(u64, f64)>),
+}
+
+impl TemperatureSchedule {
+    /// Get the target temperature at the given `step`
+    pub fn temperature(&self, step: u64) -> f64 {
+        match *self {
+            TemperatureSchedule::Constant(temperature) => temperature,
+            TemperatureSchedule::Linear { start, end, steps } => {
+                if step >= steps {
+                    end
+                } else {
+                    start + (end - start) * step as f64 / steps as f64
+                }
+            }
+            TemperatureSchedule::Exponential { start, end, tau } => {
+                end + (start - end) * f64::exp(-(step as f64) / tau)
+            }
+            TemperatureSchedule::Piecewise(ref points) => {
+                let next = points.iter().position(|&(point, _)| point > step);
+                match next {
+                    Some(0) => points[0].1,
+                    Some(i) => {
+                        let (step_0, temperature_0) = points[i - 1];
+                        let (step_1, temperature_1) = points[i];
+                        let fraction = (step - step_0) as f64 / (step_1 - step_0) as f64;
+                        temperature_0 + fraction * (temperature_1 - temperature_0)
+                    }
+                    None => points[points.len() - 1].1,
+                }
+            }
+        }
+    }
+
+    /// Check that this schedule is valid, i.e. that all the temperatures are
+    /// positive and that the duration and points are correct.
+    fn validate(&self) {
+        match *self {
+            TemperatureSchedule::Constant(temperature) => {
+                assert!(temperature >= 0.0, "The temperature must be positive in schedule.");
+            }
+            TemperatureSchedule::Linear { start, end, .. } => {
+                assert!(
+                    start >= 0.0 && end >= 0.0,
+                    "The temperature must be positive in schedule."
+                );
+            }
+            TemperatureSchedule::Exponential { start, end, tau } => {
+                assert!(
+                    start >= 0.0 && end >= 0.0,
+                    "The temperature must be positive in schedule."
+                );
+                assert!(tau > 0.0, "The decay time must be positive in exponential schedule.");
+            }
+            TemperatureSchedule::Piecewise(ref points) => {
+                assert!(!points.is_empty(), "Piecewise schedule needs at least one point.");
+                for window in points.windows(2) {
+                    assert!(
+                        window[0].0 < window[1].0,
+                        "The points of piecewise schedule must be sorted by increasing step."
+                    );
+                }
+                for &(_, temperature) in points {
+                    assert!(temperature >= 0.0, "The temperature must be positive in schedule.");
+                }
+            }
+        }
+    }
+}
+
+/// Thermostat wrapper following a [`TemperatureSchedule`] instead of a fixed
+/// target temperature.
+///
+/// The target temperature of the wrapped thermostat is updated before each
+/// step, using the number of steps since the beginning of the simulation.
+/// This can be used for simulated annealing, by slowly decreasing the
+/// temperature, or to heat a system before an equilibration.
+///
+/// [`TemperatureSchedule`]: enum.TemperatureSchedule.html
+pub struct ScheduledThermostat {
+    /// The wrapped thermostat
+    thermostat: Box<Thermostat>,
+    /// Evolution of the target temperature
+    schedule: TemperatureSchedule,
+    /// Number of steps since the beginning of the simulation
+    step: u64,
+}
+
+impl ScheduledThermostat {
+    /// Create a new `ScheduledThermostat`, setting the target temperature of
+    /// the `thermostat` at each step from the `schedule`.
+    ///
+    /// # Panics
+    ///
+    /// If the schedule contains negative temperatures or negative durations,
+    /// or if the points of a piecewise schedule are not sorted.
+    pub fn new(thermostat: Box<Thermostat>, schedule: TemperatureSchedule) -> ScheduledThermostat {
+        schedule.validate();
+        ScheduledThermostat {
+            thermostat: thermostat,
+            schedule: schedule,
+            step: 0,
+        }
+    }
+
+    /// Get the current target temperature
+    pub fn temperature(&self) -> f64 {
+        self.schedule.temperature(self.step)
+    }
+}
+
+impl Control for ScheduledThermostat {
+    fn setup(&mut self, system: &System) {
+        self.step = 0;
+        self.thermostat.set_temperature(self.schedule.temperature(0));
+        self.thermostat.setup(system);
+    }
+
+    fn control(&mut self, system: &mut System) {
+        self.thermostat.set_temperature(self.schedule.temperature(self.step));
+        self.thermostat.control(system);
+        self.step += 1;
+    }
+
+    fn finish(&mut self, system: &System) {
+        self.thermostat.finish(system);
+    }
+
+    fn removed_degrees_of_freedom(&self, system: &System) -> usize {
+        self.thermostat.removed_degrees_of_freedom(system)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("step", self.step as f64);
+        self.thermostat.save_checkpoint(checkpoint.section_mut("thermostat"));
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.step = checkpoint.value("step")? as u64;
+        self.thermostat.restore_checkpoint(checkpoint.section("thermostat")?)
+    }
+}
+
+impl Thermostat for ScheduledThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        self.schedule = TemperatureSchedule::Constant(temperature);
+        self.schedule.validate();
+    }
+
+    fn scaling_factor(&mut self, kinetic: f64, dof: f64) -> f64 {
+        self.thermostat.set_temperature(self.schedule.temperature(self.step));
+        let factor = self.thermostat.scaling_factor(kinetic, dof);
+        self.step += 1;
+        return factor;
+    }
+
+    fn conserved_energy(&self) -> Option<f64> {
+        self.thermostat.conserved_energy()
+    }
+}
+
+/// Thermostat acting separately on different groups of particles.
+///
+/// Each group of particles is coupled to its own thermostat, which only sees
+/// the kinetic energy and the degrees of freedom of the particles in the
+/// group. This allows to use different target temperatures or different
+/// thermostats for different parts of the system, for example a solute and a
+/// solvent, or the walls of a nano-channel. The particles which are not part
+/// of any group are not thermostatted.
+///
+/// The degrees of freedom of a group are the ones of the moving particles in
+/// the group, minus the ones removed by the constraints. A constraint between
+/// particles from different groups is counted in the group of its first
+/// moving particle. The degrees of freedom removed globally from the system,
+/// for example by removing the global translation, are shared between all
+/// the particles proportionally.
+pub struct GroupThermostat {
+    /// The groups of particles, with the associated thermostat
+    groups: Vec<(Vec<usize>, Box<Thermostat>)>,
+}
+
+impl GroupThermostat {
+    /// Create a new `GroupThermostat` without any group.
+    pub fn new() -> GroupThermostat {
+        GroupThermostat {
+            groups: Vec::new(),
+        }
+    }
+
+    /// Add a group containing the particles at indexes `particles`, and
+    /// controlled by the given `thermostat`. A particle can only be part of
+    /// one group.
+    pub fn add_group(&mut self, particles: Vec<usize>, thermostat: Box<Thermostat>) {
+        for &i in &particles {
+            let existing = self.groups.iter().any(|&(ref group, _)| group.contains(&i));
+            assert!(!existing, "The particle {} is already part of a thermostat group.", i);
+        }
+        self.groups.push((particles, thermostat));
+    }
+
+    /// Get the number of degrees of freedom of each group in the `system`,
+    /// in the order the groups were added.
+    pub fn degrees_of_freedom(&self, system: &System) -> Vec<f64> {
+        let dimensions = system.dimensions();
+        let mut assigned = vec![None; system.size()];
+        for (group, &(ref particles, _)) in self.groups.iter().enumerate() {
+            for &i in particles {
+                assert!(
+                    i < system.size(),
+                    "The particle index {} in thermostat group is out of bounds.", i
+                );
+                assigned[i] = Some(group);
+            }
+        }
+
+        // Virtual sites and frozen particles do not have any degree of freedom
+        let particles = system.particles();
+        let moving = |i: usize| particles.mass[i] != 0.0 && !particles.frozen[i];
+
+        let mut dof = vec![0.0; self.groups.len()];
+        let mut total = 0.0;
+        for i in 0..system.size() {
+            if moving(i) {
+                total += dimensions as f64;
+                if let Some(group) = assigned[i] {
+                    dof[group] += dimensions as f64;
+                }
+            }
+        }
+
+        for constraint in system.constraints() {
+            let constrained = constraint.particles();
+            if constrained.iter().all(|&i| particles.frozen[i]) {
+                continue;
+            }
+            let removed = constraint.removed_degrees_of_freedom_in(dimensions) as f64;
+            total -= removed;
+
+            let first = constrained.iter().find(|&&i| !particles.frozen[i]).expect("Unreachable");
+            if constrained.iter().any(|&i| moving(i) && assigned[i] != assigned[*first]) {
+                warn_once!("A constraint contains particles from different thermostat groups");
+            }
+            if let Some(group) = assigned[*first] {
+                dof[group] -= removed;
+            }
+        }
+
+        // Share the degrees of freedom removed from the whole system
+        let factor = if total > 0.0 {
+            system.degrees_of_freedom() as f64 / total
+        } else {
+            0.0
+        };
+        return dof.iter().map(|&dof| f64::max(factor * dof, 0.0)).collect();
+    }
+
+    /// Get the instant temperature of each group in the `system`, in the
+    /// order the groups were added.
+    pub fn temperatures(&self, system: &System) -> Vec<f64> {
+        let dof = self.degrees_of_freedom(system);
+        self.groups.iter()
+                   .zip(dof)
+                   .map(|(&(ref particles, _), dof)| {
+                       instant_temperature(kinetic_energy(system, particles), dof)
+                   })
+                   .collect()
+    }
+}
+
+/// Get the kinetic energy of the particles at indexes `group` in the `system`
+fn kinetic_energy(system: &System, group: &[usize]) -> f64 {
+    let particles = system.particles();
+    let mut energy = 0.0;
+    for &i in group {
+        energy += 0.5 * particles.mass[i] * particles.velocity[i].norm2();
+    }
+    return energy;
+}
+
+impl Control for GroupThermostat {
+    fn setup(&mut self, system: &System) {
+        for &mut (_, ref mut thermostat) in &mut self.groups {
+            thermostat.setup(system);
+        }
+    }
+
+    fn control(&mut self, system: &mut System) {
+        let dof = self.degrees_of_freedom(system);
+        for (&mut (ref particles, ref mut thermostat), dof) in self.groups.iter_mut().zip(dof) {
+            let kinetic = kinetic_energy(system, particles);
+            let factor = thermostat.scaling_factor(kinetic, dof);
+            let velocities = system.particles_mut().velocity;
+            for &i in particles {
+                velocities[i] *= factor;
+            }
+        }
+    }
+
+    fn finish(&mut self, system: &System) {
+        for &mut (_, ref mut thermostat) in &mut self.groups {
+            thermostat.finish(system);
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        for (i, &mut (_, ref mut thermostat)) in self.groups.iter_mut().enumerate() {
+            thermostat.save_checkpoint(checkpoint.section_mut(&i.to_string()));
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        for (i, &mut (_, ref mut thermostat)) in self.groups.iter_mut().enumerate() {
+            thermostat.restore_checkpoint(checkpoint.section(&i.to_string())?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Thermostat for GroupThermostat {
+    fn set_temperature(&mut self, temperature: f64) {
+        for &mut (_, ref mut thermostat) in &mut self.groups {
+            thermostat.set_temperature(temperature);
+        }
+    }
+
+    fn scaling_factor(&mut self, _: f64, _: f64) -> f64 {
+        panic!("Group thermostats can not be used inside other thermostats.");
+    }
+
+    fn conserved_energy(&self) -> Option<f64> {
+        let mut energy = 0.0;
+        for &(_, ref thermostat) in &self.groups {
+            energy += thermostat.conserved_energy()?;
+        }
+        return Some(energy);
+    }
+}
 
 /// Remove global translation from the system
 pub struct RemoveTranslation;
@@ -121,17 +797,31 @@ impl RemoveTranslation {
 
 impl Control for RemoveTranslation {
     fn control(&mut self, system: &mut System) {
-        let total_mass = system.particles().mass.iter().sum();
+        // Frozen particles are not moving, and are not included here
+        let mut total_mass = 0.0;
+        let mut momentum = Vector3D::zero();
+        for (&mass, &frozen, velocity) in soa_zip!(system.particles(), [mass, frozen, velocity]) {
+            if !frozen {
+                total_mass += mass;
+                momentum += velocity * mass;
+            }
+        }
 
-        let mut com_velocity = Vector3D::zero();
-        for (&mass, velocity) in soa_zip!(system.particles(), [mass, velocity]) {
-            com_velocity += velocity * mass / total_mass;
+        if total_mass == 0.0 {
+            return;
         }
 
-        for velocity in system.particles_mut().velocity {
-            *velocity -= com_velocity;
+        let com_velocity = momentum / total_mass;
+        for (velocity, &frozen) in soa_zip!(system.particles_mut(), [mut velocity, frozen]) {
+            if !frozen {
+                *velocity -= com_velocity;
+            }
         }
     }
+
+    fn removed_degrees_of_freedom(&self, system: &System) -> usize {
+        system.dimensions()
+    }
 }
 
 /// Remove global rotation from the system
@@ -146,13 +836,31 @@ impl RemoveRotation {
 
 impl Control for RemoveRotation {
     fn control(&mut self, system: &mut System) {
-        // Center-of-mass
-        let com = system.center_of_mass();
+        // Center-of-mass, frozen particles are not moving and are not
+        // included here
+        let mut total_mass = 0.0;
+        let mut com = Vector3D::zero();
+        for (&mass, &frozen, position) in soa_zip!(system.particles(), [mass, frozen, position]) {
+            if !frozen {
+                total_mass += mass;
+                com += mass * position;
+            }
+        }
+
+        if total_mass == 0.0 {
+            return;
+        }
+        com /= total_mass;
 
         // Angular momentum
         let mut moment = Vector3D::zero();
         let mut inertia = Matrix3::zero();
-        for (&mass, position, velocity) in soa_zip!(system.particles(), [mass, position, velocity]) {
+        for (&mass, &frozen, position, velocity) in soa_zip!(
+            system.particles(), [mass, frozen, position, velocity]
+        ) {
+            if frozen {
+                continue;
+            }
             let delta = position - com;
             moment += mass * (delta ^ velocity);
             inertia += -mass * delta.tensorial(&delta);
@@ -166,14 +874,25 @@ impl Control for RemoveRotation {
         // The angular velocity omega is defined by `L = I w` with L the angular
         // momentum, and I the inertia matrix.
         let angular = inertia.inverse() * moment;
-        for (position, velocity) in soa_zip!(system.particles_mut(), [position, mut velocity]) {
-            *velocity -= (position - com) ^ angular;
+        for (position, &frozen, velocity) in soa_zip!(
+            system.particles_mut(), [position, frozen, mut velocity]
+        ) {
+            if !frozen {
+                *velocity -= (position - com) ^ angular;
+            }
         }
     }
+
+    fn removed_degrees_of_freedom(&self, system: &System) -> usize {
+        // There is a single rotation axis in two dimensions
+        if system.dimensions() == 2 { 1 } else { 3 }
+    }
 }
 
 /// Rewrap all molecules' centers of mass to lie within the unit cell.
-/// Individual atoms in a molecule may still lie outside of the cell.
+/// Individual atoms in a molecule may still lie outside of the cell. The
+/// periodic images crossed by the particles are tracked, and the unwrapped
+/// positions are still available with `Configuration::unwrapped_positions`.
 pub struct Rewrap;
 
 impl Rewrap {
@@ -195,7 +914,7 @@ impl Control for Rewrap {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use core::{Particle, Molecule, System, UnitCell};
+    use core::{Constraint, DegreesOfFreedom, Particle, Molecule, System, UnitCell};
     use velocities::{BoltzmannVelocities, InitVelocities};
 
     fn testing_system() -> System {
@@ -247,6 +966,222 @@ mod tests {
         assert_ulps_eq!(temperature, 250.0, epsilon = 1e-9);
     }
 
+    #[test]
+    fn nose_hoover_chains_thermostat() {
+        let mut system = testing_system();
+        let mut thermostat = NoseHooverChainsThermostat::new(250.0, 100.0);
+        let initial = system.kinetic_energy() + thermostat.energy(&system);
+
+        let mut average = 0.0;
+        for _ in 0..3000 {
+            thermostat.control(&mut system);
+            average += system.temperature() / 3000.0;
+        }
+        assert!(f64::abs(average - 250.0) < 5.0);
+
+        // The extended energy is conserved
+        let energy = system.kinetic_energy() + thermostat.energy(&system);
+        assert_relative_eq!(energy, initial, max_relative = 1e-7);
+
+        let mut system = testing_system();
+        let mut thermostat = NoseHooverChainsThermostat::new(250.0, 100.0);
+        thermostat.set_chain_length(4);
+        thermostat.set_suzuki_yoshida_steps(7);
+        let initial = system.kinetic_energy() + thermostat.energy(&system);
+        for _ in 0..3000 {
+            thermostat.control(&mut system);
+        }
+        let energy = system.kinetic_energy() + thermostat.energy(&system);
+        assert_relative_eq!(energy, initial, max_relative = 1e-10);
+    }
+
+    #[test]
+    fn csvr_thermostat() {
+        let mut system = testing_system();
+        let initial = system.kinetic_energy();
+        let mut thermostat = CSVRThermostat::new(250.0, 10.0);
+
+        let mut temperatures = Vec::new();
+        for step in 0..6000 {
+            thermostat.control(&mut system);
+            if step >= 1000 {
+                temperatures.push(system.temperature());
+            }
+        }
+
+        // The temperature follows the canonical distribution
+        let n = temperatures.len() as f64;
+        let mean = temperatures.iter().sum::<f64>() / n;
+        let variance = temperatures.iter().map(|t| (t - mean) * (t - mean)).sum::<f64>() / n;
+        let expected = f64::sqrt(2.0 / system.degrees_of_freedom() as f64);
+        assert!(f64::abs(mean - 250.0) / 250.0 < 1e-2);
+        assert!(f64::abs(f64::sqrt(variance) / mean - expected) / expected < 0.15);
+
+        // The energy exchanged with the heat bath is tracked
+        let energy = system.kinetic_energy() + thermostat.energy();
+        assert_relative_eq!(energy, initial, max_relative = 1e-10);
+    }
+
+    #[test]
+    fn temperature_schedules() {
+        let schedule = TemperatureSchedule::Constant(300.0);
+        assert_eq!(schedule.temperature(0), 300.0);
+        assert_eq!(schedule.temperature(1000), 300.0);
+
+        let schedule = TemperatureSchedule::Linear { start: 500.0, end: 300.0, steps: 100 };
+        assert_eq!(schedule.temperature(0), 500.0);
+        assert_eq!(schedule.temperature(25), 450.0);
+        assert_eq!(schedule.temperature(100), 300.0);
+        assert_eq!(schedule.temperature(1000), 300.0);
+
+        let schedule = TemperatureSchedule::Exponential { start: 500.0, end: 300.0, tau: 100.0 };
+        assert_eq!(schedule.temperature(0), 500.0);
+        let expected = 300.0 + 200.0 / f64::exp(1.0);
+        assert_relative_eq!(schedule.temperature(100), expected, epsilon = 1e-12);
+        assert_relative_eq!(schedule.temperature(100_000), 300.0);
+
+        let schedule = TemperatureSchedule::Piecewise(
+            vec![(10, 300.0), (20, 500.0), (40, 400.0)]
+        );
+        assert_eq!(schedule.temperature(0), 300.0);
+        assert_eq!(schedule.temperature(10), 300.0);
+        assert_eq!(schedule.temperature(15), 400.0);
+        assert_eq!(schedule.temperature(20), 500.0);
+        assert_eq!(schedule.temperature(30), 450.0);
+        assert_eq!(schedule.temperature(40), 400.0);
+        assert_eq!(schedule.temperature(100), 400.0);
+    }
+
+    #[test]
+    fn scheduled_thermostat() {
+        let mut system = testing_system();
+        let rescale = Box::new(RescaleThermostat::with_tolerance(300.0, 0.0));
+        let schedule = TemperatureSchedule::Linear { start: 300.0, end: 200.0, steps: 10 };
+        let mut thermostat = ScheduledThermostat::new(rescale, schedule);
+        thermostat.setup(&system);
+
+        for step in 0..20 {
+            assert_eq!(thermostat.temperature(), f64::max(300.0 - 10.0 * step as f64, 200.0));
+            thermostat.control(&mut system);
+            let expected = f64::max(300.0 - 10.0 * step as f64, 200.0);
+            assert_ulps_eq!(system.temperature(), expected, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "must be sorted by increasing step")]
+    fn unsorted_piecewise_schedule() {
+        let rescale = Box::new(RescaleThermostat::new(300.0));
+        let schedule = TemperatureSchedule::Piecewise(vec![(10, 300.0), (5, 500.0)]);
+        let _ = ScheduledThermostat::new(rescale, schedule);
+    }
+
+    #[test]
+    fn group_thermostat() {
+        let mut system = testing_system();
+        let mut thermostat = GroupThermostat::new();
+        let cold = Box::new(RescaleThermostat::with_tolerance(200.0, 0.0));
+        thermostat.add_group((0..500).collect(), cold);
+        let hot = Box::new(RescaleThermostat::with_tolerance(400.0, 0.0));
+        thermostat.add_group((500..1000).collect(), hot);
+
+        assert_eq!(thermostat.degrees_of_freedom(&system), vec![1500.0, 1500.0]);
+        thermostat.setup(&system);
+        thermostat.control(&mut system);
+        let temperatures = thermostat.temperatures(&system);
+        assert_ulps_eq!(temperatures[0], 200.0, epsilon = 1e-9);
+        assert_ulps_eq!(temperatures[1], 400.0, epsilon = 1e-9);
+        assert_ulps_eq!(system.temperature(), 300.0, epsilon = 1e-9);
+
+        // Setting the temperature affects all the groups
+        thermostat.set_temperature(250.0);
+        thermostat.control(&mut system);
+        let temperatures = thermostat.temperatures(&system);
+        assert_ulps_eq!(temperatures[0], 250.0, epsilon = 1e-9);
+        assert_ulps_eq!(temperatures[1], 250.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn conserved_energy() {
+        let mut system = testing_system();
+        let initial = system.kinetic_energy();
+
+        let mut thermostat = BerendsenThermostat::new(250.0, 100.0);
+        thermostat.control(&mut system);
+        assert_eq!(thermostat.conserved_energy(), None);
+
+        let mut system = testing_system();
+        let mut thermostat = NoseHooverChainsThermostat::new(250.0, 100.0);
+        for _ in 0..100 {
+            thermostat.control(&mut system);
+        }
+        let energy = thermostat.conserved_energy().unwrap();
+        assert_ulps_eq!(energy, thermostat.energy(&system));
+
+        // The energy of each group is included in group thermostats
+        let mut system = testing_system();
+        let mut thermostat = GroupThermostat::new();
+        thermostat.add_group((0..500).collect(), Box::new(CSVRThermostat::new(200.0, 10.0)));
+        thermostat.add_group((500..1000).collect(), Box::new(CSVRThermostat::new(400.0, 10.0)));
+        thermostat.setup(&system);
+        for _ in 0..100 {
+            thermostat.control(&mut system);
+        }
+        let energy = system.kinetic_energy() + thermostat.conserved_energy().unwrap();
+        assert_relative_eq!(energy, initial, max_relative = 1e-10);
+
+        thermostat.add_group(vec![], Box::new(RescaleThermostat::new(300.0)));
+        assert_eq!(thermostat.conserved_energy(), None);
+    }
+
+    #[test]
+    fn group_degrees_of_freedom() {
+        let mut system = testing_system();
+        system.particles_mut().frozen[0] = true;
+        let mut thermostat = GroupThermostat::new();
+        thermostat.add_group(vec![0, 1, 2, 3], Box::new(RescaleThermostat::new(300.0)));
+        thermostat.add_group(vec![4, 5, 6], Box::new(RescaleThermostat::new(300.0)));
+        assert_eq!(thermostat.degrees_of_freedom(&system), vec![9.0, 9.0]);
+
+        // Degrees of freedom removed from the whole system are shared
+        // between all the particles
+        let mut system = testing_system();
+        system.simulated_degrees_of_freedom = DegreesOfFreedom::Frozen(300);
+        assert_eq!(system.degrees_of_freedom(), 2700);
+        let mut thermostat = GroupThermostat::new();
+        thermostat.add_group((0..100).collect(), Box::new(RescaleThermostat::new(300.0)));
+        assert_ulps_eq!(thermostat.degrees_of_freedom(&system)[0], 270.0);
+
+        // Constraints remove degrees of freedom from the group
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        let mut molecule = Molecule::new(Particle::with_position("O", [0.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [1.0, 0.0, 0.0].into()));
+        molecule.add_particle_bonded_to(0, Particle::with_position("H", [0.0, 1.0, 0.0].into()));
+        molecule.add_constraint(Constraint::Rigid { start: 0, end: 3 });
+        system.add_molecule(molecule);
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 0.0, 0.0].into())));
+
+        let mut thermostat = GroupThermostat::new();
+        thermostat.add_group(vec![0, 1, 2], Box::new(RescaleThermostat::new(300.0)));
+        thermostat.add_group(vec![3], Box::new(RescaleThermostat::new(300.0)));
+        assert_eq!(thermostat.degrees_of_freedom(&system), vec![6.0, 3.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The particle 3 is already part of a thermostat group.")]
+    fn overlapping_groups() {
+        let mut thermostat = GroupThermostat::new();
+        thermostat.add_group(vec![0, 1, 2, 3], Box::new(RescaleThermostat::new(300.0)));
+        thermostat.add_group(vec![3, 4], Box::new(RescaleThermostat::new(300.0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The number of Suzuki-Yoshida steps must be 1, 3, 5 or 7")]
+    fn bad_suzuki_yoshida_steps() {
+        let mut thermostat = NoseHooverChainsThermostat::new(250.0, 100.0);
+        thermostat.set_suzuki_yoshida_steps(4);
+    }
+
     #[test]
     #[should_panic]
     fn negative_temperature_rescale() {
@@ -259,6 +1194,18 @@ mod tests {
         let _ = BerendsenThermostat::new(-56.0, 1000.0);
     }
 
+    #[test]
+    #[should_panic]
+    fn negative_temperature_csvr() {
+        let _ = CSVRThermostat::new(-56.0, 10.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_temperature_nose_hoover() {
+        let _ = NoseHooverChainsThermostat::new(-56.0, 1000.0);
+    }
+
     #[test]
     fn remove_translation() {
         let mut system = System::with_cell(UnitCell::cubic(10.0));
@@ -273,6 +1220,23 @@ mod tests {
         assert_eq!(system.particles().velocity[1], Vector3D::new(0.0, -1.0, 0.0));
     }
 
+    #[test]
+    fn remove_translation_frozen() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ag", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ag", [1.0, 1.0, 1.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ag", [2.0, 2.0, 2.0].into())));
+        system.particles_mut().frozen[2] = true;
+
+        system.particles_mut().velocity[0] = [1.0, 2.0, 0.0].into();
+        system.particles_mut().velocity[1] = [1.0, 0.0, 0.0].into();
+
+        RemoveTranslation::new().control(&mut system);
+        assert_eq!(system.particles().velocity[0], Vector3D::new(0.0, 1.0, 0.0));
+        assert_eq!(system.particles().velocity[1], Vector3D::new(0.0, -1.0, 0.0));
+        assert_eq!(system.particles().velocity[2], Vector3D::zero());
+    }
+
     #[test]
     fn remove_rotation() {
         let mut system = System::with_cell(UnitCell::cubic(10.0));
@@ -287,6 +1251,20 @@ mod tests {
         assert_eq!(system.particles().velocity[1], Vector3D::new(0.0, 0.0, 1.0));
     }
 
+    #[test]
+    fn removed_degrees_of_freedom() {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        assert_eq!(RemoveTranslation::new().removed_degrees_of_freedom(&system), 3);
+        assert_eq!(RemoveRotation::new().removed_degrees_of_freedom(&system), 3);
+        assert_eq!(Rewrap::new().removed_degrees_of_freedom(&system), 0);
+        let thermostat = BerendsenThermostat::new(300.0, 10.0);
+        assert_eq!(thermostat.removed_degrees_of_freedom(&system), 0);
+
+        system.set_dimensions(2);
+        assert_eq!(RemoveTranslation::new().removed_degrees_of_freedom(&system), 2);
+        assert_eq!(RemoveRotation::new().removed_degrees_of_freedom(&system), 1);
+    }
+
     #[test]
     fn rewrap() {
         let mut system = System::with_cell(UnitCell::cubic(10.0));
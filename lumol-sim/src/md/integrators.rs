@@ -1,25 +1,126 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
-use core::{System, Matrix3, Vector3D};
+use rand::XorShiftRng;
+use rand::SeedableRng;
+use rand::distributions::{Normal, Distribution};
+
+use std::collections::HashMap;
+
+use core::consts::K_BOLTZMANN;
+use core::sys::compute::{BondedForces, Compute, MolecularVirial, NonBondedForces};
+use core::{Constraint, System, Matrix3, Vector3D};
+
+use checkpoint::{Checkpoint, CheckpointError};
+use rng::rng_from_seed;
+
+use super::chains::NoseHooverChain;
+use super::constraints::ConstraintSolver;
+use super::rigid::RigidBody;
 
 /// The `Integrator` trait define integrator interface for molecular dynamics.
 /// An integrator is an algorithm responsible for propagating the equations of
 /// motion in the system.
-pub trait Integrator {
+pub trait Integrator: Send {
     /// Setup the integrator. This function is called once by every simulation
     /// run.
     fn setup(&mut self, _: &System) {}
     /// Integrate the equations of motion. This is called at every step of the
     /// simulation.
     fn integrate(&mut self, system: &mut System);
+    /// Change the timestep of this integrator during a simulation, as done
+    /// by [`AdaptiveTimestep`]. The default implementation panics, because
+    /// not all integrators support a variable timestep.
+    ///
+    /// [`AdaptiveTimestep`]: struct.AdaptiveTimestep.html
+    fn set_timestep(&mut self, _: f64) {
+        panic!("This integrator does not support changing the timestep");
+    }
+    /// Get the contribution of this integrator to the conserved energy, i.e.
+    /// the energy of the additional degrees of freedom of the integrator
+    /// (barostat, heat bath, …) for the `system`. The sum of this energy and
+    /// the total energy of the system is conserved by the equations of
+    /// motion, and monitoring its drift is a good way to check the accuracy
+    /// of the integration. This is zero for integrators in the
+    /// microcanonical ensemble, and the default implementation returns
+    /// `None` for integrators without a conserved quantity.
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        None
+    }
+    /// Save the internal state of this integrator in the `checkpoint`, to
+    /// be able to restart the simulation exactly. The default implementation
+    /// does nothing, which is fine for integrators without internal state,
+    /// or with all their state computed by `setup`.
+    fn save_checkpoint(&mut self, _: &mut Checkpoint) {}
+    /// Restore the internal state of this integrator from the `checkpoint`.
+    /// This function is called after `setup` when restarting a simulation.
+    fn restore_checkpoint(&mut self, _: &Checkpoint) -> Result<(), CheckpointError> {
+        Ok(())
+    }
+}
+
+/// Splitting of the forces acting on a system in fast and slow varying parts,
+/// used by multiple time step integrators such as [`RESPA`].
+///
+/// [`RESPA`]: struct.RESPA.html
+pub trait ForceSplitting: Send {
+    /// Compute the fast varying forces acting on all the particles in the
+    /// `system`. These forces are cheap to compute, and are evaluated at
+    /// every inner time step.
+    fn fast_forces(&self, system: &System) -> Vec<Vector3D>;
+    /// Compute the slow varying forces acting on all the particles in the
+    /// `system`. These forces are expensive to compute, and are only
+    /// evaluated at every outer time step.
+    fn slow_forces(&self, system: &System) -> Vec<Vector3D>;
+}
+
+/// Default force splitting, using the bonded interactions as the fast forces
+/// and the pairs, electrostatic and global interactions as the slow forces.
+pub struct BondedSplitting;
+
+impl ForceSplitting for BondedSplitting {
+    fn fast_forces(&self, system: &System) -> Vec<Vector3D> {
+        BondedForces.compute(system)
+    }
+
+    fn slow_forces(&self, system: &System) -> Vec<Vector3D> {
+        NonBondedForces.compute(system)
+    }
+}
+
+/// Get the inverse of a particle `mass`, used to compute accelerations from
+/// forces. Massless particles, like virtual sites, and `frozen` particles are
+/// not accelerated and get a null inverse mass.
+pub(super) fn inverse_mass(mass: f64, frozen: bool) -> f64 {
+    if mass == 0.0 || frozen {
+        0.0
+    } else {
+        1.0 / mass
+    }
+}
+
+/// Check that the `system` does not contain any holonomic constraint, for the
+/// algorithms which can not enforce them. Ignoring the constraints would
+/// also give a wrong temperature, since the constrained degrees of freedom
+/// are removed from the system degrees of freedom.
+pub(crate) fn check_no_constraints(system: &System, algorithm: &str) {
+    assert!(
+        system.constraints().is_empty(),
+        "The {} can not enforce the holonomic constraints of the system",
+        algorithm
+    );
 }
 
 /// Velocity-Verlet integrator. This one is reversible and symplectic.
+///
+/// The holonomic constraints of the system are enforced with the SHAKE and
+/// RATTLE algorithms, allowing larger timesteps with rigid bonds.
 pub struct VelocityVerlet {
     /// Timestep for the integrator
     timestep: f64,
     /// Storing the accelerations
     accelerations: Vec<Vector3D>,
+    /// Solver for the holonomic constraints
+    constraints: ConstraintSolver,
 }
 
 impl VelocityVerlet {
@@ -28,6 +129,7 @@ impl VelocityVerlet {
         VelocityVerlet {
             timestep: timestep,
             accelerations: Vec::new(),
+            constraints: ConstraintSolver::new(),
         }
     }
 }
@@ -35,25 +137,29 @@ impl VelocityVerlet {
 impl Integrator for VelocityVerlet {
     fn setup(&mut self, system: &System) {
         self.accelerations = vec![Vector3D::zero(); system.size()];
+        self.constraints.setup(system);
     }
 
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
 
         // Update velocities at t + ∆t/2 and positions at t + ∆t
+        self.constraints.store_positions(system);
         for (position, velocity, acceleration) in soa_zip!(
             system.particles_mut(), [mut position, mut velocity], &self.accelerations
         ) {
             *velocity += 0.5 * dt * acceleration;
             *position += velocity * dt;
         }
+        self.constraints.constrain_positions(system, dt);
+        system.update_virtual_sites();
 
         let forces = system.forces();
         // Update accelerations at t + ∆t
-        for (&mass, acceleration, force) in soa_zip!(
-            system.particles(), [mass], &mut self.accelerations, forces
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
         ) {
-            *acceleration = force / mass;
+            *acceleration = inverse_mass(mass, frozen) * force;
         }
 
         // Update velocities at t + ∆t
@@ -62,10 +168,339 @@ impl Integrator for VelocityVerlet {
         ) {
             *velocity += 0.5 * dt * acceleration;
         }
+        self.constraints.constrain_velocities(system, dt);
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(0.0)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("accelerations", &self.accelerations);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.accelerations = checkpoint.vectors("accelerations")?;
+        Ok(())
+    }
+}
+
+/// Langevin dynamics integrator, using the BAOAB splitting of the equations
+/// of motion [1].
+///
+/// The particles are coupled to an implicit heat bath at a given temperature
+/// through a friction and a random force, and the integrator samples the
+/// canonical ensemble. This integrator can be used to simulate implicit
+/// solvent, with the friction coefficient representing the solvent
+/// viscosity. The friction coefficients can be set separately for the
+/// different particles types, and the random number generator can be seeded
+/// to get reproducible trajectories.
+///
+/// The holonomic constraints of the system are enforced with the SHAKE and
+/// RATTLE algorithms after each update of the positions and of the
+/// velocities.
+///
+/// [1] B. Leimkuhler and C. Matthews, Appl. Math. Res. Express 2013, 34
+/// (2013); doi: 10.1093/amrx/abs010
+pub struct Langevin {
+    /// Timestep for the integrator
+    timestep: f64,
+    /// Temperature of the heat bath
+    temperature: f64,
+    /// Default friction coefficient, in inverse time units
+    friction: f64,
+    /// Friction coefficients for specific particles types
+    frictions: HashMap<String, f64>,
+    /// Friction coefficients for all the particles, set in `setup`
+    particles_friction: Vec<f64>,
+    /// Storing the accelerations
+    accelerations: Vec<Vector3D>,
+    /// Kinetic energy given to the heat bath since the beginning of the
+    /// simulation
+    heat: f64,
+    /// Normal distribution for the random forces
+    dist: Normal,
+    /// Random number generator for the random forces
+    rng: XorShiftRng,
+    /// Solver for the holonomic constraints
+    constraints: ConstraintSolver,
+}
+
+impl Langevin {
+    /// Create a new Langevin integrator with a timestep of `timestep`, at
+    /// the given `temperature` and with the given `friction` coefficient for
+    /// all the particles.
+    pub fn new(timestep: f64, temperature: f64, friction: f64) -> Langevin {
+        assert!(temperature >= 0.0, "The temperature must be positive in Langevin integrator.");
+        assert!(friction >= 0.0, "The friction must be positive in Langevin integrator.");
+        Langevin {
+            timestep: timestep,
+            temperature: temperature,
+            friction: friction,
+            frictions: HashMap::new(),
+            particles_friction: Vec::new(),
+            accelerations: Vec::new(),
+            heat: 0.0,
+            dist: Normal::new(0.0, 1.0),
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+            constraints: ConstraintSolver::new(),
+        }
+    }
+
+    /// Use a specific `friction` coefficient for all the particles with the
+    /// given `atom_type`, instead of the default friction coefficient.
+    pub fn set_friction(&mut self, atom_type: &str, friction: f64) {
+        assert!(friction >= 0.0, "The friction must be positive in Langevin integrator.");
+        let _ = self.frictions.insert(atom_type.into(), friction);
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+}
+
+impl Integrator for Langevin {
+    fn setup(&mut self, system: &System) {
+        self.particles_friction.clear();
+        for atom_type in system.particles().atom_type {
+            let friction = self.frictions.get(atom_type).cloned().unwrap_or(self.friction);
+            self.particles_friction.push(friction);
+        }
+        self.heat = 0.0;
+        self.constraints.setup(system);
+
+        let forces = system.forces();
+        self.accelerations = vec![Vector3D::zero(); system.size()];
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+        let kt = K_BOLTZMANN * self.temperature;
+        let planar = system.dimensions() == 2;
+
+        // B and A steps: update velocities at t + ∆t/2 and positions at
+        // t + ∆t/2
+        self.constraints.store_positions(system);
+        for (position, velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity], &self.accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+            *position += 0.5 * dt * velocity;
+        }
+        self.constraints.constrain_positions(system, 0.5 * dt);
+
+        // O step: exact integration of the friction and random force
+        let kinetic = system.kinetic_energy();
+        for (velocity, &mass, &frozen, &friction) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen], &self.particles_friction
+        ) {
+            let damping = f64::exp(-friction * dt);
+            let sigma = f64::sqrt((1.0 - damping * damping) * kt * inverse_mass(mass, frozen));
+            let x = self.dist.sample(&mut self.rng);
+            let y = self.dist.sample(&mut self.rng);
+            let z = if planar { 0.0 } else { self.dist.sample(&mut self.rng) };
+            *velocity = damping * (*velocity) + sigma * Vector3D::new(x, y, z);
+        }
+        self.constraints.constrain_velocities(system, dt);
+        self.heat += kinetic - system.kinetic_energy();
+
+        // A step: update positions at t + ∆t
+        self.constraints.store_positions(system);
+        for (position, velocity) in soa_zip!(system.particles_mut(), [mut position, velocity]) {
+            *position += 0.5 * dt * velocity;
+        }
+        self.constraints.constrain_positions(system, 0.5 * dt);
+        system.update_virtual_sites();
+
+        // B step: update accelerations and velocities at t + ∆t
+        let forces = system.forces();
+        for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen], &mut self.accelerations, &forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+            *velocity += 0.5 * dt * acceleration;
+        }
+        self.constraints.constrain_velocities(system, dt);
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(self.heat)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("heat", self.heat);
+        checkpoint.save_rng("rng", &self.rng);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.heat = checkpoint.value("heat")?;
+        self.rng = checkpoint.rng("rng")?;
+        Ok(())
+    }
+}
+
+/// Reversible multiple time step integrator (r-RESPA) [1]. This one is
+/// reversible and symplectic.
+///
+/// The forces are split in fast and slow varying forces by a
+/// [`ForceSplitting`]. The slow forces are evaluated once every outer time
+/// step, while the fast forces are evaluated at every inner time step, using
+/// velocity-Verlet. This allows to use a larger time step for the expensive
+/// non-bonded interactions. By default, the bonded interactions are
+/// evaluated at every inner step, and the non-bonded interactions at every
+/// outer step.
+///
+/// The holonomic constraints of the system are enforced with the SHAKE and
+/// RATTLE algorithms at every inner step.
+///
+/// [1] M. Tuckerman, B.J. Berne and G.J. Martyna, J. Chem. Phys. 97, 1990
+/// (1992); doi: 10.1063/1.463137
+///
+/// [`ForceSplitting`]: trait.ForceSplitting.html
+pub struct RESPA {
+    /// Outer timestep for the integrator
+    timestep: f64,
+    /// Number of inner steps for each outer step
+    inner_steps: usize,
+    /// Splitting of the forces
+    splitting: Box<ForceSplitting>,
+    /// Storing the accelerations from the fast forces
+    fast_accelerations: Vec<Vector3D>,
+    /// Storing the accelerations from the slow forces
+    slow_accelerations: Vec<Vector3D>,
+    /// Solver for the holonomic constraints
+    constraints: ConstraintSolver,
+}
+
+impl RESPA {
+    /// Create a new integrator with an outer timestep of `timestep`, divided
+    /// in `inner_steps` inner steps, using the default [`BondedSplitting`].
+    ///
+    /// [`BondedSplitting`]: struct.BondedSplitting.html
+    pub fn new(timestep: f64, inner_steps: usize) -> RESPA {
+        RESPA::with_splitting(timestep, inner_steps, Box::new(BondedSplitting))
+    }
+
+    /// Create a new integrator with an outer timestep of `timestep`, divided
+    /// in `inner_steps` inner steps, and using the given force `splitting`.
+    pub fn with_splitting(
+        timestep: f64,
+        inner_steps: usize,
+        splitting: Box<ForceSplitting>,
+    ) -> RESPA {
+        assert!(inner_steps > 0, "The number of inner steps must be positive in RESPA integrator.");
+        RESPA {
+            timestep: timestep,
+            inner_steps: inner_steps,
+            splitting: splitting,
+            fast_accelerations: Vec::new(),
+            slow_accelerations: Vec::new(),
+            constraints: ConstraintSolver::new(),
+        }
+    }
+}
+
+impl Integrator for RESPA {
+    fn setup(&mut self, system: &System) {
+        self.constraints.setup(system);
+        let fast = self.splitting.fast_forces(system);
+        let slow = self.splitting.slow_forces(system);
+        self.fast_accelerations = vec![Vector3D::zero(); system.size()];
+        self.slow_accelerations = vec![Vector3D::zero(); system.size()];
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.fast_accelerations, fast
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.slow_accelerations, slow
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+        let inner_dt = dt / self.inner_steps as f64;
+
+        // Update velocities at t + ∆t/2 using the slow forces
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.slow_accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+        }
+
+        // Inner velocity-Verlet loop using the fast forces
+        for _ in 0..self.inner_steps {
+            self.constraints.store_positions(system);
+            for (position, velocity, acceleration) in soa_zip!(
+                system.particles_mut(), [mut position, mut velocity], &self.fast_accelerations
+            ) {
+                *velocity += 0.5 * inner_dt * acceleration;
+                *position += velocity * inner_dt;
+            }
+            self.constraints.constrain_positions(system, inner_dt);
+            system.update_virtual_sites();
+
+            let forces = self.splitting.fast_forces(system);
+            for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+                system.particles_mut(),
+                [mut velocity, mass, frozen],
+                &mut self.fast_accelerations,
+                forces
+            ) {
+                *acceleration = inverse_mass(mass, frozen) * force;
+                *velocity += 0.5 * inner_dt * (*acceleration);
+            }
+            self.constraints.constrain_velocities(system, inner_dt);
+        }
+
+        // Update velocities at t + ∆t using the slow forces
+        let forces = self.splitting.slow_forces(system);
+        for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles_mut(),
+            [mut velocity, mass, frozen],
+            &mut self.slow_accelerations,
+            forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+            *velocity += 0.5 * dt * (*acceleration);
+        }
+        self.constraints.constrain_velocities(system, dt);
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(0.0)
     }
 }
 
 /// Verlet integrator. This one is reversible and symplectic.
+///
+/// This integrator can not enforce holonomic constraints, use the
+/// [`VelocityVerlet`] integrator for systems containing constraints.
+///
+/// [`VelocityVerlet`]: struct.VelocityVerlet.html
 pub struct Verlet {
     /// Timestep for the integrator
     timestep: f64,
@@ -85,6 +520,7 @@ impl Verlet {
 
 impl Integrator for Verlet {
     fn setup(&mut self, system: &System) {
+        check_no_constraints(system, "Verlet integrator");
         self.prevpos = vec![Vector3D::zero(); system.size()];
 
         let dt = self.timestep;
@@ -101,27 +537,49 @@ impl Integrator for Verlet {
         let dt = self.timestep;
         let dt2 = dt * dt;
 
-        for (position, velocity, mass, prevpos, force) in soa_zip!(
-            system.particles_mut(), [mut position, mut velocity, mass], &mut self.prevpos, forces
+        for (position, velocity, mass, frozen, prevpos, force) in soa_zip!(
+            system.particles_mut(),
+            [mut position, mut velocity, mass, frozen],
+            &mut self.prevpos,
+            forces
         ) {
             // Save positions at t
             let tmp = *position;
             // Update positions at t + ∆t
-            *position = 2.0 * (*position) - (*prevpos) + dt2 / mass * force;
+            *position = 2.0 * (*position) - (*prevpos) + dt2 * inverse_mass(*mass, *frozen) * force;
             // Update velocities at t
             *velocity = ((*position) - (*prevpos)) / (2.0 * dt);
             // Update saved position
             *prevpos = tmp;
         }
+        system.update_virtual_sites();
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(0.0)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("previous_positions", &self.prevpos);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.prevpos = checkpoint.vectors("previous_positions")?;
+        Ok(())
     }
 }
 
 /// Leap-frog integrator. This one is reversible and symplectic.
+///
+/// The holonomic constraints of the system are enforced with the SHAKE and
+/// RATTLE algorithms, allowing larger timesteps with rigid bonds.
 pub struct LeapFrog {
     /// Timestep for the integrator
     timestep: f64,
     /// Storing the accelerations
     accelerations: Vec<Vector3D>,
+    /// Solver for the holonomic constraints
+    constraints: ConstraintSolver,
 }
 
 impl LeapFrog {
@@ -130,6 +588,7 @@ impl LeapFrog {
         LeapFrog {
             timestep: timestep,
             accelerations: Vec::new(),
+            constraints: ConstraintSolver::new(),
         }
     }
 }
@@ -137,26 +596,169 @@ impl LeapFrog {
 impl Integrator for LeapFrog {
     fn setup(&mut self, system: &System) {
         self.accelerations = vec![Vector3D::zero(); system.size()];
+        self.constraints.setup(system);
     }
 
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
         let dt2 = dt * dt;
 
+        self.constraints.store_positions(system);
         for (position, velocity, acceleration) in soa_zip!(
             system.particles_mut(), [mut position, velocity], &self.accelerations
         ) {
             *position += velocity * dt + 0.5 * acceleration * dt2;
         }
+        self.constraints.constrain_positions(system, dt);
+        system.update_virtual_sites();
 
         let forces = system.forces();
-        for (velocity, &mass, acceleration, force) in soa_zip!(
-            system.particles_mut(), [mut velocity, mass], &mut self.accelerations, &forces
+        for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen], &mut self.accelerations, &forces
         ) {
-            let new_acceleration = force / mass;
+            let new_acceleration = inverse_mass(mass, frozen) * force;
             *velocity += 0.5 * ((*acceleration) + new_acceleration) * dt;
             *acceleration = new_acceleration;
         }
+        self.constraints.constrain_velocities(system, dt);
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(0.0)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("accelerations", &self.accelerations);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.accelerations = checkpoint.vectors("accelerations")?;
+        Ok(())
+    }
+}
+
+/// Velocity-Verlet integrator for systems containing rigid bodies.
+///
+/// All the rigid fragments in the system are integrated as rigid bodies,
+/// using the position and velocity of their center-of-mass, their
+/// orientation stored as a quaternion and their angular momentum. The forces
+/// acting on the particles are accumulated in a total force and a torque
+/// acting on each body, and the free rotation of the bodies is integrated
+/// with the symplectic and time-reversible splitting from Dullweber,
+/// Leimkuhler and McLachlan [1]. The other particles are integrated with the
+/// usual velocity-Verlet algorithm. This integrator can not enforce the other
+/// holonomic constraints, and only accepts rigid fragments constraints.
+/// Rigid fragments containing frozen particles are kept fixed.
+///
+/// [1] A. Dullweber, B. Leimkuhler and R. McLachlan, J. Chem. Phys. 107, 5840
+/// (1997); doi: 10.1063/1.474310
+pub struct RigidBodyVerlet {
+    /// Timestep for the integrator
+    timestep: f64,
+    /// The rigid bodies in the system
+    bodies: Vec<RigidBody>,
+    /// Is the particle at a given index part of a rigid fragment?
+    in_rigid_fragment: Vec<bool>,
+    /// Storing the forces
+    forces: Vec<Vector3D>,
+}
+
+impl RigidBodyVerlet {
+    /// Create a new integrator with a timestep of `timestep`.
+    pub fn new(timestep: f64) -> RigidBodyVerlet {
+        RigidBodyVerlet {
+            timestep: timestep,
+            bodies: Vec::new(),
+            in_rigid_fragment: Vec::new(),
+            forces: Vec::new(),
+        }
+    }
+}
+
+impl Integrator for RigidBodyVerlet {
+    fn setup(&mut self, system: &System) {
+        self.bodies.clear();
+        self.in_rigid_fragment = vec![false; system.size()];
+        for constraint in system.constraints() {
+            if let Constraint::Rigid { start, end } = constraint {
+                for i in start..end {
+                    self.in_rigid_fragment[i] = true;
+                }
+                if let Some(body) = RigidBody::new(system, start, end) {
+                    self.bodies.push(body);
+                }
+            } else {
+                panic!(
+                    "The rigid body integrator can only enforce the rigid fragments \
+                     constraints, not {:?}", constraint
+                );
+            }
+        }
+        self.forces = system.forces();
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+
+        // Update velocities at t + ∆t/2 and positions at t + ∆t
+        for body in &mut self.bodies {
+            body.read_state(system);
+            body.kick(&self.forces, 0.5 * dt);
+            body.drift(dt);
+            body.update_particles(system);
+        }
+        for (position, velocity, &mass, &frozen, force, &rigid) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity, mass, frozen],
+            &self.forces, &self.in_rigid_fragment
+        ) {
+            if !rigid {
+                *velocity += 0.5 * dt * inverse_mass(mass, frozen) * force;
+                *position += velocity * dt;
+            }
+        }
+        system.update_virtual_sites();
+
+        // Update velocities at t + ∆t
+        self.forces = system.forces();
+        for body in &mut self.bodies {
+            body.kick(&self.forces, 0.5 * dt);
+            body.update_particles(system);
+        }
+        for (velocity, &mass, &frozen, force, &rigid) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen],
+            &self.forces, &self.in_rigid_fragment
+        ) {
+            if !rigid {
+                *velocity += 0.5 * dt * inverse_mass(mass, frozen) * force;
+            }
+        }
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+
+    fn conserved_energy(&self, _: &System) -> Option<f64> {
+        Some(0.0)
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        let section = checkpoint.section_mut("bodies");
+        for (i, body) in self.bodies.iter().enumerate() {
+            body.save_checkpoint(section.section_mut(&i.to_string()));
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let section = checkpoint.section("bodies")?;
+        for (i, body) in self.bodies.iter_mut().enumerate() {
+            body.restore_checkpoint(section.section(&i.to_string())?)?;
+        }
+        Ok(())
     }
 }
 
@@ -166,6 +768,9 @@ const WATER_COMPRESSIBILITY: f64 = 7372.0;
 
 /// Berendsen barostat integrator based on velocity-Verlet. This one neither
 /// reversible nor symplectic.
+///
+/// This integrator scales the positions of all the particles, except for the
+/// frozen particles, and can not be used with holonomic constraints.
 pub struct BerendsenBarostat {
     /// Timestep for the integrator
     timestep: f64,
@@ -196,6 +801,7 @@ impl BerendsenBarostat {
 
 impl Integrator for BerendsenBarostat {
     fn setup(&mut self, system: &System) {
+        check_no_constraints(system, "Berendsen barostat integrator");
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
@@ -203,16 +809,19 @@ impl Integrator for BerendsenBarostat {
         let dt = self.timestep;
 
         // Update velocities at t + ∆t/2 and positions at t + ∆t
-        for (position, velocity, acceleration) in soa_zip!(
-            system.particles_mut(), [mut position, mut velocity], &self.accelerations
+        for (position, velocity, &frozen, acceleration) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity, frozen], &self.accelerations
         ) {
             *velocity += 0.5 * dt * acceleration;
-            // Scale all positions
-            *position *= self.eta;
+            // Scale all positions, frozen particles are kept fixed
+            if !frozen {
+                *position *= self.eta;
+            }
             *position += velocity * dt;
         }
 
         system.cell.scale_mut(self.eta * self.eta * self.eta * Matrix3::one());
+        system.update_virtual_sites();
 
         if let Some(maximum_cutoff) = system.maximum_cutoff() {
             if system.cell.lengths().iter().any(|&d| 0.5 * d <= maximum_cutoff) {
@@ -230,17 +839,57 @@ impl Integrator for BerendsenBarostat {
 
         let forces = system.forces();
         // Update accelerations at t + ∆t and velocities at t + ∆t
-        for (velocity, &mass, acceleration, force) in soa_zip!(
-            system.particles_mut(), [mut velocity, mass], &mut self.accelerations, &forces
+        for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen], &mut self.accelerations, &forces
         ) {
-            *acceleration = force / mass;
+            *acceleration = inverse_mass(mass, frozen) * force;
             *velocity += 0.5 * dt * acceleration;
         }
     }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("accelerations", &self.accelerations);
+        checkpoint.set_value("eta", self.eta);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.accelerations = checkpoint.vectors("accelerations")?;
+        self.eta = checkpoint.value("eta")?;
+        Ok(())
+    }
+}
+
+/// Coupling between the stress and the deformations of the cell in
+/// anisotropic barostats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PressureCoupling {
+    /// Each component of the cell is coupled to the corresponding component
+    /// of the stress tensor.
+    Anisotropic,
+    /// The `x` and `y` directions are scaled together using the average of
+    /// the `xx` and `yy` components of the stress tensor, and the `z`
+    /// direction is scaled independently using the `zz` component. This is
+    /// used for membranes and interfaces normal to the `z` axis.
+    SemiIsotropic,
+    /// The `z` direction is coupled to the target normal pressure, and the
+    /// `x` and `y` directions are scaled together to get the given surface
+    /// tension in the `xy` plane. The surface tension is `γ = L_z (P_zz -
+    /// (P_xx + P_yy) / 2)`, and contains the contributions of all the
+    /// interfaces in the cell: for a system with two interfaces, this is
+    /// twice the tension of a single interface.
+    SurfaceTension(f64),
 }
 
 /// Anisotropic Berendsen barostat integrator based on velocity-Verlet. This one
 /// neither reversible nor symplectic.
+///
+/// By default, all the components of the cell are coupled to the
+/// corresponding component of the stress. Semi-isotropic and constant
+/// surface tension couplings can be used instead with
+/// [`set_coupling`](#method.set_coupling).
+///
+/// This integrator scales the positions of all the particles, except for the
+/// frozen particles, and can not be used with holonomic constraints.
 pub struct AnisoBerendsenBarostat {
     /// Timestep for the integrator
     timestep: f64,
@@ -248,6 +897,8 @@ pub struct AnisoBerendsenBarostat {
     stress: Matrix3,
     /// Barostat time scale, expressed in units of the timestep
     tau: f64,
+    /// Coupling between the stress and the cell
+    coupling: PressureCoupling,
     /// Storing the accelerations
     accelerations: Vec<Vector3D>,
     /// Storing the scaling factor
@@ -263,6 +914,7 @@ impl AnisoBerendsenBarostat {
             timestep: timestep,
             stress: stress,
             tau: tau,
+            coupling: PressureCoupling::Anisotropic,
             accelerations: Vec::new(),
             eta: Matrix3::one(),
         }
@@ -274,10 +926,54 @@ impl AnisoBerendsenBarostat {
     pub fn hydrostatic(timestep: f64, pressure: f64, tau: f64) -> AnisoBerendsenBarostat {
         AnisoBerendsenBarostat::new(timestep, pressure * Matrix3::one(), tau)
     }
+
+    /// Set the coupling between the stress and the deformations of the
+    /// cell. With semi-isotropic coupling, the target lateral pressure is
+    /// the average of the `xx` and `yy` components of the target stress, and
+    /// the normal pressure the `zz` component. With surface tension
+    /// coupling, only the `zz` component of the target stress is used.
+    pub fn set_coupling(&mut self, coupling: PressureCoupling) {
+        self.coupling = coupling;
+    }
+
+    /// Get the scaling matrix of the cell for the given `stress` in the
+    /// `system`, using the compressibility `factor`.
+    fn scaling(&self, system: &System, stress: &Matrix3, factor: f64) -> Matrix3 {
+        let lateral = 0.5 * (stress[0][0] + stress[1][1]);
+        let normal = stress[2][2];
+        let target_lateral = match self.coupling {
+            PressureCoupling::Anisotropic => {
+                let mut eta = Matrix3::one() - factor * (self.stress - *stress);
+                // Make the eta matrix symmetric here
+                for i in 0..3 {
+                    for j in 0..i {
+                        eta[i][j] = 0.5 * (eta[i][j] + eta[j][i]);
+                        eta[j][i] = eta[i][j];
+                    }
+                }
+                return eta;
+            }
+            PressureCoupling::SemiIsotropic => 0.5 * (self.stress[0][0] + self.stress[1][1]),
+            PressureCoupling::SurfaceTension(tension) => {
+                // Height of the cell along the normal to the xy plane
+                let height = system.cell.volume() / system.cell.area();
+                normal - tension / height
+            }
+        };
+
+        // The x and y directions are scaled together, and the z direction
+        // independently
+        let mut eta = Matrix3::one();
+        eta[0][0] = 1.0 - factor * (target_lateral - lateral);
+        eta[1][1] = eta[0][0];
+        eta[2][2] = 1.0 - factor * (self.stress[2][2] - normal);
+        return eta;
+    }
 }
 
 impl Integrator for AnisoBerendsenBarostat {
     fn setup(&mut self, system: &System) {
+        check_no_constraints(system, "anisotropic Berendsen barostat integrator");
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
@@ -285,16 +981,19 @@ impl Integrator for AnisoBerendsenBarostat {
         let dt = self.timestep;
 
         // Update velocities at t + ∆t/2 and positions at t + ∆t
-        for (position, velocity, acceleration) in soa_zip!(
-            system.particles_mut(), [mut position, mut velocity], &self.accelerations
+        for (position, velocity, &frozen, acceleration) in soa_zip!(
+            system.particles_mut(), [mut position, mut velocity, frozen], &self.accelerations
         ) {
             *velocity += 0.5 * dt * acceleration;
-            // Scale all positions
-            *position = self.eta * (*position);
+            // Scale all positions, frozen particles are kept fixed
+            if !frozen {
+                *position = self.eta * (*position);
+            }
             *position += velocity * dt;
         }
 
         system.cell.scale_mut(self.eta);
+        system.update_virtual_sites();
 
         if let Some(maximum_cutoff) = system.maximum_cutoff() {
             if system.cell.lengths().iter().any(|&d| 0.5 * d <= maximum_cutoff) {
@@ -308,23 +1007,511 @@ impl Integrator for AnisoBerendsenBarostat {
         };
 
         let factor = self.timestep * WATER_COMPRESSIBILITY / self.tau;
-        self.eta = Matrix3::one() - factor * (self.stress - system.stress());
+        let stress = system.stress();
+        self.eta = self.scaling(system, &stress, factor);
+
+        let forces = system.forces();
+        // Update accelerations at t + ∆t and velocities at t + ∆t
+        for (velocity, &mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles_mut(), [mut velocity, mass, frozen], &mut self.accelerations, &forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+            *velocity += 0.5 * dt * acceleration;
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_vectors("accelerations", &self.accelerations);
+        checkpoint.set_matrix("eta", &self.eta);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.accelerations = checkpoint.vectors("accelerations")?;
+        self.eta = checkpoint.matrix("eta")?;
+        Ok(())
+    }
+}
+
+/// Parrinello-Rahman barostat integrator based on velocity-Verlet, with a
+/// fully flexible unit cell [1].
+///
+/// The unit cell matrix is an additional dynamical variable, with a fictitious
+/// mass `W`, and its velocity is driven by the difference between the
+/// internal and the target stress. All the cell parameters, including the
+/// cell angles, can change during the simulation, which allows to simulate
+/// phase transitions in solids. The cell velocity is kept symmetric to remove
+/// global rotations of the cell.
+///
+/// The cell acts on the center of mass of the molecules, and the internal
+/// stress is computed using the molecular virial, so that the molecules are
+/// not deformed by the cell changes. The molecules containing frozen
+/// particles are not moved by the cell changes. The mass of the cell is computed at the
+/// beginning of the simulation as `W = (N_f + d) k_B T tau^2`, where `N_f` is
+/// the number of degrees of freedom of the system, `d` the number of
+/// dimensions, `T` the system temperature and `tau` the barostat time scale.
+/// This integrator should be used together with a thermostat to sample the
+/// isothermal-isobaric ensemble.
+///
+/// Since the molecules are only translated by the cell changes, the
+/// holonomic constraints of the system are preserved by the barostat, and
+/// enforced with the SHAKE and RATTLE algorithms after each update of the
+/// positions and of the velocities.
+///
+/// [1] M. Parrinello and A. Rahman, J. Appl. Phys. 52, 7182 (1981); doi:
+/// 10.1063/1.328693
+pub struct ParrinelloRahmanBarostat {
+    /// Timestep for the integrator
+    timestep: f64,
+    /// Target stress matrix for the barostat
+    stress: Matrix3,
+    /// Barostat time scale, expressed in units of the timestep
+    tau: f64,
+    /// Fictitious mass of the unit cell
+    mass: f64,
+    /// Velocity of the unit cell, as a symmetric strain rate
+    velocity: Matrix3,
+    /// Storing the accelerations
+    accelerations: Vec<Vector3D>,
+    /// Solver for the holonomic constraints
+    constraints: ConstraintSolver,
+}
+
+impl ParrinelloRahmanBarostat {
+    /// Create a new Parrinello-Rahman barostat with an integration timestep
+    /// of `timestep`, a target stress matrix of `stress` and the barostat time
+    /// scale `tau`.
+    pub fn new(timestep: f64, stress: Matrix3, tau: f64) -> ParrinelloRahmanBarostat {
+        assert!(tau > 0.0, "The timestep must be positive in Parrinello-Rahman barostat.");
+        ParrinelloRahmanBarostat {
+            timestep: timestep,
+            stress: stress,
+            tau: tau,
+            mass: 0.0,
+            velocity: Matrix3::zero(),
+            accelerations: Vec::new(),
+            constraints: ConstraintSolver::new(),
+        }
+    }
+
+    /// Create a new Parrinello-Rahman barostat with an integration timestep
+    /// of `timestep`, and a target hydrostatic pressure of `pressure` and the
+    /// barostat time scale `tau`.
+    pub fn hydrostatic(timestep: f64, pressure: f64, tau: f64) -> ParrinelloRahmanBarostat {
+        ParrinelloRahmanBarostat::new(timestep, pressure * Matrix3::one(), tau)
+    }
+
+    /// Update the cell velocity for half of the timestep, using the current
+    /// internal stress of the `system`.
+    fn update_cell_velocity(&mut self, system: &System) {
+        let volume = system.volume();
+        let internal = molecular_stress(system);
+        self.velocity += 0.5 * self.timestep * volume / self.mass * (internal - self.stress);
 
-        // Make the eta matrix symmetric here
+        // Remove the antisymmetric part to prevent global rotations
         for i in 0..3 {
             for j in 0..i {
-                self.eta[i][j] = 0.5 * (self.eta[i][j] + self.eta[j][i]);
-                self.eta[j][i] = self.eta[i][j];
+                self.velocity[i][j] = 0.5 * (self.velocity[i][j] + self.velocity[j][i]);
+                self.velocity[j][i] = self.velocity[i][j];
+            }
+        }
+
+        if system.dimensions() == 2 {
+            for i in 0..3 {
+                self.velocity[i][2] = 0.0;
+                self.velocity[2][i] = 0.0;
             }
         }
+    }
+
+    /// Apply the friction from the cell velocity on the velocity of the
+    /// molecules centers of mass for half of the timestep.
+    fn cell_friction(&self, system: &mut System) {
+        let friction = symmetric_exp(&self.velocity, -0.5 * self.timestep);
+        for mut molecule in system.molecules_mut() {
+            if molecule.particles().frozen.iter().any(|&frozen| frozen) {
+                continue;
+            }
+
+            let mut particles = molecule.particles_mut();
+            let com_velocity = center_of_mass_velocity(&particles.mass, &particles.velocity);
+            let delta = friction * com_velocity - com_velocity;
+            for velocity in particles.velocity.iter_mut() {
+                *velocity += delta;
+            }
+        }
+    }
+}
+
+impl Integrator for ParrinelloRahmanBarostat {
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Can not use Parrinello-Rahman barostat with an infinite cell"
+        );
+        let temperature = system.temperature();
+        assert!(
+            temperature > 0.0,
+            "The system temperature must be positive to use Parrinello-Rahman barostat"
+        );
+
+        let dof = (system.degrees_of_freedom() + system.dimensions()) as f64;
+        let tau = self.tau * self.timestep;
+        self.mass = dof * K_BOLTZMANN * temperature * tau * tau;
+        self.velocity = Matrix3::zero();
+        self.constraints.setup(system);
 
         let forces = system.forces();
-        // Update accelerations at t + ∆t and velocities at t + ∆t
-        for (velocity, &mass, acceleration, force) in soa_zip!(
-            system.particles_mut(), [mut velocity, mass], &mut self.accelerations, &forces
+        self.accelerations = vec![Vector3D::zero(); system.size()];
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+
+        // Update the cell velocity and the particles velocities at t + ∆t/2
+        self.update_cell_velocity(system);
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.accelerations
         ) {
-            *acceleration = force / mass;
             *velocity += 0.5 * dt * acceleration;
         }
+        self.cell_friction(system);
+
+        // Update positions and unit cell at t + ∆t. The centers of mass are
+        // scaled with the cell, and move with the cell velocity, except for
+        // the molecules containing frozen particles.
+        let scaling = symmetric_exp(&self.velocity, dt);
+        let half_scaling = symmetric_exp(&self.velocity, 0.5 * dt);
+        self.constraints.store_positions(system);
+        for mut molecule in system.molecules_mut() {
+            let com = molecule.center_of_mass();
+            let frozen = molecule.particles().frozen.iter().any(|&frozen| frozen);
+            let mut particles = molecule.particles_mut();
+            let delta = if frozen {
+                Vector3D::zero()
+            } else {
+                let com_velocity = center_of_mass_velocity(&particles.mass, &particles.velocity);
+                scaling * com - com + dt * (half_scaling * com_velocity - com_velocity)
+            };
+            for (position, velocity) in soa_zip!(particles, [mut position, velocity]) {
+                *position += delta + dt * velocity;
+            }
+        }
+        self.constraints.constrain_positions(system, dt);
+
+        system.cell = system.cell.scale(scaling);
+        system.update_virtual_sites();
+
+        if let Some(maximum_cutoff) = system.maximum_cutoff() {
+            if system.cell.lengths().iter().any(|&d| 0.5 * d <= maximum_cutoff) {
+                panic!(
+                    "Tried to decrease the cell size in Parrinello-Rahman barostat \
+                     but the new size is smaller than the interactions cut off \
+                     radius. You can try to increase the cell size or the number \
+                     of particles."
+                );
+            }
+        };
+
+        // Update accelerations, particles velocities and cell velocity at t + ∆t
+        let forces = system.forces();
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+
+        self.cell_friction(system);
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+        }
+        self.constraints.constrain_velocities(system, dt);
+        self.update_cell_velocity(system);
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("mass", self.mass);
+        checkpoint.set_matrix("velocity", &self.velocity);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.mass = checkpoint.value("mass")?;
+        self.velocity = checkpoint.matrix("velocity")?;
+        Ok(())
+    }
+}
+
+/// Martyna-Tobias-Klein integrator for the isothermal-isobaric ensemble [1].
+///
+/// The particles are coupled to a chain of Nosé-Hoover thermostats, and the
+/// volume of the unit cell is an additional dynamical variable, itself
+/// coupled to a separate chain of thermostats. Contrary to the Berendsen
+/// barostats, this integrator samples the isothermal-isobaric ensemble
+/// exactly, and should be preferred for production simulations. The cell is
+/// scaled isotropically, and the equations of motion are integrated with the
+/// reversible scheme from [2].
+///
+/// The mass of the barostat is `W = (N_f + d) k_B T tau_p^2`, where `N_f` is
+/// the number of degrees of freedom of the system, `d` the number of
+/// dimensions and `tau_p` the barostat time scale. The thermostats masses
+/// are the same as in the [`NoseHooverChainsThermostat`].
+///
+/// This integrator scales the positions of all the particles, except for the
+/// frozen particles, and can not be used with holonomic constraints.
+///
+/// [1] G.J. Martyna, D.J. Tobias and M.L. Klein, J. Chem. Phys. 101, 4177
+/// (1994); doi: 10.1063/1.467468
+///
+/// [2] G.J. Martyna, et al. Mol. Phys. 87, 1117 (1996); doi:
+/// 10.1080/00268979600100761
+///
+/// [`NoseHooverChainsThermostat`]: struct.NoseHooverChainsThermostat.html
+pub struct MTKIntegrator {
+    /// Timestep for the integrator
+    timestep: f64,
+    /// Target temperature
+    temperature: f64,
+    /// Target pressure
+    pressure: f64,
+    /// Barostat time scale, expressed in units of the timestep
+    tau_barostat: f64,
+    /// Mass of the barostat
+    mass: f64,
+    /// Velocity of the logarithm of the volume, divided by the number of
+    /// dimensions
+    velocity: f64,
+    /// Chain of thermostats coupled to the particles
+    thermostat: NoseHooverChain,
+    /// Chain of thermostats coupled to the barostat
+    barostat_thermostat: NoseHooverChain,
+    /// Storing the accelerations
+    accelerations: Vec<Vector3D>,
+}
+
+impl MTKIntegrator {
+    /// Create a new Martyna-Tobias-Klein integrator with an integration
+    /// timestep of `timestep`, a target `temperature` and `pressure`; and
+    /// thermostat and barostat time scales of `tau_thermostat` and
+    /// `tau_barostat` times the integrator timestep.
+    pub fn new(
+        timestep: f64,
+        temperature: f64,
+        pressure: f64,
+        tau_thermostat: f64,
+        tau_barostat: f64,
+    ) -> MTKIntegrator {
+        assert!(temperature > 0.0, "The temperature must be positive in MTK integrator.");
+        assert!(
+            tau_thermostat > 0.0 && tau_barostat > 0.0,
+            "The timesteps must be positive in MTK integrator."
+        );
+        MTKIntegrator {
+            timestep: timestep,
+            temperature: temperature,
+            pressure: pressure,
+            tau_barostat: tau_barostat,
+            mass: 0.0,
+            velocity: 0.0,
+            thermostat: NoseHooverChain::new(temperature, tau_thermostat * timestep),
+            barostat_thermostat: NoseHooverChain::new(temperature, tau_barostat * timestep),
+            accelerations: Vec::new(),
+        }
+    }
+
+    /// Set the number of thermostats in the chains coupled to the particles
+    /// and to the barostat.
+    pub fn set_chain_length(&mut self, length: usize) {
+        self.thermostat.set_length(length);
+        self.barostat_thermostat.set_length(length);
+    }
+
+    /// Get the energy of the barostat and thermostats degrees of freedom,
+    /// including the `P V` term. The sum of this energy and the total energy
+    /// of the `system` is conserved during the simulation.
+    pub fn energy(&self, system: &System) -> f64 {
+        let dof = system.degrees_of_freedom() as f64;
+        let mut energy = self.pressure * system.volume();
+        energy += 0.5 * self.mass * self.velocity * self.velocity;
+        energy += self.thermostat.energy(dof);
+        energy += self.barostat_thermostat.energy(1.0);
+        return energy;
+    }
+
+    /// Propagate the thermostats chains for half of the timestep. The chains
+    /// are propagated in the reverse order at the end of the step.
+    fn half_chains(&mut self, system: &mut System, end: bool) {
+        let dt = self.timestep;
+        let dof = system.degrees_of_freedom() as f64;
+        let kinetic = 2.0 * system.kinetic_energy();
+        let barostat_kinetic = self.mass * self.velocity * self.velocity;
+
+        let scale = if end {
+            let scale = self.thermostat.propagate(kinetic, dof, 0.5 * dt);
+            self.velocity *= self.barostat_thermostat.propagate(barostat_kinetic, 1.0, 0.5 * dt);
+            scale
+        } else {
+            self.velocity *= self.barostat_thermostat.propagate(barostat_kinetic, 1.0, 0.5 * dt);
+            self.thermostat.propagate(kinetic, dof, 0.5 * dt)
+        };
+
+        for velocity in system.particles_mut().velocity {
+            *velocity *= scale;
+        }
+    }
+
+    /// Update the barostat velocity for half of the timestep
+    fn update_barostat_velocity(&mut self, system: &System) {
+        let dimensions = system.dimensions() as f64;
+        let dof = system.degrees_of_freedom() as f64;
+        let kinetic = 2.0 * system.kinetic_energy();
+        let virial = system.virial().trace();
+        let force = (1.0 + dimensions / dof) * kinetic + virial
+                    - dimensions * system.volume() * self.pressure;
+        self.velocity += 0.5 * self.timestep * force / self.mass;
+    }
+
+    /// Update the particles velocities for half of the timestep, including
+    /// the friction from the barostat.
+    fn update_velocities(&self, system: &mut System) {
+        let dt = self.timestep;
+        let alpha = 1.0 + system.dimensions() as f64 / system.degrees_of_freedom() as f64;
+        let friction = f64::exp(-0.5 * alpha * self.velocity * dt);
+        let half_friction = f64::exp(-0.25 * alpha * self.velocity * dt);
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.accelerations
+        ) {
+            *velocity = friction * (*velocity) + 0.5 * dt * half_friction * acceleration;
+        }
+    }
+}
+
+impl Integrator for MTKIntegrator {
+    fn setup(&mut self, system: &System) {
+        assert!(!system.cell.is_infinite(), "Can not use MTK integrator with an infinite cell");
+        check_no_constraints(system, "MTK integrator");
+        let dof = (system.degrees_of_freedom() + system.dimensions()) as f64;
+        let tau = self.tau_barostat * self.timestep;
+        self.mass = dof * K_BOLTZMANN * self.temperature * tau * tau;
+        self.velocity = 0.0;
+
+        let forces = system.forces();
+        self.accelerations = vec![Vector3D::zero(); system.size()];
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+
+        self.half_chains(system, false);
+        self.update_barostat_velocity(system);
+        self.update_velocities(system);
+
+        // Update positions and unit cell at t + ∆t
+        let scaling = f64::exp(self.velocity * dt);
+        let half_scaling = f64::exp(0.5 * self.velocity * dt);
+        for (position, velocity, &frozen) in soa_zip!(
+            system.particles_mut(), [mut position, velocity, frozen]
+        ) {
+            // Frozen particles are kept fixed
+            if !frozen {
+                *position = scaling * (*position) + dt * half_scaling * velocity;
+            }
+        }
+
+        let mut cell_scaling = scaling * Matrix3::one();
+        if system.dimensions() == 2 {
+            cell_scaling[2][2] = 1.0;
+        }
+        system.cell = system.cell.scale(cell_scaling);
+        system.update_virtual_sites();
+
+        if let Some(maximum_cutoff) = system.maximum_cutoff() {
+            if system.cell.lengths().iter().any(|&d| 0.5 * d <= maximum_cutoff) {
+                panic!(
+                    "Tried to decrease the cell size in MTK integrator but the \
+                     new size is smaller than the interactions cut off radius. \
+                     You can try to increase the cell size or the number of \
+                     particles."
+                );
+            }
+        };
+
+        // Update accelerations and velocities at t + ∆t
+        let forces = system.forces();
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+        self.update_velocities(system);
+        self.update_barostat_velocity(system);
+        self.half_chains(system, true);
+    }
+
+    fn conserved_energy(&self, system: &System) -> Option<f64> {
+        Some(self.energy(system))
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("velocity", self.velocity);
+        self.thermostat.save_checkpoint(checkpoint.section_mut("thermostat"));
+        self.barostat_thermostat.save_checkpoint(checkpoint.section_mut("barostat_thermostat"));
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.velocity = checkpoint.value("velocity")?;
+        self.thermostat.restore_checkpoint(checkpoint.section("thermostat")?)?;
+        self.barostat_thermostat.restore_checkpoint(checkpoint.section("barostat_thermostat")?)?;
+        Ok(())
+    }
+}
+
+/// Compute `exp(t M)` for the symmetric matrix `M`
+fn symmetric_exp(matrix: &Matrix3, t: f64) -> Matrix3 {
+    let (values, vectors) = matrix.symmetric_eigen();
+    let mut result = Matrix3::zero();
+    for i in 0..3 {
+        let vector = Vector3D::new(vectors[0][i], vectors[1][i], vectors[2][i]);
+        result += f64::exp(t * values[i]) * vector.tensorial(&vector);
+    }
+    return result;
+}
+
+/// Get the velocity of the center of mass of a set of particles
+fn center_of_mass_velocity(masses: &[f64], velocities: &[Vector3D]) -> Vector3D {
+    let mut total_mass = 0.0;
+    let mut momentum = Vector3D::zero();
+    for (&mass, velocity) in masses.iter().zip(velocities) {
+        total_mass += mass;
+        momentum += mass * velocity;
+    }
+    if total_mass == 0.0 {
+        Vector3D::zero()
+    } else {
+        momentum / total_mass
+    }
+}
+
+/// Compute the stress tensor of the system using the molecular virial and
+/// the kinetic energy of the molecules centers of mass.
+fn molecular_stress(system: &System) -> Matrix3 {
+    let mut kinetic = Matrix3::zero();
+    for molecule in system.molecules() {
+        let particles = molecule.particles();
+        let mass = particles.mass.iter().sum::<f64>();
+        let velocity = center_of_mass_velocity(particles.mass, particles.velocity);
+        kinetic += mass * velocity.tensorial(&velocity);
     }
+    let virial = MolecularVirial.compute(system);
+    return (kinetic + virial) / system.volume();
 }
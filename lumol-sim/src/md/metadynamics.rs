@@ -0,0 +1,358 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Metadynamics, biasing the dynamics along a set of collective variables
+//! with a history-dependent potential.
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use core::consts::K_BOLTZMANN;
+use core::{System, Vector3D};
+use core::units;
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+use super::{CollectiveVariable, Control};
+
+/// A gaussian hill deposited by the metadynamics bias
+struct Hill {
+    /// Values of the collective variables at the center of the hill
+    center: Vec<f64>,
+    /// Height of the hill
+    height: f64,
+}
+
+/// Metadynamics bias on a set of collective variables [1].
+///
+/// During the simulation, gaussian hills are deposited at the current
+/// values of the collective variables $s$ every `pace` steps, building a
+/// bias potential
+///
+/// $$ V(s) = \sum_h w_h \exp \left( - \sum_k \frac{(s_k - s_k^h)^2}{2
+/// \sigma_k^2} \right) $$
+///
+/// where $w_h$ is the height of the hill $h$ centered at $s^h$, and
+/// $\sigma_k$ the width of the hills along the collective variable $k$. The
+/// bias discourages the system to come back to already visited states, and
+/// once the simulation has converged, the free energy along the collective
+/// variables is given by $F(s) = - V(s)$ up to a constant.
+///
+/// With well-tempered metadynamics [2], the height of the hills decreases
+/// as the bias grows: $w_h = w \exp(- V(s^h) / k_B \Delta T)$, where $\Delta
+/// T = (\gamma - 1) T$ for a bias factor $\gamma$ at the temperature $T$.
+/// The free energy is then given by $F(s) = - \gamma V(s) / (\gamma - 1)$.
+///
+/// As for all the controls, the bias force is applied between two steps of
+/// the integrator, by changing the velocities of the particles.
+///
+/// The deposited hills can be written to a file with
+/// [`set_output`](#method.set_output), together with the value of the
+/// accumulated bias at the center of each hill before its deposition. The
+/// bias can be reconstructed at any point by summing all the hills.
+///
+/// [1] A. Laio and M. Parrinello, PNAS 99, 12562 (2002); doi:
+/// 10.1073/pnas.202427399
+///
+/// [2] A. Barducci, G. Bussi, and M. Parrinello, Phys. Rev. Lett. 100,
+/// 020603 (2008); doi: 10.1103/PhysRevLett.100.020603
+pub struct Metadynamics {
+    /// Biased collective variables
+    variables: Vec<Box<CollectiveVariable>>,
+    /// Width of the hills along each collective variable
+    widths: Vec<f64>,
+    /// Initial height of the hills
+    height: f64,
+    /// Number of steps between two hills depositions
+    pace: u64,
+    /// Timestep of the integrator
+    timestep: f64,
+    /// Temperature increase $\Delta T$ used in well-tempered metadynamics,
+    /// if any
+    delta_temperature: Option<f64>,
+    /// All the hills deposited since the beginning of the simulation
+    hills: Vec<Hill>,
+    /// Number of steps since the beginning of the simulation
+    steps: u64,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl Metadynamics {
+    /// Create a new metadynamics bias, depositing hills with the given
+    /// `height` every `pace` steps. The `timestep` must be the one of the
+    /// integrator. Collective variables are added with
+    /// [`add_variable`](#method.add_variable).
+    pub fn new(height: f64, pace: u64, timestep: f64) -> Metadynamics {
+        assert!(height > 0.0, "The hills height must be positive in metadynamics.");
+        assert!(pace > 0, "The deposition pace must be positive in metadynamics.");
+        Metadynamics {
+            variables: Vec::new(),
+            widths: Vec::new(),
+            height: height,
+            pace: pace,
+            timestep: timestep,
+            delta_temperature: None,
+            hills: Vec::new(),
+            steps: 0,
+            output: None,
+        }
+    }
+
+    /// Add a collective `variable` to the bias, using hills with the given
+    /// `width` along this variable.
+    pub fn add_variable(&mut self, variable: Box<CollectiveVariable>, width: f64) {
+        assert!(width > 0.0, "The hills width must be positive in metadynamics.");
+        self.variables.push(variable);
+        self.widths.push(width);
+    }
+
+    /// Use well-tempered metadynamics with the given `bias_factor`, for a
+    /// simulation at `temperature`.
+    pub fn set_well_tempered(&mut self, bias_factor: f64, temperature: f64) {
+        assert!(bias_factor > 1.0, "The bias factor must be larger than 1 in metadynamics.");
+        assert!(temperature > 0.0, "The temperature must be positive in metadynamics.");
+        self.delta_temperature = Some((bias_factor - 1.0) * temperature);
+    }
+
+    /// Write the deposited hills to the file at `path`. The file is
+    /// replaced if it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the number of hills deposited since the beginning of the
+    /// simulation
+    pub fn hills_count(&self) -> usize {
+        self.hills.len()
+    }
+
+    /// Get the current values of the collective variables for the `system`
+    pub fn values(&self, system: &System) -> Vec<f64> {
+        self.variables.iter().map(|variable| variable.value(system)).collect()
+    }
+
+    /// Get the value of the accumulated bias potential at the given `values`
+    /// of the collective variables.
+    pub fn bias(&self, values: &[f64]) -> f64 {
+        assert_eq!(values.len(), self.variables.len(), "Wrong number of collective variables.");
+        self.hills.iter().map(|hill| hill.height * self.gaussian(hill, values)).sum()
+    }
+
+    /// Get the value of the unit gaussian centered on the `hill`, at the
+    /// given `values` of the collective variables.
+    fn gaussian(&self, hill: &Hill, values: &[f64]) -> f64 {
+        let mut exponent = 0.0;
+        for ((&value, &center), &width) in values.iter().zip(&hill.center).zip(&self.widths) {
+            let delta = (value - center) / width;
+            exponent += delta * delta;
+        }
+        return f64::exp(-0.5 * exponent);
+    }
+
+    /// Get the derivatives of the bias potential with respect to each
+    /// collective variable, at the given `values`.
+    fn bias_derivatives(&self, values: &[f64]) -> Vec<f64> {
+        let mut derivatives = vec![0.0; values.len()];
+        for hill in &self.hills {
+            let energy = hill.height * self.gaussian(hill, values);
+            for (k, derivative) in derivatives.iter_mut().enumerate() {
+                let width2 = self.widths[k] * self.widths[k];
+                *derivative -= energy * (values[k] - hill.center[k]) / width2;
+            }
+        }
+        return derivatives;
+    }
+
+    /// Deposit a new hill at the given `values` of the collective variables
+    fn deposit(&mut self, values: Vec<f64>, step: u64) {
+        let bias = self.bias(&values);
+        let height = match self.delta_temperature {
+            Some(delta) => self.height * f64::exp(-bias / (K_BOLTZMANN * delta)),
+            None => self.height,
+        };
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let mut line = step.to_string();
+            for value in &values {
+                line.push_str(&format!(" {}", value));
+            }
+            let height = units::to(height, "kJ/mol").expect("bad unit");
+            let bias = units::to(bias, "kJ/mol").expect("bad unit");
+            if let Err(err) = writeln!(file, "{} {} {}", line, height, bias) {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+
+        self.hills.push(Hill {
+            center: values,
+            height: height,
+        });
+    }
+}
+
+impl Control for Metadynamics {
+    fn setup(&mut self, _: &System) {
+        assert!(!self.variables.is_empty(), "Metadynamics needs at least one collective variable.");
+        self.steps = 0;
+        self.hills.clear();
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let widths = self.widths.iter().map(|width| width.to_string())
+                                           .collect::<Vec<_>>()
+                                           .join(" ");
+            let variables = (0..self.widths.len()).map(|k| format!("s{}", k + 1))
+                                                  .collect::<Vec<_>>()
+                                                  .join(" ");
+            let result = writeln!(file, "# Metadynamics hills").and_then(|_| {
+                writeln!(file, "# Widths: {}", widths)
+            }).and_then(|_| {
+                writeln!(file, "# Step {} Height(kJ/mol) Bias(kJ/mol)", variables)
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("steps", self.steps as f64);
+        let heights = self.hills.iter().map(|hill| hill.height).collect::<Vec<_>>();
+        let centers = self.hills.iter().flat_map(|hill| hill.center.clone()).collect::<Vec<_>>();
+        checkpoint.set_values("heights", &heights);
+        checkpoint.set_values("centers", &centers);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let heights = checkpoint.values("heights")?;
+        let centers = checkpoint.values("centers")?;
+        let n_variables = self.variables.len();
+        if centers.len() != heights.len() * n_variables {
+            return Err(CheckpointError::from(format!(
+                "expected metadynamics hills with {} collective variables in checkpoint",
+                n_variables
+            )));
+        }
+
+        self.steps = checkpoint.value("steps")? as u64;
+        self.hills = heights.iter().zip(centers.chunks(n_variables)).map(|(&height, center)| {
+            Hill {
+                center: center.to_vec(),
+                height: height,
+            }
+        }).collect();
+        Ok(())
+    }
+
+    fn control(&mut self, system: &mut System) {
+        let values = self.values(system);
+        self.steps += 1;
+        if self.steps % self.pace == 0 {
+            self.deposit(values.clone(), system.step);
+        }
+
+        let derivatives = self.bias_derivatives(&values);
+        if derivatives.iter().all(|&derivative| derivative == 0.0) {
+            return;
+        }
+
+        let mut forces = vec![Vector3D::zero(); system.size()];
+        for (variable, &derivative) in self.variables.iter().zip(&derivatives) {
+            for (i, gradient) in variable.gradient(system) {
+                forces[i] -= derivative * gradient;
+            }
+        }
+
+        // Frozen particles and virtual sites are not moved by the bias
+        let dt = self.timestep;
+        for (&mass, &frozen, velocity, force) in soa_zip!(
+            system.particles_mut(), [mass, frozen, mut velocity], &forces
+        ) {
+            if !frozen && mass != 0.0 {
+                *velocity += dt * force / mass;
+            }
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Some((ref mut file, ref path)) = self.output {
+            if let Err(err) = file.flush() {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+    use md::DistanceVariable;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [2.0, 0.0, 0.0].into())));
+        return system;
+    }
+
+    #[test]
+    fn deposit() {
+        let mut system = testing_system();
+        let mut metadynamics = Metadynamics::new(1.5, 2, 1.0);
+        metadynamics.add_variable(Box::new(DistanceVariable::new(0, 1)), 0.1);
+        metadynamics.setup(&system);
+
+        metadynamics.control(&mut system);
+        assert_eq!(metadynamics.hills_count(), 0);
+        assert_eq!(system.particles().velocity[0], Vector3D::zero());
+
+        metadynamics.control(&mut system);
+        assert_eq!(metadynamics.hills_count(), 1);
+        assert_ulps_eq!(metadynamics.bias(&[2.0]), 1.5);
+        assert_ulps_eq!(metadynamics.bias(&[2.1]), 1.5 * f64::exp(-0.5));
+        // The particles are exactly at the center of the hill, so there is
+        // no force
+        assert_eq!(system.particles().velocity[0], Vector3D::zero());
+
+        // Move the particles away from the hill, the bias pushes them further
+        system.particles_mut().position[1][0] = 2.05;
+        metadynamics.control(&mut system);
+        let velocities = system.particles().velocity;
+        assert!(velocities[0][0] < 0.0);
+        assert!(velocities[1][0] > 0.0);
+        assert_relative_eq!(velocities[0] + velocities[1], Vector3D::zero(), epsilon = 1e-15);
+
+        let mass = system.particles().mass[1];
+        let force = 1.5 * f64::exp(-0.5 * 0.25) * 0.05 / 0.01;
+        assert_relative_eq!(velocities[1][0], force / mass, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn well_tempered() {
+        let mut system = testing_system();
+        let mut metadynamics = Metadynamics::new(1.5, 1, 1.0);
+        metadynamics.add_variable(Box::new(DistanceVariable::new(0, 1)), 0.1);
+        metadynamics.set_well_tempered(10.0, 300.0);
+        metadynamics.setup(&system);
+
+        metadynamics.control(&mut system);
+        assert_ulps_eq!(metadynamics.bias(&[2.0]), 1.5);
+
+        // The second hill is smaller
+        metadynamics.control(&mut system);
+        let expected = 1.5 + 1.5 * f64::exp(-1.5 / (K_BOLTZMANN * 9.0 * 300.0));
+        assert_relative_eq!(metadynamics.bias(&[2.0]), expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "Metadynamics needs at least one collective variable.")]
+    fn no_variables() {
+        let system = testing_system();
+        let mut metadynamics = Metadynamics::new(1.5, 2, 1.0);
+        metadynamics.setup(&system);
+    }
+}
@@ -3,17 +3,54 @@
 
 //! Molecular dynamics algorithms.
 mod integrators;
-pub use self::integrators::AnisoBerendsenBarostat;
+pub use self::integrators::{AnisoBerendsenBarostat, PressureCoupling};
 pub use self::integrators::BerendsenBarostat;
 pub use self::integrators::Integrator;
+pub(crate) use self::integrators::check_no_constraints;
+pub use self::integrators::{BondedSplitting, ForceSplitting, RESPA};
+pub use self::integrators::Langevin;
 pub use self::integrators::LeapFrog;
+pub use self::integrators::MTKIntegrator;
+pub use self::integrators::ParrinelloRahmanBarostat;
+pub use self::integrators::RigidBodyVerlet;
 pub use self::integrators::VelocityVerlet;
 pub use self::integrators::Verlet;
 
+mod sllod;
+pub use self::sllod::SLLOD;
+
+mod adaptive;
+pub use self::adaptive::{AdaptiveTimestep, TimestepCriterion};
+
+mod chains;
+mod constraints;
+mod rigid;
+
 mod controls;
-pub use self::controls::{BerendsenThermostat, RescaleThermostat};
+pub use self::controls::{BerendsenThermostat, CSVRThermostat, RescaleThermostat};
+pub use self::controls::NoseHooverChainsThermostat;
 pub use self::controls::{Control, Thermostat};
+pub use self::controls::{ScheduledThermostat, TemperatureSchedule};
+pub use self::controls::GroupThermostat;
 pub use self::controls::{RemoveRotation, RemoveTranslation, Rewrap};
 
+mod pulling;
+pub use self::pulling::ConstantVelocityPulling;
+
+mod colvars;
+pub use self::colvars::{AngleVariable, CollectiveVariable, CoordinationNumber, DistanceVariable};
+
+mod metadynamics;
+pub use self::metadynamics::Metadynamics;
+
+mod umbrella;
+pub use self::umbrella::UmbrellaSampling;
+
+mod muller_plathe;
+pub use self::muller_plathe::{ExchangedQuantity, MullerPlathe};
+
 mod molecular_dynamics;
 pub use self::molecular_dynamics::MolecularDynamics;
+
+mod replica_exchange;
+pub use self::replica_exchange::ReplicaExchange;
@@ -2,7 +2,9 @@
 // Copyright (C) Lumol's contributors — BSD license
 
 use propagator::{Propagator, TemperatureStrategy};
-use core::{System, DegreesOfFreedom};
+use core::{profiler, System, DegreesOfFreedom};
+
+use checkpoint::{Checkpoint, CheckpointError};
 
 use super::{Control, Integrator, Thermostat};
 use super::VelocityVerlet;
@@ -43,6 +45,32 @@ impl MolecularDynamics {
     pub fn set_thermostat(&mut self, thermostat: Box<Thermostat>) {
         self.thermostat = Some(thermostat);
     }
+
+    /// Check if this simulation uses a thermostat
+    pub fn has_thermostat(&self) -> bool {
+        self.thermostat.is_some()
+    }
+
+    /// Set the target temperature of the thermostat used in this simulation
+    ///
+    /// # Panics
+    ///
+    /// If this simulation does not use a thermostat
+    pub fn set_temperature(&mut self, temperature: f64) {
+        self.thermostat.as_mut()
+                       .expect("Can not set the temperature without a thermostat")
+                       .set_temperature(temperature);
+    }
+
+    /// Get the energy of the additional degrees of freedom of the integrator
+    /// and the thermostat, if both of them have a conserved quantity.
+    fn extended_energy(&self, system: &System) -> Option<f64> {
+        let mut energy = self.integrator.conserved_energy(system)?;
+        if let Some(ref thermostat) = self.thermostat {
+            energy += thermostat.conserved_energy()?;
+        }
+        return Some(energy);
+    }
 }
 
 impl Propagator for MolecularDynamics {
@@ -50,10 +78,18 @@ impl Propagator for MolecularDynamics {
         TemperatureStrategy::Velocities
     }
 
-    fn degrees_of_freedom(&self, _: &System) -> DegreesOfFreedom {
-        // default to particles for now. change this if/when constrains are
-        // implemented
-        DegreesOfFreedom::Particles
+    fn degrees_of_freedom(&self, system: &System) -> DegreesOfFreedom {
+        // Holonomic constraints and frozen particles are directly accounted
+        // for by the system, we only need to add the degrees of freedom
+        // removed by the controls.
+        let removed = self.controls.iter()
+                                   .map(|control| control.removed_degrees_of_freedom(system))
+                                   .sum::<usize>();
+        if removed == 0 {
+            DegreesOfFreedom::Particles
+        } else {
+            DegreesOfFreedom::Frozen(removed)
+        }
     }
 
     fn setup(&mut self, system: &System) {
@@ -64,15 +100,24 @@ impl Propagator for MolecularDynamics {
     }
 
     fn propagate(&mut self, system: &mut System) {
-        self.integrator.integrate(system);
+        {
+            let _timer = profiler::start("integrator");
+            self.integrator.integrate(system);
+        }
 
         if let Some(ref mut thermostat) = self.thermostat {
+            let _timer = profiler::start("thermostat");
             thermostat.control(system);
         }
 
-        for control in &mut self.controls {
-            control.control(system);
+        if !self.controls.is_empty() {
+            let _timer = profiler::start("controls");
+            for control in &mut self.controls {
+                control.control(system);
+            }
         }
+
+        system.extended_energy = self.extended_energy(system);
     }
 
     fn finish(&mut self, system: &System) {
@@ -80,4 +125,27 @@ impl Propagator for MolecularDynamics {
             control.finish(system);
         }
     }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        self.integrator.save_checkpoint(checkpoint.section_mut("integrator"));
+        if let Some(ref mut thermostat) = self.thermostat {
+            thermostat.save_checkpoint(checkpoint.section_mut("thermostat"));
+        }
+        let section = checkpoint.section_mut("controls");
+        for (i, control) in self.controls.iter_mut().enumerate() {
+            control.save_checkpoint(section.section_mut(&i.to_string()));
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.integrator.restore_checkpoint(checkpoint.section("integrator")?)?;
+        if let Some(ref mut thermostat) = self.thermostat {
+            thermostat.restore_checkpoint(checkpoint.section("thermostat")?)?;
+        }
+        let section = checkpoint.section("controls")?;
+        for (i, control) in self.controls.iter_mut().enumerate() {
+            control.restore_checkpoint(section.section(&i.to_string())?)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,349 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Reverse non-equilibrium molecular dynamics, using the Müller-Plathe
+//! exchange algorithm.
+use core::{System, Vector3D};
+use core::units;
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+use super::Control;
+
+/// Quantity exchanged between the slabs by the Müller-Plathe algorithm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExchangedQuantity {
+    /// Exchange the x component of the momentum, creating a shear flow along
+    /// x with a velocity gradient along z, to compute the shear viscosity.
+    Momentum,
+    /// Exchange the kinetic energy, creating a temperature gradient along z,
+    /// to compute the thermal conductivity.
+    Energy,
+}
+
+/// Müller-Plathe reverse non-equilibrium molecular dynamics [1, 2].
+///
+/// The cell is divided in `slabs` slabs along the z axis, and every `every`
+/// steps, momentum or kinetic energy is exchanged between a particle in the
+/// first slab and a particle in the middle slab. This imposes a known flux
+/// $J$ through the system, and the corresponding gradient of velocity or
+/// temperature can be measured with the
+/// [`ProfilesOutput`](../output/struct.ProfilesOutput.html).
+///
+/// - When exchanging momentum, the particle with the most negative x velocity
+///   in the first slab is exchanged with the particle with the most positive x
+///   velocity in the middle slab. The shear viscosity is then given by
+///   $\eta = - J_p / (\partial v_x / \partial z)$;
+/// - When exchanging energy, the hottest particle in the first slab is
+///   exchanged with the coldest particle in the middle slab. The thermal
+///   conductivity is then given by $\lambda = - J_E / (\partial T / \partial
+///   z)$.
+///
+/// The exchange is done with an elastic collision between the two particles,
+/// which reduces to swapping their velocities when the particles have the same
+/// mass, and always conserves the total momentum and energy.
+///
+/// [1] Müller-Plathe, F. *A simple nonequilibrium molecular dynamics method
+/// for calculating the thermal conductivity*, J. Chem. Phys. **106**, 6082
+/// (1997).
+///
+/// [2] Müller-Plathe, F. *Reversing the perturbation in nonequilibrium
+/// molecular dynamics: An easy way to calculate the shear viscosity of fluids*,
+/// Phys. Rev. E **59**, 4894 (1999).
+pub struct MullerPlathe {
+    /// Exchanged quantity
+    quantity: ExchangedQuantity,
+    /// Number of slabs along the z axis
+    slabs: usize,
+    /// Number of steps between two exchanges
+    every: u64,
+    /// Timestep of the integrator
+    timestep: f64,
+    /// Number of steps since the beginning of the simulation
+    steps: u64,
+    /// Number of successful exchanges
+    exchanges: u64,
+    /// Total momentum or energy transferred by the exchanges
+    transferred: f64,
+    /// Area of the cell in the xy plane, at the last step
+    area: f64,
+}
+
+impl MullerPlathe {
+    /// Create a new Müller-Plathe control exchanging the given `quantity`
+    /// every `every` steps, using `slabs` slabs along the z axis. The
+    /// `timestep` must be the one of the integrator.
+    ///
+    /// # Panics
+    ///
+    /// If the number of slabs is not a positive even number, or if
+    /// `every` is zero.
+    pub fn new(
+        quantity: ExchangedQuantity,
+        slabs: usize,
+        every: u64,
+        timestep: f64,
+    ) -> MullerPlathe {
+        assert!(
+            slabs >= 2 && slabs % 2 == 0,
+            "The number of slabs must be an even number in Müller-Plathe exchange."
+        );
+        assert!(every > 0, "The exchange frequency must be positive in Müller-Plathe exchange.");
+        MullerPlathe {
+            quantity: quantity,
+            slabs: slabs,
+            every: every,
+            timestep: timestep,
+            steps: 0,
+            exchanges: 0,
+            transferred: 0.0,
+            area: 0.0,
+        }
+    }
+
+    /// Get the number of successful exchanges since the beginning of the
+    /// simulation
+    pub fn exchanges(&self) -> u64 {
+        self.exchanges
+    }
+
+    /// Get the total momentum or energy transferred between the first and
+    /// the middle slabs by the exchanges since the beginning of the simulation
+    pub fn transferred(&self) -> f64 {
+        self.transferred
+    }
+
+    /// Get the flux of momentum or energy imposed by the exchanges, i.e. the
+    /// transferred quantity by unit of time and by unit of area. The
+    /// transferred quantity is divided by two, as it flows in both
+    /// directions along z because of the periodic boundary conditions.
+    pub fn flux(&self) -> f64 {
+        let time = self.steps as f64 * self.timestep;
+        if time == 0.0 || self.area == 0.0 {
+            return 0.0;
+        }
+        return self.transferred / (2.0 * time * self.area);
+    }
+
+    /// Get the slab containing the given `position` in the `system`
+    fn slab(&self, system: &System, position: &Vector3D) -> usize {
+        let z = system.cell.fractional(position)[2];
+        let z = z - f64::floor(z);
+        let slab = (z * self.slabs as f64) as usize;
+        return usize::min(slab, self.slabs - 1);
+    }
+
+    /// Find the particle in the given `slab` maximizing the `score` function
+    fn find<F>(&self, system: &System, slab: usize, score: F) -> Option<usize>
+    where
+        F: Fn(f64, &Vector3D) -> f64,
+    {
+        let particles = system.particles();
+        let mut best: Option<(usize, f64)> = None;
+        for (i, (&mass, &frozen, position, velocity)) in soa_zip!(
+            particles, [mass, frozen, position, velocity]
+        ).enumerate() {
+            if frozen || mass == 0.0 || self.slab(system, position) != slab {
+                continue;
+            }
+            let value = score(mass, velocity);
+            match best {
+                Some((_, best_value)) if best_value >= value => {}
+                _ => best = Some((i, value)),
+            }
+        }
+        return best.map(|(i, _)| i);
+    }
+
+    /// Exchange the momentum or energy between the particles `i` in the
+    /// first slab and `j` in the middle slab, if this creates a flux in the
+    /// right direction. The transferred quantity is returned if the exchange
+    /// was done.
+    fn exchange(&self, system: &mut System, i: usize, j: usize) -> Option<f64> {
+        let particles = system.particles_mut();
+        let (mi, mj) = (particles.mass[i], particles.mass[j]);
+        let (vi, vj) = (particles.velocity[i], particles.velocity[j]);
+        let center = (mi * vi + mj * vj) / (mi + mj);
+        let (new_vi, new_vj, transferred) = match self.quantity {
+            ExchangedQuantity::Momentum => {
+                // Elastic collision along the x axis
+                let mut new_vi = vi;
+                let mut new_vj = vj;
+                new_vi[0] = 2.0 * center[0] - vi[0];
+                new_vj[0] = 2.0 * center[0] - vj[0];
+                (new_vi, new_vj, mj * (vj[0] - new_vj[0]))
+            }
+            ExchangedQuantity::Energy => {
+                // Elastic collision in three dimensions
+                let new_vi = 2.0 * center - vi;
+                let new_vj = 2.0 * center - vj;
+                (new_vi, new_vj, 0.5 * mi * (vi.norm2() - new_vi.norm2()))
+            }
+        };
+
+        if transferred <= 0.0 {
+            return None;
+        }
+        particles.velocity[i] = new_vi;
+        particles.velocity[j] = new_vj;
+        return Some(transferred);
+    }
+}
+
+impl Control for MullerPlathe {
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Müller-Plathe exchange can not be used with an infinite cell."
+        );
+        self.steps = 0;
+        self.exchanges = 0;
+        self.transferred = 0.0;
+        self.area = system.volume() / system.cell.lengths()[2];
+    }
+
+    fn control(&mut self, system: &mut System) {
+        self.steps += 1;
+        self.area = system.volume() / system.cell.lengths()[2];
+        if self.steps % self.every != 0 {
+            return;
+        }
+
+        let middle = self.slabs / 2;
+        let (first, second) = match self.quantity {
+            ExchangedQuantity::Momentum => (
+                self.find(system, 0, |_, velocity| -velocity[0]),
+                self.find(system, middle, |_, velocity| velocity[0]),
+            ),
+            ExchangedQuantity::Energy => (
+                self.find(system, 0, |mass, velocity| mass * velocity.norm2()),
+                self.find(system, middle, |mass, velocity| -mass * velocity.norm2()),
+            ),
+        };
+
+        if let (Some(i), Some(j)) = (first, second) {
+            if let Some(transferred) = self.exchange(system, i, j) {
+                self.transferred += transferred;
+                self.exchanges += 1;
+            }
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        match self.quantity {
+            ExchangedQuantity::Momentum => {
+                let flux = units::to(self.flux(), "bar").expect("bad unit");
+                info!(
+                    "Müller-Plathe momentum flux: {} bar, in {} exchanges",
+                    flux, self.exchanges
+                );
+            }
+            ExchangedQuantity::Energy => {
+                let flux = units::to(self.flux(), "kJ/mol/A^2/ps").expect("bad unit");
+                info!(
+                    "Müller-Plathe energy flux: {} kJ/mol/A^2/ps, in {} exchanges",
+                    flux, self.exchanges
+                );
+            }
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("steps", self.steps as f64);
+        checkpoint.set_value("exchanges", self.exchanges as f64);
+        checkpoint.set_value("transferred", self.transferred);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.steps = checkpoint.value("steps")? as u64;
+        self.exchanges = checkpoint.value("exchanges")? as u64;
+        self.transferred = checkpoint.value("transferred")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 1.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 2.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 5.5].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 6.0].into())));
+        system.particles_mut().velocity[0] = Vector3D::new(-0.1, 0.0, 0.0);
+        system.particles_mut().velocity[1] = Vector3D::new(0.05, 0.2, 0.0);
+        system.particles_mut().velocity[2] = Vector3D::new(0.3, 0.0, 0.0);
+        system.particles_mut().velocity[3] = Vector3D::new(0.01, 0.0, 0.0);
+        return system;
+    }
+
+    #[test]
+    fn momentum() {
+        let mut system = testing_system();
+        let mut exchange = MullerPlathe::new(ExchangedQuantity::Momentum, 4, 2, 1.0);
+        exchange.setup(&system);
+
+        exchange.control(&mut system);
+        assert_eq!(exchange.exchanges(), 0);
+
+        exchange.control(&mut system);
+        assert_eq!(exchange.exchanges(), 1);
+        // Equal masses: the x velocities are swapped
+        let velocities = &system.particles().velocity;
+        assert_relative_eq!(velocities[0][0], 0.3, epsilon = 1e-12);
+        assert_relative_eq!(velocities[2][0], -0.1, epsilon = 1e-12);
+
+        let mass = system.particles().mass[0];
+        assert_relative_eq!(exchange.transferred(), 0.4 * mass, epsilon = 1e-12);
+        assert_relative_eq!(exchange.flux(), 0.4 * mass / (2.0 * 2.0 * 100.0), epsilon = 1e-12);
+
+        // No exchange in the wrong direction
+        exchange.control(&mut system);
+        exchange.control(&mut system);
+        assert_eq!(exchange.exchanges(), 1);
+    }
+
+    #[test]
+    fn energy() {
+        let mut system = testing_system();
+        let mut exchange = MullerPlathe::new(ExchangedQuantity::Energy, 4, 1, 1.0);
+        exchange.setup(&system);
+        let energy = system.kinetic_energy();
+
+        exchange.control(&mut system);
+        assert_eq!(exchange.exchanges(), 1);
+        // The hottest particle in the first slab is exchanged with the
+        // coldest in the middle slab
+        let velocities = &system.particles().velocity;
+        assert_relative_eq!(velocities[1], Vector3D::new(0.01, 0.0, 0.0), epsilon = 1e-12);
+        assert_relative_eq!(velocities[3], Vector3D::new(0.05, 0.2, 0.0), epsilon = 1e-12);
+
+        assert_relative_eq!(system.kinetic_energy(), energy, epsilon = 1e-12);
+        let mass = system.particles().mass[0];
+        let expected = 0.5 * mass * (0.0425 - 0.0001);
+        assert_relative_eq!(exchange.transferred(), expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn different_masses() {
+        let mut system = testing_system();
+        system.particles_mut().mass[2] *= 3.0;
+        let momentum = system.particles().mass[0] * system.particles().velocity[0][0]
+                     + system.particles().mass[2] * system.particles().velocity[2][0];
+        let energy = system.kinetic_energy();
+
+        let mut exchange = MullerPlathe::new(ExchangedQuantity::Momentum, 4, 1, 1.0);
+        exchange.setup(&system);
+        exchange.control(&mut system);
+        assert_eq!(exchange.exchanges(), 1);
+
+        let particles = system.particles();
+        let new_momentum = particles.mass[0] * particles.velocity[0][0]
+                         + particles.mass[2] * particles.velocity[2][0];
+        assert_relative_eq!(new_momentum, momentum, epsilon = 1e-12);
+        assert_relative_eq!(system.kinetic_energy(), energy, epsilon = 1e-12);
+    }
+}
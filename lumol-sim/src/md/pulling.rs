@@ -0,0 +1,312 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Steered molecular dynamics, pulling a group of particles with a moving
+//! spring.
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use core::{System, Vector3D};
+use core::units;
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+use super::Control;
+
+/// Constant velocity pulling of a particle or of the center-of-mass of a
+/// group of particles, for steered molecular dynamics.
+///
+/// The pulled center-of-mass is attached to an harmonic spring acting along
+/// the pulling `direction`, and the other end of the spring moves with a
+/// constant velocity $v$ along this direction. Using $\xi$ for the position
+/// of the center-of-mass projected on the direction, the force exerted by
+/// the spring at time $t$ is
+///
+/// $$ f = k (\xi_0 + v t - \xi) $$
+///
+/// where $\xi_0$ is the initial position of the center-of-mass. The work
+/// done by the spring, $W = \int v f dt$, is accumulated during the
+/// simulation, and can be used to estimate the potential of mean force
+/// along the pulling coordinate with the Jarzynski equality, averaging
+/// $\exp(- W / k_B T)$ over multiple independent pulling simulations.
+///
+/// As for all the controls, the pulling force is applied between two steps
+/// of the integrator, by changing the velocities of the particles in the
+/// group by $f \Delta t / M$, where $M$ is the total mass of the group.
+///
+/// The time, the positions of the spring and of the center-of-mass, the
+/// force and the work can be written to a file after each step with
+/// [`set_output`](#method.set_output).
+pub struct ConstantVelocityPulling {
+    /// Indexes of the pulled particles
+    particles: Vec<usize>,
+    /// Unit vector giving the pulling direction
+    direction: Vector3D,
+    /// Force constant of the spring
+    k: f64,
+    /// Velocity of the spring along the pulling direction
+    velocity: f64,
+    /// Timestep of the integrator
+    timestep: f64,
+    /// Time since the beginning of the pulling
+    time: f64,
+    /// Position of the center-of-mass, followed through the periodic
+    /// boundary conditions
+    center: Vector3D,
+    /// Initial position of the center-of-mass along the pulling direction
+    initial: f64,
+    /// Force exerted by the spring during the last step
+    force: f64,
+    /// Work done by the spring since the beginning of the pulling
+    work: f64,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl ConstantVelocityPulling {
+    /// Create a new pulling control, pulling the center-of-mass of the
+    /// `particles` along the `direction` with a spring of force constant `k`
+    /// moving at `velocity`. The `timestep` must be the one of the
+    /// integrator.
+    ///
+    /// # Panics
+    ///
+    /// If there are no particles, if the direction is the zero vector, or
+    /// if the force constant is negative.
+    pub fn new(
+        particles: Vec<usize>,
+        direction: Vector3D,
+        k: f64,
+        velocity: f64,
+        timestep: f64,
+    ) -> ConstantVelocityPulling {
+        assert!(!particles.is_empty(), "Pulling needs at least one particle.");
+        assert!(direction.norm2() > 0.0, "The pulling direction can not be the zero vector.");
+        assert!(k >= 0.0, "The force constant must be positive in pulling.");
+        ConstantVelocityPulling {
+            particles: particles,
+            direction: direction.normalized(),
+            k: k,
+            velocity: velocity,
+            timestep: timestep,
+            time: 0.0,
+            center: Vector3D::zero(),
+            initial: 0.0,
+            force: 0.0,
+            work: 0.0,
+            output: None,
+        }
+    }
+
+    /// Write the pulling data to the file at `path` after each step. The
+    /// file is replaced if it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the force exerted by the spring along the pulling direction during
+    /// the last step
+    pub fn force(&self) -> f64 {
+        self.force
+    }
+
+    /// Get the work done by the spring since the beginning of the pulling
+    pub fn work(&self) -> f64 {
+        self.work
+    }
+
+    /// Get the current position of the spring along the pulling direction
+    pub fn spring_position(&self) -> f64 {
+        self.initial + self.velocity * self.time
+    }
+
+    /// Get the current position of the pulled center-of-mass along the
+    /// pulling direction
+    pub fn position(&self) -> f64 {
+        self.center * self.direction
+    }
+
+    /// Get the center-of-mass of the pulled particles in the `system`. All
+    /// the particles are brought back close to the first one using the
+    /// nearest periodic image.
+    fn center_of_mass(&self, system: &System) -> Vector3D {
+        let particles = system.particles();
+        let origin = particles.position[self.particles[0]];
+        let mut total_mass = 0.0;
+        let mut center = Vector3D::zero();
+        for &i in &self.particles {
+            let mut delta = particles.position[i] - origin;
+            system.cell.vector_image(&mut delta);
+            center += particles.mass[i] * delta;
+            total_mass += particles.mass[i];
+        }
+
+        if total_mass == 0.0 {
+            return origin;
+        }
+        return origin + center / total_mass;
+    }
+
+    /// Write a line to the output file, if any
+    fn write(&mut self, step: u64) {
+        let spring = units::to(self.spring_position(), "A").expect("bad unit");
+        let position = units::to(self.position(), "A").expect("bad unit");
+        let force = units::to(self.force, "kJ/mol/A").expect("bad unit");
+        let work = units::to(self.work, "kJ/mol").expect("bad unit");
+        let time = units::to(self.time, "ps").expect("bad unit");
+        if let Some((ref mut file, ref path)) = self.output {
+            let result = writeln!(
+                file, "{} {} {} {} {} {}", step, time, spring, position, force, work
+            );
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+impl Control for ConstantVelocityPulling {
+    fn setup(&mut self, system: &System) {
+        for &i in &self.particles {
+            assert!(i < system.size(), "The particle index {} in pulling is out of bounds.", i);
+        }
+
+        self.time = 0.0;
+        self.work = 0.0;
+        self.force = 0.0;
+        self.center = self.center_of_mass(system);
+        self.initial = self.position();
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let result = writeln!(file, "# Constant velocity pulling").and_then(|_| {
+                writeln!(file, "# Step Time(ps) Spring(A) Position(A) Force(kJ/mol/A) Work(kJ/mol)")
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn control(&mut self, system: &mut System) {
+        // Follow the center-of-mass through the periodic boundary conditions
+        let mut delta = self.center_of_mass(system) - self.center;
+        system.cell.vector_image(&mut delta);
+        self.center += delta;
+
+        self.time += self.timestep;
+        self.force = self.k * (self.spring_position() - self.position());
+        self.work += self.velocity * self.force * self.timestep;
+
+        // Frozen particles and virtual sites are not moved by the force
+        let particles = system.particles_mut();
+        let total_mass = self.particles.iter()
+                                       .filter(|&&i| !particles.frozen[i])
+                                       .map(|&i| particles.mass[i])
+                                       .sum::<f64>();
+        if total_mass != 0.0 {
+            let kick = self.force * self.timestep / total_mass * self.direction;
+            for &i in &self.particles {
+                if !particles.frozen[i] && particles.mass[i] != 0.0 {
+                    particles.velocity[i] += kick;
+                }
+            }
+        }
+
+        let step = system.step;
+        self.write(step);
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("time", self.time);
+        checkpoint.set_vectors("center", &[self.center]);
+        checkpoint.set_value("initial", self.initial);
+        checkpoint.set_value("force", self.force);
+        checkpoint.set_value("work", self.work);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let center = checkpoint.vectors("center")?;
+        if center.len() != 1 {
+            return Err(CheckpointError::from(
+                "wrong number of values for 'center' in checkpoint".to_string()
+            ));
+        }
+        self.center = center[0];
+        self.time = checkpoint.value("time")?;
+        self.initial = checkpoint.value("initial")?;
+        self.force = checkpoint.value("force")?;
+        self.work = checkpoint.value("work")?;
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Some((ref mut file, ref path)) = self.output {
+            if let Err(err) = file.flush() {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [1.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 3.0, 0.0].into())));
+        return system;
+    }
+
+    #[test]
+    fn pulling() {
+        let mut system = testing_system();
+        let direction = Vector3D::new(0.0, 0.0, 2.0);
+        let mut pulling = ConstantVelocityPulling::new(vec![0, 1], direction, 3.0, 0.01, 0.5);
+        pulling.setup(&system);
+        assert_eq!(pulling.position(), 0.0);
+        assert_eq!(pulling.spring_position(), 0.0);
+
+        pulling.control(&mut system);
+        assert_ulps_eq!(pulling.spring_position(), 0.005);
+        assert_ulps_eq!(pulling.force(), 3.0 * 0.005);
+        assert_ulps_eq!(pulling.work(), 0.01 * 3.0 * 0.005 * 0.5);
+
+        // The force is shared between the particles in the group
+        let mass = system.particles().mass[0];
+        let expected = Vector3D::new(0.0, 0.0, 3.0 * 0.005 * 0.5 / (2.0 * mass));
+        assert_relative_eq!(system.particles().velocity[0], expected, epsilon = 1e-15);
+        assert_relative_eq!(system.particles().velocity[1], expected, epsilon = 1e-15);
+        assert_eq!(system.particles().velocity[2], Vector3D::zero());
+    }
+
+    #[test]
+    fn periodic_boundary_conditions() {
+        let mut system = testing_system();
+        let direction = Vector3D::new(1.0, 0.0, 0.0);
+        let mut pulling = ConstantVelocityPulling::new(vec![0], direction, 0.0, 1.0, 1.0);
+        pulling.setup(&system);
+
+        // Crossing the cell boundary does not change the pulling coordinate
+        for _ in 0..12 {
+            system.particles_mut().position[0][0] += 1.0;
+            system.particles_mut().position[0][0] %= 10.0;
+            pulling.control(&mut system);
+        }
+        assert_ulps_eq!(pulling.position(), 12.0);
+        assert_ulps_eq!(pulling.spring_position(), 12.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The pulling direction can not be the zero vector.")]
+    fn zero_direction() {
+        let _ = ConstantVelocityPulling::new(vec![0], Vector3D::zero(), 1.0, 1.0, 1.0);
+    }
+}
@@ -0,0 +1,447 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Temperature replica exchange molecular dynamics
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rayon::prelude::*;
+
+use core::consts::K_BOLTZMANN;
+use core::{DegreesOfFreedom, System};
+
+use checkpoint::{Checkpoint, CheckpointError};
+use propagator::{Propagator, TemperatureStrategy};
+use rng::rng_from_seed;
+use super::MolecularDynamics;
+
+/// A single replica, made of a system and the propagator used to simulate it
+struct Replica {
+    /// Identifier of this replica, i.e. the index of the temperature it
+    /// started at
+    id: usize,
+    /// Molecular dynamics propagator for this replica
+    propagator: MolecularDynamics,
+    /// The system of this replica
+    system: System,
+}
+
+/// Temperature replica exchange molecular dynamics (REMD) propagator.
+///
+/// Multiple copies of the system, called replicas, are simulated in
+/// parallel at different temperatures, each one in a separate thread. Every
+/// `every` steps, exchanges of configurations between neighboring
+/// temperatures $T_i$ and $T_j$ are attempted, and accepted with the
+/// Metropolis probability
+///
+/// $$ P = \min \left(1, \exp \left[ \left( \frac{1}{k_B T_i} - \frac{1}{k_B
+/// T_j} \right) (U_i - U_j) \right] \right) $$
+///
+/// where $U_i$ and $U_j$ are the potential energies of the replicas. The
+/// velocities of exchanged replicas are rescaled to the new temperatures.
+/// Exchanges between even and odd pairs of neighbors are attempted
+/// alternately.
+///
+/// The system given to the simulation is always the one at the lowest
+/// temperature, so that all the outputs of the simulation sample the
+/// canonical ensemble at this temperature. The sequence of exchanges can be
+/// written to a file with [`set_output`](#method.set_output) to follow
+/// each replica through the temperatures, and the acceptance ratios of the
+/// exchanges are written at the end of the simulation.
+pub struct ReplicaExchange {
+    /// Replicas, ordered by increasing temperature
+    replicas: Vec<Replica>,
+    /// Temperatures of the replicas
+    temperatures: Vec<f64>,
+    /// Number of steps between exchange attempts
+    every: u64,
+    /// Number of steps since the beginning of the simulation
+    steps: u64,
+    /// Index of the first neighbor pair for the next exchange attempt,
+    /// alternating between 0 and 1
+    offset: usize,
+    /// Number of attempted exchanges between the temperatures `i` and `i + 1`
+    attempted: Vec<u64>,
+    /// Number of accepted exchanges between the temperatures `i` and `i + 1`
+    accepted: Vec<u64>,
+    /// Random number generator for the exchanges
+    rng: XorShiftRng,
+    /// Output file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl ReplicaExchange {
+    /// Create a new replica exchange propagator, attempting exchanges every
+    /// `every` steps. Replicas are added with
+    /// [`add_replica`](#method.add_replica).
+    pub fn new(every: u64) -> ReplicaExchange {
+        assert!(every > 0, "The exchange frequency must be positive in replica exchange.");
+        ReplicaExchange {
+            replicas: Vec::new(),
+            temperatures: Vec::new(),
+            every: every,
+            steps: 0,
+            offset: 0,
+            attempted: Vec::new(),
+            accepted: Vec::new(),
+            rng: XorShiftRng::from_seed([
+                0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
+                0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
+            ]),
+            output: None,
+        }
+    }
+
+    /// Add a replica at the given `temperature`, simulated with the
+    /// `propagator`. The temperature of the propagator thermostat is set to
+    /// `temperature`.
+    ///
+    /// # Panics
+    ///
+    /// If the `propagator` does not use a thermostat, or if the temperature
+    /// is not larger than the temperature of the previous replica.
+    pub fn add_replica(&mut self, mut propagator: MolecularDynamics, temperature: f64) {
+        assert!(
+            propagator.has_thermostat(),
+            "All the replicas must use a thermostat in replica exchange."
+        );
+        if let Some(&last) = self.temperatures.last() {
+            assert!(
+                temperature > last,
+                "The replicas must be added by increasing temperature in replica exchange."
+            );
+        }
+
+        propagator.set_temperature(temperature);
+        self.replicas.push(Replica {
+            id: self.replicas.len(),
+            propagator: propagator,
+            system: System::new(),
+        });
+        self.temperatures.push(temperature);
+    }
+
+    /// Set the seed of the random number generator. The default seed is 42.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
+    }
+
+    /// Write the exchanges to the file at `path`. The file is replaced if
+    /// it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the identifiers of the replicas currently simulated at each
+    /// temperature. The identifier of a replica is the index of the
+    /// temperature it started at.
+    pub fn replicas(&self) -> Vec<usize> {
+        self.replicas.iter().map(|replica| replica.id).collect()
+    }
+
+    /// Get the acceptance ratio of the exchanges between the temperatures
+    /// `i` and `i + 1`, for all `i`.
+    pub fn acceptance(&self) -> Vec<f64> {
+        self.attempted.iter().zip(&self.accepted).map(|(&attempted, &accepted)| {
+            if attempted == 0 {
+                0.0
+            } else {
+                accepted as f64 / attempted as f64
+            }
+        }).collect()
+    }
+
+    /// Attempt exchanges between the replicas, the system at the lowest
+    /// temperature being stored in the first replica.
+    fn exchange(&mut self, step: u64) {
+        let mut energies = self.replicas.iter()
+                                        .map(|replica| replica.system.potential_energy())
+                                        .collect::<Vec<_>>();
+
+        let mut i = self.offset;
+        while i + 1 < self.replicas.len() {
+            let j = i + 1;
+            let beta_i = 1.0 / (K_BOLTZMANN * self.temperatures[i]);
+            let beta_j = 1.0 / (K_BOLTZMANN * self.temperatures[j]);
+            let delta = (beta_i - beta_j) * (energies[i] - energies[j]);
+
+            self.attempted[i] += 1;
+            if delta >= 0.0 || self.rng.gen::<f64>() < f64::exp(delta) {
+                self.accepted[i] += 1;
+                self.replicas.swap(i, j);
+                energies.swap(i, j);
+                for &(k, old) in &[(i, j), (j, i)] {
+                    let temperature = self.temperatures[k];
+                    let factor = f64::sqrt(temperature / self.temperatures[old]);
+                    let replica = &mut self.replicas[k];
+                    replica.propagator.set_temperature(temperature);
+                    for velocity in replica.system.particles_mut().velocity.iter_mut() {
+                        *velocity *= factor;
+                    }
+                }
+            }
+            i += 2;
+        }
+        self.offset = 1 - self.offset;
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let mut line = step.to_string();
+            for replica in &self.replicas {
+                line.push_str(&format!(" {}", replica.id));
+            }
+            if let Err(err) = writeln!(file, "{}", line) {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+impl Propagator for ReplicaExchange {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        TemperatureStrategy::Velocities
+    }
+
+    fn degrees_of_freedom(&self, system: &System) -> DegreesOfFreedom {
+        self.replicas[0].propagator.degrees_of_freedom(system)
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert!(self.replicas.len() > 1, "Replica exchange needs at least two replicas.");
+        self.steps = 0;
+        self.offset = 0;
+        self.attempted = vec![0; self.replicas.len() - 1];
+        self.accepted = vec![0; self.replicas.len() - 1];
+
+        // All the replicas start from the same configuration, with
+        // velocities rescaled to their temperature
+        let first = self.temperatures[0];
+        for (replica, &temperature) in self.replicas.iter_mut().zip(&self.temperatures) {
+            replica.system = system.clone();
+            let factor = f64::sqrt(temperature / first);
+            for velocity in replica.system.particles_mut().velocity.iter_mut() {
+                *velocity *= factor;
+            }
+            replica.propagator.setup(&replica.system);
+        }
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let temperatures = self.temperatures.iter()
+                                                .map(|temperature| temperature.to_string())
+                                                .collect::<Vec<_>>()
+                                                .join(" ");
+            let result = writeln!(file, "# Replica exchange").and_then(|_| {
+                writeln!(file, "# Temperatures(K): {}", temperatures)
+            }).and_then(|_| {
+                writeln!(file, "# Step Replicas at each temperature")
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        // The system at the lowest temperature is stored in the first
+        // replica while propagating and exchanging the replicas.
+        let step = system.step;
+        mem::swap(system, &mut self.replicas[0].system);
+        for replica in &mut self.replicas {
+            replica.system.step = step;
+        }
+
+        self.replicas.par_iter_mut().for_each(|replica| {
+            replica.propagator.propagate(&mut replica.system);
+        });
+
+        self.steps += 1;
+        if self.steps % self.every == 0 {
+            self.exchange(step);
+        }
+
+        mem::swap(system, &mut self.replicas[0].system);
+    }
+
+    fn finish(&mut self, system: &System) {
+        for replica in &mut self.replicas[1..] {
+            replica.propagator.finish(&replica.system);
+        }
+        self.replicas[0].propagator.finish(system);
+
+        let acceptance = self.acceptance().iter()
+                                          .map(|acceptance| acceptance.to_string())
+                                          .collect::<Vec<_>>()
+                                          .join(" ");
+        info!("Acceptance ratios of replica exchanges: {}", acceptance);
+        if let Some((ref mut file, ref path)) = self.output {
+            let result = writeln!(file, "# Acceptance: {}", acceptance).and_then(|_| file.flush());
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("steps", self.steps as f64);
+        checkpoint.set_value("offset", self.offset as f64);
+        let attempted = self.attempted.iter().map(|&n| n as f64).collect::<Vec<_>>();
+        let accepted = self.accepted.iter().map(|&n| n as f64).collect::<Vec<_>>();
+        checkpoint.set_values("attempted", &attempted);
+        checkpoint.set_values("accepted", &accepted);
+        checkpoint.save_rng("rng", &self.rng);
+
+        let ids = self.replicas.iter().map(|replica| replica.id as f64).collect::<Vec<_>>();
+        checkpoint.set_values("replicas", &ids);
+        // The system at the lowest temperature is saved by the simulation,
+        // the other replicas are saved with their propagator.
+        for (i, replica) in self.replicas.iter_mut().enumerate() {
+            let section = checkpoint.section_mut(&i.to_string());
+            if i != 0 {
+                section.save_system(&replica.system);
+            }
+            replica.propagator.save_checkpoint(section);
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let ids = checkpoint.values("replicas")?;
+        let attempted = checkpoint.values("attempted")?;
+        let accepted = checkpoint.values("accepted")?;
+        let n_replicas = self.replicas.len();
+        if ids.len() != n_replicas || attempted.len() != n_replicas - 1 ||
+           accepted.len() != n_replicas - 1 {
+            return Err(CheckpointError::from(format!(
+                "expected {} replicas in replica exchange checkpoint", n_replicas
+            )));
+        }
+
+        self.steps = checkpoint.value("steps")? as u64;
+        self.offset = checkpoint.value("offset")? as usize;
+        self.attempted = attempted.iter().map(|&n| n as u64).collect();
+        self.accepted = accepted.iter().map(|&n| n as u64).collect();
+        self.rng = checkpoint.rng("rng")?;
+
+        // All the replicas start from the system at the lowest temperature,
+        // which was restored before calling `setup`
+        let first = self.replicas[0].system.clone();
+
+        // Put back each replica at the temperature it was simulated at
+        let mut replicas = mem::replace(&mut self.replicas, Vec::new());
+        replicas.sort_by_key(|replica| replica.id);
+        let mut replicas = replicas.into_iter().map(Some).collect::<Vec<_>>();
+        for &id in ids {
+            let replica = replicas.get_mut(id as usize).and_then(Option::take).ok_or_else(|| {
+                CheckpointError::from(format!("invalid replica identifier {} in checkpoint", id))
+            })?;
+            self.replicas.push(replica);
+        }
+
+        for (i, replica) in self.replicas.iter_mut().enumerate() {
+            let section = checkpoint.section(&i.to_string())?;
+            replica.system = first.clone();
+            if i != 0 {
+                section.restore_system(&mut replica.system)?;
+            }
+            replica.propagator.set_temperature(self.temperatures[i]);
+            replica.propagator.setup(&replica.system);
+            replica.propagator.restore_checkpoint(section)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell, Vector3D};
+    use core::energy::{Harmonic, PairInteraction};
+    use md::RescaleThermostat;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+        system.particles_mut().velocity[0] = Vector3D::new(0.0, 0.001, 0.0);
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 1.0 }), 8.0);
+        system.add_pair_potential(("He", "He"), pair);
+        return system;
+    }
+
+    fn replica() -> MolecularDynamics {
+        let mut md = MolecularDynamics::new(1.0);
+        md.set_thermostat(Box::new(RescaleThermostat::new(300.0)));
+        return md;
+    }
+
+    #[test]
+    fn exchanges() {
+        let mut system = testing_system();
+        let mut remd = ReplicaExchange::new(1);
+        remd.add_replica(replica(), 300.0);
+        remd.add_replica(replica(), 400.0);
+        remd.add_replica(replica(), 500.0);
+        remd.setup(&system);
+
+        for _ in 0..100 {
+            remd.propagate(&mut system);
+            system.step += 1;
+        }
+        remd.finish(&system);
+
+        let mut replicas = remd.replicas();
+        replicas.sort();
+        assert_eq!(replicas, vec![0, 1, 2]);
+
+        assert_eq!(remd.attempted, vec![50, 50]);
+        for acceptance in remd.acceptance() {
+            assert!(acceptance >= 0.0 && acceptance <= 1.0);
+        }
+    }
+
+    #[test]
+    fn identical_energies() {
+        // Without interactions, the potential energy is always zero
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("He", [0.0, 0.0, 2.0].into())));
+        system.particles_mut().velocity[0] = Vector3D::new(0.0, 0.001, 0.0);
+
+        let mut remd = ReplicaExchange::new(1);
+        remd.add_replica(replica(), 300.0);
+        remd.add_replica(replica(), 400.0);
+        remd.setup(&system);
+
+        // Exchanges between replicas with the same energy are always
+        // accepted, and there is no odd pair with two replicas
+        remd.propagate(&mut system);
+        assert_eq!(remd.replicas(), vec![1, 0]);
+        remd.propagate(&mut system);
+        assert_eq!(remd.replicas(), vec![1, 0]);
+        remd.propagate(&mut system);
+        assert_eq!(remd.replicas(), vec![0, 1]);
+        assert_eq!(remd.acceptance(), vec![1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "All the replicas must use a thermostat in replica exchange.")]
+    fn no_thermostat() {
+        let mut remd = ReplicaExchange::new(1);
+        remd.add_replica(MolecularDynamics::new(1.0), 300.0);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The replicas must be added by increasing temperature in replica exchange."
+    )]
+    fn temperature_order() {
+        let mut remd = ReplicaExchange::new(1);
+        remd.add_replica(replica(), 300.0);
+        remd.add_replica(replica(), 200.0);
+    }
+}
@@ -0,0 +1,321 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Rigid bodies, moving as a whole with the motion of their center-of-mass
+//! and a rotation around it.
+use core::{Matrix3, Quaternion, System, Vector3D};
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+/// A rigid body made of the particles with indexes in `start..end`.
+///
+/// The state of the body is made of the position and the velocity of the
+/// center-of-mass, the orientation of the principal axes of inertia stored
+/// as a quaternion, and the angular momentum in the frame of the principal
+/// axes (the body frame).
+pub(crate) struct RigidBody {
+    /// Index of the first particle in the body
+    start: usize,
+    /// Index after the last particle in the body
+    end: usize,
+    /// Total mass of the body
+    mass: f64,
+    /// Principal moments of inertia of the body
+    moments: Vector3D,
+    /// Positions of the particles relative to the center-of-mass, in the
+    /// body frame
+    body_positions: Vec<Vector3D>,
+    /// Position of the center-of-mass
+    position: Vector3D,
+    /// Velocity of the center-of-mass
+    velocity: Vector3D,
+    /// Rotation from the body frame to the laboratory frame
+    orientation: Quaternion,
+    /// Angular momentum in the body frame
+    angular_momentum: Vector3D,
+}
+
+impl RigidBody {
+    /// Create a rigid body from the particles with indexes in `start..end`
+    /// in the `system`, using their current positions to define the body
+    /// geometry. This returns `None` if some of the particles are frozen,
+    /// or if the body has no mass.
+    pub fn new(system: &System, start: usize, end: usize) -> Option<RigidBody> {
+        let particles = system.particles();
+        if particles.frozen[start..end].iter().any(|&frozen| frozen) {
+            return None;
+        }
+        let masses = &particles.mass[start..end];
+        let mass = masses.iter().sum::<f64>();
+        if mass == 0.0 {
+            return None;
+        }
+
+        // Positions relative to the center-of-mass, using the nearest images
+        // from the first particle
+        let mut relative = (start..end).map(|i| -system.nearest_image(start, i))
+                                       .collect::<Vec<_>>();
+        let mut center = Vector3D::zero();
+        for (&m, &delta) in masses.iter().zip(&relative) {
+            center += m * delta / mass;
+        }
+        for position in &mut relative {
+            *position -= center;
+        }
+
+        let mut inertia = Matrix3::zero();
+        for (&m, delta) in masses.iter().zip(&relative) {
+            inertia += m * (delta.norm2() * Matrix3::one() - delta.tensorial(delta));
+        }
+        let (moments, mut axes) = inertia.symmetric_eigen();
+        if axes.determinant() < 0.0 {
+            for i in 0..3 {
+                axes[i][2] = -axes[i][2];
+            }
+        }
+
+        let inverse = axes.transposed();
+        let mut body = RigidBody {
+            start: start,
+            end: end,
+            mass: mass,
+            moments: moments,
+            body_positions: relative.iter().map(|&delta| inverse * delta).collect(),
+            position: particles.position[start] + center,
+            velocity: Vector3D::zero(),
+            orientation: Quaternion::from_rotation_matrix(&axes),
+            angular_momentum: Vector3D::zero(),
+        };
+        body.read_velocities(system);
+        return Some(body);
+    }
+
+    /// Get the positions of the particles relative to the center-of-mass,
+    /// in the laboratory frame
+    fn lab_positions(&self) -> Vec<Vector3D> {
+        let rotation = self.orientation.rotation_matrix();
+        self.body_positions.iter().map(|&position| rotation * position).collect()
+    }
+
+    /// Check if the moment of inertia along the principal axis `k` is zero,
+    /// as for the molecular axis of linear bodies.
+    fn no_inertia(&self, k: usize) -> bool {
+        self.moments[k] <= 1e-12 * self.moments[2]
+    }
+
+    /// Update the position of the center-of-mass from the positions of the
+    /// particles, and the velocity and angular momentum from their
+    /// velocities. This takes into account any change made to the particles
+    /// outside of the integrator, for example by thermostats or controls.
+    pub fn read_state(&mut self, system: &System) {
+        let rotation = self.orientation.rotation_matrix();
+        self.position = system.particles().position[self.start] - rotation * self.body_positions[0];
+        self.read_velocities(system);
+    }
+
+    /// Update the velocity and the angular momentum of this body from the
+    /// velocities of the particles in the `system`.
+    fn read_velocities(&mut self, system: &System) {
+        let particles = system.particles();
+        let lab_positions = self.lab_positions();
+        let mut momentum = Vector3D::zero();
+        let mut angular_momentum = Vector3D::zero();
+        for (i, delta) in (self.start..self.end).zip(lab_positions) {
+            let mass = particles.mass[i];
+            momentum += mass * particles.velocity[i];
+            angular_momentum += mass * (delta ^ particles.velocity[i]);
+        }
+
+        self.velocity = momentum / self.mass;
+        let inverse = self.orientation.conj();
+        self.angular_momentum = inverse.rotate(&angular_momentum);
+        for k in 0..3 {
+            if self.no_inertia(k) {
+                self.angular_momentum[k] = 0.0;
+            }
+        }
+    }
+
+    /// Update the velocity and the angular momentum of this body during a
+    /// time `dt`, with the force and the torque coming from the `forces`
+    /// acting on all the particles in the system.
+    pub fn kick(&mut self, forces: &[Vector3D], dt: f64) {
+        let mut force = Vector3D::zero();
+        let mut torque = Vector3D::zero();
+        for (i, delta) in (self.start..self.end).zip(self.lab_positions()) {
+            force += forces[i];
+            torque += delta ^ forces[i];
+        }
+
+        self.velocity += dt * force / self.mass;
+        self.angular_momentum += dt * self.orientation.conj().rotate(&torque);
+        for k in 0..3 {
+            if self.no_inertia(k) {
+                self.angular_momentum[k] = 0.0;
+            }
+        }
+    }
+
+    /// Move this body freely during a time `dt`. The free rotation is
+    /// split into successive rotations around the principal axes.
+    pub fn drift(&mut self, dt: f64) {
+        self.position += dt * self.velocity;
+
+        self.rotate(0, 0.5 * dt);
+        self.rotate(1, 0.5 * dt);
+        self.rotate(2, dt);
+        self.rotate(1, 0.5 * dt);
+        self.rotate(0, 0.5 * dt);
+        self.orientation = self.orientation.normalized();
+    }
+
+    /// Rotate this body around its principal axis `k` during a time `dt`,
+    /// with a constant angular velocity.
+    fn rotate(&mut self, k: usize, dt: f64) {
+        if self.no_inertia(k) {
+            return;
+        }
+
+        let mut axis = Vector3D::zero();
+        axis[k] = 1.0;
+        let angle = dt * self.angular_momentum[k] / self.moments[k];
+        self.orientation = self.orientation * Quaternion::rotation(&axis, angle);
+        // The angular momentum is constant in the laboratory frame, and thus
+        // rotates in the opposite direction in the body frame.
+        self.angular_momentum = Quaternion::rotation(&axis, -angle).rotate(&self.angular_momentum);
+    }
+
+    /// Save the geometry and the orientation of this body in the
+    /// `checkpoint`. The other parts of the state are read from the
+    /// particles at each step.
+    pub fn save_checkpoint(&self, checkpoint: &mut Checkpoint) {
+        let orientation = self.orientation.vector();
+        checkpoint.set_values("orientation", &[
+            self.orientation.scalar(), orientation[0], orientation[1], orientation[2]
+        ]);
+        checkpoint.set_vectors("moments", &[self.moments]);
+        checkpoint.set_vectors("body_positions", &self.body_positions);
+    }
+
+    /// Restore the geometry and the orientation of this body from the
+    /// `checkpoint`.
+    pub fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let orientation = checkpoint.values("orientation")?;
+        let moments = checkpoint.vectors("moments")?;
+        let body_positions = checkpoint.vectors("body_positions")?;
+        if orientation.len() != 4 || moments.len() != 1 ||
+           body_positions.len() != self.end - self.start {
+            return Err(CheckpointError::from(
+                "the rigid bodies in the checkpoint do not match the system".to_string()
+            ));
+        }
+        self.orientation = Quaternion::new(
+            orientation[0], orientation[1], orientation[2], orientation[3]
+        );
+        self.moments = moments[0];
+        self.body_positions = body_positions;
+        Ok(())
+    }
+
+    /// Set the positions and the velocities of the particles in the
+    /// `system` from the state of this body.
+    pub fn update_particles(&self, system: &mut System) {
+        let mut omega = Vector3D::zero();
+        for k in 0..3 {
+            if !self.no_inertia(k) {
+                omega[k] = self.angular_momentum[k] / self.moments[k];
+            }
+        }
+        let omega = self.orientation.rotate(&omega);
+
+        let lab_positions = self.lab_positions();
+        let particles = system.particles_mut();
+        for (i, delta) in (self.start..self.end).zip(lab_positions) {
+            particles.position[i] = self.position + delta;
+            particles.velocity[i] = self.velocity + (omega ^ delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        let carbon = Particle::with_position("C", Vector3D::new(0.0, 0.0, 0.0));
+        let mut molecule = Molecule::new(carbon);
+        let positions = [
+            Vector3D::new(1.2, 0.1, 0.0),
+            Vector3D::new(-0.3, 1.5, 0.2),
+            Vector3D::new(0.2, -0.4, 1.1),
+        ];
+        for &position in &positions {
+            molecule.add_particle_bonded_to(0, Particle::with_position("O", position));
+        }
+        system.add_molecule(molecule);
+
+        let velocities = [
+            Vector3D::new(0.01, 0.0, -0.02),
+            Vector3D::new(0.0, 0.03, 0.01),
+            Vector3D::new(-0.02, 0.01, 0.0),
+            Vector3D::new(0.01, -0.01, 0.02),
+        ];
+        for (velocity, &value) in system.particles_mut().velocity.iter_mut().zip(&velocities) {
+            *velocity = value;
+        }
+        return system;
+    }
+
+    fn kinetic_energy(body: &RigidBody) -> f64 {
+        let mut energy = 0.5 * body.mass * body.velocity.norm2();
+        for k in 0..3 {
+            let momentum = body.angular_momentum[k];
+            energy += 0.5 * momentum * momentum / body.moments[k];
+        }
+        return energy;
+    }
+
+    #[test]
+    fn setup() {
+        let system = testing_system();
+        let body = RigidBody::new(&system, 0, 4).unwrap();
+        let molecule = system.molecule(0);
+        assert_relative_eq!(body.position, molecule.center_of_mass(), epsilon = 1e-12);
+
+        let (moments, _) = molecule.principal_axes();
+        assert_relative_eq!(body.moments, moments, epsilon = 1e-12);
+
+        // The particles are not moved when updating them from the body
+        let mut moved = system.clone();
+        body.update_particles(&mut moved);
+        for i in 0..4 {
+            let position = moved.particles().position[i];
+            assert_relative_eq!(position, system.particles().position[i], epsilon = 1e-12);
+        }
+        // And the velocities conserve the momentum and angular momentum
+        let momentum = system.molecule(0).angular_momentum();
+        assert_relative_eq!(moved.molecule(0).angular_momentum(), momentum, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn free_rotation() {
+        let system = testing_system();
+        let mut body = RigidBody::new(&system, 0, 4).unwrap();
+        let energy = kinetic_energy(&body);
+        let momentum = body.orientation.rotate(&body.angular_momentum);
+
+        let forces = vec![Vector3D::zero(); 4];
+        for _ in 0..1000 {
+            body.kick(&forces, 0.25);
+            body.drift(0.5);
+            body.kick(&forces, 0.25);
+
+            // The angular momentum is exactly conserved
+            let current = body.orientation.rotate(&body.angular_momentum);
+            assert_relative_eq!(current, momentum, epsilon = 1e-12);
+        }
+        assert_relative_eq!(kinetic_energy(&body), energy, max_relative = 1e-3);
+    }
+}
@@ -0,0 +1,222 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! SLLOD equations of motion for non-equilibrium shear flow.
+use core::{System, Vector3D};
+
+use super::Integrator;
+use super::integrators::{check_no_constraints, inverse_mass};
+
+/// Integrator for the SLLOD equations of motion [1], simulating a planar
+/// Couette flow with Lees-Edwards boundary conditions.
+///
+/// The system is sheared along the x axis, with a streaming velocity
+/// $\dot\gamma y$ growing linearly along the y axis. The equations of motion
+/// for the positions and the peculiar velocities of the particles are
+///
+/// $$ \dot{\mathbf r}_i = \mathbf v_i + \dot\gamma y_i \mathbf e_x $$
+/// $$ m_i \dot{\mathbf v}_i = \mathbf f_i - m_i \dot\gamma v_{y,i} \mathbf e_x $$
+///
+/// The velocities stored in the system are the peculiar velocities, i.e.
+/// relative to the streaming velocity. The temperature, the kinetic
+/// contribution to the pressure and the thermostats are thus working as
+/// usual, and a thermostat is needed to remove the viscous heating.
+///
+/// The periodic images follow the flow using Lees-Edwards boundary
+/// conditions: the cell is made triclinic, and the second cell vector is
+/// tilted along the x axis by $\dot\gamma L_y t$ (see
+/// `UnitCell::set_shear_offset`). The shear viscosity can then be computed
+/// from the average of the xy component of the pressure tensor:
+///
+/// $$ \eta = - \frac{\langle P_{xy} \rangle}{\dot\gamma} $$
+///
+/// The holonomic constraints are not enforced by this integrator.
+///
+/// [1] Evans, D. J. and Morriss, G. P. *Statistical Mechanics of
+/// Nonequilibrium Liquids*, Cambridge University Press (2008).
+pub struct SLLOD {
+    /// Timestep for the integrator
+    timestep: f64,
+    /// Shear rate of the flow
+    shear_rate: f64,
+    /// Storing the accelerations
+    accelerations: Vec<Vector3D>,
+}
+
+impl SLLOD {
+    /// Create a new SLLOD integrator with a timestep of `timestep`, shearing
+    /// the system with the given `shear_rate`.
+    pub fn new(timestep: f64, shear_rate: f64) -> SLLOD {
+        SLLOD {
+            timestep: timestep,
+            shear_rate: shear_rate,
+            accelerations: Vec::new(),
+        }
+    }
+
+    /// Get the shear rate of the flow
+    pub fn shear_rate(&self) -> f64 {
+        self.shear_rate
+    }
+
+    /// Get the instantaneous shear viscosity of the `system`, computed from
+    /// the xy component of the pressure tensor. This value must be averaged
+    /// over the simulation to get the actual viscosity.
+    pub fn viscosity(&self, system: &System) -> f64 {
+        let stress = system.stress();
+        let pxy = 0.5 * (stress[0][1] + stress[1][0]);
+        return -pxy / self.shear_rate;
+    }
+
+    /// Update the peculiar velocities for half a timestep using the forces
+    fn half_kick(&self, system: &mut System) {
+        let dt = self.timestep;
+        for (velocity, acceleration) in soa_zip!(
+            system.particles_mut(), [mut velocity], &self.accelerations
+        ) {
+            *velocity += 0.5 * dt * acceleration;
+        }
+    }
+
+    /// Apply the SLLOD coupling between the x and y components of the
+    /// peculiar velocities for half a timestep
+    fn half_shear(&self, system: &mut System) {
+        let factor = 0.5 * self.timestep * self.shear_rate;
+        for (&mass, &frozen, velocity) in soa_zip!(
+            system.particles_mut(), [mass, frozen, mut velocity]
+        ) {
+            if inverse_mass(mass, frozen) != 0.0 {
+                velocity[0] -= factor * velocity[1];
+            }
+        }
+    }
+}
+
+impl Integrator for SLLOD {
+    fn setup(&mut self, system: &System) {
+        assert!(!system.cell.is_infinite(), "SLLOD can not be used with an infinite cell.");
+        assert!(
+            system.cell.periodicity()[1],
+            "SLLOD needs periodic boundary conditions along the y axis."
+        );
+        check_no_constraints(system, "SLLOD integrator");
+
+        self.accelerations = vec![Vector3D::zero(); system.size()];
+        let forces = system.forces();
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+    }
+
+    fn integrate(&mut self, system: &mut System) {
+        let dt = self.timestep;
+        let rate = self.shear_rate;
+
+        // Update peculiar velocities at t + ∆t/2
+        self.half_kick(system);
+        self.half_shear(system);
+
+        // Update positions at t + ∆t, including the streaming velocity
+        // evaluated at the middle of the step
+        for (&mass, &frozen, position, velocity) in soa_zip!(
+            system.particles_mut(), [mass, frozen, mut position, velocity]
+        ) {
+            if inverse_mass(mass, frozen) != 0.0 {
+                let streaming = rate * (position[1] + 0.5 * dt * velocity[1]);
+                *position += dt * velocity;
+                position[0] += dt * streaming;
+            }
+        }
+
+        // Move the periodic images with the flow
+        let offset = system.cell.shear_offset();
+        let height = system.cell.matrix()[1][1];
+        system.cell.set_shear_offset(offset + rate * height * dt);
+        system.update_virtual_sites();
+
+        let forces = system.forces();
+        // Update accelerations at t + ∆t
+        for (&mass, &frozen, acceleration, force) in soa_zip!(
+            system.particles(), [mass, frozen], &mut self.accelerations, forces
+        ) {
+            *acceleration = inverse_mass(mass, frozen) * force;
+        }
+
+        // Update peculiar velocities at t + ∆t
+        self.half_shear(system);
+        self.half_kick(system);
+    }
+
+    fn set_timestep(&mut self, timestep: f64) {
+        self.timestep = timestep;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Constraint, Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 2.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [5.0, 6.0, 5.0].into())));
+        system.particles_mut().velocity[1] = Vector3D::new(0.0, 0.1, 0.0);
+        return system;
+    }
+
+    #[test]
+    fn streaming() {
+        let mut system = testing_system();
+        let mut integrator = SLLOD::new(0.5, 0.01);
+        integrator.setup(&system);
+        for _ in 0..10 {
+            integrator.integrate(&mut system);
+        }
+
+        // Without interactions, particles move in straight lines in the
+        // laboratory frame
+        let particles = system.particles();
+        assert_relative_eq!(
+            particles.position[0],
+            Vector3D::new(0.01 * 2.0 * 5.0, 2.0, 0.0),
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            particles.position[1],
+            Vector3D::new(5.0 + 0.01 * 6.0 * 5.0, 6.5, 5.0),
+            epsilon = 1e-12
+        );
+
+        // The peculiar velocity compensates for the change in streaming
+        // velocity
+        assert_relative_eq!(
+            particles.velocity[1],
+            Vector3D::new(-0.01 * 0.1 * 5.0, 0.1, 0.0),
+            epsilon = 1e-12
+        );
+
+        // The periodic images move with the flow
+        assert_relative_eq!(system.cell.shear_offset(), 0.01 * 10.0 * 5.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "SLLOD can not be used with an infinite cell.")]
+    fn infinite_cell() {
+        let mut system = testing_system();
+        system.cell = UnitCell::infinite();
+        let mut integrator = SLLOD::new(0.5, 0.01);
+        integrator.setup(&system);
+    }
+
+    #[test]
+    #[should_panic(expected = "The SLLOD integrator can not enforce the holonomic constraints")]
+    fn constraints() {
+        let mut system = testing_system();
+        system.add_constraint(Constraint::Rigid { start: 0, end: 1 });
+        let mut integrator = SLLOD::new(0.5, 0.01);
+        integrator.setup(&system);
+    }
+}
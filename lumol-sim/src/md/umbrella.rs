@@ -0,0 +1,234 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Umbrella sampling, restraining a collective variable with an harmonic
+//! bias.
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use core::{System, Vector3D};
+use core::units;
+
+use checkpoint::{Checkpoint, CheckpointError};
+
+use super::{CollectiveVariable, Control};
+
+/// Harmonic bias on a collective variable, for umbrella sampling.
+///
+/// The collective variable $s$ is restrained around the `center` $s_0$ of
+/// the current window by the bias potential
+///
+/// $$ V(s) = \frac{k}{2} (s - s_0)^2 $$
+///
+/// Running multiple simulations with windows covering the range of interest
+/// of the collective variable, the unbiased free energy profile can be
+/// recovered with the weighted histogram analysis method (WHAM).
+///
+/// The value of the collective variable can be written to a time series
+/// file with [`set_output`](#method.set_output), using two columns with the
+/// time in ps and the value of the collective variable. This file can be
+/// used directly by WHAM implementations like the one from Alan Grossfield,
+/// and the corresponding line in the WHAM metadata file can be written with
+/// [`set_metadata`](#method.set_metadata).
+///
+/// As for all the controls, the bias force is applied between two steps of
+/// the integrator, by changing the velocities of the particles.
+pub struct UmbrellaSampling {
+    /// The restrained collective variable
+    variable: Box<CollectiveVariable>,
+    /// Center of the window
+    center: f64,
+    /// Force constant of the bias
+    k: f64,
+    /// Timestep of the integrator
+    timestep: f64,
+    /// Time since the beginning of the simulation
+    time: f64,
+    /// Value of the collective variable at the last step
+    value: f64,
+    /// Time series file and the corresponding path, if any
+    output: Option<(BufWriter<File>, PathBuf)>,
+    /// Path to the WHAM metadata file, if any
+    metadata: Option<PathBuf>,
+}
+
+impl UmbrellaSampling {
+    /// Create a new umbrella sampling window, restraining the collective
+    /// `variable` around `center` with a force constant `k`. The `timestep`
+    /// must be the one of the integrator.
+    pub fn new(
+        variable: Box<CollectiveVariable>,
+        center: f64,
+        k: f64,
+        timestep: f64,
+    ) -> UmbrellaSampling {
+        assert!(k >= 0.0, "The force constant must be positive in umbrella sampling.");
+        UmbrellaSampling {
+            variable: variable,
+            center: center,
+            k: k,
+            timestep: timestep,
+            time: 0.0,
+            value: 0.0,
+            output: None,
+            metadata: None,
+        }
+    }
+
+    /// Write the time series of the collective variable to the file at
+    /// `path`. The file is replaced if it already exists.
+    pub fn set_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(path.as_ref())?);
+        self.output = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Add a line describing this window to the WHAM metadata file at
+    /// `path` when the simulation starts. The file is created if it does
+    /// not exist, and new lines are added at the end of the file, allowing
+    /// to share the same metadata file between all the windows. The time
+    /// series output must be set with [`set_output`](#method.set_output)
+    /// for this to be useful.
+    pub fn set_metadata<P: AsRef<Path>>(&mut self, path: P) {
+        self.metadata = Some(path.as_ref().to_owned());
+    }
+
+    /// Get the value of the collective variable at the last step
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Get the value of the bias potential at the last step
+    pub fn energy(&self) -> f64 {
+        let delta = self.value - self.center;
+        return 0.5 * self.k * delta * delta;
+    }
+
+    /// Append the line describing this window to the metadata file
+    fn write_metadata(&self, path: &Path) -> Result<(), io::Error> {
+        let timeseries = match self.output {
+            Some((_, ref timeseries)) => timeseries.display().to_string(),
+            None => {
+                warn!("umbrella sampling metadata written without time series output");
+                String::from("-")
+            }
+        };
+        let k = units::to(self.k, "kJ/mol").expect("bad unit");
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{} {} {}", timeseries, self.center, k)
+    }
+}
+
+impl Control for UmbrellaSampling {
+    fn setup(&mut self, system: &System) {
+        self.time = 0.0;
+        self.value = self.variable.value(system);
+
+        if let Some(ref path) = self.metadata {
+            if let Err(err) = self.write_metadata(path) {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let result = writeln!(file, "# Umbrella sampling time series").and_then(|_| {
+                writeln!(file, "# Time(ps) Value")
+            });
+            if let Err(err) = result {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn control(&mut self, system: &mut System) {
+        self.time += self.timestep;
+        self.value = self.variable.value(system);
+
+        let derivative = self.k * (self.value - self.center);
+        if derivative != 0.0 {
+            let mut forces = vec![Vector3D::zero(); system.size()];
+            for (i, gradient) in self.variable.gradient(system) {
+                forces[i] -= derivative * gradient;
+            }
+
+            // Frozen particles and virtual sites are not moved by the bias
+            let dt = self.timestep;
+            for (&mass, &frozen, velocity, force) in soa_zip!(
+                system.particles_mut(), [mass, frozen, mut velocity], &forces
+            ) {
+                if !frozen && mass != 0.0 {
+                    *velocity += dt * force / mass;
+                }
+            }
+        }
+
+        if let Some((ref mut file, ref path)) = self.output {
+            let time = units::to(self.time, "ps").expect("bad unit");
+            if let Err(err) = writeln!(file, "{} {}", time, self.value) {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &mut Checkpoint) {
+        checkpoint.set_value("time", self.time);
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.time = checkpoint.value("time")?;
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Some((ref mut file, ref path)) = self.output {
+            if let Err(err) = file.flush() {
+                error!("could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{Molecule, Particle, UnitCell};
+    use md::DistanceVariable;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(10.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 3.0, 0.0].into())));
+        return system;
+    }
+
+    #[test]
+    fn harmonic_bias() {
+        let mut system = testing_system();
+        let variable = Box::new(DistanceVariable::new(0, 1));
+        let mut umbrella = UmbrellaSampling::new(variable, 2.5, 4.0, 0.5);
+        umbrella.setup(&system);
+        assert_ulps_eq!(umbrella.value(), 3.0);
+        assert_ulps_eq!(umbrella.energy(), 0.5);
+
+        umbrella.control(&mut system);
+        // The particles are pulled back to the center of the window
+        let mass = system.particles().mass[0];
+        let expected = Vector3D::new(0.0, 4.0 * 0.5 * 0.5 / mass, 0.0);
+        assert_relative_eq!(system.particles().velocity[0], expected, epsilon = 1e-12);
+        assert_relative_eq!(system.particles().velocity[1], -expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn at_center() {
+        let mut system = testing_system();
+        let variable = Box::new(DistanceVariable::new(0, 1));
+        let mut umbrella = UmbrellaSampling::new(variable, 3.0, 4.0, 0.5);
+        umbrella.setup(&system);
+        umbrella.control(&mut system);
+        assert_eq!(umbrella.energy(), 0.0);
+        assert_eq!(system.particles().velocity[0], Vector3D::zero());
+        assert_eq!(system.particles().velocity[1], Vector3D::zero());
+    }
+}
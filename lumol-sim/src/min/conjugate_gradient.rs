@@ -0,0 +1,303 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use core::{units, System, DegreesOfFreedom, Vector3D};
+
+use super::{Minimizer, Tolerance};
+
+use std::f64;
+
+/// Maximal number of step reductions in the line search before restarting
+/// along the steepest descent direction
+const MAX_LINE_SEARCH: usize = 50;
+
+/// Sufficient decrease parameter for the Armijo condition in the line search
+const ARMIJO: f64 = 1e-4;
+
+/// Polak-Ribière conjugate gradient minimization algorithm.
+///
+/// This method propagates the system along search directions built from the
+/// current forces and the previous search direction, which makes it converge
+/// much faster than steepest descent in narrow energy valleys. The step along
+/// each direction is found with a backtracking line search, improved by a
+/// quadratic interpolation of the energy.
+///
+/// The algorithm restarts along the steepest descent direction when the
+/// Polak-Ribière coefficient is negative, when the search direction is not a
+/// descent direction, when the line search fails, and optionally every few
+/// iterations.
+pub struct ConjugateGradient {
+    /// Initial step size for the line search
+    alpha: f64,
+    /// Current search direction
+    direction: Vec<Vector3D>,
+    /// Forces at the previous iteration
+    previous: Vec<Vector3D>,
+    /// Number of iterations since the last restart
+    iterations: u64,
+    /// Number of iterations between forced restarts
+    restart: Option<u64>,
+}
+
+impl ConjugateGradient {
+    /// Create a new `ConjugateGradient` minimizer
+    pub fn new() -> ConjugateGradient {
+        ConjugateGradient {
+            alpha: units::from(0.1, "fs^2/u").expect("bad unit"),
+            direction: Vec::new(),
+            previous: Vec::new(),
+            iterations: 0,
+            restart: None,
+        }
+    }
+
+    /// Restart the minimization along the steepest descent direction every
+    /// `every` iterations.
+    ///
+    /// # Panics
+    ///
+    /// If `every` is zero.
+    pub fn set_restart(&mut self, every: u64) {
+        assert!(every > 0, "The restart frequency must be positive in conjugate gradient.");
+        self.restart = Some(every);
+    }
+
+    /// Update the search direction using the current `forces`, and return the
+    /// slope of the energy along this direction.
+    fn update_direction(&mut self, forces: &[Vector3D]) -> f64 {
+        let forced_restart = self.restart.map_or(false, |every| self.iterations >= every);
+        let beta = if self.direction.len() != forces.len() || forced_restart {
+            0.0
+        } else {
+            let mut numerator = 0.0;
+            let mut denominator = 0.0;
+            for (force, previous) in forces.iter().zip(&self.previous) {
+                numerator += force * (force - previous);
+                denominator += previous * previous;
+            }
+            if denominator == 0.0 {
+                0.0
+            } else {
+                // Restart when the coefficient is negative (PR+ variant)
+                f64::max(numerator / denominator, 0.0)
+            }
+        };
+
+        if beta == 0.0 {
+            self.direction = forces.to_vec();
+            self.iterations = 0;
+        } else {
+            for (direction, force) in self.direction.iter_mut().zip(forces) {
+                *direction = force + beta * (*direction);
+            }
+        }
+
+        let mut slope = -dot(forces, &self.direction);
+        if slope >= 0.0 {
+            // This is not a descent direction, restart along the forces
+            self.direction = forces.to_vec();
+            self.iterations = 0;
+            slope = -dot(forces, forces);
+        }
+
+        self.iterations += 1;
+        self.previous = forces.to_vec();
+        return slope;
+    }
+
+    /// Move the particles in `system` by `alpha` times the search direction
+    /// from the `initial` positions, and return the new potential energy.
+    fn energy_at(&self, system: &mut System, initial: &[Vector3D], alpha: f64) -> f64 {
+        for (position, initial, direction) in soa_zip!(
+            system.particles_mut(), [mut position], initial, &self.direction
+        ) {
+            *position = initial + alpha * direction;
+        }
+        system.update_virtual_sites();
+        return system.potential_energy();
+    }
+
+    /// Find a step along the search direction decreasing the energy of the
+    /// `system` starting at `initial_energy`, with a derivative of the energy
+    /// along the direction equal to `slope`. Returns the new energy, or `None`
+    /// if no acceptable step was found.
+    fn line_search(
+        &mut self,
+        system: &mut System,
+        initial: &[Vector3D],
+        initial_energy: f64,
+        slope: f64,
+    ) -> Option<f64> {
+        let mut alpha = self.alpha;
+        for _ in 0..MAX_LINE_SEARCH {
+            let mut step = alpha;
+            let mut energy = self.energy_at(system, initial, alpha);
+
+            // Use the minimum of a quadratic interpolation of the energy
+            // along the direction if it gives a lower energy
+            let curvature = (energy - initial_energy - slope * alpha) / (alpha * alpha);
+            if curvature > 0.0 {
+                let optimal = f64::min(-slope / (2.0 * curvature), 10.0 * alpha);
+                let optimal_energy = self.energy_at(system, initial, optimal);
+                if optimal_energy < energy {
+                    step = optimal;
+                    energy = optimal_energy;
+                } else {
+                    let _ = self.energy_at(system, initial, alpha);
+                }
+            }
+
+            if energy <= initial_energy + ARMIJO * step * slope {
+                self.alpha = step;
+                return Some(energy);
+            }
+
+            alpha = 0.5 * f64::min(alpha, step);
+        }
+        return None;
+    }
+}
+
+impl Minimizer for ConjugateGradient {
+    fn setup(&mut self, _: &System) {
+        self.direction.clear();
+        self.previous.clear();
+        self.iterations = 0;
+    }
+
+    fn degrees_of_freedom(&self, _: &System) -> DegreesOfFreedom {
+        DegreesOfFreedom::Particles
+    }
+
+    fn minimize(&mut self, system: &mut System) -> Tolerance {
+        let mut forces = system.forces();
+        // Frozen particles are not moved, and their forces are not used to
+        // check for convergence
+        for (force, &frozen) in forces.iter_mut().zip(system.particles().frozen) {
+            if frozen {
+                *force = Vector3D::zero();
+            }
+        }
+        let force2 = forces.iter().map(|&f| f.norm2()).fold(f64::NAN, f64::max);
+
+        let initial_energy = system.potential_energy();
+        let slope = self.update_direction(&forces);
+        if slope == 0.0 {
+            // All the forces are zero, we are already at a minimum
+            return Tolerance {
+                energy: initial_energy,
+                force2: force2,
+            };
+        }
+
+        let initial = system.particles().position.to_vec();
+        let energy = match self.line_search(system, &initial, initial_energy, slope) {
+            Some(energy) => energy,
+            None => {
+                warn!("Line search failed in conjugate gradient, restarting the minimization");
+                for (position, initial) in soa_zip!(
+                    system.particles_mut(), [mut position], &initial
+                ) {
+                    *position = *initial;
+                }
+                system.update_virtual_sites();
+                self.direction.clear();
+                initial_energy
+            }
+        };
+
+        return Tolerance {
+            energy: energy,
+            force2: force2,
+        };
+    }
+}
+
+/// Compute the scalar product of two sets of vectors
+fn dot(a: &[Vector3D], b: &[Vector3D]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{System, UnitCell, Molecule, Particle};
+    use min::{Minimization, SteepestDescent};
+    use propagator::Propagator;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Cl", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Cl", [0.0, 0.0, 2.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Cl", [0.0, 1.5, 1.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 0.1 }), 10.0);
+        system.add_pair_potential(("Cl", "Cl"), pair);
+        return system;
+    }
+
+    fn tolerance() -> Tolerance {
+        Tolerance {
+            energy: 1e-10,
+            force2: 1e-10,
+        }
+    }
+
+    /// Run the `minimization` on `system`, and return the number of steps
+    /// needed to converge.
+    fn converge(minimization: &mut Minimization, system: &mut System) -> usize {
+        minimization.setup(system);
+        for step in 0..1000 {
+            minimization.propagate(system);
+            if minimization.converged() {
+                return step;
+            }
+        }
+        panic!("The minimization did not converge.");
+    }
+
+    #[test]
+    fn minimization() {
+        let mut system = testing_system();
+        let mut minimization = Minimization::new(Box::new(ConjugateGradient::new()), tolerance());
+        let cg_steps = converge(&mut minimization, &mut system);
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon = 1e-3);
+        assert_relative_eq!(system.distance(0, 2), 2.3, epsilon = 1e-3);
+        assert_relative_eq!(system.distance(1, 2), 2.3, epsilon = 1e-3);
+
+        let mut system = testing_system();
+        let mut minimization = Minimization::new(Box::new(SteepestDescent::new()), tolerance());
+        let sd_steps = converge(&mut minimization, &mut system);
+        assert!(cg_steps < sd_steps);
+    }
+
+    #[test]
+    fn restart() {
+        let mut system = testing_system();
+        let mut minimizer = ConjugateGradient::new();
+        minimizer.set_restart(2);
+        let mut minimization = Minimization::new(Box::new(minimizer), tolerance());
+        let _ = converge(&mut minimization, &mut system);
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon = 1e-3);
+        assert_relative_eq!(system.distance(1, 2), 2.3, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn frozen() {
+        let mut system = testing_system();
+        system.particles_mut().frozen[0] = true;
+
+        let mut minimization = Minimization::new(Box::new(ConjugateGradient::new()), tolerance());
+        let _ = converge(&mut minimization, &mut system);
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon = 1e-3);
+        assert_eq!(system.particles().position[0], Vector3D::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The restart frequency must be positive in conjugate gradient.")]
+    fn zero_restart() {
+        let mut minimizer = ConjugateGradient::new();
+        minimizer.set_restart(0);
+    }
+}
@@ -35,16 +35,36 @@ pub trait Minimizer {
     fn degrees_of_freedom(&self, system: &System) -> DegreesOfFreedom;
 }
 
+/// Convergence criteria of a minimization
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Criterion {
+    /// The energy difference between two steps is lower than the energy
+    /// tolerance
+    Energy,
+    /// The maximal squared norm of the force acting on an atom is lower than
+    /// the force tolerance
+    Force,
+    /// The maximal displacement of an atom during one step is lower than the
+    /// maximal step size
+    Step,
+}
+
 /// Minimization propagator for simulations.
 ///
 /// The minimization stops when the energy difference between the previous and
-/// the current step is lower than the energy criterion, or when the maximal
-/// squared norm of the atomic force is lower than the force criterion.
+/// the current step is lower than the energy criterion, when the maximal
+/// squared norm of the atomic force is lower than the force criterion, or
+/// when the maximal displacement of an atom during one step is lower than the
+/// step size criterion.
 pub struct Minimization {
     minimizer: Box<Minimizer>,
-    is_converged: bool,
+    criterion: Option<Criterion>,
     last_energy: f64,
     tolerance: Tolerance,
+    /// Maximal step size criterion, disabled if zero
+    max_step: f64,
+    /// Number of minimization steps since the last setup
+    steps: u64,
 }
 
 impl Minimization {
@@ -53,15 +73,35 @@ impl Minimization {
     pub fn new(minimizer: Box<Minimizer>, tolerance: Tolerance) -> Minimization {
         Minimization {
             minimizer: minimizer,
-            is_converged: false,
+            criterion: None,
             last_energy: 0.0,
             tolerance: tolerance,
+            max_step: 0.0,
+            steps: 0,
         }
     }
 
+    /// Also stop the minimization when the maximal displacement of an atom
+    /// during one step is lower than `max_step`. This criterion is disabled
+    /// by default.
+    ///
+    /// # Panics
+    ///
+    /// If `max_step` is negative.
+    pub fn set_max_step(&mut self, max_step: f64) {
+        assert!(max_step >= 0.0, "The maximal step size must be positive in minimization.");
+        self.max_step = max_step;
+    }
+
     /// Check if the minimization has converged.
     pub fn converged(&self) -> bool {
-        self.is_converged
+        self.criterion.is_some()
+    }
+
+    /// Get the criterion which stopped the minimization, or `None` if the
+    /// minimization has not converged yet.
+    pub fn criterion(&self) -> Option<Criterion> {
+        self.criterion
     }
 }
 
@@ -75,28 +115,108 @@ impl Propagator for Minimization {
     }
 
     fn setup(&mut self, system: &System) {
-        self.is_converged = false;
+        self.criterion = None;
+        self.steps = 0;
         self.last_energy = system.potential_energy();
         self.minimizer.setup(system);
     }
 
     fn propagate(&mut self, system: &mut System) {
-        if self.is_converged {
+        if self.criterion.is_some() {
             return;
         }
 
+        let previous = if self.max_step > 0.0 {
+            system.particles().position.to_vec()
+        } else {
+            Vec::new()
+        };
+
         let result = self.minimizer.minimize(system);
+        self.steps += 1;
 
         if result.force2 < self.tolerance.force2 {
-            self.is_converged = true;
+            self.criterion = Some(Criterion::Force);
             info!("Minimization converged on force tolerance");
-        }
-
-        if (self.last_energy - result.energy).abs() < self.tolerance.energy {
-            self.is_converged = true;
+        } else if (self.last_energy - result.energy).abs() < self.tolerance.energy {
+            self.criterion = Some(Criterion::Energy);
             info!("Minimization converged on energy tolerance");
+        } else if self.max_step > 0.0 {
+            let displacement = system.particles().position.iter()
+                .zip(&previous)
+                .map(|(position, previous)| (position - previous).norm())
+                .fold(0.0, f64::max);
+            if displacement < self.max_step {
+                self.criterion = Some(Criterion::Step);
+                info!("Minimization converged on step size tolerance");
+            }
         }
 
         self.last_energy = result.energy;
     }
+
+    fn finish(&mut self, _: &System) {
+        match self.criterion {
+            Some(criterion) => {
+                let criterion = match criterion {
+                    Criterion::Energy => "energy",
+                    Criterion::Force => "force",
+                    Criterion::Step => "step size",
+                };
+                info!("Minimization stopped on {} tolerance after {} steps", criterion, self.steps);
+            }
+            None => warn!("Minimization did not converge after {} steps", self.steps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, Particle, UnitCell};
+    use min::SteepestDescent;
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::cubic(20.0));
+        system.add_molecule(Molecule::new(Particle::with_position("Cl", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Cl", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.3, k: 0.1 }), 10.0);
+        system.add_pair_potential(("Cl", "Cl"), pair);
+        return system;
+    }
+
+    fn converge(minimization: &mut Minimization, system: &mut System) {
+        minimization.setup(system);
+        for _ in 0..1000 {
+            minimization.propagate(system);
+        }
+        minimization.finish(system);
+    }
+
+    #[test]
+    fn criteria() {
+        let tolerance = Tolerance {
+            energy: 1e-10,
+            force2: 1e-10,
+        };
+        let mut minimization = Minimization::new(Box::new(SteepestDescent::new()), tolerance);
+        assert_eq!(minimization.criterion(), None);
+        converge(&mut minimization, &mut testing_system());
+        assert!(minimization.converged());
+        assert!(minimization.criterion() != Some(Criterion::Step));
+
+        let tolerance = Tolerance {
+            energy: 0.0,
+            force2: 0.0,
+        };
+        let mut minimization = Minimization::new(Box::new(SteepestDescent::new()), tolerance);
+        converge(&mut minimization, &mut testing_system());
+        assert!(!minimization.converged());
+
+        minimization.set_max_step(1e-3);
+        converge(&mut minimization, &mut testing_system());
+        assert_eq!(minimization.criterion(), Some(Criterion::Step));
+    }
 }
@@ -5,8 +5,12 @@
 
 mod minimization;
 pub use self::minimization::Minimization;
+pub use self::minimization::Criterion;
 pub use self::minimization::Minimizer;
 pub use self::minimization::Tolerance;
 
 mod steepest_descent;
 pub use self::steepest_descent::SteepestDescent;
+
+mod conjugate_gradient;
+pub use self::conjugate_gradient::ConjugateGradient;
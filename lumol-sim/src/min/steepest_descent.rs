@@ -1,7 +1,7 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
 
-use core::{units, System, DegreesOfFreedom};
+use core::{units, System, DegreesOfFreedom, Vector3D};
 
 use super::{Minimizer, Tolerance};
 
@@ -35,7 +35,15 @@ impl Minimizer for SteepestDescent {
         let prevpos = system.particles().position.to_vec();
 
         let mut gamma_changed = false;
-        let forces = system.forces();
+        let mut forces = system.forces();
+        // Frozen particles are not moved, and their forces are not used to
+        // check for convergence
+        for (force, &frozen) in forces.iter_mut().zip(system.particles().frozen) {
+            if frozen {
+                *force = Vector3D::zero();
+            }
+        }
+
         let initial_energy = system.potential_energy();
         let mut energy;
         // Update coordinates, reducing gamma until we find a configuration of
@@ -101,4 +109,24 @@ mod tests {
         assert!(minization.converged());
         assert_relative_eq!(system.distance(0, 1), 2.3, epsilon = 1e-3);
     }
+
+    #[test]
+    fn frozen() {
+        let mut system = testing_system();
+        system.particles_mut().frozen[0] = true;
+
+        let mut minization = Minimization::new(
+            Box::new(SteepestDescent::new()),
+            Tolerance {
+                energy: 1e-10,
+                force2: 1e-10,
+            },
+        );
+        for _ in 0..100 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon = 1e-3);
+        assert_eq!(system.particles().position[0], Vector3D::new(0.0, 0.0, 0.0));
+    }
 }
@@ -0,0 +1,371 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Normal modes analysis using a finite differences Hessian
+use std::f64::consts::PI;
+
+use core::{units, Array2, System, Vector3D};
+
+/// Speed of light in cm/fs, used to convert frequencies to wavenumbers
+const SPEED_OF_LIGHT: f64 = 2.99792458e-5;
+
+/// Compute the mass-weighted Hessian of the potential energy of the `system`,
+/// using central finite differences of the forces with the given
+/// `displacement` of each coordinate.
+///
+/// The returned matrix has `3 N` rows and columns, where `N` is the number of
+/// particles in the system. The element at `(3 i + α, 3 j + β)` is the second
+/// derivative of the energy with respect to the `α` coordinate of particle `i`
+/// and the `β` coordinate of particle `j`, divided by the square root of the
+/// masses of the particles. The matrix is symmetrized to reduce the finite
+/// differences errors.
+///
+/// # Panics
+///
+/// If the displacement is not strictly positive, or if one of the particles
+/// has no mass, as for virtual sites.
+pub fn hessian(system: &System, displacement: f64) -> Array2<f64> {
+    assert!(displacement > 0.0, "The displacement must be positive in Hessian computation.");
+    assert!(
+        system.particles().mass.iter().all(|&mass| mass > 0.0),
+        "All the particles must have a positive mass to compute the Hessian."
+    );
+
+    let mut system = system.clone();
+    let size = 3 * system.size();
+    let mut hessian = Array2::zeros((size, size));
+    for i in 0..system.size() {
+        for alpha in 0..3 {
+            let initial = system.particles().position[i];
+
+            system.particles_mut().position[i][alpha] = initial[alpha] + displacement;
+            let forward = system.forces();
+            system.particles_mut().position[i][alpha] = initial[alpha] - displacement;
+            let backward = system.forces();
+            system.particles_mut().position[i] = initial;
+
+            let row = 3 * i + alpha;
+            for (j, (forward, backward)) in forward.iter().zip(&backward).enumerate() {
+                let derivative = -(forward - backward) / (2.0 * displacement);
+                for beta in 0..3 {
+                    hessian[(row, 3 * j + beta)] = derivative[beta];
+                }
+            }
+        }
+    }
+
+    let masses = system.particles().mass;
+    for row in 0..size {
+        for column in 0..row {
+            let value = 0.5 * (hessian[(row, column)] + hessian[(column, row)]);
+            hessian[(row, column)] = value;
+            hessian[(column, row)] = value;
+        }
+    }
+
+    for row in 0..size {
+        for column in 0..size {
+            hessian[(row, column)] /= f64::sqrt(masses[row / 3] * masses[column / 3]);
+        }
+    }
+
+    return hessian;
+}
+
+/// Normal modes analysis of a system.
+///
+/// The normal modes are computed by diagonalizing the mass-weighted Hessian
+/// of the potential energy, computed with finite differences of the forces.
+/// The system should be at a minimum of the potential energy, for example
+/// after a minimization: the frequencies at other configurations do not
+/// correspond to vibrations of the system.
+///
+/// Each eigenvalue `λ` of the mass-weighted Hessian corresponds to an angular
+/// frequency `ω = sqrt(λ)`. Negative eigenvalues give imaginary frequencies,
+/// which are reported as negative values. For an isolated molecule, the six
+/// (or five for linear molecules) global translations and rotations have
+/// frequencies close to zero.
+///
+/// The eigenvalues are computed with the Jacobi eigenvalue algorithm, which
+/// scales as the cube of the number of particles. This analysis is intended
+/// for small systems, for example when validating new potentials.
+///
+/// # Examples
+///
+/// ```
+/// # use lumol_sim::NormalModes;
+/// # use lumol_core::energy::{Harmonic, PairInteraction};
+/// # use lumol_core::{System, Molecule, Particle, UnitCell, units};
+/// let mut system = System::with_cell(UnitCell::infinite());
+/// system.add_molecule(Molecule::new(Particle::with_position("H", [0.0, 0.0, 0.0].into())));
+/// system.add_molecule(Molecule::new(Particle::with_position("H", [0.0, 0.0, 0.74].into())));
+///
+/// let k = units::from(3500.0, "kJ/mol/A^2").unwrap();
+/// let harmonic = Harmonic { x0: 0.74, k: k };
+/// system.add_pair_potential(("H", "H"), PairInteraction::new(Box::new(harmonic), 5.0));
+///
+/// let modes = NormalModes::new(&system);
+/// let wavenumbers = modes.wavenumbers();
+/// assert_eq!(wavenumbers.len(), 6);
+/// // The stretching mode has the highest frequency
+/// assert!(wavenumbers[5] > 4000.0);
+/// ```
+pub struct NormalModes {
+    /// Eigenvalues of the mass-weighted Hessian, in increasing order
+    eigenvalues: Vec<f64>,
+    /// Eigenvectors of the mass-weighted Hessian, stored as columns
+    eigenvectors: Array2<f64>,
+    /// Masses of the particles
+    masses: Vec<f64>,
+}
+
+impl NormalModes {
+    /// Compute the normal modes of the `system`, using a displacement of
+    /// 1e-3 A for the finite differences.
+    pub fn new(system: &System) -> NormalModes {
+        let displacement = units::from(1e-3, "A").expect("bad unit");
+        NormalModes::with_displacement(system, displacement)
+    }
+
+    /// Compute the normal modes of the `system`, using the given
+    /// `displacement` for the finite differences.
+    pub fn with_displacement(system: &System, displacement: f64) -> NormalModes {
+        let hessian = hessian(system, displacement);
+        let (eigenvalues, eigenvectors) = symmetric_eigen(hessian);
+        NormalModes {
+            eigenvalues: eigenvalues,
+            eigenvectors: eigenvectors,
+            masses: system.particles().mass.to_vec(),
+        }
+    }
+
+    /// Get the eigenvalues of the mass-weighted Hessian, i.e. the squared
+    /// angular frequencies of the normal modes, in increasing order.
+    pub fn eigenvalues(&self) -> &[f64] {
+        &self.eigenvalues
+    }
+
+    /// Get the frequencies of the normal modes in internal units, in
+    /// increasing order. Imaginary frequencies are reported as negative
+    /// values.
+    pub fn frequencies(&self) -> Vec<f64> {
+        self.eigenvalues.iter()
+            .map(|&value| f64::signum(value) * f64::sqrt(f64::abs(value)) / (2.0 * PI))
+            .collect()
+    }
+
+    /// Get the wavenumbers of the normal modes in cm^-1, in increasing order.
+    /// Imaginary frequencies are reported as negative values.
+    pub fn wavenumbers(&self) -> Vec<f64> {
+        let fs = units::from(1.0, "fs").expect("bad unit");
+        self.frequencies().iter()
+            .map(|&frequency| frequency * fs / SPEED_OF_LIGHT)
+            .collect()
+    }
+
+    /// Get the cartesian displacements of the particles along the normal mode
+    /// `i`, normalized to one.
+    ///
+    /// # Panics
+    ///
+    /// If `i` is bigger than the number of modes.
+    pub fn mode(&self, i: usize) -> Vec<Vector3D> {
+        assert!(i < self.eigenvalues.len(), "Normal mode index is out of bounds.");
+        let mut displacements = self.masses.iter().enumerate().map(|(particle, &mass)| {
+            let x = self.eigenvectors[(3 * particle, i)];
+            let y = self.eigenvectors[(3 * particle + 1, i)];
+            let z = self.eigenvectors[(3 * particle + 2, i)];
+            Vector3D::new(x, y, z) / f64::sqrt(mass)
+        }).collect::<Vec<_>>();
+
+        let norm = f64::sqrt(displacements.iter().map(|d| d.norm2()).sum());
+        if norm != 0.0 {
+            for displacement in &mut displacements {
+                *displacement /= norm;
+            }
+        }
+        return displacements;
+    }
+
+    /// Log the wavenumbers of all the normal modes
+    pub fn report(&self) {
+        info!("Normal modes analysis:");
+        for (i, wavenumber) in self.wavenumbers().iter().enumerate() {
+            info!("    mode {}: {:.4} cm^-1", i, wavenumber);
+        }
+        let imaginary = self.eigenvalues.iter().filter(|&&value| value < 0.0).count();
+        if imaginary != 0 {
+            info!("    {} modes have imaginary frequencies", imaginary);
+        }
+    }
+}
+
+/// Compute the eigenvalues and eigenvectors of a symmetric `matrix`, using
+/// the cyclic Jacobi eigenvalue algorithm. The eigenvalues are returned in
+/// increasing order, and the corresponding normalized eigenvectors as the
+/// columns of the returned matrix.
+fn symmetric_eigen(mut matrix: Array2<f64>) -> (Vec<f64>, Array2<f64>) {
+    let size = matrix.dim().0;
+    let mut vectors = Array2::zeros((size, size));
+    for i in 0..size {
+        vectors[(i, i)] = 1.0;
+    }
+
+    let mut norm = 0.0;
+    for p in 0..size {
+        for q in 0..size {
+            norm += matrix[(p, q)] * matrix[(p, q)];
+        }
+    }
+
+    for _ in 0..100 {
+        let mut off_diagonal = 0.0;
+        for p in 0..size {
+            for q in (p + 1)..size {
+                off_diagonal += matrix[(p, q)] * matrix[(p, q)];
+            }
+        }
+        if off_diagonal <= 1e-30 * norm {
+            break;
+        }
+
+        for p in 0..size {
+            for q in (p + 1)..size {
+                if matrix[(p, q)] == 0.0 {
+                    continue;
+                }
+                // Find the rotation zeroing the (p, q) element
+                let theta = (matrix[(q, q)] - matrix[(p, p)]) / (2.0 * matrix[(p, q)]);
+                let t = theta.signum() / (theta.abs() + f64::sqrt(theta * theta + 1.0));
+                let cos = 1.0 / f64::sqrt(t * t + 1.0);
+                let sin = t * cos;
+
+                for k in 0..size {
+                    let (kp, kq) = (matrix[(k, p)], matrix[(k, q)]);
+                    matrix[(k, p)] = cos * kp - sin * kq;
+                    matrix[(k, q)] = sin * kp + cos * kq;
+                }
+                for k in 0..size {
+                    let (pk, qk) = (matrix[(p, k)], matrix[(q, k)]);
+                    matrix[(p, k)] = cos * pk - sin * qk;
+                    matrix[(q, k)] = sin * pk + cos * qk;
+                }
+                for k in 0..size {
+                    let (kp, kq) = (vectors[(k, p)], vectors[(k, q)]);
+                    vectors[(k, p)] = cos * kp - sin * kq;
+                    vectors[(k, q)] = sin * kp + cos * kq;
+                }
+            }
+        }
+    }
+
+    let mut order = (0..size).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| {
+        matrix[(i, i)].partial_cmp(&matrix[(j, j)]).expect("got NaN eigenvalue")
+    });
+
+    let values = order.iter().map(|&i| matrix[(i, i)]).collect();
+    let mut sorted = Array2::zeros((size, size));
+    for i in 0..size {
+        for (j, &k) in order.iter().enumerate() {
+            sorted[(i, j)] = vectors[(i, k)];
+        }
+    }
+    return (values, sorted);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::energy::{Harmonic, PairInteraction};
+    use core::{Molecule, Particle, UnitCell};
+
+    fn testing_system() -> System {
+        let mut system = System::with_cell(UnitCell::infinite());
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 0.0].into())));
+        system.add_molecule(Molecule::new(Particle::with_position("Ar", [0.0, 0.0, 2.0].into())));
+
+        let pair = PairInteraction::new(Box::new(Harmonic { x0: 2.0, k: 0.5 }), 10.0);
+        system.add_pair_potential(("Ar", "Ar"), pair);
+        return system;
+    }
+
+    #[test]
+    fn eigen() {
+        let mut matrix = Array2::zeros((3, 3));
+        matrix[(0, 0)] = 2.0;
+        matrix[(0, 1)] = 1.0;
+        matrix[(1, 0)] = 1.0;
+        matrix[(1, 1)] = 2.0;
+        matrix[(2, 2)] = 5.0;
+
+        let (values, vectors) = symmetric_eigen(matrix);
+        assert_relative_eq!(values[0], 1.0, epsilon = 1e-12);
+        assert_relative_eq!(values[1], 3.0, epsilon = 1e-12);
+        assert_relative_eq!(values[2], 5.0, epsilon = 1e-12);
+        assert_relative_eq!(f64::abs(vectors[(0, 0)]), f64::sqrt(0.5), epsilon = 1e-12);
+        assert_relative_eq!(vectors[(0, 0)], -vectors[(1, 0)], epsilon = 1e-12);
+        assert_relative_eq!(f64::abs(vectors[(2, 2)]), 1.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn hessian_symmetry() {
+        let system = testing_system();
+        let hessian = hessian(&system, 1e-3);
+        let mass = system.particles().mass[0];
+        assert_eq!(hessian.dim(), (6, 6));
+        // Only the z coordinates are coupled at equilibrium
+        assert_relative_eq!(hessian[(2, 2)], 0.5 / mass, epsilon = 1e-9);
+        assert_relative_eq!(hessian[(2, 5)], -0.5 / mass, epsilon = 1e-9);
+        assert_relative_eq!(hessian[(5, 2)], -0.5 / mass, epsilon = 1e-9);
+        assert_relative_eq!(hessian[(0, 0)], 0.0, epsilon = 1e-9);
+        assert_relative_eq!(hessian[(0, 3)], 0.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn diatomic() {
+        let system = testing_system();
+        let mass = system.particles().mass[0];
+        let modes = NormalModes::new(&system);
+
+        let eigenvalues = modes.eigenvalues();
+        assert_eq!(eigenvalues.len(), 6);
+        for &value in &eigenvalues[..5] {
+            assert_relative_eq!(value, 0.0, epsilon = 1e-9);
+        }
+        // The vibration frequency is sqrt(k / μ), with μ = m / 2
+        assert_relative_eq!(eigenvalues[5], 2.0 * 0.5 / mass, epsilon = 1e-9);
+
+        let frequencies = modes.frequencies();
+        let expected = f64::sqrt(1.0 / mass) / (2.0 * PI);
+        assert_relative_eq!(frequencies[5], expected, max_relative = 1e-6);
+        assert_relative_eq!(modes.wavenumbers()[5], expected / SPEED_OF_LIGHT, max_relative = 1e-6);
+
+        let mode = modes.mode(5);
+        assert_relative_eq!(f64::abs(mode[0][2]), f64::sqrt(0.5), epsilon = 1e-6);
+        assert_relative_eq!(mode[0][2], -mode[1][2], epsilon = 1e-6);
+        assert_relative_eq!(mode[0][0], 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn imaginary() {
+        let mut system = testing_system();
+        system.add_pair_potential(
+            ("Ar", "Ar"),
+            PairInteraction::new(Box::new(Harmonic { x0: 2.0, k: -0.5 }), 10.0)
+        );
+        let modes = NormalModes::new(&system);
+        assert!(modes.eigenvalues()[0] < 0.0);
+        assert!(modes.frequencies()[0] < 0.0);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "All the particles must have a positive mass to compute the Hessian."
+    )]
+    fn massless() {
+        let mut system = testing_system();
+        system.particles_mut().mass[0] = 0.0;
+        let _ = NormalModes::new(&system);
+    }
+}
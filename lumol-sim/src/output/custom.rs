@@ -173,6 +173,7 @@ impl FormatArgs {
                         "volume" => Some(system.volume()),
                         "temperature" => Some(system.temperature()),
                         "natoms" => Some(system.size() as f64),
+                        "conserved" => Some(system.conserved_energy().unwrap_or(f64::NAN)),
                         "cell.a" => Some(system.cell.a()),
                         "cell.b" => Some(system.cell.b()),
                         "cell.c" => Some(system.cell.c()),
@@ -257,6 +258,8 @@ fn parse_index(input: &str) -> (&str, usize) {
 /// - Physical properties: `pressure`, `volume`, `temperature`, `natoms`, stress
 ///   tensor components: `stress.xx`, `stress.yy`, `stress.zz`, `stress.xy`,
 ///   `stress.xz`, `stress.yz`, simulation `step`.
+/// - The energy conserved by the propagator: `conserved`. This is `NaN` when
+///   the propagator does not have a conserved quantity.
 /// - Unit Cell properties: `cell.a`, `cell.b`, `cell.c` are the unit cell
 ///   vector lengths; `cell.alpha`, `cell.beta` and `cell.gamma` are the unit
 ///   cell angles.
@@ -363,6 +366,18 @@ mod tests {
         assert_eq!(format("{step}"), "42");
     }
 
+    #[test]
+    fn conserved_energy() {
+        // No conserved quantity outside of a simulation
+        assert_eq!(format("{conserved}"), "NaN");
+
+        let mut system = testing_system();
+        system.extended_energy = Some(3.0);
+        let args = FormatArgs::new("{conserved}").unwrap();
+        let conserved = args.format(&system).unwrap().parse::<f64>().unwrap();
+        assert_relative_eq!(conserved, system.total_energy() + 3.0, epsilon = 1e-12);
+    }
+
     #[test]
     fn custom() {
         let template = "p {pressure/bar} t {3 * 5} \tff";
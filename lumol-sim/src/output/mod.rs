@@ -46,8 +46,14 @@ pub use self::custom::{CustomOutput, CustomOutputError};
 mod forces;
 pub use self::forces::ForcesOutput;
 
+mod profiles;
+pub use self::profiles::ProfilesOutput;
+
 mod properties;
 pub use self::properties::PropertiesOutput;
 
 mod trajectory;
 pub use self::trajectory::TrajectoryOutput;
+
+mod widom;
+pub use self::widom::WidomOutput;
@@ -0,0 +1,138 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::Output;
+
+use core::consts::K_BOLTZMANN;
+use core::{System, Vector3D};
+use core::units;
+
+/// The `ProfilesOutput` writes the velocity and temperature profiles of the
+/// system along the z axis to a file, dividing the cell in slabs. This is
+/// used with [`MullerPlathe`](../md/struct.MullerPlathe.html) reverse
+/// non-equilibrium molecular dynamics to measure the velocity or temperature
+/// gradient.
+///
+/// Each time the output is written, one line is added for each slab with the
+/// step, the position of the center of the slab along z, the x component of
+/// the center-of-mass velocity of the slab, the temperature of the slab and
+/// the number of particles in the slab. The temperature is computed after
+/// removing the center-of-mass velocity of the slab.
+pub struct ProfilesOutput {
+    file: BufWriter<File>,
+    path: PathBuf,
+    slabs: usize,
+}
+
+impl ProfilesOutput {
+    /// Create a new `ProfilesOutput` writing to `filename`, using `slabs`
+    /// slabs along the z axis. The file is replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `slabs` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, slabs: usize) -> Result<ProfilesOutput, io::Error> {
+        assert!(slabs > 0, "The number of slabs must be positive in profiles output.");
+        Ok(ProfilesOutput {
+            file: BufWriter::new(File::create(filename.as_ref())?),
+            path: filename.as_ref().to_owned(),
+            slabs: slabs,
+        })
+    }
+}
+
+impl Output for ProfilesOutput {
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Profiles output can not be used with an infinite cell."
+        );
+        writeln_or_log!(self, "# Velocity and temperature profiles along z");
+        writeln_or_log!(self, "# Step Position/A Velocity/A/fs Temperature/K Particles");
+    }
+
+    fn write(&mut self, system: &System) {
+        let mut masses = vec![0.0; self.slabs];
+        let mut momenta = vec![Vector3D::zero(); self.slabs];
+        let mut kinetic = vec![0.0; self.slabs];
+        let mut counts = vec![0_usize; self.slabs];
+        for (&mass, position, velocity) in soa_zip!(
+            system.particles(), [mass, position, velocity]
+        ) {
+            let z = system.cell.fractional(position)[2];
+            let z = z - f64::floor(z);
+            let slab = usize::min((z * self.slabs as f64) as usize, self.slabs - 1);
+            masses[slab] += mass;
+            momenta[slab] += mass * velocity;
+            kinetic[slab] += mass * velocity.norm2();
+            counts[slab] += 1;
+        }
+
+        let height = system.cell.lengths()[2];
+        for slab in 0..self.slabs {
+            let position = (slab as f64 + 0.5) / self.slabs as f64 * height;
+            let (velocity, temperature) = if masses[slab] == 0.0 || counts[slab] < 2 {
+                (Vector3D::zero(), 0.0)
+            } else {
+                let velocity = momenta[slab] / masses[slab];
+                let kinetic = kinetic[slab] - masses[slab] * velocity.norm2();
+                let dof = 3.0 * (counts[slab] - 1) as f64;
+                (velocity, kinetic / (dof * K_BOLTZMANN))
+            };
+
+            let position = units::to(position, "A").expect("bad unit");
+            let velocity = units::to(velocity[0], "A/fs").expect("bad unit");
+            let temperature = units::to(temperature, "K").expect("bad unit");
+            writeln_or_log!(
+                self, "{} {} {} {} {}", system.step, position, velocity, temperature, counts[slab]
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use super::super::tests::testing_system;
+
+    #[test]
+    fn profiles() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut output = ProfilesOutput::new(tempfile.path(), 2).unwrap();
+            output.setup(&system);
+            output.write(&system);
+            output.finish(&system);
+        }
+
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "# Velocity and temperature profiles along z");
+        assert_eq!(lines[1], "# Step Position/A Velocity/A/fs Temperature/K Particles");
+
+        // Both particles are in the first slab
+        let values = lines[2].split_whitespace()
+                             .map(|value| value.parse::<f64>().unwrap())
+                             .collect::<Vec<_>>();
+        let mass = system.particles().mass[0];
+        let temperature = units::to(0.005 * mass / (3.0 * K_BOLTZMANN), "K").unwrap();
+        assert_ulps_eq!(values[0], 42.0);
+        assert_ulps_eq!(values[1], 2.5);
+        assert_relative_eq!(values[2], 0.05, epsilon = 1e-12);
+        assert_relative_eq!(values[3], temperature, max_relative = 1e-12);
+        assert_ulps_eq!(values[4], 2.0);
+
+        assert_eq!(lines[3], "42 7.5 0 0 0");
+    }
+}
@@ -0,0 +1,191 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+use rand::{SeedableRng, XorShiftRng};
+
+use std::f64;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::Output;
+use mc::MoleculeInserter;
+
+use core::consts::K_BOLTZMANN;
+use core::{EnergyCache, MoleculeTemplate, System};
+use core::units;
+
+/// The `WidomOutput` estimates the excess chemical potential of a molecule
+/// using Widom test particle insertions, and writes it to a file.
+///
+/// Each time the output is written, `insertions` ghost copies of the
+/// molecule are inserted one at the time in the system, at random positions
+/// and orientations, and the energy change `ΔU` of each insertion is
+/// computed. The ghost molecules are not kept in the system. The excess
+/// chemical potential is then
+///
+/// $$ \mu_{ex} = - k_B T \ln \left< e^{-\beta \Delta U} \right> $$
+///
+/// The output contains the step, the excess chemical potential estimated
+/// from the insertions at this step, and the excess chemical potential
+/// estimated from all the insertions since the beginning of the simulation.
+///
+/// Like for the [`InsertMolecule`](../mc/struct.InsertMolecule.html) move,
+/// `ΔU` includes the intramolecular energy of the inserted molecule. The
+/// chemical potential to use with grand-canonical moves to get the number
+/// density `ρ` is then `μ = k_B T ln(ρ Λ^3) + μ_ex`, with `Λ` the thermal de
+/// Broglie wavelength of the molecule.
+pub struct WidomOutput {
+    file: BufWriter<File>,
+    path: PathBuf,
+    /// Creation of the ghost molecules
+    inserter: MoleculeInserter,
+    /// Inverse temperature used in the Boltzmann factors
+    beta: f64,
+    /// Number of insertions each time the output is written
+    insertions: usize,
+    /// Random number generator for the insertions
+    rng: XorShiftRng,
+    /// Sum of the Boltzmann factors of all the insertions
+    sum: f64,
+    /// Total number of insertions
+    count: u64,
+}
+
+impl WidomOutput {
+    /// Create a new `WidomOutput` writing to `filename`, inserting
+    /// `insertions` copies of the molecule described by `template` at the
+    /// given `temperature` each time the output is written. The file is
+    /// replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `insertions` is zero or if `temperature` is not positive.
+    pub fn new<P: AsRef<Path>>(
+        filename: P,
+        template: &MoleculeTemplate,
+        temperature: f64,
+        insertions: usize,
+    ) -> Result<WidomOutput, io::Error> {
+        assert!(insertions > 0, "The number of insertions must be positive in Widom output.");
+        assert!(temperature > 0.0, "The temperature must be positive in Widom output.");
+        Ok(WidomOutput {
+            file: BufWriter::new(File::create(filename.as_ref())?),
+            path: filename.as_ref().to_owned(),
+            inserter: MoleculeInserter::new(template),
+            beta: 1.0 / (K_BOLTZMANN * temperature),
+            insertions: insertions,
+            rng: XorShiftRng::from_seed([
+                0x9b, 0x2e, 0x51, 0xc7, 0x04, 0xfa, 0x68, 0x3d,
+                0xe5, 0x17, 0x8c, 0x42, 0xb0, 0x79, 0xd6, 0x2f,
+            ]),
+            sum: 0.0,
+            count: 0,
+        })
+    }
+
+    /// Get the excess chemical potential corresponding to the `sum` of
+    /// `count` Boltzmann factors, in kJ/mol
+    fn chemical_potential(&self, sum: f64, count: u64) -> f64 {
+        let mu = -f64::ln(sum / count as f64) / self.beta;
+        return units::to(mu, "kJ/mol").expect("bad unit");
+    }
+}
+
+impl Output for WidomOutput {
+    fn setup(&mut self, system: &System) {
+        assert!(
+            !system.cell.is_infinite(),
+            "Widom output can not be used with an infinite cell."
+        );
+        writeln_or_log!(self, "# Excess chemical potential from Widom insertions (kJ/mol)");
+        writeln_or_log!(self, "# Step Instantaneous Average");
+    }
+
+    fn write(&mut self, system: &System) {
+        let step = system.step;
+        let mut system = system.clone();
+        let mut cache = EnergyCache::new();
+        cache.init(&system);
+
+        let mut sum = 0.0;
+        for _ in 0..self.insertions {
+            let molecule = self.inserter.random_molecule(&system, &mut self.rng);
+            system.add_molecule(molecule);
+            let molid = system.molecules().count() - 1;
+            let delta = cache.add_molecule_cost(&system, molid);
+            let _ = system.remove_molecule(molid);
+            sum += f64::exp(-self.beta * delta);
+        }
+
+        self.sum += sum;
+        self.count += self.insertions as u64;
+        let instantaneous = self.chemical_potential(sum, self.insertions as u64);
+        let average = self.chemical_potential(self.sum, self.count);
+        writeln_or_log!(self, "{} {} {}", step, instantaneous, average);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use super::super::tests::testing_system;
+    use core::Vector3D;
+
+    fn run(template: &MoleculeTemplate, writes: usize) -> Vec<Vec<f64>> {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let temperature = units::from(300.0, "K").unwrap();
+            let mut output = WidomOutput::new(tempfile.path(), template, temperature, 50).unwrap();
+            output.setup(&system);
+            for _ in 0..writes {
+                output.write(&system);
+            }
+            output.finish(&system);
+        }
+
+        let mut content = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut content).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Excess chemical potential from Widom insertions (kJ/mol)");
+        assert_eq!(lines[1], "# Step Instantaneous Average");
+        return lines[2..].iter().map(|line| {
+            line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect()
+        }).collect();
+    }
+
+    #[test]
+    fn ideal_gas() {
+        // There are no interactions between He and F, the excess chemical
+        // potential is zero
+        let helium = MoleculeTemplate::new().particle("He", Vector3D::zero(), 0.0);
+        let values = run(&helium, 1);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0], vec![42.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn interacting() {
+        let fluorine = MoleculeTemplate::new().particle("F", Vector3D::zero(), 0.0);
+        let values = run(&fluorine, 2);
+        assert_eq!(values.len(), 2);
+        for line in &values {
+            assert!(line[1].is_finite());
+            assert!(line[2].is_finite());
+        }
+        // The average after the first write is the instantaneous value
+        assert_eq!(values[0][1], values[0][2]);
+        // The average is between the two instantaneous values
+        let (min, max) = if values[0][1] < values[1][1] {
+            (values[0][1], values[1][1])
+        } else {
+            (values[1][1], values[0][1])
+        };
+        assert!(min <= values[1][2] && values[1][2] <= max);
+    }
+}
@@ -4,6 +4,8 @@
 //! A propagator is responsible for updating the system during a simulation
 use core::{System, DegreesOfFreedom};
 
+use checkpoint::{Checkpoint, CheckpointError};
+
 /// Possible temperature computation strategies. Different propagators needs
 /// different ways to compute the temperature: Monte Carlo temperature is a
 /// constant of the simulation, whereas for molecular dynamics we use the
@@ -41,4 +43,16 @@ pub trait Propagator {
 
     /// Finish the simulation, and maybe output some information about it
     fn finish(&mut self, _: &System) {}
+
+    /// Save the internal state of this propagator in the `checkpoint`. The
+    /// state of the system is saved separately by the simulation. The
+    /// default implementation does not save anything, and restarting the
+    /// simulation will only restore the state of the system.
+    fn save_checkpoint(&mut self, _: &mut Checkpoint) {}
+
+    /// Restore the internal state of this propagator from the `checkpoint`.
+    /// This function is called after `setup` when restarting a simulation.
+    fn restore_checkpoint(&mut self, _: &Checkpoint) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
@@ -0,0 +1,43 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) Lumol's contributors — BSD license
+
+//! Seeding of the random number generators used in simulations
+use rand::{SeedableRng, XorShiftRng};
+
+/// Create a new xorshift random number generator from a 64-bit `seed`. The
+/// bytes of the seed are interleaved with fixed bytes to fill the 128-bit
+/// state of the generator, so that all seeds give a valid state.
+pub(crate) fn rng_from_seed(seed: u64) -> XorShiftRng {
+    let b1 = ((seed >> 56) & 0xff) as u8;
+    let b2 = ((seed >> 48) & 0xff) as u8;
+    let b3 = ((seed >> 40) & 0xff) as u8;
+    let b4 = ((seed >> 32) & 0xff) as u8;
+    let b5 = ((seed >> 24) & 0xff) as u8;
+    let b6 = ((seed >> 16) & 0xff) as u8;
+    let b7 = ((seed >> 8) & 0xff) as u8;
+    let b8 = (seed & 0xff) as u8;
+    let seed = [
+        b1, 0xa8, b2, 0x29, b3, 0x60, b4, 0xb0, b5, 0x77, b6, 0xa0, b7, 0x71, b8, 0xf7,
+    ];
+    return XorShiftRng::from_seed(seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seed() {
+        let mut first = rng_from_seed(42);
+        let mut second = rng_from_seed(42);
+        let mut other = rng_from_seed(43);
+        let values = (0..10).map(|_| first.gen::<u64>()).collect::<Vec<_>>();
+        assert_eq!(values, (0..10).map(|_| second.gen::<u64>()).collect::<Vec<_>>());
+        assert_ne!(values, (0..10).map(|_| other.gen::<u64>()).collect::<Vec<_>>());
+
+        // The zero seed gives a valid generator
+        let mut zero = rng_from_seed(0);
+        assert_ne!(zero.gen::<u64>(), zero.gen::<u64>());
+    }
+}
@@ -1,8 +1,12 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) Lumol's contributors — BSD license
 
-use core::{System, DegreesOfFreedom, Vector3D};
+use std::path::{Path, PathBuf};
 
+use core::{System, DegreesOfFreedom, EnergyBreakdown, Matrix3, Vector3D};
+use core::{profiler, units};
+
+use checkpoint::Checkpoint;
 use output::Output;
 use propagator::{Propagator, TemperatureStrategy};
 
@@ -47,12 +51,36 @@ impl Output for OutputFrequency {
     }
 }
 
+/// Energies, forces and pressure tensor of a system, computed by
+/// [`Simulation::single_point`](struct.Simulation.html#method.single_point).
+#[derive(Clone, Debug)]
+pub struct SinglePoint {
+    /// Potential energy, split in the contributions of the different
+    /// classes of interactions
+    pub energy: EnergyBreakdown,
+    /// Kinetic energy
+    pub kinetic: f64,
+    /// Forces acting on all the particles
+    pub forces: Vec<Vector3D>,
+    /// Stress tensor, from the virial equation
+    pub stress: Matrix3,
+    /// Pressure, from the virial equation
+    pub pressure: f64,
+}
+
 /// The Simulation struct holds all the needed algorithms for running the
 /// simulation. It should be use together with a `System` to perform the
 /// simulation.
 pub struct Simulation {
     propagator: Box<Propagator>,
     outputs: Vec<OutputFrequency>,
+    /// Path and frequency for writing checkpoints, if any
+    checkpoint: Option<(PathBuf, u64)>,
+    /// Checkpoint to restart from at the beginning of the next run, if any
+    restart: Option<Checkpoint>,
+    /// Should we measure the time spent in the different parts of the
+    /// simulation?
+    profile: bool,
 }
 
 impl Simulation {
@@ -61,41 +89,185 @@ impl Simulation {
         Simulation {
             propagator: propagator,
             outputs: Vec::new(),
+            checkpoint: None,
+            restart: None,
+            profile: false,
         }
     }
 
-    /// Run the simulation on System for `nsteps` steps.
+    /// Run the simulation on System for `nsteps` steps. If `nsteps` is zero,
+    /// this only performs a [single point](#method.single_point) evaluation.
     pub fn run(&mut self, system: &mut System, nsteps: usize) {
-        match self.propagator.temperature_strategy() {
-            TemperatureStrategy::External(temperature) => {
-                system.simulated_temperature(Some(temperature))
-            }
-            TemperatureStrategy::Velocities => system.simulated_temperature(None),
-            TemperatureStrategy::None => {}
+        if nsteps == 0 {
+            let _ = self.single_point(system);
+            return;
         }
 
-        system.simulated_degrees_of_freedom = self.propagator.degrees_of_freedom(system);
-        match system.simulated_degrees_of_freedom {
-            DegreesOfFreedom::Molecules => info!("All molecules are treated as rigid bodies"),
-            DegreesOfFreedom::Particles => info!("All particles are allowed to move in this simulation"),
-            DegreesOfFreedom::Frozen(n) => info!("{} degrees of freedom are frozen", n),
+        self.prepare(system);
+
+        let mut start = system.step;
+        let mut nsteps = nsteps;
+        if let Some(ref checkpoint) = self.restart {
+            if let Err(err) = checkpoint.restore_system(system) {
+                panic!("could not restart the simulation: {}", err);
+            }
+            start = match checkpoint.section("simulation").and_then(|s| s.value("start")) {
+                Ok(start) => start as u64,
+                Err(err) => panic!("could not restart the simulation: {}", err),
+            };
+            // Only run the steps which were not done before the checkpoint
+            let done = system.step.saturating_sub(start) as usize;
+            nsteps = nsteps.saturating_sub(done);
+            info!("Restarting the simulation at step {}", system.step);
         }
 
         self.setup(system);
+        if let Some(checkpoint) = self.restart.take() {
+            let restored = checkpoint.section("propagator").and_then(|section| {
+                self.propagator.restore_checkpoint(section)
+            });
+            if let Err(err) = restored {
+                panic!("could not restart the simulation: {}", err);
+            }
+        }
+
+        if self.profile {
+            profiler::reset();
+            profiler::enable();
+        }
+
+        let timer = profiler::start("simulation");
         for i in 0..nsteps {
-            self.propagator.propagate(system);
+            {
+                let _timer = profiler::start("propagator");
+                self.propagator.propagate(system);
+            }
             system.step += 1;
-            for output in &mut self.outputs {
-                output.write(system);
+            {
+                let _timer = profiler::start("outputs");
+                for output in &mut self.outputs {
+                    output.write(system);
+                }
             }
+            self.write_checkpoint(system, start);
 
             if i % 10_000 == 0 {
                 self.sanity_check(system);
             }
         }
+        drop(timer);
         self.finish(system);
     }
 
+    /// Compute the energies, the forces and the pressure tensor of the
+    /// `system` without propagating it. The results are written to the
+    /// log, and all the outputs are written once, regardless of their
+    /// frequency. This is useful to validate a force field against other
+    /// simulation codes.
+    pub fn single_point(&mut self, system: &mut System) -> SinglePoint {
+        self.prepare(system);
+        let result = SinglePoint {
+            energy: system.energy_breakdown(),
+            kinetic: system.kinetic_energy(),
+            forces: system.forces(),
+            stress: system.stress(),
+            pressure: system.pressure(),
+        };
+
+        let energy = |value| units::to(value, "kJ/mol").expect("bad unit");
+        info!("Single point evaluation at step {}", system.step);
+        info!("Potential energy: {} kJ/mol", energy(result.energy.total()));
+        info!("    pairs: {} kJ/mol", energy(result.energy.pairs));
+        info!("    bonds: {} kJ/mol", energy(result.energy.bonds));
+        info!("    angles: {} kJ/mol", energy(result.energy.angles));
+        info!("    dihedrals: {} kJ/mol", energy(result.energy.dihedrals));
+        info!("    impropers: {} kJ/mol", energy(result.energy.impropers));
+        info!("    coulomb: {} kJ/mol", energy(result.energy.coulomb));
+        info!("    global: {} kJ/mol", energy(result.energy.global));
+        info!("Kinetic energy: {} kJ/mol", energy(result.kinetic));
+
+        let max_force = result.forces.iter().map(|force| force.norm()).fold(0.0, f64::max);
+        let max_force = units::to(max_force, "kJ/mol/A").expect("bad unit");
+        info!("Maximal force: {} kJ/mol/A", max_force);
+
+        let pressure = |value| units::to(value, "bar").expect("bad unit");
+        info!("Pressure: {} bar", pressure(result.pressure));
+        info!("Stress tensor (bar):");
+        for i in 0..3 {
+            info!(
+                "    {} {} {}",
+                pressure(result.stress[i][0]),
+                pressure(result.stress[i][1]),
+                pressure(result.stress[i][2])
+            );
+        }
+
+        for output in &mut self.outputs {
+            output.output.setup(system);
+            output.output.write(system);
+            output.output.finish(system);
+        }
+
+        return result;
+    }
+
+    /// Write a checkpoint of the simulation to the file at `path` every
+    /// `frequency` steps, replacing the previous checkpoint. The checkpoint
+    /// contains the state of the system and the internal state of the
+    /// propagator, and can be used with [`restart`](#method.restart) to
+    /// continue an interrupted simulation.
+    ///
+    /// # Panics
+    ///
+    /// If `frequency` is zero.
+    pub fn set_checkpoint<P: AsRef<Path>>(&mut self, path: P, frequency: u64) {
+        assert!(frequency > 0, "The checkpoint frequency must be positive.");
+        self.checkpoint = Some((path.as_ref().to_owned(), frequency));
+    }
+
+    /// Restart the next run of this simulation from the `checkpoint`.
+    ///
+    /// The system and the propagator state are restored from the
+    /// checkpoint, and the run only performs the steps which were not done
+    /// yet when the checkpoint was written. The system given to `run` must
+    /// contain the same particles as the one used to write the checkpoint,
+    /// and the simulation must use the same propagator. The restarted
+    /// simulation then follows exactly the same trajectory as the initial
+    /// one. Outputs are not restored, and start again from the beginning.
+    pub fn restart(&mut self, checkpoint: Checkpoint) {
+        self.restart = Some(checkpoint);
+    }
+
+    /// Write the checkpoint file if needed at the current step. `start` is
+    /// the step at the beginning of the initial run.
+    fn write_checkpoint(&mut self, system: &System, start: u64) {
+        let (path, frequency) = match self.checkpoint {
+            Some((ref path, frequency)) => (path, frequency),
+            None => return,
+        };
+        if system.step % frequency != 0 {
+            return;
+        }
+
+        let _timer = profiler::start("checkpoints");
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.save_system(system);
+        checkpoint.section_mut("simulation").set_value("start", start as f64);
+        self.propagator.save_checkpoint(checkpoint.section_mut("propagator"));
+        if let Err(err) = checkpoint.write(path) {
+            error!("could not write checkpoint to '{}': {}", path.display(), err);
+        }
+    }
+
+    /// Measure the time spent in the different parts of the simulation
+    /// (propagator, integrator, forces computation, outputs, *etc.*) during
+    /// the next runs, and log a breakdown of these timings at the end of
+    /// each run. The profiler is global, and profiling multiple simulations
+    /// running at the same time mixes their timings.
+    pub fn enable_profiling(&mut self) {
+        self.profile = true;
+    }
+
     /// Add a new `Output` algorithm in the outputs list
     pub fn add_output(&mut self, output: Box<Output>) {
         self.outputs.push(OutputFrequency::new(output));
@@ -108,7 +280,43 @@ impl Simulation {
         self.outputs.push(OutputFrequency::with_frequency(output, frequency));
     }
 
+    /// Prepare the `system` for a simulation with the current propagator
+    fn prepare(&mut self, system: &mut System) {
+        match self.propagator.temperature_strategy() {
+            TemperatureStrategy::External(temperature) => {
+                system.simulated_temperature(Some(temperature))
+            }
+            TemperatureStrategy::Velocities => system.simulated_temperature(None),
+            TemperatureStrategy::None => {}
+        }
+
+        system.simulated_degrees_of_freedom = self.propagator.degrees_of_freedom(system);
+        match system.simulated_degrees_of_freedom {
+            DegreesOfFreedom::Molecules => info!("All molecules are treated as rigid bodies"),
+            DegreesOfFreedom::Particles => info!("All particles are allowed to move in this simulation"),
+            DegreesOfFreedom::Frozen(n) => info!("{} degrees of freedom are frozen", n),
+        }
+
+        if let Err(errors) = system.validate() {
+            for error in errors {
+                warn!("{}", error);
+            }
+        }
+
+        // The extended energy from a previous simulation is not valid anymore,
+        // it will be set by the propagator if it has a conserved quantity
+        system.extended_energy = None;
+    }
+
     fn setup(&mut self, system: &mut System) {
+        // Frozen particles must never move, so we remove any velocity they
+        // could have been given
+        for (velocity, &frozen) in soa_zip!(system.particles_mut(), [mut velocity, frozen]) {
+            if frozen {
+                *velocity = Vector3D::zero();
+            }
+        }
+
         self.propagator.setup(system);
         for output in &mut self.outputs {
             output.setup(system);
@@ -120,6 +328,11 @@ impl Simulation {
         for output in &mut self.outputs {
             output.finish(system);
         }
+
+        if self.profile {
+            profiler::disable();
+            profiler::report("simulation");
+        }
     }
 
     /// Perform some sanity checks on the system
@@ -7,14 +7,18 @@ use rand::SeedableRng;
 use rand::distributions::{Normal, Range, Distribution};
 
 use core::consts::K_BOLTZMANN;
-use core::{System, Vector3D};
+use core::{ParticleSliceMut, System, Vector3D};
 
 use md::{Control, RemoveRotation, RemoveTranslation};
+use rng::rng_from_seed;
 
 /// Scale all velocities in the `System` such that the `system` temperature
 /// is `temperature`.
 pub fn scale(system: &mut System, temperature: f64) {
     let instant_temperature = system.temperature();
+    if instant_temperature == 0.0 {
+        return;
+    }
     let factor = f64::sqrt(temperature / instant_temperature);
     for velocity in system.particles_mut().velocity {
         *velocity *= factor;
@@ -30,8 +34,16 @@ pub trait InitVelocities {
 }
 
 /// Initialize the velocities from a Boltzmann distribution.
+///
+/// By default, all the particles are initialized at the same temperature,
+/// and the global translation and rotation of the system are removed. Groups
+/// of particles can be given a different temperature with
+/// [`group`](#method.group), and the total momentum of each molecule can be
+/// set to zero with [`zero_molecular_momentum`](#method.zero_molecular_momentum).
 pub struct BoltzmannVelocities {
     temperature: f64,
+    groups: Vec<(Vec<usize>, f64)>,
+    molecular_momentum: bool,
     dist: Normal,
     rng: XorShiftRng,
 }
@@ -41,42 +53,165 @@ impl BoltzmannVelocities {
     pub fn new(temperature: f64) -> BoltzmannVelocities {
         BoltzmannVelocities {
             temperature: temperature,
-            dist: Normal::new(0.0, f64::sqrt(K_BOLTZMANN * temperature)),
+            groups: Vec::new(),
+            molecular_momentum: false,
+            dist: Normal::new(0.0, 1.0),
             rng: XorShiftRng::from_seed([
                 0xeb, 0xa8, 0xe4, 0x29, 0xca, 0x60, 0x44, 0xb0,
                 0xd3, 0x77, 0xc6, 0xa0, 0x21, 0x71, 0x37, 0xf7,
             ]),
         }
     }
+
+    /// Initialize the velocities of the particles at indexes `particles` at
+    /// a different `temperature`. The other particles are initialized at the
+    /// temperature given to [`new`](#method.new). If a particle is part of
+    /// multiple groups, the last group wins.
+    ///
+    /// When using groups, the total momentum of each group is removed
+    /// separately, and the velocities in each group are scaled to match the
+    /// group temperature, computed with three degrees of freedom per moving
+    /// particle. The global rotation of the system is not removed.
+    pub fn group(&mut self, particles: Vec<usize>, temperature: f64) {
+        self.groups.push((particles, temperature));
+    }
+
+    /// Set the total momentum of each molecule to zero if `zero` is true,
+    /// instead of only removing the total momentum of the system. The global
+    /// rotation of the system is not removed in this case. Groups should
+    /// contain complete molecules for the molecular momentum to stay zero.
+    pub fn zero_molecular_momentum(&mut self, zero: bool) {
+        self.molecular_momentum = zero;
+    }
+
+    /// Split the particles into groups at the same temperature. The first
+    /// group contains all the particles not explicitly part of a group.
+    fn partition(&self, size: usize) -> Vec<(Vec<usize>, f64)> {
+        let mut assigned = vec![0; size];
+        for (group, &(ref particles, _)) in self.groups.iter().enumerate() {
+            for &i in particles {
+                assert!(i < size, "particle index {} in velocities group is out of bounds", i);
+                assigned[i] = group + 1;
+            }
+        }
+
+        let mut partition = vec![(Vec::new(), self.temperature)];
+        partition.extend(self.groups.iter().map(|&(_, temperature)| (Vec::new(), temperature)));
+        for (i, &group) in assigned.iter().enumerate() {
+            partition[group].0.push(i);
+        }
+        partition.retain(|&(ref particles, _)| !particles.is_empty());
+        return partition;
+    }
 }
 
 impl InitVelocities for BoltzmannVelocities {
     fn init(&mut self, system: &mut System) {
-        for particle in system.particles_mut() {
-            let m_inv = 1.0 / (*particle.mass);
-            let x = f64::sqrt(m_inv) * self.dist.sample(&mut self.rng);
-            let y = f64::sqrt(m_inv) * self.dist.sample(&mut self.rng);
-            let z = f64::sqrt(m_inv) * self.dist.sample(&mut self.rng);
-            *particle.velocity = Vector3D::new(x, y, z);
+        let partition = self.partition(system.size());
+        let planar = system.dimensions() == 2;
+        for &(ref particles, temperature) in &partition {
+            let sigma = f64::sqrt(K_BOLTZMANN * temperature);
+            let mut all_particles = system.particles_mut();
+            for &i in particles {
+                // Massless particles (virtual sites) and frozen particles do
+                // not get any velocity
+                let m_inv = if all_particles.mass[i] == 0.0 || all_particles.frozen[i] {
+                    0.0
+                } else {
+                    1.0 / all_particles.mass[i]
+                };
+                let x = f64::sqrt(m_inv) * sigma * self.dist.sample(&mut self.rng);
+                let y = f64::sqrt(m_inv) * sigma * self.dist.sample(&mut self.rng);
+                let z = if planar {
+                    0.0
+                } else {
+                    f64::sqrt(m_inv) * sigma * self.dist.sample(&mut self.rng)
+                };
+                all_particles.velocity[i] = Vector3D::new(x, y, z);
+            }
+        }
+
+        if self.molecular_momentum {
+            for mut molecule in system.molecules_mut() {
+                let particles = (0..molecule.size()).collect::<Vec<_>>();
+                remove_momentum(&mut molecule.particles_mut(), &particles);
+            }
+        } else if partition.len() > 1 {
+            for &(ref particles, _) in &partition {
+                remove_momentum(&mut system.particles_mut(), particles);
+            }
+        } else {
+            RemoveTranslation.control(system);
+        }
+
+        if partition.len() > 1 {
+            let dimensions = system.dimensions();
+            for &(ref particles, temperature) in &partition {
+                scale_group(&mut system.particles_mut(), particles, temperature, dimensions);
+            }
+        } else {
+            if !self.molecular_momentum {
+                RemoveRotation.control(system);
+            }
+            scale(system, self.temperature);
         }
-        RemoveTranslation.control(system);
-        RemoveRotation.control(system);
-        scale(system, self.temperature);
     }
 
     fn seed(&mut self, seed: u64) {
-        let b1 = ((seed >> 56) & 0xff) as u8;
-        let b2 = ((seed >> 48) & 0xff) as u8;
-        let b3 = ((seed >> 40) & 0xff) as u8;
-        let b4 = ((seed >> 32) & 0xff) as u8;
-        let b5 = ((seed >> 24) & 0xff) as u8;
-        let b6 = ((seed >> 16) & 0xff) as u8;
-        let b7 = ((seed >> 8) & 0xff) as u8;
-        let b8 = (seed & 0xff) as u8;
-        let seed = [
-            b1, 0xa8, b2, 0x29, b3, 0x60, b4, 0xb0, b5, 0x77, b6, 0xa0, b7, 0x71, b8, 0xf7,
-        ];
-        self.rng = XorShiftRng::from_seed(seed);
+        self.rng = rng_from_seed(seed);
+    }
+}
+
+/// Remove the total momentum of the moving particles at indexes `group` in
+/// `particles`.
+fn remove_momentum(particles: &mut ParticleSliceMut, group: &[usize]) {
+    let mut total_mass = 0.0;
+    let mut momentum = Vector3D::zero();
+    for &i in group {
+        if !particles.frozen[i] {
+            total_mass += particles.mass[i];
+            momentum += particles.mass[i] * particles.velocity[i];
+        }
+    }
+
+    if total_mass == 0.0 {
+        return;
+    }
+
+    let com_velocity = momentum / total_mass;
+    for &i in group {
+        if !particles.frozen[i] {
+            particles.velocity[i] -= com_velocity;
+        }
+    }
+}
+
+/// Scale the velocities of the particles at indexes `group` in `particles` to
+/// set their temperature to `temperature`, using `dimensions` degrees of
+/// freedom for each moving particle.
+fn scale_group(
+    particles: &mut ParticleSliceMut,
+    group: &[usize],
+    temperature: f64,
+    dimensions: usize,
+) {
+    let mut kinetic = 0.0;
+    let mut moving = 0;
+    for &i in group {
+        if particles.mass[i] != 0.0 && !particles.frozen[i] {
+            kinetic += particles.mass[i] * particles.velocity[i].norm2();
+            moving += 1;
+        }
+    }
+
+    if kinetic == 0.0 {
+        return;
+    }
+
+    let instant_temperature = kinetic / ((dimensions * moving) as f64 * K_BOLTZMANN);
+    let factor = f64::sqrt(temperature / instant_temperature);
+    for &i in group {
+        particles.velocity[i] *= factor;
     }
 }
 
@@ -104,13 +239,19 @@ impl UniformVelocities {
 
 impl InitVelocities for UniformVelocities {
     fn init(&mut self, system: &mut System) {
+        let planar = system.dimensions() == 2;
         for particle in system.particles_mut() {
-            let m_inv = 1.0 / (*particle.mass);
-            *particle.velocity = f64::sqrt(m_inv) * Vector3D::new(
-                self.dist.sample(&mut self.rng),
-                self.dist.sample(&mut self.rng),
-                self.dist.sample(&mut self.rng),
-            );
+            // Massless particles (virtual sites) and frozen particles do not
+            // get any velocity
+            let m_inv = if *particle.mass == 0.0 || *particle.frozen {
+                0.0
+            } else {
+                1.0 / (*particle.mass)
+            };
+            let x = self.dist.sample(&mut self.rng);
+            let y = self.dist.sample(&mut self.rng);
+            let z = if planar { 0.0 } else { self.dist.sample(&mut self.rng) };
+            *particle.velocity = f64::sqrt(m_inv) * Vector3D::new(x, y, z);
         }
         RemoveTranslation.control(system);
         RemoveRotation.control(system);
@@ -118,18 +259,7 @@ impl InitVelocities for UniformVelocities {
     }
 
     fn seed(&mut self, seed: u64) {
-        let b1 = ((seed >> 56) & 0xff) as u8;
-        let b2 = ((seed >> 48) & 0xff) as u8;
-        let b3 = ((seed >> 40) & 0xff) as u8;
-        let b4 = ((seed >> 32) & 0xff) as u8;
-        let b5 = ((seed >> 24) & 0xff) as u8;
-        let b6 = ((seed >> 16) & 0xff) as u8;
-        let b7 = ((seed >> 8) & 0xff) as u8;
-        let b8 = (seed & 0xff) as u8;
-        let seed = [
-            b1, 0xa8, b2, 0x29, b3, 0x60, b4, 0xb0, b5, 0x77, b6, 0xa0, b7, 0x71, b8, 0xf7,
-        ];
-        self.rng = XorShiftRng::from_seed(seed);
+        self.rng = rng_from_seed(seed);
     }
 }
 
@@ -175,6 +305,90 @@ mod test {
         assert_ulps_eq!(global_translation(&system), 0.0);
     }
 
+    #[test]
+    fn init_boltzmann_groups() {
+        let mut system = testing_system();
+        let mut velocities = BoltzmannVelocities::new(300.0);
+        velocities.group((0..2000).collect(), 1000.0);
+        velocities.seed(1234);
+        velocities.init(&mut system);
+
+        let group_temperature = |range: ::std::ops::Range<usize>| {
+            let particles = system.particles();
+            let kinetic = range.clone().map(|i| {
+                particles.mass[i] * particles.velocity[i].norm2()
+            }).sum::<f64>();
+            kinetic / (3.0 * range.len() as f64 * K_BOLTZMANN)
+        };
+        assert_ulps_eq!(group_temperature(0..2000), 1000.0, epsilon = 1e-9);
+        assert_ulps_eq!(group_temperature(2000..10000), 300.0, epsilon = 1e-9);
+        assert_ulps_eq!(global_translation(&system), 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn init_boltzmann_molecular_momentum() {
+        let mut system = System::new();
+        for i in 0..500 {
+            let mut molecule = Molecule::new(
+                Particle::with_position("O", Vector3D::new(i as f64, 0.0, 0.0))
+            );
+            molecule.add_particle_bonded_to(
+                0, Particle::with_position("H", Vector3D::new(i as f64, 1.0, 0.0))
+            );
+            system.add_molecule(molecule);
+        }
+
+        let mut velocities = BoltzmannVelocities::new(300.0);
+        velocities.zero_molecular_momentum(true);
+        velocities.init(&mut system);
+        assert_ulps_eq!(system.temperature(), 300.0, epsilon = 1e-9);
+        for molecule in system.molecules() {
+            let mut momentum = Vector3D::zero();
+            for (&mass, velocity) in soa_zip!(molecule.particles(), [mass, velocity]) {
+                momentum += mass * velocity;
+            }
+            assert!(momentum.norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn boltzmann_seed() {
+        let mut system = testing_system();
+        let mut velocities = BoltzmannVelocities::new(300.0);
+        velocities.seed(42);
+        velocities.init(&mut system);
+        let first = system.particles().velocity.to_vec();
+
+        let mut velocities = BoltzmannVelocities::new(300.0);
+        velocities.seed(42);
+        velocities.init(&mut system);
+        assert_eq!(system.particles().velocity, &first[..]);
+
+        velocities.seed(43);
+        velocities.init(&mut system);
+        assert_ne!(system.particles().velocity, &first[..]);
+    }
+
+    #[test]
+    fn init_two_dimensions() {
+        let mut system = testing_system();
+        for position in system.particles_mut().position {
+            position[2] = 0.0;
+        }
+        system.set_dimensions(2);
+
+        let mut velocities = BoltzmannVelocities::new(300.0);
+        velocities.init(&mut system);
+        assert!(system.particles().velocity.iter().all(|velocity| velocity[2] == 0.0));
+        assert_ulps_eq!(system.temperature(), 300.0, epsilon = 1e-9);
+        assert_ulps_eq!(global_translation(&system), 0.0);
+
+        let mut velocities = UniformVelocities::new(300.0);
+        velocities.init(&mut system);
+        assert!(system.particles().velocity.iter().all(|velocity| velocity[2] == 0.0));
+        assert_ulps_eq!(system.temperature(), 300.0, epsilon = 1e-9);
+    }
+
     #[test]
     fn init_uniform() {
         let mut system = testing_system();
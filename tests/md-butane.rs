@@ -41,6 +41,22 @@ fn constant_energy() {
     let mut config = Input::new(path).unwrap().read().unwrap();
 
 
+    let e_initial = config.system.total_energy();
+    config.simulation.run(&mut config.system, config.nsteps);
+    let e_final = config.system.total_energy();
+    assert!(f64::abs((e_initial - e_final) / e_final) < 1e-3);
+}
+
+#[test]
+fn constant_energy_respa() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-butane")
+                                 .join("nve-respa.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
     let e_initial = config.system.total_energy();
     config.simulation.run(&mut config.system, config.nsteps);
     let e_final = config.system.total_energy();
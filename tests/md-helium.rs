@@ -9,9 +9,15 @@ extern crate lumol_input as input;
 
 use input::Input;
 use lumol::consts::K_BOLTZMANN;
+use lumol::sim::{Checkpoint, MolecularDynamics, Simulation};
+use lumol::sim::md::{AnisoBerendsenBarostat, BerendsenBarostat, Integrator};
+use lumol::sim::md::{MTKIntegrator, ParrinelloRahmanBarostat};
+use lumol::types::Vector3D;
 use lumol::units;
 
-use std::path::Path;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Once, ONCE_INIT};
 static START: Once = ONCE_INIT;
 
@@ -114,6 +120,95 @@ fn berendsen_barostat() {
     assert!(f64::abs(temperature - expected) / expected < 1e-2);
 }
 
+#[test]
+fn parrinello_rahman_barostat() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("npt-parrinello-rahman.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let collecter = utils::Collecter::starting_at(5000);
+    let temperatures = collecter.temperatures();
+    let pressures = collecter.pressures();
+
+    config.simulation.add_output(Box::new(collecter));
+    config.simulation.run(&mut config.system, config.nsteps);
+
+    let expected = units::from(5000.0, "bar").unwrap();
+    let pressure = ::utils::mean(pressures.clone());
+    assert!(f64::abs(pressure - expected) / expected < 1e-1);
+
+    let expected = units::from(273.0, "K").unwrap();
+    let temperature = ::utils::mean(temperatures.clone());
+    assert!(f64::abs(temperature - expected) / expected < 2e-2);
+}
+
+#[test]
+fn mtk_integrator() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("npt-mtk.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let collecter = utils::Collecter::starting_at(5000);
+    let temperatures = collecter.temperatures();
+    let pressures = collecter.pressures();
+
+    config.simulation.add_output(Box::new(collecter));
+    config.simulation.run(&mut config.system, config.nsteps);
+
+    let expected = units::from(5000.0, "bar").unwrap();
+    let pressure = ::utils::mean(pressures.clone());
+    assert!(f64::abs(pressure - expected) / expected < 1e-1);
+
+    let expected = units::from(273.0, "K").unwrap();
+    let temperature = ::utils::mean(temperatures.clone());
+    assert!(f64::abs(temperature - expected) / expected < 2e-2);
+}
+
+#[test]
+fn frozen_barostats() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("npt-berendsen-barostat.toml");
+    let system = Input::new(path).unwrap().read().unwrap().system;
+
+    let timestep = units::from(1.0, "fs").unwrap();
+    let pressure = units::from(5000.0, "bar").unwrap();
+    let temperature = units::from(273.0, "K").unwrap();
+    let integrators: Vec<Box<Integrator>> = vec![
+        Box::new(BerendsenBarostat::new(timestep, pressure, 1000.0)),
+        Box::new(AnisoBerendsenBarostat::hydrostatic(timestep, pressure, 1000.0)),
+        Box::new(ParrinelloRahmanBarostat::hydrostatic(timestep, pressure, 1000.0)),
+        Box::new(MTKIntegrator::new(timestep, temperature, pressure, 100.0, 1000.0)),
+    ];
+
+    for integrator in integrators {
+        let mut system = system.clone();
+        system.particles_mut().frozen[0] = true;
+        system.particles_mut().velocity[0] = Vector3D::zero();
+        let position = system.particles().position[0];
+        let volume = system.volume();
+
+        let md = MolecularDynamics::from_integrator(integrator);
+        let mut simulation = Simulation::new(Box::new(md));
+        simulation.run(&mut system, 100);
+
+        // The cell changed, but not the position of the frozen particle
+        assert_ne!(system.volume(), volume);
+        assert_eq!(system.particles().position[0], position);
+    }
+}
+
 #[test]
 fn shifted() {
     START.call_once(::env_logger::init);
@@ -146,3 +241,170 @@ fn table_computation() {
     let e_final = config.system.total_energy();
     assert!(f64::abs((e_initial - e_final) / e_final) < 5e-3);
 }
+
+#[test]
+fn langevin() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-langevin.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let collecter = utils::Collecter::starting_at(2000);
+    let temperatures = collecter.temperatures();
+
+    config.simulation.add_output(Box::new(collecter));
+    config.simulation.run(&mut config.system, config.nsteps);
+
+    let expected = units::from(273.0, "K").unwrap();
+    let temperature = ::utils::mean(temperatures.clone());
+    assert!(f64::abs(temperature - expected) / expected < 2e-2);
+}
+
+#[test]
+fn langevin_conserved_energy() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-langevin.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let e_initial = config.system.total_energy();
+    config.simulation.run(&mut config.system, config.nsteps);
+    let e_final = config.system.conserved_energy().unwrap();
+    assert!(f64::abs((e_initial - e_final) / e_final) < 1e-2);
+}
+
+#[test]
+fn langevin_reproducible() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-langevin.toml");
+
+    let mut first = Input::new(path.clone()).unwrap().read().unwrap();
+    first.simulation.run(&mut first.system, 100);
+    let mut second = Input::new(path.clone()).unwrap().read().unwrap();
+    second.simulation.run(&mut second.system, 100);
+
+    assert_eq!(first.system.particles().position, second.system.particles().position);
+}
+
+#[test]
+fn dissipative_particle_dynamics() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-dpd.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let collecter = utils::Collecter::starting_at(2000);
+    let temperatures = collecter.temperatures();
+
+    config.simulation.add_output(Box::new(collecter));
+    config.simulation.run(&mut config.system, config.nsteps);
+
+    let expected = units::from(273.0, "K").unwrap();
+    let temperature = ::utils::mean(temperatures.clone());
+    assert!(f64::abs(temperature - expected) / expected < 2e-2);
+}
+
+#[test]
+fn single_point() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nve-velocity-verlet.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let positions = config.system.particles().position.to_vec();
+    let result = config.simulation.single_point(&mut config.system);
+
+    // The system is not propagated
+    assert_eq!(config.system.step, 0);
+    assert_eq!(config.system.particles().position, &positions[..]);
+
+    let energy = config.system.potential_energy();
+    assert!(f64::abs((result.energy.total() - energy) / energy) < 1e-12);
+    assert!(f64::abs((result.energy.pairs - energy) / energy) < 1e-12);
+    assert_eq!(result.forces, config.system.forces());
+    assert_eq!(result.pressure, config.system.pressure());
+}
+
+/// Check that a simulation interrupted after half of the steps and restarted
+/// from a checkpoint gives exactly the same trajectory as the full simulation.
+/// The full simulation does not write any checkpoint, to also check that
+/// writing a checkpoint does not change the trajectory.
+fn check_exact_restart(path: PathBuf, name: &str) {
+    let checkpoint = env::temp_dir().join(format!("lumol-{}.chk", name));
+
+    let mut reference = Input::new(path.clone()).unwrap().read().unwrap();
+    reference.simulation.run(&mut reference.system, 100);
+
+    let mut first = Input::new(path.clone()).unwrap().read().unwrap();
+    first.simulation.set_checkpoint(&checkpoint, 50);
+    first.simulation.run(&mut first.system, 50);
+
+    let mut second = Input::new(path.clone()).unwrap().read().unwrap();
+    second.simulation.restart(Checkpoint::read(&checkpoint).unwrap());
+    second.simulation.run(&mut second.system, 100);
+
+    fs::remove_file(&checkpoint).unwrap();
+
+    assert_eq!(second.system.step, reference.system.step);
+    assert_eq!(second.system.cell, reference.system.cell);
+    assert_eq!(second.system.particles().position, reference.system.particles().position);
+    assert_eq!(second.system.particles().velocity, reference.system.particles().velocity);
+
+    // Writing checkpoints during a run does not change the trajectory
+    let mut checkpointed = Input::new(path).unwrap().read().unwrap();
+    checkpointed.simulation.set_checkpoint(&checkpoint, 25);
+    checkpointed.simulation.run(&mut checkpointed.system, 100);
+    fs::remove_file(&checkpoint).unwrap();
+
+    assert_eq!(checkpointed.system.particles().position, reference.system.particles().position);
+    assert_eq!(checkpointed.system.particles().velocity, reference.system.particles().velocity);
+}
+
+#[test]
+fn restart_langevin() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-langevin.toml");
+    check_exact_restart(path, "md-helium-langevin");
+}
+
+#[test]
+fn restart_mtk() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("npt-mtk.toml");
+    check_exact_restart(path, "md-helium-mtk");
+}
+
+#[test]
+fn restart_dpd() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("nvt-dpd.toml");
+    check_exact_restart(path, "md-helium-dpd");
+}
@@ -59,6 +59,31 @@ mod wolf {
         let temperature = ::utils::mean(temperatures.clone());
         assert!(f64::abs(temperature - expected) / expected < 1e-2);
     }
+
+    #[test]
+    fn semi_isotropic_berendsen() {
+        START.call_once(::env_logger::init);
+        let path = Path::new(file!()).parent()
+                                     .unwrap()
+                                     .join("data")
+                                     .join("md-nacl")
+                                     .join("npt-wolf-semi-isotropic.toml");
+        let mut config = Input::new(path).unwrap().read().unwrap();
+
+        let collecter = ::utils::Collecter::starting_at(9000);
+        let pressures = collecter.pressures();
+
+        config.simulation.add_output(Box::new(collecter));
+        config.simulation.run(&mut config.system, config.nsteps);
+
+        let expected = units::from(50000.0, "bar").unwrap();
+        let pressure = ::utils::mean(pressures.clone());
+        assert!(f64::abs(pressure - expected) / expected < 2e-3);
+
+        // The x and y directions are scaled together
+        let cell = &config.system.cell;
+        assert!(f64::abs(cell.a() - cell.b()) / cell.a() < 1e-12);
+    }
 }
 
 mod ewald {
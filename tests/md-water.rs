@@ -3,8 +3,13 @@
 
 //! Testing physical properties of f-SPC water
 extern crate env_logger;
+extern crate lumol;
 extern crate lumol_input as input;
 
+use lumol::sim::{Simulation, MolecularDynamics};
+use lumol::sim::md::{Langevin, RigidBodyVerlet, Verlet};
+use lumol::sys::Constraint;
+use lumol::units;
 use input::Input;
 
 use std::path::Path;
@@ -45,3 +50,166 @@ fn constant_energy_wolf() {
     let e_final = config.system.total_energy();
     assert!(f64::abs((e_initial - e_final) / e_final) < 3e-2);
 }
+
+#[test]
+fn constant_energy_shake() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-shake.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    // Constrain all the O-H bonds to their initial length
+    let mut bonds = Vec::new();
+    for molecule in config.system.molecules() {
+        let oxygen = molecule.start();
+        for hydrogen in (oxygen + 1)..molecule.end() {
+            bonds.push((oxygen, hydrogen, config.system.distance(oxygen, hydrogen)));
+        }
+    }
+    for &(i, j, distance) in &bonds {
+        config.system.add_constraint(Constraint::Distance { i: i, j: j, distance: distance });
+    }
+
+    let e_initial = config.system.total_energy();
+    config.simulation.run(&mut config.system, config.nsteps);
+    let e_final = config.system.total_energy();
+    assert!(f64::abs((e_initial - e_final) / e_final) < 3e-2);
+
+    for &(i, j, distance) in &bonds {
+        assert!(f64::abs(config.system.distance(i, j) - distance) < 1e-6);
+    }
+}
+
+#[test]
+fn constant_energy_settle() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-shake.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    // Use rigid water molecules
+    let molecules = config.system.molecules()
+                                 .map(|molecule| (molecule.start(), molecule.end()))
+                                 .collect::<Vec<_>>();
+    for &(start, end) in &molecules {
+        config.system.add_constraint(Constraint::Rigid { start: start, end: end });
+    }
+    let oxygen = molecules[0].0;
+    let (first, second) = (oxygen + 1, oxygen + 2);
+    let oh = config.system.distance(oxygen, first);
+    let hh = config.system.distance(first, second);
+
+    let e_initial = config.system.total_energy();
+    config.simulation.run(&mut config.system, config.nsteps);
+    let e_final = config.system.total_energy();
+    assert!(f64::abs((e_initial - e_final) / e_final) < 3e-2);
+
+    assert!(f64::abs(config.system.distance(oxygen, first) - oh) < 1e-6);
+    assert!(f64::abs(config.system.distance(first, second) - hh) < 1e-6);
+}
+
+#[test]
+fn constant_energy_rigid_bodies() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-rigid.toml");
+    let mut config = Input::new(path).unwrap().read().unwrap();
+
+    let molecules = config.system.molecules()
+                                 .map(|molecule| (molecule.start(), molecule.end()))
+                                 .collect::<Vec<_>>();
+    for &(start, end) in &molecules {
+        config.system.add_constraint(Constraint::Rigid { start: start, end: end });
+    }
+    let oxygen = molecules[0].0;
+    let (first, second) = (oxygen + 1, oxygen + 2);
+    let oh = config.system.distance(oxygen, first);
+    let hh = config.system.distance(first, second);
+
+    let e_initial = config.system.total_energy();
+    config.simulation.run(&mut config.system, config.nsteps);
+    let e_final = config.system.total_energy();
+    assert!(f64::abs((e_initial - e_final) / e_final) < 3e-2);
+
+    assert!(f64::abs(config.system.distance(oxygen, first) - oh) < 1e-8);
+    assert!(f64::abs(config.system.distance(first, second) - hh) < 1e-8);
+}
+
+#[test]
+fn langevin_settle() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-shake.toml");
+    let mut system = Input::new(path).unwrap().read().unwrap().system;
+
+    let molecules = system.molecules()
+                          .map(|molecule| (molecule.start(), molecule.end()))
+                          .collect::<Vec<_>>();
+    for &(start, end) in &molecules {
+        system.add_constraint(Constraint::Rigid { start: start, end: end });
+    }
+    let oxygen = molecules[0].0;
+    let (first, second) = (oxygen + 1, oxygen + 2);
+    let oh = system.distance(oxygen, first);
+    let hh = system.distance(first, second);
+
+    let timestep = units::from(2.0, "fs").unwrap();
+    let temperature = units::from(300.0, "K").unwrap();
+    let friction = units::from(1.0, "ps^-1").unwrap();
+    let integrator = Langevin::new(timestep, temperature, friction);
+    let md = MolecularDynamics::from_integrator(Box::new(integrator));
+    let mut simulation = Simulation::new(Box::new(md));
+    simulation.run(&mut system, 200);
+
+    assert!(f64::abs(system.distance(oxygen, first) - oh) < 1e-6);
+    assert!(f64::abs(system.distance(first, second) - hh) < 1e-6);
+}
+
+#[test]
+#[should_panic(expected = "The Verlet integrator can not enforce the holonomic constraints")]
+fn verlet_constraints() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-shake.toml");
+    let mut system = Input::new(path).unwrap().read().unwrap().system;
+    system.add_constraint(Constraint::Rigid { start: 0, end: 3 });
+
+    let timestep = units::from(1.0, "fs").unwrap();
+    let md = MolecularDynamics::from_integrator(Box::new(Verlet::new(timestep)));
+    let mut simulation = Simulation::new(Box::new(md));
+    simulation.run(&mut system, 1);
+}
+
+#[test]
+#[should_panic(expected = "The rigid body integrator can only enforce the rigid fragments")]
+fn rigid_body_distance_constraints() {
+    START.call_once(::env_logger::init);
+    let path = Path::new(file!()).parent()
+                                 .unwrap()
+                                 .join("data")
+                                 .join("md-water")
+                                 .join("nve-shake.toml");
+    let mut system = Input::new(path).unwrap().read().unwrap().system;
+    let distance = system.distance(0, 1);
+    system.add_constraint(Constraint::Distance { i: 0, j: 1, distance: distance });
+
+    let timestep = units::from(1.0, "fs").unwrap();
+    let md = MolecularDynamics::from_integrator(Box::new(RigidBodyVerlet::new(timestep)));
+    let mut simulation = Simulation::new(Box::new(md));
+    simulation.run(&mut system, 1);
+}